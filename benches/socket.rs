@@ -0,0 +1,150 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use daemon_rs::bench::frame;
+use daemon_rs::schema::SchemaValidator;
+use daemon_rs::{DaemonBuilder, DaemonHandle};
+use serde_json::json;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+/// Logs sent per iteration, one connection at a time
+const BATCH: usize = 1000;
+
+/// Longest a batch may take to be ingested before the benchmark gives up
+const INGEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Payload shapes: (name, message bytes, with metadata)
+const PAYLOADS: [(&str, usize, bool); 4] = [
+    ("small", 64, false),
+    ("small_metadata", 64, true),
+    ("large", 16 * 1024, false),
+    ("large_metadata", 16 * 1024, true),
+];
+
+/// A daemon on a temp socket that records when each log was accepted
+struct Harness {
+    daemon: DaemonHandle,
+    accepted: Arc<Mutex<Vec<Instant>>>,
+    _dir: TempDir,
+}
+
+impl Harness {
+    fn start() -> Self {
+        let dir = TempDir::new().unwrap();
+        let accepted = Arc::new(Mutex::new(Vec::with_capacity(BATCH)));
+        let on_accept = accepted.clone();
+        let daemon = DaemonBuilder::new()
+            .with_socket_path(dir.path().join("bench.sock"))
+            .with_storage_dir(dir.path().join("logs"))
+            .with_schema(SchemaValidator::default_schema().unwrap())
+            .on_log(move |_| on_accept.lock().unwrap().push(Instant::now()))
+            .start()
+            .unwrap();
+        Self {
+            daemon,
+            accepted,
+            _dir: dir,
+        }
+    }
+
+    /// Send `frames` on a fresh connection and wait until the daemon has queued all of them
+    /// for storage; returns when each frame started being written and the elapsed time
+    fn send(&self, frames: &[Vec<u8>]) -> (Vec<Instant>, Duration) {
+        self.accepted.lock().unwrap().clear();
+        let target = self.daemon.stats().ingested_total + frames.len() as u64;
+
+        let start = Instant::now();
+        let mut stream = UnixStream::connect(self.daemon.socket_path()).unwrap();
+        let mut sent = Vec::with_capacity(frames.len());
+        for frame in frames {
+            sent.push(Instant::now());
+            stream.write_all(frame).unwrap();
+        }
+        drop(stream);
+        while self.daemon.stats().ingested_total < target {
+            assert!(
+                start.elapsed() < INGEST_TIMEOUT,
+                "the daemon stopped ingesting"
+            );
+            std::thread::yield_now();
+        }
+        (sent, start.elapsed())
+    }
+
+    /// p99 of the time from starting to write each frame to the daemon accepting it
+    fn p99_latency(&self, sent: &[Instant]) -> Duration {
+        let accepted = self.accepted.lock().unwrap();
+        let mut latencies: Vec<Duration> = sent
+            .iter()
+            .zip(accepted.iter())
+            .map(|(sent, accepted)| accepted.duration_since(*sent))
+            .collect();
+        latencies.sort_unstable();
+        latencies[(latencies.len() * 99 / 100).min(latencies.len() - 1)]
+    }
+}
+
+/// `BATCH` framed logs in the default schema
+fn frames(message_bytes: usize, metadata: bool) -> Vec<Vec<u8>> {
+    let padding = "x".repeat(message_bytes);
+    (0..BATCH)
+        .map(|i| {
+            let mut log = json!({
+                "timestamp": "2026-01-15T19:00:00.000000Z",
+                "level": if i % 10 == 0 { "error" } else { "info" },
+                "message": format!("{} {}", i, padding),
+                "service": "bench",
+            });
+            if metadata {
+                log["metadata"] = json!({
+                    "request_id": format!("req-{}", i),
+                    "user_id": i % 97,
+                    "route": "/api/checkout",
+                    "duration_ms": 12.5,
+                    "tags": ["web", "eu-west-1"],
+                });
+            }
+            frame(&serde_json::to_vec(&log).unwrap())
+        })
+        .collect()
+}
+
+fn benchmark_socket_ingest(c: &mut Criterion) {
+    let harness = Harness::start();
+
+    let mut group = c.benchmark_group("socket_ingest");
+    group.throughput(Throughput::Elements(BATCH as u64));
+    group.sample_size(20);
+    group.measurement_time(Duration::from_secs(5));
+    for (name, message_bytes, metadata) in PAYLOADS {
+        let frames = frames(message_bytes, metadata);
+        group.bench_with_input(BenchmarkId::from_parameter(name), &frames, |b, frames| {
+            b.iter_custom(|iters| (0..iters).map(|_| harness.send(frames).1).sum())
+        });
+    }
+    group.finish();
+
+    // Each iteration reports the p99 latency of one batch, so regressions show up in the
+    // usual criterion comparison
+    let mut group = c.benchmark_group("socket_p99_enqueue_latency");
+    group.sample_size(20);
+    group.measurement_time(Duration::from_secs(5));
+    for (name, message_bytes, metadata) in PAYLOADS {
+        let frames = frames(message_bytes, metadata);
+        group.bench_with_input(BenchmarkId::from_parameter(name), &frames, |b, frames| {
+            b.iter_custom(|iters| {
+                (0..iters)
+                    .map(|_| harness.p99_latency(&harness.send(frames).0))
+                    .sum()
+            })
+        });
+    }
+    group.finish();
+
+    harness.daemon.shutdown().unwrap();
+}
+
+criterion_group!(benches, benchmark_socket_ingest);
+criterion_main!(benches);
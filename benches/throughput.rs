@@ -59,5 +59,67 @@ fn benchmark_parquet_write(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, benchmark_json_validation, benchmark_parquet_write);
+/// Write speed and file size of each codec and level on the same logs
+fn benchmark_compression(c: &mut Criterion) {
+    use daemon_rs::storage::{try_parse_compression, StorageEngine};
+    use tempfile::TempDir;
+
+    const LOGS: usize = 10_000;
+    let levels = ["info", "info", "warn", "error"];
+    let services = ["checkout", "payments", "search"];
+    let logs: Vec<daemon_rs::schema::LogEntry> = (0..LOGS)
+        .map(|i| {
+            serde_json::from_value(json!({
+                "timestamp": format!("2026-01-15T19:{:02}:{:02}Z", i / 60 % 60, i % 60),
+                "level": levels[i % 4],
+                "message": format!("Request {} to /api/orders/{} finished in {}ms", i, i % 500, i % 97),
+                "service": services[i % 3],
+                "metadata": {"user_id": i % 1000, "region": "eu-west-1"}
+            }))
+            .unwrap()
+        })
+        .collect();
+
+    let write = |codec: &str| {
+        let temp_dir = TempDir::new().unwrap();
+        let mut engine = StorageEngine::new(
+            temp_dir.path().to_path_buf(),
+            try_parse_compression(codec).unwrap(),
+            LOGS,
+            1024 * 1024 * 100,
+        )
+        .unwrap();
+        for log in &logs {
+            engine.add_log(log.clone()).unwrap();
+        }
+        engine.flush().unwrap();
+        std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().metadata().unwrap().len())
+            .sum::<u64>()
+    };
+
+    let mut group = c.benchmark_group("compression");
+    group.throughput(Throughput::Elements(LOGS as u64));
+    group.measurement_time(Duration::from_secs(10));
+    for codec in [
+        "none", "snappy", "gzip:6", "zstd:1", "zstd:3", "zstd:7", "zstd:15",
+    ] {
+        println!(
+            "compression/{}: {} bytes for {} logs",
+            codec,
+            write(codec),
+            LOGS
+        );
+        group.bench_function(codec, |b| b.iter(|| write(black_box(codec))));
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    benchmark_json_validation,
+    benchmark_parquet_write,
+    benchmark_compression
+);
 criterion_main!(benches);
@@ -0,0 +1,61 @@
+// Generates the gRPC service for proto/agent.proto from the hand-written prost
+// messages in src/grpc.rs, so building does not require protoc.
+fn main() {
+    #[cfg(feature = "api")]
+    agent_api();
+    println!("cargo:rerun-if-changed=build.rs");
+}
+
+#[cfg(feature = "api")]
+fn agent_api() {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    fn method(name: &str, route: &str, input: &str, output: &str, streaming: bool) -> Method {
+        let builder = Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("crate::grpc::proto::{}", input))
+            .output_type(format!("crate::grpc::proto::{}", output))
+            .codec_path("tonic::codec::ProstCodec");
+        if streaming {
+            builder.server_streaming().build()
+        } else {
+            builder.build()
+        }
+    }
+
+    let service = Service::builder()
+        .name("AgentApi")
+        .package("daemon_rs.agent.v1")
+        .method(method(
+            "list_traces",
+            "ListTraces",
+            "TraceQuery",
+            "TraceSummary",
+            true,
+        ))
+        .method(method(
+            "get_trace",
+            "GetTrace",
+            "GetTraceRequest",
+            "Trace",
+            false,
+        ))
+        .method(method(
+            "query_logs",
+            "QueryLogs",
+            "LogQuery",
+            "LogRecord",
+            true,
+        ))
+        .method(method(
+            "tail_logs",
+            "TailLogs",
+            "LogQuery",
+            "LogRecord",
+            true,
+        ))
+        .build();
+
+    Builder::new().build_client(true).compile(&[service]);
+}
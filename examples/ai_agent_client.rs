@@ -14,6 +14,7 @@ struct TraceListResponse {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct TraceSummary {
     trace_id: String,
     root_span_name: String,
@@ -24,6 +25,7 @@ struct TraceSummary {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct TraceDetailResponse {
     trace_id: String,
     root_span: SpanNode,
@@ -31,6 +33,7 @@ struct TraceDetailResponse {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct SpanNode {
     span_id: String,
     name: String,
@@ -50,9 +53,10 @@ struct TraceAnalysis {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct SlowOperation {
     name: String,
-    duration: f64,
+    duration_ms: f64,
     span_id: String,
 }
 
@@ -131,7 +135,7 @@ fn analyze_trace(detail: &TraceDetailResponse) {
     if !detail.summary.slowest_operations.is_empty() {
         println!("     Bottlenecks:");
         for (i, op) in detail.summary.slowest_operations.iter().take(3).enumerate() {
-            println!("       {}. {} ({:.2}ms)", i + 1, op.name, op.duration);
+            println!("       {}. {} ({:.2}ms)", i + 1, op.name, op.duration_ms);
         }
     }
 
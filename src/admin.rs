@@ -0,0 +1,364 @@
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+use tracing::{debug, info, warn};
+
+use crate::audit::{self, Outcome};
+use crate::config_reload::ConfigReloadTrigger;
+use crate::handover;
+use crate::otel::LogFilterHandle;
+use crate::privileges::PrivilegeDrop;
+use crate::schema_registry::SchemaRegistry;
+use crate::schema_reload::{self, SharedSources};
+use crate::server::{ServerControl, DRAIN_TIMEOUT};
+use crate::status::DaemonStatus;
+
+/// Longest command line accepted, in bytes
+const MAX_COMMAND_BYTES: u64 = 4096;
+
+/// A command sent to the admin socket, one per line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdminCommand {
+    /// Write out every log received so far
+    FlushNow,
+    /// Close the current Parquet file; storage starts a new file on every flush, so this
+    /// flushes
+    RotateNow,
+    ReloadConfig,
+    ReloadSchema,
+    /// Replace the log filter, e.g. `debug` or `daemon_rs=trace,info`
+    SetLogLevel(String),
+    Stats,
+    /// Stop accepting connections, flush and exit
+    Drain,
+}
+
+/// Commands and their arguments, for help and error messages
+pub const COMMANDS: &str =
+    "flush-now, rotate-now, reload-config, reload-schema, set-log-level <filter>, stats, drain";
+
+impl FromStr for AdminCommand {
+    type Err = anyhow::Error;
+
+    fn from_str(line: &str) -> Result<Self> {
+        let mut words = line.split_whitespace();
+        let command = match (words.next(), words.next()) {
+            (Some("flush-now"), None) => AdminCommand::FlushNow,
+            (Some("rotate-now"), None) => AdminCommand::RotateNow,
+            (Some("reload-config"), None) => AdminCommand::ReloadConfig,
+            (Some("reload-schema"), None) => AdminCommand::ReloadSchema,
+            (Some("set-log-level"), Some(filter)) => AdminCommand::SetLogLevel(filter.to_string()),
+            (Some("stats"), None) => AdminCommand::Stats,
+            (Some("drain"), None) => AdminCommand::Drain,
+            _ => bail!(
+                "Unknown command {:?}; use one of: {}",
+                line.trim(),
+                COMMANDS
+            ),
+        };
+        if words.next().is_some() {
+            bail!("Too many arguments in {:?}", line.trim());
+        }
+        Ok(command)
+    }
+}
+
+/// The running components admin commands act on
+#[derive(Clone)]
+pub struct AdminTargets {
+    pub server: ServerControl,
+    pub config_reload: ConfigReloadTrigger,
+    pub schema_sources: SharedSources,
+    pub schema_registry: Arc<SchemaRegistry>,
+    pub log_filter: LogFilterHandle,
+    pub status: DaemonStatus,
+    pub storage_dir: PathBuf,
+}
+
+impl AdminTargets {
+    /// Run `command`, returning the reply fields besides `ok`
+    pub async fn execute(&self, command: &AdminCommand) -> Result<Value> {
+        let message = match command {
+            AdminCommand::FlushNow => {
+                format!("Flushed {} log(s)", self.server.flush().await?)
+            }
+            AdminCommand::RotateNow => format!(
+                "Flushed {} log(s); new logs go to a new file",
+                self.server.flush().await?
+            ),
+            AdminCommand::ReloadConfig => {
+                let summary = self.config_reload.reload().await?;
+                let mut message = if summary.applied.is_empty() {
+                    "Reloaded config; no reloadable settings changed".to_string()
+                } else {
+                    format!("Reloaded config; changed: {}", summary.applied.join(", "))
+                };
+                if !summary.needs_restart.is_empty() {
+                    message += &format!("; needs a restart: {}", summary.needs_restart.join(", "));
+                }
+                message
+            }
+            AdminCommand::ReloadSchema => {
+                let sources = self
+                    .schema_sources
+                    .read()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .clone();
+                schema_reload::reload(&sources, &self.schema_registry)?;
+                info!("Reloaded schemas after admin request");
+                "Reloaded schemas".to_string()
+            }
+            AdminCommand::SetLogLevel(filter) => {
+                self.log_filter
+                    .reload(crate::otel::env_filter(filter)?)
+                    .context("Failed to replace the log filter")?;
+                info!("Log filter set to {} by admin request", filter);
+                format!("Log filter set to {} until the next config reload", filter)
+            }
+            AdminCommand::Stats => {
+                return Ok(json!({ "status": self.status.report(&self.storage_dir) }));
+            }
+            AdminCommand::Drain => {
+                self.server.drain();
+                format!(
+                    "Draining: no new connections; the daemon flushes and exits once open \
+                     connections close, at most {:?} from now",
+                    DRAIN_TIMEOUT
+                )
+            }
+        };
+        Ok(json!({ "message": message }))
+    }
+
+    /// The reply line for `line` from `actor`: `{"ok": true, ...}`, or
+    /// `{"ok": false, "error": "..."}`
+    async fn reply(&self, line: &str, actor: &str) -> Value {
+        let result = match line.parse::<AdminCommand>() {
+            Ok(command) => {
+                info!("Admin command from {}: {}", actor, line.trim());
+                let result = self.execute(&command).await;
+                if command != AdminCommand::Stats {
+                    audit::record(
+                        actor,
+                        &format!("admin.{}", line.split_whitespace().next().unwrap_or("")),
+                        Outcome::of(&result),
+                        json!({ "command": line.trim(), "reply": result.as_ref().ok() }),
+                    );
+                }
+                result
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(mut reply) => {
+                reply["ok"] = Value::Bool(true);
+                reply
+            }
+            Err(e) => json!({ "ok": false, "error": format!("{:#}", e) }),
+        }
+    }
+}
+
+/// Listen for admin commands on `path`, readable and writable by its owner only: `owner` when
+/// the daemon drops privileges to it, otherwise the daemon's user
+pub fn spawn_admin_server(
+    path: &Path,
+    owner: Option<&PrivilegeDrop>,
+    targets: AdminTargets,
+) -> Result<()> {
+    // After an upgrade the socket is already bound and owned
+    let listener = match handover::take_unix("admin", path) {
+        Some(listener) => listener,
+        None => {
+            if path.exists() {
+                std::fs::remove_file(path).with_context(|| {
+                    format!("Failed to remove existing admin socket: {:?}", path)
+                })?;
+            }
+            let bound = crate::privileges::bind_with_umask(path, 0o177);
+            let listener =
+                bound.with_context(|| format!("Failed to bind admin socket: {:?}", path))?;
+            if let Some(owner) = owner {
+                std::os::unix::fs::chown(path, owner.uid(), Some(owner.gid()))
+                    .with_context(|| format!("Failed to change the owner of {:?}", path))?;
+            }
+            listener
+        }
+    };
+    handover::register("admin", &listener)?;
+    listener.set_nonblocking(true)?;
+    let listener = UnixListener::from_std(listener)?;
+    info!("Admin socket listening on {:?}", path);
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let targets = targets.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_admin_connection(stream, targets).await {
+                            debug!("Admin connection closed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => warn!("Failed to accept admin connection: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Answer each command line on `stream` until the client closes it
+async fn handle_admin_connection(stream: UnixStream, targets: AdminTargets) -> Result<()> {
+    let actor = match stream.peer_cred() {
+        Ok(cred) => match cred.pid() {
+            Some(pid) => format!("uid={} pid={}", cred.uid(), pid),
+            None => format!("uid={}", cred.uid()),
+        },
+        Err(_) => "unknown".to_string(),
+    };
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = tokio::io::BufReader::new(reader);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = (&mut reader)
+            .take(MAX_COMMAND_BYTES)
+            .read_line(&mut line)
+            .await?;
+        if read == 0 {
+            return Ok(());
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = targets.reply(&line, &actor).await;
+        writer.write_all(format!("{}\n", reply).as_bytes()).await?;
+    }
+}
+
+/// Send `command` to the admin socket at `path` and return the reply; a reply with `ok: false`
+/// is an error
+pub fn send(path: &Path, command: &str) -> Result<Value> {
+    let mut stream = std::os::unix::net::UnixStream::connect(path)
+        .with_context(|| format!("Failed to connect to admin socket {:?}", path))?;
+    stream.write_all(format!("{}\n", command).as_bytes())?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    let reply: Value = serde_json::from_str(&line)
+        .with_context(|| format!("Invalid reply from admin socket: {:?}", line))?;
+    if reply["ok"] != Value::Bool(true) {
+        bail!(
+            "{}",
+            reply["error"].as_str().unwrap_or("Admin command failed")
+        );
+    }
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::config_reload::{spawn_config_reloader, ReloadTargets};
+    use crate::otel::SamplerHandle;
+    use crate::rate_limit::RateLimiter;
+    use crate::schema::SharedSchemas;
+    use crate::schema_reload::{spawn_schema_reloader, SchemaSources};
+    use crate::server::LogServer;
+    use std::sync::RwLock;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_admin_socket_runs_commands() {
+        assert_eq!(
+            "set-log-level debug".parse::<AdminCommand>().unwrap(),
+            AdminCommand::SetLogLevel("debug".to_string())
+        );
+        assert!("set-log-level".parse::<AdminCommand>().is_err());
+        assert!("flush-now please".parse::<AdminCommand>().is_err());
+
+        let temp_dir = TempDir::new().unwrap();
+        let sources: SharedSources = Arc::new(RwLock::new(SchemaSources::default()));
+        let schemas = SharedSchemas::new(SchemaSources::default().build().unwrap());
+        let registry = Arc::new(SchemaRegistry::new(
+            temp_dir.path().join("registry"),
+            schemas.clone(),
+        ));
+        let (_layer, log_filter) = crate::otel::log_filter("info").unwrap();
+        let server = LogServer::new(temp_dir.path().join("ingest.sock"), schemas, 10, 5);
+        let schema_reload = spawn_schema_reloader(sources.clone(), registry.clone());
+        let config_reload = spawn_config_reloader(
+            Config::default(),
+            || {
+                Ok(Config {
+                    flush_interval_secs: 9,
+                    batch_size: 10,
+                    ..Config::default()
+                })
+            },
+            ReloadTargets {
+                flush_interval: server.flush_interval(),
+                rate_limiter: Arc::new(RateLimiter::new(10.0, 20)),
+                sampler: SamplerHandle::new(1.0),
+                log_filter: log_filter.clone(),
+                schema_sources: sources.clone(),
+                schema_reload,
+                key_rotation: Default::default(),
+            },
+        )
+        .unwrap();
+
+        let path = temp_dir.path().join("admin.sock");
+        spawn_admin_server(
+            &path,
+            None,
+            AdminTargets {
+                server: server.control(),
+                config_reload,
+                schema_sources: sources,
+                schema_registry: registry,
+                log_filter,
+                status: DaemonStatus::new(),
+                storage_dir: temp_dir.path().to_path_buf(),
+            },
+        )
+        .unwrap();
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let send = |command: &'static str| {
+            let path = path.clone();
+            tokio::task::spawn_blocking(move || send(&path, command))
+        };
+        let reply = send("reload-config").await.unwrap().unwrap();
+        assert_eq!(
+            reply["message"],
+            "Reloaded config; changed: flush_interval_secs; needs a restart: batch_size"
+        );
+        assert_eq!(server.flush_interval().get().as_secs(), 9);
+
+        let reply = send("stats").await.unwrap().unwrap();
+        assert_eq!(reply["status"]["ingested_total"], 0);
+        send("set-log-level daemon_rs=debug")
+            .await
+            .unwrap()
+            .unwrap();
+        send("reload-schema").await.unwrap().unwrap();
+
+        let error = send("set-log-level [nope").await.unwrap().unwrap_err();
+        assert!(
+            error.to_string().contains("Invalid log filter"),
+            "{}",
+            error
+        );
+        let error = send("restart").await.unwrap().unwrap_err();
+        assert!(error.to_string().contains("flush-now"), "{}", error);
+    }
+}
@@ -1,29 +1,65 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::{IntoResponse, Json},
-    routing::get,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Extension, Path, Query, State,
+    },
+    http::{HeaderMap, StatusCode},
+    middleware,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
+    routing::{get, post},
     Router,
 };
-use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use axum_server::tls_rustls::RustlsConfig;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use tokio::net::TcpListener;
-use tower_http::cors::CorsLayer;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower_http::compression::CompressionLayer;
 use tower_http::trace::TraceLayer;
-use tracing::info;
+use tracing::{error, info, warn};
+use utoipa::{IntoParams, OpenApi, ToSchema};
 
-use crate::trace_storage::{SpanStatus, TraceSpan};
+use crate::anomaly::{Anomaly, AnomalyDetector, AnomalyMetric};
+use crate::api_auth::{cors_layer, redacted_uri, require_auth, ApiActor, ApiAuth};
+use crate::api_config::{ApiConfig, TlsConfig};
+use crate::audit::{self, Outcome};
+use crate::federation::{FederatedQuery, FORWARDED_HEADER};
+use crate::flamegraph::{folded_stacks, speedscope};
+use crate::keys::KeyRotation;
+use crate::live::{is_error_level, LiveFeed};
+use crate::operations::{OperationAggregator, OperationStats};
+use crate::query::{LogFilter, LogRecord, LogStats};
+use crate::rate_limit::{enforce_rate_limit, RateLimiter};
+use crate::schema_registry::{RegisteredSchema, RegistryError, SchemaRegistry, SchemaVersions};
+use crate::status::{DaemonStatus, StatusReport};
+use crate::trace_export::{to_jaeger_json, to_otlp_json};
+use crate::trace_index::{spawn_refresh_task, TraceIndex, TraceSummary, DEFAULT_REFRESH_INTERVAL};
+use crate::trace_storage::{SpanFilter, SpanStatus, TraceSpan};
 
 /// AI Agent API server state
 #[derive(Clone)]
 pub struct ApiState {
     pub trace_storage_dir: std::path::PathBuf,
+    pub log_storage_dir: std::path::PathBuf,
+    pub index: Arc<TraceIndex>,
+    /// The daemon's own logs and any federated sources
+    pub query_engine: Arc<FederatedQuery>,
+    pub live: LiveFeed,
+    pub status: DaemonStatus,
+    pub schema_registry: Option<Arc<SchemaRegistry>>,
 }
 
 /// Query parameters for trace listing
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct TraceQueryParams {
     #[serde(default)]
     pub start_time: Option<String>,
@@ -35,40 +71,141 @@ pub struct TraceQueryParams {
     pub has_error: Option<bool>,
     #[serde(default = "default_limit")]
     pub limit: usize,
+    /// Opaque cursor from a previous response's `next_cursor`
+    #[serde(default)]
+    pub cursor: Option<String>,
+    /// Number of traces to skip after the cursor position
+    #[serde(default)]
+    pub offset: Option<usize>,
+    /// Only traces containing a span with this name
+    #[serde(default)]
+    pub span_name: Option<String>,
+    /// `attr.<key>=<value>` filters, collected from the raw query string
+    #[serde(skip)]
+    #[param(ignore)]
+    pub attributes: Vec<(String, String)>,
 }
 
 fn default_limit() -> usize {
     100
 }
 
+/// Parse an optional RFC 3339 query parameter
+fn parse_timestamp(
+    value: &Option<String>,
+) -> Result<Option<chrono::DateTime<chrono::FixedOffset>>> {
+    value
+        .as_deref()
+        .map(|v| {
+            chrono::DateTime::parse_from_rfc3339(v)
+                .map_err(|e| anyhow::anyhow!("Invalid timestamp '{}': {}", v, e))
+        })
+        .transpose()
+}
+
+/// Query string prefix for attribute filters
+const ATTRIBUTE_FILTER_PREFIX: &str = "attr.";
+
+impl TraceQueryParams {
+    /// Collect `attr.<key>=<value>` pairs from the raw query pairs
+    pub(crate) fn with_attribute_filters(mut self, pairs: Vec<(String, String)>) -> Self {
+        self.attributes = pairs
+            .into_iter()
+            .filter_map(|(key, value)| {
+                key.strip_prefix(ATTRIBUTE_FILTER_PREFIX)
+                    .map(|k| (k.to_string(), value))
+            })
+            .collect();
+        self
+    }
+
+    /// Parse `start_time`/`end_time` (RFC 3339) into microsecond bounds
+    pub(crate) fn time_range(&self) -> Result<(Option<i64>, Option<i64>)> {
+        Ok((
+            parse_timestamp(&self.start_time)?.map(|dt| dt.timestamp_micros()),
+            parse_timestamp(&self.end_time)?.map(|dt| dt.timestamp_micros()),
+        ))
+    }
+
+    fn has_span_filters(&self) -> bool {
+        self.span_name.is_some() || !self.attributes.is_empty()
+    }
+
+    /// Check one span against each span-level criterion.
+    ///
+    /// Index 0 is the span name, followed by one entry per attribute filter.
+    fn span_matches(&self, span: &TraceSpan) -> Vec<bool> {
+        let mut matches = Vec::with_capacity(1 + self.attributes.len());
+        matches.push(
+            self.span_name
+                .as_ref()
+                .is_none_or(|name| span.name == *name),
+        );
+        for (key, value) in &self.attributes {
+            matches.push(span.attributes.get(key) == Some(value));
+        }
+        matches
+    }
+
+    /// Which of `candidates` have every span-level criterion met by at least one span starting
+    /// in `[start_us, end_us)`; only the files and rows of the candidate traces in that range
+    /// are read
+    fn matching_traces(
+        &self,
+        index: &TraceIndex,
+        candidates: HashSet<String>,
+        start_us: Option<i64>,
+        end_us: Option<i64>,
+    ) -> Result<HashSet<String>> {
+        let mut satisfied: HashMap<String, Vec<bool>> = HashMap::new();
+        let filter = SpanFilter {
+            trace_ids: Some(candidates),
+            start_us,
+            end_us,
+        };
+
+        index.for_each_span(&filter, |span| {
+            let matches = self.span_matches(span);
+            let entry = satisfied
+                .entry(span.trace_id.clone())
+                .or_insert_with(|| vec![false; matches.len()]);
+            for (seen, matched) in entry.iter_mut().zip(matches) {
+                *seen |= matched;
+            }
+        })?;
+
+        Ok(satisfied
+            .into_iter()
+            .filter(|(_, seen)| seen.iter().all(|s| *s))
+            .map(|(trace_id, _)| trace_id)
+            .collect())
+    }
+}
+
 /// Response for trace listing
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct TraceListResponse {
     pub traces: Vec<TraceSummary>,
     pub total_count: usize,
-}
-
-/// Summary of a trace for listing
-#[derive(Debug, Serialize)]
-pub struct TraceSummary {
-    pub trace_id: String,
-    pub root_span_name: String,
-    pub start_time: String,
-    pub total_duration_ms: f64,
-    pub span_count: usize,
-    pub error_count: usize,
+    /// Cursor for the next page, absent on the last page
+    pub next_cursor: Option<String>,
 }
 
 /// Complete trace tree response
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct TraceDetailResponse {
     pub trace_id: String,
+    /// Every parent span is stored and the trace has a single root; when false, spans may
+    /// still be on their way and the trace is worth fetching again later
+    pub is_complete: bool,
+    /// Parent span ids referenced by spans of the trace but not stored
+    pub missing_parents: Vec<String>,
     pub root_span: SpanNode,
     pub summary: TraceAnalysis,
 }
 
 /// Hierarchical span node for trace tree
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct SpanNode {
     pub span_id: String,
     pub name: String,
@@ -78,9 +215,15 @@ pub struct SpanNode {
     pub events: Vec<SpanEventInfo>,
     pub status: String,
     pub children: Vec<SpanNode>,
+    /// The span's parent is not in the trace
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub orphan: bool,
+    /// Stands in for a missing root span; its children are the trace's top-level spans
+    #[serde(rename = "virtual", skip_serializing_if = "std::ops::Not::not")]
+    pub is_virtual: bool,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct SpanEventInfo {
     pub name: String,
     pub timestamp: String,
@@ -88,7 +231,7 @@ pub struct SpanEventInfo {
 }
 
 /// AI-friendly trace analysis
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct TraceAnalysis {
     pub total_spans: usize,
     pub total_duration_ms: f64,
@@ -96,69 +239,606 @@ pub struct TraceAnalysis {
     pub critical_path_ms: f64,
     pub span_breakdown: HashMap<String, usize>,
     pub slowest_operations: Vec<SlowOperation>,
+    /// Spans whose parent is not in the trace, attached to the root with `orphan` set
+    pub orphan_spans: usize,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct SlowOperation {
     pub name: String,
     pub duration_ms: f64,
     pub span_id: String,
 }
 
-/// Start the AI Agent API server
-pub async fn start_api_server(port: u16, trace_storage_dir: std::path::PathBuf) -> Result<()> {
-    let state = ApiState { trace_storage_dir };
+/// Query parameters for anomaly detection
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AnomalyQueryParams {
+    /// Length of the recent window that is scored
+    #[serde(default = "default_window_minutes")]
+    pub window_minutes: u64,
+    /// History used as the baseline, split into windows of `window_minutes`
+    #[serde(default = "default_baseline_hours")]
+    pub baseline_hours: u64,
+    /// Minimum z-score reported
+    #[serde(default = "default_anomaly_threshold")]
+    pub threshold: f64,
+}
 
-    let app = Router::new()
+fn default_window_minutes() -> u64 {
+    15
+}
+
+fn default_baseline_hours() -> u64 {
+    24
+}
+
+fn default_anomaly_threshold() -> f64 {
+    3.0
+}
+
+/// Longest baseline an anomaly query may use
+const MAX_BASELINE_HOURS: u64 = 24 * 366;
+
+/// Most baseline windows an anomaly query may split its baseline into
+const MAX_BASELINE_WINDOWS: u64 = 10_000;
+
+/// Scored anomalies per operation
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AnomalyResponse {
+    pub window_start: String,
+    pub window_end: String,
+    pub baseline_start: String,
+    pub anomalies: Vec<Anomaly>,
+}
+
+/// Query parameters for the trace volume time series
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct VolumeQueryParams {
+    /// Bucket width such as `30s`, `5m`, `1h` or `1d`
+    #[serde(default = "default_volume_bucket")]
+    pub bucket: String,
+    /// RFC 3339; defaults to 24 hours before `end_time`
+    #[serde(default)]
+    pub start_time: Option<String>,
+    /// RFC 3339; defaults to now
+    #[serde(default)]
+    pub end_time: Option<String>,
+}
+
+fn default_volume_bucket() -> String {
+    "5m".to_string()
+}
+
+/// Most buckets a single volume query may return
+const MAX_VOLUME_BUCKETS: i64 = 10_000;
+
+/// Trace and error counts for traces starting in one bucket
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct VolumeBucket {
+    /// Bucket start (RFC 3339)
+    pub start_time: String,
+    pub trace_count: usize,
+    /// Traces with at least one error span
+    pub error_count: usize,
+    pub error_rate: f64,
+}
+
+/// Trace volume time series
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VolumeResponse {
+    pub bucket_seconds: i64,
+    pub buckets: Vec<VolumeBucket>,
+}
+
+/// Parse a duration like `30s`, `5m`, `1h` or `1d` into microseconds
+fn parse_bucket(value: &str) -> Result<i64> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid bucket '{}'", value))?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        _ => anyhow::bail!("Invalid bucket unit in '{}': use s, m, h or d", value),
+    };
+    if amount <= 0 {
+        anyhow::bail!("Bucket must be greater than zero");
+    }
+    amount
+        .checked_mul(unit_secs * 1_000_000)
+        .ok_or_else(|| anyhow::anyhow!("Bucket '{}' is too large", value))
+}
+
+/// Count traces per bucket over `[start_us, end_us)`, including empty buckets
+fn volume_buckets(
+    summaries: &[TraceSummary],
+    start_us: i64,
+    end_us: i64,
+    bucket_us: i64,
+) -> Vec<VolumeBucket> {
+    // Rounds up without adding the bucket to the range, which could overflow
+    let count = (end_us.saturating_sub(start_us).max(0) as u64).div_ceil(bucket_us as u64) as usize;
+    let mut counts = vec![(0usize, 0usize); count];
+
+    for summary in summaries {
+        if summary.start_time_us < start_us || summary.start_time_us >= end_us {
+            continue;
+        }
+        let slot = &mut counts[((summary.start_time_us - start_us) / bucket_us) as usize];
+        slot.0 += 1;
+        if summary.error_count > 0 {
+            slot.1 += 1;
+        }
+    }
+
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, (traces, errors))| VolumeBucket {
+            start_time: chrono::DateTime::from_timestamp_micros(start_us + i as i64 * bucket_us)
+                .unwrap_or_default()
+                .to_rfc3339(),
+            trace_count: traces,
+            error_count: errors,
+            error_rate: if traces == 0 {
+                0.0
+            } else {
+                errors as f64 / traces as f64
+            },
+        })
+        .collect()
+}
+
+/// Output format of the flamegraph endpoint
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FlamegraphFormat {
+    /// `root;child;leaf <self µs>` lines for flamegraph.pl / inferno
+    #[default]
+    Folded,
+    /// speedscope JSON file
+    Speedscope,
+}
+
+/// Query parameters for the flamegraph endpoint
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FlamegraphQueryParams {
+    #[serde(default)]
+    pub format: FlamegraphFormat,
+}
+
+/// Wire format of an exported trace
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// OTLP/JSON `TracesData`
+    #[default]
+    Otlp,
+    /// Jaeger query API JSON, importable in the Jaeger UI
+    Jaeger,
+}
+
+/// Query parameters for trace export
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQueryParams {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// Query parameters for the per-operation breakdown
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OperationQueryParams {
+    /// RFC 3339; defaults to `window_minutes` before `end_time`
+    #[serde(default)]
+    pub start_time: Option<String>,
+    /// RFC 3339; defaults to now
+    #[serde(default)]
+    pub end_time: Option<String>,
+    #[serde(default = "default_operations_window_minutes")]
+    pub window_minutes: u64,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+fn default_operations_window_minutes() -> u64 {
+    60
+}
+
+/// Per-operation stats across all traces in a window
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OperationsResponse {
+    pub start_time: String,
+    pub end_time: String,
+    pub operations: Vec<OperationStats>,
+    pub total_operations: usize,
+}
+
+/// Query parameters for log listing and stats
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LogQueryParams {
+    #[serde(default)]
+    pub start_time: Option<String>,
+    #[serde(default)]
+    pub end_time: Option<String>,
+    #[serde(default)]
+    pub level: Option<String>,
+    #[serde(default)]
+    pub service: Option<String>,
+    #[serde(default)]
+    pub trace_id: Option<String>,
+    /// Case-insensitive substring of the message
+    #[serde(default)]
+    pub contains: Option<String>,
+    /// Filter expression (`level:error service:api text`); explicit parameters take precedence
+    #[serde(default)]
+    pub filter: Option<String>,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+impl LogQueryParams {
+    pub(crate) fn to_filter(&self) -> Result<LogFilter> {
+        let expression = self
+            .filter
+            .as_deref()
+            .map(LogFilter::parse)
+            .unwrap_or_default();
+
+        Ok(LogFilter {
+            start_ms: parse_timestamp(&self.start_time)?.map(|dt| dt.timestamp_millis()),
+            end_ms: parse_timestamp(&self.end_time)?.map(|dt| dt.timestamp_millis()),
+            level: self.level.clone().or(expression.level),
+            service: self.service.clone().or(expression.service),
+            trace_id: self.trace_id.clone().or(expression.trace_id),
+            trace_ids: None,
+            contains: self.contains.clone().or(expression.contains),
+        })
+    }
+}
+
+/// Response for log listing
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LogListResponse {
+    pub logs: Vec<LogRecord>,
+    pub total_count: usize,
+    /// Federated sources that could not be queried and are missing from the results
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unavailable_sources: Vec<String>,
+}
+
+/// OpenAPI description of the AI Agent API
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "daemon_rs AI Agent API"),
+    paths(
+        list_traces,
+        get_trace_detail,
+        search_traces,
+        trace_volume,
+        trace_flamegraph,
+        export_trace,
+        detect_anomalies,
+        list_operations,
+        list_logs,
+        log_stats,
+        tail_logs,
+        stream_events,
+        list_schemas,
+        get_schema,
+        register_schema,
+        activate_schema,
+        daemon_status
+    ),
+    components(schemas(
+        TraceListResponse,
+        TraceSummary,
+        TraceDetailResponse,
+        SpanNode,
+        SpanEventInfo,
+        TraceAnalysis,
+        SlowOperation,
+        AnomalyResponse,
+        Anomaly,
+        AnomalyMetric,
+        OperationsResponse,
+        OperationStats,
+        VolumeResponse,
+        VolumeBucket,
+        FlamegraphFormat,
+        ExportFormat,
+        LogListResponse,
+        LogRecord,
+        LogStats,
+        SchemaVersions,
+        RegisteredSchema,
+        StatusReport
+    ))
+)]
+pub struct ApiDoc;
+
+/// Swagger UI page rendering `/api/openapi.json`
+const SWAGGER_UI_HTML: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+  <title>daemon_rs AI Agent API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>SwaggerUIBundle({ url: "/api/openapi.json", dom_id: "#swagger-ui" });</script>
+</body>
+</html>"##;
+
+/// Start the AI Agent API server; `limiter` enforces `config.rate_limit` and is shared so the
+/// limits can change while it runs
+#[allow(clippy::too_many_arguments)] // each is a separate piece of daemon state
+pub async fn start_api_server(
+    addr: SocketAddr,
+    trace_storage_dir: std::path::PathBuf,
+    log_storage_dir: std::path::PathBuf,
+    live: LiveFeed,
+    status: DaemonStatus,
+    schema_registry: Option<Arc<SchemaRegistry>>,
+    config: ApiConfig,
+    limiter: Arc<RateLimiter>,
+    key_rotation: KeyRotation,
+) -> Result<()> {
+    let cors = cors_layer(&config.cors_origins)?;
+    let auth = Arc::new(ApiAuth::new(config.tokens));
+    if !auth.is_enabled() {
+        warn!("AI API authentication is disabled; configure [[api.tokens]] to enable it");
+    }
+
+    let index = Arc::new(TraceIndex::new(trace_storage_dir.clone()));
+    let initial = index.clone();
+    tokio::task::spawn_blocking(move || initial.refresh()).await??;
+    spawn_refresh_task(index.clone(), DEFAULT_REFRESH_INTERVAL, live.clone());
+
+    let state = ApiState {
+        trace_storage_dir,
+        index,
+        query_engine: Arc::new(
+            FederatedQuery::new(log_storage_dir.clone(), &config.federation)?
+                .with_query_cache(&config.query_cache),
+        ),
+        log_storage_dir,
+        live,
+        status,
+        schema_registry,
+    };
+
+    let rustls = match config.tls {
+        Some(tls) => {
+            let (cert, key) = tls.load().await?;
+            let rustls = RustlsConfig::from_pem(cert, key).await.with_context(|| {
+                format!(
+                    "Failed to load TLS certificate {:?} / key from {}",
+                    tls.cert_path,
+                    tls.key_source()
+                )
+            })?;
+            tokio::spawn(rotate_tls_keys(tls, rustls.clone(), key_rotation));
+            Some(rustls)
+        }
+        None => None,
+    };
+    // Both listeners share one concurrency cap and one set of per-client rates
+    let concurrency = GlobalConcurrencyLimitLayer::new(config.rate_limit.max_concurrent.max(1));
+
+    if let Some(grpc_addr) = config.grpc_bind {
+        let grpc = crate::grpc::router(state.clone(), auth.clone())
+            .route_layer(concurrency.clone())
+            .route_layer(middleware::from_fn_with_state(
+                limiter.clone(),
+                enforce_rate_limit,
+            ));
+        let rustls = rustls.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve("grpc", "AI Agent gRPC API", grpc_addr, grpc, rustls).await {
+                error!("AI Agent gRPC API error: {}", e);
+            }
+        });
+    }
+
+    // Trace responses only change when trace files do, so they support conditional requests
+    let traces = Router::new()
         .route("/api/traces", get(list_traces))
         .route("/api/traces/:trace_id", get(get_trace_detail))
         .route("/api/traces/search", get(search_traces))
-        .route("/api/health", get(health_check))
-        .layer(CorsLayer::permissive())
-        .layer(TraceLayer::new_for_http())
-        .with_state(state);
+        .route("/api/traces/volume", get(trace_volume))
+        .route("/api/traces/:trace_id/flamegraph", get(trace_flamegraph))
+        .route("/api/traces/:trace_id/export", get(export_trace))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            conditional_get,
+        ));
 
-    let addr = format!("0.0.0.0:{}", port);
-    info!("AI Agent API listening on http://{}", addr);
+    let protected = Router::new()
+        .merge(traces)
+        .route("/api/anomalies", get(detect_anomalies))
+        .route("/api/operations", get(list_operations))
+        .route("/api/logs", get(list_logs))
+        .route("/api/logs/stats", get(log_stats))
+        .route("/api/logs/tail", get(tail_logs))
+        .route("/api/stream", get(stream_events))
+        .route("/api/schemas", get(list_schemas))
+        .route("/api/schemas/:name/versions", post(register_schema))
+        .route("/api/schemas/:name/versions/:version", get(get_schema))
+        .route(
+            "/api/schemas/:name/versions/:version/activate",
+            post(activate_schema),
+        );
+    #[cfg(feature = "graphql")]
+    let protected = protected.merge(crate::graphql::router(state.clone()));
+    let protected = protected
+        // Layers run bottom-up: auth, then the client's rate, then a global concurrency cap
+        .route_layer(concurrency)
+        .route_layer(middleware::from_fn_with_state(limiter, enforce_rate_limit))
+        .route_layer(middleware::from_fn_with_state(auth, require_auth));
 
-    let listener = TcpListener::bind(&addr).await?;
-    axum::serve(listener, app).await?;
+    // Health and API description stay reachable without a token
+    let app = Router::new()
+        .route("/api/health", get(daemon_status))
+        .route("/api/status", get(daemon_status))
+        .route("/api/openapi.json", get(openapi_spec))
+        .route("/api/docs", get(swagger_ui));
+    #[cfg(feature = "graphql")]
+    let app = app.route("/api/graphiql", get(crate::graphql::graphiql));
+    let app = app
+        .merge(protected)
+        .layer(CompressionLayer::new())
+        .layer(cors)
+        // The default request span, but without tokens passed in the query string
+        .layer(
+            TraceLayer::new_for_http().make_span_with(|req: &axum::extract::Request| {
+                tracing::debug_span!(
+                    "request",
+                    method = %req.method(),
+                    uri = %redacted_uri(req.uri()),
+                    version = ?req.version(),
+                )
+            }),
+        )
+        .with_state(state);
 
+    serve("api", "AI Agent API", addr, app, rustls).await
+}
+
+/// Serve `app` on `addr`, the listener handed over as `name`, over TLS when `rustls` is set
+async fn serve(
+    name: &str,
+    label: &str,
+    addr: SocketAddr,
+    app: Router,
+    rustls: Option<RustlsConfig>,
+) -> Result<()> {
+    let listener = crate::handover::bind_tcp(name, addr).await?;
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    match rustls {
+        Some(rustls) => {
+            info!("{} listening on https://{}", label, addr);
+            axum_server::from_tcp_rustls(listener.into_std()?, rustls)
+                .serve(app)
+                .await?;
+        }
+        None => {
+            info!("{} listening on http://{}", label, addr);
+            axum::serve(listener, app).await?;
+        }
+    }
     Ok(())
 }
 
-/// Health check endpoint
-async fn health_check() -> impl IntoResponse {
-    Json(serde_json::json!({
-        "status": "healthy",
-        "service": "daemon_rs_ai_api"
-    }))
+/// Load the certificate and key again on every rotation request; on failure the ones in use
+/// are kept
+async fn rotate_tls_keys(tls: TlsConfig, rustls: RustlsConfig, rotation: KeyRotation) {
+    loop {
+        rotation.requested().await;
+        let reloaded = match tls.load().await {
+            Ok((cert, key)) => rustls
+                .reload_from_pem(cert, key)
+                .await
+                .context("Invalid certificate or key"),
+            Err(e) => Err(e),
+        };
+        match reloaded {
+            Ok(()) => info!("Reloaded the AI API TLS certificate and key"),
+            Err(e) => warn!(
+                "Failed to reload the AI API TLS key from {}; keeping the current one: {:#}",
+                tls.key_source(),
+                e
+            ),
+        }
+    }
+}
+
+/// OpenAPI 3 document
+async fn openapi_spec() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Swagger UI over the OpenAPI document
+async fn swagger_ui() -> axum::response::Html<&'static str> {
+    axum::response::Html(SWAGGER_UI_HTML)
+}
+
+/// Ingest and storage health; `/api/health` is an alias for probes
+#[utoipa::path(
+    get,
+    path = "/api/status",
+    tag = "health",
+    responses(
+        (status = 200, description = "Daemon is healthy", body = StatusReport),
+        (status = 503, description = "Daemon is unhealthy; `problems` lists why", body = StatusReport)
+    )
+)]
+async fn daemon_status(State(state): State<ApiState>) -> (StatusCode, Json<StatusReport>) {
+    // Served without a token, so without storage error messages
+    let report = state.status.public_report(&state.log_storage_dir);
+    let code = if report.is_healthy() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (code, Json(report))
 }
 
 /// List traces with filtering
+#[utoipa::path(
+    get,
+    path = "/api/traces",
+    tag = "traces",
+    params(TraceQueryParams),
+    responses(
+        (status = 200, description = "Traces newest first; `attr.<key>=<value>` query parameters filter by span attribute", body = TraceListResponse),
+        (status = 400, description = "Invalid cursor or timestamp")
+    )
+)]
 async fn list_traces(
     State(state): State<ApiState>,
     Query(params): Query<TraceQueryParams>,
+    Query(pairs): Query<Vec<(String, String)>>,
 ) -> Result<Json<TraceListResponse>, (StatusCode, String)> {
-    let spans = load_all_spans(&state.trace_storage_dir)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let params = params.with_attribute_filters(pairs);
+    // Span name and attribute filters read trace files, so off the async workers
+    tokio::task::spawn_blocking(move || query_traces(&state, &params))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map(Json)
+}
 
-    // Group spans by trace_id
-    let mut traces_map: HashMap<String, Vec<TraceSpan>> = HashMap::new();
-    for span in spans {
-        traces_map
-            .entry(span.trace_id.clone())
-            .or_default()
-            .push(span);
-    }
+/// Filter, order and paginate indexed traces; shared by the HTTP and gRPC APIs. Blocks on
+/// file reads when span filters are set, so async callers run it on a blocking task
+pub(crate) fn query_traces(
+    state: &ApiState,
+    params: &TraceQueryParams,
+) -> Result<TraceListResponse, (StatusCode, String)> {
+    let (start_us, end_us) = params
+        .time_range()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let mut summaries = state.index.summaries();
 
-    // Build trace summaries
-    let mut summaries: Vec<TraceSummary> = traces_map
-        .into_iter()
-        .map(|(trace_id, spans)| build_trace_summary(trace_id, spans))
-        .collect();
+    // Apply filters; the time range is start-inclusive, end-exclusive
+    if start_us.is_some() || end_us.is_some() {
+        summaries.retain(|s| {
+            start_us.is_none_or(|start| s.start_time_us >= start)
+                && end_us.is_none_or(|end| s.start_time_us < end)
+        });
+    }
 
-    // Apply filters
     if let Some(min_duration) = params.min_duration_ms {
         summaries.retain(|s| s.total_duration_ms >= min_duration as f64);
     }
@@ -169,30 +849,177 @@ async fn list_traces(
         }
     }
 
-    // Sort by start time (most recent first)
-    summaries.sort_by(|a, b| b.start_time.cmp(&a.start_time));
+    // Span name and attribute filters need the spans themselves, so they run last and only
+    // scan the traces the summary filters kept
+    if params.has_span_filters() && !summaries.is_empty() {
+        let candidates = summaries.iter().map(|s| s.trace_id.clone()).collect();
+        let matching = params
+            .matching_traces(&state.index, candidates, start_us, end_us)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        summaries.retain(|s| matching.contains(&s.trace_id));
+    }
+
+    // Sort by start time (most recent first), trace id breaks ties for a stable order
+    summaries.sort_by(|a, b| {
+        b.start_time_us
+            .cmp(&a.start_time_us)
+            .then_with(|| a.trace_id.cmp(&b.trace_id))
+    });
 
     let total_count = summaries.len();
-    summaries.truncate(params.limit);
+    let (traces, next_cursor) =
+        paginate(summaries, params).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
 
-    Ok(Json(TraceListResponse {
-        traces: summaries,
+    Ok(TraceListResponse {
+        traces,
         total_count,
+        next_cursor,
+    })
+}
+
+/// Apply cursor, offset and limit to summaries already in list order
+fn paginate(
+    summaries: Vec<TraceSummary>,
+    params: &TraceQueryParams,
+) -> Result<(Vec<TraceSummary>, Option<String>)> {
+    let position = params.cursor.as_deref().map(decode_cursor).transpose()?;
+
+    let mut remaining = summaries
+        .into_iter()
+        .filter(|s| match &position {
+            // Keep only summaries ordered strictly after the cursor
+            Some((start_us, trace_id)) => {
+                s.start_time_us < *start_us
+                    || (s.start_time_us == *start_us && s.trace_id > *trace_id)
+            }
+            None => true,
+        })
+        .skip(params.offset.unwrap_or(0))
+        .peekable();
+
+    let page: Vec<TraceSummary> = remaining.by_ref().take(params.limit).collect();
+    let next_cursor = match (remaining.peek(), page.last()) {
+        (Some(_), Some(last)) => Some(encode_cursor(last)),
+        _ => None,
+    };
+
+    Ok((page, next_cursor))
+}
+
+fn encode_cursor(summary: &TraceSummary) -> String {
+    format!("{}:{}", summary.start_time_us, summary.trace_id)
+}
+
+fn decode_cursor(cursor: &str) -> Result<(i64, String)> {
+    let (start_us, trace_id) = cursor
+        .split_once(':')
+        .ok_or_else(|| anyhow::anyhow!("Invalid cursor: {}", cursor))?;
+    let start_us = start_us
+        .parse::<i64>()
+        .map_err(|_| anyhow::anyhow!("Invalid cursor: {}", cursor))?;
+    Ok((start_us, trace_id.to_string()))
+}
+
+/// Traces and errors per time bucket
+#[utoipa::path(
+    get,
+    path = "/api/traces/volume",
+    tag = "traces",
+    params(VolumeQueryParams),
+    responses(
+        (status = 200, description = "Buckets oldest first, including empty ones", body = VolumeResponse),
+        (status = 400, description = "Invalid bucket or time range")
+    )
+)]
+async fn trace_volume(
+    State(state): State<ApiState>,
+    Query(params): Query<VolumeQueryParams>,
+) -> Result<Json<VolumeResponse>, (StatusCode, String)> {
+    let bad_request = |e: anyhow::Error| (StatusCode::BAD_REQUEST, e.to_string());
+    let bucket_us = parse_bucket(&params.bucket).map_err(bad_request)?;
+    let end_us = parse_timestamp(&params.end_time)
+        .map_err(bad_request)?
+        .map(|dt| dt.timestamp_micros())
+        .unwrap_or_else(|| chrono::Utc::now().timestamp_micros());
+    let start_us = parse_timestamp(&params.start_time)
+        .map_err(bad_request)?
+        .map(|dt| dt.timestamp_micros())
+        .unwrap_or(end_us - 24 * 3600 * 1_000_000);
+
+    // Align to bucket boundaries so repeated queries line up
+    let start_us = start_us
+        .checked_sub(start_us.rem_euclid(bucket_us))
+        .ok_or_else(|| bad_request(anyhow::anyhow!("Bucket is too large for start_time")))?;
+    if start_us >= end_us {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "start_time must be before end_time".to_string(),
+        ));
+    }
+    if end_us
+        .checked_sub(start_us)
+        .is_none_or(|range_us| range_us / bucket_us >= MAX_VOLUME_BUCKETS)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Time range spans more than {} buckets; use a larger bucket",
+                MAX_VOLUME_BUCKETS
+            ),
+        ));
+    }
+
+    Ok(Json(VolumeResponse {
+        bucket_seconds: bucket_us / 1_000_000,
+        buckets: volume_buckets(&state.index.summaries(), start_us, end_us, bucket_us),
     }))
 }
 
 /// Get detailed trace tree
+#[utoipa::path(
+    get,
+    path = "/api/traces/{trace_id}",
+    tag = "traces",
+    params(("trace_id" = String, Path, description = "Trace id")),
+    responses(
+        (status = 200, description = "Trace tree and analysis", body = TraceDetailResponse),
+        (status = 404, description = "Trace not found")
+    )
+)]
 async fn get_trace_detail(
     State(state): State<ApiState>,
     Path(trace_id): Path<String>,
 ) -> Result<Json<TraceDetailResponse>, (StatusCode, String)> {
-    let spans = load_all_spans(&state.trace_storage_dir)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let trace_spans = find_trace(&state, &trace_id).await?;
+    let (root_span, orphan_spans) = build_trace_tree(&trace_spans);
+    let summary = TraceAnalysis {
+        orphan_spans,
+        ..analyze_trace(&trace_spans)
+    };
 
-    let trace_spans: Vec<TraceSpan> = spans
-        .into_iter()
-        .filter(|s| s.trace_id == trace_id)
-        .collect();
+    Ok(Json(TraceDetailResponse {
+        trace_id,
+        is_complete: is_complete(&trace_spans),
+        missing_parents: missing_parents(&trace_spans),
+        root_span,
+        summary,
+    }))
+}
+
+/// Load every span of a trace, or 404 when it is not stored
+pub(crate) async fn find_trace(
+    state: &ApiState,
+    trace_id: &str,
+) -> Result<Vec<TraceSpan>, (StatusCode, String)> {
+    let (index, live, id) = (
+        state.index.clone(),
+        state.live.clone(),
+        trace_id.to_string(),
+    );
+    let trace_spans = tokio::task::spawn_blocking(move || load_trace(&index, &live, &id))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     if trace_spans.is_empty() {
         return Err((
@@ -201,201 +1028,703 @@ async fn get_trace_detail(
         ));
     }
 
-    let root_span = build_trace_tree(&trace_spans);
-    let summary = analyze_trace(&trace_spans);
+    Ok(trace_spans)
+}
 
-    Ok(Json(TraceDetailResponse {
-        trace_id,
-        root_span,
-        summary,
+/// Spans of `trace_id`, refreshing the index first when the trace looks incomplete, since the
+/// trace or the rest of it may have been written since the last background refresh. Traces
+/// found by the refresh are published to `/api/stream` as the background refresh does.
+fn load_trace(index: &TraceIndex, live: &LiveFeed, trace_id: &str) -> Result<Vec<TraceSpan>> {
+    let mut trace_spans = index.load_trace(trace_id)?;
+    if !is_complete(&trace_spans) && index.claim_refresh(trace_id, Instant::now()) {
+        let refreshed = index.refresh()?;
+        let updated = refreshed.iter().any(|s| s.trace_id == trace_id);
+        for summary in refreshed {
+            live.publish_trace(summary);
+        }
+        if trace_spans.is_empty() || updated {
+            trace_spans = index.load_trace(trace_id)?;
+        }
+    }
+    Ok(trace_spans)
+}
+
+/// Trace span tree as flamegraph data, weighted by span self time
+#[utoipa::path(
+    get,
+    path = "/api/traces/{trace_id}/flamegraph",
+    tag = "traces",
+    params(("trace_id" = String, Path, description = "Trace id"), FlamegraphQueryParams),
+    responses(
+        (status = 200, description = "Folded stacks (`text/plain`) or a speedscope file (`application/json`)"),
+        (status = 404, description = "Trace not found")
+    )
+)]
+async fn trace_flamegraph(
+    State(state): State<ApiState>,
+    Path(trace_id): Path<String>,
+    Query(params): Query<FlamegraphQueryParams>,
+) -> Result<Response, (StatusCode, String)> {
+    let spans = find_trace(&state, &trace_id).await?;
+
+    Ok(match params.format {
+        FlamegraphFormat::Folded => (
+            [(
+                axum::http::header::CONTENT_TYPE,
+                "text/plain; charset=utf-8",
+            )],
+            folded_stacks(&spans),
+        )
+            .into_response(),
+        FlamegraphFormat::Speedscope => Json(speedscope(&spans, &trace_id)).into_response(),
+    })
+}
+
+/// Stored trace re-encoded for import into other tracing tools
+#[utoipa::path(
+    get,
+    path = "/api/traces/{trace_id}/export",
+    tag = "traces",
+    params(("trace_id" = String, Path, description = "Trace id"), ExportQueryParams),
+    responses(
+        (status = 200, description = "OTLP/JSON or Jaeger JSON document", body = Object),
+        (status = 404, description = "Trace not found")
+    )
+)]
+async fn export_trace(
+    State(state): State<ApiState>,
+    Path(trace_id): Path<String>,
+    Query(params): Query<ExportQueryParams>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let spans = find_trace(&state, &trace_id).await?;
+
+    Ok(Json(match params.format {
+        ExportFormat::Otlp => to_otlp_json(&spans),
+        ExportFormat::Jaeger => to_jaeger_json(&trace_id, &spans),
     }))
 }
 
 /// Search traces (alias for list_traces with different endpoint)
+#[utoipa::path(
+    get,
+    path = "/api/traces/search",
+    tag = "traces",
+    params(TraceQueryParams),
+    responses(
+        (status = 200, description = "Same as /api/traces", body = TraceListResponse),
+        (status = 400, description = "Invalid cursor or timestamp")
+    )
+)]
 async fn search_traces(
     state: State<ApiState>,
     params: Query<TraceQueryParams>,
+    pairs: Query<Vec<(String, String)>>,
 ) -> Result<Json<TraceListResponse>, (StatusCode, String)> {
-    list_traces(state, params).await
+    list_traces(state, params, pairs).await
 }
 
-/// Load all spans from Parquet files
-fn load_all_spans(storage_dir: &std::path::Path) -> Result<Vec<TraceSpan>> {
-    let mut all_spans = Vec::new();
-
-    // Return empty vec if directory doesn't exist yet
-    if !storage_dir.exists() {
-        return Ok(all_spans);
+/// Sources a log query is answered from: a query forwarded by another daemon never fans out
+/// again, so daemons whose federations include each other do not loop
+fn log_sources(state: &ApiState, headers: &HeaderMap) -> Arc<FederatedQuery> {
+    if headers.contains_key(FORWARDED_HEADER) {
+        Arc::new(state.query_engine.own_storage())
+    } else {
+        state.query_engine.clone()
     }
+}
 
-    for entry in std::fs::read_dir(storage_dir)? {
-        let entry = entry?;
-        let path = entry.path();
+/// List logs matching the filters, newest first
+#[utoipa::path(
+    get,
+    path = "/api/logs",
+    tag = "logs",
+    params(LogQueryParams),
+    responses(
+        (status = 200, description = "Matching logs, newest first", body = LogListResponse),
+        (status = 400, description = "Invalid timestamp")
+    )
+)]
+async fn list_logs(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Query(params): Query<LogQueryParams>,
+) -> Result<Json<LogListResponse>, (StatusCode, String)> {
+    let filter = params
+        .to_filter()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
 
-        if path.extension().and_then(|s| s.to_str()) == Some("parquet") {
-            let file = std::fs::File::open(&path)?;
-            let reader = ParquetRecordBatchReaderBuilder::try_new(file)?.build()?;
+    let logs = log_sources(&state, &headers)
+        .query(&filter, params.limit)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-            for batch_result in reader {
-                let batch = batch_result?;
-                let spans = parse_spans_from_batch(&batch)?;
-                all_spans.extend(spans);
+    Ok(Json(LogListResponse {
+        logs: logs.logs,
+        total_count: logs.total_count,
+        unavailable_sources: logs.unavailable_sources,
+    }))
+}
+
+/// Level and service breakdown of logs matching the filters
+#[utoipa::path(
+    get,
+    path = "/api/logs/stats",
+    tag = "logs",
+    params(LogQueryParams),
+    responses(
+        (status = 200, description = "Counts of matching logs", body = LogStats),
+        (status = 400, description = "Invalid timestamp")
+    )
+)]
+async fn log_stats(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Query(params): Query<LogQueryParams>,
+) -> Result<Json<LogStats>, (StatusCode, String)> {
+    let filter = params
+        .to_filter()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let stats = log_sources(&state, &headers)
+        .stats(&filter)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(stats))
+}
+
+fn schema_registry(state: &ApiState) -> Result<&SchemaRegistry, (StatusCode, String)> {
+    state.schema_registry.as_deref().ok_or((
+        StatusCode::NOT_FOUND,
+        "Schema registry is not enabled".to_string(),
+    ))
+}
+
+fn registry_error(e: RegistryError) -> (StatusCode, String) {
+    let status = match e {
+        RegistryError::Invalid(_) => StatusCode::BAD_REQUEST,
+        RegistryError::NotFound { .. } => StatusCode::NOT_FOUND,
+        RegistryError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, e.to_string())
+}
+
+/// Registered schemas, their versions and which one is active
+#[utoipa::path(
+    get,
+    path = "/api/schemas",
+    tag = "schemas",
+    responses((status = 200, description = "Registered schemas", body = [SchemaVersions]))
+)]
+async fn list_schemas(
+    State(state): State<ApiState>,
+) -> Result<Json<Vec<SchemaVersions>>, (StatusCode, String)> {
+    let schemas = schema_registry(&state)?.list().map_err(registry_error)?;
+    Ok(Json(schemas))
+}
+
+/// The JSON Schema stored as one version
+#[utoipa::path(
+    get,
+    path = "/api/schemas/{name}/versions/{version}",
+    tag = "schemas",
+    params(
+        ("name" = String, Path, description = "`default` or a service name"),
+        ("version" = u32, Path, description = "Schema version")
+    ),
+    responses(
+        (status = 200, description = "JSON Schema document"),
+        (status = 404, description = "Version not registered")
+    )
+)]
+async fn get_schema(
+    State(state): State<ApiState>,
+    Path((name, version)): Path<(String, u32)>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let schema = schema_registry(&state)?
+        .get(&name, version)
+        .map_err(registry_error)?;
+    Ok(Json(schema))
+}
+
+/// Store a JSON Schema as the next version of `name`; it applies once activated
+#[utoipa::path(
+    post,
+    path = "/api/schemas/{name}/versions",
+    tag = "schemas",
+    params(("name" = String, Path, description = "`default` or a service name")),
+    request_body(content = Object, description = "JSON Schema document"),
+    responses(
+        (status = 201, description = "Stored version", body = RegisteredSchema),
+        (status = 400, description = "Invalid name or schema")
+    )
+)]
+async fn register_schema(
+    State(state): State<ApiState>,
+    actor: Option<Extension<ApiActor>>,
+    Path(name): Path<String>,
+    Json(schema): Json<serde_json::Value>,
+) -> Result<(StatusCode, Json<RegisteredSchema>), (StatusCode, String)> {
+    let registered = schema_registry(&state)?.register(&name, schema);
+    audit_schema_change(actor, "schema.register", &name, None, &registered);
+    Ok((
+        StatusCode::CREATED,
+        Json(registered.map_err(registry_error)?),
+    ))
+}
+
+/// Record a registry change requested over the API in the audit log
+fn audit_schema_change(
+    actor: Option<Extension<ApiActor>>,
+    action: &str,
+    name: &str,
+    version: Option<u32>,
+    result: &Result<RegisteredSchema, RegistryError>,
+) {
+    let actor = actor.map_or_else(|| "unknown".to_string(), |Extension(actor)| actor.0);
+    let (outcome, version) = match result {
+        Ok(schema) => (Outcome::Ok, Some(schema.version)),
+        Err(e) => (Outcome::Failed(e.to_string()), version),
+    };
+    audit::record(
+        &actor,
+        action,
+        outcome,
+        serde_json::json!({ "schema": name, "version": version }),
+    );
+}
+
+/// Validate new logs for `name` with a stored version; survives restarts
+#[utoipa::path(
+    post,
+    path = "/api/schemas/{name}/versions/{version}/activate",
+    tag = "schemas",
+    params(
+        ("name" = String, Path, description = "`default` or a service name"),
+        ("version" = u32, Path, description = "Schema version")
+    ),
+    responses(
+        (status = 200, description = "Activated version", body = RegisteredSchema),
+        (status = 404, description = "Version not registered")
+    )
+)]
+async fn activate_schema(
+    State(state): State<ApiState>,
+    actor: Option<Extension<ApiActor>>,
+    Path((name, version)): Path<(String, u32)>,
+) -> Result<Json<RegisteredSchema>, (StatusCode, String)> {
+    let activated = schema_registry(&state)?.activate(&name, version);
+    audit_schema_change(actor, "schema.activate", &name, Some(version), &activated);
+    Ok(Json(activated.map_err(registry_error)?))
+}
+
+/// Server-Sent Events of newly flushed traces (`trace`) and error-level logs (`log`)
+#[utoipa::path(
+    get,
+    path = "/api/stream",
+    tag = "live",
+    responses((status = 200, description = "`text/event-stream` of `trace` (TraceSummary) and `log` (LogRecord) events"))
+)]
+async fn stream_events(
+    State(state): State<ApiState>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let traces = BroadcastStream::new(state.live.subscribe_traces())
+        .filter_map(|summary| summary.ok())
+        .map(|summary| Event::default().event("trace").json_data(summary));
+
+    let logs = BroadcastStream::new(state.live.subscribe_logs())
+        .filter_map(|log| log.ok())
+        .filter(|log| is_error_level(&log.level))
+        .map(|log| {
+            Event::default()
+                .event("log")
+                .json_data(LogRecord::from_entry(&log))
+        });
+
+    Sse::new(traces.merge(logs)).keep_alive(KeepAlive::default())
+}
+
+/// WebSocket live tail of ingested logs matching the filters given at connect
+#[utoipa::path(
+    get,
+    path = "/api/logs/tail",
+    tag = "live",
+    params(LogQueryParams),
+    responses((status = 101, description = "WebSocket streaming matching LogRecord JSON messages"))
+)]
+async fn tail_logs(
+    ws: WebSocketUpgrade,
+    State(state): State<ApiState>,
+    Query(params): Query<LogQueryParams>,
+) -> Result<Response, (StatusCode, String)> {
+    let filter = params
+        .to_filter()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let logs = state.live.subscribe_logs();
+
+    Ok(ws.on_upgrade(move |socket| tail_socket(socket, logs, filter)))
+}
+
+async fn tail_socket(
+    mut socket: WebSocket,
+    mut logs: tokio::sync::broadcast::Receiver<Arc<crate::schema::LogEntry>>,
+    filter: LogFilter,
+) {
+    use tokio::sync::broadcast::error::RecvError;
+
+    loop {
+        tokio::select! {
+            received = logs.recv() => {
+                let text = match received {
+                    Ok(log) => {
+                        let record = LogRecord::from_entry(&log);
+                        if !filter.matches(&record) {
+                            continue;
+                        }
+                        serde_json::to_string(&record)
+                    }
+                    // Tell the client it fell behind instead of silently skipping
+                    Err(RecvError::Lagged(missed)) => {
+                        serde_json::to_string(&serde_json::json!({ "lagged": missed }))
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+
+                let Ok(text) = text else { continue };
+                if socket.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
             }
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
         }
     }
-
-    Ok(all_spans)
 }
 
-/// Parse spans from Arrow RecordBatch
-fn parse_spans_from_batch(batch: &arrow::array::RecordBatch) -> Result<Vec<TraceSpan>> {
-    use arrow::array::{Array, StringArray, TimestampMicrosecondArray, UInt64Array};
+/// Compare each operation's recent window against its historical baseline
+#[utoipa::path(
+    get,
+    path = "/api/anomalies",
+    tag = "analysis",
+    params(AnomalyQueryParams),
+    responses(
+        (status = 200, description = "Scored anomalies, highest first", body = AnomalyResponse),
+        (status = 400, description = "Invalid window")
+    )
+)]
+async fn detect_anomalies(
+    State(state): State<ApiState>,
+    Query(params): Query<AnomalyQueryParams>,
+) -> Result<Json<AnomalyResponse>, (StatusCode, String)> {
+    if params.baseline_hours > MAX_BASELINE_HOURS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("baseline_hours must be at most {}", MAX_BASELINE_HOURS),
+        ));
+    }
+    let baseline_minutes = params.baseline_hours * 60;
+    if params.window_minutes == 0 || baseline_minutes < params.window_minutes {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "baseline_hours must cover at least one window of window_minutes > 0".to_string(),
+        ));
+    }
+    let baseline_windows = baseline_minutes / params.window_minutes;
+    if baseline_windows > MAX_BASELINE_WINDOWS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "baseline_hours may hold at most {} windows of window_minutes",
+                MAX_BASELINE_WINDOWS
+            ),
+        ));
+    }
 
-    let trace_ids = batch
-        .column(0)
-        .as_any()
-        .downcast_ref::<StringArray>()
-        .unwrap();
-    let span_ids = batch
-        .column(1)
-        .as_any()
-        .downcast_ref::<StringArray>()
-        .unwrap();
-    let parent_span_ids = batch
-        .column(2)
-        .as_any()
-        .downcast_ref::<StringArray>()
-        .unwrap();
-    let names = batch
-        .column(3)
-        .as_any()
-        .downcast_ref::<StringArray>()
-        .unwrap();
-    let start_times = batch
-        .column(4)
-        .as_any()
-        .downcast_ref::<TimestampMicrosecondArray>()
-        .unwrap();
-    let end_times = batch
-        .column(5)
-        .as_any()
-        .downcast_ref::<TimestampMicrosecondArray>()
-        .unwrap();
-    let durations = batch
-        .column(6)
-        .as_any()
-        .downcast_ref::<UInt64Array>()
-        .unwrap();
-    let attributes = batch
-        .column(7)
-        .as_any()
-        .downcast_ref::<StringArray>()
-        .unwrap();
-    let events = batch
-        .column(8)
-        .as_any()
-        .downcast_ref::<StringArray>()
-        .unwrap();
-    let statuses = batch
-        .column(9)
-        .as_any()
-        .downcast_ref::<StringArray>()
-        .unwrap();
+    let now = chrono::Utc::now();
+    // Both are bounded by MAX_BASELINE_HOURS above, far from overflowing
+    let window_us = params.window_minutes as i64 * 60 * 1_000_000;
+    let baseline_windows = baseline_windows as usize;
+    let mut detector = AnomalyDetector::new(
+        now.timestamp_micros(),
+        window_us,
+        baseline_windows,
+        params.threshold,
+    );
 
-    let mut spans = Vec::new();
+    let baseline_start = detector.baseline_start_us();
+    // Reads every file of the baseline, so off the async workers
+    let index = state.index.clone();
+    let anomalies = tokio::task::spawn_blocking(move || {
+        index.for_each_span(&SpanFilter::since(Some(baseline_start)), |span| {
+            detector.observe(span)
+        })?;
+        anyhow::Ok(detector.detect())
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    for i in 0..batch.num_rows() {
-        let trace_id = trace_ids.value(i).to_string();
-        let span_id = span_ids.value(i).to_string();
-        let parent_span_id = if parent_span_ids.is_null(i) {
-            None
-        } else {
-            Some(parent_span_ids.value(i).to_string())
-        };
-        let name = names.value(i).to_string();
-        let start_time =
-            chrono::DateTime::from_timestamp_micros(start_times.value(i)).unwrap_or_default();
-        let end_time =
-            chrono::DateTime::from_timestamp_micros(end_times.value(i)).unwrap_or_default();
-        let duration_us = durations.value(i);
-
-        let attrs: HashMap<String, String> = serde_json::from_str(attributes.value(i))?;
-        let evts: Vec<crate::trace_storage::SpanEvent> = serde_json::from_str(events.value(i))?;
-
-        let status_str = statuses.value(i);
-        let status = if status_str.starts_with("ERROR") {
-            SpanStatus::Error {
-                message: status_str.strip_prefix("ERROR: ").unwrap_or("").to_string(),
-            }
-        } else {
-            SpanStatus::Ok
-        };
+    let to_rfc3339 = |us: i64| {
+        chrono::DateTime::from_timestamp_micros(us)
+            .unwrap_or_default()
+            .to_rfc3339()
+    };
 
-        spans.push(TraceSpan {
-            trace_id,
-            span_id,
-            parent_span_id,
-            name,
-            start_time,
-            end_time,
-            duration_us,
-            attributes: attrs,
-            events: evts,
-            status,
-        });
+    Ok(Json(AnomalyResponse {
+        window_start: to_rfc3339(now.timestamp_micros() - window_us),
+        window_end: now.to_rfc3339(),
+        baseline_start: to_rfc3339(baseline_start),
+        anomalies,
+    }))
+}
+
+/// Count, total/self time and error rate per span name across every trace in a window
+#[utoipa::path(
+    get,
+    path = "/api/operations",
+    tag = "analysis",
+    params(OperationQueryParams),
+    responses(
+        (status = 200, description = "Operations, largest total time first", body = OperationsResponse),
+        (status = 400, description = "Invalid time range")
+    )
+)]
+async fn list_operations(
+    State(state): State<ApiState>,
+    Query(params): Query<OperationQueryParams>,
+) -> Result<Json<OperationsResponse>, (StatusCode, String)> {
+    let bad_request = |e: anyhow::Error| (StatusCode::BAD_REQUEST, e.to_string());
+    let end = parse_timestamp(&params.end_time)
+        .map_err(bad_request)?
+        .map(|dt| dt.with_timezone(&chrono::Utc))
+        .unwrap_or_else(chrono::Utc::now);
+    let start = match parse_timestamp(&params.start_time).map_err(bad_request)? {
+        Some(start) => start.with_timezone(&chrono::Utc),
+        None => i64::try_from(params.window_minutes)
+            .ok()
+            .and_then(chrono::Duration::try_minutes)
+            .and_then(|window| end.checked_sub_signed(window))
+            .ok_or_else(|| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    "window_minutes is too large".to_string(),
+                )
+            })?,
+    };
+    if start >= end {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "start_time must be before end_time".to_string(),
+        ));
     }
 
-    Ok(spans)
+    let (start_us, end_us) = (start.timestamp_micros(), end.timestamp_micros());
+    // Reads every file of the range, so off the async workers
+    let index = state.index.clone();
+    let mut operations = tokio::task::spawn_blocking(move || {
+        let mut aggregator = OperationAggregator::new(start_us, end_us);
+        index.for_each_span(
+            &SpanFilter {
+                start_us: Some(start_us),
+                end_us: Some(end_us),
+                ..SpanFilter::default()
+            },
+            |span| aggregator.observe(span),
+        )?;
+        anyhow::Ok(aggregator.finish())
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let total_operations = operations.len();
+    operations.truncate(params.limit);
+
+    Ok(Json(OperationsResponse {
+        start_time: start.to_rfc3339(),
+        end_time: end.to_rfc3339(),
+        operations,
+        total_operations,
+    }))
 }
 
-/// Build trace summary from spans
-fn build_trace_summary(trace_id: String, spans: Vec<TraceSpan>) -> TraceSummary {
-    let span_count = spans.len();
-    let error_count = spans
-        .iter()
-        .filter(|s| matches!(s.status, SpanStatus::Error { .. }))
-        .count();
+/// Trace id addressed by a `/api/traces/:trace_id[/...]` path
+fn trace_id_from_path(path: &str) -> Option<&str> {
+    let id = path.strip_prefix("/api/traces/")?.split('/').next()?;
+    (!id.is_empty() && id != "search" && id != "volume").then_some(id)
+}
 
-    let root_span = spans
-        .iter()
-        .find(|s| s.parent_span_id.is_none())
-        .or_else(|| spans.first())
-        .unwrap();
+/// Answer `If-None-Match` / `If-Modified-Since` with 304 and tag fresh responses.
+///
+/// Validators are derived from the indexed trace files: those holding the addressed
+/// trace, or all of them for listings (with the query string folded into the ETag). They
+/// are taken once the handler has run, since it may refresh the index for a trace that
+/// looks incomplete, so a tag always matches the body it is sent with.
+async fn conditional_get(
+    State(state): State<ApiState>,
+    req: axum::extract::Request,
+    next: middleware::Next,
+) -> Response {
+    use axum::http::header;
+    use sha2::{Digest, Sha256};
 
-    let total_duration_ms = root_span.duration_us as f64 / 1000.0;
+    let uri = req.uri().clone();
+    let if_none_match = req.headers().get(header::IF_NONE_MATCH).cloned();
+    let if_modified_since = req.headers().get(header::IF_MODIFIED_SINCE).cloned();
+    let response = next.run(req).await;
+    if !response.status().is_success() {
+        return response;
+    }
+    let Some(version) = state.index.files_version(trace_id_from_path(uri.path())) else {
+        return response;
+    };
 
-    TraceSummary {
-        trace_id,
-        root_span_name: root_span.name.clone(),
-        start_time: root_span.start_time.to_rfc3339(),
-        total_duration_ms,
-        span_count,
-        error_count,
+    let modified_secs = version
+        .modified
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let mut hasher = Sha256::new();
+    hasher.update(uri.to_string());
+    hasher.update(format!(
+        "{:?}:{}:{}",
+        version.modified, version.files, version.bytes
+    ));
+    let digest = hasher.finalize();
+    let hex: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    let etag = format!("\"{}\"", hex);
+    let last_modified = chrono::DateTime::from_timestamp(modified_secs, 0)
+        .unwrap_or_default()
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string();
+
+    let not_modified = match if_none_match {
+        Some(tags) => tags
+            .to_str()
+            .map(|tags| {
+                tags.split(',')
+                    .any(|t| t.trim().trim_start_matches("W/") == etag || t.trim() == "*")
+            })
+            .unwrap_or(false),
+        None => if_modified_since
+            .as_ref()
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| chrono::DateTime::parse_from_rfc2822(v).ok())
+            .is_some_and(|since| since.timestamp() >= modified_secs),
+    };
+
+    let mut response = if not_modified {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        response
+    };
+    let headers = response.headers_mut();
+    if let Ok(value) = etag.parse() {
+        headers.insert(header::ETAG, value);
+    }
+    if let Ok(value) = last_modified.parse() {
+        headers.insert(header::LAST_MODIFIED, value);
     }
+    response
 }
 
-/// Build hierarchical trace tree
-fn build_trace_tree(spans: &[TraceSpan]) -> SpanNode {
-    let mut span_map: HashMap<String, &TraceSpan> = HashMap::new();
+/// Name of the node standing in for a missing root span
+const VIRTUAL_ROOT_NAME: &str = "(virtual root)";
+
+/// Build hierarchical trace tree. Spans whose parent is missing (or whose parents form a cycle)
+/// are attached with `orphan` set: under the root span, or with the root spans under a
+/// virtual root when the trace has no single root. Returns the tree and the orphan count.
+fn build_trace_tree(spans: &[TraceSpan]) -> (SpanNode, usize) {
+    let mut children: HashMap<&str, Vec<&TraceSpan>> = HashMap::new();
+    let ids: HashSet<&str> = spans.iter().map(|s| s.span_id.as_str()).collect();
     for span in spans {
-        span_map.insert(span.span_id.clone(), span);
+        if let Some(parent) = &span.parent_span_id {
+            children.entry(parent.as_str()).or_default().push(span);
+        }
     }
 
-    let root = spans
+    let mut visited = HashSet::new();
+    let mut roots: Vec<SpanNode> = spans
         .iter()
-        .find(|s| s.parent_span_id.is_none())
-        .or_else(|| spans.first())
-        .unwrap();
+        .filter(|s| s.parent_span_id.is_none())
+        .map(|root| build_span_node(root, &children, &mut visited, false))
+        .collect();
 
-    build_span_node(root, spans)
+    // Spans with a missing parent first, so their children stay under them
+    let (missing_parent, rest): (Vec<&TraceSpan>, Vec<&TraceSpan>) = spans.iter().partition(|s| {
+        s.parent_span_id
+            .as_deref()
+            .is_some_and(|p| !ids.contains(p))
+    });
+    let mut orphans = Vec::new();
+    for span in missing_parent.into_iter().chain(rest) {
+        if !visited.contains(span.span_id.as_str()) {
+            orphans.push(build_span_node(span, &children, &mut visited, true));
+        }
+    }
+    let orphan_count = orphans.len();
+
+    if roots.len() == 1 {
+        let mut root = roots.pop().unwrap();
+        root.children.extend(orphans);
+        return (root, orphan_count);
+    }
+    let (start_us, end_us) = trace_extent(spans);
+    let root = SpanNode {
+        span_id: String::new(),
+        name: VIRTUAL_ROOT_NAME.to_string(),
+        start_time: chrono::DateTime::from_timestamp_micros(start_us)
+            .unwrap_or_default()
+            .to_rfc3339(),
+        duration_ms: (end_us - start_us) as f64 / 1000.0,
+        attributes: HashMap::new(),
+        events: Vec::new(),
+        status: "OK".to_string(),
+        children: roots.into_iter().chain(orphans).collect(),
+        orphan: false,
+        is_virtual: true,
+    };
+    (root, orphan_count)
 }
 
-fn build_span_node(span: &TraceSpan, all_spans: &[TraceSpan]) -> SpanNode {
-    let children: Vec<SpanNode> = all_spans
+/// Parent span ids that no span of the trace has, sorted
+fn missing_parents(spans: &[TraceSpan]) -> Vec<String> {
+    let ids: HashSet<&str> = spans.iter().map(|s| s.span_id.as_str()).collect();
+    let missing: BTreeSet<&str> = spans
         .iter()
-        .filter(|s| s.parent_span_id.as_ref() == Some(&span.span_id))
-        .map(|child| build_span_node(child, all_spans))
+        .filter_map(|s| s.parent_span_id.as_deref())
+        .filter(|parent| !ids.contains(parent))
         .collect();
+    missing.into_iter().map(str::to_string).collect()
+}
+
+/// Whether every span's parent is stored and exactly one span is the root
+fn is_complete(spans: &[TraceSpan]) -> bool {
+    spans.iter().filter(|s| s.parent_span_id.is_none()).count() == 1
+        && missing_parents(spans).is_empty()
+}
+
+/// Earliest start and latest end of the spans, in microseconds
+fn trace_extent(spans: &[TraceSpan]) -> (i64, i64) {
+    let start = spans.iter().map(|s| s.start_time.timestamp_micros()).min();
+    let end = spans.iter().map(|s| s.end_time.timestamp_micros()).max();
+    (start.unwrap_or(0), end.unwrap_or(0))
+}
+
+fn build_span_node<'a>(
+    span: &'a TraceSpan,
+    children: &HashMap<&str, Vec<&'a TraceSpan>>,
+    visited: &mut HashSet<&'a str>,
+    orphan: bool,
+) -> SpanNode {
+    visited.insert(span.span_id.as_str());
+    let mut child_nodes = Vec::new();
+    for child in children.get(span.span_id.as_str()).into_iter().flatten() {
+        if !visited.contains(child.span_id.as_str()) {
+            child_nodes.push(build_span_node(child, children, visited, false));
+        }
+    }
 
     let events = span
         .events
@@ -420,7 +1749,9 @@ fn build_span_node(span: &TraceSpan, all_spans: &[TraceSpan]) -> SpanNode {
         attributes: span.attributes.clone(),
         events,
         status,
-        children,
+        children: child_nodes,
+        orphan,
+        is_virtual: false,
     }
 }
 
@@ -432,12 +1763,15 @@ fn analyze_trace(spans: &[TraceSpan]) -> TraceAnalysis {
         .filter(|s| matches!(s.status, SpanStatus::Error { .. }))
         .count();
 
-    let root = spans
-        .iter()
-        .find(|s| s.parent_span_id.is_none())
-        .or_else(|| spans.first())
-        .unwrap();
-    let total_duration_ms = root.duration_us as f64 / 1000.0;
+    // Without a single root span, the trace lasts from its first start to its last end
+    let mut roots = spans.iter().filter(|s| s.parent_span_id.is_none());
+    let total_duration_ms = match (roots.next(), roots.next()) {
+        (Some(root), None) => root.duration_us as f64 / 1000.0,
+        _ => {
+            let (start_us, end_us) = trace_extent(spans);
+            (end_us - start_us) as f64 / 1000.0
+        }
+    };
 
     let mut span_breakdown: HashMap<String, usize> = HashMap::new();
     for span in spans {
@@ -462,5 +1796,181 @@ fn analyze_trace(spans: &[TraceSpan]) -> TraceAnalysis {
         critical_path_ms: total_duration_ms,
         span_breakdown,
         slowest_operations: slowest,
+        orphan_spans: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(trace_id: &str, start_time_us: i64) -> TraceSummary {
+        TraceSummary {
+            trace_id: trace_id.to_string(),
+            root_span_name: "root".to_string(),
+            start_time: String::new(),
+            start_time_us,
+            total_duration_ms: 1.0,
+            span_count: 1,
+            error_count: 0,
+        }
+    }
+
+    fn params(limit: usize, cursor: Option<String>) -> TraceQueryParams {
+        serde_json::from_value(serde_json::json!({ "limit": limit, "cursor": cursor })).unwrap()
+    }
+
+    #[test]
+    fn test_trace_tree_attaches_orphans() {
+        let now = chrono::Utc::now();
+        let span = |span_id: &str, parent: Option<&str>, offset_ms: i64| TraceSpan {
+            trace_id: "t".to_string(),
+            span_id: span_id.to_string(),
+            parent_span_id: parent.map(str::to_string),
+            name: span_id.to_string(),
+            start_time: now + chrono::Duration::milliseconds(offset_ms),
+            end_time: now + chrono::Duration::milliseconds(offset_ms + 10),
+            duration_us: 10_000,
+            attributes: HashMap::new(),
+            events: Vec::new(),
+            status: SpanStatus::Ok,
+        };
+        let names = |node: &SpanNode| -> Vec<(String, bool)> {
+            node.children
+                .iter()
+                .map(|c| (c.name.clone(), c.orphan))
+                .collect()
+        };
+
+        // One root: orphans, with their own children, go under it
+        let spans = vec![
+            span("child", Some("orphan"), 2),
+            span("root", None, 0),
+            span("orphan", Some("lost"), 1),
+            span("a", Some("b"), 3),
+            span("b", Some("a"), 4),
+        ];
+        let (root, orphans) = build_trace_tree(&spans);
+        assert_eq!(root.name, "root");
+        assert_eq!(orphans, 2);
+        assert_eq!(
+            names(&root),
+            [("orphan".to_string(), true), ("a".to_string(), true)]
+        );
+        assert_eq!(root.children[0].children[0].name, "child");
+        assert_eq!(root.children[1].children[0].name, "b");
+
+        // No root: a virtual one spans the whole trace
+        let spans = vec![span("x", Some("gone"), 0), span("y", Some("gone"), 20)];
+        let (root, orphans) = build_trace_tree(&spans);
+        assert!(root.is_virtual);
+        assert_eq!(root.name, VIRTUAL_ROOT_NAME);
+        assert_eq!(root.duration_ms, 30.0);
+        assert_eq!(orphans, 2);
+        assert_eq!(analyze_trace(&spans).total_duration_ms, 30.0);
+        assert_eq!(missing_parents(&spans), ["gone"]);
+        assert!(!is_complete(&spans));
+        assert!(is_complete(&[
+            span("root", None, 0),
+            span("c", Some("root"), 1)
+        ]));
+        let json = serde_json::to_value(&root).unwrap();
+        assert_eq!(json["virtual"], true);
+        assert_eq!(json["children"][0]["orphan"], true);
+        assert!(json["children"][0].get("virtual").is_none());
+    }
+
+    #[test]
+    fn test_openapi_lists_endpoints() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        assert!(spec["paths"]["/api/traces/{trace_id}"]["get"].is_object());
+        assert!(spec["components"]["schemas"]["TraceSummary"].is_object());
+    }
+
+    #[test]
+    fn test_conditional_paths_address_single_traces() {
+        assert_eq!(trace_id_from_path("/api/traces/abc"), Some("abc"));
+        assert_eq!(
+            trace_id_from_path("/api/traces/abc/flamegraph"),
+            Some("abc")
+        );
+        assert_eq!(trace_id_from_path("/api/traces/search"), None);
+        assert_eq!(trace_id_from_path("/api/traces"), None);
+    }
+
+    #[test]
+    fn test_volume_buckets_count_traces_and_errors() {
+        let bucket_us = parse_bucket("5m").unwrap();
+        assert_eq!(bucket_us, 300_000_000);
+        assert!(parse_bucket("5w").is_err());
+        assert!(parse_bucket("9223372036854775807d").is_err());
+
+        let mut failed = summary("b", bucket_us + 1);
+        failed.error_count = 2;
+        let summaries = vec![summary("a", 0), failed, summary("c", bucket_us + 2)];
+
+        let buckets = volume_buckets(&summaries, 0, 3 * bucket_us, bucket_us);
+        assert_eq!(buckets.len(), 3);
+        assert_eq!(buckets[0].trace_count, 1);
+        assert_eq!(buckets[1].trace_count, 2);
+        assert_eq!(buckets[1].error_rate, 0.5);
+        assert_eq!(buckets[2].trace_count, 0);
+
+        // A bucket wider than the range is a single bucket holding every trace
+        let buckets = volume_buckets(&summaries, 0, 3 * bucket_us, i64::MAX);
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].trace_count, 3);
+    }
+
+    #[test]
+    fn test_span_filters_require_every_criterion() {
+        let params = params(10, None).with_attribute_filters(vec![
+            ("attr.http.route".to_string(), "/checkout".to_string()),
+            ("limit".to_string(), "10".to_string()),
+        ]);
+        assert_eq!(params.attributes.len(), 1);
+
+        let mut span: TraceSpan = serde_json::from_value(serde_json::json!({
+            "trace_id": "t1",
+            "span_id": "s1",
+            "parent_span_id": null,
+            "name": "GET /checkout",
+            "start_time": "2026-01-15T19:00:00Z",
+            "end_time": "2026-01-15T19:00:01Z",
+            "duration_us": 1000,
+            "attributes": { "http.route": "/checkout" },
+            "events": [],
+            "status": "Ok"
+        }))
+        .unwrap();
+        assert_eq!(params.span_matches(&span), [true, true]);
+
+        span.attributes.clear();
+        assert_eq!(params.span_matches(&span), [true, false]);
+    }
+
+    #[test]
+    fn test_cursor_pagination_walks_all_traces() {
+        let all = || {
+            vec![
+                summary("a", 3),
+                summary("b", 2),
+                summary("c", 2),
+                summary("d", 1),
+            ]
+        };
+
+        let (page, cursor) = paginate(all(), &params(2, None)).unwrap();
+        assert_eq!(
+            page.iter().map(|s| s.trace_id.as_str()).collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+
+        let (page, cursor) = paginate(all(), &params(2, cursor)).unwrap();
+        assert_eq!(
+            page.iter().map(|s| s.trace_id.as_str()).collect::<Vec<_>>(),
+            ["c", "d"]
+        );
+        assert!(cursor.is_none());
     }
 }
@@ -0,0 +1,348 @@
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::notify::{Notification, Notifier, SinkConfig, SinkTarget, Sinks};
+use crate::query::LogRecord;
+use crate::schema::LogEntry;
+
+/// One rule of the alert rules file: fires once `threshold` matching logs arrive within
+/// `window_secs`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub name: String,
+
+    /// Only logs from this service
+    #[serde(default)]
+    pub service: Option<String>,
+
+    /// Only logs at this level, case-insensitive
+    #[serde(default)]
+    pub level: Option<String>,
+
+    /// Only logs whose message matches this regex
+    #[serde(default)]
+    pub matches: Option<String>,
+
+    /// Matching logs within the window that fire the rule
+    #[serde(default = "default_threshold")]
+    pub threshold: usize,
+
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+
+    /// Quiet time after firing; defaults to the window
+    #[serde(default)]
+    pub cooldown_secs: Option<u64>,
+
+    /// Notification sinks from the `[notify]` config the alert is sent to
+    #[serde(default)]
+    pub sinks: Vec<String>,
+
+    /// URL the alert is POSTed to as JSON
+    #[serde(default)]
+    pub webhook: Option<String>,
+
+    /// Command run with the alert notification as JSON on stdin, e.g.
+    /// `["/usr/local/bin/page", "ops"]`
+    #[serde(default)]
+    pub exec: Vec<String>,
+}
+
+impl AlertRule {
+    /// Sinks for the rule's own `webhook` and `exec`
+    fn inline_sinks(&self) -> Vec<SinkConfig> {
+        let mut sinks = Vec::new();
+        if let Some(url) = &self.webhook {
+            sinks.push(SinkConfig {
+                name: format!("{}.webhook", self.name),
+                target: SinkTarget::Webhook {
+                    url: url.clone(),
+                    // The alert itself, without the notification around it
+                    body: Some("{{details}}".to_string()),
+                    headers: Default::default(),
+                },
+            });
+        }
+        if !self.exec.is_empty() {
+            sinks.push(SinkConfig {
+                name: format!("{}.exec", self.name),
+                target: SinkTarget::Exec {
+                    command: self.exec.clone(),
+                },
+            });
+        }
+        sinks
+    }
+}
+
+fn default_threshold() -> usize {
+    1
+}
+
+fn default_window_secs() -> u64 {
+    60
+}
+
+#[derive(Debug, Deserialize)]
+struct RulesFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<AlertRule>,
+}
+
+/// Read and check the `[[rule]]` entries of a TOML rules file
+pub fn load_rules(path: &Path) -> Result<Vec<AlertRule>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read alert rules {:?}", path))?;
+    let file: RulesFile =
+        toml::from_str(&text).with_context(|| format!("Invalid alert rules {:?}", path))?;
+    let mut names = HashSet::new();
+    for rule in &file.rules {
+        if rule.name.is_empty() {
+            bail!("Alert rules need a name");
+        }
+        if !names.insert(rule.name.as_str()) {
+            bail!("Duplicate alert rule: {}", rule.name);
+        }
+        if rule.threshold == 0 || rule.window_secs == 0 {
+            bail!(
+                "Alert rule {}: threshold and window_secs must be greater than 0",
+                rule.name
+            );
+        }
+        if let Some(pattern) = &rule.matches {
+            Regex::new(pattern)
+                .with_context(|| format!("Alert rule {}: invalid regex", rule.name))?;
+        }
+    }
+    Ok(file.rules)
+}
+
+/// A fired rule, sent as the `details` of an `alert` notification
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub rule: String,
+    /// Matching logs within the window when the rule fired
+    pub count: usize,
+    pub window_secs: u64,
+    pub fired_at: String,
+    /// The log that fired the rule
+    pub log: LogRecord,
+}
+
+struct CompiledRule {
+    rule: AlertRule,
+    sinks: Sinks,
+    matches: Option<Regex>,
+    cooldown: Duration,
+    window: Duration,
+    state: Mutex<RuleState>,
+}
+
+#[derive(Default)]
+struct RuleState {
+    /// Arrival times of recent matching logs, at most `threshold` of them
+    hits: VecDeque<Instant>,
+    last_fired: Option<Instant>,
+}
+
+impl CompiledRule {
+    fn matches(&self, log: &LogEntry) -> bool {
+        self.rule
+            .service
+            .as_deref()
+            .is_none_or(|service| log.service.as_deref() == Some(service))
+            && self
+                .rule
+                .level
+                .as_deref()
+                .is_none_or(|level| log.level.eq_ignore_ascii_case(level))
+            && self
+                .matches
+                .as_ref()
+                .is_none_or(|regex| regex.is_match(&log.message))
+    }
+
+    /// Record a matching log; the number of hits when this one fires the rule
+    fn hit(&self, now: Instant) -> Option<usize> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        while state
+            .hits
+            .front()
+            .is_some_and(|hit| now.duration_since(*hit) > self.window)
+        {
+            state.hits.pop_front();
+        }
+        if state.hits.len() == self.rule.threshold {
+            state.hits.pop_front();
+        }
+        state.hits.push_back(now);
+
+        let cooling = state
+            .last_fired
+            .is_some_and(|fired| now.duration_since(fired) < self.cooldown);
+        if state.hits.len() < self.rule.threshold || cooling {
+            return None;
+        }
+        state.last_fired = Some(now);
+        let count = state.hits.len();
+        state.hits.clear();
+        Some(count)
+    }
+}
+
+/// Evaluates the alert rules on every accepted log and sends fired alerts to their sinks
+pub struct AlertEngine {
+    rules: Vec<CompiledRule>,
+    notifier: Notifier,
+}
+
+impl AlertEngine {
+    /// Evaluate `rules`, sending fired alerts through `notifier`
+    pub fn new(rules: Vec<AlertRule>, notifier: Notifier) -> Result<Self> {
+        let rules = rules
+            .into_iter()
+            .map(|rule| {
+                let mut sinks = notifier
+                    .sinks(&rule.sinks)
+                    .with_context(|| format!("Alert rule {}", rule.name))?;
+                sinks.extend(rule.inline_sinks().into_iter().map(Into::into));
+                Ok(CompiledRule {
+                    sinks: sinks.into(),
+                    matches: rule.matches.as_deref().map(Regex::new).transpose()?,
+                    cooldown: Duration::from_secs(rule.cooldown_secs.unwrap_or(rule.window_secs)),
+                    window: Duration::from_secs(rule.window_secs),
+                    state: Mutex::new(RuleState::default()),
+                    rule,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        info!("Evaluating {} alert rule(s)", rules.len());
+        Ok(Self { rules, notifier })
+    }
+
+    /// Check `log` against every rule; runs on the ingest path, so it never waits
+    pub fn observe(&self, log: &LogEntry) {
+        let now = Instant::now();
+        for rule in &self.rules {
+            if !rule.matches(log) {
+                continue;
+            }
+            crate::metrics::record_alert_rule_hit(&rule.rule.name);
+            let Some(count) = rule.hit(now) else {
+                continue;
+            };
+            crate::metrics::record_alert_fired(&rule.rule.name);
+            let alert = Alert {
+                rule: rule.rule.name.clone(),
+                count,
+                window_secs: rule.rule.window_secs,
+                fired_at: Utc::now().to_rfc3339(),
+                log: LogRecord::from_entry(log),
+            };
+            let summary = format!("Alert {} fired: {} matching log(s)", alert.rule, count);
+            warn!("{}", summary);
+            let details = match serde_json::to_value(&alert) {
+                Ok(details) => details,
+                Err(e) => {
+                    warn!("Alert {} could not be encoded: {}", alert.rule, e);
+                    continue;
+                }
+            };
+            self.notifier.send(
+                Notification::new("alert", &alert.rule, summary, details),
+                &rule.sinks,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn log(service: &str, level: &str, message: &str) -> LogEntry {
+        serde_json::from_value(serde_json::json!({
+            "timestamp": "2026-01-15T19:00:00Z",
+            "level": level,
+            "message": message,
+            "service": service,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rules_fire_on_threshold_and_cool_down() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("alerts.toml");
+        std::fs::write(
+            &path,
+            r#"
+            [[rule]]
+            name = "checkout-errors"
+            service = "checkout"
+            level = "error"
+            threshold = 3
+            sinks = ["ops"]
+
+            [[rule]]
+            name = "panics"
+            matches = "panic|segfault"
+            cooldown_secs = 0
+            exec = ["/usr/local/bin/page"]
+            "#,
+        )
+        .unwrap();
+        let rules = load_rules(&path).unwrap();
+        let ops = SinkConfig {
+            name: "ops".to_string(),
+            target: SinkTarget::File {
+                path: temp_dir.path().join("alerts.jsonl"),
+            },
+        };
+        let (notifier, _) = Notifier::channel(&[]);
+        assert!(AlertEngine::new(rules.clone(), notifier).is_err());
+        let (notifier, mut rx) = Notifier::channel(&[ops]);
+        let engine = AlertEngine::new(rules, notifier).unwrap();
+
+        for _ in 0..2 {
+            engine.observe(&log("checkout", "ERROR", "payment failed"));
+        }
+        engine.observe(&log("search", "error", "index missing"));
+        assert!(rx.try_recv().is_err());
+        engine.observe(&log("checkout", "error", "payment failed"));
+        let (notification, sinks) = rx.try_recv().unwrap();
+        assert_eq!(notification.kind, "alert");
+        assert_eq!(notification.name, "checkout-errors");
+        assert_eq!(notification.details["count"], 3);
+        assert_eq!(sinks.len(), 1);
+        assert_eq!(sinks[0].name, "ops");
+        // Cooling down until the window has passed
+        for _ in 0..3 {
+            engine.observe(&log("checkout", "error", "payment failed"));
+        }
+        assert!(rx.try_recv().is_err());
+
+        engine.observe(&log("api", "info", "worker panic at main.rs"));
+        engine.observe(&log("api", "info", "worker panic again"));
+        let (notification, sinks) = rx.try_recv().unwrap();
+        assert_eq!(
+            notification.details["log"]["message"],
+            "worker panic at main.rs"
+        );
+        assert_eq!(sinks[0].name, "panics.exec");
+        assert_eq!(rx.try_recv().unwrap().0.name, "panics");
+
+        std::fs::write(&path, "[[rule]]\nname = \"bad\"\nmatches = \"(\"\n").unwrap();
+        assert!(load_rules(&path).is_err());
+        std::fs::write(&path, "[[rule]]\nname = \"a\"\n[[rule]]\nname = \"a\"\n").unwrap();
+        assert!(load_rules(&path).is_err());
+    }
+}
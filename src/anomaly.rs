@@ -0,0 +1,207 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::trace_storage::{SpanStatus, TraceSpan};
+
+/// Metric compared between the recent window and the baseline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyMetric {
+    /// Mean span duration in milliseconds
+    Latency,
+    /// Fraction of spans with an error status
+    ErrorRate,
+    /// Number of spans per window
+    Volume,
+}
+
+impl AnomalyMetric {
+    /// Smallest standard deviation used when scoring, so flat baselines still score sanely
+    fn min_stddev(self) -> f64 {
+        match self {
+            AnomalyMetric::Latency => 1.0,
+            AnomalyMetric::ErrorRate => 0.01,
+            AnomalyMetric::Volume => 1.0,
+        }
+    }
+}
+
+/// A scored deviation of one operation's metric from its baseline
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema))]
+pub struct Anomaly {
+    pub operation: String,
+    pub metric: AnomalyMetric,
+    pub current: f64,
+    pub baseline_mean: f64,
+    pub baseline_stddev: f64,
+    /// Absolute z-score of `current` against the baseline
+    pub score: f64,
+    pub direction: &'static str,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct BucketStats {
+    count: u64,
+    errors: u64,
+    duration_us: u64,
+}
+
+impl BucketStats {
+    fn value(&self, metric: AnomalyMetric) -> Option<f64> {
+        match metric {
+            AnomalyMetric::Volume => Some(self.count as f64),
+            _ if self.count == 0 => None,
+            AnomalyMetric::Latency => Some(self.duration_us as f64 / self.count as f64 / 1000.0),
+            AnomalyMetric::ErrorRate => Some(self.errors as f64 / self.count as f64),
+        }
+    }
+}
+
+/// Accumulates spans into fixed windows and scores the latest window against the rest.
+///
+/// The baseline is split into windows of the same length as the recent window, so each
+/// baseline window is directly comparable with the current one.
+pub struct AnomalyDetector {
+    baseline_start_us: i64,
+    window_us: i64,
+    /// Baseline windows plus the recent window (last)
+    buckets: usize,
+    threshold: f64,
+    operations: HashMap<String, Vec<BucketStats>>,
+}
+
+impl AnomalyDetector {
+    pub fn new(now_us: i64, window_us: i64, baseline_windows: usize, threshold: f64) -> Self {
+        let window_us = window_us.max(1);
+        let buckets = baseline_windows + 1;
+        Self {
+            baseline_start_us: now_us - window_us * buckets as i64,
+            window_us,
+            buckets,
+            threshold,
+            operations: HashMap::new(),
+        }
+    }
+
+    /// Earliest span start time that can affect the result
+    pub fn baseline_start_us(&self) -> i64 {
+        self.baseline_start_us
+    }
+
+    pub fn observe(&mut self, span: &TraceSpan) {
+        let offset = span.start_time.timestamp_micros() - self.baseline_start_us;
+        if offset < 0 {
+            return;
+        }
+        let index = (offset / self.window_us) as usize;
+        if index >= self.buckets {
+            return;
+        }
+
+        let buckets = self
+            .operations
+            .entry(span.name.clone())
+            .or_insert_with(|| vec![BucketStats::default(); self.buckets]);
+        let bucket = &mut buckets[index];
+        bucket.count += 1;
+        bucket.duration_us += span.duration_us;
+        if matches!(span.status, SpanStatus::Error { .. }) {
+            bucket.errors += 1;
+        }
+    }
+
+    /// Anomalies at or above the threshold, highest score first
+    pub fn detect(&self) -> Vec<Anomaly> {
+        let mut anomalies = Vec::new();
+
+        for (operation, buckets) in &self.operations {
+            let (baseline, recent) = buckets.split_at(self.buckets - 1);
+            for metric in [
+                AnomalyMetric::Latency,
+                AnomalyMetric::ErrorRate,
+                AnomalyMetric::Volume,
+            ] {
+                let Some(current) = recent[0].value(metric) else {
+                    continue;
+                };
+                let history: Vec<f64> = baseline.iter().filter_map(|b| b.value(metric)).collect();
+                if history.is_empty() {
+                    continue;
+                }
+
+                let (mean, stddev) = mean_stddev(&history);
+                let scale = stddev.max(0.1 * mean.abs()).max(metric.min_stddev());
+                let score = (current - mean).abs() / scale;
+
+                if score >= self.threshold {
+                    anomalies.push(Anomaly {
+                        operation: operation.clone(),
+                        metric,
+                        current,
+                        baseline_mean: mean,
+                        baseline_stddev: stddev,
+                        score,
+                        direction: if current >= mean {
+                            "increase"
+                        } else {
+                            "decrease"
+                        },
+                    });
+                }
+            }
+        }
+
+        anomalies.sort_by(|a, b| b.score.total_cmp(&a.score));
+        anomalies
+    }
+}
+
+fn mean_stddev(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    (mean, variance.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+
+    fn span(name: &str, start_us: i64, duration_us: u64) -> TraceSpan {
+        let start = DateTime::<Utc>::from_timestamp_micros(start_us).unwrap();
+        TraceSpan {
+            trace_id: "t".to_string(),
+            span_id: "s".to_string(),
+            parent_span_id: None,
+            name: name.to_string(),
+            start_time: start,
+            end_time: start,
+            duration_us,
+            attributes: HashMap::new(),
+            events: Vec::new(),
+            status: SpanStatus::Ok,
+        }
+    }
+
+    #[test]
+    fn test_latency_spike_is_detected() {
+        let window = 60_000_000;
+        let now = 100 * window;
+        let mut detector = AnomalyDetector::new(now, window, 10, 3.0);
+
+        // Ten baseline windows at ~10ms, then a recent window at 100ms
+        for i in 0..10 {
+            let start = detector.baseline_start_us() + i * window;
+            detector.observe(&span("checkout", start, 10_000 + (i as u64 % 2) * 500));
+        }
+        detector.observe(&span("checkout", now - window / 2, 100_000));
+
+        let anomalies = detector.detect();
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].metric, AnomalyMetric::Latency);
+        assert_eq!(anomalies[0].direction, "increase");
+    }
+}
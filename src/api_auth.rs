@@ -0,0 +1,267 @@
+use axum::{
+    extract::{ConnectInfo, Query, Request, State},
+    http::{header, Method, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::borrow::Cow;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::audit::{self, Outcome};
+
+/// Access level granted by an API token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiScope {
+    /// Query endpoints only
+    Read,
+    /// Everything, including endpoints that change daemon state
+    Admin,
+}
+
+/// Endpoints that take a POST body but only read data
+const READ_ONLY_POST_PATHS: &[&str] = &["/api/graphql"];
+
+impl ApiScope {
+    /// Scope a request needs: safe methods are reads, anything else is an admin action
+    pub fn required_for(method: &Method, path: &str) -> Self {
+        let read_only = matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+            || (*method == Method::POST && READ_ONLY_POST_PATHS.contains(&path));
+        if read_only {
+            ApiScope::Read
+        } else {
+            ApiScope::Admin
+        }
+    }
+}
+
+/// A token accepted by the AI API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    /// Label used in logs, never the token itself
+    #[serde(default)]
+    pub name: Option<String>,
+
+    pub token: String,
+
+    #[serde(default = "default_scope")]
+    pub scope: ApiScope,
+}
+
+fn default_scope() -> ApiScope {
+    ApiScope::Read
+}
+
+/// Checks presented tokens against the configured set
+#[derive(Debug, Clone, Default)]
+pub struct ApiAuth {
+    tokens: Vec<ApiToken>,
+}
+
+impl ApiAuth {
+    pub fn new(tokens: Vec<ApiToken>) -> Self {
+        Self { tokens }
+    }
+
+    /// Authentication is only enforced once at least one token is configured
+    pub fn is_enabled(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    /// Scope granted to a presented token, if it is known
+    pub fn scope_for(&self, presented: &str) -> Option<ApiScope> {
+        self.token_for(presented).map(|t| t.scope)
+    }
+
+    /// The configured token matching `presented`, with the widest scope
+    fn token_for(&self, presented: &str) -> Option<&ApiToken> {
+        self.tokens
+            .iter()
+            .filter(|t| constant_time_eq(t.token.as_bytes(), presented.as_bytes()))
+            .max_by_key(|t| t.scope)
+    }
+}
+
+/// Who made an API request, for the audit log: `token:<name>@<address>`, or the client
+/// address while authentication is disabled
+#[derive(Debug, Clone)]
+pub struct ApiActor(pub String);
+
+/// Query parameter holding a token
+const TOKEN_PARAM: &str = "access_token";
+
+/// Token from `Authorization: Bearer`, `X-API-Key`, or the URL-encoded `access_token` query
+/// parameter (for EventSource and WebSocket clients that cannot set headers)
+pub fn presented_token(req: &Request) -> Option<Cow<'_, str>> {
+    let headers = req.headers();
+    if let Some(bearer) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    {
+        return Some(Cow::Borrowed(bearer.trim()));
+    }
+    if let Some(key) = headers.get("x-api-key").and_then(|v| v.to_str().ok()) {
+        return Some(Cow::Borrowed(key.trim()));
+    }
+    query_pairs(req.uri())
+        .into_iter()
+        .find(|(name, _)| name == TOKEN_PARAM)
+        .map(|(_, token)| Cow::Owned(token))
+}
+
+/// `uri` without its `access_token` query parameter, for logging
+pub fn redacted_uri(uri: &Uri) -> Cow<'_, str> {
+    let pairs = query_pairs(uri);
+    let (Some(query), true) = (
+        uri.query(),
+        pairs.iter().any(|(name, _)| name == TOKEN_PARAM),
+    ) else {
+        return Cow::Owned(uri.to_string());
+    };
+    // Decoding skips empty pairs, so the rest line up with the decoded ones
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .zip(&pairs)
+        .filter(|(_, (name, _))| name != TOKEN_PARAM)
+        .map(|(pair, _)| pair)
+        .collect();
+    let path = uri.path();
+    match kept.is_empty() {
+        true => Cow::Borrowed(path),
+        false => Cow::Owned(format!("{}?{}", path, kept.join("&"))),
+    }
+}
+
+/// Decoded query parameters of `uri`; none when the query does not decode
+fn query_pairs(uri: &Uri) -> Vec<(String, String)> {
+    Query::<Vec<(String, String)>>::try_from_uri(uri)
+        .map(|Query(pairs)| pairs)
+        .unwrap_or_default()
+}
+
+/// Middleware rejecting requests without a token of sufficient scope
+pub async fn require_auth(
+    State(auth): State<Arc<ApiAuth>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let client = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map_or_else(|| "unknown".to_string(), |info| info.0.ip().to_string());
+    if !auth.is_enabled() {
+        req.extensions_mut().insert(ApiActor(client));
+        return next.run(req).await;
+    }
+
+    // The path only: the query string may hold the token
+    let details = json!({ "method": req.method().as_str(), "path": req.uri().path() });
+    let Some(token) = presented_token(&req).and_then(|t| auth.token_for(&t)) else {
+        let reason = "Missing or invalid API token";
+        audit::record(
+            &client,
+            "api.auth",
+            Outcome::Denied(reason.to_string()),
+            details,
+        );
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            reason,
+        )
+            .into_response();
+    };
+
+    let actor = format!(
+        "token:{}@{}",
+        token.name.as_deref().unwrap_or("unnamed"),
+        client
+    );
+    if token.scope < ApiScope::required_for(req.method(), req.uri().path()) {
+        let reason = "API token lacks the admin scope";
+        audit::record(
+            &actor,
+            "api.auth",
+            Outcome::Denied(reason.to_string()),
+            details,
+        );
+        return (StatusCode::FORBIDDEN, reason).into_response();
+    }
+
+    req.extensions_mut().insert(ApiActor(actor));
+    next.run(req).await
+}
+
+/// CORS policy for the configured origins; an empty list allows any origin
+#[cfg(feature = "api")]
+pub fn cors_layer(origins: &[String]) -> anyhow::Result<tower_http::cors::CorsLayer> {
+    use anyhow::{Context, Result};
+    use axum::http::HeaderValue;
+    use tower_http::cors::{AllowOrigin, CorsLayer};
+
+    if origins.is_empty() || origins.iter().any(|o| o == "*") {
+        return Ok(CorsLayer::permissive());
+    }
+
+    let origins = origins
+        .iter()
+        .map(|o| HeaderValue::from_str(o).with_context(|| format!("Invalid CORS origin: {}", o)))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods(tower_http::cors::Any)
+        .allow_headers(tower_http::cors::Any))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    #[test]
+    fn test_tokens_resolve_to_scopes() {
+        let auth = ApiAuth::new(vec![
+            ApiToken {
+                name: Some("agent".to_string()),
+                token: "read-token".to_string(),
+                scope: ApiScope::Read,
+            },
+            ApiToken {
+                name: None,
+                token: "admin-token".to_string(),
+                scope: ApiScope::Admin,
+            },
+        ]);
+
+        assert_eq!(auth.scope_for("read-token"), Some(ApiScope::Read));
+        assert_eq!(auth.scope_for("admin-token"), Some(ApiScope::Admin));
+        assert_eq!(auth.scope_for("read-toke"), None);
+        assert!(ApiScope::Read < ApiScope::required_for(&Method::DELETE, "/api/traces"));
+
+        let req = Request::builder()
+            .uri("/api/stream?x=1&access_token=read%2Btoken%3D&y=2")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(presented_token(&req).as_deref(), Some("read+token="));
+        assert_eq!(redacted_uri(req.uri()), "/api/stream?x=1&y=2");
+        let uri: Uri = "/api/stream?access_token=t".parse().unwrap();
+        assert_eq!(redacted_uri(&uri), "/api/stream");
+
+        let req = Request::builder()
+            .uri("/api/traces")
+            .header(header::AUTHORIZATION, "Bearer admin-token")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(presented_token(&req).as_deref(), Some("admin-token"));
+    }
+}
@@ -0,0 +1,102 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use crate::api_auth::ApiToken;
+use crate::federation::FederationConfig;
+use crate::keys::KeySource;
+use crate::query_cache::QueryCacheConfig;
+use crate::rate_limit::RateLimitConfig;
+
+/// Port the API listens on, on `127.0.0.1`, when `bind` is not set
+pub const DEFAULT_API_PORT: u16 = 9101;
+
+/// AI Agent API settings (the `[api]` section of the config file)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApiConfig {
+    /// Accepted API tokens; the API is unauthenticated while this is empty
+    #[serde(default)]
+    pub tokens: Vec<ApiToken>,
+
+    /// Origins allowed by CORS; empty (or `"*"`) allows any origin
+    #[serde(default)]
+    pub cors_origins: Vec<String>,
+
+    /// Listen address; defaults to `127.0.0.1` on the `--ai-api-port`
+    #[serde(default)]
+    pub bind: Option<SocketAddr>,
+
+    /// Serve HTTPS with this certificate and key
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+
+    /// Per-client request rate and global concurrency limits
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+
+    /// Also serve the gRPC agent API (`proto/agent.proto`) on this address
+    #[serde(default)]
+    pub grpc_bind: Option<SocketAddr>,
+
+    /// Other storage roots and daemons whose logs are served alongside this daemon's own
+    #[serde(default)]
+    pub federation: FederationConfig,
+
+    /// Cached log query results
+    #[serde(default)]
+    pub query_cache: QueryCacheConfig,
+}
+
+impl ApiConfig {
+    /// `bind`, or `127.0.0.1` on the default port
+    pub fn listen_addr(&self) -> SocketAddr {
+        self.bind
+            .unwrap_or_else(|| SocketAddr::from(([127, 0, 0, 1], DEFAULT_API_PORT)))
+    }
+}
+
+/// PEM certificate chain and private key for HTTPS
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+
+    /// Private key file; or set `key`
+    #[serde(default)]
+    pub key_path: Option<PathBuf>,
+
+    /// Private key from a file, an environment variable or a command such as a KMS client;
+    /// loaded again with the certificate on SIGHUP
+    #[serde(default)]
+    pub key: Option<KeySource>,
+}
+
+impl TlsConfig {
+    pub fn validate(&self) -> Result<()> {
+        match &self.key {
+            Some(_) if self.key_path.is_some() => {
+                bail!("api.tls: set key_path or key, not both")
+            }
+            Some(key) => key.validate(),
+            None if self.key_path.is_none() => bail!("api.tls needs key_path or key"),
+            None => Ok(()),
+        }
+    }
+
+    /// Where the private key is loaded from
+    pub fn key_source(&self) -> KeySource {
+        match (&self.key, &self.key_path) {
+            (Some(key), _) => key.clone(),
+            (None, path) => KeySource::File(path.clone().unwrap_or_default()),
+        }
+    }
+
+    /// The PEM certificate chain and private key, read from their sources now
+    pub async fn load(&self) -> Result<(Vec<u8>, Vec<u8>)> {
+        let cert = tokio::fs::read(&self.cert_path)
+            .await
+            .with_context(|| format!("Failed to read TLS certificate {:?}", self.cert_path))?;
+        let key = self.key_source().load().await?;
+        Ok((cert, key))
+    }
+}
@@ -0,0 +1,241 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::OnceLock;
+use tracing::{info, warn};
+
+/// The audit file of this process, once opened
+static AUDIT_LOG: OnceLock<AuditLog> = OnceLock::new();
+
+/// Events waiting for the writer thread; recording blocks once this many are queued, so
+/// none are ever dropped
+const AUDIT_QUEUE: usize = 1024;
+
+/// How an audited action ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Ok,
+    Failed(String),
+    /// Refused before it ran, e.g. a bad API token
+    Denied(String),
+}
+
+impl Outcome {
+    pub fn of<T>(result: &Result<T>) -> Self {
+        match result {
+            Ok(_) => Outcome::Ok,
+            Err(e) => Outcome::Failed(format!("{:#}", e)),
+        }
+    }
+}
+
+/// One line of the audit file
+#[derive(Debug, Serialize)]
+struct AuditEvent<'a> {
+    at: String,
+    /// Who asked: `uid=1000 pid=4242` on the admin socket, `token:<name>@<address>` on the
+    /// API, `signal:SIGHUP`, ...
+    actor: &'a str,
+    action: &'a str,
+    outcome: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+    details: &'a Value,
+}
+
+/// What the writer thread is asked to do
+enum Message {
+    Line(Vec<u8>),
+    /// Answered once every line queued before it is synced to disk
+    Flush(SyncSender<()>),
+}
+
+/// Append-only JSON Lines file of administrative actions, written by a thread of its own
+/// that syncs each batch of queued events once
+pub struct AuditLog {
+    path: PathBuf,
+    queue: SyncSender<Message>,
+}
+
+impl AuditLog {
+    /// Open `path` for appending, creating it readable by its owner only
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create audit log directory {:?}", dir))?;
+        }
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .mode(0o600)
+            .open(path)
+            .with_context(|| format!("Failed to open audit log {:?}", path))?;
+        let (queue, messages) = mpsc::sync_channel(AUDIT_QUEUE);
+        let writer_path = path.to_path_buf();
+        std::thread::Builder::new()
+            .name("audit-writer".to_string())
+            .spawn(move || write_batches(file, &writer_path, messages))
+            .context("Failed to start the audit log writer")?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            queue,
+        })
+    }
+
+    /// Queue one event for appending; `flush` waits until it is on disk
+    pub fn write(
+        &self,
+        actor: &str,
+        action: &str,
+        outcome: &Outcome,
+        details: &Value,
+    ) -> Result<()> {
+        let (outcome, error) = match outcome {
+            Outcome::Ok => ("ok", None),
+            Outcome::Failed(error) => ("failed", Some(error.as_str())),
+            Outcome::Denied(reason) => ("denied", Some(reason.as_str())),
+        };
+        let mut line = serde_json::to_vec(&AuditEvent {
+            at: Utc::now().to_rfc3339(),
+            actor,
+            action,
+            outcome,
+            error,
+            details,
+        })?;
+        line.push(b'\n');
+        self.queue
+            .send(Message::Line(line))
+            .map_err(|_| anyhow::anyhow!("Audit log writer stopped"))
+    }
+
+    /// Wait until every event queued so far is synced to disk
+    pub fn flush(&self) {
+        let (done, synced) = mpsc::sync_channel(1);
+        if self.queue.send(Message::Flush(done)).is_ok() {
+            let _ = synced.recv();
+        }
+    }
+}
+
+/// Append the lines queued in `messages`, syncing once per batch of what was queued
+/// together, until the log is dropped
+fn write_batches(mut file: File, path: &Path, messages: Receiver<Message>) {
+    while let Ok(first) = messages.recv() {
+        let mut lines = Vec::new();
+        let mut waiters = Vec::new();
+        for message in std::iter::once(first).chain(messages.try_iter()) {
+            match message {
+                Message::Line(line) => lines.extend_from_slice(&line),
+                Message::Flush(done) => waiters.push(done),
+            }
+        }
+        if !lines.is_empty() {
+            if let Err(e) = file.write_all(&lines).and_then(|()| file.sync_data()) {
+                warn!("Failed to write audit log {:?}: {}", path, e);
+            }
+        }
+        for done in waiters {
+            let _ = done.send(());
+        }
+    }
+}
+
+/// Record administrative actions of this process in `path` from now on; opened before
+/// privileges are dropped, so the file can stay owned by root
+pub fn init(path: &Path) -> Result<()> {
+    let log = AuditLog::open(path)?;
+    info!("Recording administrative actions in {:?}", path);
+    if AUDIT_LOG.set(log).is_err() {
+        warn!("Audit log already open; ignoring {:?}", path);
+    }
+    Ok(())
+}
+
+/// Wait until the events recorded so far are on disk, before the process exits
+pub fn flush() {
+    if let Some(log) = AUDIT_LOG.get() {
+        log.flush();
+    }
+}
+
+/// Record `action` by `actor`; does nothing unless `init` opened an audit file
+pub fn record(actor: &str, action: &str, outcome: Outcome, details: Value) {
+    let Some(log) = AUDIT_LOG.get() else {
+        return;
+    };
+    if let Err(e) = log.write(actor, action, &outcome, &details) {
+        warn!(
+            "Failed to record {} by {} in audit log {:?}: {:#}",
+            action, actor, log.path, e
+        );
+    }
+}
+
+/// Actor for the user running this process, for CLI commands
+pub fn local_user() -> String {
+    // SAFETY: getuid cannot fail
+    format!("uid={}", unsafe { libc::getuid() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_appends_events_across_opens() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("audit/audit.jsonl");
+
+        let log = AuditLog::open(&path).unwrap();
+        log.write(
+            "uid=0 pid=1",
+            "admin.flush-now",
+            &Outcome::Ok,
+            &json!({"message": "Flushed 3 log(s)"}),
+        )
+        .unwrap();
+        log.flush();
+        drop(log);
+        let log = AuditLog::open(&path).unwrap();
+        log.write(
+            "203.0.113.9",
+            "api.auth",
+            &Outcome::Denied("Missing or invalid API token".to_string()),
+            &json!({"method": "POST", "path": "/api/schemas/default/versions"}),
+        )
+        .unwrap();
+        log.write(
+            "signal:SIGHUP",
+            "config.reload",
+            &Outcome::of::<()>(&Err(anyhow::anyhow!("bad toml"))),
+            &json!({}),
+        )
+        .unwrap();
+        log.flush();
+
+        let events: Vec<Value> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0]["action"], "admin.flush-now");
+        assert_eq!(events[0]["outcome"], "ok");
+        assert!(events[0].get("error").is_none());
+        assert_eq!(events[1]["outcome"], "denied");
+        assert_eq!(events[1]["details"]["method"], "POST");
+        assert_eq!(events[2]["error"], "bad toml");
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
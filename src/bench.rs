@@ -0,0 +1,305 @@
+use anyhow::{bail, Context, Result};
+use chrono::{SecondsFormat, Utc};
+use serde_json::json;
+use std::fmt;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::stats::human_bytes;
+
+/// How long a rate-limited sender sleeps once it is ahead of schedule
+const PACING_SLEEP: Duration = Duration::from_micros(200);
+
+/// How often progress is printed
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Spaces sends out to a target rate
+#[derive(Debug, Clone, Copy)]
+pub struct Pacer {
+    start: Instant,
+    rate: Option<u64>,
+}
+
+impl Pacer {
+    /// Pace sends from `start` at `rate` per second; `None` never waits
+    pub fn new(start: Instant, rate: Option<u64>) -> Self {
+        Self { start, rate }
+    }
+
+    /// Whether `sent` logs are ahead of schedule, so the next one has to wait
+    pub fn is_ahead(&self, sent: u64) -> bool {
+        self.rate
+            .is_some_and(|rate| sent >= (self.start.elapsed().as_secs_f64() * rate as f64) as u64)
+    }
+
+    /// Sleep until the log after `sent` is due
+    pub fn wait(&self, sent: u64) {
+        while self.is_ahead(sent) {
+            std::thread::sleep(PACING_SLEEP);
+        }
+    }
+}
+
+/// `payload` with the 4-byte big-endian length prefix of the wire protocol
+pub fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(4 + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Settings of a `bench` run
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    pub socket_path: PathBuf,
+    /// Target logs per second across all connections; `None` sends as fast as possible
+    pub rate: Option<u64>,
+    pub duration: Duration,
+    /// Size of each JSON payload, excluding the length prefix
+    pub payload_bytes: usize,
+    pub connections: usize,
+}
+
+/// Outcome of a `bench` run
+#[derive(Debug)]
+pub struct BenchReport {
+    pub sent: u64,
+    pub bytes: u64,
+    pub elapsed: Duration,
+    pub connections: usize,
+    pub target_rate: Option<u64>,
+    /// Time each frame took to be written to the socket, in nanoseconds, sorted
+    latencies: Vec<u64>,
+}
+
+impl BenchReport {
+    /// Logs per second actually sent
+    pub fn throughput(&self) -> f64 {
+        self.sent as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// Write latency at percentile `p`, from 0.0 to 100.0
+    pub fn latency(&self, p: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (p / 100.0 * (self.latencies.len() - 1) as f64).round() as usize;
+        Duration::from_nanos(self.latencies[rank.min(self.latencies.len() - 1)])
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Sent {} logs ({}) in {:.2}s over {} connection(s)",
+            self.sent,
+            human_bytes(self.bytes),
+            self.elapsed.as_secs_f64(),
+            self.connections
+        )?;
+        write!(f, "Throughput: {:.0} logs/s", self.throughput())?;
+        if let Some(rate) = self.target_rate {
+            write!(
+                f,
+                " ({:.1}% of the {} target)",
+                self.throughput() / rate as f64 * 100.0,
+                rate
+            )?;
+        }
+        writeln!(
+            f,
+            ", {}/s",
+            human_bytes((self.bytes as f64 / self.elapsed.as_secs_f64()) as u64)
+        )?;
+        write!(
+            f,
+            "Write latency: p50 {:?}, p90 {:?}, p99 {:?}, p99.9 {:?}, max {:?}",
+            self.latency(50.0),
+            self.latency(90.0),
+            self.latency(99.0),
+            self.latency(99.9),
+            self.latency(100.0)
+        )
+    }
+}
+
+/// Send generated logs to the daemon at `socket_path` for `duration`, printing progress to
+/// stderr. The protocol has no acknowledgements, so latency is how long each frame took to be
+/// written; it grows once the daemon falls behind and the socket buffer fills up.
+pub fn run(config: &BenchConfig) -> Result<BenchReport> {
+    if config.connections == 0 {
+        bail!("connections must be greater than 0");
+    }
+    if config.rate == Some(0) {
+        bail!("rate must be greater than 0");
+    }
+    let streams = (0..config.connections)
+        .map(|_| {
+            UnixStream::connect(&config.socket_path)
+                .with_context(|| format!("Failed to connect to {:?}", config.socket_path))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let padding = "x".repeat(config.payload_bytes.saturating_sub(payload(0, 0, "").len()));
+    let sent = AtomicU64::new(0);
+    let start = Instant::now();
+
+    let results: Vec<Result<(u64, Vec<u64>)>> = std::thread::scope(|scope| {
+        let senders: Vec<_> = streams
+            .into_iter()
+            .enumerate()
+            .map(|(connection, stream)| {
+                // Spread the rate, remainder included, across the connections
+                let rate = config.rate.map(|rate| {
+                    let share = rate / config.connections as u64;
+                    share + u64::from((connection as u64) < rate % config.connections as u64)
+                });
+                let (padding, sent) = (&padding, &sent);
+                scope.spawn(move || {
+                    send(
+                        stream,
+                        connection,
+                        rate,
+                        start,
+                        config.duration,
+                        padding,
+                        sent,
+                    )
+                })
+            })
+            .collect();
+
+        let mut next_progress = start + PROGRESS_INTERVAL;
+        while !senders.iter().all(|sender| sender.is_finished()) {
+            std::thread::sleep(Duration::from_millis(50));
+            if Instant::now() >= next_progress {
+                next_progress += PROGRESS_INTERVAL;
+                eprint!(
+                    "\rSent {} logs in {:.0}s",
+                    sent.load(Ordering::Relaxed),
+                    start.elapsed().as_secs_f64()
+                );
+            }
+        }
+        eprintln!();
+        senders
+            .into_iter()
+            .map(|sender| sender.join().expect("bench sender panicked"))
+            .collect()
+    });
+    let elapsed = start.elapsed();
+
+    let mut bytes = 0;
+    let mut latencies = Vec::new();
+    for result in results {
+        let (sender_bytes, sender_latencies) = result?;
+        bytes += sender_bytes;
+        latencies.extend(sender_latencies);
+    }
+    latencies.sort_unstable();
+
+    Ok(BenchReport {
+        sent: latencies.len() as u64,
+        bytes,
+        elapsed,
+        connections: config.connections,
+        target_rate: config.rate,
+        latencies,
+    })
+}
+
+/// Send on one connection until `duration` has passed since `start`; returns the bytes sent
+/// and each write's latency
+fn send(
+    mut stream: UnixStream,
+    connection: usize,
+    rate: Option<u64>,
+    start: Instant,
+    duration: Duration,
+    padding: &str,
+    sent: &AtomicU64,
+) -> Result<(u64, Vec<u64>)> {
+    let mut bytes = 0;
+    let mut latencies = Vec::new();
+    let mut iteration = 0u64;
+    let pacer = Pacer::new(start, rate);
+    while start.elapsed() < duration {
+        if pacer.is_ahead(iteration) {
+            std::thread::sleep(PACING_SLEEP);
+            continue;
+        }
+
+        let frame = frame(&payload(connection, iteration, padding));
+
+        let written = Instant::now();
+        stream.write_all(&frame).with_context(|| {
+            format!("Connection {} failed after {} logs", connection, iteration)
+        })?;
+        latencies.push(written.elapsed().as_nanos() as u64);
+
+        bytes += frame.len() as u64;
+        iteration += 1;
+        sent.fetch_add(1, Ordering::Relaxed);
+    }
+    stream.flush()?;
+    Ok((bytes, latencies))
+}
+
+/// A log in the default schema; one in ten is an error
+fn payload(connection: usize, iteration: u64, padding: &str) -> Vec<u8> {
+    json!({
+        "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+        "level": if iteration.is_multiple_of(10) { "error" } else { "info" },
+        "message": format!("Bench message {} {}", iteration, padding),
+        "service": "bench",
+        "metadata": {
+            "connection": connection,
+            "iteration": iteration
+        }
+    })
+    .to_string()
+    .into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::os::unix::net::UnixListener;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_run_paces_and_measures() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("bench.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let reader = std::thread::spawn(move || {
+            let mut received = 0;
+            for stream in listener.incoming().take(2) {
+                let mut data = Vec::new();
+                stream.unwrap().read_to_end(&mut data).unwrap();
+                received += data.len();
+            }
+            received as u64
+        });
+
+        let report = run(&BenchConfig {
+            socket_path,
+            rate: Some(1000),
+            duration: Duration::from_millis(300),
+            payload_bytes: 512,
+            connections: 2,
+        })
+        .unwrap();
+
+        assert!((250..=300).contains(&report.sent), "{}", report.sent);
+        assert_eq!(report.bytes, reader.join().unwrap());
+        assert!((report.bytes / report.sent).abs_diff(4 + 512) <= 8);
+        assert!(report.latency(50.0) <= report.latency(100.0));
+        assert!(report.to_string().contains("% of the 1000 target"));
+    }
+}
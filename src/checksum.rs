@@ -0,0 +1,501 @@
+use anyhow::{Context, Result};
+use parquet::file::reader::SerializedFileReader;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// Checksums of the files of a storage directory, one JSON object per line
+pub const MANIFEST_FILE: &str = "manifest.jsonl";
+
+/// Subdirectory of a storage directory that files failing verification are moved into, out of
+/// the way of queries and retention
+pub const QUARANTINE_DIR: &str = "quarantine";
+
+/// Files without a checksum modified more recently than this may still be being written
+const IN_FLIGHT: Duration = Duration::from_secs(60);
+
+/// Serializes manifest appends and rewrites within the process
+static MANIFEST_LOCK: Mutex<()> = Mutex::new(());
+
+/// Held while the manifest of a directory is appended to or rewritten
+struct ManifestLock {
+    _guard: MutexGuard<'static, ()>,
+    /// The directory, `flock`ed so the process taking over during an upgrade waits too;
+    /// closing it releases the lock
+    _dir: File,
+}
+
+fn lock_manifest(dir: &Path) -> Result<ManifestLock> {
+    let guard = MANIFEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let file = File::open(dir).with_context(|| format!("Failed to open {:?}", dir))?;
+    // SAFETY: the descriptor is owned by `file`, which outlives the call
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to lock {:?}", dir));
+    }
+    Ok(ManifestLock {
+        _guard: guard,
+        _dir: file,
+    })
+}
+
+/// Background verification of stored files (the `[scrub]` section of the config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScrubConfig {
+    /// Seconds between passes over the log and trace directories; 0 turns the scrubber off
+    #[serde(default = "default_interval")]
+    pub interval_secs: u64,
+    /// Move files failing verification into `quarantine/`; off by default, so they are only
+    /// reported
+    #[serde(default)]
+    pub quarantine: bool,
+}
+
+impl Default for ScrubConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: default_interval(),
+            quarantine: false,
+        }
+    }
+}
+
+fn default_interval() -> u64 {
+    24 * 60 * 60
+}
+
+/// Size and SHA-256 of a file as written
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checksum {
+    pub bytes: u64,
+    pub sha256: String,
+}
+
+/// Line of the manifest
+#[derive(Debug, Serialize, Deserialize)]
+struct ManifestEntry {
+    /// File name within the storage directory
+    file: String,
+    #[serde(flatten)]
+    checksum: Checksum,
+}
+
+/// Writer hashing what goes through it, so a file's checksum comes without reading it back
+pub struct ChecksumWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    bytes: u64,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            bytes: 0,
+        }
+    }
+
+    /// The wrapped writer and the checksum of everything written to it
+    pub fn finish(self) -> (W, Checksum) {
+        let checksum = Checksum {
+            bytes: self.bytes,
+            sha256: hex(&self.hasher.finalize()),
+        };
+        (self.inner, checksum)
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.bytes += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Checksum of a file on disk
+pub fn checksum_file(path: &Path) -> std::io::Result<Checksum> {
+    let mut writer = ChecksumWriter::new(std::io::sink());
+    std::io::copy(&mut File::open(path)?, &mut writer)?;
+    Ok(writer.finish().1)
+}
+
+/// Add the checksum of a newly written file to the manifest of its directory
+pub fn record(path: &Path, checksum: Checksum) -> Result<()> {
+    let (Some(dir), Some(file)) = (path.parent(), path.file_name()) else {
+        anyhow::bail!("No directory or file name in {:?}", path);
+    };
+    let entry = ManifestEntry {
+        file: file.to_string_lossy().into_owned(),
+        checksum,
+    };
+    let mut line = serde_json::to_string(&entry)?;
+    line.push('\n');
+    let _lock = lock_manifest(dir)?;
+    let manifest = dir.join(MANIFEST_FILE);
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&manifest)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .with_context(|| format!("Failed to append to {:?}", manifest))
+}
+
+/// Checksums recorded in the manifest of `dir` by file name; the latest line for a file wins
+pub fn load_manifest(dir: &Path) -> Result<HashMap<String, Checksum>> {
+    let path = dir.join(MANIFEST_FILE);
+    match File::open(&path) {
+        Ok(file) => parse_manifest(BufReader::new(file))
+            .with_context(|| format!("Failed to read {:?}", path)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e).with_context(|| format!("Failed to open {:?}", path)),
+    }
+}
+
+/// Checksums by file name from manifest lines
+pub fn parse_manifest(reader: impl BufRead) -> Result<HashMap<String, Checksum>> {
+    let mut checksums = HashMap::new();
+    for (i, line) in reader.lines().enumerate() {
+        match serde_json::from_str::<ManifestEntry>(&line?) {
+            Ok(entry) => {
+                checksums.insert(entry.file, entry.checksum);
+            }
+            // A line cut short by a crash; its file shows up as unrecorded
+            Err(e) => warn!("Skipping manifest line {}: {}", i + 1, e),
+        }
+    }
+    Ok(checksums)
+}
+
+/// Manifest lines for `checksums`, sorted by file name
+pub fn manifest_contents(checksums: impl IntoIterator<Item = (String, Checksum)>) -> String {
+    let mut entries: Vec<_> = checksums.into_iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut contents = String::new();
+    for (file, checksum) in entries {
+        let entry = ManifestEntry { file, checksum };
+        // Strings and integers only, so serializing cannot fail
+        contents.push_str(&serde_json::to_string(&entry).unwrap_or_default());
+        contents.push('\n');
+    }
+    contents
+}
+
+/// Rewrite the manifest of `dir` without the lines of files no longer there, e.g. deleted by
+/// retention or quarantined
+pub fn prune_manifest(dir: &Path) -> Result<()> {
+    let _lock = lock_manifest(dir)?;
+    let contents = manifest_contents(
+        load_manifest(dir)?
+            .into_iter()
+            .filter(|(file, _)| dir.join(file).exists()),
+    );
+    let path = dir.join(MANIFEST_FILE);
+    let tmp = path.with_extension("jsonl.tmp");
+    std::fs::write(&tmp, contents).with_context(|| format!("Failed to write {:?}", tmp))?;
+    std::fs::rename(&tmp, &path).with_context(|| format!("Failed to replace {:?}", path))
+}
+
+/// Outcome of verifying a stored file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileCheck {
+    Ok,
+    /// No checksum recorded, e.g. written by an older release, but the Parquet footer reads
+    Unrecorded,
+    /// Truncated or grown since it was written
+    SizeMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    ChecksumMismatch,
+    /// Could not be read, or has no checksum and no readable footer
+    Unreadable(String),
+}
+
+impl FileCheck {
+    pub fn is_bad(&self) -> bool {
+        !matches!(self, Self::Ok | Self::Unrecorded)
+    }
+
+    /// Label of the `log_daemon_corrupt_files` metric
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Unrecorded => "unrecorded",
+            Self::SizeMismatch { .. } => "size",
+            Self::ChecksumMismatch => "checksum",
+            Self::Unreadable(_) => "unreadable",
+        }
+    }
+}
+
+impl fmt::Display for FileCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ok => write!(f, "ok"),
+            Self::Unrecorded => write!(f, "no checksum recorded; footer readable"),
+            Self::SizeMismatch { expected, actual } => {
+                write!(f, "{} bytes, written as {}", actual, expected)
+            }
+            Self::ChecksumMismatch => write!(f, "checksum mismatch"),
+            Self::Unreadable(e) => write!(f, "unreadable: {}", e),
+        }
+    }
+}
+
+/// Compare a file with its recorded checksum; without one, check that it opens as Parquet
+pub fn verify_file(path: &Path, expected: Option<&Checksum>) -> FileCheck {
+    let Some(expected) = expected else {
+        return match File::open(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| {
+                SerializedFileReader::new(file)?;
+                Ok(())
+            }) {
+            Ok(()) => FileCheck::Unrecorded,
+            Err(e) => FileCheck::Unreadable(e.to_string()),
+        };
+    };
+    match checksum_file(path) {
+        Ok(actual) if actual.bytes != expected.bytes => FileCheck::SizeMismatch {
+            expected: expected.bytes,
+            actual: actual.bytes,
+        },
+        Ok(actual) if actual.sha256 != expected.sha256 => FileCheck::ChecksumMismatch,
+        Ok(_) => FileCheck::Ok,
+        Err(e) => FileCheck::Unreadable(e.to_string()),
+    }
+}
+
+/// Move a file into the quarantine directory next to it
+pub fn quarantine_file(path: &Path) -> Result<PathBuf> {
+    let (Some(dir), Some(file)) = (path.parent(), path.file_name()) else {
+        anyhow::bail!("No directory or file name in {:?}", path);
+    };
+    let quarantine = dir.join(QUARANTINE_DIR);
+    std::fs::create_dir_all(&quarantine)
+        .with_context(|| format!("Failed to create {:?}", quarantine))?;
+    let target = quarantine.join(file);
+    std::fs::rename(path, &target)
+        .with_context(|| format!("Failed to move {:?} to {:?}", path, target))?;
+    Ok(target)
+}
+
+/// Every Parquet file of a storage directory checked against the manifest
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub files: Vec<(PathBuf, FileCheck)>,
+    /// Where the bad files went, when quarantined
+    pub quarantined: HashMap<PathBuf, PathBuf>,
+}
+
+impl VerifyReport {
+    pub fn bad(&self) -> impl Iterator<Item = &(PathBuf, FileCheck)> {
+        self.files.iter().filter(|(_, check)| check.is_bad())
+    }
+}
+
+impl fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (path, check) in &self.files {
+            if *check == FileCheck::Ok {
+                continue;
+            }
+            write!(f, "{}  {}", path.display(), check)?;
+            if let Some(target) = self.quarantined.get(path) {
+                write!(f, "  (moved to {})", target.display())?;
+            }
+            writeln!(f)?;
+        }
+        let count = |wanted: fn(&FileCheck) -> bool| {
+            self.files.iter().filter(|(_, check)| wanted(check)).count()
+        };
+        write!(
+            f,
+            "{} file(s): {} ok, {} without checksum, {} bad",
+            self.files.len(),
+            count(|check| *check == FileCheck::Ok),
+            count(|check| *check == FileCheck::Unrecorded),
+            count(FileCheck::is_bad)
+        )
+    }
+}
+
+/// Verify the Parquet files of `dir`, optionally quarantining the bad ones. A missing
+/// directory has no files.
+pub fn verify_dir(dir: &Path, quarantine: bool) -> Result<VerifyReport> {
+    let checksums = load_manifest(dir)?;
+    let mut files = Vec::new();
+    match std::fs::read_dir(dir) {
+        Ok(entries) => {
+            for entry in entries {
+                let path = entry?.path();
+                if path.extension().and_then(|s| s.to_str()) == Some("parquet") {
+                    files.push(path);
+                }
+            }
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("Failed to list {:?}", dir)),
+    }
+    files.sort();
+
+    let mut report = VerifyReport::default();
+    for path in files {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        let expected = name.and_then(|name| checksums.get(&name));
+        if expected.is_none() && in_flight(&path) {
+            continue;
+        }
+        let check = verify_file(&path, expected);
+        if check.is_bad() && quarantine {
+            let target = quarantine_file(&path)?;
+            report.quarantined.insert(path.clone(), target);
+        }
+        report.files.push((path, check));
+    }
+    Ok(report)
+}
+
+/// Whether a file without a checksum was modified too recently to be complete
+pub fn in_flight(path: &Path) -> bool {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < IN_FLIGHT)
+}
+
+/// Verify the log and trace directories every `interval_secs`, starting one interval after
+/// startup
+pub fn spawn_scrubber(config: ScrubConfig, stores: Vec<(&'static str, PathBuf)>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(config.interval_secs));
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            for (store, dir) in &stores {
+                let path = dir.clone();
+                let quarantine = config.quarantine;
+                let scrubbed = tokio::task::spawn_blocking(move || {
+                    let report = verify_dir(&path, quarantine)?;
+                    prune_manifest(&path)?;
+                    anyhow::Ok(report)
+                })
+                .await;
+                let report = match scrubbed {
+                    Ok(Ok(report)) => report,
+                    Ok(Err(e)) => {
+                        warn!("Failed to verify {:?}: {:#}", dir, e);
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!("Scrub task failed: {}", e);
+                        continue;
+                    }
+                };
+                crate::metrics::record_scrubbed_files(store, report.files.len() as u64);
+                for (path, check) in report.bad() {
+                    crate::metrics::record_corrupt_file(store, check.reason());
+                    match report.quarantined.get(path) {
+                        Some(target) => warn!(
+                            "Stored file {:?} failed verification ({}); moved to {:?}",
+                            path, check, target
+                        ),
+                        None => warn!("Stored file {:?} failed verification ({})", path, check),
+                    }
+                }
+                info!(
+                    "Verified {:?}: {}",
+                    dir,
+                    report.to_string().lines().last().unwrap_or("")
+                );
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::LogEntry;
+    use crate::storage::StorageEngine;
+    use parquet::basic::Compression;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_damaged_files_are_quarantined() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().to_path_buf();
+        let mut storage =
+            StorageEngine::new(dir.clone(), Compression::SNAPPY, 100, 1 << 20).unwrap();
+        for _ in 0..3 {
+            let log: LogEntry = serde_json::from_value(json!({
+                "timestamp": "2026-01-15T19:00:00Z",
+                "level": "info",
+                "message": "m",
+            }))
+            .unwrap();
+            storage.add_log(log).unwrap();
+            storage.flush().unwrap();
+        }
+        let files = storage.list_files().unwrap();
+        let checksums = load_manifest(&dir).unwrap();
+        assert_eq!(checksums.len(), 3);
+        let name = |path: &PathBuf| path.file_name().unwrap().to_str().unwrap().to_string();
+        assert_eq!(
+            checksums[&name(&files[0])],
+            checksum_file(&files[0]).unwrap()
+        );
+        assert_eq!(verify_dir(&dir, false).unwrap().bad().count(), 0);
+
+        // Flip a bit in one file and cut another short
+        let mut bytes = std::fs::read(&files[0]).unwrap();
+        bytes[10] ^= 1;
+        std::fs::write(&files[0], &bytes).unwrap();
+        let len = std::fs::metadata(&files[1]).unwrap().len();
+        File::options()
+            .write(true)
+            .open(&files[1])
+            .unwrap()
+            .set_len(len - 8)
+            .unwrap();
+
+        let report = verify_dir(&dir, true).unwrap();
+        assert_eq!(report.files[0].1, FileCheck::ChecksumMismatch);
+        assert_eq!(
+            report.files[1].1,
+            FileCheck::SizeMismatch {
+                expected: len,
+                actual: len - 8
+            }
+        );
+        assert_eq!(report.files[2].1, FileCheck::Ok);
+        assert!(dir.join(QUARANTINE_DIR).join(name(&files[0])).exists());
+        assert_eq!(storage.list_files().unwrap(), vec![files[2].clone()]);
+
+        prune_manifest(&dir).unwrap();
+        assert_eq!(load_manifest(&dir).unwrap().len(), 1);
+    }
+}
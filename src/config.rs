@@ -1,20 +1,103 @@
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::api_config::ApiConfig;
+use crate::checksum::ScrubConfig;
+use crate::disk_watch::DiskWatchConfig;
+use crate::forward::ForwardConfig;
+use crate::log_format::LogFormat;
+use crate::metrics::MetricsConfig;
+use crate::notify::NotifyConfig;
+use crate::otel::OtelConfig;
+use crate::payload_log::PayloadLogConfig;
+use crate::pipeline::IngestConfig;
+use crate::redaction::RedactionConfig;
+use crate::sandbox::SandboxConfig;
+use crate::storage::{PartitionConfig, WriteFailureConfig};
+use crate::trace_storage::SpanLimits;
+
+/// Prefix of environment variables overriding config file values
+pub const ENV_PREFIX: &str = "DAEMON_RS_";
+
+/// Environment variable naming the config file, used when `--config` is not given
+pub const CONFIG_PATH_ENV: &str = "DAEMON_RS_CONFIG";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[allow(dead_code)]
 pub struct Config {
     /// Unix socket path for log ingestion
     #[serde(default = "default_socket_path")]
     pub socket_path: PathBuf,
 
+    /// Octal mode of the socket, e.g. `0660`; defaults to what the umask allows
+    #[serde(default)]
+    pub socket_mode: Option<String>,
+
+    /// Owner of the socket as `user:group`, `user` or `:group`
+    #[serde(default)]
+    pub socket_owner: Option<String>,
+
+    /// Unix socket for admin commands such as flush-now and drain; disabled when unset
+    #[serde(default)]
+    pub admin_socket: Option<PathBuf>,
+
+    /// Append-only file recording administrative actions; disabled when unset
+    #[serde(default)]
+    pub audit_log: Option<PathBuf>,
+
     /// Directory for storing Parquet files
     #[serde(default = "default_storage_dir")]
     pub storage_dir: PathBuf,
 
+    /// Directory the AI Agent API reads traces from
+    #[serde(default = "default_trace_storage_dir")]
+    pub trace_storage_dir: PathBuf,
+
+    /// Spans written per trace Parquet file
+    #[serde(default = "default_batch_size")]
+    pub trace_batch_size: usize,
+
+    /// Compression codec of trace files (snappy, zstd, gzip, none)
+    #[serde(default = "default_compression")]
+    pub trace_compression: String,
+
+    /// Seconds a partial batch of spans waits before it is written
+    #[serde(default = "default_flush_interval")]
+    pub trace_flush_interval_secs: u64,
+
+    /// Write each trace whole, once its root span ends, so a trace is read from one place
+    #[serde(default)]
+    pub trace_colocate: bool,
+
+    /// Seconds a trace without a root span is held in co-location mode before it is written
+    #[serde(default = "default_trace_colocate_timeout")]
+    pub trace_colocate_timeout_secs: u64,
+
     /// Path to JSON Schema file for validation
     pub schema_path: Option<PathBuf>,
 
+    /// Per-service JSON Schema files, keyed by the `service` field they apply to
+    #[serde(default)]
+    pub schemas: BTreeMap<String, PathBuf>,
+
+    /// TOML file of alert rules evaluated on every accepted log
+    #[serde(default)]
+    pub alert_rules: Option<PathBuf>,
+
+    /// Notification sinks for alerts and daemon health events
+    #[serde(default)]
+    pub notify: NotifyConfig,
+
+    /// Also relay accepted logs to an upstream daemon or OTLP receiver
+    #[serde(default)]
+    pub forward: Option<ForwardConfig>,
+
+    /// Where schema versions registered over the API are kept; defaults to
+    /// `schema_registry` next to the config file
+    #[serde(default)]
+    pub schema_registry_dir: Option<PathBuf>,
+
     /// Batch size for Parquet writes
     #[serde(default = "default_batch_size")]
     pub batch_size: usize,
@@ -34,68 +117,239 @@ pub struct Config {
     /// Flush interval in seconds (default: 5s)
     #[serde(default = "default_flush_interval")]
     pub flush_interval_secs: u64,
+
+    /// Event-time partitioning of log files
+    #[serde(default)]
+    pub partitioning: PartitionConfig,
+
+    /// Retries and buffering while storage writes fail
+    #[serde(default)]
+    pub write_failures: WriteFailureConfig,
+
+    /// Background verification of stored files against their checksums
+    #[serde(default)]
+    pub scrub: ScrubConfig,
+
+    /// Free-space thresholds at which ingestion degrades before the storage volume fills
+    #[serde(default)]
+    pub disk_watch: DiskWatchConfig,
+
+    /// Log filter directives, e.g. `info` or `daemon_rs=debug,warn`; defaults to `RUST_LOG`,
+    /// then `info`
+    #[serde(default)]
+    pub log_level: Option<String>,
+
+    /// Format of the daemon's own logs on stderr
+    #[serde(default)]
+    pub log_format: LogFormat,
+
+    /// Sampling of raw client payloads into the daemon's own warnings; off by default
+    #[serde(default)]
+    pub log_payloads: PayloadLogConfig,
+
+    /// User, by name or id, to switch to once the socket is bound; needs root
+    #[serde(default)]
+    pub user: Option<String>,
+
+    /// Group, by name or id, to switch to once the socket is bound; defaults to the user's
+    /// primary group
+    #[serde(default)]
+    pub group: Option<String>,
+
+    /// Landlock and seccomp hardening of `serve`
+    #[serde(default)]
+    pub sandbox: SandboxConfig,
+
+    /// Processing applied to accepted logs before storage
+    #[serde(default)]
+    pub ingest: IngestConfig,
+
+    /// Attribute redaction applied to stored spans
+    #[serde(default)]
+    pub trace_redaction: RedactionConfig,
+
+    /// Caps on the attributes and events of stored spans
+    #[serde(default)]
+    pub trace_limits: SpanLimits,
+
+    /// AI Agent API authentication and CORS
+    #[serde(default)]
+    pub api: ApiConfig,
+
+    /// Prometheus metric naming and histogram buckets
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+
+    /// OpenTelemetry tracing and export
+    #[serde(default)]
+    pub otel: OtelConfig,
 }
 
 impl Default for Config {
-    #[allow(dead_code)]
     fn default() -> Self {
         Self {
             socket_path: default_socket_path(),
+            socket_mode: None,
+            socket_owner: None,
+            admin_socket: None,
+            audit_log: None,
             storage_dir: default_storage_dir(),
+            trace_storage_dir: default_trace_storage_dir(),
+            trace_batch_size: default_batch_size(),
+            trace_compression: default_compression(),
+            trace_flush_interval_secs: default_flush_interval(),
+            trace_colocate: false,
+            trace_colocate_timeout_secs: default_trace_colocate_timeout(),
             schema_path: None,
+            schemas: BTreeMap::new(),
+            alert_rules: None,
+            notify: NotifyConfig::default(),
+            forward: None,
+            schema_registry_dir: None,
             batch_size: default_batch_size(),
             compression: default_compression(),
             max_connections: default_max_connections(),
             rotation_size: default_rotation_size(),
             flush_interval_secs: default_flush_interval(),
+            partitioning: PartitionConfig::default(),
+            write_failures: WriteFailureConfig::default(),
+            scrub: ScrubConfig::default(),
+            disk_watch: DiskWatchConfig::default(),
+            log_level: None,
+            log_format: LogFormat::default(),
+            log_payloads: PayloadLogConfig::default(),
+            user: None,
+            group: None,
+            sandbox: SandboxConfig::default(),
+            ingest: IngestConfig::default(),
+            trace_redaction: RedactionConfig::default(),
+            trace_limits: SpanLimits::default(),
+            api: ApiConfig::default(),
+            metrics: MetricsConfig::default(),
+            otel: OtelConfig::default(),
         }
     }
 }
 
-#[allow(dead_code)]
 fn default_socket_path() -> PathBuf {
     PathBuf::from("/tmp/logdaemon.sock")
 }
 
-#[allow(dead_code)]
 fn default_storage_dir() -> PathBuf {
     PathBuf::from("./logs")
 }
 
-#[allow(dead_code)]
+fn default_trace_storage_dir() -> PathBuf {
+    PathBuf::from("./traces")
+}
+
 fn default_batch_size() -> usize {
     1000
 }
 
-#[allow(dead_code)]
 fn default_compression() -> String {
     "snappy".to_string()
 }
 
-#[allow(dead_code)]
 fn default_max_connections() -> usize {
     1000
 }
 
-#[allow(dead_code)]
 fn default_rotation_size() -> u64 {
     100 * 1024 * 1024 // 100MB
 }
 
-#[allow(dead_code)]
 fn default_flush_interval() -> u64 {
     5
 }
 
-#[allow(dead_code)]
+fn default_trace_colocate_timeout() -> u64 {
+    30
+}
+
 impl Config {
     /// Load configuration from a TOML file
-    pub fn from_file(path: &PathBuf) -> anyhow::Result<Self> {
-        let content = std::fs::read_to_string(path)?;
-        let config: Config = toml::from_str(&content)?;
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {:?}", path))?;
+        let config: Config = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse config file {:?}", path))?;
         Ok(config)
     }
 
+    /// Load the config file, if any, with `DAEMON_RS_*` environment variables layered over it
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        Self::load_with_env(path, std::env::vars())
+    }
+
+    /// Like `load`, with the environment given as `vars`
+    pub fn load_with_env(
+        path: Option<&Path>,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> anyhow::Result<Self> {
+        let mut table = match path {
+            Some(path) => {
+                let content = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read config file {:?}", path))?;
+                toml::from_str(&content)
+                    .with_context(|| format!("Failed to parse config file {:?}", path))?
+            }
+            None => toml::Table::new(),
+        };
+        let defaults = toml::Table::try_from(Config::default())?;
+
+        let mut vars: Vec<_> = vars
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX) && name != CONFIG_PATH_ENV)
+            .collect();
+        // Deterministic when both a section and one of its keys are set
+        vars.sort();
+        // Values read as numbers or booleans for keys without a default, such as the optional
+        // paths, which are retried as strings if the typed reading does not fit
+        let mut untyped = Vec::new();
+        for (name, value) in vars {
+            let path: Vec<String> = name[ENV_PREFIX.len()..]
+                .split("__")
+                .map(str::to_ascii_lowercase)
+                .collect();
+            if path.iter().any(String::is_empty) {
+                anyhow::bail!("Invalid config environment variable name: {}", name);
+            }
+            let default = lookup(&defaults, &path);
+            let parsed = env_value(&value, default);
+            if default.is_none() && !parsed.is_str() {
+                untyped.push((path.clone(), value));
+            }
+            insert(&mut table, &path, parsed);
+        }
+
+        let typed = toml::Value::Table(table.clone()).try_into::<Config>();
+        if typed.is_err() && !untyped.is_empty() {
+            for (path, value) in untyped {
+                insert(&mut table, &path, toml::Value::String(value));
+            }
+            if let Ok(config) = toml::Value::Table(table).try_into() {
+                return Ok(config);
+            }
+        }
+        typed.context("Invalid configuration")
+    }
+
+    /// `schema_registry_dir`, or `schema_registry` in `config_dir`, the config file's directory
+    pub fn registry_dir(&self, config_dir: &Path) -> PathBuf {
+        self.schema_registry_dir
+            .clone()
+            .unwrap_or_else(|| config_dir.join("schema_registry"))
+    }
+
+    /// The log filter directives in effect
+    pub fn log_filter(&self) -> String {
+        self.log_level
+            .clone()
+            .or_else(|| std::env::var("RUST_LOG").ok())
+            .unwrap_or_else(|| "info".to_string())
+    }
+
     /// Validate configuration
     pub fn validate(&self) -> anyhow::Result<()> {
         if self.batch_size == 0 {
@@ -106,13 +360,278 @@ impl Config {
             anyhow::bail!("max_connections must be greater than 0");
         }
 
-        if !["snappy", "zstd", "gzip", "none"].contains(&self.compression.as_str()) {
-            anyhow::bail!(
-                "Invalid compression codec: {}. Must be one of: snappy, zstd, gzip, none",
-                self.compression
-            );
+        if self.rotation_size == 0 {
+            anyhow::bail!("rotation_size must be greater than 0");
+        }
+
+        if self.flush_interval_secs == 0 {
+            anyhow::bail!("flush_interval_secs must be greater than 0");
+        }
+
+        crate::storage::try_parse_compression(&self.compression)?;
+
+        if self.trace_batch_size == 0 {
+            anyhow::bail!("trace_batch_size must be greater than 0");
+        }
+
+        if self.trace_flush_interval_secs == 0 {
+            anyhow::bail!("trace_flush_interval_secs must be greater than 0");
+        }
+
+        if self.trace_colocate_timeout_secs == 0 {
+            anyhow::bail!("trace_colocate_timeout_secs must be greater than 0");
+        }
+
+        crate::storage::try_parse_compression(&self.trace_compression)
+            .context("Invalid trace_compression")?;
+        self.write_failures.validate()?;
+
+        if self
+            .trace_redaction
+            .patterns
+            .iter()
+            .any(|p| p.trim().is_empty())
+        {
+            anyhow::bail!("trace_redaction.patterns must not contain empty patterns");
         }
+        self.trace_limits.validate()?;
+
+        if self.api.tokens.iter().any(|t| t.token.trim().is_empty()) {
+            anyhow::bail!("api.tokens must not contain empty tokens");
+        }
+
+        if self.api.rate_limit.max_concurrent == 0 {
+            anyhow::bail!("api.rate_limit.max_concurrent must be greater than 0");
+        }
+
+        if self.api.rate_limit.requests_per_second < 0.0 {
+            anyhow::bail!("api.rate_limit.requests_per_second must not be negative");
+        }
+
+        self.api.federation.validate()?;
+        if let Some(tls) = &self.api.tls {
+            tls.validate()?;
+        }
+
+        if self.schemas.keys().any(|service| service.trim().is_empty()) {
+            anyhow::bail!("schemas must not contain an empty service name");
+        }
+        for listener in &self.ingest.listeners {
+            if let Some(schema) = &listener.schema {
+                if !self.schemas.contains_key(schema) {
+                    anyhow::bail!(
+                        "ingest.listeners {:?} uses schema {:?}, which is not in [schemas]",
+                        listener.name,
+                        schema
+                    );
+                }
+            }
+        }
+
+        if let Some(level) = &self.log_level {
+            crate::otel::env_filter(level).context("Invalid log_level")?;
+        }
+
+        for (key, value) in [("user", &self.user), ("group", &self.group)] {
+            if value.as_deref().is_some_and(|v| v.trim().is_empty()) {
+                anyhow::bail!("{} must not be empty", key);
+            }
+        }
+
+        if let Some(mode) = &self.socket_mode {
+            crate::privileges::parse_mode(mode).context("Invalid socket_mode")?;
+        }
+
+        if let Some(owner) = &self.socket_owner {
+            crate::privileges::parse_owner(owner).context("Invalid socket_owner")?;
+        }
+
+        if self.admin_socket.as_ref() == Some(&self.socket_path) {
+            anyhow::bail!("admin_socket must differ from socket_path");
+        }
+        let mut sockets: Vec<&PathBuf> = vec![&self.socket_path];
+        sockets.extend(&self.admin_socket);
+        for listener in &self.ingest.listeners {
+            if sockets.contains(&&listener.socket_path) {
+                anyhow::bail!(
+                    "ingest.listeners {}: socket_path {:?} is already in use",
+                    listener.name,
+                    listener.socket_path
+                );
+            }
+            sockets.push(&listener.socket_path);
+        }
+
+        self.log_payloads.validate()?;
+        self.sandbox.validate()?;
+        self.ingest.validate()?;
+        self.metrics.validate()?;
+        self.notify.validate()?;
+        self.disk_watch.validate()?;
+        if let Some(forward) = &self.forward {
+            forward.validate()?;
+        }
+        for quota in &self.ingest.quotas {
+            if let Some(sink) = quota
+                .sinks
+                .iter()
+                .find(|sink| !self.notify.sinks.iter().any(|s| &s.name == *sink))
+            {
+                anyhow::bail!(
+                    "ingest.quotas for {} names an unknown sink: {}",
+                    quota.service,
+                    sink
+                );
+            }
+        }
+        self.otel.validate()?;
 
         Ok(())
     }
 }
+
+/// The value at `path`, if every step of it exists
+fn lookup<'a>(table: &'a toml::Table, path: &[String]) -> Option<&'a toml::Value> {
+    let (last, parents) = path.split_last()?;
+    let mut current = table;
+    for key in parents {
+        current = current.get(key)?.as_table()?;
+    }
+    current.get(last)
+}
+
+/// Set the value at `path`, creating tables for missing or non-table steps
+fn insert(table: &mut toml::Table, path: &[String], value: toml::Value) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let mut current = table;
+    for key in parents {
+        let next = current
+            .entry(key.clone())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        if !next.is_table() {
+            *next = toml::Value::Table(toml::Table::new());
+        }
+        current = next.as_table_mut().expect("replaced with a table above");
+    }
+    current.insert(last.clone(), value);
+}
+
+/// Read an environment variable as TOML (numbers, booleans, arrays, inline tables), except for
+/// fields whose default is a string, and fall back to the raw string
+fn env_value(raw: &str, default: Option<&toml::Value>) -> toml::Value {
+    if matches!(default, Some(toml::Value::String(_))) {
+        return toml::Value::String(raw.to_string());
+    }
+    toml::from_str::<toml::Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut parsed| parsed.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_config_file_defaults_and_validation() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("daemon.toml");
+        std::fs::write(
+            &path,
+            r#"
+            storage_dir = "/var/log/daemon"
+            compression = "zstd"
+
+            [otel]
+            enabled = false
+            sampling_rate = 0.25
+
+            [api]
+            bind = "0.0.0.0:9101"
+            "#,
+        )
+        .unwrap();
+
+        let config = Config::from_file(&path).unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.storage_dir, PathBuf::from("/var/log/daemon"));
+        assert_eq!(config.socket_path, default_socket_path());
+        assert_eq!(config.trace_storage_dir, default_trace_storage_dir());
+        assert_eq!(config.trace_batch_size, default_batch_size());
+        assert!(!config.otel.enabled);
+        assert_eq!(config.api.bind, Some("0.0.0.0:9101".parse().unwrap()));
+
+        let mut invalid = config.clone();
+        invalid.otel.sampling_rate = 1.5;
+        assert!(invalid.validate().is_err());
+        let mut invalid = config.clone();
+        invalid.trace_compression = "lz5".into();
+        assert!(invalid.validate().is_err());
+        std::fs::write(&path, "batch_size = \"many\"").unwrap();
+        assert!(Config::from_file(&path).is_err());
+    }
+
+    #[test]
+    fn test_compression_levels_are_validated() {
+        let config = |compression: &str| Config {
+            compression: compression.to_string(),
+            ..Config::default()
+        };
+        config("zstd:7").validate().unwrap();
+        config("gzip:9").validate().unwrap();
+        assert!(config("zstd:23").validate().is_err());
+        assert!(config("snappy:3").validate().is_err());
+    }
+
+    #[test]
+    fn test_environment_overrides_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("daemon.toml");
+        std::fs::write(
+            &path,
+            "compression = \"zstd\"\nbatch_size = 10\n[otel]\nenabled = false\n",
+        )
+        .unwrap();
+        let env = |vars: &[(&str, &str)]| {
+            vars.iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        let config = Config::load_with_env(
+            Some(&path),
+            env(&[
+                ("DAEMON_RS_BATCH_SIZE", "500"),
+                ("DAEMON_RS_STORAGE_DIR", "/data/logs"),
+                ("DAEMON_RS_OTEL__SAMPLING_RATE", "0.5"),
+                ("DAEMON_RS_API__BIND", "0.0.0.0:9101"),
+                ("DAEMON_RS_API__CORS_ORIGINS", r#"["https://example.com"]"#),
+                ("DAEMON_RS_SOCKET_PATH", "4242"),
+                ("DAEMON_RS_AUDIT_LOG", "123"),
+                ("DAEMON_RS_USER", "1000"),
+                ("DAEMON_RS_CONFIG", "ignored.toml"),
+                ("HOME", "/root"),
+            ]),
+        )
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.compression, "zstd");
+        assert_eq!(config.batch_size, 500);
+        assert_eq!(config.storage_dir, PathBuf::from("/data/logs"));
+        assert!(!config.otel.enabled);
+        assert_eq!(config.otel.sampling_rate, 0.5);
+        assert_eq!(config.api.bind, Some("0.0.0.0:9101".parse().unwrap()));
+        assert_eq!(config.api.cors_origins, vec!["https://example.com"]);
+        // String fields keep values that look like numbers
+        assert_eq!(config.socket_path, PathBuf::from("4242"));
+        // Including optional ones, which have no default to go by
+        assert_eq!(config.audit_log, Some(PathBuf::from("123")));
+        assert_eq!(config.user.as_deref(), Some("1000"));
+
+        assert!(Config::load_with_env(None, env(&[("DAEMON_RS_BATCH_SIZE", "many")])).is_err());
+        assert!(Config::load_with_env(None, env(&[("DAEMON_RS_OTEL__", "x")])).is_err());
+    }
+}
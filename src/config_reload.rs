@@ -0,0 +1,302 @@
+use anyhow::{anyhow, Context, Result};
+use std::sync::Arc;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{info, warn};
+
+use crate::audit::{self, Outcome};
+use crate::config::Config;
+use crate::keys::KeyRotation;
+use crate::otel::{LogFilterHandle, SamplerHandle};
+use crate::rate_limit::RateLimiter;
+use crate::schema_reload::{SchemaReloadTrigger, SchemaSources, SharedSources};
+use crate::server::FlushInterval;
+
+/// Config keys applied without a restart; each also covers the keys nested under it
+pub const RELOADABLE_KEYS: &[&str] = &[
+    "flush_interval_secs",
+    "log_level",
+    "log_payloads",
+    "otel.sampling_rate",
+    "api.rate_limit.requests_per_second",
+    "api.rate_limit.burst",
+    "schema_path",
+    "schemas",
+];
+
+/// The running components a config reload updates
+pub struct ReloadTargets {
+    pub flush_interval: FlushInterval,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub sampler: SamplerHandle,
+    pub log_filter: LogFilterHandle,
+    pub schema_sources: SharedSources,
+    pub schema_reload: SchemaReloadTrigger,
+    /// Asks TLS key holders to load their keys again
+    pub key_rotation: KeyRotation,
+}
+
+impl ReloadTargets {
+    /// Apply the reloadable settings of `config`, then reload the schemas
+    pub fn apply(&self, config: &Config) -> Result<()> {
+        self.log_filter
+            .reload(crate::otel::env_filter(&config.log_filter())?)
+            .context("Failed to replace the log filter")?;
+        crate::payload_log::configure(&config.log_payloads);
+        self.flush_interval.set_secs(config.flush_interval_secs);
+        self.rate_limiter.set_limits(
+            config.api.rate_limit.requests_per_second,
+            config.api.rate_limit.burst,
+        );
+        self.sampler.set_rate(config.otel.sampling_rate);
+        *self
+            .schema_sources
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = SchemaSources {
+            default: config.schema_path.clone(),
+            services: config.schemas.clone(),
+        };
+        self.schema_reload.reload("config reload");
+        self.key_rotation.rotate();
+        Ok(())
+    }
+}
+
+/// Asks the task started by `spawn_config_reloader` to reload the config, as on SIGHUP
+#[derive(Debug, Clone)]
+pub struct ConfigReloadTrigger(mpsc::Sender<oneshot::Sender<Result<ReloadSummary>>>);
+
+impl ConfigReloadTrigger {
+    /// Reload now and return which keys changed
+    pub async fn reload(&self) -> Result<ReloadSummary> {
+        let (reply, summary) = oneshot::channel();
+        self.0
+            .send(reply)
+            .await
+            .map_err(|_| anyhow!("The config reloader is not running"))?;
+        summary
+            .await
+            .map_err(|_| anyhow!("The config reloader stopped"))?
+    }
+}
+
+/// Changed config keys, split by whether a reload applied them
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReloadSummary {
+    pub applied: Vec<String>,
+    pub needs_restart: Vec<String>,
+}
+
+fn is_reloadable(key: &str) -> bool {
+    RELOADABLE_KEYS.iter().any(|reloadable| {
+        key.strip_prefix(reloadable)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+    })
+}
+
+/// Dotted keys whose values differ between `old` and `new`
+pub fn changed_keys(old: &Config, new: &Config) -> Result<Vec<String>> {
+    let mut old_values = Vec::new();
+    let mut new_values = Vec::new();
+    flatten("", &toml::Value::try_from(old)?, &mut old_values);
+    flatten("", &toml::Value::try_from(new)?, &mut new_values);
+
+    let mut changed: Vec<String> = old_values
+        .iter()
+        .filter(|entry| !new_values.contains(entry))
+        .chain(
+            new_values
+                .iter()
+                .filter(|entry| !old_values.contains(entry)),
+        )
+        .map(|(key, _)| key.clone())
+        .collect();
+    changed.sort();
+    changed.dedup();
+    Ok(changed)
+}
+
+/// Leaf values of `value` with their dotted keys; arrays count as one value
+fn flatten(prefix: &str, value: &toml::Value, out: &mut Vec<(String, toml::Value)>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten(&key, value, out);
+            }
+        }
+        value => out.push((prefix.to_string(), value.clone())),
+    }
+}
+
+/// Apply the reloadable settings of `new` and record them in `current`; other changes are
+/// reported and left for a restart
+pub fn reload_config(
+    current: &mut Config,
+    new: Config,
+    targets: &ReloadTargets,
+) -> Result<ReloadSummary> {
+    let (applied, needs_restart) = changed_keys(current, &new)?
+        .into_iter()
+        .partition(|key| is_reloadable(key));
+    targets.apply(&new)?;
+
+    current.flush_interval_secs = new.flush_interval_secs;
+    current.log_level = new.log_level;
+    current.log_payloads = new.log_payloads;
+    current.otel.sampling_rate = new.otel.sampling_rate;
+    current.api.rate_limit.requests_per_second = new.api.rate_limit.requests_per_second;
+    current.api.rate_limit.burst = new.api.rate_limit.burst;
+    current.schema_path = new.schema_path;
+    current.schemas = new.schemas;
+
+    Ok(ReloadSummary {
+        applied,
+        needs_restart,
+    })
+}
+
+/// Reload the config with `load` on SIGHUP or when the returned trigger fires, which validates
+/// it, and apply what can change without a restart; the schemas are reloaded even when the
+/// config is invalid
+pub fn spawn_config_reloader(
+    mut current: Config,
+    load: impl Fn() -> Result<Config> + Send + 'static,
+    targets: ReloadTargets,
+) -> Result<ConfigReloadTrigger> {
+    let mut hangups = signal(SignalKind::hangup()).context("Failed to listen for SIGHUP")?;
+    let (tx, mut requests) = mpsc::channel::<oneshot::Sender<Result<ReloadSummary>>>(1);
+
+    tokio::spawn(async move {
+        loop {
+            let (trigger, reply) = tokio::select! {
+                Some(()) = hangups.recv() => ("SIGHUP", None),
+                Some(reply) = requests.recv() => ("admin request", Some(reply)),
+                else => break,
+            };
+            let summary = load().and_then(|new| reload_config(&mut current, new, &targets));
+            match &summary {
+                Ok(summary) => {
+                    if summary.applied.is_empty() {
+                        info!(
+                            "Reloaded config after {}; no reloadable settings changed",
+                            trigger
+                        );
+                    } else {
+                        info!(
+                            "Reloaded config after {}; changed: {}",
+                            trigger,
+                            summary.applied.join(", ")
+                        );
+                    }
+                    if !summary.needs_restart.is_empty() {
+                        warn!(
+                            "Config changes need a restart to take effect: {}",
+                            summary.needs_restart.join(", ")
+                        );
+                    }
+                }
+                Err(e) => {
+                    warn!(
+                        "Config reload after {} failed, keeping the previous settings: {:#}",
+                        trigger, e
+                    );
+                    targets.schema_reload.reload(trigger);
+                }
+            }
+            // Admin socket requests are audited with the requester by the admin server
+            if reply.is_none() {
+                let details = summary.as_ref().ok().map(|summary| {
+                    serde_json::json!({
+                        "applied": summary.applied,
+                        "needs_restart": summary.needs_restart,
+                    })
+                });
+                audit::record(
+                    &format!("signal:{}", trigger),
+                    "config.reload",
+                    Outcome::of(&summary),
+                    details.unwrap_or_default(),
+                );
+            }
+            if let Some(reply) = reply {
+                // The requester may have given up waiting
+                let _ = reply.send(summary);
+            }
+        }
+    });
+
+    Ok(ConfigReloadTrigger(tx))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::SharedSchemas;
+    use crate::schema_registry::SchemaRegistry;
+    use crate::schema_reload::spawn_schema_reloader;
+    use std::sync::RwLock;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_reload_applies_reloadable_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        let sources: SharedSources = Arc::new(RwLock::new(SchemaSources::default()));
+        let schemas = SharedSchemas::new(SchemaSources::default().build().unwrap());
+        let registry = Arc::new(SchemaRegistry::new(
+            temp_dir.path().join("registry"),
+            schemas,
+        ));
+        // The handle only works while its layer is alive
+        let (_layer, log_filter) = crate::otel::log_filter("info").unwrap();
+        let targets = ReloadTargets {
+            flush_interval: FlushInterval::from_secs(5),
+            rate_limiter: Arc::new(RateLimiter::new(10.0, 20)),
+            sampler: SamplerHandle::new(1.0),
+            log_filter,
+            schema_sources: sources.clone(),
+            schema_reload: spawn_schema_reloader(sources.clone(), registry),
+            key_rotation: KeyRotation::default(),
+        };
+
+        let mut current = Config::default();
+        let mut new = Config {
+            flush_interval_secs: 30,
+            batch_size: 50,
+            ..Config::default()
+        };
+        new.otel.sampling_rate = 0.5;
+        new.api.rate_limit.burst = 5;
+        new.schemas
+            .insert("api".to_string(), temp_dir.path().join("api.json"));
+
+        let summary = reload_config(&mut current, new, &targets).unwrap();
+        assert_eq!(
+            summary,
+            ReloadSummary {
+                applied: vec![
+                    "api.rate_limit.burst".to_string(),
+                    "flush_interval_secs".to_string(),
+                    "otel.sampling_rate".to_string(),
+                    "schemas.api".to_string(),
+                ],
+                needs_restart: vec!["batch_size".to_string()],
+            }
+        );
+        assert_eq!(targets.flush_interval.get(), Duration::from_secs(30));
+        assert!(sources.read().unwrap().services.contains_key("api"));
+        // Settings that need a restart keep their running values
+        assert_eq!(current.batch_size, Config::default().batch_size);
+        assert!(changed_keys(&current, &Config::default())
+            .unwrap()
+            .iter()
+            .all(|key| is_reloadable(key)));
+
+        assert!(!is_reloadable("schemas_dir"));
+    }
+}
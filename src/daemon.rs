@@ -0,0 +1,313 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::alerts::{load_rules, AlertEngine};
+use crate::config::Config;
+use crate::forward::Forwarder;
+use crate::log_metrics::LogMetrics;
+use crate::notify::Notifier;
+use crate::pipeline::IngestPipeline;
+use crate::privileges::SocketPermissions;
+use crate::quota::Quotas;
+use crate::schema::{LogEntry, SharedSchemas};
+use crate::schema_reload::SchemaSources;
+use crate::server::{LogCallback, LogServer, ServerControl};
+use crate::status::{DaemonStatus, StatusReport};
+use crate::storage::{parse_compression, StorageEngine};
+
+/// How long `start` waits for the ingest socket to be bound
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Runs the ingest pipeline inside another program: socket, validation, processing and storage,
+/// without the API, metrics endpoint or signal handling of `daemon_rs serve`
+pub struct DaemonBuilder {
+    config: Config,
+    schemas: Option<SharedSchemas>,
+    callbacks: Vec<LogCallback>,
+}
+
+impl DaemonBuilder {
+    /// A daemon with the default settings
+    pub fn new() -> Self {
+        Self::from_config(Config::default())
+    }
+
+    /// A daemon with the ingest and storage settings of `config`
+    pub fn from_config(config: Config) -> Self {
+        Self {
+            config,
+            schemas: None,
+            callbacks: Vec::new(),
+        }
+    }
+
+    pub fn with_socket_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.socket_path = path.into();
+        self
+    }
+
+    pub fn with_storage_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.storage_dir = dir.into();
+        self
+    }
+
+    /// Validate logs with `schemas` instead of the schema files in the config
+    pub fn with_schema(mut self, schemas: impl Into<SharedSchemas>) -> Self {
+        self.schemas = Some(schemas.into());
+        self
+    }
+
+    /// Call `on_log` with every accepted log before it is stored; callbacks run on the ingest
+    /// thread in the order they were added, so they should not block
+    pub fn on_log(mut self, on_log: impl Fn(&LogEntry) + Send + Sync + 'static) -> Self {
+        self.callbacks.push(Arc::new(on_log));
+        self
+    }
+
+    /// Bind the socket and start ingesting on a thread of its own; returns once the socket
+    /// accepts connections
+    pub fn start(self) -> Result<DaemonHandle> {
+        let config = self.config;
+        config.validate()?;
+        let schemas = match self.schemas {
+            Some(schemas) => schemas,
+            None => SharedSchemas::new(
+                SchemaSources {
+                    default: config.schema_path.clone(),
+                    services: config.schemas.clone(),
+                }
+                .build()?,
+            ),
+        };
+
+        let status = DaemonStatus::new();
+        let storage = StorageEngine::new(
+            config.storage_dir.clone(),
+            parse_compression(&config.compression),
+            config.batch_size,
+            config.rotation_size,
+        )?
+        .with_partitioning(config.partitioning.clone())
+        .with_write_failures(config.write_failures.clone())?;
+        let mut server = LogServer::new(
+            config.socket_path.clone(),
+            schemas,
+            config.max_connections,
+            config.flush_interval_secs,
+        )
+        .with_pipeline(IngestPipeline::from_config(&config.ingest)?)
+        .with_parse_workers(config.ingest.parse_workers)
+        .with_ingest_listeners(&config.ingest, &config.storage_dir)?
+        .with_status(status.clone())
+        .without_handover()
+        .with_sampling_report(Duration::from_secs(config.ingest.sampling_report_secs))
+        .with_disk_watch(
+            &config.disk_watch,
+            &config.storage_dir,
+            &config.trace_storage_dir,
+        )
+        .with_socket_permissions(SocketPermissions::resolve(
+            config.socket_mode.as_deref(),
+            config.socket_owner.as_deref(),
+        )?);
+        let notifier = if config.notify.sinks.is_empty() && config.alert_rules.is_none() {
+            None
+        } else {
+            let notifier = Notifier::start(&config.notify.sinks)?;
+            notifier.watch_health(&config.notify, status.clone(), config.storage_dir.clone())?;
+            Some(notifier)
+        };
+        if !config.ingest.quotas.is_empty() {
+            server = server.with_quotas(Quotas::new(&config.ingest.quotas, notifier.clone())?);
+        }
+        let mut callbacks = self.callbacks;
+        callbacks.extend(log_callbacks(&config, notifier.as_ref())?);
+        let forwarder = config.forward.as_ref().map(Forwarder::start).transpose()?;
+        if let Some(forwarder) = &forwarder {
+            callbacks.push(forwarder.on_log());
+        }
+        server = server.with_log_callbacks(callbacks);
+
+        let control = server.control();
+        let thread = std::thread::Builder::new()
+            .name("daemon_rs-ingest".to_string())
+            .spawn(move || server.run(storage))
+            .context("Failed to start the ingest thread")?;
+
+        let started = Instant::now();
+        while !status.is_listening() {
+            if thread.is_finished() {
+                return Err(match thread.join() {
+                    Ok(Err(e)) => e,
+                    _ => anyhow!("The ingest thread stopped while starting"),
+                });
+            }
+            if started.elapsed() > START_TIMEOUT {
+                control.drain();
+                bail!(
+                    "The ingest socket {:?} was not bound within {:?}",
+                    config.socket_path,
+                    START_TIMEOUT
+                );
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        Ok(DaemonHandle {
+            socket_path: config.socket_path,
+            storage_dir: config.storage_dir,
+            control,
+            status,
+            _notifier: notifier,
+            forwarder,
+            thread: Some(thread),
+        })
+    }
+}
+
+/// The callbacks `config` asks for on every accepted log: its alert rules, firing through
+/// `notifier`, and the metrics extracted from log fields
+pub fn log_callbacks(config: &Config, notifier: Option<&Notifier>) -> Result<Vec<LogCallback>> {
+    let mut callbacks: Vec<LogCallback> = Vec::new();
+    if let (Some(path), Some(notifier)) = (&config.alert_rules, notifier) {
+        let alerts = AlertEngine::new(load_rules(path)?, notifier.clone())?;
+        callbacks.push(Arc::new(move |log: &LogEntry| alerts.observe(log)));
+    }
+    if !config.metrics.extract.is_empty() {
+        let extract = LogMetrics::new(&config.metrics.extract)?;
+        callbacks.push(Arc::new(move |log: &LogEntry| extract.observe(log)));
+    }
+    Ok(callbacks)
+}
+
+impl Default for DaemonBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A running embedded daemon; dropping it drains the daemon without waiting for it to stop
+pub struct DaemonHandle {
+    socket_path: PathBuf,
+    storage_dir: PathBuf,
+    control: ServerControl,
+    status: DaemonStatus,
+    /// Keeps health events flowing while the daemon runs
+    _notifier: Option<Notifier>,
+    forwarder: Option<Forwarder>,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+impl DaemonHandle {
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    pub fn storage_dir(&self) -> &Path {
+        &self.storage_dir
+    }
+
+    /// Ingest counters, queue depth and health, as the admin `stats` command reports them
+    pub fn stats(&self) -> StatusReport {
+        self.status.report(&self.storage_dir)
+    }
+
+    /// Write out every log received so far; returns how many were written
+    pub async fn flush(&self) -> Result<usize> {
+        self.control.flush().await
+    }
+
+    /// Stop accepting connections, wait for open ones to close, write out the remaining logs
+    /// and wait for the ingest thread and forwarder to stop; blocks, so async callers should use
+    /// `spawn_blocking`
+    pub fn shutdown(mut self) -> Result<()> {
+        self.control.drain();
+        let result = match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => bail!("The ingest thread panicked"),
+            None => Ok(()),
+        };
+        if let Some(forwarder) = self.forwarder.take() {
+            forwarder.finish();
+        }
+        result
+    }
+}
+
+impl Drop for DaemonHandle {
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            self.control.drain();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench::frame;
+    use crate::query::QueryEngine;
+    use crate::schema::SchemaValidator;
+    use std::io::Write;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_embedded_daemon_ingests_and_stops() {
+        let temp_dir = TempDir::new().unwrap();
+        let seen = Arc::new(AtomicUsize::new(0));
+        let counter = seen.clone();
+        let daemon = DaemonBuilder::new()
+            .with_socket_path(temp_dir.path().join("ingest.sock"))
+            .with_storage_dir(temp_dir.path().join("logs"))
+            .with_schema(SchemaValidator::default_schema().unwrap())
+            .on_log(move |log| {
+                assert_eq!(log.service.as_deref(), Some("embedded"));
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .start()
+            .unwrap();
+        assert!(daemon.socket_path().exists());
+
+        let mut stream = std::os::unix::net::UnixStream::connect(daemon.socket_path()).unwrap();
+        for i in 0..3 {
+            let message = format!(
+                r#"{{"timestamp":"2026-01-01T00:00:0{}Z","level":"info","message":"hello","service":"embedded"}}"#,
+                i
+            );
+            stream.write_all(&frame(message.as_bytes())).unwrap();
+        }
+        stream
+            .write_all(&frame(br#"{"level":"info","message":"no timestamp"}"#))
+            .unwrap();
+        drop(stream);
+
+        let started = Instant::now();
+        while daemon.stats().ingested_total < 3 && started.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let stats = daemon.stats();
+        assert_eq!(stats.ingested_total, 3);
+        assert_eq!(stats.dropped_total, 0);
+        assert_eq!(seen.load(Ordering::SeqCst), 3);
+
+        let socket_path = daemon.socket_path().to_path_buf();
+        daemon.shutdown().unwrap();
+        assert!(!socket_path.exists());
+        let stored = QueryEngine::new(temp_dir.path().join("logs"))
+            .count_logs()
+            .unwrap();
+        assert_eq!(stored, 3);
+
+        let error = DaemonBuilder::new()
+            .with_socket_path(temp_dir.path().join("missing/ingest.sock"))
+            .with_storage_dir(temp_dir.path().join("logs"))
+            .start()
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("Failed to bind"), "{:#}", error);
+    }
+}
@@ -0,0 +1,388 @@
+use anyhow::{bail, Context, Result};
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use tracing::warn;
+
+use crate::redaction::{LogRedactionConfig, LogRedactor};
+use crate::schema::{LogEntry, ParseError};
+
+/// File in the dead-letter directory that rejected payloads are appended to
+pub const DEAD_LETTER_FILE: &str = "dead_letter.ndjson";
+
+/// Dead-letter records queued for the writer thread; further ones are dropped and counted
+const DEAD_LETTER_QUEUE: usize = 1024;
+
+/// Metadata key holding the details of a log accepted despite failing validation
+pub const INVALID_KEY: &str = "_invalid";
+
+/// What a listener does with logs that fail validation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InvalidPolicy {
+    /// Count and log a warning, then discard
+    #[default]
+    Drop,
+    /// Discard and send the client an error frame
+    Reject,
+    /// Append the raw payload to the dead-letter file for later inspection or replay
+    DeadLetter,
+    /// Store a `warn` log carrying the raw payload in its metadata
+    Accept,
+}
+
+/// Handling of invalid logs (the `[ingest.invalid]` section of the config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvalidLogConfig {
+    #[serde(default)]
+    pub policy: InvalidPolicy,
+
+    /// Where `dead_letter` writes; defaults to `dead_letter` in the storage directory
+    #[serde(default)]
+    pub dead_letter_dir: Option<PathBuf>,
+
+    /// Size at which `dead_letter.ndjson` is rotated to `dead_letter.ndjson.1`
+    #[serde(default = "default_dead_letter_max_bytes")]
+    pub dead_letter_max_bytes: u64,
+
+    /// Rotated dead-letter files kept; older ones are deleted
+    #[serde(default = "default_dead_letter_max_files")]
+    pub dead_letter_max_files: usize,
+}
+
+impl Default for InvalidLogConfig {
+    fn default() -> Self {
+        Self {
+            policy: InvalidPolicy::default(),
+            dead_letter_dir: None,
+            dead_letter_max_bytes: default_dead_letter_max_bytes(),
+            dead_letter_max_files: default_dead_letter_max_files(),
+        }
+    }
+}
+
+fn default_dead_letter_max_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+fn default_dead_letter_max_files() -> usize {
+    4
+}
+
+impl InvalidLogConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.dead_letter_max_bytes == 0 {
+            bail!("ingest.invalid.dead_letter_max_bytes must be greater than 0");
+        }
+        Ok(())
+    }
+
+    /// Where `dead_letter` writes, when that is the policy
+    pub fn dead_letter_dir(&self, storage_dir: &Path) -> Option<PathBuf> {
+        (self.policy == InvalidPolicy::DeadLetter).then(|| {
+            self.dead_letter_dir
+                .clone()
+                .unwrap_or_else(|| storage_dir.join("dead_letter"))
+        })
+    }
+}
+
+/// The result of handling an invalid log
+#[derive(Debug)]
+pub enum InvalidOutcome {
+    /// Nothing more to do
+    Dropped,
+    /// Frame to write back to the client
+    Reply(Vec<u8>),
+    /// Log to queue in place of the invalid one
+    Accepted(LogEntry),
+}
+
+/// Applies a listener's `InvalidPolicy` to logs that fail validation
+#[derive(Debug, Default)]
+pub struct InvalidLogHandler {
+    /// Listener the handler belongs to, for the dropped-record metric
+    listener: String,
+    policy: InvalidPolicy,
+    /// Rewrites kept payloads before they are written or stored
+    redactor: Option<LogRedactor>,
+    dead_letter: Option<SyncSender<String>>,
+}
+
+impl InvalidLogHandler {
+    /// Build the handler of `listener`, starting the dead-letter writer when the policy needs
+    /// it; payloads it keeps are redacted as `redaction` says
+    pub fn from_config(
+        listener: &str,
+        config: &InvalidLogConfig,
+        redaction: &LogRedactionConfig,
+        storage_dir: &Path,
+    ) -> Result<Self> {
+        let dead_letter = match config.dead_letter_dir(storage_dir) {
+            Some(dir) => Some(DeadLetterWriter::open(&dir, config)?.start(listener)?),
+            None => None,
+        };
+        let redactor = if redaction.is_empty() {
+            None
+        } else {
+            Some(LogRedactor::from_config(redaction)?)
+        };
+
+        Ok(Self {
+            listener: listener.to_string(),
+            policy: config.policy,
+            redactor,
+            dead_letter,
+        })
+    }
+
+    pub fn policy(&self) -> InvalidPolicy {
+        self.policy
+    }
+
+    /// Whether `handle` needs the raw payload; parsing rewrites the buffer in place, so it has
+    /// to be copied beforehand
+    pub fn keeps_payload(&self) -> bool {
+        matches!(
+            self.policy,
+            InvalidPolicy::DeadLetter | InvalidPolicy::Accept
+        )
+    }
+
+    pub fn handle(&self, error: &ParseError, payload: Option<&[u8]>) -> InvalidOutcome {
+        let payload = payload.map(String::from_utf8_lossy).unwrap_or_default();
+        let payload = match &self.redactor {
+            Some(redactor) => redactor.redact_payload(&payload),
+            None => payload.into_owned(),
+        };
+        match self.policy {
+            InvalidPolicy::Drop => InvalidOutcome::Dropped,
+            InvalidPolicy::Reject => {
+                let body = json!({
+                    "error": error.reason.as_str(),
+                    "message": error.to_string(),
+                    "issues": error.issues(),
+                })
+                .to_string();
+                let mut frame = Vec::with_capacity(4 + body.len());
+                frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+                frame.extend_from_slice(body.as_bytes());
+                InvalidOutcome::Reply(frame)
+            }
+            InvalidPolicy::DeadLetter => {
+                let mut record = json!({
+                    "received_at": now(),
+                    "reason": error.reason.as_str(),
+                    "error": error.to_string(),
+                    "issues": error.issues(),
+                    "payload": payload,
+                })
+                .to_string();
+                record.push('\n');
+                // Writing is left to the writer thread, so a slow disk never stalls ingestion
+                if let Some(dead_letter) = &self.dead_letter {
+                    match dead_letter.try_send(record) {
+                        Ok(()) | Err(TrySendError::Disconnected(_)) => {}
+                        Err(TrySendError::Full(_)) => {
+                            crate::metrics::record_dead_letter_dropped(&self.listener)
+                        }
+                    }
+                }
+                InvalidOutcome::Dropped
+            }
+            InvalidPolicy::Accept => {
+                let metadata = json!({
+                    INVALID_KEY: {
+                        "reason": error.reason.as_str(),
+                        "issues": error.issues(),
+                        "payload": payload,
+                    }
+                });
+                InvalidOutcome::Accepted(LogEntry {
+                    timestamp: now(),
+                    level: crate::intern::intern("warn"),
+                    message: format!("Invalid log: {}", error),
+                    service: None,
+                    trace_id: None,
+                    metadata: serde_json::from_value(metadata).ok(),
+                    schema_version: None,
+                })
+            }
+        }
+    }
+}
+
+/// Appends dead-letter records to `dead_letter.ndjson`, rotating it at `max_bytes`
+struct DeadLetterWriter {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+    max_files: usize,
+}
+
+impl DeadLetterWriter {
+    fn open(dir: &Path, config: &InvalidLogConfig) -> Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create dead-letter directory {:?}", dir))?;
+        let path = dir.join(DEAD_LETTER_FILE);
+        let file = Self::open_file(&path)?;
+        Ok(Self {
+            size: file.metadata()?.len(),
+            path,
+            file,
+            max_bytes: config.dead_letter_max_bytes,
+            max_files: config.dead_letter_max_files,
+        })
+    }
+
+    fn open_file(path: &Path) -> Result<File> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open dead-letter file {:?}", path))
+    }
+
+    /// Write records sent to the returned queue on a thread of `listener`'s; it stops once
+    /// the handler is dropped
+    fn start(mut self, listener: &str) -> Result<SyncSender<String>> {
+        let (tx, rx) = mpsc::sync_channel::<String>(DEAD_LETTER_QUEUE);
+        std::thread::Builder::new()
+            .name(format!("dead-letter-{}", listener))
+            .spawn(move || {
+                for record in rx {
+                    if let Err(e) = self.write(&record) {
+                        warn!("Failed to write dead-letter record: {:#}", e);
+                    }
+                }
+            })
+            .context("Failed to start the dead-letter writer")?;
+        Ok(tx)
+    }
+
+    fn write(&mut self, record: &str) -> Result<()> {
+        if self.size > 0 && self.size + record.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(record.as_bytes())?;
+        self.size += record.len() as u64;
+        Ok(())
+    }
+
+    /// Shift `dead_letter.ndjson.N` to `.N+1`, deleting the one past `max_files`, and start
+    /// an empty file
+    fn rotate(&mut self) -> Result<()> {
+        let rotated = |n: usize| PathBuf::from(format!("{}.{}", self.path.display(), n));
+        let _ = std::fs::remove_file(rotated(self.max_files.max(1)));
+        for n in (1..self.max_files.max(1)).rev() {
+            let _ = std::fs::rename(rotated(n), rotated(n + 1));
+        }
+        if self.max_files == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            std::fs::rename(&self.path, rotated(1))?;
+        }
+        self.file = Self::open_file(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::SchemaValidator;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_invalid_policies() {
+        let temp_dir = TempDir::new().unwrap();
+        let validator = SchemaValidator::default_schema().unwrap();
+        let payload = br#"{"level":"info","password":"hunter2"}"#.to_vec();
+        let error = validator.parse_fast(&mut payload.clone()).unwrap_err();
+        let redaction = LogRedactionConfig {
+            keys: vec!["password".to_string()],
+            ..LogRedactionConfig::default()
+        };
+        let handler = |policy| {
+            InvalidLogHandler::from_config(
+                "ingest",
+                &InvalidLogConfig {
+                    policy,
+                    ..InvalidLogConfig::default()
+                },
+                &redaction,
+                temp_dir.path(),
+            )
+            .unwrap()
+        };
+        let redacted = r#"{"level":"info","password":"[REDACTED]"}"#;
+
+        let InvalidOutcome::Reply(frame) = handler(InvalidPolicy::Reject).handle(&error, None)
+        else {
+            panic!("reject replies to the client");
+        };
+        let reply: serde_json::Value = serde_json::from_slice(&frame[4..]).unwrap();
+        assert_eq!(frame[..4], ((frame.len() - 4) as u32).to_be_bytes());
+        assert_eq!(reply["error"], "schema_violation");
+        assert_eq!(reply["issues"][0]["keyword"], "required");
+
+        let dead_letter = handler(InvalidPolicy::DeadLetter);
+        assert!(dead_letter.keeps_payload());
+        dead_letter.handle(&error, Some(&payload));
+        let path = temp_dir.path().join("dead_letter").join(DEAD_LETTER_FILE);
+        let written = wait_for_contents(&path);
+        let record: serde_json::Value = serde_json::from_str(written.trim_end()).unwrap();
+        assert_eq!(record["payload"], redacted);
+
+        let InvalidOutcome::Accepted(entry) =
+            handler(InvalidPolicy::Accept).handle(&error, Some(&payload))
+        else {
+            panic!("accept stores a replacement log");
+        };
+        assert_eq!(&*entry.level, "warn");
+        let metadata = serde_json::to_value(entry.metadata.unwrap()).unwrap();
+        assert_eq!(metadata[INVALID_KEY]["payload"], redacted);
+    }
+
+    /// The contents of `path` once the writer thread has written to it
+    fn wait_for_contents(path: &Path) -> String {
+        for _ in 0..100 {
+            match std::fs::read_to_string(path) {
+                Ok(contents) if !contents.is_empty() => return contents,
+                _ => std::thread::sleep(std::time::Duration::from_millis(10)),
+            }
+        }
+        panic!("nothing was written to {:?}", path);
+    }
+
+    #[test]
+    fn test_dead_letter_file_rotates() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = InvalidLogConfig {
+            policy: InvalidPolicy::DeadLetter,
+            dead_letter_max_bytes: 10,
+            dead_letter_max_files: 2,
+            ..InvalidLogConfig::default()
+        };
+        let mut writer = DeadLetterWriter::open(temp_dir.path(), &config).unwrap();
+        for record in ["first\n", "second\n", "third\n", "fourth\n"] {
+            writer.write(record).unwrap();
+        }
+
+        let read = |name: &str| std::fs::read_to_string(temp_dir.path().join(name)).unwrap();
+        assert_eq!(read(DEAD_LETTER_FILE), "fourth\n");
+        assert_eq!(read("dead_letter.ndjson.1"), "third\n");
+        assert_eq!(read("dead_letter.ndjson.2"), "second\n");
+        assert!(!temp_dir.path().join("dead_letter.ndjson.3").exists());
+    }
+}
@@ -0,0 +1,321 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+use crate::audit::{self, Outcome};
+use crate::retention::{self, RetentionOverride, RetentionPolicy};
+use crate::stats::human_bytes;
+use crate::status::{free_disk_bytes, DaemonStatus};
+
+/// Free space a level needs above its threshold before the watchdog leaves it, as a fraction
+/// of the threshold, so a volume hovering at one does not flap
+const RECOVERY_MARGIN: f64 = 0.1;
+
+/// Free-space thresholds on the storage volume (the `[disk_watch]` section of the config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiskWatchConfig {
+    /// Seconds between free-space checks; 0, the default, turns the watchdog off
+    #[serde(default)]
+    pub check_secs: u64,
+
+    /// Below this many free bytes, debug and trace logs are dropped at ingest
+    #[serde(default = "default_shed_debug_below_bytes")]
+    pub shed_debug_below_bytes: u64,
+
+    /// Below this many free bytes, the oldest files are deleted when `delete_oldest` is set
+    #[serde(default = "default_cleanup_below_bytes")]
+    pub cleanup_below_bytes: u64,
+
+    /// Delete the oldest log and trace files below `cleanup_below_bytes` until free space is
+    /// back above it
+    #[serde(default)]
+    pub delete_oldest: bool,
+
+    /// Retention overrides, written like `retention --keep`, that the cleanup applies first:
+    /// files every log of which an override lets expire go before the oldest ones
+    #[serde(default)]
+    pub keep: Vec<String>,
+
+    /// Below this many free bytes, every log is rejected with a `disk_full` reply
+    #[serde(default = "default_reject_below_bytes")]
+    pub reject_below_bytes: u64,
+}
+
+impl Default for DiskWatchConfig {
+    fn default() -> Self {
+        Self {
+            check_secs: 0,
+            shed_debug_below_bytes: default_shed_debug_below_bytes(),
+            cleanup_below_bytes: default_cleanup_below_bytes(),
+            delete_oldest: false,
+            keep: Vec::new(),
+            reject_below_bytes: default_reject_below_bytes(),
+        }
+    }
+}
+
+fn default_shed_debug_below_bytes() -> u64 {
+    2 * 1024 * 1024 * 1024
+}
+
+fn default_cleanup_below_bytes() -> u64 {
+    1024 * 1024 * 1024
+}
+
+fn default_reject_below_bytes() -> u64 {
+    crate::status::MIN_FREE_DISK_BYTES
+}
+
+/// How far ingestion is degraded to spare the storage volume, least to most
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DiskPressure {
+    #[default]
+    Normal,
+    /// Debug and trace logs are dropped
+    ShedDebug,
+    /// Debug and trace logs are dropped and the oldest files deleted
+    Cleanup,
+    /// Every log is rejected
+    Reject,
+}
+
+impl DiskPressure {
+    const ALL: [DiskPressure; 4] = [
+        DiskPressure::Normal,
+        DiskPressure::ShedDebug,
+        DiskPressure::Cleanup,
+        DiskPressure::Reject,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DiskPressure::Normal => "normal",
+            DiskPressure::ShedDebug => "shed_debug",
+            DiskPressure::Cleanup => "cleanup",
+            DiskPressure::Reject => "reject",
+        }
+    }
+
+    pub fn as_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_u8(value: u8) -> Self {
+        Self::ALL
+            .get(value as usize)
+            .copied()
+            .unwrap_or(DiskPressure::Reject)
+    }
+
+    /// Whether a log at `level` is still accepted
+    pub fn admits(self, level: &str) -> bool {
+        match self {
+            DiskPressure::Normal => true,
+            DiskPressure::ShedDebug | DiskPressure::Cleanup => {
+                !level.eq_ignore_ascii_case("debug") && !level.eq_ignore_ascii_case("trace")
+            }
+            DiskPressure::Reject => false,
+        }
+    }
+}
+
+impl DiskWatchConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.reject_below_bytes > self.cleanup_below_bytes
+            || self.cleanup_below_bytes > self.shed_debug_below_bytes
+        {
+            bail!(
+                "disk_watch thresholds must not increase with severity: \
+                 reject_below_bytes <= cleanup_below_bytes <= shed_debug_below_bytes"
+            );
+        }
+        self.overrides()?;
+        Ok(())
+    }
+
+    fn overrides(&self) -> Result<Vec<RetentionOverride>> {
+        self.keep
+            .iter()
+            .map(|rule| rule.parse().context("Invalid disk_watch.keep"))
+            .collect()
+    }
+
+    fn threshold(&self, pressure: DiskPressure) -> u64 {
+        match pressure {
+            DiskPressure::Normal => 0,
+            DiskPressure::ShedDebug => self.shed_debug_below_bytes,
+            DiskPressure::Cleanup => self.cleanup_below_bytes,
+            DiskPressure::Reject => self.reject_below_bytes,
+        }
+    }
+
+    /// Pressure with `free` bytes left, coming from `current`: levels are entered below their
+    /// threshold and left only past it plus `RECOVERY_MARGIN`
+    pub fn pressure(&self, free: u64, current: DiskPressure) -> DiskPressure {
+        let level = |margin: f64| {
+            DiskPressure::ALL
+                .into_iter()
+                .rev()
+                .find(|&p| (free as f64) < self.threshold(p) as f64 * (1.0 + margin))
+                .unwrap_or(DiskPressure::Normal)
+        };
+        level(0.0).max(current.min(level(RECOVERY_MARGIN)))
+    }
+
+    /// Free bytes the cleanup works towards
+    fn cleanup_target(&self) -> u64 {
+        (self.cleanup_below_bytes as f64 * (1.0 + RECOVERY_MARGIN)) as u64
+    }
+}
+
+/// Frame written back for each log rejected while the storage volume is nearly full
+pub fn reject_frame() -> Vec<u8> {
+    let body = serde_json::json!({
+        "error": "disk_full",
+        "message": "The daemon's storage volume is nearly full; logs are rejected until space is freed",
+    })
+    .to_string();
+    let mut frame = Vec::with_capacity(4 + body.len());
+    frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    frame.extend_from_slice(body.as_bytes());
+    frame
+}
+
+/// Check free space on the volume holding `storage_dir` every `check_secs`, publishing the
+/// pressure through `status` and deleting the oldest files while cleaning up
+pub fn spawn(
+    config: DiskWatchConfig,
+    status: DaemonStatus,
+    storage_dir: PathBuf,
+    trace_dir: PathBuf,
+) -> Result<()> {
+    let policy = RetentionPolicy {
+        overrides: config.overrides()?,
+        ..RetentionPolicy::default()
+    };
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(config.check_secs));
+        loop {
+            ticker.tick().await;
+            let Some(free) = free_disk_bytes(&storage_dir) else {
+                continue;
+            };
+            let current = status.disk_pressure();
+            let pressure = config.pressure(free, current);
+            crate::metrics::record_disk_pressure(pressure);
+            if pressure != current {
+                status.set_disk_pressure(pressure);
+                let message = format!(
+                    "Storage volume has {} free; disk pressure {} -> {}",
+                    human_bytes(free),
+                    current.as_str(),
+                    pressure.as_str()
+                );
+                if pressure > current {
+                    warn!("{}", message);
+                } else {
+                    info!("{}", message);
+                }
+            }
+
+            if pressure >= DiskPressure::Cleanup && config.delete_oldest {
+                let need = config.cleanup_target().saturating_sub(free);
+                let (storage_dir, trace_dir) = (storage_dir.clone(), trace_dir.clone());
+                let policy = policy.clone();
+                let cleaned = tokio::task::spawn_blocking(move || {
+                    delete_oldest(&storage_dir, &trace_dir, need, policy)
+                })
+                .await;
+                match cleaned {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => warn!("Failed to free disk space: {:#}", e),
+                    Err(e) => warn!("Disk cleanup task failed: {}", e),
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Delete files the overrides of `policy` let expire, then the oldest log and trace files,
+/// until `need` bytes are freed or none are left
+fn delete_oldest(
+    storage_dir: &Path,
+    trace_dir: &Path,
+    need: u64,
+    mut policy: RetentionPolicy,
+) -> Result<()> {
+    let mut files = retention::stored_files(storage_dir, trace_dir)?;
+    retention::read_contents(&mut files, &policy);
+    let total: u64 = files.iter().map(|file| file.size).sum();
+    policy.max_size = Some(total.saturating_sub(need));
+    let plan = retention::plan(files, &policy, SystemTime::now());
+    if plan.delete.is_empty() {
+        return Ok(());
+    }
+    let result = plan.apply();
+    audit::record(
+        "daemon",
+        "disk_watch.delete",
+        Outcome::of(&result),
+        serde_json::json!({
+            "files": plan.delete.iter().map(|(file, _)| &file.path).collect::<Vec<_>>(),
+            "bytes": plan.reclaimed(),
+        }),
+    );
+    result?;
+    warn!(
+        "Deleted the {} oldest file(s), {}, to free disk space",
+        plan.delete.len(),
+        human_bytes(plan.reclaimed())
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pressure_levels_with_recovery_margin() {
+        let config = DiskWatchConfig {
+            shed_debug_below_bytes: 1000,
+            cleanup_below_bytes: 500,
+            reject_below_bytes: 100,
+            ..DiskWatchConfig::default()
+        };
+        config.validate().unwrap();
+        use DiskPressure::*;
+        let normal = |free| config.pressure(free, Normal);
+        assert_eq!(
+            [normal(2000), normal(999), normal(499), normal(99)],
+            [Normal, ShedDebug, Cleanup, Reject]
+        );
+        // Leaving a level takes 10% more than its threshold
+        assert_eq!(config.pressure(105, Reject), Reject);
+        assert_eq!(config.pressure(120, Reject), Cleanup);
+        assert_eq!(config.pressure(1050, Cleanup), ShedDebug);
+        assert_eq!(config.pressure(1200, Cleanup), Normal);
+        assert_eq!(config.pressure(50, ShedDebug), Reject);
+        assert_eq!(config.cleanup_target(), 550);
+
+        assert!(!ShedDebug.admits("DEBUG") && !Cleanup.admits("trace"));
+        assert!(ShedDebug.admits("error") && !Reject.admits("error"));
+        for pressure in DiskPressure::ALL {
+            assert_eq!(DiskPressure::from_u8(pressure.as_u8()), pressure);
+        }
+
+        let mut inverted = config.clone();
+        inverted.reject_below_bytes = 600;
+        assert!(inverted.validate().is_err());
+
+        assert_eq!(DiskWatchConfig::default().check_secs, 0);
+        let mut keep = config.clone();
+        keep.keep = vec!["level=debug:24h".to_string()];
+        keep.validate().unwrap();
+        keep.keep.push("debug".to_string());
+        assert!(keep.validate().is_err());
+    }
+}
@@ -0,0 +1,329 @@
+use anyhow::Result;
+use std::fmt;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixStream;
+
+use crate::config::Config;
+use crate::config_check;
+use crate::stats::human_bytes;
+use crate::status::{free_disk_bytes, MIN_FREE_DISK_BYTES};
+
+/// Longest Unix socket path the kernel accepts, in bytes
+const MAX_SOCKET_PATH_BYTES: usize = 107;
+
+/// Rotations' worth of free space below which the storage volume is reported as low
+const LOW_SPACE_ROTATIONS: u64 = 10;
+
+/// How a check turned out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Ok,
+    Warning,
+    Failure,
+}
+
+/// Result of one doctor check, with a suggested fix when something is wrong
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub severity: Severity,
+    pub check: &'static str,
+    pub detail: String,
+    pub hint: Option<String>,
+}
+
+impl Finding {
+    fn ok(check: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Ok,
+            check,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn warning(check: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            check,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn failure(check: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Failure,
+            check,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self.severity {
+            Severity::Ok => "✓",
+            Severity::Warning => "⚠",
+            Severity::Failure => "✗",
+        };
+        write!(f, "{} {}: {}", symbol, self.check, self.detail)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n    → {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the host and the config `serve` would run with for the usual setup problems: io_uring
+/// support, the socket path, storage permissions and free space, the listen ports and the
+/// schemas. A config that failed to load is reported instead of the checks that need it.
+pub fn diagnose(config: Result<Config>) -> Vec<Finding> {
+    let mut findings = vec![check_io_uring()];
+    match config {
+        Ok(config) => {
+            findings.push(check_socket(&config));
+            findings.extend(check_storage(&config));
+            findings.extend(check_ports(&config));
+            findings.push(check_schemas(&config));
+        }
+        Err(e) => findings.push(Finding::failure(
+            "config",
+            format!("{:#}", e),
+            "Fix the config file, DAEMON_RS_* variables or flags; the remaining checks need it",
+        )),
+    }
+    findings
+}
+
+/// Whether the kernel lets this process set up an io_uring instance, which `serve` needs
+pub fn check_io_uring() -> Finding {
+    let kernel = kernel_release();
+    // Room for struct io_uring_params (120 bytes), which the kernel fills in
+    let mut params = [0u32; 30];
+    // SAFETY: io_uring_setup writes at most size_of::<io_uring_params>() bytes to `params`
+    let fd = unsafe { libc::syscall(libc::SYS_io_uring_setup, 1u32, params.as_mut_ptr()) };
+    if fd >= 0 {
+        // SAFETY: `fd` was just returned by io_uring_setup and is not used elsewhere
+        unsafe { libc::close(fd as libc::c_int) };
+        return Finding::ok("io_uring", format!("supported on Linux {}", kernel));
+    }
+
+    let error = std::io::Error::last_os_error();
+    let hint = match error.raw_os_error() {
+        Some(libc::ENOSYS) => "This kernel has no io_uring; run on Linux 5.11 or newer",
+        Some(libc::EPERM) => {
+            "io_uring is disabled: `sysctl kernel.io_uring_disabled` must be 0, and containers \
+             need a seccomp profile that allows io_uring (Docker's default one blocks it)"
+        }
+        Some(libc::ENOMEM) => {
+            "The locked memory limit is too low for io_uring on this kernel: raise `ulimit -l`, \
+             or LimitMEMLOCK= in the systemd unit"
+        }
+        _ => "serve needs io_uring for the ingest socket",
+    };
+    Finding::failure(
+        "io_uring",
+        format!("cannot set up io_uring on Linux {}: {}", kernel, error),
+        hint,
+    )
+}
+
+/// `uname -r`
+fn kernel_release() -> String {
+    // SAFETY: `uname` fills the zeroed struct with NUL-terminated strings
+    let mut name: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut name) } != 0 {
+        return "(unknown version)".to_string();
+    }
+    unsafe { std::ffi::CStr::from_ptr(name.release.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn check_socket(config: &Config) -> Finding {
+    let path = &config.socket_path;
+    let length = path.as_os_str().len();
+    if length > MAX_SOCKET_PATH_BYTES {
+        return Finding::failure(
+            "socket",
+            format!(
+                "{:?} is {} bytes; Unix socket paths are limited to {}",
+                path, length, MAX_SOCKET_PATH_BYTES
+            ),
+            "Use a shorter --socket path",
+        );
+    }
+
+    let dir = path.parent().unwrap_or(std::path::Path::new("."));
+    if let Err(e) = config_check::check_writable(dir) {
+        return Finding::failure(
+            "socket",
+            format!("cannot create {:?}: {}", path, e),
+            format!(
+                "Create {:?} and give it to the user running daemon_rs, or pick another --socket",
+                dir
+            ),
+        );
+    }
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if !metadata.file_type().is_socket() => Finding::warning(
+            "socket",
+            format!("{:?} exists and is not a socket", path),
+            "serve deletes whatever is at the socket path; move that file or pick another --socket",
+        ),
+        Ok(_) if UnixStream::connect(path).is_ok() => Finding::warning(
+            "socket",
+            format!("another process is listening on {:?}", path),
+            "Stop the other daemon first, or give this one its own --socket; serve replaces the \
+             socket file",
+        ),
+        _ => Finding::ok("socket", format!("{:?} can be created", path)),
+    }
+}
+
+fn check_storage(config: &Config) -> Vec<Finding> {
+    let dir = &config.storage_dir;
+    if let Err(e) = config_check::check_writable(dir) {
+        return vec![Finding::failure(
+            "storage",
+            format!("{:?} is not writable: {}", dir, e),
+            format!(
+                "`mkdir -p {}` and `chown` it to the user running daemon_rs, or pass --storage",
+                dir.display()
+            ),
+        )];
+    }
+
+    let mut findings = vec![Finding::ok("storage", format!("{:?} is writable", dir))];
+    let existing = config_check::existing_ancestor(dir);
+    match free_disk_bytes(existing) {
+        Some(free) if free < MIN_FREE_DISK_BYTES => findings.push(Finding::failure(
+            "disk space",
+            format!("only {} free for {:?}", human_bytes(free), dir),
+            format!(
+                "Free space or move --storage to a larger volume; below {} the readiness probe \
+                 reports the daemon unhealthy",
+                human_bytes(MIN_FREE_DISK_BYTES)
+            ),
+        )),
+        Some(free) if free < config.rotation_size.saturating_mul(LOW_SPACE_ROTATIONS) => findings
+            .push(Finding::warning(
+                "disk space",
+                format!(
+                    "{} free for {:?}, less than {} files of {}",
+                    human_bytes(free),
+                    dir,
+                    LOW_SPACE_ROTATIONS,
+                    human_bytes(config.rotation_size)
+                ),
+                "Free space, move --storage to a larger volume, or lower --rotation-mb",
+            )),
+        Some(free) => findings.push(Finding::ok(
+            "disk space",
+            format!("{} free for {:?}", human_bytes(free), dir),
+        )),
+        None => findings.push(Finding::warning(
+            "disk space",
+            format!("cannot read the free space for {:?}", dir),
+            "Check the volume with `df`",
+        )),
+    }
+    findings
+}
+
+fn check_ports(config: &Config) -> Vec<Finding> {
+    let problems = config_check::check_addrs(config);
+    if !problems.is_empty() {
+        return problems
+            .into_iter()
+            .map(|problem| {
+                Finding::failure(
+                    "ports",
+                    problem,
+                    "Stop whatever holds the port (see `ss -ltnp`), or move this listener with \
+                     the named setting",
+                )
+            })
+            .collect();
+    }
+
+    let addrs = config_check::listen_addrs(config);
+    let detail = if addrs.is_empty() {
+        "no TCP listeners configured".to_string()
+    } else {
+        let addrs: Vec<String> = addrs
+            .iter()
+            .map(|(key, addr)| format!("{} {}", key, addr))
+            .collect();
+        format!("free: {}", addrs.join(", "))
+    };
+    vec![Finding::ok("ports", detail)]
+}
+
+fn check_schemas(config: &Config) -> Finding {
+    if let Some(problem) = config_check::check_schemas(config).into_iter().next() {
+        return Finding::failure(
+            "schemas",
+            problem,
+            "Fix the schema file; `daemon_rs validate-schema <file>` checks one on its own",
+        );
+    }
+    let files = config.schema_path.iter().count() + config.schemas.len();
+    if files == 0 {
+        Finding::ok("schemas", "using the built-in default schema")
+    } else {
+        Finding::ok("schemas", format!("{} schema file(s) compile", files))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_diagnose_reports_actionable_findings() {
+        let temp_dir = TempDir::new().unwrap();
+        let busy = TcpListener::bind("127.0.0.1:0").unwrap();
+        let schema = temp_dir.path().join("schema.json");
+        std::fs::write(&schema, "{ not json").unwrap();
+
+        let mut config = Config {
+            socket_path: temp_dir.path().join("s".repeat(MAX_SOCKET_PATH_BYTES)),
+            storage_dir: temp_dir.path().join("logs"),
+            schema_path: Some(schema),
+            rotation_size: u64::MAX / LOW_SPACE_ROTATIONS,
+            ..Config::default()
+        };
+        config.otel.enabled = false;
+        config.metrics.bind = busy.local_addr().unwrap();
+
+        let findings = diagnose(Ok(config));
+        let summary: Vec<(&str, Severity)> = findings
+            .iter()
+            .skip(1) // io_uring depends on the host
+            .map(|finding| (finding.check, finding.severity))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("socket", Severity::Failure),
+                ("storage", Severity::Ok),
+                ("disk space", Severity::Warning),
+                ("ports", Severity::Failure),
+                ("schemas", Severity::Failure),
+            ]
+        );
+        assert!(findings
+            .iter()
+            .all(|finding| (finding.severity == Severity::Ok) == finding.hint.is_none()));
+        assert!(findings[1].to_string().starts_with("✗ socket: "));
+
+        let findings = diagnose(Err(anyhow::anyhow!("batch_size must be greater than 0")));
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[1].check, "config");
+    }
+}
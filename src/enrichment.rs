@@ -0,0 +1,242 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::os::unix::io::RawFd;
+use std::path::PathBuf;
+use tracing::warn;
+
+use crate::schema::LogEntry;
+
+/// Metadata injected into incoming logs (the `[ingest.enrich]` section of the config file)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EnrichmentConfig {
+    /// Add the daemon's host name as `hostname`
+    #[serde(default)]
+    pub hostname: bool,
+
+    /// Metadata keys set to a fixed value, e.g. `environment = "production"`
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+
+    /// Metadata keys set from environment variables, e.g. `pod = "POD_NAME"`; unset
+    /// variables are skipped
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
+    /// Metadata keys set from the contents of a file, e.g. a Kubernetes downward API volume;
+    /// missing files are skipped
+    #[serde(default)]
+    pub files: BTreeMap<String, PathBuf>,
+
+    /// Add the pid, uid, gid and command name of the process that sent the log as
+    /// `peer_pid`, `peer_uid`, `peer_gid` and `peer_process`
+    #[serde(default)]
+    pub peer: bool,
+}
+
+impl EnrichmentConfig {
+    pub fn is_empty(&self) -> bool {
+        !self.hostname
+            && !self.peer
+            && self.fields.is_empty()
+            && self.env.is_empty()
+            && self.files.is_empty()
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let keys = self
+            .fields
+            .keys()
+            .chain(self.env.keys())
+            .chain(self.files.keys());
+        for key in keys {
+            if key.trim().is_empty() {
+                bail!("ingest.enrich must not contain an empty metadata key");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Credentials of the process on the other end of a Unix socket connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerCredentials {
+    pub pid: i32,
+    pub uid: u32,
+    pub gid: u32,
+    /// Command name from `/proc/<pid>/comm`, if the process is still visible
+    pub process: Option<String>,
+}
+
+impl PeerCredentials {
+    /// Read `SO_PEERCRED` from a connected Unix socket
+    pub fn from_fd(fd: RawFd) -> std::io::Result<Self> {
+        let mut cred: libc::ucred = unsafe { std::mem::zeroed() };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        let result = unsafe {
+            libc::getsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut cred as *mut libc::ucred as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if result != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let process = std::fs::read_to_string(format!("/proc/{}/comm", cred.pid))
+            .ok()
+            .map(|comm| comm.trim_end().to_string());
+        Ok(Self {
+            pid: cred.pid,
+            uid: cred.uid,
+            gid: cred.gid,
+            process,
+        })
+    }
+}
+
+/// Adds host, environment and peer metadata to accepted logs; keys a producer already set
+/// are left alone
+#[derive(Debug, Clone)]
+pub struct Enricher {
+    /// Resolved once at startup
+    fields: Vec<(String, String)>,
+    peer: bool,
+}
+
+impl Enricher {
+    pub fn from_config(config: &EnrichmentConfig) -> Result<Self> {
+        Self::from_config_with_env(config, |name| std::env::var(name).ok())
+    }
+
+    /// Like `from_config`, with environment variables looked up through `var`
+    pub fn from_config_with_env(
+        config: &EnrichmentConfig,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self> {
+        let mut fields = BTreeMap::new();
+        if config.hostname {
+            fields.insert("hostname".to_string(), hostname()?);
+        }
+        for (key, name) in &config.env {
+            if let Some(value) = var(name) {
+                fields.insert(key.clone(), value);
+            }
+        }
+        for (key, path) in &config.files {
+            match std::fs::read_to_string(path) {
+                Ok(value) => {
+                    fields.insert(key.clone(), value.trim().to_string());
+                }
+                Err(e) => warn!("Skipping enrichment {} from {:?}: {}", key, path, e),
+            }
+        }
+        for (key, value) in &config.fields {
+            fields.insert(key.clone(), value.clone());
+        }
+
+        Ok(Self {
+            fields: fields.into_iter().collect(),
+            peer: config.peer,
+        })
+    }
+
+    /// Whether `enrich` uses the connection's peer credentials
+    pub fn wants_peer(&self) -> bool {
+        self.peer
+    }
+
+    /// Add the configured metadata to `entry`; metadata that is not a JSON object is left as is
+    pub fn enrich(&self, entry: &mut LogEntry, peer: Option<&PeerCredentials>) {
+        let metadata = entry
+            .metadata
+            .get_or_insert_with(|| Value::Object(Map::new()));
+        let Value::Object(object) = metadata else {
+            return;
+        };
+
+        for (key, value) in &self.fields {
+            insert_missing(object, key, || Value::String(value.clone()));
+        }
+        if let (true, Some(peer)) = (self.peer, peer) {
+            insert_missing(object, "peer_pid", || Value::from(peer.pid));
+            insert_missing(object, "peer_uid", || Value::from(peer.uid));
+            insert_missing(object, "peer_gid", || Value::from(peer.gid));
+            if let Some(process) = &peer.process {
+                insert_missing(object, "peer_process", || Value::String(process.clone()));
+            }
+        }
+    }
+}
+
+fn insert_missing(object: &mut Map<String, Value>, key: &str, value: impl FnOnce() -> Value) {
+    if !object.contains_key(key) {
+        object.insert(key.to_string(), value());
+    }
+}
+
+fn hostname() -> Result<String> {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to read the host name");
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    Ok(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_enrich_keeps_producer_values() {
+        let env = |name: &str| (name == "DAEMON_RS_TEST_REGION").then(|| "eu-west-1".to_string());
+        let enricher = Enricher::from_config_with_env(
+            &EnrichmentConfig {
+                hostname: true,
+                fields: BTreeMap::from([("environment".to_string(), "production".to_string())]),
+                env: BTreeMap::from([
+                    ("region".to_string(), "DAEMON_RS_TEST_REGION".to_string()),
+                    ("pod".to_string(), "DAEMON_RS_TEST_UNSET".to_string()),
+                ]),
+                peer: true,
+                ..EnrichmentConfig::default()
+            },
+            env,
+        )
+        .unwrap();
+
+        let mut entry: LogEntry = serde_json::from_value(json!({
+            "timestamp": "2026-01-15T19:00:00Z",
+            "level": "info",
+            "message": "m",
+            "metadata": { "environment": "staging" }
+        }))
+        .unwrap();
+        let peer = PeerCredentials {
+            pid: 42,
+            uid: 1000,
+            gid: 1000,
+            process: Some("checkout".to_string()),
+        };
+        enricher.enrich(&mut entry, Some(&peer));
+
+        let metadata = serde_json::to_value(entry.metadata.unwrap()).unwrap();
+        assert_eq!(metadata["environment"], "staging");
+        assert_eq!(metadata["region"], "eu-west-1");
+        assert_eq!(metadata["peer_pid"], 42);
+        assert_eq!(metadata["peer_process"], "checkout");
+        assert!(metadata["hostname"].is_string());
+        assert!(metadata.get("pod").is_none());
+
+        let (ours, theirs) = std::os::unix::net::UnixStream::pair().unwrap();
+        let creds = PeerCredentials::from_fd(std::os::unix::io::AsRawFd::as_raw_fd(&ours)).unwrap();
+        assert_eq!(creds.pid, std::process::id() as i32);
+        drop(theirs);
+    }
+}
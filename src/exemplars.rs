@@ -0,0 +1,171 @@
+#[cfg(feature = "otel")]
+use opentelemetry::trace::TraceContextExt;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+#[cfg(feature = "otel")]
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// A single OpenMetrics exemplar pointing at the trace that produced a sample
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exemplar {
+    pub trace_id: String,
+    pub span_id: String,
+    pub value: f64,
+    pub timestamp: f64,
+}
+
+/// Latest exemplar per series, keyed the way the Prometheus exporter names series
+static EXEMPLARS: OnceLock<Mutex<HashMap<String, Exemplar>>> = OnceLock::new();
+
+fn store() -> &'static Mutex<HashMap<String, Exemplar>> {
+    EXEMPLARS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Build an exemplar from the current tracing span, if it belongs to a sampled trace
+#[cfg(feature = "otel")]
+pub fn current_exemplar(value: f64) -> Option<Exemplar> {
+    let context = tracing::Span::current().context();
+    let span = context.span();
+    let span_context = span.span_context();
+
+    if !span_context.is_valid() || !span_context.is_sampled() {
+        return None;
+    }
+
+    Some(Exemplar {
+        trace_id: span_context.trace_id().to_string(),
+        span_id: span_context.span_id().to_string(),
+        value,
+        timestamp: chrono::Utc::now().timestamp_millis() as f64 / 1000.0,
+    })
+}
+
+/// Without the `otel` feature no span belongs to a trace
+#[cfg(not(feature = "otel"))]
+pub fn current_exemplar(_value: f64) -> Option<Exemplar> {
+    None
+}
+
+/// Attach the current trace to a counter series
+pub fn record_counter(name: &str, value: u64) {
+    if let Some(exemplar) = current_exemplar(value as f64) {
+        insert(name.to_string(), exemplar);
+    }
+}
+
+/// Attach the current trace to the histogram bucket the value falls into
+pub fn record_histogram(name: &str, buckets: &[f64], value: f64) {
+    if let Some(exemplar) = current_exemplar(value) {
+        insert(bucket_series(name, buckets, value), exemplar);
+    }
+}
+
+fn insert(series: String, exemplar: Exemplar) {
+    if let Ok(mut exemplars) = store().lock() {
+        exemplars.insert(series, exemplar);
+    }
+}
+
+/// Series key of the smallest bucket holding `value` (e.g. `name_bucket{le="5"}`)
+fn bucket_series(name: &str, buckets: &[f64], value: f64) -> String {
+    let le = buckets
+        .iter()
+        .find(|bound| value <= **bound)
+        .map(|bound| bound.to_string())
+        .unwrap_or_else(|| "+Inf".to_string());
+    format!("{}_bucket{{le=\"{}\"}}", name, le)
+}
+
+/// Snapshot of all recorded exemplars
+pub fn snapshot() -> HashMap<String, Exemplar> {
+    store().lock().map(|e| e.clone()).unwrap_or_default()
+}
+
+/// Convert Prometheus text output into OpenMetrics, appending exemplars to matching series.
+///
+/// Counter samples gain the `_total` suffix OpenMetrics requires and the payload is
+/// terminated with `# EOF`.
+pub fn render_openmetrics(prometheus_text: &str, exemplars: &HashMap<String, Exemplar>) -> String {
+    let mut output = String::with_capacity(prometheus_text.len() + 64);
+    let mut counters: Vec<&str> = Vec::new();
+
+    for line in prometheus_text.lines() {
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(rest) = line.strip_prefix("# TYPE ") {
+            let mut parts = rest.split_whitespace();
+            if let (Some(name), Some("counter")) = (parts.next(), parts.next()) {
+                counters.push(name);
+            }
+            output.push_str(line);
+            output.push('\n');
+            continue;
+        }
+
+        if line.starts_with('#') {
+            output.push_str(line);
+            output.push('\n');
+            continue;
+        }
+
+        let Some((series, sample)) = line.rsplit_once(' ') else {
+            output.push_str(line);
+            output.push('\n');
+            continue;
+        };
+
+        let name_end = series.find('{').unwrap_or(series.len());
+        let (name, labels) = series.split_at(name_end);
+
+        if counters.contains(&name) {
+            output.push_str(name);
+            output.push_str("_total");
+            output.push_str(labels);
+        } else {
+            output.push_str(series);
+        }
+        output.push(' ');
+        output.push_str(sample);
+
+        if let Some(exemplar) = exemplars.get(series) {
+            output.push_str(&format!(
+                " # {{trace_id=\"{}\",span_id=\"{}\"}} {} {}",
+                exemplar.trace_id, exemplar.span_id, exemplar.value, exemplar.timestamp
+            ));
+        }
+        output.push('\n');
+    }
+
+    output.push_str("# EOF\n");
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_openmetrics_with_exemplars() {
+        let text = "# TYPE requests counter\nrequests 3\n\n# TYPE latency histogram\nlatency_bucket{le=\"5\"} 1\nlatency_bucket{le=\"+Inf\"} 1\nlatency_sum 4\nlatency_count 1\n";
+
+        let mut exemplars = HashMap::new();
+        let exemplar = Exemplar {
+            trace_id: "abc".to_string(),
+            span_id: "def".to_string(),
+            value: 4.0,
+            timestamp: 1.5,
+        };
+        exemplars.insert(bucket_series("latency", &[5.0], 4.0), exemplar.clone());
+        exemplars.insert("requests".to_string(), exemplar);
+
+        let rendered = render_openmetrics(text, &exemplars);
+
+        assert!(rendered.contains("requests_total 3 # {trace_id=\"abc\",span_id=\"def\"} 4 1.5\n"));
+        assert!(rendered
+            .contains("latency_bucket{le=\"5\"} 1 # {trace_id=\"abc\",span_id=\"def\"} 4 1.5\n"));
+        assert!(rendered.contains("latency_bucket{le=\"+Inf\"} 1\n"));
+        assert!(rendered.ends_with("# EOF\n"));
+    }
+}
@@ -0,0 +1,566 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, BinaryHeap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tracing::warn;
+
+use crate::query::{LogFilter, LogRecord, LogStats, Newest, QueryEngine};
+use crate::query_cache::{QueryCache, QueryCacheConfig};
+
+/// Logs buffered per source while `FederatedQuery::stream` merges them
+const STREAM_BUFFER: usize = 64;
+
+/// Header marking a request one daemon sends another for a federated query; such requests
+/// are answered from the receiving daemon's own storage, so daemons that federate each other
+/// do not query each other back
+pub const FORWARDED_HEADER: &str = "x-log-daemon-forwarded";
+
+/// Other log stores queried alongside the daemon's own (the `[api.federation]` section)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FederationConfig {
+    /// Storage directories, or `http(s)://host:port` AI API addresses of other daemons
+    #[serde(default)]
+    pub sources: Vec<String>,
+
+    /// Bearer token sent to remote daemons
+    #[serde(default)]
+    pub token: Option<String>,
+
+    /// Longest a remote daemon may take to answer before it is reported unavailable
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+impl Default for FederationConfig {
+    fn default() -> Self {
+        Self {
+            sources: Vec::new(),
+            token: None,
+            timeout_secs: default_timeout_secs(),
+        }
+    }
+}
+
+impl FederationConfig {
+    pub fn validate(&self) -> Result<()> {
+        for source in &self.sources {
+            if source.trim().is_empty() {
+                bail!("api.federation.sources must not contain empty sources");
+            }
+            if is_remote(source) {
+                reqwest::Url::parse(source)
+                    .with_context(|| format!("Invalid federation source {}", source))?;
+            }
+        }
+        if self.timeout_secs == 0 {
+            bail!("api.federation.timeout_secs must be greater than 0");
+        }
+        Ok(())
+    }
+}
+
+fn is_remote(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+/// Logs matching a federated query, newest first
+#[derive(Debug, Default)]
+pub struct FederatedLogs {
+    pub logs: Vec<LogRecord>,
+    /// Matching logs across all answering sources, before the limit
+    pub total_count: usize,
+    pub unavailable_sources: Vec<String>,
+}
+
+/// `/api/logs` response of a remote daemon
+#[derive(Deserialize)]
+struct RemoteLogs {
+    logs: Vec<LogRecord>,
+    total_count: usize,
+    #[serde(default)]
+    unavailable_sources: Vec<String>,
+}
+
+#[derive(Clone)]
+enum Source {
+    Local(Arc<QueryEngine>),
+    Remote {
+        url: String,
+        client: reqwest::Client,
+        token: Option<String>,
+    },
+}
+
+impl Source {
+    async fn logs(self, filter: LogFilter, limit: usize) -> Result<FederatedLogs> {
+        match self {
+            Source::Local(engine) => {
+                let mut logs = engine.query_async(&filter).await?;
+                let total_count = logs.len();
+                logs.truncate(limit);
+                Ok(FederatedLogs {
+                    logs,
+                    total_count,
+                    unavailable_sources: Vec::new(),
+                })
+            }
+            Source::Remote { .. } => {
+                let mut params = filter_params(&filter);
+                params.push(("limit", limit.to_string()));
+                let remote: RemoteLogs = self.get("/api/logs", &params).await?;
+                let mut logs = remote.logs;
+                // `timestamp_ms` is not part of the JSON
+                for log in &mut logs {
+                    log.timestamp_ms = timestamp_ms(&log.timestamp).unwrap_or(0);
+                }
+                Ok(FederatedLogs {
+                    logs,
+                    total_count: remote.total_count,
+                    unavailable_sources: remote.unavailable_sources,
+                })
+            }
+        }
+    }
+
+    /// Up to `limit` logs of each of `trace_ids`, newest first; local storage is read once for
+    /// all of them, a remote daemon is asked once per trace
+    async fn logs_by_trace(
+        self,
+        trace_ids: BTreeSet<String>,
+        limit: usize,
+    ) -> Result<HashMap<String, Vec<LogRecord>>> {
+        let mut by_trace: HashMap<String, Vec<LogRecord>> = HashMap::new();
+        match &self {
+            Source::Local(engine) => {
+                let filter = LogFilter {
+                    trace_ids: Some(trace_ids),
+                    ..LogFilter::default()
+                };
+                for log in engine.query_async(&filter).await? {
+                    let Some(trace_id) = &log.trace_id else {
+                        continue;
+                    };
+                    let logs = by_trace.entry(trace_id.clone()).or_default();
+                    if logs.len() < limit {
+                        logs.push(log);
+                    }
+                }
+            }
+            Source::Remote { .. } => {
+                for trace_id in trace_ids {
+                    let filter = LogFilter {
+                        trace_id: Some(trace_id.clone()),
+                        ..LogFilter::default()
+                    };
+                    by_trace.insert(trace_id, self.clone().logs(filter, limit).await?.logs);
+                }
+            }
+        }
+        Ok(by_trace)
+    }
+
+    /// Send up to `limit` logs matching `filter` to `tx`, newest first
+    async fn stream(
+        self,
+        filter: LogFilter,
+        limit: usize,
+        tx: mpsc::Sender<LogRecord>,
+    ) -> Result<()> {
+        match self {
+            Source::Local(engine) => engine.stream_async(&filter, limit, tx).await,
+            Source::Remote { .. } => {
+                // A remote daemon answers with at most `limit` logs at once
+                for log in self.logs(filter, limit).await?.logs {
+                    if tx.send(log).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    async fn stats(self, filter: LogFilter) -> Result<LogStats> {
+        match self {
+            Source::Local(engine) => engine.stats_async(&filter).await,
+            Source::Remote { .. } => self.get("/api/logs/stats", &filter_params(&filter)).await,
+        }
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        params: &[(&str, String)],
+    ) -> Result<T> {
+        let Source::Remote { url, client, token } = self else {
+            unreachable!("only remote sources are fetched over HTTP");
+        };
+        let mut request = client
+            .get(format!("{}{}", url, path))
+            .header(FORWARDED_HEADER, "1")
+            .query(params);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let body = request.send().await?.error_for_status()?.bytes().await?;
+        serde_json::from_slice(&body).context("Invalid response")
+    }
+}
+
+/// Query parameters of the AI API equivalent to `filter`; `trace_ids` has none, so remote
+/// daemons are asked for one trace at a time
+fn filter_params(filter: &LogFilter) -> Vec<(&'static str, String)> {
+    let time = |ms: Option<i64>| {
+        ms.and_then(DateTime::from_timestamp_millis)
+            .map(|dt| dt.to_rfc3339_opts(SecondsFormat::Millis, true))
+    };
+    [
+        ("start_time", time(filter.start_ms)),
+        ("end_time", time(filter.end_ms)),
+        ("level", filter.level.clone()),
+        ("service", filter.service.clone()),
+        ("trace_id", filter.trace_id.clone()),
+        ("contains", filter.contains.clone()),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| value.map(|value| (key, value)))
+    .collect()
+}
+
+fn timestamp_ms(timestamp: &str) -> Option<i64> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|dt| dt.timestamp_millis())
+}
+
+/// Answers log queries from the daemon's own storage and any federated sources, merging
+/// the results; sources that fail are reported rather than failing the query
+pub struct FederatedQuery {
+    /// Display name and source, the daemon's own storage first
+    sources: Vec<(String, Source)>,
+}
+
+impl FederatedQuery {
+    /// Query only `storage_dir`
+    pub fn local(storage_dir: PathBuf) -> Self {
+        Self {
+            sources: vec![local_source(storage_dir)],
+        }
+    }
+
+    /// Only the daemon's own storage, for requests forwarded by another daemon
+    pub fn own_storage(&self) -> Self {
+        Self {
+            sources: self.sources[..1].to_vec(),
+        }
+    }
+
+    /// Cache the results of local sources as `config` says
+    pub fn with_query_cache(mut self, config: &QueryCacheConfig) -> Self {
+        if config.max_records == 0 {
+            return self;
+        }
+        for (_, source) in &mut self.sources {
+            if let Source::Local(engine) = source {
+                let cache = Arc::new(QueryCache::new(config));
+                *engine = Arc::new(
+                    QueryEngine::new(engine.storage_dir().to_path_buf()).with_cache(cache),
+                );
+            }
+        }
+        self
+    }
+
+    /// Query `storage_dir` and the sources of `config`
+    pub fn new(storage_dir: PathBuf, config: &FederationConfig) -> Result<Self> {
+        config.validate()?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()?;
+        let mut query = Self::local(storage_dir);
+        for source in &config.sources {
+            if is_remote(source) {
+                let url = source.trim_end_matches('/').to_string();
+                query.sources.push((
+                    url.clone(),
+                    Source::Remote {
+                        url,
+                        client: client.clone(),
+                        token: config.token.clone(),
+                    },
+                ));
+            } else {
+                query.sources.push(local_source(PathBuf::from(source)));
+            }
+        }
+        Ok(query)
+    }
+
+    /// Up to `limit` logs matching `filter` across all sources, newest first
+    pub async fn query(&self, filter: &LogFilter, limit: usize) -> Result<FederatedLogs> {
+        let mut merged = FederatedLogs::default();
+        let answers = self
+            .fan_out(|source| source.logs(filter.clone(), limit))
+            .await?;
+        for answer in answers {
+            match answer {
+                Ok(logs) => {
+                    merged.logs.extend(logs.logs);
+                    merged.total_count += logs.total_count;
+                    merged.unavailable_sources.extend(logs.unavailable_sources);
+                }
+                Err(name) => merged.unavailable_sources.push(name),
+            }
+        }
+        merged
+            .logs
+            .sort_by_key(|log| std::cmp::Reverse(log.timestamp_ms));
+        merged.logs.truncate(limit);
+        Ok(merged)
+    }
+
+    /// Send up to `limit` logs matching `filter` across all sources to `tx`, newest first, as
+    /// the sources produce them; sources that fail are logged and skipped
+    pub async fn stream(&self, filter: &LogFilter, limit: usize, tx: mpsc::Sender<LogRecord>) {
+        let mut sources = Vec::new();
+        for (name, source) in &self.sources {
+            let (source_tx, source_rx) = mpsc::channel(STREAM_BUFFER);
+            let (name, source, filter) = (name.clone(), source.clone(), filter.clone());
+            tokio::spawn(async move {
+                if let Err(e) = source.stream(filter, limit, source_tx).await {
+                    warn!("Log source {} is unavailable: {:#}", name, e);
+                }
+            });
+            sources.push(source_rx);
+        }
+
+        // Each source is newest first, so the newest of their next logs is the next overall
+        let mut heads = BinaryHeap::new();
+        for (position, source) in sources.iter_mut().enumerate() {
+            if let Some(log) = source.recv().await {
+                heads.push(Newest(log, position as u64));
+            }
+        }
+        for _ in 0..limit {
+            let Some(Newest(log, position)) = heads.pop() else {
+                break;
+            };
+            if tx.send(log).await.is_err() {
+                break;
+            }
+            if let Some(next) = sources[position as usize].recv().await {
+                heads.push(Newest(next, position));
+            }
+        }
+    }
+
+    /// Up to `limit` logs of each of `trace_ids` across all sources, newest first, keyed by
+    /// trace; traces without logs are left out
+    pub async fn query_traces(
+        &self,
+        trace_ids: &BTreeSet<String>,
+        limit: usize,
+    ) -> Result<HashMap<String, Vec<LogRecord>>> {
+        let answers = self
+            .fan_out(|source| source.logs_by_trace(trace_ids.clone(), limit))
+            .await?;
+        let mut merged: HashMap<String, Vec<LogRecord>> = HashMap::new();
+        for by_trace in answers.into_iter().flatten() {
+            for (trace_id, logs) in by_trace {
+                merged.entry(trace_id).or_default().extend(logs);
+            }
+        }
+        for logs in merged.values_mut() {
+            logs.sort_by_key(|log| std::cmp::Reverse(log.timestamp_ms));
+            logs.truncate(limit);
+        }
+        Ok(merged)
+    }
+
+    /// Level and service counts of logs matching `filter` across all sources
+    pub async fn stats(&self, filter: &LogFilter) -> Result<LogStats> {
+        let mut merged = LogStats::default();
+        let answers = self.fan_out(|source| source.stats(filter.clone())).await?;
+        for answer in answers {
+            match answer {
+                Ok(stats) => merge_stats(&mut merged, stats),
+                Err(name) => merged.unavailable_sources.push(name),
+            }
+        }
+        Ok(merged)
+    }
+
+    /// Run `ask` against every source at once; each answer, or the name of a source that
+    /// failed, in source order. Fails only when every source does.
+    async fn fan_out<T, F, Fut>(&self, ask: F) -> Result<Vec<Result<T, String>>>
+    where
+        T: Send + 'static,
+        F: Fn(Source) -> Fut,
+        Fut: std::future::Future<Output = Result<T>> + Send + 'static,
+    {
+        let mut asks = JoinSet::new();
+        for (position, (_, source)) in self.sources.iter().enumerate() {
+            let answer = ask(source.clone());
+            asks.spawn(async move { (position, answer.await) });
+        }
+
+        let mut answers: Vec<Option<Result<T, String>>> =
+            (0..self.sources.len()).map(|_| None).collect();
+        let mut first_error = None;
+        while let Some(joined) = asks.join_next().await {
+            let (position, answer) = joined?;
+            let name = &self.sources[position].0;
+            answers[position] = Some(answer.map_err(|e| {
+                warn!("Log source {} is unavailable: {:#}", name, e);
+                first_error.get_or_insert(e.context(format!("Log source {}", name)));
+                name.clone()
+            }));
+        }
+        if answers.iter().all(|answer| matches!(answer, Some(Err(_)))) {
+            if let Some(e) = first_error {
+                return Err(e);
+            }
+        }
+        Ok(answers.into_iter().flatten().collect())
+    }
+}
+
+fn local_source(storage_dir: PathBuf) -> (String, Source) {
+    (
+        storage_dir.display().to_string(),
+        Source::Local(Arc::new(QueryEngine::new(storage_dir))),
+    )
+}
+
+/// Add the counts of `other` to `stats`, widening its time range
+fn merge_stats(stats: &mut LogStats, other: LogStats) {
+    stats.total_count += other.total_count;
+    for (level, count) in other.by_level {
+        *stats.by_level.entry(level).or_insert(0) += count;
+    }
+    for (service, count) in other.by_service {
+        *stats.by_service.entry(service).or_insert(0) += count;
+    }
+    let ms = |timestamp: &Option<String>| timestamp.as_deref().and_then(timestamp_ms);
+    if other.earliest.is_some()
+        && (stats.earliest.is_none() || ms(&other.earliest) < ms(&stats.earliest))
+    {
+        stats.earliest = other.earliest;
+    }
+    if ms(&other.latest) > ms(&stats.latest) {
+        stats.latest = other.latest;
+    }
+    stats.unavailable_sources.extend(other.unavailable_sources);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{parse_compression, StorageEngine};
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_merges_local_and_remote_sources() {
+        let temp_dir = TempDir::new().unwrap();
+        let own = temp_dir.path().join("own");
+        let mut engine =
+            StorageEngine::new(own.clone(), parse_compression("snappy"), 10, 1 << 20).unwrap();
+        for (second, level) in [(0, "info"), (20, "error"), (40, "info")] {
+            let log = serde_json::from_value(json!({
+                "timestamp": format!("2026-01-15T19:00:{:02}Z", second),
+                "level": level,
+                "message": format!("local {}", second),
+                "service": "api",
+            }))
+            .unwrap();
+            engine.add_log(log).unwrap();
+        }
+        engine.flush().unwrap();
+
+        // A daemon on another host, answering with its newest logs
+        let remote = Router::new()
+            .route(
+                "/api/logs",
+                // The remote is told the request is forwarded, so it does not fan out again
+                get(|headers: axum::http::HeaderMap| async move {
+                    assert!(headers.contains_key(FORWARDED_HEADER));
+                    Json(json!({
+                        "logs": [
+                            {"timestamp": "2026-01-15T19:00:30+00:00", "level": "error",
+                             "message": "remote 30", "service": "db"},
+                            {"timestamp": "2026-01-15T19:00:10+00:00", "level": "error",
+                             "message": "remote 10", "service": "db"},
+                        ],
+                        "total_count": 5,
+                    }))
+                }),
+            )
+            .route(
+                "/api/logs/stats",
+                get(|| async {
+                    Json(json!({
+                        "total_count": 5,
+                        "by_level": {"error": 5},
+                        "by_service": {"db": 5},
+                        "earliest": "2026-01-15T18:00:00+00:00",
+                        "latest": "2026-01-15T19:00:30+00:00",
+                    }))
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, remote).await });
+        // Nothing listens on the discard port
+        let config = FederationConfig {
+            sources: vec![
+                format!("http://{}/", addr),
+                "http://127.0.0.1:9".to_string(),
+            ],
+            ..Default::default()
+        };
+        let query = FederatedQuery::new(own, &config).unwrap();
+
+        let logs = query.query(&LogFilter::default(), 3).await.unwrap();
+        let messages: Vec<_> = logs.logs.iter().map(|log| log.message.as_str()).collect();
+        assert_eq!(messages, ["local 40", "remote 30", "local 20"]);
+        assert_eq!(logs.total_count, 8);
+        assert_eq!(logs.unavailable_sources, ["http://127.0.0.1:9"]);
+        let own_logs = query.own_storage().query(&LogFilter::default(), 10).await;
+        assert_eq!(own_logs.unwrap().total_count, 3);
+
+        let stats = query.stats(&LogFilter::default()).await.unwrap();
+        assert_eq!(stats.total_count, 8);
+        assert_eq!(stats.by_level["error"], 6);
+        assert_eq!(stats.by_service["api"], 3);
+        assert_eq!(stats.earliest.as_deref(), Some("2026-01-15T18:00:00+00:00"));
+        assert_eq!(stats.latest.as_deref(), Some("2026-01-15T19:00:40+00:00"));
+
+        // With nothing answering, the query fails
+        let down = FederationConfig {
+            sources: vec!["http://127.0.0.1:9".to_string()],
+            ..Default::default()
+        };
+        let not_a_dir = temp_dir.path().join("logs.parquet");
+        std::fs::write(&not_a_dir, b"").unwrap();
+        let query = FederatedQuery::new(not_a_dir, &down).unwrap();
+        assert!(query.query(&LogFilter::default(), 10).await.is_err());
+        assert!(FederationConfig {
+            sources: vec![String::new()],
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::schema::{ParseError, RejectReason};
+
+/// A rule rewriting the fields of incoming messages before they are validated; paths are
+/// dot-separated, e.g. `ctx.trace_id`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum FieldMapping {
+    /// Rename or move `from` to `to`, replacing any value already there
+    Move { from: String, to: String },
+    /// Remove a field
+    Drop { drop: String },
+}
+
+impl FieldMapping {
+    fn paths(&self) -> impl Iterator<Item = &str> {
+        let (first, second) = match self {
+            FieldMapping::Move { from, to } => (from.as_str(), Some(to.as_str())),
+            FieldMapping::Drop { drop } => (drop.as_str(), None),
+        };
+        std::iter::once(first).chain(second)
+    }
+}
+
+/// Validate `[[ingest.field_mappings]]` rules
+pub fn validate_mappings(mappings: &[FieldMapping]) -> Result<()> {
+    for mapping in mappings {
+        for path in mapping.paths() {
+            if path.split('.').any(str::is_empty) {
+                bail!(
+                    "ingest.field_mappings contains an invalid field path: {:?}",
+                    path
+                );
+            }
+        }
+        if let FieldMapping::Move { from, to } = mapping {
+            if to == from || to.starts_with(&format!("{}.", from)) {
+                bail!("ingest.field_mappings cannot move {} into itself", from);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Applies field mappings to raw messages, which costs an extra parse and re-encode per message
+#[derive(Debug, Clone)]
+pub struct FieldMapper {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone)]
+enum Rule {
+    Move { from: Vec<String>, to: Vec<String> },
+    Drop(Vec<String>),
+}
+
+fn split(path: &str) -> Vec<String> {
+    path.split('.').map(str::to_string).collect()
+}
+
+impl FieldMapper {
+    pub fn new(mappings: &[FieldMapping]) -> Self {
+        let rules = mappings
+            .iter()
+            .map(|mapping| match mapping {
+                FieldMapping::Move { from, to } => Rule::Move {
+                    from: split(from),
+                    to: split(to),
+                },
+                FieldMapping::Drop { drop } => Rule::Drop(split(drop)),
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// Rewrite a raw message, applying the rules in order
+    pub fn map(&self, data: &[u8]) -> Result<Vec<u8>, ParseError> {
+        let mut message: Value = serde_json::from_slice(data)
+            .map_err(|e| ParseError::new(RejectReason::MalformedJson, e))?;
+        if let Value::Object(fields) = &mut message {
+            self.map_fields(fields);
+        }
+        serde_json::to_vec(&message).map_err(|e| ParseError::new(RejectReason::MalformedJson, e))
+    }
+
+    fn map_fields(&self, fields: &mut Map<String, Value>) {
+        for rule in &self.rules {
+            match rule {
+                Rule::Move { from, to } => {
+                    if let Some(value) = take(fields, from) {
+                        put(fields, to, value);
+                    }
+                }
+                Rule::Drop(path) => {
+                    take(fields, path);
+                }
+            }
+        }
+    }
+}
+
+/// Remove the value at `path`, if every step of it exists
+fn take(fields: &mut Map<String, Value>, path: &[String]) -> Option<Value> {
+    let (last, parents) = path.split_last()?;
+    let mut current = fields;
+    for key in parents {
+        current = current.get_mut(key)?.as_object_mut()?;
+    }
+    current.remove(last)
+}
+
+/// Set the value at `path`, creating objects for missing or non-object steps
+fn put(fields: &mut Map<String, Value>, path: &[String], value: Value) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let mut current = fields;
+    for key in parents {
+        let next = current
+            .entry(key.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+        if !next.is_object() {
+            *next = Value::Object(Map::new());
+        }
+        current = next.as_object_mut().expect("replaced with an object above");
+    }
+    current.insert(last.clone(), value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_mappings_rename_move_and_drop() {
+        let mappings: Vec<FieldMapping> = toml::from_str::<toml::Table>(
+            r#"
+            rules = [
+                { from = "msg", to = "message" },
+                { from = "severity", to = "level" },
+                { from = "ctx.trace_id", to = "traceId" },
+                { from = "user_id", to = "metadata.user.id" },
+                { drop = "ctx" },
+            ]
+            "#,
+        )
+        .unwrap()["rules"]
+            .clone()
+            .try_into()
+            .unwrap();
+        validate_mappings(&mappings).unwrap();
+
+        let mapper = FieldMapper::new(&mappings);
+        let mapped = mapper
+            .map(
+                json!({
+                    "timestamp": "2026-01-15T19:00:00Z",
+                    "severity": "warn",
+                    "msg": "disk almost full",
+                    "ctx": { "trace_id": "abc", "span": "1" },
+                    "user_id": 7,
+                    "metadata": "not an object"
+                })
+                .to_string()
+                .as_bytes(),
+            )
+            .unwrap();
+        let mapped: Value = serde_json::from_slice(&mapped).unwrap();
+        assert_eq!(
+            mapped,
+            json!({
+                "timestamp": "2026-01-15T19:00:00Z",
+                "level": "warn",
+                "message": "disk almost full",
+                "traceId": "abc",
+                "metadata": { "user": { "id": 7 } }
+            })
+        );
+
+        assert!(mapper.map(b"{ not json").is_err());
+        assert!(validate_mappings(&[FieldMapping::Move {
+            from: "ctx".into(),
+            to: "ctx.inner".into()
+        }])
+        .is_err());
+        assert!(validate_mappings(&[FieldMapping::Drop {
+            drop: "ctx..id".into()
+        }])
+        .is_err());
+    }
+}
@@ -0,0 +1,291 @@
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::bench::{frame, Pacer};
+
+/// Invalid records reported individually; later ones are only counted
+const MAX_REPORTED_ERRORS: u64 = 20;
+
+/// How often progress is printed
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Outcome of loading files through the socket
+#[derive(Debug, Default)]
+pub struct FileIngestReport {
+    pub files: usize,
+    pub sent: u64,
+    /// Records that were not JSON objects, or files that could not be read
+    pub invalid: u64,
+    /// Error frames the daemon sent back, by reason; only the `reject` policy sends them
+    pub rejected: BTreeMap<String, u64>,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for FileIngestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Sent {} logs from {} file(s) in {:.2}s ({:.0} logs/s)",
+            self.sent,
+            self.files,
+            self.elapsed.as_secs_f64(),
+            self.sent as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+        )?;
+        if self.invalid > 0 {
+            write!(f, "; skipped {} invalid record(s)", self.invalid)?;
+        }
+        if !self.rejected.is_empty() {
+            let reasons: Vec<String> = self
+                .rejected
+                .iter()
+                .map(|(reason, count)| format!("{} {}", count, reason))
+                .collect();
+            write!(f, "; rejected by the daemon: {}", reasons.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Files matching `patterns`, which are paths or glob patterns such as `logs/*.ndjson`, in order
+/// and without duplicates. A pattern that matches nothing is an error.
+pub fn expand_patterns(patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for pattern in patterns {
+        let matched: Vec<PathBuf> = glob::glob(pattern)
+            .with_context(|| format!("Invalid file pattern: {}", pattern))?
+            .filter_map(|entry| entry.ok())
+            .filter(|path| path.is_file())
+            .collect();
+        if matched.is_empty() {
+            bail!("No files match {}", pattern);
+        }
+        for path in matched {
+            if !files.contains(&path) {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Send every log in `files` to the daemon at `socket_path`, at most `rate` per second.
+/// Newline-delimited files send one log per line; `.json` files, and files starting with `[`,
+/// hold one log or an array of them. Invalid records are reported on stderr and skipped.
+pub fn ingest_files(
+    socket_path: &Path,
+    files: &[PathBuf],
+    rate: Option<u64>,
+) -> Result<FileIngestReport> {
+    if rate == Some(0) {
+        bail!("rate must be greater than 0");
+    }
+    let mut stream = UnixStream::connect(socket_path)
+        .with_context(|| format!("Failed to connect to {:?}", socket_path))?;
+    // Read error frames as they come, so the daemon never stalls on a full socket buffer
+    let replies = stream.try_clone()?;
+    let reader = std::thread::spawn(move || read_replies(replies));
+
+    let start = Instant::now();
+    let pacer = Pacer::new(start, rate);
+    let mut report = FileIngestReport::default();
+    let mut next_progress = start + PROGRESS_INTERVAL;
+
+    for path in files {
+        report.files += 1;
+        let mut send_error = None;
+        let result = for_each_record(path, |record, location| {
+            let log = match record {
+                Ok(log) if log.is_object() => log,
+                invalid => {
+                    report.invalid += 1;
+                    if report.invalid <= MAX_REPORTED_ERRORS {
+                        let reason = invalid.err().unwrap_or_else(|| "not a JSON object".into());
+                        eprintln!("\r{}: {}", location, reason);
+                    }
+                    return Ok(());
+                }
+            };
+            pacer.wait(report.sent);
+            if let Err(e) = stream.write_all(&frame(log.to_string().as_bytes())) {
+                send_error = Some(anyhow::Error::new(e).context(format!(
+                    "Failed to send {} after {} logs",
+                    location, report.sent
+                )));
+                bail!("connection failed");
+            }
+            report.sent += 1;
+            if Instant::now() >= next_progress {
+                next_progress += PROGRESS_INTERVAL;
+                eprint!("\rSent {} logs", report.sent);
+            }
+            Ok(())
+        });
+        if let Some(e) = send_error {
+            return Err(e);
+        }
+        // An unreadable file is reported and skipped
+        if let Err(e) = result {
+            report.invalid += 1;
+            eprintln!("\r{}: {:#}", path.display(), e);
+        }
+    }
+    stream.flush()?;
+    stream.shutdown(Shutdown::Write)?;
+
+    report.rejected = reader.join().expect("reply reader panicked");
+    report.elapsed = start.elapsed();
+    if report.sent > 0 {
+        eprintln!();
+    }
+    if report.invalid > MAX_REPORTED_ERRORS {
+        eprintln!(
+            "{} more invalid record(s) not shown",
+            report.invalid - MAX_REPORTED_ERRORS
+        );
+    }
+    Ok(report)
+}
+
+/// Where a record came from, for error messages
+struct Location<'a> {
+    path: &'a Path,
+    /// Line in newline-delimited files, element index in JSON arrays
+    index: Option<(&'static str, usize)>,
+}
+
+impl fmt::Display for Location<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let Some((kind, index)) = self.index {
+            write!(f, " {} {}", kind, index)?;
+        }
+        Ok(())
+    }
+}
+
+/// Call `handle` with each record of the file at `path`
+pub(crate) fn for_each_record(
+    path: &Path,
+    mut handle: impl FnMut(Result<Value, String>, &dyn fmt::Display) -> Result<()>,
+) -> Result<()> {
+    let mut reader =
+        BufReader::new(File::open(path).with_context(|| format!("Failed to open {:?}", path))?);
+    let starts_with_array = reader
+        .fill_buf()?
+        .iter()
+        .find(|byte| !byte.is_ascii_whitespace())
+        == Some(&b'[');
+
+    if starts_with_array || path.extension().is_some_and(|ext| ext == "json") {
+        let document: Value =
+            serde_json::from_reader(reader).context("Failed to parse the JSON document")?;
+        match document {
+            Value::Array(records) => {
+                for (index, record) in records.into_iter().enumerate() {
+                    let location = Location {
+                        path,
+                        index: Some(("element", index)),
+                    };
+                    handle(Ok(record), &location)?;
+                }
+            }
+            record => handle(Ok(record), &Location { path, index: None })?,
+        }
+        return Ok(());
+    }
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to read {:?}", path))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let location = Location {
+            path,
+            index: Some(("line", index + 1)),
+        };
+        let record = serde_json::from_str(&line).map_err(|e| format!("invalid JSON: {}", e));
+        handle(record, &location)?;
+    }
+    Ok(())
+}
+
+/// Count the error frames the daemon sends back by their `error` reason, until it closes the
+/// connection
+fn read_replies(mut stream: UnixStream) -> BTreeMap<String, u64> {
+    let mut rejected = BTreeMap::new();
+    let mut length = [0u8; 4];
+    while stream.read_exact(&mut length).is_ok() {
+        let mut body = vec![0u8; u32::from_be_bytes(length) as usize];
+        if stream.read_exact(&mut body).is_err() {
+            break;
+        }
+        let reason = serde_json::from_slice::<Value>(&body)
+            .ok()
+            .and_then(|reply| reply["error"].as_str().map(str::to_string))
+            .unwrap_or_else(|| "unknown".to_string());
+        *rejected.entry(reason).or_insert(0) += 1;
+    }
+    rejected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_ingest_files_sends_records_and_counts_rejections() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(
+            temp_dir.path().join("a.ndjson"),
+            "{\"level\":\"info\",\"message\":\"one\"}\n\nnot json\n{\"level\":\"info\",\"message\":\"two\"}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            temp_dir.path().join("b.json"),
+            "[\n  {\"level\": \"error\", \"message\": \"three\"},\n  42\n]\n",
+        )
+        .unwrap();
+
+        let pattern = temp_dir.path().join("*.*json").display().to_string();
+        let files = expand_patterns(&[pattern.clone(), pattern]).unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(expand_patterns(&["/no/such/dir/*.ndjson".to_string()]).is_err());
+
+        // Rejects every log it receives, like the `reject` policy
+        let socket_path = temp_dir.path().join("ingest.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut messages = Vec::new();
+            let mut length = [0u8; 4];
+            while stream.read_exact(&mut length).is_ok() {
+                let mut body = vec![0u8; u32::from_be_bytes(length) as usize];
+                stream.read_exact(&mut body).unwrap();
+                let log: Value = serde_json::from_slice(&body).unwrap();
+                messages.push(log["message"].as_str().unwrap().to_string());
+                stream
+                    .write_all(&frame(br#"{"error":"schema_violation"}"#))
+                    .unwrap();
+            }
+            messages
+        });
+
+        let report = ingest_files(&socket_path, &files, Some(1000)).unwrap();
+        assert_eq!(server.join().unwrap(), vec!["one", "two", "three"]);
+        assert_eq!((report.files, report.sent, report.invalid), (2, 3, 2));
+        assert_eq!(report.rejected.get("schema_violation"), Some(&3));
+        assert!(report
+            .to_string()
+            .contains("rejected by the daemon: 3 schema_violation"));
+    }
+}
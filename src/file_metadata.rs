@@ -0,0 +1,154 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use parquet::file::metadata::KeyValue;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Prefix of the key-value metadata keys the daemon writes into its Parquet files
+pub const KEY_PREFIX: &str = "daemon_rs.";
+
+/// Most distinct services or levels listed in a file's metadata; files holding more list none
+pub const MAX_LISTED: usize = 256;
+
+/// Version of the column layout of log and trace files, bumped when the columns change
+pub const FORMAT_VERSION: u32 = 1;
+
+/// What a Parquet file written by the daemon records about itself, so it stays readable
+/// without the config or the release that wrote it
+#[derive(Debug, Clone)]
+pub struct FileMetadata {
+    /// `logs` or `traces`
+    kind: &'static str,
+    /// Writer settings, e.g. `compression` and `batch_size`
+    writer: BTreeMap<&'static str, String>,
+    /// Earliest and latest timestamp of the rows
+    time_range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    /// Distinct `schema_version` values of the logs
+    schema_versions: Vec<String>,
+    /// Distinct services of the logs, `""` for logs without one
+    services: Option<BTreeSet<String>>,
+    /// Distinct levels of the logs
+    levels: Option<BTreeSet<String>>,
+}
+
+impl FileMetadata {
+    pub fn new(kind: &'static str) -> Self {
+        Self {
+            kind,
+            writer: BTreeMap::new(),
+            time_range: None,
+            schema_versions: Vec::new(),
+            services: None,
+            levels: None,
+        }
+    }
+
+    pub fn with_writer(mut self, key: &'static str, value: impl ToString) -> Self {
+        self.writer.insert(key, value.to_string());
+        self
+    }
+
+    /// Earliest and latest timestamp of the rows, in microseconds
+    pub fn with_time_range(mut self, min_us: Option<i64>, max_us: Option<i64>) -> Self {
+        let time = |us: Option<i64>| us.and_then(DateTime::from_timestamp_micros);
+        if let (Some(min), Some(max)) = (time(min_us), time(max_us)) {
+            self.time_range = Some((min, max));
+        }
+        self
+    }
+
+    pub fn with_schema_versions<'a>(mut self, versions: impl IntoIterator<Item = &'a str>) -> Self {
+        let versions: std::collections::BTreeSet<&str> = versions.into_iter().collect();
+        self.schema_versions = versions.into_iter().map(str::to_string).collect();
+        self
+    }
+
+    /// Services of the logs, so retention overrides can tell which apply without reading them
+    pub fn with_services<'a>(
+        mut self,
+        services: impl IntoIterator<Item = Option<&'a str>>,
+    ) -> Self {
+        self.services = listed(services.into_iter().map(Option::unwrap_or_default));
+        self
+    }
+
+    /// Levels of the logs, for the same reason as the services
+    pub fn with_levels<'a>(mut self, levels: impl IntoIterator<Item = &'a str>) -> Self {
+        self.levels = listed(levels);
+        self
+    }
+
+    /// Entries for the file footer
+    pub fn key_values(&self) -> Vec<KeyValue> {
+        let time = |t: &DateTime<Utc>| t.to_rfc3339_opts(SecondsFormat::Micros, true);
+        let mut entries = vec![
+            ("version".to_string(), env!("CARGO_PKG_VERSION").to_string()),
+            ("format_version".to_string(), FORMAT_VERSION.to_string()),
+            ("kind".to_string(), self.kind.to_string()),
+            ("written_at".to_string(), time(&Utc::now())),
+        ];
+        if let Some((min, max)) = &self.time_range {
+            entries.push(("min_time".to_string(), time(min)));
+            entries.push(("max_time".to_string(), time(max)));
+        }
+        if !self.schema_versions.is_empty() {
+            entries.push((
+                "schema_versions".to_string(),
+                self.schema_versions.join(","),
+            ));
+        }
+        for (key, values) in [("services", &self.services), ("levels", &self.levels)] {
+            if let Some(values) = values {
+                let json = serde_json::to_string(values).unwrap_or_default();
+                entries.push((key.to_string(), json));
+            }
+        }
+        for (key, value) in &self.writer {
+            entries.push((format!("writer.{}", key), value.clone()));
+        }
+        entries
+            .into_iter()
+            .map(|(key, value)| KeyValue::new(format!("{}{}", KEY_PREFIX, key), value))
+            .collect()
+    }
+}
+
+fn listed<'a>(values: impl IntoIterator<Item = &'a str>) -> Option<BTreeSet<String>> {
+    let mut listed = BTreeSet::new();
+    for value in values {
+        if !listed.contains(value) {
+            if listed.len() == MAX_LISTED {
+                return None;
+            }
+            listed.insert(value.to_string());
+        }
+    }
+    Some(listed)
+}
+
+/// The values listed under `key` (`services` or `levels`) in the daemon's entries; None for
+/// files that do not list them
+pub fn listed_values(entries: &BTreeMap<String, String>, key: &str) -> Option<BTreeSet<String>> {
+    serde_json::from_str(entries.get(key)?).ok()
+}
+
+/// Earliest and latest row timestamp recorded in the daemon's entries, in milliseconds
+pub fn time_range_ms(entries: &BTreeMap<String, String>) -> Option<(i64, i64)> {
+    let ms = |key| {
+        DateTime::parse_from_rfc3339(entries.get(key)?)
+            .ok()
+            .map(|t| t.timestamp_millis())
+    };
+    Some((ms("min_time")?, ms("max_time")?))
+}
+
+/// The entries of `key_values` under `KEY_PREFIX`, without the prefix; empty for files
+/// written by other tools or by releases before it was recorded
+pub fn daemon_entries(key_values: Option<&Vec<KeyValue>>) -> BTreeMap<String, String> {
+    key_values
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let key = entry.key.strip_prefix(KEY_PREFIX)?;
+            Some((key.to_string(), entry.value.clone().unwrap_or_default()))
+        })
+        .collect()
+}
@@ -0,0 +1,150 @@
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::trace_storage::TraceSpan;
+
+/// Root-to-leaf span name paths with the self time (microseconds) of the leaf span
+fn span_stacks(spans: &[TraceSpan]) -> Vec<(Vec<&str>, u64)> {
+    let ids: HashSet<&str> = spans.iter().map(|s| s.span_id.as_str()).collect();
+    let mut children: HashMap<&str, Vec<&TraceSpan>> = HashMap::new();
+    let mut roots = Vec::new();
+    for span in spans {
+        match span.parent_span_id.as_deref() {
+            // Spans whose parent was never stored are drawn as extra roots
+            Some(parent) if ids.contains(parent) && parent != span.span_id => {
+                children.entry(parent).or_default().push(span)
+            }
+            _ => roots.push(span),
+        }
+    }
+
+    let mut stacks = Vec::with_capacity(spans.len());
+    let mut visited = HashSet::new();
+    let mut pending: Vec<(&TraceSpan, Vec<&str>)> =
+        roots.into_iter().map(|r| (r, vec![])).collect();
+    while let Some((span, mut path)) = pending.pop() {
+        // Guard against cycles in malformed parent links
+        if !visited.insert(span.span_id.as_str()) {
+            continue;
+        }
+        path.push(span.name.as_str());
+
+        let kids = children
+            .get(span.span_id.as_str())
+            .map(Vec::as_slice)
+            .unwrap_or(&[]);
+        let child_time: u64 = kids.iter().map(|c| c.duration_us).sum();
+        stacks.push((path.clone(), span.duration_us.saturating_sub(child_time)));
+
+        for child in kids {
+            pending.push((child, path.clone()));
+        }
+    }
+    stacks
+}
+
+/// Flamegraph frame names may not contain the folded-format separators
+fn frame_name(name: &str) -> String {
+    name.replace([';', '\n'], " ")
+}
+
+/// Brendan Gregg's folded-stack format: `root;child;leaf <self time in µs>` per line
+pub fn folded_stacks(spans: &[TraceSpan]) -> String {
+    let mut folded: BTreeMap<String, u64> = BTreeMap::new();
+    for (path, self_us) in span_stacks(spans) {
+        let key = path
+            .iter()
+            .map(|name| frame_name(name))
+            .collect::<Vec<_>>()
+            .join(";");
+        *folded.entry(key).or_default() += self_us;
+    }
+
+    folded
+        .into_iter()
+        .map(|(stack, value)| format!("{} {}\n", stack, value))
+        .collect()
+}
+
+/// speedscope file with one sampled profile weighted by span self time
+pub fn speedscope(spans: &[TraceSpan], name: &str) -> serde_json::Value {
+    let mut frames: Vec<&str> = Vec::new();
+    let mut frame_index: HashMap<&str, usize> = HashMap::new();
+    let mut samples = Vec::new();
+    let mut weights = Vec::new();
+
+    for (path, self_us) in span_stacks(spans) {
+        let stack: Vec<usize> = path
+            .iter()
+            .map(|frame| {
+                *frame_index.entry(frame).or_insert_with(|| {
+                    frames.push(frame);
+                    frames.len() - 1
+                })
+            })
+            .collect();
+        samples.push(stack);
+        weights.push(self_us);
+    }
+    let total: u64 = weights.iter().sum();
+
+    json!({
+        "$schema": "https://www.speedscope.app/file-format-schema.json",
+        "name": name,
+        "exporter": "daemon_rs",
+        "shared": {
+            "frames": frames.iter().map(|f| json!({ "name": f })).collect::<Vec<_>>(),
+        },
+        "profiles": [{
+            "type": "sampled",
+            "name": name,
+            "unit": "microseconds",
+            "startValue": 0,
+            "endValue": total,
+            "samples": samples,
+            "weights": weights,
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace_storage::SpanStatus;
+    use chrono::Utc;
+
+    fn span(span_id: &str, parent: Option<&str>, name: &str, duration_us: u64) -> TraceSpan {
+        let now = Utc::now();
+        TraceSpan {
+            trace_id: "t".to_string(),
+            span_id: span_id.to_string(),
+            parent_span_id: parent.map(str::to_string),
+            name: name.to_string(),
+            start_time: now,
+            end_time: now,
+            duration_us,
+            attributes: HashMap::new(),
+            events: Vec::new(),
+            status: SpanStatus::Ok,
+        }
+    }
+
+    #[test]
+    fn test_folded_stacks_use_self_time() {
+        let spans = vec![
+            span("root", None, "GET /checkout", 10_000),
+            span("a", Some("root"), "db;query", 3_000),
+            span("b", Some("root"), "db;query", 2_000),
+            span("c", Some("a"), "parse", 1_000),
+        ];
+
+        assert_eq!(
+            folded_stacks(&spans),
+            "GET /checkout 5000\nGET /checkout;db query 4000\nGET /checkout;db query;parse 1000\n"
+        );
+
+        let profile = speedscope(&spans, "t");
+        assert_eq!(profile["shared"]["frames"].as_array().unwrap().len(), 3);
+        assert_eq!(profile["profiles"][0]["endValue"], 10_000);
+    }
+}
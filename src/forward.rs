@@ -0,0 +1,501 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::UnixStream;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::bench::frame;
+use crate::schema::LogEntry;
+use crate::server::LogCallback;
+use crate::spool::{Spool, SpoolMetrics};
+
+/// Longest a batch waits for more logs before it is sent
+const BATCH_WAIT: Duration = Duration::from_secs(1);
+
+/// Longest a request to an OTLP upstream may take
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Upper bound on the wait between attempts to drain the spool
+const MAX_RETRY: Duration = Duration::from_secs(300);
+
+const SPOOL_METRICS: SpoolMetrics = SpoolMetrics {
+    spooled: crate::metrics::record_forward_spooled,
+    dropped: |logs| crate::metrics::record_forward_dropped("spool_full", logs),
+    size: crate::metrics::record_spool_size,
+};
+
+/// Relaying of accepted logs to an upstream (the `[forward]` section of the config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForwardConfig {
+    /// `unix:///path/to/daemon_rs.sock` for another daemon_rs, or the `http(s)://.../v1/logs`
+    /// URL of an OTLP/HTTP receiver
+    pub upstream: String,
+
+    /// Where logs wait while the upstream is unreachable
+    #[serde(default = "default_spool_dir")]
+    pub spool_dir: PathBuf,
+
+    /// Spool size past which the oldest spooled logs are dropped
+    #[serde(default = "default_max_spool_bytes")]
+    pub max_spool_bytes: u64,
+
+    /// Logs sent upstream per request
+    #[serde(default = "default_forward_batch_size")]
+    pub batch_size: usize,
+
+    /// Logs held in memory for the forwarder before new ones are dropped
+    #[serde(default = "default_queue_capacity")]
+    pub queue_capacity: usize,
+
+    /// First wait before retrying an unreachable upstream; doubles up to 5 minutes
+    #[serde(default = "default_retry_secs")]
+    pub retry_secs: u64,
+
+    /// Extra headers for an OTLP upstream, e.g. for authentication
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+fn default_spool_dir() -> PathBuf {
+    PathBuf::from("./spool")
+}
+
+fn default_max_spool_bytes() -> u64 {
+    1024 * 1024 * 1024
+}
+
+fn default_forward_batch_size() -> usize {
+    500
+}
+
+fn default_queue_capacity() -> usize {
+    10_000
+}
+
+fn default_retry_secs() -> u64 {
+    5
+}
+
+impl ForwardConfig {
+    pub fn new(upstream: impl Into<String>) -> Self {
+        Self {
+            upstream: upstream.into(),
+            spool_dir: default_spool_dir(),
+            max_spool_bytes: default_max_spool_bytes(),
+            batch_size: default_forward_batch_size(),
+            queue_capacity: default_queue_capacity(),
+            retry_secs: default_retry_secs(),
+            headers: BTreeMap::new(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        Upstream::parse(&self.upstream)?;
+        if self.max_spool_bytes == 0
+            || self.batch_size == 0
+            || self.queue_capacity == 0
+            || self.retry_secs == 0
+        {
+            bail!(
+                "forward.max_spool_bytes, batch_size, queue_capacity and retry_secs must be greater than 0"
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Where forwarded logs go
+#[derive(Debug, Clone, PartialEq)]
+enum Upstream {
+    /// Another daemon_rs, over its ingest socket
+    Socket(PathBuf),
+    /// An OTLP/HTTP logs endpoint, sent OTLP/JSON
+    Otlp(String),
+}
+
+impl Upstream {
+    fn parse(upstream: &str) -> Result<Self> {
+        if upstream.starts_with("http://") || upstream.starts_with("https://") {
+            return Ok(Self::Otlp(upstream.to_string()));
+        }
+        match upstream.strip_prefix("unix://") {
+            Some(path) if path.starts_with('/') => Ok(Self::Socket(PathBuf::from(path))),
+            _ => bail!(
+                "forward.upstream must be unix:///path/to/socket or an http(s):// OTLP URL: {}",
+                upstream
+            ),
+        }
+    }
+}
+
+/// Relays accepted logs to the upstream from a thread of its own
+pub struct Forwarder {
+    logs: mpsc::Sender<LogEntry>,
+    thread: JoinHandle<()>,
+}
+
+impl Forwarder {
+    /// Start forwarding; logs spooled by an earlier run are sent first
+    pub fn start(config: &ForwardConfig) -> Result<Self> {
+        config.validate()?;
+        let (logs, queue) = mpsc::channel(config.queue_capacity);
+        let mut task = ForwardTask::new(config)?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("Failed to start the forwarder")?;
+        let thread = std::thread::Builder::new()
+            .name("daemon_rs-forward".to_string())
+            .spawn(move || runtime.block_on(task.run(queue)))
+            .context("Failed to start the forwarder")?;
+        info!("Forwarding logs to {}", config.upstream);
+        Ok(Self { logs, thread })
+    }
+
+    /// A log callback that queues every accepted log for the upstream without waiting
+    pub fn on_log(&self) -> LogCallback {
+        let logs = self.logs.clone();
+        std::sync::Arc::new(move |log: &LogEntry| {
+            if logs.try_send(log.clone()).is_err() {
+                crate::metrics::record_forward_dropped("queue_full", 1);
+            }
+        })
+    }
+
+    /// Wait for the queued logs to be sent or spooled; the callbacks from `on_log` must be
+    /// dropped first
+    pub fn finish(self) {
+        let Self { logs, thread } = self;
+        drop(logs);
+        let _ = thread.join();
+    }
+}
+
+struct ForwardTask {
+    upstream: Upstream,
+    batch_size: usize,
+    retry: Duration,
+    client: reqwest::Client,
+    headers: BTreeMap<String, String>,
+    stream: Option<OwnedWriteHalf>,
+    spool: Spool,
+}
+
+impl ForwardTask {
+    fn new(config: &ForwardConfig) -> Result<Self> {
+        let spool = Spool::open(&config.spool_dir, config.max_spool_bytes, SPOOL_METRICS)?;
+        if !spool.is_empty() {
+            info!(
+                "{} spooled batch(es) from an earlier run will be forwarded",
+                spool.len()
+            );
+        }
+        Ok(Self {
+            upstream: Upstream::parse(&config.upstream)?,
+            batch_size: config.batch_size,
+            retry: Duration::from_secs(config.retry_secs),
+            client: reqwest::Client::builder()
+                .timeout(UPSTREAM_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            headers: config.headers.clone(),
+            stream: None,
+            spool,
+        })
+    }
+
+    /// Send or spool logs until every sender is dropped
+    async fn run(&mut self, mut queue: mpsc::Receiver<LogEntry>) {
+        let mut backoff = self.retry;
+        let mut retry_at: Option<Instant> = None;
+        let mut open = true;
+        while open {
+            let mut batch = Vec::new();
+            open = collect(&mut queue, &mut batch, self.batch_size).await;
+            // Batches spooled behind older ones must not push the next retry back
+            if !batch.is_empty() && self.deliver(batch).await.is_err() && retry_at.is_none() {
+                retry_at = Some(Instant::now() + backoff);
+            }
+
+            if self.spool.is_empty() || retry_at.is_some_and(|at| Instant::now() < at) {
+                continue;
+            }
+            match self.drain().await {
+                Ok(()) => {
+                    if retry_at.is_some() {
+                        info!("Upstream reachable again; spooled logs sent");
+                    }
+                    backoff = self.retry;
+                    retry_at = None;
+                }
+                Err(e) => {
+                    warn!(
+                        "Upstream still unreachable, retrying in {:?}: {:#}",
+                        backoff, e
+                    );
+                    retry_at = Some(Instant::now() + backoff);
+                    backoff = (backoff * 2).min(MAX_RETRY);
+                }
+            }
+        }
+    }
+
+    /// Send `batch` upstream, or spool it when the upstream is down or logs are already
+    /// waiting in the spool, so they keep their order
+    async fn deliver(&mut self, batch: Vec<LogEntry>) -> Result<()> {
+        let result = if self.spool.is_empty() {
+            self.send(&batch).await
+        } else {
+            Err(anyhow!("older logs are still spooled"))
+        };
+        if let Err(e) = &result {
+            if self.spool.is_empty() {
+                warn!("Upstream unreachable, spooling logs: {:#}", e);
+            }
+            if let Err(e) = self.spool.append(&batch) {
+                warn!("Failed to spool {} log(s): {:#}", batch.len(), e);
+                crate::metrics::record_forward_dropped("spool_error", batch.len() as u64);
+            }
+        }
+        result
+    }
+
+    /// Send spooled logs, oldest first, until the spool is empty or the upstream fails
+    async fn drain(&mut self) -> Result<()> {
+        while let Some(logs) = self.spool.front()? {
+            self.send(&logs).await?;
+            self.spool.pop_front()?;
+        }
+        Ok(())
+    }
+
+    async fn send(&mut self, logs: &[LogEntry]) -> Result<()> {
+        match &self.upstream {
+            Upstream::Socket(path) => {
+                let mut frames = Vec::new();
+                for log in logs {
+                    frames.extend(frame(&serde_json::to_vec(&wire_entry(log))?));
+                }
+                let stream = match &mut self.stream {
+                    Some(stream) => stream,
+                    stream => {
+                        let (replies, writer) = UnixStream::connect(path)
+                            .await
+                            .with_context(|| format!("Failed to connect to {:?}", path))?
+                            .into_split();
+                        // Unread replies would fill the socket buffer and stall our writes
+                        tokio::spawn(read_replies(replies));
+                        stream.insert(writer)
+                    }
+                };
+                if let Err(e) = stream.write_all(&frames).await {
+                    self.stream = None;
+                    return Err(e).context("Failed to write to the upstream socket");
+                }
+            }
+            Upstream::Otlp(url) => {
+                let mut request = self
+                    .client
+                    .post(url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(to_otlp_logs(logs).to_string());
+                for (name, value) in &self.headers {
+                    request = request.header(name, value);
+                }
+                request.send().await?.error_for_status()?;
+            }
+        }
+        crate::metrics::record_forwarded(logs.len() as u64);
+        Ok(())
+    }
+}
+
+/// Count the error frames the upstream sends back for logs it rejected, until it closes the
+/// connection
+async fn read_replies(mut replies: OwnedReadHalf) {
+    let mut length = [0u8; 4];
+    while replies.read_exact(&mut length).await.is_ok() {
+        let mut body = vec![0u8; u32::from_be_bytes(length) as usize];
+        if replies.read_exact(&mut body).await.is_err() {
+            break;
+        }
+        crate::metrics::record_forward_dropped("rejected", 1);
+    }
+}
+
+/// Take logs until `max` arrived or BATCH_WAIT passed; false once every sender is gone and
+/// the queue is empty
+async fn collect(
+    queue: &mut mpsc::Receiver<LogEntry>,
+    batch: &mut Vec<LogEntry>,
+    max: usize,
+) -> bool {
+    let deadline = tokio::time::Instant::now() + BATCH_WAIT;
+    while batch.len() < max {
+        match tokio::time::timeout_at(deadline, queue.recv()).await {
+            Ok(Some(log)) => batch.push(log),
+            Ok(None) => return false,
+            Err(_) => break,
+        }
+    }
+    true
+}
+
+/// A log as a producer would send it, without the fields the daemon sets
+fn wire_entry(log: &LogEntry) -> Value {
+    let mut entry = Map::new();
+    entry.insert("timestamp".to_string(), json!(log.timestamp));
+    entry.insert("level".to_string(), json!(&*log.level));
+    entry.insert("message".to_string(), json!(log.message));
+    if let Some(service) = &log.service {
+        entry.insert("service".to_string(), json!(&**service));
+    }
+    if let Some(trace_id) = &log.trace_id {
+        entry.insert("traceId".to_string(), json!(trace_id));
+    }
+    if let Some(metadata) = &log.metadata {
+        entry.insert("metadata".to_string(), metadata.clone());
+    }
+    Value::Object(entry)
+}
+
+/// OTLP severity number of a canonical level
+fn severity_number(level: &str) -> u8 {
+    match crate::pipeline::canonical_level(level) {
+        Some("trace") => 1,
+        Some("debug") => 5,
+        Some("info") => 9,
+        Some("warn") => 13,
+        Some("error") => 17,
+        Some("fatal") => 21,
+        _ => 0,
+    }
+}
+
+/// Encode logs as an OTLP/JSON `LogsData` document, one resource per service
+fn to_otlp_logs(logs: &[LogEntry]) -> Value {
+    let mut by_service: BTreeMap<&str, Vec<Value>> = BTreeMap::new();
+    for log in logs {
+        let nanos = chrono::DateTime::parse_from_rfc3339(&log.timestamp)
+            .ok()
+            .and_then(|time| time.timestamp_nanos_opt())
+            .unwrap_or_default();
+        let attributes: Vec<Value> = match &log.metadata {
+            Some(Value::Object(metadata)) => metadata
+                .iter()
+                .map(|(key, value)| {
+                    let value = match value {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    json!({ "key": key, "value": { "stringValue": value } })
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        by_service
+            .entry(log.service.as_deref().unwrap_or("unknown_service"))
+            .or_default()
+            .push(json!({
+                // OTLP/JSON encodes 64-bit integers as strings
+                "timeUnixNano": nanos.to_string(),
+                "severityNumber": severity_number(&log.level),
+                "severityText": &*log.level,
+                "body": { "stringValue": log.message },
+                "traceId": log.trace_id.as_deref().unwrap_or_default(),
+                "attributes": attributes,
+            }));
+    }
+
+    let resource_logs: Vec<Value> = by_service
+        .into_iter()
+        .map(|(service, records)| {
+            json!({
+                "resource": {
+                    "attributes": [{ "key": "service.name", "value": { "stringValue": service } }]
+                },
+                "scopeLogs": [{ "scope": { "name": "daemon_rs" }, "logRecords": records }],
+            })
+        })
+        .collect();
+    json!({ "resourceLogs": resource_logs })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn log(i: usize) -> LogEntry {
+        serde_json::from_value(json!({
+            "timestamp": "2026-01-15T19:00:00Z",
+            "level": "error",
+            "message": format!("m{}", i),
+            "service": "checkout",
+            "metadata": {"attempt": i},
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_spools_while_upstream_is_down_and_drains_in_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket = temp_dir.path().join("upstream.sock");
+        let mut config = ForwardConfig::new(format!("unix://{}", socket.display()));
+        config.spool_dir = temp_dir.path().join("spool");
+        config.validate().unwrap();
+        let mut task = ForwardTask::new(&config).unwrap();
+
+        // Nothing listens yet: both batches wait in the spool, in order
+        assert!(task.deliver(vec![log(0), log(1)]).await.is_err());
+        assert!(task.deliver(vec![log(2)]).await.is_err());
+        assert!(task.drain().await.is_err());
+        drop(task);
+        // A restart picks the spool up again
+        let mut task = ForwardTask::new(&config).unwrap();
+        assert_eq!(task.spool.len(), 2);
+
+        let listener = tokio::net::UnixListener::bind(&socket).unwrap();
+        task.drain().await.unwrap();
+        assert!(task.spool.is_empty());
+        task.deliver(vec![log(3)]).await.unwrap();
+        drop(task);
+
+        let (mut upstream, _) = listener.accept().await.unwrap();
+        let mut received = Vec::new();
+        upstream.read_to_end(&mut received).await.unwrap();
+        let mut messages = Vec::new();
+        let mut rest = received.as_slice();
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let entry: Value = serde_json::from_slice(&rest[4..4 + length]).unwrap();
+            assert!(entry.get("schemaVersion").is_none());
+            messages.push(entry["message"].as_str().unwrap().to_string());
+            rest = &rest[4 + length..];
+        }
+        assert_eq!(messages, ["m0", "m1", "m2", "m3"]);
+
+        // Past max_spool_bytes the oldest batches go first
+        let mut spool = Spool::open(&temp_dir.path().join("small"), 1, SPOOL_METRICS).unwrap();
+        spool.append(&[log(4)]).unwrap();
+        spool.append(&[log(5)]).unwrap();
+        assert_eq!(spool.len(), 1);
+        assert_eq!(spool.front().unwrap().unwrap()[0].message, "m5");
+
+        let otlp = to_otlp_logs(&[log(6)]);
+        let record = &otlp["resourceLogs"][0]["scopeLogs"][0]["logRecords"][0];
+        assert_eq!(record["severityNumber"], 17);
+        assert_eq!(record["timeUnixNano"], "1768503600000000000");
+        assert_eq!(record["attributes"][0]["value"]["stringValue"], "6");
+        assert!(ForwardConfig::new("tcp://upstream:9000")
+            .validate()
+            .is_err());
+    }
+}
@@ -0,0 +1,406 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::bench::frame;
+use crate::config::Config;
+use crate::schema::SchemaValidator;
+use crate::storage::{parse_compression, StorageEngine};
+use crate::trace_storage::{SpanStatus, TraceSpan, TraceStorage};
+
+/// Service names used before falling back to numbered ones
+const SERVICE_NAMES: &[&str] = &[
+    "api-gateway",
+    "checkout",
+    "payments",
+    "inventory",
+    "search",
+    "auth",
+    "notifications",
+    "shipping",
+    "recommendations",
+    "users",
+];
+
+const ROUTES: &[(&str, &str)] = &[
+    ("GET", "/api/products"),
+    ("GET", "/api/products/{id}"),
+    ("POST", "/api/cart"),
+    ("POST", "/api/checkout"),
+    ("GET", "/api/orders/{id}"),
+    ("GET", "/api/search"),
+    ("POST", "/api/login"),
+];
+
+const REGIONS: &[&str] = &["us-east-1", "us-west-2", "eu-west-1", "ap-southeast-1"];
+
+const ERRORS: &[&str] = &[
+    "upstream timed out",
+    "connection refused by database",
+    "payment provider returned 502",
+    "deadlock detected, transaction rolled back",
+];
+
+/// How often progress is printed
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Share of logs that are warnings and debug messages; the rest not errors are `info`
+const WARN_RATE: f64 = 0.05;
+const DEBUG_RATE: f64 = 0.10;
+
+/// Where generated logs go
+#[derive(Debug, Clone)]
+pub enum GenerateTarget {
+    /// Through a running daemon's socket
+    Socket(PathBuf),
+    /// Straight into a storage directory as Parquet files, after the default schema's parsing
+    Storage(PathBuf),
+}
+
+/// Settings of a `generate` run
+#[derive(Debug, Clone)]
+pub struct GenerateConfig {
+    pub count: u64,
+    pub services: usize,
+    /// Fraction of logs at `error` level, from 0.0 to 1.0
+    pub error_rate: f64,
+    /// Timestamps are spread over this window, ending now
+    pub time_span: Duration,
+    /// Store a trace for every log here and link the log to it
+    pub trace_storage: Option<PathBuf>,
+    /// Seed for reproducible data
+    pub seed: Option<u64>,
+}
+
+/// Outcome of a `generate` run
+#[derive(Debug, Default)]
+pub struct GenerateReport {
+    pub logs: u64,
+    pub errors: u64,
+    pub spans: u64,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for GenerateReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Generated {} logs ({} errors)", self.logs, self.errors)?;
+        if self.spans > 0 {
+            write!(f, " and {} spans", self.spans)?;
+        }
+        write!(f, " in {:.2}s", self.elapsed.as_secs_f64())
+    }
+}
+
+/// Produces logs in the default schema that look like a fleet of web services: HTTP request
+/// metadata, latencies, users and regions, with errors at the configured rate
+pub struct LogGenerator {
+    rng: StdRng,
+    services: Vec<String>,
+    error_rate: f64,
+    traces: bool,
+    start: DateTime<Utc>,
+    step_us: f64,
+}
+
+impl LogGenerator {
+    pub fn new(config: &GenerateConfig) -> Result<Self> {
+        if config.services == 0 {
+            bail!("services must be greater than 0");
+        }
+        if !(0.0..=1.0).contains(&config.error_rate) {
+            bail!(
+                "error rate must be between 0.0 and 1.0: {}",
+                config.error_rate
+            );
+        }
+        let services = (0..config.services)
+            .map(|i| match SERVICE_NAMES.get(i) {
+                Some(name) => name.to_string(),
+                None => format!("service-{}", i + 1),
+            })
+            .collect();
+        let span = chrono::Duration::from_std(config.time_span).context("Time span too long")?;
+        Ok(Self {
+            rng: match config.seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            },
+            services,
+            error_rate: config.error_rate,
+            traces: config.trace_storage.is_some(),
+            start: Utc::now() - span,
+            step_us: config.time_span.as_micros() as f64 / config.count.max(1) as f64,
+        })
+    }
+
+    /// The `index`th log, with the spans of its trace when traces are generated
+    pub fn log(&mut self, index: u64) -> (Value, Vec<TraceSpan>) {
+        let timestamp =
+            self.start + chrono::Duration::microseconds((index as f64 * self.step_us) as i64);
+        let service = self.services[self.rng.gen_range(0..self.services.len())].clone();
+        let (method, route) = ROUTES[self.rng.gen_range(0..ROUTES.len())];
+        let region = REGIONS[self.rng.gen_range(0..REGIONS.len())];
+        let user_id = self.rng.gen_range(1..100_000);
+
+        let roll: f64 = self.rng.gen();
+        let (level, status, duration_ms, message) = if roll < self.error_rate {
+            let error = ERRORS[self.rng.gen_range(0..ERRORS.len())];
+            let status = if self.rng.gen_bool(0.5) { 500 } else { 503 };
+            let duration = self.rng.gen_range(1000..5000);
+            (
+                "error",
+                status,
+                duration,
+                format!("{} {} failed: {}", method, route, error),
+            )
+        } else if roll < self.error_rate + WARN_RATE {
+            let duration = self.rng.gen_range(800..3000);
+            (
+                "warn",
+                200,
+                duration,
+                format!("Slow request {} {} took {}ms", method, route, duration),
+            )
+        } else if roll < self.error_rate + WARN_RATE + DEBUG_RATE {
+            let duration = self.rng.gen_range(1..20);
+            (
+                "debug",
+                200,
+                duration,
+                format!("Cache hit for {} {}", method, route),
+            )
+        } else {
+            let status = if self.rng.gen_bool(0.05) { 404 } else { 200 };
+            let duration = self.rng.gen_range(5..400);
+            (
+                "info",
+                status,
+                duration,
+                format!("{} {} {} in {}ms", method, route, status, duration),
+            )
+        };
+
+        let mut log = json!({
+            "timestamp": timestamp.to_rfc3339_opts(SecondsFormat::Micros, true),
+            "level": level,
+            "message": message,
+            "service": service,
+            "metadata": {
+                "http": { "method": method, "route": route, "status": status },
+                "duration_ms": duration_ms,
+                "user_id": user_id,
+                "region": region,
+            }
+        });
+        if !self.traces {
+            return (log, Vec::new());
+        }
+
+        let trace_id = format!("{:032x}", self.rng.gen::<u128>());
+        log["trace_id"] = Value::from(trace_id.clone());
+        let root_id = format!("{:016x}", self.rng.gen::<u64>());
+        let end = timestamp + chrono::Duration::milliseconds(duration_ms);
+        let span_status = match level {
+            "error" => SpanStatus::Error {
+                message: log["message"].as_str().unwrap_or_default().to_string(),
+            },
+            _ => SpanStatus::Ok,
+        };
+        let attributes = |pairs: &[(&str, String)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone()))
+                .collect()
+        };
+
+        // The request, and the database query that takes part of it
+        let query_ms = duration_ms * self.rng.gen_range(20..80) / 100;
+        let query_start = timestamp + chrono::Duration::milliseconds(duration_ms / 10);
+        let spans = vec![
+            TraceSpan {
+                trace_id: trace_id.clone(),
+                span_id: root_id.clone(),
+                parent_span_id: None,
+                name: format!("{} {}", method, route),
+                start_time: timestamp,
+                end_time: end,
+                duration_us: duration_ms as u64 * 1000,
+                attributes: attributes(&[
+                    ("service.name", service.clone()),
+                    ("http.method", method.to_string()),
+                    ("http.route", route.to_string()),
+                    ("http.status_code", status.to_string()),
+                ]),
+                events: Vec::new(),
+                status: span_status,
+            },
+            TraceSpan {
+                trace_id,
+                span_id: format!("{:016x}", self.rng.gen::<u64>()),
+                parent_span_id: Some(root_id),
+                name: "db.query".to_string(),
+                start_time: query_start,
+                end_time: query_start + chrono::Duration::milliseconds(query_ms),
+                duration_us: query_ms as u64 * 1000,
+                attributes: attributes(&[
+                    ("service.name", service),
+                    ("db.system", "postgresql".to_string()),
+                ]),
+                events: Vec::new(),
+                status: SpanStatus::Ok,
+            },
+        ];
+        (log, spans)
+    }
+}
+
+/// Generate `config.count` logs into `target`, printing progress to stderr
+pub fn run(config: &GenerateConfig, target: &GenerateTarget) -> Result<GenerateReport> {
+    let mut generator = LogGenerator::new(config)?;
+    let defaults = Config::default();
+    let compression = parse_compression(&defaults.compression);
+
+    let mut socket = None;
+    let mut storage = None;
+    match target {
+        GenerateTarget::Socket(path) => {
+            socket = Some(
+                UnixStream::connect(path)
+                    .with_context(|| format!("Failed to connect to {:?}", path))?,
+            );
+        }
+        GenerateTarget::Storage(dir) => {
+            storage = Some((
+                StorageEngine::new(
+                    dir.clone(),
+                    compression,
+                    defaults.batch_size,
+                    defaults.rotation_size,
+                )?,
+                SchemaValidator::default_schema()?,
+            ));
+        }
+    }
+    let mut traces = match &config.trace_storage {
+        Some(dir) => Some(TraceStorage::new(
+            dir.clone(),
+            compression,
+            defaults.batch_size,
+        )?),
+        None => None,
+    };
+
+    let start = Instant::now();
+    let mut report = GenerateReport::default();
+    let mut next_progress = start + PROGRESS_INTERVAL;
+    for index in 0..config.count {
+        let (log, spans) = generator.log(index);
+        if log["level"] == "error" {
+            report.errors += 1;
+        }
+        let mut payload = log.to_string().into_bytes();
+        if let Some(stream) = &mut socket {
+            stream
+                .write_all(&frame(&payload))
+                .with_context(|| format!("Failed to send log {}", index))?;
+        }
+        if let Some((storage, validator)) = &mut storage {
+            let entry = validator
+                .parse_fast(&mut payload)
+                .map_err(|e| anyhow::anyhow!("Generated an invalid log: {}", e))?;
+            storage.add_log(entry)?;
+        }
+        if let Some(traces) = &mut traces {
+            report.spans += spans.len() as u64;
+            for span in spans {
+                traces.add_span(span)?;
+            }
+        }
+        report.logs += 1;
+        if Instant::now() >= next_progress {
+            next_progress += PROGRESS_INTERVAL;
+            eprint!(
+                "\rGenerated {} of {} logs ({:.0}%)",
+                report.logs,
+                config.count,
+                report.logs as f64 / config.count as f64 * 100.0
+            );
+        }
+    }
+    if let Some(stream) = &mut socket {
+        stream.flush()?;
+    }
+    if let Some((storage, _)) = &mut storage {
+        storage.flush()?;
+    }
+    if let Some(traces) = &mut traces {
+        traces.flush()?;
+    }
+    if report.logs > 0 {
+        eprintln!();
+    }
+    report.elapsed = start.elapsed();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::QueryEngine;
+    use crate::trace_storage::list_trace_files;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_generate_into_storage_with_traces() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = GenerateConfig {
+            count: 2000,
+            services: 12,
+            error_rate: 0.2,
+            time_span: Duration::from_secs(3600),
+            trace_storage: Some(temp_dir.path().join("traces")),
+            seed: Some(7),
+        };
+
+        // The same seed gives the same logs
+        let (first, _) = LogGenerator::new(&config).unwrap().log(0);
+        let (again, _) = LogGenerator::new(&config).unwrap().log(0);
+        assert_eq!(first["message"], again["message"]);
+        assert_eq!(first["trace_id"], again["trace_id"]);
+
+        let report = run(
+            &config,
+            &GenerateTarget::Storage(temp_dir.path().join("logs")),
+        )
+        .unwrap();
+        assert_eq!((report.logs, report.spans), (2000, 4000));
+        // About a fifth are errors
+        assert!((300..500).contains(&report.errors), "{}", report.errors);
+
+        let query = QueryEngine::new(temp_dir.path().join("logs"));
+        assert_eq!(query.count_logs().unwrap(), 2000);
+        assert!(!list_trace_files(&temp_dir.path().join("traces"))
+            .unwrap()
+            .is_empty());
+
+        let mut generator = LogGenerator::new(&config).unwrap();
+        let services: std::collections::HashSet<String> = (0..500)
+            .map(|i| generator.log(i).0["service"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(services.len(), 12);
+        assert!(services.contains("service-12"));
+        assert!(LogGenerator::new(&GenerateConfig {
+            error_rate: 1.5,
+            ..config
+        })
+        .is_err());
+    }
+}
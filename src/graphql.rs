@@ -0,0 +1,577 @@
+use async_graphql::http::GraphiQLSource;
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, InputObject, Object, Schema, SimpleObject,
+};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{Html, Json};
+use axum::routing::post;
+use axum::Router;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+
+use crate::ai_api::{find_trace, query_traces, ApiState, LogQueryParams, TraceQueryParams};
+use crate::query::LogRecord;
+use crate::trace_index::summarize_trace;
+use crate::trace_storage::{SpanStatus, TraceSpan};
+
+/// Deepest selection accepted, enough for a few levels of `children`
+const MAX_QUERY_DEPTH: usize = 16;
+
+/// Most fields a query may resolve, counting each list field once per item its `limit`
+/// allows
+const MAX_QUERY_COMPLEXITY: usize = 100_000;
+
+/// Results per list field when the query leaves `limit` unset
+const DEFAULT_GRAPHQL_LIMIT: usize = 100;
+
+pub type AgentSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// Build the schema over the same state as the REST API
+pub fn schema(state: ApiState) -> AgentSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(state)
+        .limit_depth(MAX_QUERY_DEPTH)
+        .limit_complexity(MAX_QUERY_COMPLEXITY)
+        .finish()
+}
+
+/// `POST /api/graphql`, to be merged into the authenticated routes
+pub fn router<S>(state: ApiState) -> Router<S> {
+    Router::new()
+        .route("/api/graphql", post(execute))
+        .with_state(schema(state))
+}
+
+async fn execute(
+    State(schema): State<AgentSchema>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    Json(schema.execute(request).await)
+}
+
+/// GraphiQL IDE pointed at `/api/graphql`; tokens go in its headers panel
+pub async fn graphiql() -> Html<String> {
+    Html(GraphiQLSource::build().endpoint("/api/graphql").finish())
+}
+
+fn to_error((_, message): (StatusCode, String)) -> async_graphql::Error {
+    async_graphql::Error::new(message)
+}
+
+#[derive(Debug, Clone, SimpleObject)]
+pub struct Attribute {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, InputObject)]
+pub struct AttributeInput {
+    pub key: String,
+    pub value: String,
+}
+
+fn attributes(map: &HashMap<String, String>) -> Vec<Attribute> {
+    let mut attributes: Vec<Attribute> = map
+        .iter()
+        .map(|(key, value)| Attribute {
+            key: key.clone(),
+            value: value.clone(),
+        })
+        .collect();
+    attributes.sort_by(|a, b| a.key.cmp(&b.key));
+    attributes
+}
+
+/// Trace filters, with the same meaning as the `/api/traces` query parameters
+#[derive(Debug, Default, InputObject)]
+pub struct TraceFilter {
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
+    pub min_duration_ms: Option<u64>,
+    pub has_error: Option<bool>,
+    pub span_name: Option<String>,
+    #[graphql(default)]
+    pub attributes: Vec<AttributeInput>,
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+}
+
+/// Log filters, with the same meaning as the `/api/logs` query parameters
+#[derive(Debug, Default, InputObject)]
+pub struct LogFilterInput {
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
+    pub level: Option<String>,
+    pub service: Option<String>,
+    pub trace_id: Option<String>,
+    pub contains: Option<String>,
+    pub filter: Option<String>,
+    pub limit: Option<usize>,
+}
+
+impl From<LogFilterInput> for LogQueryParams {
+    fn from(input: LogFilterInput) -> Self {
+        Self {
+            start_time: input.start_time,
+            end_time: input.end_time,
+            level: input.level,
+            service: input.service,
+            trace_id: input.trace_id,
+            contains: input.contains,
+            filter: input.filter,
+            limit: input.limit.unwrap_or(DEFAULT_GRAPHQL_LIMIT),
+        }
+    }
+}
+
+async fn query_logs(state: &ApiState, params: LogQueryParams) -> async_graphql::Result<Vec<Log>> {
+    let filter = params.to_filter()?;
+    let logs = state.query_engine.query(&filter, params.limit).await?.logs;
+    Ok(logs.into_iter().map(Log).collect())
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Traces matching the filter, newest first
+    #[graphql(complexity = "filter
+        .limit
+        .unwrap_or(DEFAULT_GRAPHQL_LIMIT)
+        .saturating_mul(child_complexity)")]
+    async fn traces(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: TraceFilter,
+    ) -> async_graphql::Result<TraceConnection> {
+        let state = ctx.data::<ApiState>()?;
+        let params = TraceQueryParams {
+            start_time: filter.start_time,
+            end_time: filter.end_time,
+            min_duration_ms: filter.min_duration_ms,
+            has_error: filter.has_error,
+            limit: filter.limit.unwrap_or(DEFAULT_GRAPHQL_LIMIT),
+            cursor: filter.cursor,
+            offset: None,
+            span_name: filter.span_name,
+            attributes: filter
+                .attributes
+                .into_iter()
+                .map(|a| (a.key, a.value))
+                .collect(),
+        };
+
+        let query_state = state.clone();
+        let response = tokio::task::spawn_blocking(move || query_traces(&query_state, &params))
+            .await?
+            .map_err(to_error)?;
+        // Read the logs of every listed trace at once rather than once per trace
+        let mut logs = if ctx.look_ahead().field("traces").field("logs").exists() {
+            let trace_ids: BTreeSet<String> = response
+                .traces
+                .iter()
+                .map(|summary| summary.trace_id.clone())
+                .collect();
+            Some(
+                state
+                    .query_engine
+                    .query_traces(&trace_ids, DEFAULT_GRAPHQL_LIMIT)
+                    .await?,
+            )
+        } else {
+            None
+        };
+        Ok(TraceConnection {
+            traces: response
+                .traces
+                .into_iter()
+                .map(|summary| Trace {
+                    logs: logs
+                        .as_mut()
+                        .map(|logs| logs.remove(&summary.trace_id).unwrap_or_default()),
+                    summary,
+                    spans: Default::default(),
+                })
+                .collect(),
+            total_count: response.total_count,
+            next_cursor: response.next_cursor,
+        })
+    }
+
+    /// A single trace, or null when it is not stored
+    async fn trace(&self, ctx: &Context<'_>, id: String) -> async_graphql::Result<Option<Trace>> {
+        let state = ctx.data::<ApiState>()?;
+        let spans = match find_trace(state, &id).await {
+            Ok(spans) => spans,
+            Err((StatusCode::NOT_FOUND, _)) => return Ok(None),
+            Err(e) => return Err(to_error(e)),
+        };
+
+        Ok(summarize_trace(&id, &spans).map(|summary| Trace {
+            summary,
+            spans: tokio::sync::OnceCell::new_with(Some(spans.into())),
+            logs: None,
+        }))
+    }
+
+    /// Stored logs matching the filter, newest first
+    #[graphql(complexity = "filter
+        .limit
+        .unwrap_or(DEFAULT_GRAPHQL_LIMIT)
+        .saturating_mul(child_complexity)")]
+    async fn logs(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: LogFilterInput,
+    ) -> async_graphql::Result<Vec<Log>> {
+        query_logs(ctx.data::<ApiState>()?, filter.into()).await
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct TraceConnection {
+    pub traces: Vec<Trace>,
+    /// Matching traces across all pages
+    pub total_count: usize,
+    /// Pass as `filter.cursor` to fetch the next page
+    pub next_cursor: Option<String>,
+}
+
+pub struct Trace {
+    summary: crate::trace_index::TraceSummary,
+    /// Loaded on first use, so listings that only select summary fields stay cheap
+    spans: tokio::sync::OnceCell<Arc<[TraceSpan]>>,
+    /// Up to `DEFAULT_GRAPHQL_LIMIT` of its newest logs, when read along with other traces
+    logs: Option<Vec<LogRecord>>,
+}
+
+impl Trace {
+    async fn load_spans(&self, state: &ApiState) -> async_graphql::Result<Arc<[TraceSpan]>> {
+        self.spans
+            .get_or_try_init(|| async {
+                find_trace(state, &self.summary.trace_id)
+                    .await
+                    .map(Into::into)
+                    .map_err(to_error)
+            })
+            .await
+            .cloned()
+    }
+}
+
+#[Object]
+impl Trace {
+    async fn id(&self) -> &str {
+        &self.summary.trace_id
+    }
+
+    async fn root_span_name(&self) -> &str {
+        &self.summary.root_span_name
+    }
+
+    async fn start_time(&self) -> &str {
+        &self.summary.start_time
+    }
+
+    async fn duration_ms(&self) -> f64 {
+        self.summary.total_duration_ms
+    }
+
+    async fn span_count(&self) -> usize {
+        self.summary.span_count
+    }
+
+    async fn error_count(&self) -> usize {
+        self.summary.error_count
+    }
+
+    /// Every span of the trace, optionally only those with the given name
+    async fn spans(
+        &self,
+        ctx: &Context<'_>,
+        name: Option<String>,
+    ) -> async_graphql::Result<Vec<Span>> {
+        let trace = self.load_spans(ctx.data::<ApiState>()?).await?;
+        Ok((0..trace.len())
+            .filter(|&i| name.as_ref().is_none_or(|n| trace[i].name == *n))
+            .map(|index| Span {
+                trace: trace.clone(),
+                index,
+            })
+            .collect())
+    }
+
+    /// Spans without a stored parent; select `children` to walk the tree
+    async fn root_spans(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Span>> {
+        let trace = self.load_spans(ctx.data::<ApiState>()?).await?;
+        Ok((0..trace.len())
+            .filter(|&i| {
+                trace[i]
+                    .parent_span_id
+                    .as_ref()
+                    .is_none_or(|parent| !trace.iter().any(|s| s.span_id == *parent))
+            })
+            .map(|index| Span {
+                trace: trace.clone(),
+                index,
+            })
+            .collect())
+    }
+
+    /// Logs correlated with this trace
+    #[graphql(complexity = "limit
+        .unwrap_or(DEFAULT_GRAPHQL_LIMIT)
+        .saturating_mul(child_complexity)")]
+    async fn logs(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<usize>,
+    ) -> async_graphql::Result<Vec<Log>> {
+        let wanted = limit.unwrap_or(DEFAULT_GRAPHQL_LIMIT);
+        if let Some(logs) = self
+            .logs
+            .as_ref()
+            .filter(|_| wanted <= DEFAULT_GRAPHQL_LIMIT)
+        {
+            return Ok(logs.iter().take(wanted).cloned().map(Log).collect());
+        }
+        let params = LogFilterInput {
+            trace_id: Some(self.summary.trace_id.clone()),
+            limit,
+            ..Default::default()
+        };
+        query_logs(ctx.data::<ApiState>()?, params.into()).await
+    }
+}
+
+/// One span, resolved against the rest of its trace for `parent` and `children`
+pub struct Span {
+    trace: Arc<[TraceSpan]>,
+    index: usize,
+}
+
+impl Span {
+    fn span(&self) -> &TraceSpan {
+        &self.trace[self.index]
+    }
+}
+
+#[Object]
+impl Span {
+    async fn span_id(&self) -> &str {
+        &self.span().span_id
+    }
+
+    async fn parent_span_id(&self) -> Option<&str> {
+        self.span().parent_span_id.as_deref()
+    }
+
+    async fn name(&self) -> &str {
+        &self.span().name
+    }
+
+    async fn start_time(&self) -> String {
+        self.span().start_time.to_rfc3339()
+    }
+
+    async fn end_time(&self) -> String {
+        self.span().end_time.to_rfc3339()
+    }
+
+    async fn duration_ms(&self) -> f64 {
+        self.span().duration_us as f64 / 1000.0
+    }
+
+    /// `OK` or `ERROR`
+    async fn status(&self) -> &str {
+        match self.span().status {
+            SpanStatus::Ok => "OK",
+            SpanStatus::Error { .. } => "ERROR",
+        }
+    }
+
+    async fn error_message(&self) -> Option<&str> {
+        match &self.span().status {
+            SpanStatus::Ok => None,
+            SpanStatus::Error { message } => Some(message),
+        }
+    }
+
+    async fn attributes(&self) -> Vec<Attribute> {
+        attributes(&self.span().attributes)
+    }
+
+    /// Value of a single attribute
+    async fn attribute(&self, key: String) -> Option<&str> {
+        self.span().attributes.get(&key).map(String::as_str)
+    }
+
+    async fn events(&self) -> Vec<SpanEvent> {
+        self.span()
+            .events
+            .iter()
+            .map(|event| SpanEvent {
+                name: event.name.clone(),
+                timestamp: event.timestamp.to_rfc3339(),
+                attributes: attributes(&event.attributes),
+            })
+            .collect()
+    }
+
+    async fn parent(&self) -> Option<Span> {
+        let parent = self.span().parent_span_id.as_ref()?;
+        let index = self.trace.iter().position(|s| s.span_id == *parent)?;
+        Some(Span {
+            trace: self.trace.clone(),
+            index,
+        })
+    }
+
+    async fn children(&self) -> Vec<Span> {
+        let span_id = &self.span().span_id;
+        (0..self.trace.len())
+            .filter(|&i| i != self.index && self.trace[i].parent_span_id.as_ref() == Some(span_id))
+            .map(|index| Span {
+                trace: self.trace.clone(),
+                index,
+            })
+            .collect()
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct SpanEvent {
+    pub name: String,
+    pub timestamp: String,
+    pub attributes: Vec<Attribute>,
+}
+
+pub struct Log(LogRecord);
+
+#[Object]
+impl Log {
+    async fn timestamp(&self) -> &str {
+        &self.0.timestamp
+    }
+
+    async fn level(&self) -> &str {
+        &self.0.level
+    }
+
+    async fn message(&self) -> &str {
+        &self.0.message
+    }
+
+    async fn service(&self) -> Option<&str> {
+        self.0.service.as_deref()
+    }
+
+    async fn trace_id(&self) -> Option<&str> {
+        self.0.trace_id.as_deref()
+    }
+
+    async fn metadata(&self) -> Option<async_graphql::Json<&serde_json::Value>> {
+        self.0.metadata.as_ref().map(async_graphql::Json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::federation::FederatedQuery;
+    use crate::live::LiveFeed;
+    use crate::status::DaemonStatus;
+    use crate::storage::StorageEngine;
+    use crate::trace_index::TraceIndex;
+    use crate::trace_storage::TraceStorage;
+    use chrono::Utc;
+    use parquet::basic::Compression;
+    use tempfile::TempDir;
+
+    fn span(span_id: &str, parent: Option<&str>, name: &str) -> TraceSpan {
+        let now = Utc::now();
+        TraceSpan {
+            trace_id: "t1".to_string(),
+            span_id: span_id.to_string(),
+            parent_span_id: parent.map(str::to_string),
+            name: name.to_string(),
+            start_time: now,
+            end_time: now,
+            duration_us: 2000,
+            attributes: HashMap::new(),
+            events: Vec::new(),
+            status: SpanStatus::Ok,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_nested_selection_walks_span_tree() {
+        let traces = TempDir::new().unwrap();
+        let logs = TempDir::new().unwrap();
+        let mut storage =
+            TraceStorage::new(traces.path().to_path_buf(), Compression::SNAPPY, 100).unwrap();
+        storage.add_span(span("root", None, "checkout")).unwrap();
+        storage
+            .add_span(span("db", Some("root"), "db.query"))
+            .unwrap();
+        storage.flush().unwrap();
+        let mut log_storage =
+            StorageEngine::new(logs.path().to_path_buf(), Compression::SNAPPY, 100, 1 << 20)
+                .unwrap();
+        for trace_id in ["t1", "t2"] {
+            let log = serde_json::from_value(serde_json::json!({
+                "timestamp": Utc::now().to_rfc3339(),
+                "level": "info",
+                "message": format!("in {}", trace_id),
+                "traceId": trace_id,
+            }))
+            .unwrap();
+            log_storage.add_log(log).unwrap();
+        }
+        log_storage.flush().unwrap();
+
+        let index = Arc::new(TraceIndex::new(traces.path().to_path_buf()));
+        index.refresh().unwrap();
+        let state = ApiState {
+            trace_storage_dir: traces.path().to_path_buf(),
+            log_storage_dir: logs.path().to_path_buf(),
+            index,
+            query_engine: Arc::new(FederatedQuery::local(logs.path().to_path_buf())),
+            live: LiveFeed::new(),
+            status: DaemonStatus::new(),
+            schema_registry: None,
+        };
+
+        let schema = schema(state);
+        let response = schema
+            .execute(
+                r#"{
+                    traces { totalCount traces { id spanCount logs { message } } }
+                    trace(id: "t1") { rootSpans { name children { name parent { spanId } } } }
+                    missing: trace(id: "nope") { id }
+                }"#,
+            )
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+        let data = response.data.into_json().unwrap();
+        assert_eq!(data["traces"]["totalCount"], 1);
+        assert_eq!(data["traces"]["traces"][0]["spanCount"], 2);
+        assert_eq!(
+            data["traces"]["traces"][0]["logs"],
+            serde_json::json!([{ "message": "in t1" }])
+        );
+        let root = &data["trace"]["rootSpans"][0];
+        assert_eq!(root["name"], "checkout");
+        assert_eq!(root["children"][0]["name"], "db.query");
+        assert_eq!(root["children"][0]["parent"]["spanId"], "root");
+        assert!(data["missing"].is_null());
+
+        // Each listed trace may bring its logs, so large limits multiply
+        let response = schema
+            .execute(r#"{ traces(filter: { limit: 1000 }) { traces { logs { message } } } }"#)
+            .await;
+        assert!(!response.errors.is_empty());
+    }
+}
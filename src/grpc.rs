@@ -0,0 +1,484 @@
+// tonic::Status is fixed by the generated service traits
+#![allow(clippy::result_large_err)]
+
+use axum::http::StatusCode;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status};
+
+use crate::ai_api::{find_trace, query_traces, ApiState, LogQueryParams, TraceQueryParams};
+use crate::api_auth::{ApiAuth, ApiScope};
+use crate::query::LogRecord;
+use crate::trace_storage::{SpanStatus, TraceSpan};
+
+/// Messages and service of `proto/agent.proto`
+pub mod proto {
+    use std::collections::HashMap;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TraceQuery {
+        #[prost(string, optional, tag = "1")]
+        pub start_time: Option<String>,
+        #[prost(string, optional, tag = "2")]
+        pub end_time: Option<String>,
+        #[prost(uint64, optional, tag = "3")]
+        pub min_duration_ms: Option<u64>,
+        #[prost(bool, optional, tag = "4")]
+        pub has_error: Option<bool>,
+        #[prost(uint32, tag = "5")]
+        pub limit: u32,
+        #[prost(string, optional, tag = "6")]
+        pub cursor: Option<String>,
+        #[prost(string, optional, tag = "7")]
+        pub span_name: Option<String>,
+        #[prost(map = "string, string", tag = "8")]
+        pub attributes: HashMap<String, String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TraceSummary {
+        #[prost(string, tag = "1")]
+        pub trace_id: String,
+        #[prost(string, tag = "2")]
+        pub root_span_name: String,
+        #[prost(string, tag = "3")]
+        pub start_time: String,
+        #[prost(double, tag = "4")]
+        pub total_duration_ms: f64,
+        #[prost(uint64, tag = "5")]
+        pub span_count: u64,
+        #[prost(uint64, tag = "6")]
+        pub error_count: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GetTraceRequest {
+        #[prost(string, tag = "1")]
+        pub trace_id: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SpanEvent {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, tag = "2")]
+        pub timestamp: String,
+        #[prost(map = "string, string", tag = "3")]
+        pub attributes: HashMap<String, String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Span {
+        #[prost(string, tag = "1")]
+        pub trace_id: String,
+        #[prost(string, tag = "2")]
+        pub span_id: String,
+        #[prost(string, optional, tag = "3")]
+        pub parent_span_id: Option<String>,
+        #[prost(string, tag = "4")]
+        pub name: String,
+        #[prost(string, tag = "5")]
+        pub start_time: String,
+        #[prost(string, tag = "6")]
+        pub end_time: String,
+        #[prost(uint64, tag = "7")]
+        pub duration_us: u64,
+        #[prost(map = "string, string", tag = "8")]
+        pub attributes: HashMap<String, String>,
+        #[prost(message, repeated, tag = "9")]
+        pub events: Vec<SpanEvent>,
+        #[prost(string, optional, tag = "10")]
+        pub error_message: Option<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Trace {
+        #[prost(string, tag = "1")]
+        pub trace_id: String,
+        #[prost(message, repeated, tag = "2")]
+        pub spans: Vec<Span>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct LogQuery {
+        #[prost(string, optional, tag = "1")]
+        pub start_time: Option<String>,
+        #[prost(string, optional, tag = "2")]
+        pub end_time: Option<String>,
+        #[prost(string, optional, tag = "3")]
+        pub level: Option<String>,
+        #[prost(string, optional, tag = "4")]
+        pub service: Option<String>,
+        #[prost(string, optional, tag = "5")]
+        pub trace_id: Option<String>,
+        #[prost(string, optional, tag = "6")]
+        pub contains: Option<String>,
+        #[prost(string, optional, tag = "7")]
+        pub filter: Option<String>,
+        #[prost(uint32, tag = "8")]
+        pub limit: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct LogRecord {
+        #[prost(string, tag = "1")]
+        pub timestamp: String,
+        #[prost(string, tag = "2")]
+        pub level: String,
+        #[prost(string, tag = "3")]
+        pub message: String,
+        #[prost(string, optional, tag = "4")]
+        pub service: Option<String>,
+        #[prost(string, optional, tag = "5")]
+        pub trace_id: Option<String>,
+        #[prost(string, optional, tag = "6")]
+        pub metadata_json: Option<String>,
+    }
+
+    include!(concat!(env!("OUT_DIR"), "/daemon_rs.agent.v1.AgentApi.rs"));
+}
+
+use proto::agent_api_server::{AgentApi, AgentApiServer};
+
+/// Results per call when the request leaves `limit` at 0
+const DEFAULT_GRPC_LIMIT: usize = 100;
+
+/// Messages buffered per `QueryLogs` or `TailLogs` call before the producing task waits
+const STREAM_BUFFER: usize = 64;
+
+type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+/// gRPC implementation of the agent query surface, sharing state with the HTTP API
+pub struct AgentService {
+    state: ApiState,
+}
+
+impl AgentService {
+    pub fn new(state: ApiState) -> Self {
+        Self { state }
+    }
+}
+
+fn to_status((code, message): (StatusCode, String)) -> Status {
+    match code {
+        StatusCode::BAD_REQUEST => Status::invalid_argument(message),
+        StatusCode::NOT_FOUND => Status::not_found(message),
+        _ => Status::internal(message),
+    }
+}
+
+fn limit_or_default(limit: u32) -> usize {
+    match limit {
+        0 => DEFAULT_GRPC_LIMIT,
+        n => n as usize,
+    }
+}
+
+impl From<crate::trace_index::TraceSummary> for proto::TraceSummary {
+    fn from(summary: crate::trace_index::TraceSummary) -> Self {
+        Self {
+            trace_id: summary.trace_id,
+            root_span_name: summary.root_span_name,
+            start_time: summary.start_time,
+            total_duration_ms: summary.total_duration_ms,
+            span_count: summary.span_count as u64,
+            error_count: summary.error_count as u64,
+        }
+    }
+}
+
+impl From<TraceSpan> for proto::Span {
+    fn from(span: TraceSpan) -> Self {
+        Self {
+            trace_id: span.trace_id,
+            span_id: span.span_id,
+            parent_span_id: span.parent_span_id,
+            name: span.name,
+            start_time: span.start_time.to_rfc3339(),
+            end_time: span.end_time.to_rfc3339(),
+            duration_us: span.duration_us,
+            attributes: span.attributes,
+            events: span
+                .events
+                .into_iter()
+                .map(|e| proto::SpanEvent {
+                    name: e.name,
+                    timestamp: e.timestamp.to_rfc3339(),
+                    attributes: e.attributes,
+                })
+                .collect(),
+            error_message: match span.status {
+                SpanStatus::Ok => None,
+                SpanStatus::Error { message } => Some(message),
+            },
+        }
+    }
+}
+
+impl From<LogRecord> for proto::LogRecord {
+    fn from(record: LogRecord) -> Self {
+        Self {
+            timestamp: record.timestamp,
+            level: record.level,
+            message: record.message,
+            service: record.service,
+            trace_id: record.trace_id,
+            metadata_json: record.metadata.map(|m| m.to_string()),
+        }
+    }
+}
+
+impl From<proto::LogQuery> for LogQueryParams {
+    fn from(query: proto::LogQuery) -> Self {
+        Self {
+            start_time: query.start_time,
+            end_time: query.end_time,
+            level: query.level,
+            service: query.service,
+            trace_id: query.trace_id,
+            contains: query.contains,
+            filter: query.filter,
+            limit: limit_or_default(query.limit),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl AgentApi for AgentService {
+    type ListTracesStream = ResponseStream<proto::TraceSummary>;
+    type QueryLogsStream = ResponseStream<proto::LogRecord>;
+    type TailLogsStream = ResponseStream<proto::LogRecord>;
+
+    async fn list_traces(
+        &self,
+        request: Request<proto::TraceQuery>,
+    ) -> Result<Response<Self::ListTracesStream>, Status> {
+        let query = request.into_inner();
+        let mut params = TraceQueryParams {
+            start_time: query.start_time,
+            end_time: query.end_time,
+            min_duration_ms: query.min_duration_ms,
+            has_error: query.has_error,
+            limit: limit_or_default(query.limit),
+            cursor: query.cursor,
+            offset: None,
+            span_name: query.span_name,
+            attributes: query.attributes.into_iter().collect(),
+        };
+
+        params
+            .time_range()
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        // Traces are sent a page at a time, each page looked up once the last one is sent
+        let (tx, rx) = tokio::sync::mpsc::channel(STREAM_BUFFER);
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            let mut remaining = params.limit;
+            while remaining > 0 {
+                params.limit = remaining.min(STREAM_BUFFER);
+                let (page_state, page_params) = (state.clone(), params.clone());
+                let page = tokio::task::spawn_blocking(move || {
+                    query_traces(&page_state, &page_params).map_err(to_status)
+                })
+                .await
+                .unwrap_or_else(|e| Err(Status::internal(e.to_string())));
+                let page = match page {
+                    Ok(page) => page,
+                    Err(status) => {
+                        let _ = tx.send(Err(status)).await;
+                        return;
+                    }
+                };
+                remaining -= page.traces.len().min(remaining);
+                for summary in page.traces {
+                    if tx.send(Ok(summary.into())).await.is_err() {
+                        return;
+                    }
+                }
+                match page.next_cursor {
+                    Some(cursor) => params.cursor = Some(cursor),
+                    None => return,
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    async fn get_trace(
+        &self,
+        request: Request<proto::GetTraceRequest>,
+    ) -> Result<Response<proto::Trace>, Status> {
+        let trace_id = request.into_inner().trace_id;
+        let spans = find_trace(&self.state, &trace_id)
+            .await
+            .map_err(to_status)?;
+
+        Ok(Response::new(proto::Trace {
+            trace_id,
+            spans: spans.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn query_logs(
+        &self,
+        request: Request<proto::LogQuery>,
+    ) -> Result<Response<Self::QueryLogsStream>, Status> {
+        let params = LogQueryParams::from(request.into_inner());
+        let filter = params
+            .to_filter()
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        // Logs are sent as the sources produce them; the query stops when the client goes away
+        let (tx, rx) = tokio::sync::mpsc::channel(STREAM_BUFFER);
+        let query_engine = self.state.query_engine.clone();
+        tokio::spawn(async move {
+            query_engine.stream(&filter, params.limit, tx).await;
+        });
+        let stream = ReceiverStream::new(rx).map(|record| Ok(record.into()));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn tail_logs(
+        &self,
+        request: Request<proto::LogQuery>,
+    ) -> Result<Response<Self::TailLogsStream>, Status> {
+        let filter = LogQueryParams::from(request.into_inner())
+            .to_filter()
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let mut logs = self.state.live.subscribe_logs();
+
+        // The forwarding task ends when the client disconnects and a send fails
+        let (tx, rx) = tokio::sync::mpsc::channel(STREAM_BUFFER);
+        tokio::spawn(async move {
+            loop {
+                let record = match logs.recv().await {
+                    Ok(log) => LogRecord::from_entry(&log),
+                    Err(RecvError::Lagged(missed)) => {
+                        let status = Status::data_loss(format!(
+                            "Tail fell behind and skipped {} logs",
+                            missed
+                        ));
+                        let _ = tx.send(Err(status)).await;
+                        break;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if filter.matches(&record) && tx.send(Ok(record.into())).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+}
+
+/// Reject calls without a known token, mirroring the HTTP API's bearer auth
+fn check_token(auth: &ApiAuth, request: Request<()>) -> Result<Request<()>, Status> {
+    if !auth.is_enabled() {
+        return Ok(request);
+    }
+
+    let metadata = request.metadata();
+    let token = metadata
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| metadata.get("x-api-key").and_then(|v| v.to_str().ok()))
+        .map(str::trim);
+
+    // Every gRPC call is a read, so any known token is sufficient
+    match token.and_then(|t| auth.scope_for(t)) {
+        Some(scope) if scope >= ApiScope::Read => Ok(request),
+        _ => Err(Status::unauthenticated("Missing or invalid API token")),
+    }
+}
+
+/// The gRPC agent API as an axum router, so it is served with the HTTP API's TLS and limits
+pub fn router(state: ApiState, auth: Arc<ApiAuth>) -> axum::Router {
+    let service = AgentApiServer::with_interceptor(AgentService::new(state), move |request| {
+        check_token(&auth, request)
+    });
+    tonic::service::Routes::new(service).into_axum_router()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::federation::FederatedQuery;
+    use crate::live::LiveFeed;
+    use crate::status::DaemonStatus;
+    use crate::trace_index::TraceIndex;
+    use crate::trace_storage::TraceStorage;
+    use chrono::Utc;
+    use parquet::basic::Compression;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_lists_and_fetches_stored_traces() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut storage =
+            TraceStorage::new(temp_dir.path().to_path_buf(), Compression::SNAPPY, 100).unwrap();
+        let now = Utc::now();
+        storage
+            .add_span(TraceSpan {
+                trace_id: "t1".to_string(),
+                span_id: "root".to_string(),
+                parent_span_id: None,
+                name: "checkout".to_string(),
+                start_time: now,
+                end_time: now,
+                duration_us: 2000,
+                attributes: Default::default(),
+                events: Vec::new(),
+                status: SpanStatus::Error {
+                    message: "boom".to_string(),
+                },
+            })
+            .unwrap();
+        storage.flush().unwrap();
+
+        let index = Arc::new(TraceIndex::new(temp_dir.path().to_path_buf()));
+        index.refresh().unwrap();
+        let service = AgentService::new(ApiState {
+            trace_storage_dir: temp_dir.path().to_path_buf(),
+            log_storage_dir: temp_dir.path().join("logs"),
+            index,
+            query_engine: Arc::new(FederatedQuery::local(temp_dir.path().join("logs"))),
+            live: LiveFeed::new(),
+            status: DaemonStatus::new(),
+            schema_registry: None,
+        });
+
+        let listed: Vec<_> = service
+            .list_traces(Request::new(proto::TraceQuery::default()))
+            .await
+            .unwrap()
+            .into_inner()
+            .collect()
+            .await;
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].as_ref().unwrap().error_count, 1);
+
+        let trace = service
+            .get_trace(Request::new(proto::GetTraceRequest {
+                trace_id: "t1".to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(trace.spans[0].error_message.as_deref(), Some("boom"));
+
+        let missing = service
+            .get_trace(Request::new(proto::GetTraceRequest {
+                trace_id: "nope".to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(missing.code(), tonic::Code::NotFound);
+    }
+}
@@ -0,0 +1,355 @@
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixListener;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info, warn};
+
+use crate::server::ServerControl;
+
+/// Listening sockets passed to the new process, as comma-separated `name=fd` pairs
+pub const LISTEN_FDS_ENV: &str = "DAEMON_RS_LISTEN_FDS";
+
+/// Pipe the new process writes a byte to once it accepts connections
+pub const READY_FD_ENV: &str = "DAEMON_RS_READY_FD";
+
+/// How long the old process waits for the new one to accept connections
+pub const UPGRADE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Name of the ingest socket's listener
+pub const INGEST: &str = "ingest";
+
+/// What the previous process passed down and is not claimed yet
+#[derive(Default)]
+struct Inherited {
+    listeners: BTreeMap<String, OwnedFd>,
+    ready: Option<OwnedFd>,
+}
+
+fn inherited() -> MutexGuard<'static, Inherited> {
+    static INHERITED: OnceLock<Mutex<Inherited>> = OnceLock::new();
+    INHERITED
+        .get_or_init(|| Mutex::new(inherit_from_env()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Copies of the listeners in use, passed on at the next upgrade
+fn active() -> MutexGuard<'static, BTreeMap<String, OwnedFd>> {
+    static ACTIVE: OnceLock<Mutex<BTreeMap<String, OwnedFd>>> = OnceLock::new();
+    ACTIVE
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Parse `name=fd` pairs separated by commas
+pub fn parse_fds(spec: &str) -> Result<Vec<(String, RawFd)>> {
+    spec.split(',')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, fd) = pair
+                .split_once('=')
+                .with_context(|| format!("Expected name=fd, got {:?}", pair))?;
+            let fd = fd
+                .parse()
+                .with_context(|| format!("Invalid file descriptor in {:?}", pair))?;
+            Ok((name.to_string(), fd))
+        })
+        .collect()
+}
+
+/// Take ownership of the descriptors named in the environment; any that are not what they claim
+/// to be are left alone
+fn inherit_from_env() -> Inherited {
+    let mut inherited = Inherited::default();
+    if let Ok(spec) = std::env::var(LISTEN_FDS_ENV) {
+        match parse_fds(&spec) {
+            Ok(fds) => {
+                for (name, fd) in fds {
+                    match claim_fd(fd, libc::S_IFSOCK) {
+                        Ok(fd) => {
+                            inherited.listeners.insert(name, fd);
+                        }
+                        Err(e) => warn!("Ignoring inherited {} listener {}: {:#}", name, fd, e),
+                    }
+                }
+            }
+            Err(e) => warn!("Ignoring {}: {:#}", LISTEN_FDS_ENV, e),
+        }
+    }
+    if let Ok(fd) = std::env::var(READY_FD_ENV) {
+        match fd.parse().map_err(anyhow::Error::from) {
+            Ok(fd) => match claim_fd(fd, libc::S_IFIFO) {
+                Ok(fd) => inherited.ready = Some(fd),
+                Err(e) => warn!("Ignoring {}: {:#}", READY_FD_ENV, e),
+            },
+            Err(e) => warn!("Ignoring {}: {:#}", READY_FD_ENV, e),
+        }
+    }
+    inherited
+}
+
+/// Own `fd` if it is open and of file type `kind`, closing it on exec again
+fn claim_fd(fd: RawFd, kind: libc::mode_t) -> Result<OwnedFd> {
+    // SAFETY: fstat only writes into `stat`
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    if stat.st_mode & libc::S_IFMT != kind {
+        bail!("Not the expected kind of file");
+    }
+    set_cloexec(fd, true)?;
+    // SAFETY: the descriptor is open and was passed to this process to own
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+fn set_cloexec(fd: RawFd, cloexec: bool) -> std::io::Result<()> {
+    // SAFETY: fcntl on a descriptor number; async-signal-safe, so usable before exec
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFD);
+        if flags < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let flags = if cloexec {
+            flags | libc::FD_CLOEXEC
+        } else {
+            flags & !libc::FD_CLOEXEC
+        };
+        if libc::fcntl(fd, libc::F_SETFD, flags) < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// The Unix socket listener `name` passed down by the previous process, if it is bound to `path`
+pub fn take_unix(name: &str, path: &Path) -> Option<UnixListener> {
+    let listener = UnixListener::from(inherited().listeners.remove(name)?);
+    match listener.local_addr() {
+        Ok(addr) if addr.as_pathname() == Some(path) => Some(listener),
+        _ => {
+            warn!(
+                "Not reusing the inherited {} socket: it is not bound to {:?}",
+                name, path
+            );
+            None
+        }
+    }
+}
+
+/// The TCP listener `name` passed down by the previous process, if it is bound to `addr`
+pub fn take_tcp(name: &str, addr: SocketAddr) -> Option<std::net::TcpListener> {
+    let listener = std::net::TcpListener::from(inherited().listeners.remove(name)?);
+    match listener.local_addr() {
+        Ok(bound) if bound == addr => Some(listener),
+        _ => {
+            warn!(
+                "Not reusing the inherited {} listener: it is not bound to {}",
+                name, addr
+            );
+            None
+        }
+    }
+}
+
+/// Pass `listener` on as `name` at the next upgrade
+pub fn register(name: &str, listener: impl AsFd) -> Result<()> {
+    let fd = listener
+        .as_fd()
+        .try_clone_to_owned()
+        .with_context(|| format!("Failed to keep a copy of the {} listener", name))?;
+    active().insert(name.to_string(), fd);
+    Ok(())
+}
+
+/// Bind `addr`, or take it over from the previous process, and pass it on at the next upgrade
+pub async fn bind_tcp(name: &str, addr: SocketAddr) -> std::io::Result<tokio::net::TcpListener> {
+    let listener = match take_tcp(name, addr) {
+        Some(listener) => {
+            info!(
+                "Took over the {} listener on {} from the previous process",
+                name, addr
+            );
+            listener.set_nonblocking(true)?;
+            tokio::net::TcpListener::from_std(listener)?
+        }
+        None => tokio::net::TcpListener::bind(addr).await?,
+    };
+    register(name, &listener).map_err(std::io::Error::other)?;
+    Ok(listener)
+}
+
+/// Tell the process that started this one that connections are being accepted; does nothing
+/// after the first call or when not started by an upgrade
+pub fn notify_ready() {
+    if let Some(ready) = inherited().ready.take() {
+        if let Err(e) = std::fs::File::from(ready).write_all(b"1") {
+            warn!(
+                "Failed to tell the previous process this one is ready: {}",
+                e
+            );
+        }
+    }
+}
+
+/// Start a new daemon from the same command line, passing it every listener; returns its pid
+/// once it accepts connections
+pub fn start_successor() -> Result<u32> {
+    let mut args = std::env::args_os();
+    // argv[0] rather than /proc/self/exe, which points at the replaced binary after an upgrade
+    let program = args.next().context("No program name to start")?;
+    let mut command = Command::new(program);
+    command.args(args);
+    spawn_successor(command, UPGRADE_TIMEOUT)
+}
+
+fn spawn_successor(mut command: Command, timeout: Duration) -> Result<u32> {
+    let active = active();
+    if !active.contains_key(INGEST) {
+        bail!("The ingest socket is not listening yet");
+    }
+    let (ready_read, ready_write) = pipe()?;
+    let mut fds = vec![ready_write.as_raw_fd()];
+    let mut spec = Vec::with_capacity(active.len());
+    for (name, fd) in active.iter() {
+        spec.push(format!("{}={}", name, fd.as_raw_fd()));
+        fds.push(fd.as_raw_fd());
+    }
+    command
+        .env(LISTEN_FDS_ENV, spec.join(","))
+        .env(READY_FD_ENV, ready_write.as_raw_fd().to_string());
+    // SAFETY: only clears close-on-exec on descriptors this process keeps open across the fork
+    unsafe {
+        command.pre_exec(move || fds.iter().try_for_each(|&fd| set_cloexec(fd, false)));
+    }
+    let mut child = command.spawn().context("Failed to start the new process")?;
+    drop(active);
+    // Only the child holds the write end now, so its exit ends the wait
+    drop(ready_write);
+
+    if !wait_readable(&ready_read, timeout)? {
+        let _ = child.kill();
+        let _ = child.wait();
+        bail!(
+            "The new process was not ready after {:?}; stopped it",
+            timeout
+        );
+    }
+    let mut byte = [0u8; 1];
+    if std::fs::File::from(ready_read).read(&mut byte)? == 0 {
+        let status = child.wait()?;
+        bail!("The new process exited before it was ready ({})", status);
+    }
+    Ok(child.id())
+}
+
+fn pipe() -> Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    // SAFETY: pipe2 writes two new descriptors into `fds`
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to create a pipe");
+    }
+    // SAFETY: both descriptors were just created and are owned here
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
+
+/// Whether `fd` has data or was closed within `timeout`
+fn wait_readable(fd: &OwnedFd, timeout: Duration) -> Result<bool> {
+    let mut poll = libc::pollfd {
+        fd: fd.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let millis = libc::c_int::try_from(timeout.as_millis()).unwrap_or(libc::c_int::MAX);
+    loop {
+        // SAFETY: polls the one descriptor in `poll`
+        match unsafe { libc::poll(&mut poll, 1, millis) } {
+            0 => return Ok(false),
+            n if n > 0 => return Ok(true),
+            _ => {
+                let e = std::io::Error::last_os_error();
+                if e.kind() != std::io::ErrorKind::Interrupted {
+                    return Err(e).context("Failed to wait for the new process");
+                }
+            }
+        }
+    }
+}
+
+/// On SIGUSR2, start a new daemon with the same command line and listeners, then drain this
+/// one without removing the socket. A failed start leaves this process serving.
+pub fn spawn_upgrade_handler(server: ServerControl) -> Result<()> {
+    let mut upgrades =
+        signal(SignalKind::user_defined2()).context("Failed to listen for SIGUSR2")?;
+    tokio::spawn(async move {
+        while upgrades.recv().await.is_some() {
+            info!("Upgrading after SIGUSR2: starting a new process");
+            match tokio::task::spawn_blocking(start_successor).await {
+                Ok(Ok(pid)) => {
+                    info!("New process {} accepts connections; draining this one", pid);
+                    server.hand_over();
+                    break;
+                }
+                Ok(Err(e)) => error!("Upgrade failed, this process keeps serving: {:#}", e),
+                Err(e) => error!("Upgrade failed, this process keeps serving: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_listeners_pass_to_successor() {
+        assert_eq!(
+            parse_fds("ingest=3,metrics=4").unwrap(),
+            vec![("ingest".to_string(), 3), ("metrics".to_string(), 4)]
+        );
+        assert!(parse_fds("ingest").is_err());
+        assert!(parse_fds("ingest=x").is_err());
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("ingest.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let start = |script: &str| {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(script);
+            spawn_successor(command, Duration::from_secs(10))
+        };
+        register(INGEST, &listener).unwrap();
+
+        // The child finds the listener open under the number given and reports ready
+        let script = format!(
+            r#"fd=$(echo "${}" | tr , '\n' | sed -n 's/^ingest=//p') && test -L /proc/$$/fd/$fd && printf 1 > /proc/$$/fd/${}"#,
+            LISTEN_FDS_ENV, READY_FD_ENV
+        );
+        start(&script).unwrap();
+        let error = start("exit 3").unwrap_err();
+        assert!(error.to_string().contains("exited before"), "{}", error);
+
+        // A listener is only taken over where it is still wanted
+        inherited()
+            .listeners
+            .insert("a".into(), listener.as_fd().try_clone_to_owned().unwrap());
+        inherited()
+            .listeners
+            .insert("b".into(), listener.as_fd().try_clone_to_owned().unwrap());
+        assert!(take_unix("a", &temp_dir.path().join("other.sock")).is_none());
+        let taken = take_unix("b", &path).unwrap();
+        assert_eq!(taken.local_addr().unwrap().as_pathname(), Some(&*path));
+        assert!(take_unix("b", &path).is_none());
+    }
+}
@@ -0,0 +1,449 @@
+use anyhow::{bail, Context, Result};
+use arrow::array::{Array, AsArray, RecordBatch};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Float64Type, Int64Type, TimeUnit, UInt64Type};
+use arrow::util::display::array_value_to_string;
+use chrono::{DateTime, SecondsFormat, Utc};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::checksum;
+use crate::config::Config;
+use crate::field_mapping::{validate_mappings, FieldMapping};
+use crate::file_ingest::for_each_record;
+use crate::pipeline::IngestPipeline;
+use crate::schema::{LogEntry, ParseError, SchemaRouter};
+use crate::schema_reload::SchemaSources;
+use crate::storage::{parse_compression, StorageEngine};
+
+/// Rejected records reported individually; later ones are only counted
+const MAX_REPORTED_ERRORS: u64 = 20;
+
+/// Extensions of the files `import` reads from a directory
+const EXTENSIONS: &[&str] = &["parquet", "jsonl", "ndjson", "json"];
+
+/// Parse a `--map` rule such as `level=severity`: store the source field `severity` as `level`.
+/// Both sides may be dot-separated paths.
+pub fn parse_map(value: &str) -> Result<FieldMapping> {
+    let Some((to, from)) = value.split_once('=') else {
+        bail!(
+            "Invalid mapping {:?}; use target=source, e.g. level=severity",
+            value
+        );
+    };
+    let mapping = FieldMapping::Move {
+        from: from.trim().to_string(),
+        to: to.trim().to_string(),
+    };
+    validate_mappings(std::slice::from_ref(&mapping))?;
+    Ok(mapping)
+}
+
+/// Outcome of an import
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub files: usize,
+    pub imported: u64,
+    /// Records that were not stored, by reason
+    pub rejected: BTreeMap<String, u64>,
+    pub elapsed: Duration,
+}
+
+impl ImportReport {
+    fn reject(&mut self, reason: &str, location: &dyn fmt::Display, detail: impl fmt::Display) {
+        let rejected: u64 = self.rejected.values().sum();
+        if rejected < MAX_REPORTED_ERRORS {
+            eprintln!("{}: {}", location, detail);
+        }
+        *self.rejected.entry(reason.to_string()).or_insert(0) += 1;
+    }
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Imported {} logs from {} file(s) in {:.2}s",
+            self.imported,
+            self.files,
+            self.elapsed.as_secs_f64()
+        )?;
+        if !self.rejected.is_empty() {
+            let reasons: Vec<String> = self
+                .rejected
+                .iter()
+                .map(|(reason, count)| format!("{} {}", count, reason))
+                .collect();
+            write!(f, "; rejected {}", reasons.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parquet, JSONL and JSON files under `paths`, which are files or directories searched
+/// recursively, sorted within each directory
+pub fn find_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            collect_dir(path, &mut files)?;
+        } else if path.is_file() {
+            files.push(path.clone());
+        } else {
+            bail!("No such file or directory: {:?}", path);
+        }
+    }
+    Ok(files)
+}
+
+fn collect_dir(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {:?}", dir))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
+        // The checksums and bad files of another daemon's storage directory
+        let name = path.file_name().and_then(|name| name.to_str());
+        if name == Some(checksum::MANIFEST_FILE) || name == Some(checksum::QUARANTINE_DIR) {
+            continue;
+        }
+        if path.is_dir() {
+            collect_dir(&path, files)?;
+        } else if path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| EXTENSIONS.contains(&ext))
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Store the logs in `files` the way `serve` would: `mappings` are applied before the
+/// configured field mappings, then the schemas, normalization, redaction and enrichment of
+/// `config`. Records that fail are reported on stderr and skipped.
+pub fn import(
+    files: &[PathBuf],
+    config: &Config,
+    mappings: &[FieldMapping],
+) -> Result<ImportReport> {
+    let mut ingest = config.ingest.clone();
+    ingest.field_mappings = mappings
+        .iter()
+        .chain(&config.ingest.field_mappings)
+        .cloned()
+        .collect();
+    let pipeline = IngestPipeline::from_config(&ingest)?;
+    let router = SchemaSources {
+        default: config.schema_path.clone(),
+        services: config.schemas.clone(),
+    }
+    .build()?;
+    let mut storage = StorageEngine::new(
+        config.storage_dir.clone(),
+        parse_compression(&config.compression),
+        config.batch_size,
+        config.rotation_size,
+    )?;
+
+    let start = Instant::now();
+    let mut report = ImportReport::default();
+    for path in files {
+        report.files += 1;
+        let mut store = |record: Result<Value, String>, location: &dyn fmt::Display| {
+            let record = match record {
+                Ok(record) if record.is_object() => record,
+                Ok(_) => {
+                    report.reject("malformed_json", location, "not a JSON object");
+                    return Ok(());
+                }
+                Err(e) => {
+                    report.reject("malformed_json", location, e);
+                    return Ok(());
+                }
+            };
+            match process(&record, &pipeline, &router) {
+                Ok(entry) => {
+                    storage.add_log(entry)?;
+                    report.imported += 1;
+                }
+                Err(e) => report.reject(e.reason.as_str(), location, e),
+            }
+            Ok(())
+        };
+        let result = if path.extension().is_some_and(|ext| ext == "parquet") {
+            for_each_parquet_record(path, &mut store)
+        } else {
+            for_each_record(path, &mut store)
+        };
+        result.with_context(|| format!("Failed to import {:?}", path))?;
+    }
+    storage.flush()?;
+
+    let rejected: u64 = report.rejected.values().sum();
+    if rejected > MAX_REPORTED_ERRORS {
+        eprintln!(
+            "{} more rejected record(s) not shown",
+            rejected - MAX_REPORTED_ERRORS
+        );
+    }
+    report.elapsed = start.elapsed();
+    Ok(report)
+}
+
+/// Map, validate and normalize one record
+fn process(
+    record: &Value,
+    pipeline: &IngestPipeline,
+    router: &SchemaRouter,
+) -> Result<LogEntry, ParseError> {
+    let mut data = serde_json::to_vec(record).expect("a JSON value serializes");
+    if let Some(mapped) = pipeline.map_fields(&data) {
+        data = mapped?;
+    }
+    let mut entry = router.parse_fast(&mut data)?;
+    pipeline.process(&mut entry, None)?;
+    Ok(entry)
+}
+
+/// Call `handle` with each row of the Parquet file at `path` as a JSON object
+fn for_each_parquet_record(
+    path: &Path,
+    handle: &mut impl FnMut(Result<Value, String>, &dyn fmt::Display) -> Result<()>,
+) -> Result<()> {
+    let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let reader = ParquetRecordBatchReaderBuilder::try_new(file)?.build()?;
+    let mut row_number = 0;
+    for batch in reader {
+        let batch = batch?;
+        for row in batch_to_json(&batch)? {
+            row_number += 1;
+            let location = format!("{} row {}", path.display(), row_number);
+            handle(Ok(row), &location)?;
+        }
+    }
+    Ok(())
+}
+
+/// Rows of `batch` as JSON objects without their null columns. Timestamps become RFC 3339,
+/// strings holding a JSON object or array are parsed, and other nested types are written as
+/// their display form.
+fn batch_to_json(batch: &RecordBatch) -> Result<Vec<Value>> {
+    let mut rows = vec![Map::new(); batch.num_rows()];
+    let schema = batch.schema();
+    for (field, column) in schema.fields().iter().zip(batch.columns()) {
+        let values = column_to_json(column.as_ref())?;
+        for (row, value) in rows.iter_mut().zip(values) {
+            if let Some(value) = value {
+                row.insert(field.name().clone(), value);
+            }
+        }
+    }
+    Ok(rows.into_iter().map(Value::Object).collect())
+}
+
+fn column_to_json(column: &dyn Array) -> Result<Vec<Option<Value>>> {
+    let present = |i: usize| column.is_valid(i);
+    let values = match column.data_type() {
+        DataType::Null => vec![None; column.len()],
+        DataType::Boolean => {
+            let array = column.as_boolean();
+            (0..array.len())
+                .map(|i| present(i).then(|| Value::from(array.value(i))))
+                .collect()
+        }
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64 => {
+            let array = cast(column, &DataType::Int64)?;
+            let array = array.as_primitive::<Int64Type>();
+            (0..array.len())
+                .map(|i| present(i).then(|| Value::from(array.value(i))))
+                .collect()
+        }
+        DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64 => {
+            let array = cast(column, &DataType::UInt64)?;
+            let array = array.as_primitive::<UInt64Type>();
+            (0..array.len())
+                .map(|i| present(i).then(|| Value::from(array.value(i))))
+                .collect()
+        }
+        DataType::Float16 | DataType::Float32 | DataType::Float64 => {
+            let array = cast(column, &DataType::Float64)?;
+            let array = array.as_primitive::<Float64Type>();
+            (0..array.len())
+                .map(|i| present(i).then(|| Value::from(array.value(i))))
+                .collect()
+        }
+        DataType::Timestamp(unit, _) => {
+            let array = cast(column, &DataType::Int64)?;
+            let array = array.as_primitive::<Int64Type>();
+            (0..array.len())
+                .map(|i| {
+                    present(i)
+                        .then(|| timestamp(array.value(i), unit))
+                        .flatten()
+                        .map(|dt| Value::from(dt.to_rfc3339_opts(SecondsFormat::AutoSi, true)))
+                })
+                .collect()
+        }
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => {
+            let array = cast(column, &DataType::Utf8)?;
+            let array = array.as_string::<i32>();
+            (0..array.len())
+                .map(|i| present(i).then(|| string_value(array.value(i))))
+                .collect()
+        }
+        _ => (0..column.len())
+            .map(|i| {
+                if !present(i) {
+                    return Ok(None);
+                }
+                Ok(Some(Value::from(array_value_to_string(column, i)?)))
+            })
+            .collect::<Result<_>>()?,
+    };
+    Ok(values)
+}
+
+fn timestamp(value: i64, unit: &TimeUnit) -> Option<DateTime<Utc>> {
+    match unit {
+        TimeUnit::Second => DateTime::from_timestamp(value, 0),
+        TimeUnit::Millisecond => DateTime::from_timestamp_millis(value),
+        TimeUnit::Microsecond => DateTime::from_timestamp_micros(value),
+        TimeUnit::Nanosecond => Some(DateTime::from_timestamp_nanos(value)),
+    }
+}
+
+/// A string column value, parsed when it holds JSON such as the daemon's own `metadata` column
+fn string_value(value: &str) -> Value {
+    if value.starts_with('{') || value.starts_with('[') {
+        if let Ok(parsed) = serde_json::from_str(value) {
+            return parsed;
+        }
+    }
+    Value::from(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::IngestConfig;
+    use crate::query::{LogFilter, QueryEngine};
+    use arrow::array::{ArrayRef, Int32Array, StringArray, TimestampSecondArray};
+    use arrow::datatypes::{Field, Schema};
+    use parquet::arrow::ArrowWriter;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_import_maps_foreign_parquet_and_jsonl() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("other_logs");
+        std::fs::create_dir_all(source.join("2024")).unwrap();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("ts", DataType::Timestamp(TimeUnit::Second, None), true),
+            Field::new("severity", DataType::Utf8, true),
+            Field::new("msg", DataType::Utf8, true),
+            Field::new("status", DataType::Int32, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(TimestampSecondArray::from(vec![
+                    1_700_000_000,
+                    1_700_000_060,
+                ])) as ArrayRef,
+                Arc::new(StringArray::from(vec!["WARNING", "Error"])),
+                Arc::new(StringArray::from(vec![Some("slow disk"), None])),
+                Arc::new(Int32Array::from(vec![Some(200), None])),
+            ],
+        )
+        .unwrap();
+        let file = File::create(source.join("2024").join("old.parquet")).unwrap();
+        let mut writer = ArrowWriter::try_new(file, schema, None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+        std::fs::write(
+            source.join("app.jsonl"),
+            "{\"ts\":\"2024-03-01 10:00:00\",\"severity\":\"info\",\"msg\":\"started\"}\nnot json\n",
+        )
+        .unwrap();
+        std::fs::write(source.join("notes.txt"), "skipped").unwrap();
+
+        let files = find_files(std::slice::from_ref(&source)).unwrap();
+        assert_eq!(
+            files,
+            vec![source.join("2024/old.parquet"), source.join("app.jsonl")]
+        );
+
+        let mappings = [
+            "timestamp=ts",
+            "level=severity",
+            "message=msg",
+            "metadata.http.status=status",
+        ]
+        .iter()
+        .map(|map| parse_map(map).unwrap())
+        .collect::<Vec<_>>();
+        assert!(parse_map("level").is_err());
+
+        let config = Config {
+            storage_dir: temp_dir.path().join("logs"),
+            ..Config::default()
+        };
+        let report = import(&files, &config, &mappings).unwrap();
+        assert_eq!((report.files, report.imported), (2, 2));
+        // The row without a message fails the schema, the line that is not JSON fails to parse
+        assert_eq!(report.rejected.get("schema_violation"), Some(&1));
+        assert_eq!(report.rejected.get("malformed_json"), Some(&1));
+
+        let records = QueryEngine::new(config.storage_dir)
+            .query(&LogFilter::default())
+            .unwrap();
+        let mut stored: Vec<(String, String, String)> = records
+            .iter()
+            .map(|record| {
+                (
+                    record.timestamp.clone(),
+                    record.level.clone(),
+                    record.message.clone(),
+                )
+            })
+            .collect();
+        stored.sort();
+        assert_eq!(
+            stored,
+            vec![
+                (
+                    "2023-11-14T22:13:20+00:00".into(),
+                    "warn".into(),
+                    "slow disk".into()
+                ),
+                (
+                    "2024-03-01T10:00:00+00:00".into(),
+                    "info".into(),
+                    "started".into()
+                ),
+            ]
+        );
+
+        // Nested targets land in the metadata object
+        let pipeline = IngestPipeline::from_config(&IngestConfig {
+            field_mappings: mappings,
+            ..IngestConfig::default()
+        })
+        .unwrap();
+        let router = SchemaSources::default().build().unwrap();
+        let record = serde_json::json!({ "ts": 1_700_000_000, "severity": "info", "msg": "ok", "status": 404 });
+        let entry = process(&record, &pipeline, &router).unwrap();
+        let metadata = serde_json::to_value(&entry.metadata).unwrap();
+        assert_eq!(metadata["http"]["status"], 404);
+    }
+}
@@ -0,0 +1,405 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat};
+use parquet::basic::{Compression, LogicalType, TimeUnit};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::file::statistics::Statistics;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use crate::file_metadata;
+use crate::stats::human_bytes;
+
+/// Characters of a string min or max shown before it is cut
+const MAX_VALUE_CHARS: usize = 40;
+
+/// What `daemon_rs inspect` reports about a Parquet file
+#[derive(Debug, Clone)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub file_bytes: u64,
+    pub rows: i64,
+    pub row_groups: Vec<RowGroupReport>,
+    pub columns: Vec<ColumnReport>,
+    /// Library that wrote the file, from its footer
+    pub created_by: Option<String>,
+    /// The daemon's key-value metadata, without the `daemon_rs.` prefix
+    pub metadata: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RowGroupReport {
+    pub rows: i64,
+    pub compressed_bytes: i64,
+    pub uncompressed_bytes: i64,
+}
+
+/// A column over all row groups
+#[derive(Debug, Clone)]
+pub struct ColumnReport {
+    pub name: String,
+    pub codec: String,
+    pub compressed_bytes: i64,
+    pub uncompressed_bytes: i64,
+    /// None when a row group has no statistics
+    pub nulls: Option<u64>,
+    pub min: Option<String>,
+    pub max: Option<String>,
+}
+
+impl FileReport {
+    pub fn compressed_bytes(&self) -> i64 {
+        self.row_groups
+            .iter()
+            .map(|group| group.compressed_bytes)
+            .sum()
+    }
+
+    pub fn uncompressed_bytes(&self) -> i64 {
+        self.row_groups
+            .iter()
+            .map(|group| group.uncompressed_bytes)
+            .sum()
+    }
+}
+
+/// Read the footer of a log or trace file
+pub fn inspect(path: &Path) -> Result<FileReport> {
+    let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let file_bytes = file.metadata()?.len();
+    let reader = SerializedFileReader::new(file)
+        .with_context(|| format!("Failed to read Parquet footer of {:?}", path))?;
+    let metadata = reader.metadata();
+    let footer = metadata.file_metadata();
+
+    let mut columns: Vec<ColumnReport> = Vec::new();
+    let mut bounds: Vec<Option<(Bound, Bound)>> = Vec::new();
+    let mut row_groups = Vec::new();
+    for group in metadata.row_groups() {
+        row_groups.push(RowGroupReport {
+            rows: group.num_rows(),
+            compressed_bytes: group.compressed_size(),
+            uncompressed_bytes: group.total_byte_size(),
+        });
+        for (i, column) in group.columns().iter().enumerate() {
+            if i == columns.len() {
+                columns.push(ColumnReport {
+                    name: column.column_path().string(),
+                    codec: codec(column.compression()),
+                    compressed_bytes: 0,
+                    uncompressed_bytes: 0,
+                    nulls: Some(0),
+                    min: None,
+                    max: None,
+                });
+                bounds.push(None);
+            }
+            let report = &mut columns[i];
+            report.compressed_bytes += column.compressed_size();
+            report.uncompressed_bytes += column.uncompressed_size();
+            let stats = column.statistics();
+            report.nulls = report
+                .nulls
+                .zip(stats.and_then(Statistics::null_count_opt))
+                .map(|(a, b)| a + b);
+            if let Some(next) = stats.and_then(Bound::of) {
+                bounds[i] = Some(match bounds[i].take() {
+                    Some((min, max)) => (
+                        if next.0 < min { next.0 } else { min },
+                        if next.1 > max { next.1 } else { max },
+                    ),
+                    None => next,
+                });
+            }
+        }
+    }
+    let schema = footer.schema_descr();
+    for (i, (column, bounds)) in columns.iter_mut().zip(bounds).enumerate() {
+        let logical_type = schema.column(i).logical_type();
+        if let Some((min, max)) = bounds {
+            column.min = Some(min.show(logical_type.as_ref()));
+            column.max = Some(max.show(logical_type.as_ref()));
+        }
+    }
+
+    Ok(FileReport {
+        path: path.to_path_buf(),
+        file_bytes,
+        rows: footer.num_rows(),
+        row_groups,
+        columns,
+        created_by: footer.created_by().map(str::to_string),
+        metadata: file_metadata::daemon_entries(footer.key_value_metadata()),
+    })
+}
+
+/// A column min or max, comparable across row groups
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+enum Bound {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl Bound {
+    fn of(stats: &Statistics) -> Option<(Self, Self)> {
+        let text = |bytes: &[u8]| Self::Text(String::from_utf8_lossy(bytes).into_owned());
+        Some(match stats {
+            Statistics::Boolean(s) => (Self::Bool(*s.min_opt()?), Self::Bool(*s.max_opt()?)),
+            Statistics::Int32(s) => (
+                Self::Int(i64::from(*s.min_opt()?)),
+                Self::Int(i64::from(*s.max_opt()?)),
+            ),
+            Statistics::Int64(s) => (Self::Int(*s.min_opt()?), Self::Int(*s.max_opt()?)),
+            Statistics::Float(s) => (
+                Self::Float(f64::from(*s.min_opt()?)),
+                Self::Float(f64::from(*s.max_opt()?)),
+            ),
+            Statistics::Double(s) => (Self::Float(*s.min_opt()?), Self::Float(*s.max_opt()?)),
+            Statistics::ByteArray(_) | Statistics::FixedLenByteArray(_) => {
+                (text(stats.min_bytes_opt()?), text(stats.max_bytes_opt()?))
+            }
+            Statistics::Int96(_) => return None,
+        })
+    }
+
+    /// Timestamps as RFC 3339, long strings cut
+    fn show(&self, logical_type: Option<&LogicalType>) -> String {
+        match (self, logical_type) {
+            (Self::Int(value), Some(LogicalType::Timestamp { unit, .. })) => {
+                let time = match unit {
+                    TimeUnit::MILLIS(_) => DateTime::from_timestamp_millis(*value),
+                    TimeUnit::MICROS(_) => DateTime::from_timestamp_micros(*value),
+                    TimeUnit::NANOS(_) => Some(DateTime::from_timestamp_nanos(*value)),
+                };
+                time.map_or_else(
+                    || value.to_string(),
+                    |time| time.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+                )
+            }
+            (Self::Text(value), _) if value.chars().count() > MAX_VALUE_CHARS => {
+                let cut: String = value.chars().take(MAX_VALUE_CHARS).collect();
+                format!("{:?}...", cut)
+            }
+            (Self::Text(value), _) => format!("{:?}", value),
+            (Self::Bool(value), _) => value.to_string(),
+            (Self::Int(value), _) => value.to_string(),
+            (Self::Float(value), _) => value.to_string(),
+        }
+    }
+}
+
+fn codec(compression: Compression) -> String {
+    match compression {
+        Compression::UNCOMPRESSED => "none".to_string(),
+        Compression::GZIP(_) => "gzip".to_string(),
+        Compression::BROTLI(_) => "brotli".to_string(),
+        Compression::ZSTD(_) => "zstd".to_string(),
+        other => other.to_string().to_lowercase(),
+    }
+}
+
+/// `3.2x`, uncompressed over compressed size
+fn ratio(compressed: i64, uncompressed: i64) -> String {
+    if compressed > 0 {
+        format!("{:.1}x", uncompressed as f64 / compressed as f64)
+    } else {
+        "-".to_string()
+    }
+}
+
+fn bytes(size: i64) -> String {
+    human_bytes(size.max(0) as u64)
+}
+
+/// Rows with each column padded to its widest cell, each on a new line indented by four spaces
+fn table(f: &mut fmt::Formatter<'_>, rows: &[Vec<String>]) -> fmt::Result {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|i| {
+            rows.iter()
+                .filter_map(|row| row.get(i))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    for row in rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        write!(f, "\n    {}", line.join("  ").trim_end())?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for FileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        write!(
+            f,
+            "\n  {}, {} rows in {} row group(s), {} compressed from {} ({})",
+            human_bytes(self.file_bytes),
+            self.rows,
+            self.row_groups.len(),
+            bytes(self.compressed_bytes()),
+            bytes(self.uncompressed_bytes()),
+            ratio(self.compressed_bytes(), self.uncompressed_bytes()),
+        )?;
+
+        write!(f, "\n  row groups:")?;
+        let mut rows = vec![vec![
+            "#".to_string(),
+            "rows".to_string(),
+            "compressed".to_string(),
+            "ratio".to_string(),
+        ]];
+        for (i, group) in self.row_groups.iter().enumerate() {
+            rows.push(vec![
+                i.to_string(),
+                group.rows.to_string(),
+                bytes(group.compressed_bytes),
+                ratio(group.compressed_bytes, group.uncompressed_bytes),
+            ]);
+        }
+        table(f, &rows)?;
+
+        write!(f, "\n  columns:")?;
+        let mut rows = vec![[
+            "column",
+            "codec",
+            "compressed",
+            "ratio",
+            "nulls",
+            "min",
+            "max",
+        ]
+        .map(str::to_string)
+        .to_vec()];
+        for column in &self.columns {
+            let or_dash = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+            rows.push(vec![
+                column.name.clone(),
+                column.codec.clone(),
+                bytes(column.compressed_bytes),
+                ratio(column.compressed_bytes, column.uncompressed_bytes),
+                or_dash(&column.nulls.map(|nulls| nulls.to_string())),
+                or_dash(&column.min),
+                or_dash(&column.max),
+            ]);
+        }
+        table(f, &rows)?;
+
+        if self.metadata.is_empty() {
+            return write!(
+                f,
+                "\n  no daemon_rs metadata (written by {})",
+                self.created_by.as_deref().unwrap_or("unknown")
+            );
+        }
+        write!(f, "\n  metadata:")?;
+        let rows: Vec<Vec<String>> = self
+            .metadata
+            .iter()
+            .map(|(key, value)| vec![key.clone(), value.clone()])
+            .collect();
+        table(f, &rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::LogEntry;
+    use crate::storage::StorageEngine;
+    use crate::trace_storage::{list_trace_files, SpanStatus, TraceSpan, TraceStorage};
+    use parquet::basic::{Compression, ZstdLevel};
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_files_describe_themselves() {
+        let temp_dir = TempDir::new().unwrap();
+        let logs_dir = temp_dir.path().join("logs");
+        let mut storage = StorageEngine::new(
+            logs_dir.clone(),
+            Compression::ZSTD(ZstdLevel::try_new(3).unwrap()),
+            100,
+            1 << 20,
+        )
+        .unwrap();
+        for (timestamp, version) in [
+            ("2026-01-15T19:00:00Z", "v2"),
+            ("2026-01-15T18:00:00Z", "v1"),
+        ] {
+            let mut log: LogEntry = serde_json::from_value(json!({
+                "timestamp": timestamp,
+                "level": "info",
+                "message": "m",
+            }))
+            .unwrap();
+            log.schema_version = Some(version.to_string());
+            storage.add_log(log).unwrap();
+        }
+        storage.flush().unwrap();
+        let path = storage.list_files().unwrap()[0].clone();
+
+        let report = inspect(&path).unwrap();
+        assert_eq!(report.rows, 2);
+        assert_eq!(report.row_groups.len(), 1);
+        let column = |name: &str| report.columns.iter().find(|c| c.name == name).unwrap();
+        assert_eq!(column("timestamp").codec, "zstd");
+        assert_eq!(
+            column("timestamp").min.as_deref(),
+            Some("2026-01-15T18:00:00Z")
+        );
+        assert_eq!(
+            column("timestamp").max.as_deref(),
+            Some("2026-01-15T19:00:00Z")
+        );
+        assert_eq!(column("level").max.as_deref(), Some("\"info\""));
+        assert_eq!(column("trace_id").nulls, Some(2));
+        assert_eq!(column("trace_id").min, None);
+        assert!(report.compressed_bytes() > 0);
+        assert_eq!(report.metadata["kind"], "logs");
+        assert_eq!(report.metadata["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(report.metadata["writer.compression"], "zstd:3");
+        assert_eq!(report.metadata["min_time"], "2026-01-15T18:00:00.000000Z");
+        assert_eq!(report.metadata["max_time"], "2026-01-15T19:00:00.000000Z");
+        assert_eq!(report.metadata["schema_versions"], "v1,v2");
+        assert_eq!(report.metadata["services"], r#"[""]"#);
+        assert_eq!(report.metadata["levels"], r#"["info"]"#);
+        assert!(report
+            .to_string()
+            .lines()
+            .any(|line| line.split_whitespace().eq(["writer.batch_size", "100"])));
+
+        let traces_dir = temp_dir.path().join("traces");
+        let mut traces = TraceStorage::new(traces_dir.clone(), Compression::SNAPPY, 100).unwrap();
+        let now = chrono::Utc::now();
+        traces
+            .add_span(TraceSpan {
+                trace_id: "t".to_string(),
+                span_id: "s".to_string(),
+                parent_span_id: None,
+                name: "request".to_string(),
+                start_time: now,
+                end_time: now,
+                duration_us: 0,
+                attributes: Default::default(),
+                events: Vec::new(),
+                status: SpanStatus::Ok,
+            })
+            .unwrap();
+        traces.flush().unwrap();
+        let report = inspect(&list_trace_files(&traces_dir).unwrap()[0]).unwrap();
+        assert_eq!(report.metadata["kind"], "traces");
+        assert_eq!(report.columns[0].name, "trace_id");
+        assert_eq!(report.columns[0].min.as_deref(), Some("\"t\""));
+        assert_eq!(report.metadata["writer.compression"], "snappy");
+    }
+}
@@ -0,0 +1,125 @@
+use serde::{Deserialize, Deserializer};
+use std::collections::HashSet;
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// Distinct strings shared before new ones get copies of their own, so a producer sending
+/// random service names cannot grow the table without bound
+pub const MAX_INTERNED: usize = 10_000;
+
+/// Shared copies of strings that repeat across logs, such as levels and service names
+pub struct Interner {
+    max: usize,
+    strings: RwLock<HashSet<Arc<str>>>,
+}
+
+impl Interner {
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            strings: RwLock::new(HashSet::new()),
+        }
+    }
+
+    /// The shared copy of `s`; a fresh one once the table is full
+    pub fn intern(&self, s: &str) -> Arc<str> {
+        if let Some(shared) = self
+            .strings
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(s)
+        {
+            return shared.clone();
+        }
+        let mut strings = self.strings.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(shared) = strings.get(s) {
+            return shared.clone();
+        }
+        let interned: Arc<str> = Arc::from(s);
+        if strings.len() < self.max {
+            strings.insert(interned.clone());
+        }
+        interned
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.read().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// `s` from the process-wide interner
+pub fn intern(s: &str) -> Arc<str> {
+    static INTERNER: OnceLock<Interner> = OnceLock::new();
+    INTERNER
+        .get_or_init(|| Interner::new(MAX_INTERNED))
+        .intern(s)
+}
+
+/// A string read straight into its interned copy, without an owned `String` in between
+struct Interned(Arc<str>);
+
+impl<'de> Deserialize<'de> for Interned {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = Interned;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a string")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(Interned(intern(v)))
+            }
+        }
+
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+/// `deserialize_with` for interned string fields
+pub fn interned<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Arc<str>, D::Error> {
+    Interned::deserialize(deserializer).map(|interned| interned.0)
+}
+
+/// `deserialize_with` for optional interned string fields
+pub fn interned_opt<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Arc<str>>, D::Error> {
+    Option::<Interned>::deserialize(deserializer).map(|interned| interned.map(|i| i.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interner_shares_and_stays_bounded() {
+        let interner = Interner::new(2);
+        let info = interner.intern("info");
+        assert!(Arc::ptr_eq(&info, &interner.intern("info")));
+        interner.intern("error");
+        // Past the limit strings still come back, just not shared
+        let debug = interner.intern("debug");
+        assert_eq!(&*debug, "debug");
+        assert!(!Arc::ptr_eq(&debug, &interner.intern("debug")));
+        assert_eq!(interner.len(), 2);
+
+        #[derive(Deserialize)]
+        struct Entry {
+            #[serde(deserialize_with = "interned")]
+            level: Arc<str>,
+            #[serde(default, deserialize_with = "interned_opt")]
+            service: Option<Arc<str>>,
+        }
+        let a: Entry = serde_json::from_str(r#"{"level":"warn","service":"api"}"#).unwrap();
+        let b: Entry = serde_json::from_str(r#"{"level":"warn"}"#).unwrap();
+        assert!(Arc::ptr_eq(&a.level, &b.level));
+        assert_eq!(a.service.as_deref(), Some("api"));
+        assert_eq!(b.service, None);
+    }
+}
@@ -0,0 +1,162 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// How long a key command may take
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where secret key material comes from, e.g. `{ file = "/etc/daemon_rs/api.key" }`,
+/// `{ env = "API_TLS_KEY" }` or `{ command = ["vault", "kv", "get", "-field=key", "tls"] }`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeySource {
+    File(PathBuf),
+    /// An environment variable holding the key itself
+    Env(String),
+    /// A command, e.g. a KMS client, printing the key on stdout; its stderr is passed through
+    Command(Vec<String>),
+}
+
+impl KeySource {
+    pub fn validate(&self) -> Result<()> {
+        let empty = match self {
+            KeySource::File(path) => path.as_os_str().is_empty(),
+            KeySource::Env(name) => name.is_empty(),
+            KeySource::Command(command) => command.first().is_none_or(String::is_empty),
+        };
+        if empty {
+            bail!("Key source {} is empty", self);
+        }
+        Ok(())
+    }
+
+    /// Read the key now; called again on every rotation
+    pub async fn load(&self) -> Result<Vec<u8>> {
+        self.load_with_env(|name| std::env::var_os(name)).await
+    }
+
+    /// Like `load`, with environment variables looked up through `var`
+    pub async fn load_with_env(
+        &self,
+        var: impl Fn(&str) -> Option<std::ffi::OsString>,
+    ) -> Result<Vec<u8>> {
+        let key = match self {
+            KeySource::File(path) => tokio::fs::read(path)
+                .await
+                .with_context(|| format!("Failed to read key file {:?}", path))?,
+            KeySource::Env(name) => var(name)
+                .with_context(|| format!("Key variable {} is not set", name))?
+                .into_encoded_bytes(),
+            KeySource::Command(command) => run_command(command).await?,
+        };
+        if key.iter().all(u8::is_ascii_whitespace) {
+            bail!("Key from {} is empty", self);
+        }
+        Ok(key)
+    }
+}
+
+impl std::fmt::Display for KeySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeySource::File(path) => write!(f, "file {:?}", path),
+            KeySource::Env(name) => write!(f, "variable {}", name),
+            KeySource::Command(command) => {
+                write!(
+                    f,
+                    "command {:?}",
+                    command.first().map_or("", String::as_str)
+                )
+            }
+        }
+    }
+}
+
+/// Run `command` and return its stdout; the output is never logged
+async fn run_command(command: &[String]) -> Result<Vec<u8>> {
+    let child = tokio::process::Command::new(&command[0])
+        .args(&command[1..])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to run key command {:?}", command[0]))?;
+    let output = tokio::time::timeout(COMMAND_TIMEOUT, child.wait_with_output())
+        .await
+        .with_context(|| format!("Key command {:?} timed out", command[0]))??;
+    if !output.status.success() {
+        bail!("Key command {:?} exited with {}", command[0], output.status);
+    }
+    Ok(output.stdout)
+}
+
+/// Asks the holders of loaded keys to load them again from their sources, e.g. on SIGHUP
+#[derive(Debug, Clone, Default)]
+pub struct KeyRotation(Arc<Notify>);
+
+impl KeyRotation {
+    /// Request a reload; requests made while one is running are folded into the next
+    pub fn rotate(&self) {
+        self.0.notify_one();
+    }
+
+    /// Wait for the next request
+    pub async fn requested(&self) {
+        self.0.notified().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_loads_keys_from_each_source() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("api.key");
+        std::fs::write(&path, "file key\n").unwrap();
+
+        let sources = toml::from_str::<toml::Table>(&format!(
+            r#"
+            file = {{ file = "{}" }}
+            env = {{ env = "DAEMON_RS_TEST_KEY" }}
+            command = {{ command = ["sh", "-c", "echo command key"] }}
+            failing = {{ command = ["sh", "-c", "exit 3"] }}
+            unset = {{ env = "DAEMON_RS_TEST_UNSET_KEY" }}
+            "#,
+            path.display()
+        ))
+        .unwrap();
+        let mut loaded = std::collections::HashMap::new();
+        for (name, value) in sources {
+            let source: KeySource = value.try_into().unwrap();
+            source.validate().unwrap();
+            let key = source
+                .load_with_env(|name| (name == "DAEMON_RS_TEST_KEY").then(|| "env key".into()))
+                .await;
+            loaded.insert(name, key.map_err(|e| e.to_string()));
+        }
+        assert_eq!(loaded["file"].as_deref().unwrap(), b"file key\n");
+        assert_eq!(loaded["env"].as_deref().unwrap(), b"env key");
+        assert_eq!(loaded["command"].as_deref().unwrap(), b"command key\n");
+        assert!(loaded["failing"]
+            .as_ref()
+            .unwrap_err()
+            .contains("exited with"));
+        assert!(loaded["unset"].as_ref().unwrap_err().contains("not set"));
+        assert!(KeySource::Command(Vec::new()).validate().is_err());
+
+        // A request made before anyone waits is kept for the next wait
+        let rotation = KeyRotation::default();
+        rotation.rotate();
+        tokio::time::timeout(Duration::from_secs(1), rotation.requested())
+            .await
+            .unwrap();
+    }
+}
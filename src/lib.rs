@@ -1,9 +1,74 @@
+pub mod admin;
+#[cfg(feature = "api")]
 pub mod ai_api;
+pub mod alerts;
+pub mod anomaly;
+pub mod api_auth;
+pub mod api_config;
+pub mod audit;
+pub mod bench;
+pub mod checksum;
 pub mod config;
+pub mod config_check;
+pub mod config_reload;
+pub mod daemon;
+pub mod dead_letter;
+pub mod disk_watch;
+pub mod doctor;
+pub mod enrichment;
+pub mod exemplars;
+pub mod federation;
+pub mod field_mapping;
+pub mod file_ingest;
+pub mod file_metadata;
+pub mod flamegraph;
+pub mod forward;
+pub mod generate;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+#[cfg(feature = "api")]
+pub mod grpc;
+pub mod handover;
+pub mod import;
+pub mod inspect;
+pub mod intern;
+pub mod keys;
+pub mod live;
+pub mod log_format;
+pub mod log_metrics;
 pub mod metrics;
+pub mod notify;
+pub mod operations;
 pub mod otel;
+pub mod parse_pool;
+pub mod payload_log;
+pub mod pipeline;
+pub mod privileges;
 pub mod query;
+pub mod query_cache;
+pub mod quota;
+pub mod rate_limit;
+pub mod redaction;
+pub mod remote_write;
+pub mod retention;
+pub mod sampling_report;
+pub mod sandbox;
 pub mod schema;
+pub mod schema_formats;
+pub mod schema_infer;
+pub mod schema_registry;
+pub mod schema_reload;
 pub mod server;
+pub mod snapshot;
+pub mod spool;
+pub mod stats;
+pub mod status;
 pub mod storage;
+pub mod trace_export;
+pub mod trace_index;
 pub mod trace_storage;
+#[cfg(feature = "otel")]
+pub mod trace_writer;
+pub mod units;
+
+pub use daemon::{DaemonBuilder, DaemonHandle};
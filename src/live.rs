@@ -0,0 +1,63 @@
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+use crate::schema::LogEntry;
+use crate::trace_index::TraceSummary;
+
+/// Buffered events per subscriber before slow subscribers start missing events
+pub const LIVE_CHANNEL_CAPACITY: usize = 1024;
+
+/// Fan-out of newly ingested logs and newly flushed traces to live API subscribers
+#[derive(Clone)]
+pub struct LiveFeed {
+    logs: broadcast::Sender<Arc<LogEntry>>,
+    traces: broadcast::Sender<TraceSummary>,
+}
+
+impl LiveFeed {
+    pub fn new() -> Self {
+        let (logs, _) = broadcast::channel(LIVE_CHANNEL_CAPACITY);
+        let (traces, _) = broadcast::channel(LIVE_CHANNEL_CAPACITY);
+        Self { logs, traces }
+    }
+
+    /// Publish an ingested log; a no-op (and no clone) without subscribers
+    pub fn publish_log(&self, log: &LogEntry) {
+        if self.logs.receiver_count() > 0 {
+            let _ = self.logs.send(Arc::new(log.clone()));
+        }
+    }
+
+    /// Publish a trace summary seen for the first time (or updated) on disk
+    pub fn publish_trace(&self, summary: TraceSummary) {
+        if self.traces.receiver_count() > 0 {
+            let _ = self.traces.send(summary);
+        }
+    }
+
+    pub fn subscribe_logs(&self) -> broadcast::Receiver<Arc<LogEntry>> {
+        self.logs.subscribe()
+    }
+
+    pub fn subscribe_traces(&self) -> broadcast::Receiver<TraceSummary> {
+        self.traces.subscribe()
+    }
+
+    /// Events still buffered for the slowest log and trace subscriber
+    pub fn queued(&self) -> (usize, usize) {
+        (self.logs.len(), self.traces.len())
+    }
+}
+
+impl Default for LiveFeed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Levels treated as errors by live streams
+pub fn is_error_level(level: &str) -> bool {
+    ["error", "fatal", "critical", "panic"]
+        .iter()
+        .any(|l| level.eq_ignore_ascii_case(l))
+}
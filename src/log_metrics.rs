@@ -0,0 +1,415 @@
+use anyhow::{bail, Result};
+use metrics::Label;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::RwLock;
+
+use crate::metrics::{DEFAULT_METRIC_PREFIX, OTHER_SERVICE_LABEL};
+use crate::schema::LogEntry;
+
+/// Histogram buckets for extracted values unless a rule sets its own
+pub const EXTRACT_BUCKETS: &[f64] = &[
+    1.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0,
+];
+
+/// Label combinations a rule exports unless it sets `max_series`
+pub const DEFAULT_MAX_SERIES: usize = 1000;
+
+/// One `[[metrics.extract]]` rule: a numeric log field turned into a Prometheus metric
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtractRule {
+    /// Metric name after the prefix: `request_latency_ms` exports `log_daemon_request_latency_ms`
+    pub name: String,
+
+    /// Metadata field holding the value, e.g. `metadata.latency_ms`; numbers and numeric
+    /// strings are accepted
+    pub field: String,
+
+    #[serde(default)]
+    pub kind: ExtractKind,
+
+    /// HELP text of the metric
+    #[serde(default)]
+    pub help: Option<String>,
+
+    /// Only logs from this service
+    #[serde(default)]
+    pub service: Option<String>,
+
+    /// Only logs at this level, case-insensitive
+    #[serde(default)]
+    pub level: Option<String>,
+
+    /// Fields exported as labels, named after their last path segment: `service`, `level` or
+    /// `metadata.<path>`
+    #[serde(default = "default_labels")]
+    pub labels: Vec<String>,
+
+    /// Histogram bucket upper bounds; defaults to EXTRACT_BUCKETS
+    #[serde(default)]
+    pub buckets: Option<Vec<f64>>,
+
+    /// Label combinations exported before new ones are folded into `other`
+    #[serde(default = "default_max_series")]
+    pub max_series: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExtractKind {
+    /// Observe each value
+    #[default]
+    Histogram,
+    /// Add each value, rounded down; negative values are skipped
+    Counter,
+    /// Set to the latest value
+    Gauge,
+}
+
+fn default_labels() -> Vec<String> {
+    vec!["service".to_string()]
+}
+
+fn default_max_series() -> usize {
+    DEFAULT_MAX_SERIES
+}
+
+impl ExtractRule {
+    /// Exported name before the prefix is applied
+    pub fn metric_name(&self) -> String {
+        format!("{}_{}", DEFAULT_METRIC_PREFIX, self.name)
+    }
+
+    /// Buckets in effect for a histogram rule
+    pub fn bucket_bounds(&self) -> &[f64] {
+        self.buckets.as_deref().unwrap_or(EXTRACT_BUCKETS)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let valid_name = !self.name.is_empty()
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':');
+        if !valid_name {
+            bail!("metrics.extract: invalid metric name {:?}", self.name);
+        }
+        if !matches!(FieldPath::parse(&self.field)?, FieldPath::Metadata(_)) {
+            bail!(
+                "metrics.extract {}: field must be a metadata field, got {}",
+                self.name,
+                self.field
+            );
+        }
+        let mut names = HashSet::new();
+        for label in &self.labels {
+            let path = FieldPath::parse(label)?;
+            if !names.insert(path.label_name().to_string()) {
+                bail!("metrics.extract {}: duplicate label {}", self.name, label);
+            }
+        }
+        if let Some(buckets) = &self.buckets {
+            if buckets.is_empty()
+                || buckets.iter().any(|b| !b.is_finite())
+                || buckets.windows(2).any(|w| w[0] >= w[1])
+            {
+                bail!(
+                    "metrics.extract {}: buckets must be a non-empty, strictly increasing list of finite bounds",
+                    self.name
+                );
+            }
+        }
+        if self.max_series == 0 {
+            bail!(
+                "metrics.extract {}: max_series must be greater than 0",
+                self.name
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Where a value or label is read from
+#[derive(Debug, Clone, PartialEq)]
+enum FieldPath {
+    Service,
+    Level,
+    Metadata(Vec<String>),
+}
+
+impl FieldPath {
+    fn parse(path: &str) -> Result<Self> {
+        match path {
+            "service" => Ok(Self::Service),
+            "level" => Ok(Self::Level),
+            _ => match path.strip_prefix("metadata.") {
+                Some(rest) if !rest.split('.').any(str::is_empty) => Ok(Self::Metadata(
+                    rest.split('.').map(str::to_string).collect(),
+                )),
+                _ => bail!(
+                    "metrics.extract: unknown field {:?}; use service, level or metadata.<path>",
+                    path
+                ),
+            },
+        }
+    }
+
+    fn label_name(&self) -> &str {
+        match self {
+            Self::Service => "service",
+            Self::Level => "level",
+            Self::Metadata(keys) => keys.last().map(String::as_str).unwrap_or_default(),
+        }
+    }
+
+    fn value<'a>(&self, log: &'a LogEntry) -> Option<&'a Value> {
+        let Self::Metadata(keys) = self else {
+            return None;
+        };
+        keys.iter()
+            .try_fold(log.metadata.as_ref()?, |value, key| value.get(key))
+    }
+
+    fn label(&self, log: &LogEntry) -> String {
+        match self {
+            Self::Service => log.service.as_deref().unwrap_or_default().to_string(),
+            Self::Level => log.level.to_string(),
+            Self::Metadata(_) => match self.value(log) {
+                Some(Value::String(s)) => s.clone(),
+                Some(Value::Null) | None => String::new(),
+                Some(other) => other.to_string(),
+            },
+        }
+    }
+}
+
+struct CompiledRule {
+    rule: ExtractRule,
+    name: String,
+    field: FieldPath,
+    labels: Vec<(String, FieldPath)>,
+    /// Label value combinations exported so far
+    series: RwLock<HashSet<Vec<String>>>,
+}
+
+impl CompiledRule {
+    fn matches(&self, log: &LogEntry) -> bool {
+        self.rule
+            .service
+            .as_deref()
+            .is_none_or(|service| log.service.as_deref() == Some(service))
+            && self
+                .rule
+                .level
+                .as_deref()
+                .is_none_or(|level| log.level.eq_ignore_ascii_case(level))
+    }
+
+    /// Label values for `log`, all `other` once `max_series` combinations have been seen
+    fn label_values(&self, log: &LogEntry) -> Vec<String> {
+        let values: Vec<String> = self
+            .labels
+            .iter()
+            .map(|(_, path)| path.label(log))
+            .collect();
+        if self
+            .series
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains(&values)
+        {
+            return values;
+        }
+        let mut series = self.series.write().unwrap_or_else(|e| e.into_inner());
+        if series.len() < self.rule.max_series || series.contains(&values) {
+            series.insert(values.clone());
+            values
+        } else {
+            vec![OTHER_SERVICE_LABEL.to_string(); values.len()]
+        }
+    }
+}
+
+/// Records the `[[metrics.extract]]` rules for every accepted log
+pub struct LogMetrics {
+    rules: Vec<CompiledRule>,
+}
+
+impl LogMetrics {
+    pub fn new(rules: &[ExtractRule]) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                rule.validate()?;
+                let labels = rule
+                    .labels
+                    .iter()
+                    .map(|label| {
+                        let path = FieldPath::parse(label)?;
+                        Ok((path.label_name().to_string(), path))
+                    })
+                    .collect::<Result<_>>()?;
+                Ok(CompiledRule {
+                    name: rule.metric_name(),
+                    field: FieldPath::parse(&rule.field)?,
+                    labels,
+                    series: RwLock::new(HashSet::new()),
+                    rule: rule.clone(),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Record every rule `log` matches; runs on the ingest path
+    pub fn observe(&self, log: &LogEntry) {
+        for sample in self.samples(log) {
+            let name = sample.metric.to_string();
+            match sample.kind {
+                ExtractKind::Histogram => metrics::histogram!(name, sample.value, sample.labels),
+                ExtractKind::Counter => metrics::counter!(name, sample.value as u64, sample.labels),
+                ExtractKind::Gauge => metrics::gauge!(name, sample.value, sample.labels),
+            }
+        }
+    }
+
+    /// Values the rules extract from `log`; unusable values are counted and left out
+    pub fn samples(&self, log: &LogEntry) -> Vec<Sample<'_>> {
+        let mut samples = Vec::new();
+        for rule in &self.rules {
+            if !rule.matches(log) {
+                continue;
+            }
+            let Some(value) = rule.field.value(log) else {
+                continue;
+            };
+            let number = match value {
+                Value::Number(n) => n.as_f64(),
+                Value::String(s) => s.trim().parse::<f64>().ok(),
+                _ => None,
+            };
+            let usable = number
+                .filter(|n| n.is_finite() && (rule.rule.kind != ExtractKind::Counter || *n >= 0.0));
+            let Some(value) = usable else {
+                crate::metrics::record_extract_invalid(&rule.rule.name);
+                continue;
+            };
+            let labels = rule
+                .labels
+                .iter()
+                .zip(rule.label_values(log))
+                .map(|((name, _), value)| Label::new(name.clone(), value))
+                .collect();
+            samples.push(Sample {
+                metric: &rule.name,
+                kind: rule.rule.kind,
+                value,
+                labels,
+            });
+        }
+        samples
+    }
+}
+
+/// A value extracted from a log, as it is recorded
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample<'a> {
+    pub metric: &'a str,
+    pub kind: ExtractKind,
+    pub value: f64,
+    pub labels: Vec<Label>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(service: &str, metadata: Value) -> LogEntry {
+        serde_json::from_value(serde_json::json!({
+            "timestamp": "2026-01-15T19:00:00Z",
+            "level": "info",
+            "message": "request done",
+            "service": service,
+            "metadata": metadata,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_extracts_samples_from_metadata() {
+        #[derive(Deserialize)]
+        struct Rules {
+            extract: Vec<ExtractRule>,
+        }
+        let rules = toml::from_str::<Rules>(
+            r#"
+            [[extract]]
+            name = "request_latency_ms"
+            field = "metadata.latency_ms"
+            labels = ["service", "metadata.http.route"]
+            max_series = 2
+
+            [[extract]]
+            name = "response_bytes"
+            field = "metadata.bytes"
+            kind = "counter"
+            service = "api"
+            "#,
+        )
+        .unwrap()
+        .extract;
+        let engine = LogMetrics::new(&rules).unwrap();
+        let request = |latency: Value, route: &str| serde_json::json!({"latency_ms": latency, "bytes": -1, "http": {"route": route}});
+        let samples = |service: &str, metadata: Value| -> Vec<(String, f64, Vec<String>)> {
+            engine
+                .samples(&log(service, metadata))
+                .into_iter()
+                .map(|sample| {
+                    let labels = sample
+                        .labels
+                        .iter()
+                        .map(|label| format!("{}={}", label.key(), label.value()))
+                        .collect();
+                    (sample.metric.to_string(), sample.value, labels)
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            samples(
+                "api",
+                serde_json::json!({"latency_ms": 12.5, "bytes": 512, "http": {"route": "/a"}})
+            ),
+            [
+                (
+                    "log_daemon_request_latency_ms".to_string(),
+                    12.5,
+                    vec!["service=api".to_string(), "route=/a".to_string()]
+                ),
+                (
+                    "log_daemon_response_bytes".to_string(),
+                    512.0,
+                    vec!["service=api".to_string()]
+                ),
+            ]
+        );
+        // Numeric strings count; negative counter values and non-numbers do not
+        assert_eq!(samples("api", request("40".into(), "/b")).len(), 1);
+        assert!(samples("api", request("slow".into(), "/b")).is_empty());
+        assert!(samples("api", serde_json::json!({"other": 1})).is_empty());
+        // Past max_series, new label combinations are folded into `other`
+        assert_eq!(
+            samples("web", request(7.into(), "/c"))[0].2,
+            ["service=other", "route=other"]
+        );
+        assert_eq!(samples("api", request(7.into(), "/a"))[0].2[1], "route=/a");
+
+        let mut bad = rules[0].clone();
+        bad.field = "message".to_string();
+        assert!(bad.validate().is_err());
+        bad.field = "metadata.latency_ms".to_string();
+        bad.labels = vec!["metadata.a.route".to_string(), "metadata.route".to_string()];
+        assert!(bad.validate().is_err());
+    }
+}
@@ -1,22 +1,56 @@
-mod ai_api;
-mod config;
-mod metrics;
-mod otel;
-mod query;
-mod schema;
-mod server;
-mod storage;
-mod trace_storage;
-
-use anyhow::Result;
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use tracing::info;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::Registry;
 
-use query::QueryEngine;
-use schema::SchemaValidator;
-use server::LogServer;
-use storage::{parse_compression, StorageEngine};
+use daemon_rs::admin::{self, AdminTargets};
+#[cfg(feature = "api")]
+use daemon_rs::ai_api;
+use daemon_rs::api_config::TlsConfig;
+use daemon_rs::audit::{self, Outcome};
+use daemon_rs::bench::{self, BenchConfig};
+use daemon_rs::checksum;
+use daemon_rs::config::{Config, CONFIG_PATH_ENV};
+use daemon_rs::config_check;
+use daemon_rs::config_reload::{self, ReloadTargets};
+use daemon_rs::doctor::{self, Severity};
+use daemon_rs::federation::{FederatedQuery, FederationConfig};
+use daemon_rs::field_mapping::FieldMapping;
+use daemon_rs::file_ingest;
+use daemon_rs::forward::Forwarder;
+use daemon_rs::generate::{self, GenerateConfig, GenerateTarget};
+use daemon_rs::import;
+use daemon_rs::inspect;
+use daemon_rs::keys::KeyRotation;
+use daemon_rs::live::LiveFeed;
+use daemon_rs::log_format::{self, LogFormat};
+use daemon_rs::metrics::PushGatewayConfig;
+use daemon_rs::notify::Notifier;
+use daemon_rs::otel::SamplerHandle;
+use daemon_rs::pipeline::IngestPipeline;
+use daemon_rs::privileges::{PrivilegeDrop, SocketPermissions};
+use daemon_rs::query::{LogFilter, QueryEngine};
+use daemon_rs::quota::Quotas;
+use daemon_rs::rate_limit::RateLimiter;
+use daemon_rs::retention::{self, RetentionOverride, RetentionPolicy};
+use daemon_rs::schema::{SchemaValidator, SharedSchemas};
+use daemon_rs::schema_infer::{SchemaInference, DEFAULT_MAX_ENUM_VALUES};
+use daemon_rs::schema_registry::SchemaRegistry;
+use daemon_rs::schema_reload::{self, SchemaSources};
+use daemon_rs::server::LogServer;
+use daemon_rs::snapshot;
+use daemon_rs::status::DaemonStatus;
+use daemon_rs::storage::{parse_compression, StorageEngine};
+#[cfg(feature = "otel")]
+use daemon_rs::trace_storage::TraceStorage;
+#[cfg(feature = "otel")]
+use daemon_rs::trace_writer::TraceWriter;
+use daemon_rs::{daemon, handover, otel, sandbox, units};
 
 #[derive(Parser)]
 #[command(name = "daemon_rs")]
@@ -27,142 +61,746 @@ struct Cli {
 }
 
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)] // parsed once at startup
 enum Commands {
-    /// Start the log daemon server
-    Serve {
+    /// Start the log daemon server; flags override the config file, which overrides the defaults
+    Serve(ServeArgs),
+
+    /// Query stored logs
+    Query {
+        /// Storage directory; repeat to query several
+        #[arg(short = 'd', long, default_value = "./logs")]
+        storage: Vec<PathBuf>,
+
+        /// AI API address of another daemon to query, e.g. `http://host-a:9101`; repeatable
+        #[arg(long)]
+        remote: Vec<String>,
+
+        /// Bearer token for the remote daemons
+        #[arg(long)]
+        token: Option<String>,
+
+        /// Filter expression such as `level:error service:checkout timeout`
+        #[arg(short, long)]
+        filter: Option<String>,
+
+        /// Most logs printed when filtering or querying several sources
+        #[arg(short, long, default_value_t = 100)]
+        limit: usize,
+
+        /// Show total count only
+        #[arg(short, long)]
+        count: bool,
+
+        /// Print all logs oldest first, merging files that overlap in time, instead of in file
+        /// order; filtered and multi-source queries are always newest first
+        #[arg(long)]
+        ordered: bool,
+    },
+
+    /// Validate a JSON Schema file
+    ValidateSchema {
+        /// Path to schema file
+        schema: PathBuf,
+    },
+
+    /// Derive a JSON Schema from sample logs to bootstrap stricter validation
+    InferSchema {
+        /// Newline-delimited JSON logs to learn from
+        #[arg(long)]
+        from: PathBuf,
+
+        /// Write the schema here instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Most distinct `level`/`service` values still turned into an `enum`
+        #[arg(long, default_value_t = DEFAULT_MAX_ENUM_VALUES)]
+        max_enum_values: usize,
+    },
+
+    /// Inspect the daemon configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+
+    /// Send a command to a running daemon's admin socket
+    Admin {
+        /// Path to the admin socket
+        #[arg(short, long, default_value = "/tmp/logdaemon-admin.sock")]
+        socket: PathBuf,
+
+        /// flush-now, rotate-now, reload-config, reload-schema, set-log-level <filter>, stats
+        /// or drain
+        #[arg(required = true, num_args = 1..)]
+        command: Vec<String>,
+    },
+
+    /// Send generated logs to a running daemon and report throughput and write latency
+    Bench {
         /// Path to Unix socket
         #[arg(short, long, default_value = "/tmp/logdaemon.sock")]
         socket: PathBuf,
 
-        /// Storage directory for Parquet files
+        /// Target logs per second across all connections [default: as fast as possible]
+        #[arg(long)]
+        rate: Option<u64>,
+
+        /// How long to send, e.g. 60s or 5m
+        #[arg(long, default_value = "10s", value_parser = units::parse_duration)]
+        duration: Duration,
+
+        /// Size of each JSON log in bytes
+        #[arg(long, default_value_t = 512)]
+        payload_bytes: usize,
+
+        /// Connections sending in parallel
+        #[arg(long, default_value_t = 1)]
+        connections: usize,
+    },
+
+    /// Diagnose common setup problems: io_uring support, socket path, storage, ports and schemas
+    Doctor(ServeArgs),
+
+    /// Delete stored logs and traces past a max age or over a max total size
+    Retention {
+        /// Log storage directory
         #[arg(short = 'd', long, default_value = "./logs")]
         storage: PathBuf,
 
-        /// Path to JSON Schema file (optional, uses default if not provided)
-        #[arg(long)]
-        schema: Option<PathBuf>,
+        /// Trace storage directory
+        #[arg(long, default_value = "./traces")]
+        traces: PathBuf,
 
-        /// Batch size for Parquet writes
-        #[arg(short, long, default_value = "1000")]
-        batch_size: usize,
+        /// Delete files last written longer ago than this, e.g. 30d
+        #[arg(long, value_parser = units::parse_duration)]
+        max_age: Option<Duration>,
 
-        /// Compression codec (snappy, zstd, gzip, none)
-        #[arg(short, long, default_value = "snappy")]
-        compression: String,
+        /// Delete the oldest files until logs and traces fit in this, e.g. 50GB
+        #[arg(long, value_parser = units::parse_size)]
+        max_size: Option<u64>,
 
-        /// Maximum concurrent connections
-        #[arg(short, long, default_value = "1000")]
-        max_connections: usize,
+        /// Keep the logs of a service or level for another age than --max-age, e.g.
+        /// service=payments:180d or level=debug:24h; repeatable, the first match wins
+        #[arg(long = "keep")]
+        overrides: Vec<RetentionOverride>,
 
-        /// File rotation size in MB
-        #[arg(short, long, default_value = "100")]
-        rotation_mb: u64,
+        /// Only print what would be deleted
+        #[arg(long)]
+        dry_run: bool,
 
-        /// Flush interval in seconds
-        #[arg(short, long, default_value = "5")]
-        flush_interval: u64,
+        /// Record the deletion in this audit log, e.g. the daemon's `audit_log`
+        #[arg(long)]
+        audit_log: Option<PathBuf>,
+    },
 
-        /// Enable OpenTelemetry tracing
-        #[arg(long, default_value = "true")]
-        otel_enabled: bool,
+    /// Archive the log and trace directories, with their checksums, into a .tar.zst file
+    Snapshot {
+        /// Archive to write, e.g. backup.tar.zst
+        #[arg(long)]
+        out: PathBuf,
 
-        /// OTLP endpoint for trace export (optional)
+        /// Log storage directory
+        #[arg(short = 'd', long, default_value = "./logs")]
+        storage: PathBuf,
+
+        /// Trace storage directory
+        #[arg(long, default_value = "./traces")]
+        traces: PathBuf,
+
+        /// Admin socket of the running daemon, to flush its buffered logs into the snapshot
         #[arg(long)]
-        otel_endpoint: Option<String>,
+        admin_socket: Option<PathBuf>,
 
-        /// Trace sampling rate (0.0 to 1.0)
-        #[arg(long, default_value = "1.0")]
-        otel_sampling_rate: f64,
+        /// zstd compression level, 1 to 22
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(i32).range(1..=22))]
+        level: i32,
+    },
 
-        /// AI API server port
-        #[arg(long, default_value = "9101")]
-        ai_api_port: u16,
+    /// Unpack a snapshot into the log and trace directories, next to the files there
+    Restore {
+        /// Archive written by `snapshot`
+        #[arg(long)]
+        from: PathBuf,
+
+        /// Log storage directory
+        #[arg(short = 'd', long, default_value = "./logs")]
+        storage: PathBuf,
 
         /// Trace storage directory
         #[arg(long, default_value = "./traces")]
-        trace_storage: PathBuf,
+        traces: PathBuf,
     },
 
-    /// Query stored logs
-    Query {
-        /// Storage directory
+    /// Check stored files against their recorded checksums
+    Verify {
+        /// Log storage directory
         #[arg(short = 'd', long, default_value = "./logs")]
         storage: PathBuf,
 
-        /// Show total count only
-        #[arg(short, long)]
-        count: bool,
+        /// Trace storage directory
+        #[arg(long, default_value = "./traces")]
+        traces: PathBuf,
+
+        /// Move files that fail into the `quarantine` directory next to them
+        #[arg(long)]
+        quarantine: bool,
     },
 
-    /// Validate a JSON Schema file
-    ValidateSchema {
-        /// Path to schema file
-        schema: PathBuf,
+    /// Print row groups, column statistics, compression and embedded metadata of Parquet files
+    Inspect {
+        /// Log or trace files
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Synthesize realistic logs, and optionally traces, for sizing tests and demos
+    Generate {
+        /// How many logs to generate, e.g. 50000 or 1M
+        #[arg(long, default_value = "10000", value_parser = units::parse_count)]
+        count: u64,
+
+        /// Number of services the logs come from
+        #[arg(long, default_value_t = 5)]
+        services: usize,
+
+        /// Fraction of logs at error level, from 0.0 to 1.0
+        #[arg(long, default_value_t = 0.05)]
+        error_rate: f64,
+
+        /// Window the timestamps are spread over, ending now
+        #[arg(long, default_value = "1h", value_parser = units::parse_duration)]
+        span: Duration,
+
+        /// Path to Unix socket of the daemon to send the logs to
+        #[arg(short, long, default_value = "/tmp/logdaemon.sock")]
+        socket: PathBuf,
+
+        /// Write straight into this storage directory instead of sending to the daemon
+        #[arg(long)]
+        storage: Option<PathBuf>,
+
+        /// Also write a trace per log into this trace storage directory
+        #[arg(long)]
+        traces: Option<PathBuf>,
+
+        /// Seed for reproducible output
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+
+    /// Migrate Parquet or JSONL files from another system into the store; takes serve's settings
+    Import {
+        /// File or directory to read, searched recursively for .parquet, .jsonl, .ndjson and
+        /// .json files; repeatable
+        #[arg(long, required = true)]
+        from: Vec<PathBuf>,
+
+        /// Store a source field under a daemon field, e.g. level=severity; repeatable
+        #[arg(long, value_parser = import::parse_map)]
+        map: Vec<FieldMapping>,
+
+        #[command(flatten)]
+        args: ServeArgs,
     },
 
-    /// Ingest logs from stdin (for testing)
+    /// Ingest logs from NDJSON/JSON files, or interactively from stdin
     Ingest {
         /// Path to Unix socket
         #[arg(short, long, default_value = "/tmp/logdaemon.sock")]
         socket: PathBuf,
+
+        /// File or glob pattern to load, e.g. 'logs/*.ndjson'; repeatable
+        #[arg(short, long)]
+        file: Vec<String>,
+
+        /// Most logs per second sent from files [default: unlimited]
+        #[arg(long, requires = "file")]
+        rate: Option<u64>,
     },
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Validate the config `serve` would run with, check its paths, ports and schemas, and
+    /// print it
+    Check(ServeArgs),
+}
+
+/// Options of `serve`; each overrides the config file when given
+#[derive(Args, Clone)]
+struct ServeArgs {
+    /// Path to Unix socket [default: /tmp/logdaemon.sock]
+    #[arg(short, long)]
+    socket: Option<PathBuf>,
+
+    /// Storage directory for Parquet files [default: ./logs]
+    #[arg(short = 'd', long)]
+    storage: Option<PathBuf>,
+
+    /// Path to JSON Schema file (optional, uses default if not provided)
+    #[arg(long)]
+    schema: Option<PathBuf>,
+
+    /// Batch size for Parquet writes [default: 1000]
+    #[arg(short, long)]
+    batch_size: Option<usize>,
+
+    /// Compression codec (snappy, zstd, gzip, none), with an optional level such as zstd:7
+    /// [default: snappy]
+    #[arg(short, long)]
+    compression: Option<String>,
+
+    /// Maximum concurrent connections [default: 1000]
+    #[arg(short, long)]
+    max_connections: Option<usize>,
+
+    /// File rotation size in MB [default: 100]
+    #[arg(short, long)]
+    rotation_mb: Option<u64>,
+
+    /// Flush interval in seconds [default: 5]
+    #[arg(short, long)]
+    flush_interval: Option<u64>,
+
+    /// Enable OpenTelemetry tracing and the AI API; `--otel-enabled false` disables them
+    /// [default: true]
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    otel_enabled: Option<bool>,
+
+    /// OTLP endpoint for trace export (optional)
+    #[arg(long)]
+    otel_endpoint: Option<String>,
+
+    /// Trace sampling rate (0.0 to 1.0) [default: 1.0]
+    #[arg(long)]
+    otel_sampling_rate: Option<f64>,
+
+    /// AI API server port on 127.0.0.1 [default: 9101]
+    #[arg(long)]
+    ai_api_port: Option<u16>,
+
+    /// AI API listen address, e.g. 0.0.0.0:9101 (overrides --ai-api-port)
+    #[arg(long)]
+    ai_api_bind: Option<SocketAddr>,
+
+    /// Also serve the gRPC agent API on this address, e.g. 127.0.0.1:9102
+    #[arg(long)]
+    ai_api_grpc_bind: Option<SocketAddr>,
+
+    /// PEM certificate for serving the AI API over HTTPS
+    #[arg(long, requires = "ai_api_tls_key")]
+    ai_api_tls_cert: Option<PathBuf>,
+
+    /// PEM private key for serving the AI API over HTTPS
+    #[arg(long, requires = "ai_api_tls_cert")]
+    ai_api_tls_key: Option<PathBuf>,
+
+    /// Trace storage directory [default: ./traces]
+    #[arg(long)]
+    trace_storage: Option<PathBuf>,
+
+    /// Spans per trace Parquet file [default: 1000]
+    #[arg(long)]
+    trace_batch_size: Option<usize>,
+
+    /// Compression codec of trace files, like --compression [default: snappy]
+    #[arg(long)]
+    trace_compression: Option<String>,
+
+    /// Seconds before a partial batch of spans is written [default: 5]
+    #[arg(long)]
+    trace_flush_interval: Option<u64>,
+
+    /// Prometheus endpoint and health probe listen address (default 0.0.0.0:9100)
+    #[arg(long)]
+    metrics_bind: Option<SocketAddr>,
+
+    /// Disable the Prometheus endpoint and metric collection; the health probes stay up
+    #[arg(long)]
+    no_metrics: bool,
+
+    /// Also push metrics to this Prometheus Pushgateway URL
+    #[arg(long, conflicts_with = "no_metrics")]
+    metrics_push_url: Option<String>,
+
+    /// Log filter for the daemon's own logs, e.g. debug or daemon_rs=debug,warn [default:
+    /// RUST_LOG, then info]
+    #[arg(long)]
+    log_level: Option<String>,
+
+    /// Format of the daemon's own logs on stderr: text or json [default: text]
+    #[arg(long)]
+    log_format: Option<LogFormat>,
+
+    /// Octal mode of the socket, e.g. 0660 [default: from the umask]
+    #[arg(long)]
+    socket_mode: Option<String>,
+
+    /// Owner of the socket as user:group, user or :group, e.g. app:loggers
+    #[arg(long)]
+    socket_owner: Option<String>,
+
+    /// Unix socket for admin commands (flush-now, drain, ...), owner-only [default: disabled]
+    #[arg(long)]
+    admin_socket: Option<PathBuf>,
+
+    /// Append-only file recording admin actions and API auth failures [default: disabled]
+    #[arg(long)]
+    audit_log: Option<PathBuf>,
+
+    /// Switch to this user, by name or id, once the socket is bound (requires root)
+    #[arg(long)]
+    user: Option<String>,
+
+    /// Switch to this group, by name or id, once the socket is bound [default: the user's
+    /// primary group]
+    #[arg(long)]
+    group: Option<String>,
+
+    /// Restrict filesystem access with Landlock and dangerous syscalls with seccomp
+    #[arg(long)]
+    sandbox: bool,
+
+    /// TOML config file, e.g. daemon.toml (or `DAEMON_RS_CONFIG`)
+    #[arg(long)]
+    config: Option<PathBuf>,
+}
+
+impl ServeArgs {
+    /// `--config`, or `DAEMON_RS_CONFIG` when it is not given
+    fn config_path(&self) -> Option<PathBuf> {
+        self.config
+            .clone()
+            .or_else(|| std::env::var_os(CONFIG_PATH_ENV).map(PathBuf::from))
+    }
+
+    /// Directory of the config file; relative paths derived from it resolve there
+    fn config_dir(&self) -> PathBuf {
+        self.config_path()
+            .as_deref()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf)
+            .unwrap_or_default()
+    }
+
+    /// Load the config file and environment, apply the flags over them, and validate the result
+    fn load_config(&self) -> Result<Config> {
+        let mut config = Config::load(self.config_path().as_deref())?;
+        if let Some(socket) = &self.socket {
+            config.socket_path = socket.clone();
+        }
+        if let Some(storage) = &self.storage {
+            config.storage_dir = storage.clone();
+        }
+        if let Some(trace_storage) = &self.trace_storage {
+            config.trace_storage_dir = trace_storage.clone();
+        }
+        if let Some(batch_size) = self.trace_batch_size {
+            config.trace_batch_size = batch_size;
+        }
+        if let Some(compression) = &self.trace_compression {
+            config.trace_compression = compression.clone();
+        }
+        if let Some(flush_interval) = self.trace_flush_interval {
+            config.trace_flush_interval_secs = flush_interval;
+        }
+        if self.schema.is_some() {
+            config.schema_path = self.schema.clone();
+        }
+        if let Some(batch_size) = self.batch_size {
+            config.batch_size = batch_size;
+        }
+        if let Some(compression) = &self.compression {
+            config.compression = compression.clone();
+        }
+        if let Some(max_connections) = self.max_connections {
+            config.max_connections = max_connections;
+        }
+        if let Some(rotation_mb) = self.rotation_mb {
+            config.rotation_size = rotation_mb * 1024 * 1024;
+        }
+        if let Some(flush_interval) = self.flush_interval {
+            config.flush_interval_secs = flush_interval;
+        }
+        if let Some(enabled) = self.otel_enabled {
+            config.otel.enabled = enabled;
+        }
+        if self.otel_endpoint.is_some() {
+            config.otel.endpoint = self.otel_endpoint.clone();
+        }
+        if let Some(rate) = self.otel_sampling_rate {
+            config.otel.sampling_rate = rate;
+        }
+        if self.log_level.is_some() {
+            config.log_level = self.log_level.clone();
+        }
+        if let Some(format) = self.log_format {
+            config.log_format = format;
+        }
+        if self.socket_mode.is_some() {
+            config.socket_mode = self.socket_mode.clone();
+        }
+        if self.socket_owner.is_some() {
+            config.socket_owner = self.socket_owner.clone();
+        }
+        if self.admin_socket.is_some() {
+            config.admin_socket = self.admin_socket.clone();
+        }
+        if self.audit_log.is_some() {
+            config.audit_log = self.audit_log.clone();
+        }
+        if self.user.is_some() {
+            config.user = self.user.clone();
+        }
+        if self.group.is_some() {
+            config.group = self.group.clone();
+        }
+        if self.sandbox {
+            config.sandbox.enabled = true;
+        }
+        if let Some(port) = self.ai_api_port {
+            config.api.bind = Some(SocketAddr::from(([127, 0, 0, 1], port)));
+        }
+        if self.ai_api_bind.is_some() {
+            config.api.bind = self.ai_api_bind;
+        }
+        if self.ai_api_grpc_bind.is_some() {
+            config.api.grpc_bind = self.ai_api_grpc_bind;
+        }
+        if let Some(bind) = self.metrics_bind {
+            config.metrics.bind = bind;
+        }
+        if self.no_metrics {
+            config.metrics.enabled = false;
+        }
+        if let Some(url) = &self.metrics_push_url {
+            match config.metrics.push.as_mut() {
+                Some(push) => push.url = url.clone(),
+                None => config.metrics.push = Some(PushGatewayConfig::new(url.clone())),
+            }
+        }
+        if let (Some(cert_path), Some(key_path)) = (&self.ai_api_tls_cert, &self.ai_api_tls_key) {
+            config.api.tls = Some(TlsConfig {
+                cert_path: cert_path.clone(),
+                key_path: Some(key_path.clone()),
+                key: None,
+            });
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Install the global subscriber: logs through `filter`, plus OpenTelemetry tracing when it is
+/// enabled and built in
+#[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+fn init_tracing(
+    config: &Config,
+    sampler: &SamplerHandle,
+    filter: otel::LogFilterLayer,
+) -> Result<()> {
+    #[cfg(feature = "otel")]
+    if config.otel.enabled {
+        info!("Initializing OpenTelemetry tracing...");
+        let mut storage = TraceStorage::new(
+            config.trace_storage_dir.clone(),
+            parse_compression(&config.trace_compression),
+            config.trace_batch_size,
+        )?
+        .with_redactor(daemon_rs::redaction::AttributeRedactor::from_config(
+            &config.trace_redaction,
+        ))
+        .with_limits(config.trace_limits.clone());
+        if config.trace_colocate {
+            storage = storage
+                .with_trace_grouping(Duration::from_secs(config.trace_colocate_timeout_secs));
+        }
+        let writer = TraceWriter::new(storage);
+        writer.spawn_flusher(Duration::from_secs(config.trace_flush_interval_secs));
+        let subscriber = otel::init_tracing_and_subscriber(
+            "daemon_rs",
+            config.otel.endpoint.clone(),
+            Some(writer),
+            sampler.clone(),
+            filter,
+            config.log_format,
+        )?;
+        tracing::subscriber::set_global_default(subscriber)
+            .expect("Failed to set tracing subscriber");
+        return Ok(());
+    }
+
+    // Standard tracing without OTEL
+    tracing::subscriber::set_global_default(
+        Registry::default()
+            .with(filter)
+            .with(log_format::fmt_layer(config.log_format)),
+    )
+    .expect("Failed to set tracing subscriber");
+    #[cfg(not(feature = "otel"))]
+    if config.otel.enabled && config.otel.endpoint.is_some() {
+        tracing::warn!("Not exporting traces: this build leaves out the `otel` feature");
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    // Landlock and seccomp only bind threads started after them, so the sandbox goes on
+    // before the runtime starts its workers
+    let sandbox = match &cli.command {
+        Commands::Serve(args) => sandbox::apply_for_serve(
+            &args.load_config()?,
+            args.config_path().as_deref(),
+            &args.config_dir(),
+        )?,
+        _ => None,
+    };
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(run(cli, sandbox))
+}
+
+async fn run(cli: Cli, sandbox: Option<sandbox::Applied>) -> Result<()> {
     match cli.command {
-        Commands::Serve {
-            socket,
-            storage,
-            schema,
-            batch_size,
-            compression,
-            max_connections,
-            rotation_mb,
-            flush_interval,
-            otel_enabled,
-            otel_endpoint,
-            otel_sampling_rate,
-            ai_api_port,
-            trace_storage,
-        } => {
+        Commands::Serve(args) => {
             info!("Starting log daemon server...");
 
-            // Initialize OpenTelemetry if enabled
-            if otel_enabled {
-                info!("Initializing OpenTelemetry tracing...");
-                let subscriber = otel::init_tracing_and_subscriber(
-                    "daemon_rs",
-                    otel_endpoint.clone(),
-                    otel_sampling_rate,
-                )?;
-                tracing::subscriber::set_global_default(subscriber)
-                    .expect("Failed to set tracing subscriber");
-            } else {
-                // Standard tracing without OTEL
-                tracing_subscriber::fmt()
-                    .with_env_filter(
-                        tracing_subscriber::EnvFilter::from_default_env()
-                            .add_directive(tracing::Level::INFO.into()),
-                    )
-                    .init();
+            let config_dir = args.config_dir();
+            let config = args.load_config()?;
+            let Config {
+                socket_path: socket,
+                storage_dir: storage,
+                trace_storage_dir: trace_storage,
+                batch_size,
+                compression,
+                max_connections,
+                flush_interval_secs: flush_interval,
+                ..
+            } = config.clone();
+
+            // The log filter and sampling rate can change on SIGHUP
+            let (filter, log_filter) = otel::log_filter(&config.log_filter())?;
+            let sampler = SamplerHandle::new(config.otel.sampling_rate);
+
+            init_tracing(&config, &sampler, filter)?;
+            daemon_rs::payload_log::configure(&config.log_payloads);
+            if let Some(applied) = &sandbox {
+                sandbox::report(applied);
             }
 
-            // Initialize metrics on port 9100
-            crate::metrics::init_metrics(9100).await?;
+            // Live feed shared by the ingest path and the AI API streams
+            let live = LiveFeed::new();
+            // Ingest health shared with the AI API status endpoint and the readiness probe
+            let status = DaemonStatus::new();
+
+            daemon_rs::metrics::init_metrics(
+                config.metrics.clone(),
+                status.clone(),
+                storage.clone(),
+            )
+            .await?;
+            if config.metrics.enabled {
+                daemon_rs::metrics::spawn_self_metrics(live.clone());
+                daemon_rs::metrics::spawn_storage_metrics(vec![
+                    ("logs", storage.clone()),
+                    ("traces", trace_storage.clone()),
+                ]);
+            }
+            if config.scrub.interval_secs > 0 {
+                checksum::spawn_scrubber(
+                    config.scrub.clone(),
+                    vec![("logs", storage.clone()), ("traces", trace_storage.clone())],
+                );
+            }
+
+            // Opened before privileges are dropped, so the file can stay owned by root
+            if let Some(path) = &config.audit_log {
+                audit::init(path)?;
+            }
+
+            // Load the schema files; they are reloaded on SIGHUP or when they change
+            let schema_sources = SchemaSources {
+                default: config.schema_path.clone(),
+                services: config.schemas.clone(),
+            };
+            let router = schema_sources.build()?;
+            let schema_sources = Arc::new(RwLock::new(schema_sources));
+
+            // Schema versions registered over the API override the files above
+            let schemas = SharedSchemas::new(router);
+            let registry_dir = config.registry_dir(&config_dir);
+            let schema_registry = Arc::new(SchemaRegistry::new(registry_dir, schemas.clone()));
+            let restored = schema_registry.load_active()?;
+            if restored > 0 {
+                info!(
+                    "Activated {} registered schema(s) from {:?}",
+                    restored,
+                    schema_registry.dir()
+                );
+            }
+            let schema_reload = schema_reload::spawn_schema_reloader(
+                schema_sources.clone(),
+                schema_registry.clone(),
+            );
+            let rate_limiter = Arc::new(RateLimiter::from_config(&config.api.rate_limit));
+            // TLS keys are loaded again from their sources on every config reload
+            let key_rotation = KeyRotation::default();
+
+            // The API serves no request before the ingest server has dropped privileges
+            let privilege_drop =
+                PrivilegeDrop::resolve(config.user.as_deref(), config.group.as_deref())?
+                    .filter(|target| !target.is_current());
+            let (dropped_tx, dropped_rx) = tokio::sync::oneshot::channel();
 
             // Start AI API server if OTEL is enabled
-            if otel_enabled {
+            #[cfg(feature = "api")]
+            if config.otel.enabled {
+                let api_ready = privilege_drop.is_some().then_some(dropped_rx);
                 let trace_dir = trace_storage.clone();
-                let api_port = ai_api_port;
+                let log_dir = storage.clone();
+                let api_addr = config.api.listen_addr();
+                let api_live = live.clone();
+                let api_status = status.clone();
+                let api_registry = schema_registry.clone();
+                let api_config = config.api.clone();
+                let api_limiter = rate_limiter.clone();
+                let api_key_rotation = key_rotation.clone();
                 tokio::spawn(async move {
-                    if let Err(e) = ai_api::start_api_server(api_port, trace_dir).await {
+                    if let Some(ready) = api_ready {
+                        if ready.await.is_err() {
+                            return;
+                        }
+                    }
+                    if let Err(e) = ai_api::start_api_server(
+                        api_addr,
+                        trace_dir,
+                        log_dir,
+                        api_live,
+                        api_status,
+                        Some(api_registry),
+                        api_config,
+                        api_limiter,
+                        api_key_rotation,
+                    )
+                    .await
+                    {
                         eprintln!("AI API server error: {}", e);
                     }
                 });
-                info!("AI Agent API started on port {}", ai_api_port);
+                info!("AI Agent API started on {}", api_addr);
+            }
+            #[cfg(not(feature = "api"))]
+            {
+                drop(dropped_rx);
+                if config.otel.enabled {
+                    info!("AI Agent API not started: this build leaves out the `api` feature");
+                }
             }
 
             info!("Socket: {:?}", socket);
@@ -170,13 +808,13 @@ async fn main() -> Result<()> {
             info!("Batch size: {}", batch_size);
             info!("Compression: {}", compression);
 
-            // Load or create schema validator
-            let validator = if let Some(schema_path) = schema {
-                info!("Loading schema from {:?}", schema_path);
-                SchemaValidator::from_file(&schema_path)?
+            // Alerts and health events share one notifier; it stops when `serve` returns
+            let notifier = if config.notify.sinks.is_empty() && config.alert_rules.is_none() {
+                None
             } else {
-                info!("Using default schema");
-                SchemaValidator::default_schema()?
+                let notifier = Notifier::start(&config.notify.sinks)?;
+                notifier.watch_health(&config.notify, status.clone(), storage.clone())?;
+                Some(notifier)
             };
 
             // Create storage engine
@@ -184,36 +822,166 @@ async fn main() -> Result<()> {
                 storage,
                 parse_compression(&compression),
                 batch_size,
-                rotation_mb * 1024 * 1024,
-            )?;
+                config.rotation_size,
+            )?
+            .with_partitioning(config.partitioning.clone())
+            .with_write_failures(config.write_failures.clone())?;
 
             // Create and run server (runs with tokio-uring)
             // Note: LogServer::run now blocks the current thread with tokio-uring runtime
-            let server = LogServer::new(socket, validator, max_connections, flush_interval);
+            let mut server = LogServer::new(socket, schemas, max_connections, flush_interval)
+                .with_pipeline(IngestPipeline::from_config(&config.ingest)?)
+                .with_parse_workers(config.ingest.parse_workers)
+                .with_ingest_listeners(&config.ingest, &config.storage_dir)?
+                .with_live_feed(live)
+                .with_status(status.clone())
+                .with_sampling_report(Duration::from_secs(config.ingest.sampling_report_secs))
+                .with_disk_watch(&config.disk_watch, &config.storage_dir, &trace_storage)
+                .with_socket_permissions(SocketPermissions::resolve(
+                    config.socket_mode.as_deref(),
+                    config.socket_owner.as_deref(),
+                )?);
+            if !config.ingest.quotas.is_empty() {
+                server = server.with_quotas(Quotas::new(&config.ingest.quotas, notifier.clone())?);
+            }
+            let mut callbacks = daemon::log_callbacks(&config, notifier.as_ref())?;
+            let forwarder = config.forward.as_ref().map(Forwarder::start).transpose()?;
+            if let Some(forwarder) = &forwarder {
+                callbacks.push(forwarder.on_log());
+            }
+            server = server.with_log_callbacks(callbacks);
+
+            // Hand the directories written after the switch to the unprivileged account
+            let admin_owner = privilege_drop.clone();
+            if let Some(target) = privilege_drop {
+                let mut dirs = vec![
+                    config.storage_dir.clone(),
+                    config.trace_storage_dir.clone(),
+                    config.registry_dir(&config_dir),
+                ];
+                dirs.extend(config.ingest.dead_letter_dirs(&config.storage_dir));
+                dirs.extend(config.forward.as_ref().map(|f| f.spool_dir.clone()));
+                dirs.extend(config.write_failures.wal_dir.clone());
+                for dir in &dirs {
+                    target.own_dir(dir)?;
+                }
+                info!("Dropping privileges to {} after binding the socket", target);
+                server = server.with_privilege_drop(target, dropped_tx);
+            }
+
+            // Reload the config on SIGHUP or admin request, keeping the flags on top
+            let admin_socket = config.admin_socket.clone();
+            let storage_dir = config.storage_dir.clone();
+            #[cfg(feature = "otel")]
+            let tracing_enabled = config.otel.enabled;
+            let config_reload = config_reload::spawn_config_reloader(
+                config,
+                move || args.load_config(),
+                ReloadTargets {
+                    flush_interval: server.flush_interval(),
+                    rate_limiter,
+                    sampler,
+                    log_filter: log_filter.clone(),
+                    schema_sources: schema_sources.clone(),
+                    schema_reload,
+                    key_rotation,
+                },
+            )?;
+
+            // SIGUSR2 hands the sockets to a new process started from the same command line
+            handover::spawn_upgrade_handler(server.control())?;
+
+            if let Some(path) = admin_socket {
+                admin::spawn_admin_server(
+                    &path,
+                    admin_owner.as_ref(),
+                    AdminTargets {
+                        server: server.control(),
+                        config_reload,
+                        schema_sources,
+                        schema_registry,
+                        log_filter,
+                        status,
+                        storage_dir,
+                    },
+                )?;
+            }
 
             // We need to run this outside of the current tokio runtime if we are inside one?
-            // #[tokio::main] creates a runtime. tokio-uring creates its own.
+            // main creates a tokio runtime. tokio-uring creates its own.
             // Nesting tokio-uring inside tokio runtime is tricky.
-            // Ideally we shouldn't use #[tokio::main] if using tokio-uring for the main thread.
+            // Ideally we shouldn't run inside a tokio runtime if using tokio-uring for the main thread.
             // But we need tokio for metrics/CLIs.
 
             // Solution: Spawn the server on a dedicated thread that sets up tokio-uring
             std::thread::spawn(move || {
                 if let Err(e) = server.run(storage_engine) {
                     eprintln!("Server error: {}", e);
+                    audit::flush();
                     std::process::exit(1);
                 }
             })
             .join()
             .expect("Server thread panicked");
+            audit::flush();
+            // The server and its callbacks are gone; send or spool what is still queued
+            if let Some(forwarder) = forwarder {
+                forwarder.finish();
+            }
+            // Export the spans still batched, and write them to the trace storage
+            #[cfg(feature = "otel")]
+            if tracing_enabled {
+                tokio::task::spawn_blocking(otel::shutdown_tracing).await?;
+            }
         }
 
-        Commands::Query { storage, count } => {
-            let query_engine = QueryEngine::new(storage);
+        Commands::Query {
+            mut storage,
+            remote,
+            token,
+            filter,
+            limit,
+            count,
+            ..
+        } if storage.len() > 1 || !remote.is_empty() || filter.is_some() => {
+            let federation = FederationConfig {
+                sources: storage
+                    .drain(1..)
+                    .map(|dir| dir.display().to_string())
+                    .chain(remote)
+                    .collect(),
+                token,
+                ..Default::default()
+            };
+            let query = FederatedQuery::new(storage.remove(0), &federation)?;
+            let filter = filter.as_deref().map(LogFilter::parse).unwrap_or_default();
+            let logs = query.query(&filter, limit).await?;
+            for source in &logs.unavailable_sources {
+                eprintln!("Warning: {} could not be queried", source);
+            }
+            if count {
+                println!("Total logs: {}", logs.total_count);
+            } else {
+                for log in &logs.logs {
+                    println!("{}", serde_json::to_string(log)?);
+                }
+            }
+        }
+
+        Commands::Query {
+            storage,
+            count,
+            ordered,
+            ..
+        } => {
+            let query_engine = QueryEngine::new(storage.into_iter().next().unwrap_or_default());
 
             if count {
                 let total = query_engine.count_logs()?;
                 println!("Total logs: {}", total);
+            } else if ordered {
+                let batches = query_engine.read_ordered()?;
+                query_engine.print_logs(&batches)?;
             } else {
                 let batches = query_engine.read_all()?;
                 query_engine.print_logs(&batches)?;
@@ -226,7 +994,228 @@ async fn main() -> Result<()> {
             println!("✓ Schema is valid");
         }
 
-        Commands::Ingest { socket } => {
+        Commands::InferSchema {
+            from,
+            output,
+            max_enum_values,
+        } => {
+            let file = std::fs::File::open(&from)
+                .with_context(|| format!("Failed to open samples {:?}", from))?;
+            let mut inference = SchemaInference::new(max_enum_values);
+            inference.observe_ndjson(std::io::BufReader::new(file))?;
+            let schema = inference.schema()?;
+
+            let validator = SchemaValidator::from_value(schema.clone())?;
+            eprintln!(
+                "Inferred schema from {} samples ({} lines skipped){}",
+                inference.samples(),
+                inference.skipped(),
+                if validator.uses_fast_path() {
+                    ""
+                } else {
+                    "; it uses the slower full-document validation"
+                }
+            );
+
+            let rendered = serde_json::to_string_pretty(&schema)?;
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, rendered + "\n")
+                        .with_context(|| format!("Failed to write schema {:?}", path))?;
+                    eprintln!("✓ Wrote {:?}", path);
+                }
+                None => println!("{}", rendered),
+            }
+        }
+
+        Commands::Config {
+            command: ConfigCommand::Check(args),
+        } => {
+            let config_dir = args.config_dir();
+            let config = args.load_config()?;
+            print!("{}", config_check::render(&config)?);
+
+            let problems = config_check::check(&config, &config_dir);
+            for problem in &problems {
+                eprintln!("✗ {}", problem);
+            }
+            if !problems.is_empty() {
+                anyhow::bail!("{} problem(s) found", problems.len());
+            }
+            eprintln!("✓ Config is valid");
+        }
+
+        Commands::Admin { socket, command } => {
+            let reply = admin::send(&socket, &command.join(" "))?;
+            match reply["message"].as_str() {
+                Some(message) => println!("{}", message),
+                None => println!("{}", serde_json::to_string_pretty(&reply["status"])?),
+            }
+        }
+
+        Commands::Bench {
+            socket,
+            rate,
+            duration,
+            payload_bytes,
+            connections,
+        } => {
+            let report = bench::run(&BenchConfig {
+                socket_path: socket,
+                rate,
+                duration,
+                payload_bytes,
+                connections,
+            })?;
+            println!("{}", report);
+        }
+
+        Commands::Doctor(args) => {
+            let findings = doctor::diagnose(args.load_config());
+            for finding in &findings {
+                println!("{}", finding);
+            }
+            let count = |severity| findings.iter().filter(|f| f.severity == severity).count();
+            let (failures, warnings) = (count(Severity::Failure), count(Severity::Warning));
+            if failures > 0 {
+                anyhow::bail!("{} problem(s) and {} warning(s) found", failures, warnings);
+            }
+            if warnings > 0 {
+                println!("\n{} warning(s) found, nothing blocks serve", warnings);
+            } else {
+                println!("\n✓ No problems found");
+            }
+        }
+
+        Commands::Retention {
+            storage,
+            traces,
+            max_age,
+            max_size,
+            overrides,
+            dry_run,
+            audit_log,
+        } => {
+            let policy = RetentionPolicy {
+                max_age,
+                max_size,
+                overrides,
+            };
+            let plan = retention::plan_dirs(&storage, &traces, &policy)?;
+            println!("{}", plan);
+            if dry_run {
+                println!("Dry run: nothing deleted");
+            } else {
+                if let Some(path) = &audit_log {
+                    audit::init(path)?;
+                }
+                let result = plan.apply();
+                audit::record(
+                    &audit::local_user(),
+                    "retention.delete",
+                    Outcome::of(&result),
+                    serde_json::json!({
+                        "files": plan.delete.iter().map(|(file, _)| &file.path).collect::<Vec<_>>(),
+                        "bytes": plan.reclaimed(),
+                    }),
+                );
+                audit::flush();
+                result?;
+            }
+        }
+
+        Commands::Snapshot {
+            out,
+            storage,
+            traces,
+            admin_socket,
+            level,
+        } => {
+            if let Some(socket) = &admin_socket {
+                let reply = admin::send(socket, "rotate-now")?;
+                println!("{}", reply["message"].as_str().unwrap_or_default());
+            }
+            let report = snapshot::snapshot(&storage, &traces, &out, level)?;
+            println!("{}", report);
+            println!("Wrote {:?}", out);
+        }
+
+        Commands::Restore {
+            from,
+            storage,
+            traces,
+        } => {
+            let report = snapshot::restore(&from, &storage, &traces)?;
+            println!("{}", report);
+        }
+
+        Commands::Verify {
+            storage,
+            traces,
+            quarantine,
+        } => {
+            let mut bad = 0;
+            for dir in [&storage, &traces] {
+                let report = checksum::verify_dir(dir, quarantine)?;
+                println!("{}:\n{}", dir.display(), report);
+                bad += report.bad().count();
+            }
+            if bad > 0 {
+                anyhow::bail!("{} file(s) failed verification", bad);
+            }
+        }
+
+        Commands::Inspect { files } => {
+            for (i, file) in files.iter().enumerate() {
+                if i > 0 {
+                    println!();
+                }
+                println!("{}", inspect::inspect(file)?);
+            }
+        }
+
+        Commands::Generate {
+            count,
+            services,
+            error_rate,
+            span,
+            socket,
+            storage,
+            traces,
+            seed,
+        } => {
+            let target = match storage {
+                Some(dir) => GenerateTarget::Storage(dir),
+                None => GenerateTarget::Socket(socket),
+            };
+            let report = generate::run(
+                &GenerateConfig {
+                    count,
+                    services,
+                    error_rate,
+                    time_span: span,
+                    trace_storage: traces,
+                    seed,
+                },
+                &target,
+            )?;
+            println!("{}", report);
+        }
+
+        Commands::Import { from, map, args } => {
+            let config = args.load_config()?;
+            let files = import::find_files(&from)?;
+            let report = import::import(&files, &config, &map)?;
+            println!("{} into {:?}", report, config.storage_dir);
+        }
+
+        Commands::Ingest { socket, file, rate } if !file.is_empty() => {
+            let files = file_ingest::expand_patterns(&file)?;
+            let report = file_ingest::ingest_files(&socket, &files, rate)?;
+            println!("{}", report);
+        }
+
+        Commands::Ingest { socket, .. } => {
             use tokio::io::{AsyncBufReadExt, BufReader};
             use tokio::net::UnixStream;
 
@@ -1,29 +1,394 @@
-use anyhow::Result;
-use metrics_exporter_prometheus::PrometheusBuilder;
+use anyhow::{bail, Context, Result};
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, SystemTime};
 use tracing::{info, warn};
 
+use crate::exemplars;
+use crate::live::{LiveFeed, LIVE_CHANNEL_CAPACITY};
+use crate::log_metrics::{ExtractKind, ExtractRule};
+use crate::remote_write::{spawn_remote_write, RemoteWriteConfig};
+use crate::stats::stats;
+use crate::status::DaemonStatus;
+
+/// Port of the Prometheus endpoint unless `[metrics] bind` says otherwise
+pub const DEFAULT_METRICS_PORT: u16 = 9100;
+
+/// Prefix of every metric name below; replaced on export by `MetricsConfig::prefix`
+pub const DEFAULT_METRIC_PREFIX: &str = "log_daemon";
+
 pub const INGEST_COUNT: &str = "log_daemon_ingest_count";
 pub const BYTES_PROCESSED: &str = "log_daemon_bytes_processed";
 pub const DROPPED_MESSAGES: &str = "log_daemon_dropped_messages";
 pub const WRITE_LATENCY: &str = "log_daemon_write_latency_ms";
 pub const ACTIVE_CONNECTIONS: &str = "log_daemon_active_connections";
+pub const INGEST_QUEUE_DEPTH: &str = "log_daemon_ingest_queue_depth";
+pub const FLUSH_DURATION: &str = "log_daemon_flush_duration_seconds";
+pub const FLUSH_ROWS: &str = "log_daemon_flush_rows";
+pub const VALIDATION_FAILURES: &str = "log_daemon_validation_failures";
+pub const FILES_WRITTEN: &str = "log_daemon_files_written";
+pub const BYTES_WRITTEN: &str = "log_daemon_bytes_written";
+pub const PROCESS_RESIDENT_MEMORY: &str = "log_daemon_process_resident_memory_bytes";
+pub const PROCESS_OPEN_FDS: &str = "log_daemon_process_open_fds";
+pub const RUNTIME_ALIVE_TASKS: &str = "log_daemon_runtime_alive_tasks";
+pub const CHANNEL_CAPACITY: &str = "log_daemon_channel_capacity";
+pub const CHANNEL_LEN: &str = "log_daemon_channel_len";
+pub const STORAGE_BYTES: &str = "log_daemon_storage_bytes";
+pub const STORAGE_FILES: &str = "log_daemon_storage_files";
+pub const STORAGE_OLDEST_FILE_AGE: &str = "log_daemon_storage_oldest_file_age_seconds";
+pub const ALERT_RULE_HITS: &str = "log_daemon_alert_rule_hits";
+pub const ALERTS_FIRED: &str = "log_daemon_alerts_fired";
+pub const NOTIFY_FAILURES: &str = "log_daemon_notification_failures";
+pub const EXTRACT_INVALID: &str = "log_daemon_log_metric_invalid_values";
+pub const QUOTA_EXCEEDED: &str = "log_daemon_quota_exceeded";
+pub const QUOTA_DROPPED: &str = "log_daemon_quota_dropped";
+pub const FORWARDED: &str = "log_daemon_forwarded";
+pub const FORWARD_SPOOLED: &str = "log_daemon_forward_spooled";
+pub const FORWARD_DROPPED: &str = "log_daemon_forward_dropped";
+pub const FORWARD_SPOOL_BYTES: &str = "log_daemon_forward_spool_bytes";
+pub const FORWARD_SPOOL_BATCHES: &str = "log_daemon_forward_spool_batches";
+pub const STORAGE_FAILURE_STREAK: &str = "log_daemon_storage_failure_streak";
+pub const STORAGE_WAL_LOGS: &str = "log_daemon_storage_wal_logs";
+pub const STORAGE_WAL_BYTES: &str = "log_daemon_storage_wal_bytes";
+pub const STORAGE_WAL_BATCHES: &str = "log_daemon_storage_wal_batches";
+pub const STORAGE_DROPPED: &str = "log_daemon_storage_dropped";
+pub const QUERY_CACHE: &str = "log_daemon_query_cache_lookups";
+pub const SPANS_TRUNCATED: &str = "log_daemon_spans_truncated";
+pub const DUPLICATE_SPANS: &str = "log_daemon_duplicate_spans";
+pub const SCRUBBED_FILES: &str = "log_daemon_scrubbed_files";
+pub const CORRUPT_FILES: &str = "log_daemon_corrupt_files";
+pub const DISK_PRESSURE: &str = "log_daemon_disk_pressure";
+pub const DISK_DROPPED: &str = "log_daemon_disk_dropped";
+pub const DEAD_LETTER_DROPPED: &str = "log_daemon_dead_letter_dropped";
 
-/// Initialize metrics exporter and signal handler
-pub async fn init_metrics(port: u16) -> Result<()> {
-    // Setup Prometheus exporter
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
-    let builder = PrometheusBuilder::new();
-
-    builder
-        .with_http_listener(addr)
-        .install()
-        .map_err(|e| anyhow::anyhow!("Failed to install Prometheus exporter: {}", e))?;
+/// How often process, runtime and channel gauges are refreshed
+pub const SELF_METRICS_INTERVAL: Duration = Duration::from_secs(10);
 
-    info!(
-        "Metrics endpoint listening on http://0.0.0.0:{}/metrics",
-        port
-    );
+/// How often storage directories are walked for the disk usage gauges
+pub const STORAGE_METRICS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// `job` grouping label used when pushing unless `[metrics.push] job` says otherwise
+pub const DEFAULT_PUSH_JOB: &str = "daemon_rs";
+
+/// Seconds between Pushgateway pushes unless `[metrics.push] interval_secs` says otherwise
+pub const DEFAULT_PUSH_INTERVAL_SECS: u64 = 15;
+
+/// Upper bound on a single push request
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Distinct `service` label values exported before new services are folded into `other`
+pub const MAX_SERVICE_LABELS: usize = 200;
+
+/// Label for logs that do not name a service
+pub const UNKNOWN_SERVICE_LABEL: &str = "unknown";
+
+/// Label shared by services seen after MAX_SERVICE_LABELS was reached
+pub const OTHER_SERVICE_LABEL: &str = "other";
+
+/// Histogram buckets (milliseconds) for WRITE_LATENCY
+pub const WRITE_LATENCY_BUCKETS: &[f64] = &[
+    1.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0,
+];
+
+/// Histogram buckets (seconds) for FLUSH_DURATION
+pub const FLUSH_DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/// Histogram buckets (rows) for FLUSH_ROWS
+pub const FLUSH_ROWS_BUCKETS: &[f64] = &[1.0, 10.0, 100.0, 500.0, 1000.0, 5000.0, 10000.0, 50000.0];
+
+/// Export settings from the `[metrics]` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    /// Serve the Prometheus endpoint and record metrics at all
+    #[serde(default = "default_metrics_enabled")]
+    pub enabled: bool,
+
+    /// Listen address of the `/metrics` endpoint and the health probes
+    #[serde(default = "default_metrics_bind")]
+    pub bind: SocketAddr,
+
+    /// Replaces `log_daemon` at the start of every exported metric name; may be empty
+    #[serde(default = "default_metric_prefix")]
+    pub prefix: String,
+
+    /// Bucket upper bounds (milliseconds) for the write latency histogram
+    #[serde(default = "default_write_latency_buckets")]
+    pub write_latency_buckets: Vec<f64>,
+
+    /// Bucket upper bounds (seconds) for the flush duration histogram
+    #[serde(default = "default_flush_duration_buckets")]
+    pub flush_duration_buckets: Vec<f64>,
+
+    /// Bucket upper bounds (rows) for the rows-per-flush histogram
+    #[serde(default = "default_flush_rows_buckets")]
+    pub flush_rows_buckets: Vec<f64>,
+
+    /// Also push metrics to a Prometheus Pushgateway
+    #[serde(default)]
+    pub push: Option<PushGatewayConfig>,
+
+    /// Metrics extracted from numeric fields of accepted logs
+    #[serde(default)]
+    pub extract: Vec<ExtractRule>,
+
+    /// Also write derived metrics to a Prometheus remote-write endpoint
+    #[serde(default)]
+    pub remote_write: Option<RemoteWriteConfig>,
+}
+
+/// The `[metrics.push]` section, for deployments Prometheus cannot scrape
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PushGatewayConfig {
+    /// Base URL of the Pushgateway, e.g. `http://pushgateway:9091`
+    pub url: String,
+
+    /// Value of the `job` grouping label
+    #[serde(default = "default_push_job")]
+    pub job: String,
+
+    /// Optional `instance` grouping label, so several daemons can push under one job
+    #[serde(default)]
+    pub instance: Option<String>,
+
+    /// Seconds between pushes
+    #[serde(default = "default_push_interval_secs")]
+    pub interval_secs: u64,
+}
+
+impl PushGatewayConfig {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            job: default_push_job(),
+            instance: None,
+            interval_secs: default_push_interval_secs(),
+        }
+    }
+
+    /// Endpoint of this daemon's metric group, `<url>/metrics/job/<job>[/instance/<instance>]`
+    pub fn group_url(&self) -> String {
+        let mut url = format!(
+            "{}/metrics/job/{}",
+            self.url.trim_end_matches('/'),
+            self.job
+        );
+        if let Some(instance) = &self.instance {
+            url.push_str("/instance/");
+            url.push_str(instance);
+        }
+        url
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(self.url.starts_with("http://") || self.url.starts_with("https://")) {
+            bail!(
+                "metrics.push.url must be an http:// or https:// URL: {}",
+                self.url
+            );
+        }
+        // Grouping label values are path segments of the push URL
+        let valid_label = |value: &str| {
+            !value.is_empty()
+                && value
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | ':'))
+        };
+        if !valid_label(&self.job) {
+            bail!(
+                "metrics.push.job must be non-empty and URL-safe: {}",
+                self.job
+            );
+        }
+        if let Some(instance) = self.instance.as_deref().filter(|i| !valid_label(i)) {
+            bail!(
+                "metrics.push.instance must be non-empty and URL-safe: {}",
+                instance
+            );
+        }
+        if self.interval_secs == 0 {
+            bail!("metrics.push.interval_secs must be at least 1");
+        }
+        Ok(())
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_metrics_enabled(),
+            bind: default_metrics_bind(),
+            prefix: default_metric_prefix(),
+            write_latency_buckets: default_write_latency_buckets(),
+            flush_duration_buckets: default_flush_duration_buckets(),
+            flush_rows_buckets: default_flush_rows_buckets(),
+            push: None,
+            extract: Vec::new(),
+            remote_write: None,
+        }
+    }
+}
+
+fn default_metrics_enabled() -> bool {
+    true
+}
+
+fn default_metrics_bind() -> SocketAddr {
+    SocketAddr::from(([0, 0, 0, 0], DEFAULT_METRICS_PORT))
+}
+
+fn default_metric_prefix() -> String {
+    DEFAULT_METRIC_PREFIX.to_string()
+}
+
+fn default_write_latency_buckets() -> Vec<f64> {
+    WRITE_LATENCY_BUCKETS.to_vec()
+}
+
+fn default_flush_duration_buckets() -> Vec<f64> {
+    FLUSH_DURATION_BUCKETS.to_vec()
+}
+
+fn default_flush_rows_buckets() -> Vec<f64> {
+    FLUSH_ROWS_BUCKETS.to_vec()
+}
+
+fn default_push_job() -> String {
+    DEFAULT_PUSH_JOB.to_string()
+}
+
+fn default_push_interval_secs() -> u64 {
+    DEFAULT_PUSH_INTERVAL_SECS
+}
+
+impl MetricsConfig {
+    pub fn validate(&self) -> Result<()> {
+        let valid_prefix = self.prefix.chars().enumerate().all(|(i, c)| {
+            c.is_ascii_alphabetic() || c == '_' || c == ':' || (i > 0 && c.is_ascii_digit())
+        });
+        if !valid_prefix {
+            bail!(
+                "metrics.prefix must be a valid Prometheus metric name prefix: {}",
+                self.prefix
+            );
+        }
+
+        for (name, buckets) in [
+            ("write_latency_buckets", &self.write_latency_buckets),
+            ("flush_duration_buckets", &self.flush_duration_buckets),
+            ("flush_rows_buckets", &self.flush_rows_buckets),
+        ] {
+            if buckets.is_empty()
+                || buckets.iter().any(|b| !b.is_finite())
+                || buckets.windows(2).any(|w| w[0] >= w[1])
+            {
+                bail!(
+                    "metrics.{} must be a non-empty, strictly increasing list of finite bounds",
+                    name
+                );
+            }
+        }
+
+        if let Some(push) = &self.push {
+            push.validate()?;
+        }
+
+        let mut names = HashSet::new();
+        for rule in &self.extract {
+            rule.validate()?;
+            if !names.insert(rule.name.as_str()) {
+                bail!("metrics.extract: duplicate metric {}", rule.name);
+            }
+        }
+
+        if let Some(remote_write) = &self.remote_write {
+            remote_write.validate()?;
+            if remote_write.include.is_empty() && self.extract.is_empty() {
+                bail!("metrics.remote_write needs include patterns or [[metrics.extract]] rules");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Write latency bounds in effect, used to place exemplars in the right bucket
+static WRITE_LATENCY_BOUNDS: OnceLock<Vec<f64>> = OnceLock::new();
+
+fn write_latency_bounds() -> &'static [f64] {
+    WRITE_LATENCY_BOUNDS.get_or_init(|| WRITE_LATENCY_BUCKETS.to_vec())
+}
+
+/// Swap DEFAULT_METRIC_PREFIX for `prefix` at the start of a metric name
+pub(crate) fn rename_prefix<'a>(name: &'a str, prefix: &str) -> std::borrow::Cow<'a, str> {
+    match name.strip_prefix(DEFAULT_METRIC_PREFIX) {
+        Some(rest) if prefix != DEFAULT_METRIC_PREFIX => {
+            // An empty prefix also drops the separator
+            let rest = if prefix.is_empty() {
+                rest.trim_start_matches('_')
+            } else {
+                rest
+            };
+            format!("{}{}", prefix, rest).into()
+        }
+        _ => name.into(),
+    }
+}
+
+/// Apply the configured prefix to every sample and `# HELP`/`# TYPE` line of an exposition
+pub(crate) fn rename_exposition(text: &str, prefix: &str) -> String {
+    if prefix == DEFAULT_METRIC_PREFIX {
+        return text.to_string();
+    }
+
+    let mut output = String::with_capacity(text.len());
+    for line in text.lines() {
+        let (comment, rest) = match line.get(..7) {
+            Some("# HELP " | "# TYPE ") => line.split_at(7),
+            _ => ("", line),
+        };
+        output.push_str(comment);
+        output.push_str(&rename_prefix(rest, prefix));
+        output.push('\n');
+    }
+    output
+}
+
+#[derive(Clone)]
+struct ScrapeState {
+    handle: PrometheusHandle,
+    prefix: Arc<str>,
+}
+
+/// What the health probes look at; served even with metrics turned off
+#[derive(Clone)]
+struct ProbeState {
+    status: DaemonStatus,
+    storage_dir: Arc<Path>,
+}
+
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Initialize metrics exporter and signal handler
+pub async fn init_metrics(
+    config: MetricsConfig,
+    status: DaemonStatus,
+    storage_dir: PathBuf,
+) -> Result<()> {
+    config.validate()?;
 
     // Spawn signal handler for SIGUSR1 to dump stats to log
     tokio::spawn(async move {
@@ -32,9 +397,689 @@ pub async fn init_metrics(port: u16) -> Result<()> {
         }
     });
 
+    let addr = config.bind;
+    let probes = Router::new()
+        .route("/healthz", get(liveness))
+        .route("/readyz", get(readiness))
+        .with_state(ProbeState {
+            status,
+            storage_dir: storage_dir.into(),
+        });
+    let app = if config.enabled {
+        probes.merge(install_recorder(config)?)
+    } else {
+        // Without an installed recorder the metric macros are no-ops
+        info!("Metrics disabled; serving only the health probes");
+        probes
+    };
+    let listener = crate::handover::bind_tcp("metrics", addr)
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to bind metrics endpoint on {} (change it with --metrics-bind)",
+                addr
+            )
+        })?;
+
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            warn!("Metrics endpoint error: {}", e);
+        }
+    });
+
+    info!("Metrics endpoint listening on http://{}/metrics", addr);
+
     Ok(())
 }
 
+/// Install the Prometheus recorder and its pushers; the `/metrics` route serving it
+fn install_recorder(config: MetricsConfig) -> Result<Router> {
+    let _ = WRITE_LATENCY_BOUNDS.set(config.write_latency_buckets.clone());
+
+    // Setup Prometheus recorder; the scrape endpoint is served below so exemplars can be added
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full(WRITE_LATENCY.to_string()),
+            &config.write_latency_buckets,
+        )
+        .and_then(|b| {
+            b.set_buckets_for_metric(
+                Matcher::Full(FLUSH_DURATION.to_string()),
+                &config.flush_duration_buckets,
+            )
+        })
+        .and_then(|b| {
+            b.set_buckets_for_metric(
+                Matcher::Full(FLUSH_ROWS.to_string()),
+                &config.flush_rows_buckets,
+            )
+        })
+        .and_then(|b| {
+            config
+                .extract
+                .iter()
+                .filter(|rule| rule.kind == ExtractKind::Histogram)
+                .try_fold(b, |b, rule| {
+                    b.set_buckets_for_metric(
+                        Matcher::Full(rule.metric_name()),
+                        rule.bucket_bounds(),
+                    )
+                })
+        })
+        .map_err(|e| anyhow::anyhow!("Invalid histogram buckets: {}", e))?
+        .install_recorder()
+        .map_err(|e| anyhow::anyhow!("Failed to install Prometheus exporter: {}", e))?;
+    describe_metrics();
+    for rule in &config.extract {
+        if let Some(help) = rule.help.clone() {
+            match rule.kind {
+                ExtractKind::Histogram => metrics::describe_histogram!(rule.metric_name(), help),
+                ExtractKind::Counter => metrics::describe_counter!(rule.metric_name(), help),
+                ExtractKind::Gauge => metrics::describe_gauge!(rule.metric_name(), help),
+            }
+        }
+    }
+
+    if let Some(push) = config.push.clone() {
+        spawn_pushgateway(push, handle.clone(), config.prefix.clone())?;
+    }
+    if let Some(remote_write) = config.remote_write.clone() {
+        let extracted: Vec<String> = config.extract.iter().map(|r| r.metric_name()).collect();
+        spawn_remote_write(
+            remote_write,
+            handle.clone(),
+            config.prefix.clone(),
+            &extracted,
+        )?;
+    }
+
+    Ok(Router::new()
+        .route("/metrics", get(render_metrics))
+        .with_state(ScrapeState {
+            handle,
+            prefix: config.prefix.into(),
+        }))
+}
+
+/// Liveness probe: the process is up and its runtime is serving requests
+async fn liveness() -> &'static str {
+    "ok"
+}
+
+/// Readiness probe: socket bound, storage writable and ingest queue not saturated
+async fn readiness(State(state): State<ProbeState>) -> (StatusCode, String) {
+    let problems = state.status.readiness_problems(&state.storage_dir);
+    if problems.is_empty() {
+        (StatusCode::OK, "ready\n".to_string())
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("not ready\n{}\n", problems.join("\n")),
+        )
+    }
+}
+
+/// HELP text for every metric the daemon records
+fn describe_metrics() {
+    use metrics::{describe_counter, describe_gauge, describe_histogram, Unit};
+
+    describe_counter!(INGEST_COUNT, "Logs accepted into the ingest queue");
+    describe_counter!(
+        BYTES_PROCESSED,
+        Unit::Bytes,
+        "Bytes of accepted log messages"
+    );
+    describe_counter!(
+        DROPPED_MESSAGES,
+        "Logs dropped because the ingest queue was full"
+    );
+    describe_histogram!(
+        WRITE_LATENCY,
+        Unit::Milliseconds,
+        "Time spent writing a batch to a Parquet file"
+    );
+    describe_gauge!(ACTIVE_CONNECTIONS, "Connected ingest clients");
+    describe_gauge!(
+        INGEST_QUEUE_DEPTH,
+        "Logs waiting in the ingest queue for the storage task"
+    );
+    describe_histogram!(
+        FLUSH_DURATION,
+        Unit::Seconds,
+        "Time to convert and write a batch, per flush"
+    );
+    describe_histogram!(FLUSH_ROWS, "Rows written per flush");
+    describe_counter!(
+        VALIDATION_FAILURES,
+        "Messages rejected by parsing or schema validation, by reason"
+    );
+    describe_counter!(FILES_WRITTEN, "Parquet files written");
+    describe_counter!(BYTES_WRITTEN, Unit::Bytes, "Bytes of Parquet files written");
+    describe_gauge!(
+        PROCESS_RESIDENT_MEMORY,
+        Unit::Bytes,
+        "Resident set size of the daemon process"
+    );
+    describe_gauge!(
+        PROCESS_OPEN_FDS,
+        "Open file descriptors of the daemon process"
+    );
+    describe_gauge!(
+        RUNTIME_ALIVE_TASKS,
+        "Tasks alive on each async runtime (`main` or the io_uring ingest `uring`)"
+    );
+    describe_gauge!(
+        CHANNEL_CAPACITY,
+        "Capacity of internal channels, by channel"
+    );
+    describe_gauge!(
+        CHANNEL_LEN,
+        "Messages buffered in internal channels, by channel"
+    );
+    describe_gauge!(STORAGE_BYTES, Unit::Bytes, "Bytes stored, by store");
+    describe_gauge!(STORAGE_FILES, "Files stored, by store");
+    describe_gauge!(
+        STORAGE_OLDEST_FILE_AGE,
+        Unit::Seconds,
+        "Age of the oldest stored file, by store"
+    );
+    describe_counter!(ALERT_RULE_HITS, "Logs matching an alert rule, by rule");
+    describe_counter!(ALERTS_FIRED, "Alerts fired, by rule");
+    describe_counter!(
+        NOTIFY_FAILURES,
+        "Notifications that could not be delivered, by sink and kind"
+    );
+    describe_counter!(
+        EXTRACT_INVALID,
+        "Log fields named by a metrics.extract rule that held no usable number, by metric"
+    );
+    describe_counter!(
+        QUOTA_EXCEEDED,
+        "Quota periods in which a service went over its quota, by service and action"
+    );
+    describe_counter!(QUOTA_DROPPED, "Logs dropped over quota, by service");
+    describe_counter!(FORWARDED, "Logs sent to the forwarding upstream");
+    describe_counter!(
+        FORWARD_SPOOLED,
+        "Logs spooled to disk while the forwarding upstream was unreachable"
+    );
+    describe_counter!(
+        FORWARD_DROPPED,
+        "Logs never forwarded, by reason (queue_full, spool_full, spool_error, rejected)"
+    );
+    describe_gauge!(
+        FORWARD_SPOOL_BYTES,
+        Unit::Bytes,
+        "Size of the forwarding spool"
+    );
+    describe_gauge!(FORWARD_SPOOL_BATCHES, "Batches in the forwarding spool");
+    describe_gauge!(
+        STORAGE_FAILURE_STREAK,
+        "Storage write attempts failed in a row, 0 once a write succeeds"
+    );
+    describe_counter!(
+        STORAGE_WAL_LOGS,
+        "Logs written to the write-ahead log while storage writes failed"
+    );
+    describe_gauge!(
+        STORAGE_WAL_BYTES,
+        Unit::Bytes,
+        "Size of the storage write-ahead log"
+    );
+    describe_gauge!(
+        STORAGE_WAL_BATCHES,
+        "Batches in the storage write-ahead log"
+    );
+    describe_counter!(
+        STORAGE_DROPPED,
+        "Logs never stored, by reason (buffer_full, wal_full)"
+    );
+    describe_counter!(
+        QUERY_CACHE,
+        "Query cache lookups of a file's matching logs, by result (hit, miss)"
+    );
+    describe_counter!(
+        SPANS_TRUNCATED,
+        "Span attributes and events dropped and attribute values cut to fit the span limits, by limit"
+    );
+    describe_counter!(
+        DUPLICATE_SPANS,
+        "Spans received again with a span id already stored or queued, and dropped"
+    );
+    describe_counter!(
+        SCRUBBED_FILES,
+        "Stored files checked against their recorded checksums by the scrubber, by store"
+    );
+    describe_counter!(
+        CORRUPT_FILES,
+        "Stored files failing verification, by store and reason (size, checksum, unreadable)"
+    );
+    describe_gauge!(
+        DISK_PRESSURE,
+        "Disk watchdog level: 0 normal, 1 shedding debug logs, 2 cleaning up, 3 rejecting logs"
+    );
+    describe_counter!(
+        DISK_DROPPED,
+        "Logs dropped or rejected at ingest to spare the storage volume, by service and pressure"
+    );
+    describe_counter!(
+        DEAD_LETTER_DROPPED,
+        "Dead-letter records dropped because the writer fell behind, by listener"
+    );
+}
+
+/// Periodically PUT the text exposition to the Pushgateway, replacing the previous push
+fn spawn_pushgateway(
+    config: PushGatewayConfig,
+    handle: PrometheusHandle,
+    prefix: String,
+) -> Result<()> {
+    let client = reqwest::Client::builder()
+        .timeout(PUSH_TIMEOUT)
+        .build()
+        .context("Failed to build Pushgateway client")?;
+    let url = config.group_url();
+    info!("Pushing metrics to {} every {}s", url, config.interval_secs);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(config.interval_secs));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // Only log the first failure of a run so an unreachable gateway does not flood the log
+        let mut failing = false;
+        loop {
+            interval.tick().await;
+            let result = client
+                .put(&url)
+                .header(reqwest::header::CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)
+                .body(rename_exposition(&handle.render(), &prefix))
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            match result {
+                Ok(_) if failing => {
+                    info!("Pushgateway push to {} succeeded again", url);
+                    failing = false;
+                }
+                Ok(_) => {}
+                Err(e) if !failing => {
+                    warn!("Pushgateway push to {} failed: {}", url, e);
+                    failing = true;
+                }
+                Err(_) => {}
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Scrape endpoint; OpenMetrics (with exemplars) when the scraper asks for it
+async fn render_metrics(
+    State(ScrapeState { handle, prefix, .. }): State<ScrapeState>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let wants_openmetrics = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.contains("application/openmetrics-text"))
+        .unwrap_or(false);
+
+    if wants_openmetrics {
+        // Exemplars are keyed by the recorded names, so rename after attaching them
+        let body = exemplars::render_openmetrics(&handle.render(), &exemplars::snapshot());
+        (
+            [(header::CONTENT_TYPE, OPENMETRICS_CONTENT_TYPE)],
+            rename_exposition(&body, &prefix),
+        )
+    } else {
+        (
+            [(header::CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)],
+            rename_exposition(&handle.render(), &prefix),
+        )
+    }
+}
+
+/// Periodically export process memory and descriptors, main runtime tasks and live feed buffers
+pub fn spawn_self_metrics(live: LiveFeed) {
+    for channel in ["live_logs", "live_traces"] {
+        metrics::gauge!(CHANNEL_CAPACITY, LIVE_CHANNEL_CAPACITY as f64, "channel" => channel);
+    }
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(SELF_METRICS_INTERVAL);
+        loop {
+            ticker.tick().await;
+            if let Some(bytes) = resident_memory_bytes() {
+                metrics::gauge!(PROCESS_RESIDENT_MEMORY, bytes as f64);
+            }
+            if let Some(fds) = open_fds() {
+                metrics::gauge!(PROCESS_OPEN_FDS, fds as f64);
+            }
+            record_runtime_tasks("main");
+
+            let (logs, traces) = live.queued();
+            metrics::gauge!(CHANNEL_LEN, logs as f64, "channel" => "live_logs");
+            metrics::gauge!(CHANNEL_LEN, traces as f64, "channel" => "live_traces");
+        }
+    });
+}
+
+/// Export the alive task count of the runtime the caller is running on
+pub fn record_runtime_tasks(runtime: &'static str) {
+    let tasks = tokio::runtime::Handle::current()
+        .metrics()
+        .num_alive_tasks();
+    metrics::gauge!(RUNTIME_ALIVE_TASKS, tasks as f64, "runtime" => runtime);
+}
+
+/// Resident set size from `/proc/self/statm`
+fn resident_memory_bytes() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    // SAFETY: sysconf has no preconditions
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    (page_size > 0).then(|| pages * page_size as u64)
+}
+
+/// Entries of `/proc/self/fd`, minus the descriptor used to list it
+fn open_fds() -> Option<usize> {
+    let count = std::fs::read_dir("/proc/self/fd").ok()?.count();
+    Some(count.saturating_sub(1))
+}
+
+/// Size of a storage directory tree
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DirUsage {
+    pub bytes: u64,
+    pub files: u64,
+    /// Modification time of the least recently written file
+    pub oldest: Option<SystemTime>,
+}
+
+/// Walk `dir` recursively; a missing directory is empty
+pub fn measure_dir(dir: &Path) -> std::io::Result<DirUsage> {
+    let mut usage = DirUsage::default();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else if metadata.is_file() {
+                usage.bytes += metadata.len();
+                usage.files += 1;
+                if let Ok(modified) = metadata.modified() {
+                    usage.oldest = Some(usage.oldest.map_or(modified, |o| o.min(modified)));
+                }
+            }
+        }
+    }
+    Ok(usage)
+}
+
+/// Periodically export size, file count and oldest file age of each `(store, directory)`
+pub fn spawn_storage_metrics(stores: Vec<(&'static str, PathBuf)>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(STORAGE_METRICS_INTERVAL);
+        loop {
+            ticker.tick().await;
+            for (store, dir) in &stores {
+                let path = dir.clone();
+                let usage = match tokio::task::spawn_blocking(move || measure_dir(&path)).await {
+                    Ok(Ok(usage)) => usage,
+                    Ok(Err(e)) => {
+                        warn!("Failed to measure {:?}: {}", dir, e);
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!("Storage measurement task failed: {}", e);
+                        continue;
+                    }
+                };
+
+                metrics::gauge!(STORAGE_BYTES, usage.bytes as f64, "store" => *store);
+                metrics::gauge!(STORAGE_FILES, usage.files as f64, "store" => *store);
+                let age = usage
+                    .oldest
+                    .and_then(|oldest| SystemTime::now().duration_since(oldest).ok())
+                    .unwrap_or_default();
+                metrics::gauge!(STORAGE_OLDEST_FILE_AGE, age.as_secs_f64(), "store" => *store);
+            }
+        }
+    });
+}
+
+/// Increment a counter and link it to the current trace
+pub fn increment_counter(name: &'static str, value: u64) {
+    metrics::counter!(name, value);
+    exemplars::record_counter(name, value);
+}
+
+/// Increment a counter for one producing service and link it to the current trace
+pub fn increment_service_counter(name: &'static str, service: &str, value: u64) {
+    metrics::counter!(name, value, "service" => service.to_string());
+    exemplars::record_counter(&format!("{}{{service=\"{}\"}}", name, service), value);
+}
+
+/// Count a log accepted into the ingest queue
+pub fn record_ingested(service: &str, bytes: u64) {
+    increment_service_counter(INGEST_COUNT, service, 1);
+    increment_service_counter(BYTES_PROCESSED, service, bytes);
+    stats().record_ingested(bytes);
+}
+
+/// Count a log dropped because the ingest queue was full
+pub fn record_dropped(service: &str) {
+    increment_service_counter(DROPPED_MESSAGES, service, 1);
+    stats().record_dropped();
+}
+
+pub fn record_validation_failure(reason: &'static str) {
+    metrics::counter!(VALIDATION_FAILURES, 1, "reason" => reason);
+    stats().record_validation_failure();
+}
+
+pub fn connection_opened() {
+    metrics::increment_gauge!(ACTIVE_CONNECTIONS, 1.0);
+    stats().connection_opened();
+}
+
+pub fn connection_closed() {
+    metrics::decrement_gauge!(ACTIVE_CONNECTIONS, 1.0);
+    stats().connection_closed();
+}
+
+/// Record a flush of `rows` rows into one file of `file_bytes` bytes
+pub fn record_flush(rows: usize, duration: Duration, file_bytes: u64) {
+    metrics::histogram!(FLUSH_DURATION, duration.as_secs_f64());
+    metrics::histogram!(FLUSH_ROWS, rows as f64);
+    metrics::counter!(FILES_WRITTEN, 1);
+    metrics::counter!(BYTES_WRITTEN, file_bytes);
+    stats().record_flush(rows as u64, file_bytes, SystemTime::now());
+}
+
+/// Count a log matching an alert rule
+pub fn record_alert_rule_hit(rule: &str) {
+    metrics::counter!(ALERT_RULE_HITS, 1, "rule" => rule.to_string());
+}
+
+pub fn record_alert_fired(rule: &str) {
+    metrics::counter!(ALERTS_FIRED, 1, "rule" => rule.to_string());
+}
+
+/// Count a notification that could not be delivered to a sink
+pub fn record_notify_failure(sink: &str, kind: &'static str) {
+    metrics::counter!(NOTIFY_FAILURES, 1, "sink" => sink.to_string(), "kind" => kind);
+}
+
+/// Count an extracted value that was not a usable number
+pub fn record_extract_invalid(metric: &str) {
+    metrics::counter!(EXTRACT_INVALID, 1, "metric" => metric.to_string());
+}
+
+/// Count a service going over a quota for the rest of the period
+pub fn record_quota_exceeded(service: &str, action: &'static str) {
+    metrics::counter!(QUOTA_EXCEEDED, 1, "service" => service.to_string(), "action" => action);
+}
+
+pub fn record_quota_dropped(service: &str) {
+    metrics::counter!(QUOTA_DROPPED, 1, "service" => service.to_string());
+}
+
+pub fn record_forwarded(logs: u64) {
+    metrics::counter!(FORWARDED, logs);
+}
+
+pub fn record_forward_spooled(logs: u64) {
+    metrics::counter!(FORWARD_SPOOLED, logs);
+}
+
+pub fn record_forward_dropped(reason: &'static str, logs: u64) {
+    metrics::counter!(FORWARD_DROPPED, logs, "reason" => reason);
+}
+
+pub fn record_spool_size(bytes: u64, batches: usize) {
+    metrics::gauge!(FORWARD_SPOOL_BYTES, bytes as f64);
+    metrics::gauge!(FORWARD_SPOOL_BATCHES, batches as f64);
+}
+
+pub fn record_storage_failure_streak(streak: u32) {
+    metrics::gauge!(STORAGE_FAILURE_STREAK, streak as f64);
+}
+
+pub fn record_storage_wal_logs(logs: u64) {
+    metrics::counter!(STORAGE_WAL_LOGS, logs);
+}
+
+pub fn record_storage_dropped(reason: &'static str, logs: u64) {
+    metrics::counter!(STORAGE_DROPPED, logs, "reason" => reason);
+}
+
+pub fn record_query_cache(hit: bool) {
+    metrics::counter!(QUERY_CACHE, 1, "result" => if hit { "hit" } else { "miss" });
+}
+
+pub fn record_span_truncated(limit: &'static str, count: u64) {
+    metrics::counter!(SPANS_TRUNCATED, count, "limit" => limit);
+}
+
+pub fn record_duplicate_spans(count: u64) {
+    metrics::counter!(DUPLICATE_SPANS, count);
+}
+
+pub fn record_scrubbed_files(store: &'static str, count: u64) {
+    metrics::counter!(SCRUBBED_FILES, count, "store" => store);
+}
+
+pub fn record_corrupt_file(store: &'static str, reason: &'static str) {
+    metrics::counter!(CORRUPT_FILES, 1, "store" => store, "reason" => reason);
+}
+
+pub fn record_disk_pressure(pressure: crate::disk_watch::DiskPressure) {
+    metrics::gauge!(DISK_PRESSURE, pressure.as_u8() as f64);
+}
+
+/// Count a log dropped at ingest under disk `pressure`
+pub fn record_disk_dropped(service: &str, pressure: crate::disk_watch::DiskPressure) {
+    metrics::counter!(DISK_DROPPED, 1, "service" => service.to_string(), "pressure" => pressure.as_str());
+}
+
+/// Count a dead-letter record of `listener` dropped because its writer fell behind
+pub fn record_dead_letter_dropped(listener: &str) {
+    metrics::counter!(DEAD_LETTER_DROPPED, 1, "listener" => listener.to_string());
+}
+
+pub fn record_storage_wal_size(bytes: u64, batches: usize) {
+    metrics::gauge!(STORAGE_WAL_BYTES, bytes as f64);
+    metrics::gauge!(STORAGE_WAL_BATCHES, batches as f64);
+}
+
+/// Bounded set of `service` label values, so a misbehaving producer cannot explode cardinality
+pub struct ServiceLabels {
+    max: usize,
+    seen: RwLock<HashSet<String>>,
+}
+
+impl ServiceLabels {
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            seen: RwLock::new(HashSet::new()),
+        }
+    }
+
+    /// Label value for a log's `service` field
+    pub fn label(&self, service: Option<&str>) -> String {
+        let Some(service) = service.filter(|s| !s.is_empty()) else {
+            return UNKNOWN_SERVICE_LABEL.to_string();
+        };
+        // Keep values safe to splice into exemplar series keys
+        let service: String = service
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' | '.' | '/' | ':' => c,
+                _ => '_',
+            })
+            .collect();
+
+        if self
+            .seen
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains(&service)
+        {
+            return service;
+        }
+
+        let mut seen = self.seen.write().unwrap_or_else(|e| e.into_inner());
+        if seen.len() < self.max || seen.contains(&service) {
+            seen.insert(service.clone());
+            service
+        } else {
+            OTHER_SERVICE_LABEL.to_string()
+        }
+    }
+}
+
+/// Process-wide `service` labels for ingest metrics
+pub fn service_label(service: Option<&str>) -> String {
+    static LABELS: OnceLock<ServiceLabels> = OnceLock::new();
+    LABELS
+        .get_or_init(|| ServiceLabels::new(MAX_SERVICE_LABELS))
+        .label(service)
+}
+
+/// Services shown in a span's `services` field
+const SPAN_SERVICES: usize = 5;
+
+/// Log counts by service for a span field, most logs first: `api=120 web=30 (+2 more)`
+pub fn service_summary<'a>(counts: impl IntoIterator<Item = (&'a str, u64)>) -> String {
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    let mut summary = counts
+        .iter()
+        .take(SPAN_SERVICES)
+        .map(|(service, logs)| format!("{}={}", service, logs))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if counts.len() > SPAN_SERVICES {
+        summary.push_str(&format!(" (+{} more)", counts.len() - SPAN_SERVICES));
+    }
+    summary
+}
+
+/// Record a WRITE_LATENCY observation and link its bucket to the current trace
+pub fn record_write_latency(value_ms: f64) {
+    metrics::histogram!(WRITE_LATENCY, value_ms);
+    exemplars::record_histogram(WRITE_LATENCY, write_latency_bounds(), value_ms);
+}
+
 #[cfg(unix)]
 async fn handle_signals() -> Result<()> {
     use tokio::signal::unix::{signal, SignalKind};
@@ -55,8 +1100,97 @@ async fn handle_signals() -> Result<()> {
 }
 
 fn dump_stats() {
-    // getting metrics values is a bit complex with the generic facade,
-    // so we'll just log that we received the signal for now
-    // In a real app we might maintain a separate atomic counter for easy dumping
-    info!("Received SIGUSR1: Metrics are available at /metrics endpoint");
+    info!("Received SIGUSR1\n{}", stats().snapshot());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_renames_exposition() {
+        let text = "# HELP log_daemon_flush_rows Rows\n# TYPE log_daemon_flush_rows histogram\nlog_daemon_flush_rows_bucket{le=\"10\"} 1\n";
+        assert_eq!(
+            rename_exposition(text, "acme_logs"),
+            "# HELP acme_logs_flush_rows Rows\n# TYPE acme_logs_flush_rows histogram\nacme_logs_flush_rows_bucket{le=\"10\"} 1\n"
+        );
+        assert_eq!(rename_prefix(INGEST_COUNT, ""), "ingest_count");
+
+        let mut config = MetricsConfig::default();
+        assert!(config.validate().is_ok());
+        config.flush_rows_buckets = vec![10.0, 5.0];
+        assert!(config.validate().is_err());
+        config.flush_rows_buckets = FLUSH_ROWS_BUCKETS.to_vec();
+        config.prefix = "9bad".to_string();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_push_group_url() {
+        let mut push = PushGatewayConfig::new("http://pushgateway:9091/");
+        assert_eq!(
+            push.group_url(),
+            "http://pushgateway:9091/metrics/job/daemon_rs"
+        );
+        push.instance = Some("node-1".to_string());
+        assert_eq!(
+            push.group_url(),
+            "http://pushgateway:9091/metrics/job/daemon_rs/instance/node-1"
+        );
+        assert!(push.validate().is_ok());
+
+        push.job = "a/b".to_string();
+        assert!(push.validate().is_err());
+        push.job = DEFAULT_PUSH_JOB.to_string();
+        push.url = "pushgateway:9091".to_string();
+        assert!(push.validate().is_err());
+    }
+
+    #[test]
+    fn test_process_stats_are_read_from_proc() {
+        if std::path::Path::new("/proc/self/statm").exists() {
+            assert!(resident_memory_bytes().unwrap() > 0);
+            assert!(open_fds().unwrap() >= 3);
+        }
+    }
+
+    #[test]
+    fn test_measure_dir_walks_subdirectories() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("a.parquet"), [0u8; 10]).unwrap();
+        std::fs::create_dir(dir.path().join("2026")).unwrap();
+        std::fs::write(dir.path().join("2026").join("b.parquet"), [0u8; 5]).unwrap();
+
+        let usage = measure_dir(dir.path()).unwrap();
+        assert_eq!(usage.bytes, 15);
+        assert_eq!(usage.files, 2);
+        assert!(usage.oldest.is_some());
+        assert_eq!(
+            measure_dir(&dir.path().join("missing")).unwrap(),
+            DirUsage::default()
+        );
+    }
+
+    #[test]
+    fn test_service_labels_are_bounded() {
+        let labels = ServiceLabels::new(2);
+        assert_eq!(labels.label(None), UNKNOWN_SERVICE_LABEL);
+        assert_eq!(labels.label(Some("checkout")), "checkout");
+        assert_eq!(labels.label(Some("pay\"ments")), "pay_ments");
+        assert_eq!(labels.label(Some("search")), OTHER_SERVICE_LABEL);
+        // Services seen before the limit keep their own label
+        assert_eq!(labels.label(Some("checkout")), "checkout");
+
+        let counts = [
+            ("a", 1),
+            ("b", 7),
+            ("c", 2),
+            ("d", 2),
+            ("e", 1),
+            ("f", 3),
+            ("g", 1),
+        ];
+        assert_eq!(service_summary(counts), "b=7 f=3 c=2 d=2 a=1 (+2 more)");
+        assert_eq!(service_summary([]), "");
+    }
 }
@@ -0,0 +1,635 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::status::{DaemonStatus, StatusReport};
+
+/// Notifications waiting for delivery before new ones are dropped
+const NOTIFY_QUEUE_CAPACITY: usize = 64;
+
+/// Longest a webhook request or exec command may take
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often daemon health is checked for events
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Notification sinks and the health events sent to them (the `[notify]` section of the config
+/// file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotifyConfig {
+    #[serde(default)]
+    pub sinks: Vec<SinkConfig>,
+
+    /// Sinks that receive daemon health events: storage write failures, persistent storage
+    /// outages and their recovery, and overload
+    #[serde(default)]
+    pub health_sinks: Vec<String>,
+
+    /// How long logs must be dropped or the ingest queue be nearly full before an overload
+    /// event is sent
+    #[serde(default = "default_overload_secs")]
+    pub overload_secs: u64,
+
+    /// Least time between two storage failure events while writes keep failing
+    #[serde(default = "default_repeat_secs")]
+    pub repeat_secs: u64,
+
+    /// Failed storage writes in a row after which a `storage_write_failing` event is sent once,
+    /// followed by `storage_write_recovered` when a write succeeds again
+    #[serde(default = "default_failure_streak")]
+    pub failure_streak: u32,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            sinks: Vec::new(),
+            health_sinks: Vec::new(),
+            overload_secs: default_overload_secs(),
+            repeat_secs: default_repeat_secs(),
+            failure_streak: default_failure_streak(),
+        }
+    }
+}
+
+fn default_overload_secs() -> u64 {
+    60
+}
+
+fn default_repeat_secs() -> u64 {
+    300
+}
+
+fn default_failure_streak() -> u32 {
+    5
+}
+
+impl NotifyConfig {
+    pub fn validate(&self) -> Result<()> {
+        let mut names = HashSet::new();
+        for sink in &self.sinks {
+            if !names.insert(sink.name.as_str()) {
+                bail!("Duplicate notification sink: {}", sink.name);
+            }
+            sink.validate()?;
+        }
+        for name in &self.health_sinks {
+            if !names.contains(name.as_str()) {
+                bail!("notify.health_sinks names an unknown sink: {}", name);
+            }
+        }
+        if self.failure_streak == 0 {
+            bail!("notify.failure_streak must be greater than 0");
+        }
+        Ok(())
+    }
+}
+
+/// A named place notifications are delivered to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SinkConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub target: SinkTarget,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkTarget {
+    /// POST to `url`; the body is the notification as JSON unless `body` gives a template
+    Webhook {
+        url: String,
+        #[serde(default)]
+        body: Option<String>,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+    },
+    /// Run `command` with the notification as JSON on stdin
+    Exec { command: Vec<String> },
+    /// Append the notification to `path` as one line of JSON
+    File { path: PathBuf },
+}
+
+impl SinkConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.name.is_empty() {
+            bail!("Notification sinks need a name");
+        }
+        let empty = match &self.target {
+            SinkTarget::Webhook { url, .. } => url.is_empty(),
+            SinkTarget::Exec { command } => command.first().is_none_or(String::is_empty),
+            SinkTarget::File { path } => path.as_os_str().is_empty(),
+        };
+        if empty {
+            bail!("Notification sink {} has no target", self.name);
+        }
+        Ok(())
+    }
+}
+
+/// Something worth telling an operator about
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    /// `alert`, `health` or `quota`
+    pub kind: &'static str,
+    /// The alert rule, the health event (`storage_write_failed`, `storage_write_failing`,
+    /// `storage_write_recovered` or `overload`) or the service over its quota
+    pub name: String,
+    pub summary: String,
+    pub at: String,
+    /// The fired alert, or the numbers behind a health event
+    pub details: Value,
+}
+
+impl Notification {
+    pub fn new(kind: &'static str, name: &str, summary: String, details: Value) -> Self {
+        Self {
+            kind,
+            name: name.to_string(),
+            summary,
+            at: chrono::Utc::now().to_rfc3339(),
+            details,
+        }
+    }
+}
+
+/// Sinks a notification goes to
+pub type Sinks = Arc<[Arc<SinkConfig>]>;
+
+/// A notification with its sinks, as queued for delivery
+pub type Delivery = (Notification, Sinks);
+
+/// Queues notifications for delivery on a thread of its own; cheap to clone. Delivery stops
+/// once every clone is dropped.
+#[derive(Clone)]
+pub struct Notifier {
+    sinks: Arc<HashMap<String, Arc<SinkConfig>>>,
+    queue: mpsc::Sender<Delivery>,
+    runtime: Option<tokio::runtime::Handle>,
+}
+
+impl Notifier {
+    /// A notifier for `sinks` that queues deliveries on the returned receiver
+    pub fn channel(sinks: &[SinkConfig]) -> (Self, mpsc::Receiver<Delivery>) {
+        let (queue, deliveries) = mpsc::channel(NOTIFY_QUEUE_CAPACITY);
+        let sinks = sinks
+            .iter()
+            .map(|sink| (sink.name.clone(), Arc::new(sink.clone())))
+            .collect();
+        let notifier = Self {
+            sinks: Arc::new(sinks),
+            queue,
+            runtime: None,
+        };
+        (notifier, deliveries)
+    }
+
+    /// Start delivering to `sinks`
+    pub fn start(sinks: &[SinkConfig]) -> Result<Self> {
+        let (mut notifier, deliveries) = Self::channel(sinks);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("Failed to start the notifier")?;
+        notifier.runtime = Some(runtime.handle().clone());
+        std::thread::Builder::new()
+            .name("daemon_rs-notify".to_string())
+            .spawn(move || runtime.block_on(deliver(deliveries)))
+            .context("Failed to start the notifier")?;
+        Ok(notifier)
+    }
+
+    /// The configured sinks called `names`
+    pub fn sinks(&self, names: &[String]) -> Result<Vec<Arc<SinkConfig>>> {
+        names
+            .iter()
+            .map(|name| {
+                self.sinks
+                    .get(name)
+                    .cloned()
+                    .with_context(|| format!("Unknown notification sink: {}", name))
+            })
+            .collect()
+    }
+
+    /// Queue `notification` for `sinks`; never waits, dropping it when delivery is behind
+    pub fn send(&self, notification: Notification, sinks: &Sinks) {
+        if sinks.is_empty() {
+            return;
+        }
+        let name = notification.name.clone();
+        if self.queue.try_send((notification, sinks.clone())).is_err() {
+            warn!("Notification {} dropped: delivery is behind", name);
+        }
+    }
+
+    /// Send storage failure and overload events from `status` to `config.health_sinks` while
+    /// any clone of the notifier is alive
+    pub fn watch_health(
+        &self,
+        config: &NotifyConfig,
+        status: DaemonStatus,
+        storage_dir: PathBuf,
+    ) -> Result<()> {
+        let sinks: Sinks = self.sinks(&config.health_sinks)?.into();
+        if sinks.is_empty() {
+            return Ok(());
+        }
+        let runtime = self
+            .runtime
+            .clone()
+            .context("Health events need a started notifier")?;
+        let queue = self.queue.downgrade();
+        let mut watch = HealthWatch::new(config);
+        runtime.spawn(async move {
+            let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                let Some(queue) = queue.upgrade() else {
+                    return;
+                };
+                let report = status.report(&storage_dir);
+                for notification in watch.check(&report, Instant::now()) {
+                    warn!("{}", notification.summary);
+                    let _ = queue.try_send((notification, sinks.clone()));
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Turns status snapshots into health events
+pub struct HealthWatch {
+    overload_after: Duration,
+    repeat_after: Duration,
+    failure_streak: u32,
+    /// Whether `storage_write_failing` went out for the current streak
+    failing_sent: bool,
+    storage_errors: Option<u64>,
+    last_storage_event: Option<Instant>,
+    dropped: Option<u64>,
+    overloaded_since: Option<Instant>,
+    overload_sent: bool,
+    disk_pressure: Option<&'static str>,
+}
+
+impl HealthWatch {
+    pub fn new(config: &NotifyConfig) -> Self {
+        Self {
+            overload_after: Duration::from_secs(config.overload_secs),
+            repeat_after: Duration::from_secs(config.repeat_secs),
+            failure_streak: config.failure_streak,
+            failing_sent: false,
+            storage_errors: None,
+            last_storage_event: None,
+            dropped: None,
+            overloaded_since: None,
+            overload_sent: false,
+            disk_pressure: None,
+        }
+    }
+
+    /// Events raised by `report`, taken at `now`, since the previous one
+    pub fn check(&mut self, report: &StatusReport, now: Instant) -> Vec<Notification> {
+        let mut events = Vec::new();
+
+        let new_errors = self
+            .storage_errors
+            .replace(report.storage_errors_total)
+            .is_some_and(|seen| report.storage_errors_total > seen);
+        let repeating = self
+            .last_storage_event
+            .is_some_and(|at| now.duration_since(at) < self.repeat_after);
+        if new_errors && !repeating {
+            self.last_storage_event = Some(now);
+            events.push(Notification::new(
+                "health",
+                "storage_write_failed",
+                format!(
+                    "Storage write failed: {}",
+                    report
+                        .last_storage_error
+                        .as_deref()
+                        .unwrap_or("unknown error")
+                ),
+                serde_json::json!({
+                    "storage_errors_total": report.storage_errors_total,
+                    "last_storage_error": report.last_storage_error,
+                    "last_storage_error_at": report.last_storage_error_at,
+                }),
+            ));
+        }
+
+        let streak = report.storage_failure_streak;
+        if streak >= self.failure_streak && !self.failing_sent {
+            self.failing_sent = true;
+            events.push(Notification::new(
+                "health",
+                "storage_write_failing",
+                format!(
+                    "Storage writes failed {} times in a row; logs are held until they succeed",
+                    streak
+                ),
+                serde_json::json!({
+                    "storage_failure_streak": streak,
+                    "last_storage_error": report.last_storage_error,
+                }),
+            ));
+        } else if streak == 0 && self.failing_sent {
+            self.failing_sent = false;
+            events.push(Notification::new(
+                "health",
+                "storage_write_recovered",
+                "Storage writes succeed again".to_string(),
+                serde_json::json!({ "storage_errors_total": report.storage_errors_total }),
+            ));
+        }
+
+        let dropping = self
+            .dropped
+            .replace(report.dropped_total)
+            .is_some_and(|seen| report.dropped_total > seen);
+        let saturated =
+            report.queue_capacity > 0 && report.queue_depth * 10 >= report.queue_capacity * 9;
+        if dropping || saturated {
+            let since = *self.overloaded_since.get_or_insert(now);
+            if !self.overload_sent && now.duration_since(since) >= self.overload_after {
+                self.overload_sent = true;
+                events.push(Notification::new(
+                    "health",
+                    "overload",
+                    format!(
+                        "Ingest overloaded for {}s: {} logs dropped so far, queue {}/{}",
+                        now.duration_since(since).as_secs(),
+                        report.dropped_total,
+                        report.queue_depth,
+                        report.queue_capacity
+                    ),
+                    serde_json::json!({
+                        "dropped_total": report.dropped_total,
+                        "queue_depth": report.queue_depth,
+                        "queue_capacity": report.queue_capacity,
+                    }),
+                ));
+            }
+        } else {
+            self.overloaded_since = None;
+            self.overload_sent = false;
+        }
+
+        let pressure = report.disk_pressure;
+        if let Some(previous) = self.disk_pressure.replace(pressure) {
+            if previous != pressure {
+                events.push(Notification::new(
+                    "health",
+                    "disk_pressure",
+                    format!(
+                        "Disk pressure {} -> {} with {} bytes free on the storage volume",
+                        previous,
+                        pressure,
+                        report.disk_free_bytes.unwrap_or(0)
+                    ),
+                    serde_json::json!({
+                        "disk_pressure": pressure,
+                        "previous": previous,
+                        "disk_free_bytes": report.disk_free_bytes,
+                    }),
+                ));
+            }
+        }
+        events
+    }
+}
+
+/// Deliver queued notifications until every notifier is dropped
+async fn deliver(mut deliveries: mpsc::Receiver<Delivery>) {
+    let client = reqwest::Client::builder()
+        .timeout(NOTIFY_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    while let Some((notification, sinks)) = deliveries.recv().await {
+        let json = match serde_json::to_value(&notification) {
+            Ok(json) => json,
+            Err(e) => {
+                warn!(
+                    "Notification {} could not be encoded: {}",
+                    notification.name, e
+                );
+                continue;
+            }
+        };
+        for sink in sinks.iter() {
+            if let Err(e) = deliver_to(&client, sink, &notification, &json).await {
+                warn!(
+                    "Notification {} to sink {} failed: {:#}",
+                    notification.name, sink.name, e
+                );
+                crate::metrics::record_notify_failure(&sink.name, notification.kind);
+            }
+        }
+    }
+}
+
+async fn deliver_to(
+    client: &reqwest::Client,
+    sink: &SinkConfig,
+    notification: &Notification,
+    json: &Value,
+) -> Result<()> {
+    match &sink.target {
+        SinkTarget::Webhook { url, body, headers } => {
+            let body = match body {
+                Some(template) => render_template(template, json),
+                None => json.to_string(),
+            };
+            let mut request = client
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body);
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            request.send().await?.error_for_status()?;
+        }
+        SinkTarget::Exec { command } => run_command(command, notification, json).await?,
+        SinkTarget::File { path } => append_line(path, json).await?,
+    }
+    Ok(())
+}
+
+/// Run `command` with the notification on stdin and `NOTIFY_KIND`, `NOTIFY_NAME` and
+/// `NOTIFY_SUMMARY` set
+async fn run_command(command: &[String], notification: &Notification, json: &Value) -> Result<()> {
+    let mut child = tokio::process::Command::new(&command[0])
+        .args(&command[1..])
+        .env("NOTIFY_KIND", notification.kind)
+        .env("NOTIFY_NAME", &notification.name)
+        .env("NOTIFY_SUMMARY", &notification.summary)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to run {:?}", command[0]))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(json.to_string().as_bytes()).await?;
+    }
+    let status = tokio::time::timeout(NOTIFY_TIMEOUT, child.wait())
+        .await
+        .context("Timed out")??;
+    if !status.success() {
+        bail!("{:?} exited with {}", command[0], status);
+    }
+    Ok(())
+}
+
+async fn append_line(path: &Path, json: &Value) -> Result<()> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .with_context(|| format!("Failed to open {:?}", path))?;
+    file.write_all(format!("{}\n", json).as_bytes()).await?;
+    Ok(())
+}
+
+/// Replace each `{{path}}` in `template` with the value at that dotted path of `json`, escaped
+/// for use inside a JSON string; missing values become empty
+pub fn render_template(template: &str, json: &Value) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let path = rest[start + 2..start + end].trim();
+        let value = path.split('.').try_fold(json, |value, key| value.get(key));
+        match value {
+            Some(Value::String(s)) => {
+                let quoted = Value::String(s.clone()).to_string();
+                out.push_str(&quoted[1..quoted.len() - 1]);
+            }
+            Some(Value::Null) | None => {}
+            Some(other) => out.push_str(&other.to_string()),
+        }
+        rest = &rest[start + end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_templates_and_health_events() {
+        let notification = Notification::new(
+            "alert",
+            "checkout-errors",
+            "checkout \"errors\"".to_string(),
+            serde_json::json!({"count": 50, "log": {"service": "checkout"}}),
+        );
+        let json = serde_json::to_value(&notification).unwrap();
+        assert_eq!(
+            render_template(
+                r#"{"text": "{{ summary }} ({{details.count}} from {{details.log.service}}){{missing}}"}"#,
+                &json
+            ),
+            r#"{"text": "checkout \"errors\" (50 from checkout)"}"#
+        );
+
+        let config: NotifyConfig = toml::from_str(
+            r#"
+            health_sinks = ["ops"]
+            overload_secs = 10
+
+            [[sinks]]
+            name = "ops"
+            type = "webhook"
+            url = "http://localhost:9/hook"
+            body = '{"text": "{{summary}}"}'
+
+            [[sinks]]
+            name = "journal"
+            type = "file"
+            path = "/tmp/notifications.jsonl"
+            "#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        let mut unknown = config.clone();
+        unknown.health_sinks = vec!["pager".to_string()];
+        assert!(unknown.validate().is_err());
+
+        let report = |errors: u64, dropped: u64| StatusReport {
+            status: "healthy",
+            problems: Vec::new(),
+            uptime_secs: 0,
+            queue_depth: 0,
+            queue_capacity: 100,
+            ingested_total: 0,
+            dropped_total: dropped,
+            last_flush: None,
+            storage_errors_total: errors,
+            storage_failure_streak: 0,
+            last_storage_error: Some("No space left on device".to_string()),
+            last_storage_error_at: None,
+            disk_free_bytes: None,
+            disk_pressure: "normal",
+        };
+        let mut watch = HealthWatch::new(&config);
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let names = |events: Vec<Notification>| -> Vec<String> {
+            events.into_iter().map(|event| event.name).collect()
+        };
+        assert!(watch.check(&report(0, 0), at(0)).is_empty());
+        assert_eq!(
+            names(watch.check(&report(2, 0), at(5))),
+            ["storage_write_failed"]
+        );
+        // Still failing, but within repeat_secs of the last event
+        assert!(watch.check(&report(3, 0), at(10)).is_empty());
+        assert!(watch.check(&report(3, 5), at(15)).is_empty());
+        assert_eq!(names(watch.check(&report(3, 9), at(25))), ["overload"]);
+        assert!(watch.check(&report(3, 12), at(30)).is_empty());
+        assert!(watch.check(&report(3, 12), at(35)).is_empty());
+        assert!(watch.check(&report(3, 20), at(40)).is_empty());
+        assert_eq!(names(watch.check(&report(3, 30), at(50))), ["overload"]);
+
+        // A long failure streak is reported once, and so is its end
+        let mut watch = HealthWatch::new(&config);
+        let streak = |streak: u32| StatusReport {
+            storage_failure_streak: streak,
+            ..report(streak.into(), 0)
+        };
+        assert!(watch.check(&streak(0), at(0)).is_empty());
+        assert_eq!(
+            names(watch.check(&streak(1), at(5))),
+            ["storage_write_failed"]
+        );
+        assert_eq!(
+            names(watch.check(&streak(5), at(10))),
+            ["storage_write_failing"]
+        );
+        assert_eq!(
+            names(watch.check(&streak(9), at(400))),
+            ["storage_write_failed"]
+        );
+        assert_eq!(
+            names(watch.check(&streak(0), at(405))),
+            ["storage_write_recovered"]
+        );
+    }
+}
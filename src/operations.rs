@@ -0,0 +1,155 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::trace_storage::{SpanStatus, TraceSpan};
+
+/// Aggregate timing of one span name across every trace in a window
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema))]
+pub struct OperationStats {
+    pub operation: String,
+    pub count: u64,
+    pub error_count: u64,
+    pub error_rate: f64,
+    pub total_time_ms: f64,
+    /// Time not covered by child spans, summed over all spans
+    pub self_time_ms: f64,
+    pub avg_duration_ms: f64,
+    pub max_duration_ms: f64,
+}
+
+#[derive(Debug, Default)]
+struct OperationTotals {
+    count: u64,
+    errors: u64,
+    duration_us: u64,
+    max_duration_us: u64,
+}
+
+/// Span kept until child durations are known, so self time can be computed
+#[derive(Debug)]
+struct PendingSpan {
+    key: (String, String),
+    name: String,
+    duration_us: u64,
+}
+
+/// Accumulates spans within a time range into per-operation stats
+pub struct OperationAggregator {
+    start_us: i64,
+    end_us: i64,
+    totals: HashMap<String, OperationTotals>,
+    spans: Vec<PendingSpan>,
+    /// Summed child duration keyed by (trace id, parent span id)
+    child_time_us: HashMap<(String, String), u64>,
+}
+
+impl OperationAggregator {
+    /// Spans starting in `[start_us, end_us)` are counted
+    pub fn new(start_us: i64, end_us: i64) -> Self {
+        Self {
+            start_us,
+            end_us,
+            totals: HashMap::new(),
+            spans: Vec::new(),
+            child_time_us: HashMap::new(),
+        }
+    }
+
+    pub fn observe(&mut self, span: &TraceSpan) {
+        let start_us = span.start_time.timestamp_micros();
+        if start_us < self.start_us || start_us >= self.end_us {
+            return;
+        }
+
+        let totals = self.totals.entry(span.name.clone()).or_default();
+        totals.count += 1;
+        totals.duration_us += span.duration_us;
+        totals.max_duration_us = totals.max_duration_us.max(span.duration_us);
+        if matches!(span.status, SpanStatus::Error { .. }) {
+            totals.errors += 1;
+        }
+
+        if let Some(parent) = &span.parent_span_id {
+            *self
+                .child_time_us
+                .entry((span.trace_id.clone(), parent.clone()))
+                .or_default() += span.duration_us;
+        }
+        self.spans.push(PendingSpan {
+            key: (span.trace_id.clone(), span.span_id.clone()),
+            name: span.name.clone(),
+            duration_us: span.duration_us,
+        });
+    }
+
+    /// Stats per operation, largest total time first
+    pub fn finish(self) -> Vec<OperationStats> {
+        let mut self_time_us: HashMap<&str, u64> = HashMap::new();
+        for span in &self.spans {
+            let children = self.child_time_us.get(&span.key).copied().unwrap_or(0);
+            // Concurrent children can add up to more than the parent
+            *self_time_us.entry(&span.name).or_default() +=
+                span.duration_us.saturating_sub(children);
+        }
+
+        let mut operations: Vec<OperationStats> = self
+            .totals
+            .iter()
+            .map(|(name, totals)| OperationStats {
+                operation: name.clone(),
+                count: totals.count,
+                error_count: totals.errors,
+                error_rate: totals.errors as f64 / totals.count as f64,
+                total_time_ms: totals.duration_us as f64 / 1000.0,
+                self_time_ms: self_time_us.get(name.as_str()).copied().unwrap_or(0) as f64 / 1000.0,
+                avg_duration_ms: totals.duration_us as f64 / totals.count as f64 / 1000.0,
+                max_duration_ms: totals.max_duration_us as f64 / 1000.0,
+            })
+            .collect();
+
+        operations.sort_by(|a, b| {
+            b.total_time_ms
+                .total_cmp(&a.total_time_ms)
+                .then_with(|| a.operation.cmp(&b.operation))
+        });
+        operations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+
+    fn span(span_id: &str, parent: Option<&str>, name: &str, duration_us: u64) -> TraceSpan {
+        let start = DateTime::<Utc>::from_timestamp_micros(1_000_000).unwrap();
+        TraceSpan {
+            trace_id: "t".to_string(),
+            span_id: span_id.to_string(),
+            parent_span_id: parent.map(str::to_string),
+            name: name.to_string(),
+            start_time: start,
+            end_time: start,
+            duration_us,
+            attributes: HashMap::new(),
+            events: Vec::new(),
+            status: SpanStatus::Ok,
+        }
+    }
+
+    #[test]
+    fn test_self_time_excludes_children() {
+        let mut aggregator = OperationAggregator::new(0, 2_000_000);
+        aggregator.observe(&span("root", None, "checkout", 10_000));
+        aggregator.observe(&span("a", Some("root"), "db.query", 3_000));
+        aggregator.observe(&span("b", Some("root"), "db.query", 4_000));
+
+        let operations = aggregator.finish();
+        assert_eq!(operations[0].operation, "checkout");
+        assert_eq!(operations[0].self_time_ms, 3.0);
+        assert_eq!(operations[1].count, 2);
+        assert_eq!(operations[1].total_time_ms, 7.0);
+        assert_eq!(operations[1].avg_duration_ms, 3.5);
+    }
+}
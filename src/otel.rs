@@ -1,19 +1,168 @@
 use anyhow::{Context, Result};
-use opentelemetry::trace::{Tracer, TracerProvider as _};
-use opentelemetry::{global, KeyValue};
+#[cfg(feature = "otel")]
+use opentelemetry::trace::{Link, SamplingResult, SpanKind, TraceId, TracerProvider as _};
+#[cfg(feature = "otel")]
+use opentelemetry::{global, Context as OtelContext, KeyValue};
+#[cfg(feature = "otel")]
 use opentelemetry_otlp::WithExportConfig;
-use opentelemetry_sdk::trace::{Sampler, TracerProvider};
+#[cfg(feature = "otel")]
+use opentelemetry_sdk::trace::{Sampler, ShouldSample, TracerProvider};
+#[cfg(feature = "otel")]
 use opentelemetry_sdk::Resource;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+#[cfg(feature = "otel")]
 use tracing::Subscriber;
+#[cfg(feature = "otel")]
 use tracing_subscriber::layer::SubscriberExt;
-use tracing_subscriber::Registry;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+#[cfg(feature = "otel")]
+use crate::log_format::LogFormat;
+#[cfg(feature = "otel")]
+use crate::trace_writer::TraceWriter;
+
+/// Log filter layer whose directives can be replaced while the daemon runs
+pub type LogFilterLayer = reload::Layer<EnvFilter, Registry>;
+
+/// Replaces the directives of a `LogFilterLayer`
+pub type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
+
+/// Tracing settings from the `[otel]` config section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OtelConfig {
+    /// Trace the daemon and serve the AI Agent API
+    #[serde(default = "default_otel_enabled")]
+    pub enabled: bool,
+
+    /// OTLP endpoint spans are also exported to, besides the trace storage directory
+    #[serde(default)]
+    pub endpoint: Option<String>,
+
+    /// Fraction of traces sampled, from 0.0 to 1.0
+    #[serde(default = "default_sampling_rate")]
+    pub sampling_rate: f64,
+}
+
+impl Default for OtelConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_otel_enabled(),
+            endpoint: None,
+            sampling_rate: default_sampling_rate(),
+        }
+    }
+}
+
+fn default_otel_enabled() -> bool {
+    true
+}
+
+fn default_sampling_rate() -> f64 {
+    1.0
+}
+
+impl OtelConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !(0.0..=1.0).contains(&self.sampling_rate) {
+            anyhow::bail!(
+                "otel.sampling_rate must be between 0.0 and 1.0: {}",
+                self.sampling_rate
+            );
+        }
+        if self
+            .endpoint
+            .as_deref()
+            .is_some_and(|e| e.trim().is_empty())
+        {
+            anyhow::bail!("otel.endpoint must not be empty");
+        }
+        Ok(())
+    }
+}
+
+/// Sampler whose rate can change while the daemon runs; clones share the rate
+#[cfg(feature = "otel")]
+#[derive(Debug, Clone)]
+pub struct SamplerHandle(Arc<RwLock<Sampler>>);
+
+#[cfg(feature = "otel")]
+impl SamplerHandle {
+    pub fn new(sampling_rate: f64) -> Self {
+        Self(Arc::new(RwLock::new(sampler(sampling_rate))))
+    }
+
+    pub fn set_rate(&self, sampling_rate: f64) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = sampler(sampling_rate);
+    }
+}
+
+#[cfg(feature = "otel")]
+impl ShouldSample for SamplerHandle {
+    fn should_sample(
+        &self,
+        parent_context: Option<&OtelContext>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        self.0
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .should_sample(parent_context, trace_id, name, span_kind, attributes, links)
+    }
+}
+
+/// Sampling rate kept for reloads; nothing is traced without the `otel` feature
+#[cfg(not(feature = "otel"))]
+#[derive(Debug, Clone)]
+pub struct SamplerHandle(Arc<RwLock<f64>>);
+
+#[cfg(not(feature = "otel"))]
+impl SamplerHandle {
+    pub fn new(sampling_rate: f64) -> Self {
+        Self(Arc::new(RwLock::new(sampling_rate)))
+    }
+
+    pub fn set_rate(&self, sampling_rate: f64) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = sampling_rate;
+    }
+}
+
+/// Sampler for a sampling rate
+#[cfg(feature = "otel")]
+fn sampler(sampling_rate: f64) -> Sampler {
+    if sampling_rate >= 1.0 {
+        Sampler::AlwaysOn
+    } else if sampling_rate <= 0.0 {
+        Sampler::AlwaysOff
+    } else {
+        Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(sampling_rate)))
+    }
+}
+
+/// Filter for log directives, e.g. `info` or `daemon_rs=debug,warn`
+pub fn env_filter(directives: &str) -> Result<EnvFilter> {
+    EnvFilter::try_new(directives).with_context(|| format!("Invalid log filter: {}", directives))
+}
+
+/// Log filter layer for `directives`, and a handle to change them
+pub fn log_filter(directives: &str) -> Result<(LogFilterLayer, LogFilterHandle)> {
+    Ok(reload::Layer::new(env_filter(directives)?))
+}
 
 /// Initialize OpenTelemetry tracing and return a subscriber
 /// This combines init and subscriber creation to work around type limitations
+#[cfg(feature = "otel")]
 pub fn init_tracing_and_subscriber(
     service_name: &str,
     otlp_endpoint: Option<String>,
-    sampling_rate: f64,
+    trace_writer: Option<TraceWriter>,
+    sampler: SamplerHandle,
+    filter: LogFilterLayer,
+    format: LogFormat,
 ) -> Result<impl Subscriber> {
     // Create resource with service name
     let resource = Resource::new(vec![KeyValue::new(
@@ -21,15 +170,6 @@ pub fn init_tracing_and_subscriber(
         service_name.to_string(),
     )]);
 
-    // Configure sampler based on sampling rate
-    let sampler = if sampling_rate >= 1.0 {
-        Sampler::AlwaysOn
-    } else if sampling_rate <= 0.0 {
-        Sampler::AlwaysOff
-    } else {
-        Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(sampling_rate)))
-    };
-
     // Build tracer provider
     let mut provider_builder = TracerProvider::builder().with_config(
         opentelemetry_sdk::trace::Config::default()
@@ -54,6 +194,12 @@ pub fn init_tracing_and_subscriber(
         provider_builder = provider_builder.with_span_processor(batch_processor);
     }
 
+    // Write spans locally, for the AI Agent API
+    if let Some(writer) = trace_writer {
+        provider_builder =
+            provider_builder.with_batch_exporter(writer, opentelemetry_sdk::runtime::Tokio);
+    }
+
     let provider = provider_builder.build();
 
     // Get SDK tracer before setting global provider
@@ -67,14 +213,15 @@ pub fn init_tracing_and_subscriber(
 
     // Create and return subscriber
     let subscriber = Registry::default()
-        .with(tracing_subscriber::EnvFilter::from_default_env())
-        .with(tracing_subscriber::fmt::layer())
+        .with(filter)
+        .with(crate::log_format::fmt_layer(format))
         .with(telemetry);
 
     Ok(subscriber)
 }
 
 /// Shutdown OpenTelemetry gracefully
+#[cfg(feature = "otel")]
 pub fn shutdown_tracing() {
     global::shutdown_tracer_provider();
 }
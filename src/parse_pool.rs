@@ -0,0 +1,179 @@
+use anyhow::{anyhow, Context, Result};
+use bytes::BytesMut;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, oneshot};
+use tracing::Span;
+
+use crate::enrichment::PeerCredentials;
+use crate::pipeline::IngestPipeline;
+use crate::schema::{LogEntry, ParseBuffers, ParseError, SchemaRouter};
+
+/// Jobs queued per worker before connections wait for one to free up
+const JOBS_PER_WORKER: usize = 4;
+
+/// Outcome of each frame of a job, in the order the frames were read
+pub type Parsed = Vec<Result<LogEntry, ParseError>>;
+
+/// Frames from one read of one connection
+struct ParseJob {
+    router: Arc<SchemaRouter>,
+    pipeline: Arc<IngestPipeline>,
+    peer: Option<PeerCredentials>,
+    frames: Vec<BytesMut>,
+    /// The connection's span, so parse spans keep their parent on a worker
+    span: Span,
+    reply: oneshot::Sender<Parsed>,
+}
+
+/// Threads that parse, validate and process frames for every connection, so CPU-heavy
+/// validation uses more cores than the one running the io_uring reactor
+#[derive(Clone)]
+pub struct ParsePool {
+    jobs: mpsc::Sender<ParseJob>,
+}
+
+impl ParsePool {
+    /// Start `workers` threads; they stop once every clone of the pool is dropped
+    pub fn start(workers: usize) -> Result<Self> {
+        let (jobs, queue) = mpsc::channel(workers.max(1) * JOBS_PER_WORKER);
+        let queue = Arc::new(Mutex::new(queue));
+        for i in 0..workers {
+            let queue = queue.clone();
+            std::thread::Builder::new()
+                .name(format!("daemon_rs-parse-{}", i))
+                .spawn(move || work(&queue))
+                .context("Failed to start a parse worker")?;
+        }
+        Ok(Self { jobs })
+    }
+
+    /// Parse `frames` received from `peer` on a worker; waits while every worker is busy and
+    /// the queue is full
+    pub async fn parse(
+        &self,
+        router: Arc<SchemaRouter>,
+        pipeline: Arc<IngestPipeline>,
+        peer: Option<PeerCredentials>,
+        frames: Vec<BytesMut>,
+    ) -> Result<Parsed> {
+        let (reply, parsed) = oneshot::channel();
+        let job = ParseJob {
+            router,
+            pipeline,
+            peer,
+            frames,
+            span: Span::current(),
+            reply,
+        };
+        self.jobs
+            .send(job)
+            .await
+            .map_err(|_| anyhow!("The parse workers stopped"))?;
+        parsed.await.map_err(|_| anyhow!("A parse worker stopped"))
+    }
+}
+
+/// Run jobs until the pool is dropped, reusing one set of parser buffers
+fn work(queue: &Mutex<mpsc::Receiver<ParseJob>>) {
+    let mut buffers = ParseBuffers::default();
+    loop {
+        let job = queue
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .blocking_recv();
+        let Some(job) = job else {
+            return;
+        };
+        let parsed = job.span.in_scope(|| {
+            job.frames
+                .into_iter()
+                .map(|mut frame| {
+                    parse_frame(
+                        &job.router,
+                        &job.pipeline,
+                        job.peer.as_ref(),
+                        &mut frame,
+                        &mut buffers,
+                    )
+                })
+                .collect()
+        });
+        // The connection is gone when nothing waits for the reply
+        let _ = job.reply.send(parsed);
+    }
+}
+
+/// Turn one frame into a log: field mappings, schema validation, then the ingest pipeline
+pub fn parse_frame(
+    router: &SchemaRouter,
+    pipeline: &IngestPipeline,
+    peer: Option<&PeerCredentials>,
+    frame: &mut [u8],
+    buffers: &mut ParseBuffers,
+) -> Result<LogEntry, ParseError> {
+    // Note: simd_json modifies the input slice (in-place string filtering)
+    let _span = tracing::info_span!("parse_log", message_size = frame.len()).entered();
+    let mut log = match pipeline.map_fields(frame) {
+        Some(mapped) => router.parse_with(&mut mapped?, buffers)?,
+        None => router.parse_with(frame, buffers)?,
+    };
+    pipeline.process(&mut log, peer)?;
+    Ok(log)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{RejectReason, SchemaValidator};
+
+    #[tokio::test]
+    async fn test_pool_parses_frames_in_order() {
+        let router = Arc::new(SchemaRouter::new(
+            SchemaValidator::default_schema().unwrap(),
+        ));
+        let pipeline = Arc::new(IngestPipeline::default());
+        let pool = ParsePool::start(3).unwrap();
+
+        let message = |i: usize| {
+            BytesMut::from(
+                format!(
+                    r#"{{"timestamp":"2026-01-01T00:00:00Z","level":"INFO","message":"m{}"}}"#,
+                    i
+                )
+                .as_str(),
+            )
+        };
+        let mut frames: Vec<_> = (0..20).map(message).collect();
+        frames[7] = BytesMut::from(r#"{"level":"info""#);
+
+        let mut inline = ParseBuffers::default();
+        let expected: Vec<_> = frames
+            .iter()
+            .map(|frame| {
+                parse_frame(&router, &pipeline, None, &mut frame.clone(), &mut inline)
+                    .map(|log| log.message)
+                    .map_err(|e| e.reason)
+            })
+            .collect();
+
+        let jobs: Vec<_> = (0..4)
+            .map(|_| {
+                let pool = pool.clone();
+                let (router, pipeline, frames) = (router.clone(), pipeline.clone(), frames.clone());
+                tokio::spawn(async move { pool.parse(router, pipeline, None, frames).await })
+            })
+            .collect();
+        for job in jobs {
+            let parsed: Vec<_> = job
+                .await
+                .unwrap()
+                .unwrap()
+                .into_iter()
+                .map(|parsed| parsed.map(|log| log.message).map_err(|e| e.reason))
+                .collect();
+            assert_eq!(parsed, expected);
+        }
+        assert_eq!(expected[7], Err(RejectReason::MalformedJson));
+        assert_eq!(expected[8], Ok("m8".to_string()));
+    }
+}
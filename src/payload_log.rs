@@ -0,0 +1,111 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Rejected logs seen so far, for sampling
+static SEEN: AtomicU64 = AtomicU64::new(0);
+static SAMPLE_RATE: AtomicU64 = AtomicU64::new(0);
+static MAX_BYTES: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_BYTES);
+
+const DEFAULT_MAX_BYTES: usize = 256;
+
+/// Raw client payloads in the daemon's own logs (the `[log_payloads]` section of the config
+/// file); by default they never appear there
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PayloadLogConfig {
+    /// Add the raw payload to the warning for one in this many rejected logs; 0 never does.
+    /// Meant for debugging: payloads may hold secrets
+    #[serde(default)]
+    pub sample_rate: u64,
+
+    /// Bytes of a sampled payload shown; the rest is cut
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: usize,
+}
+
+impl Default for PayloadLogConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 0,
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+}
+
+fn default_max_bytes() -> usize {
+    DEFAULT_MAX_BYTES
+}
+
+impl PayloadLogConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.max_bytes == 0 {
+            bail!("log_payloads.max_bytes must be greater than 0");
+        }
+        Ok(())
+    }
+}
+
+/// Apply `config` to every later warning; called at startup and on config reload
+pub fn configure(config: &PayloadLogConfig) {
+    MAX_BYTES.store(config.max_bytes, Ordering::Relaxed);
+    SAMPLE_RATE.store(config.sample_rate, Ordering::Relaxed);
+}
+
+/// Whether payloads may be sampled, so the listener keeps a copy before parsing
+pub fn enabled() -> bool {
+    SAMPLE_RATE.load(Ordering::Relaxed) > 0
+}
+
+/// The excerpt of `payload` to log, passed through `redact` first, for the sampled share of
+/// rejected logs
+pub fn sample(payload: &[u8], redact: impl FnOnce(&str) -> String) -> Option<String> {
+    let rate = SAMPLE_RATE.load(Ordering::Relaxed);
+    if rate == 0 || !is_sampled(SEEN.fetch_add(1, Ordering::Relaxed), rate) {
+        return None;
+    }
+    let redacted = redact(&String::from_utf8_lossy(payload));
+    Some(excerpt(
+        redacted.as_bytes(),
+        MAX_BYTES.load(Ordering::Relaxed),
+    ))
+}
+
+/// Whether the rejected log numbered `seen` is one of the one in `rate` logged
+fn is_sampled(seen: u64, rate: u64) -> bool {
+    rate > 0 && seen.is_multiple_of(rate)
+}
+
+/// At most `max_bytes` of `payload`, with control characters escaped so a payload cannot
+/// forge log lines
+fn excerpt(payload: &[u8], max_bytes: usize) -> String {
+    let text = String::from_utf8_lossy(&payload[..payload.len().min(max_bytes)]);
+    let mut excerpt: String = text.escape_debug().collect();
+    if payload.len() > max_bytes {
+        excerpt.push_str(&format!("... ({} bytes)", payload.len()));
+    }
+    excerpt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_excerpt_truncates_and_escapes() {
+        assert_eq!(excerpt(br#"{"a":1}"#, 64), r#"{\"a\":1}"#);
+        assert_eq!(
+            excerpt(b"{\"message\":\"x\"}\nWARN forged", 64),
+            r#"{\"message\":\"x\"}\nWARN forged"#
+        );
+        assert_eq!(excerpt(b"abcdefgh", 4), "abcd... (8 bytes)");
+        // A cut inside a multi-byte character stays valid text
+        assert_eq!(excerpt("é".as_bytes(), 1), "\u{fffd}... (2 bytes)");
+
+        let sampled = (0..7)
+            .filter(|&seen| is_sampled(seen, 3))
+            .collect::<Vec<_>>();
+        assert_eq!(sampled, [0, 3, 6]);
+        assert!(!(0..7).any(|seen| is_sampled(seen, 0)));
+        assert!(!enabled() && sample(b"secret", str::to_string).is_none());
+    }
+}
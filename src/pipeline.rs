@@ -0,0 +1,834 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use crate::dead_letter::{InvalidLogConfig, INVALID_KEY};
+use crate::enrichment::{Enricher, EnrichmentConfig, PeerCredentials};
+use crate::field_mapping::{validate_mappings, FieldMapper, FieldMapping};
+use crate::intern::intern;
+use crate::quota::QuotaConfig;
+use crate::redaction::{LogRedactionConfig, LogRedactor};
+use crate::schema::{LogEntry, ParseError, RejectReason};
+
+/// Levels stored after normalization, least to most severe
+pub const CANONICAL_LEVELS: &[&str] = &["trace", "debug", "info", "warn", "error", "fatal"];
+
+/// Ingest processing settings (the `[ingest]` section of the config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestConfig {
+    /// Rewrite levels to the canonical lowercase set before storage
+    #[serde(default = "default_normalize_levels")]
+    pub normalize_levels: bool,
+
+    /// Canonical level stored for levels that are not recognized
+    #[serde(default = "default_unknown_level")]
+    pub unknown_level: String,
+
+    /// What happens to logs whose `timestamp` is missing or cannot be parsed
+    #[serde(default)]
+    pub timestamp_fallback: TimestampFallback,
+
+    /// PII redaction applied before logs are queued for storage
+    #[serde(default)]
+    pub redaction: LogRedactionConfig,
+
+    /// Host, environment and peer metadata added to accepted logs
+    #[serde(default)]
+    pub enrich: EnrichmentConfig,
+
+    /// Rename, move and drop rules applied to raw messages before validation
+    #[serde(default)]
+    pub field_mappings: Vec<FieldMapping>,
+
+    /// Size caps on the message and metadata of each log
+    #[serde(default)]
+    pub limits: SizeLimits,
+
+    /// What the listener does with logs that fail validation
+    #[serde(default)]
+    pub invalid: InvalidLogConfig,
+
+    /// Sockets accepting logs besides `socket_path`, each with its own settings
+    #[serde(default)]
+    pub listeners: Vec<ListenerConfig>,
+
+    /// Top-level metadata keys kept or dropped before storage
+    #[serde(default)]
+    pub metadata_keys: MetadataKeysConfig,
+
+    /// Threads that parse and validate messages; 0 parses on the ingest thread
+    #[serde(default)]
+    pub parse_workers: usize,
+
+    /// Hourly or daily volume allowed per service
+    #[serde(default)]
+    pub quotas: Vec<QuotaConfig>,
+
+    /// Seconds between the rows recording logs dropped over quota; 0 stores none
+    #[serde(default = "default_sampling_report_secs")]
+    pub sampling_report_secs: u64,
+}
+
+impl Default for IngestConfig {
+    fn default() -> Self {
+        Self {
+            normalize_levels: default_normalize_levels(),
+            unknown_level: default_unknown_level(),
+            timestamp_fallback: TimestampFallback::default(),
+            redaction: LogRedactionConfig::default(),
+            enrich: EnrichmentConfig::default(),
+            field_mappings: Vec::new(),
+            limits: SizeLimits::default(),
+            invalid: InvalidLogConfig::default(),
+            listeners: Vec::new(),
+            metadata_keys: MetadataKeysConfig::default(),
+            parse_workers: 0,
+            quotas: Vec::new(),
+            sampling_report_secs: default_sampling_report_secs(),
+        }
+    }
+}
+
+fn default_sampling_report_secs() -> u64 {
+    60
+}
+
+/// Handling of logs without a usable `timestamp`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampFallback {
+    /// Reject the log as an `invalid_timestamp` validation failure
+    #[default]
+    Reject,
+    /// Store the time the daemon received it
+    IngestTime,
+}
+
+/// Metadata key recording which fields were truncated, with their original sizes in bytes
+pub const TRUNCATED_KEY: &str = "_truncated";
+
+/// Size caps on stored fields (the `[ingest.limits]` section of the config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SizeLimits {
+    /// Longest stored message in bytes
+    #[serde(default = "default_max_message_bytes")]
+    pub max_message_bytes: usize,
+
+    /// Largest stored metadata in bytes, measured as encoded JSON
+    #[serde(default = "default_max_metadata_bytes")]
+    pub max_metadata_bytes: usize,
+
+    /// What happens to logs over a limit
+    #[serde(default)]
+    pub policy: OversizePolicy,
+}
+
+impl Default for SizeLimits {
+    fn default() -> Self {
+        Self {
+            max_message_bytes: default_max_message_bytes(),
+            max_metadata_bytes: default_max_metadata_bytes(),
+            policy: OversizePolicy::default(),
+        }
+    }
+}
+
+fn default_max_message_bytes() -> usize {
+    64 * 1024
+}
+
+fn default_max_metadata_bytes() -> usize {
+    256 * 1024
+}
+
+/// Handling of logs over a size limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OversizePolicy {
+    /// Cut the message, drop the metadata, and record both under `_truncated` in the metadata
+    #[default]
+    Truncate,
+    /// Reject the log as a `too_large` validation failure
+    Reject,
+}
+
+/// A further socket accepting logs (an `[[ingest.listeners]]` entry)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListenerConfig {
+    /// Name of the listener in logs and metrics
+    pub name: String,
+
+    pub socket_path: PathBuf,
+
+    /// What this listener does with logs that fail validation, instead of `[ingest.invalid]`
+    #[serde(default)]
+    pub invalid: Option<InvalidLogConfig>,
+
+    /// Validate every log on this listener against the `[schemas]` entry of this service,
+    /// whatever its `service` field says
+    #[serde(default)]
+    pub schema: Option<String>,
+}
+
+/// Name of the listener on `socket_path`
+pub const MAIN_LISTENER: &str = "ingest";
+
+/// Allow and deny lists for top-level metadata keys (the `[ingest.metadata_keys]` section of
+/// the config file); a pattern is an exact key or a prefix ending in `*`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetadataKeysConfig {
+    /// When non-empty, only keys matching one of these are kept
+    #[serde(default)]
+    pub allow: Vec<String>,
+
+    /// Keys matching one of these are dropped, even when allowed
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl MetadataKeysConfig {
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for (list, patterns) in [("allow", &self.allow), ("deny", &self.deny)] {
+            for pattern in patterns {
+                let prefix = pattern.strip_suffix('*').unwrap_or(pattern);
+                if pattern.trim().is_empty() || prefix.contains('*') {
+                    bail!(
+                        "ingest.metadata_keys.{} contains an invalid pattern: {:?}",
+                        list,
+                        pattern
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a metadata key survives the lists
+    fn keeps(&self, key: &str) -> bool {
+        let matches = |pattern: &String| match pattern.strip_suffix('*') {
+            Some(prefix) => key.starts_with(prefix),
+            None => key == pattern,
+        };
+        (self.allow.is_empty() || self.allow.iter().any(matches)) && !self.deny.iter().any(matches)
+    }
+}
+
+fn default_normalize_levels() -> bool {
+    true
+}
+
+fn default_unknown_level() -> String {
+    "info".to_string()
+}
+
+impl IngestConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !CANONICAL_LEVELS.contains(&self.unknown_level.as_str()) {
+            bail!(
+                "ingest.unknown_level must be one of {}: {}",
+                CANONICAL_LEVELS.join(", "),
+                self.unknown_level
+            );
+        }
+        LogRedactor::from_config(&self.redaction)?;
+        if self.limits.max_message_bytes == 0 || self.limits.max_metadata_bytes == 0 {
+            bail!("ingest.limits must be greater than 0");
+        }
+        self.enrich.validate()?;
+        self.metadata_keys.validate()?;
+        validate_mappings(&self.field_mappings)?;
+        for quota in &self.quotas {
+            quota.validate()?;
+        }
+        self.invalid.validate()?;
+        let mut names = BTreeSet::from([MAIN_LISTENER]);
+        for listener in &self.listeners {
+            if listener.name.trim().is_empty() || !names.insert(listener.name.as_str()) {
+                bail!(
+                    "ingest.listeners names must be unique, non-empty and not {:?}: {:?}",
+                    MAIN_LISTENER,
+                    listener.name
+                );
+            }
+            if let Some(invalid) = &listener.invalid {
+                invalid.validate()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Invalid-log handling of each listener by name, the main socket first. Dead letters of
+    /// the other listeners go to a subdirectory named after them unless they set a directory.
+    pub fn invalid_by_listener(&self, storage_dir: &Path) -> Vec<(&str, InvalidLogConfig)> {
+        let main_dir = self
+            .invalid
+            .dead_letter_dir
+            .clone()
+            .unwrap_or_else(|| storage_dir.join("dead_letter"));
+        let mut configs = vec![(MAIN_LISTENER, self.invalid.clone())];
+        for listener in &self.listeners {
+            let mut invalid = listener
+                .invalid
+                .clone()
+                .unwrap_or_else(|| self.invalid.clone());
+            if listener
+                .invalid
+                .as_ref()
+                .is_none_or(|own| own.dead_letter_dir.is_none())
+            {
+                invalid.dead_letter_dir = Some(main_dir.join(&listener.name));
+            }
+            configs.push((listener.name.as_str(), invalid));
+        }
+        configs
+    }
+
+    /// Directories the listeners write dead letters to
+    pub fn dead_letter_dirs(&self, storage_dir: &Path) -> Vec<PathBuf> {
+        self.invalid_by_listener(storage_dir)
+            .into_iter()
+            .filter_map(|(_, invalid)| invalid.dead_letter_dir(storage_dir))
+            .collect()
+    }
+}
+
+/// Canonical level for a level name, syslog severity (`0`-`7`) or bunyan/pino level
+/// (`10`-`60`), or `None` when it is not recognized
+pub fn canonical_level(level: &str) -> Option<&'static str> {
+    let level = level.trim();
+    if let Some(canonical) = CANONICAL_LEVELS.iter().find(|l| **l == level) {
+        return Some(canonical);
+    }
+    if let Ok(number) = level.parse::<u32>() {
+        return match number {
+            0..=2 => Some("fatal"),
+            3 => Some("error"),
+            4 => Some("warn"),
+            5 | 6 => Some("info"),
+            7 => Some("debug"),
+            10 => Some("trace"),
+            20 => Some("debug"),
+            30 => Some("info"),
+            40 => Some("warn"),
+            50 => Some("error"),
+            60 => Some("fatal"),
+            _ => None,
+        };
+    }
+
+    let canonical = match level.to_ascii_lowercase().as_str() {
+        "trace" | "finest" | "finer" => "trace",
+        "debug" | "dbg" | "fine" | "verbose" => "debug",
+        "info" | "information" | "informational" | "notice" | "config" => "info",
+        "warn" | "warning" => "warn",
+        "error" | "err" | "severe" => "error",
+        "fatal" | "critical" | "crit" | "alert" | "emerg" | "emergency" | "panic" => "fatal",
+        _ => return None,
+    };
+    Some(canonical)
+}
+
+/// Naive formats read as UTC, tried after RFC 3339 and RFC 2822
+const NAIVE_TIMESTAMP_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y/%m/%d %H:%M:%S%.f",
+];
+
+/// Formats with an explicit offset
+const ZONED_TIMESTAMP_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S%.f%z",
+    "%Y-%m-%d %H:%M:%S%.f %z",
+    // Common Log Format, e.g. 10/Oct/2000:13:55:36 -0700
+    "%d/%b/%Y:%H:%M:%S %z",
+];
+
+/// Parse RFC 3339, epoch seconds/millis/micros/nanos (told apart by magnitude) or one of a
+/// few common date formats
+pub fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    let timestamp = timestamp.trim();
+    if let Ok(parsed) = DateTime::parse_from_rfc3339(timestamp) {
+        return Some(parsed.with_timezone(&Utc));
+    }
+    if let Some(parsed) = parse_epoch(timestamp) {
+        return Some(parsed);
+    }
+    if let Ok(parsed) = DateTime::parse_from_rfc2822(timestamp) {
+        return Some(parsed.with_timezone(&Utc));
+    }
+    for format in ZONED_TIMESTAMP_FORMATS {
+        if let Ok(parsed) = DateTime::parse_from_str(timestamp, format) {
+            return Some(parsed.with_timezone(&Utc));
+        }
+    }
+    NAIVE_TIMESTAMP_FORMATS.iter().find_map(|format| {
+        NaiveDateTime::parse_from_str(timestamp, format)
+            .ok()
+            .map(|naive| naive.and_utc())
+    })
+}
+
+fn parse_epoch(timestamp: &str) -> Option<DateTime<Utc>> {
+    if timestamp.is_empty() || !timestamp.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+        return None;
+    }
+    if timestamp.contains('.') {
+        // Fractional values are seconds
+        let seconds: f64 = timestamp.parse().ok()?;
+        return DateTime::from_timestamp_micros((seconds * 1e6).round() as i64);
+    }
+
+    let value: i64 = timestamp.parse().ok()?;
+    match value {
+        0..=99_999_999_999 => Utc.timestamp_opt(value, 0).single(),
+        100_000_000_000..=99_999_999_999_999 => DateTime::from_timestamp_millis(value),
+        100_000_000_000_000..=99_999_999_999_999_999 => DateTime::from_timestamp_micros(value),
+        _ => Some(DateTime::from_timestamp_nanos(value)),
+    }
+}
+
+/// Rewrites accepted logs between validation and the storage queue
+#[derive(Debug, Clone)]
+pub struct IngestPipeline {
+    normalize_levels: bool,
+    unknown_level: String,
+    timestamp_fallback: TimestampFallback,
+    redactor: Option<LogRedactor>,
+    enricher: Option<Enricher>,
+    mapper: Option<FieldMapper>,
+    limits: SizeLimits,
+    metadata_keys: Option<MetadataKeysConfig>,
+}
+
+impl IngestPipeline {
+    pub fn from_config(config: &IngestConfig) -> Result<Self> {
+        let redactor = if config.redaction.is_empty() {
+            None
+        } else {
+            Some(LogRedactor::from_config(&config.redaction)?)
+        };
+        let enricher = if config.enrich.is_empty() {
+            None
+        } else {
+            Some(Enricher::from_config(&config.enrich)?)
+        };
+        Ok(Self {
+            normalize_levels: config.normalize_levels,
+            unknown_level: config.unknown_level.clone(),
+            timestamp_fallback: config.timestamp_fallback,
+            redactor,
+            enricher,
+            mapper: (!config.field_mappings.is_empty())
+                .then(|| FieldMapper::new(&config.field_mappings)),
+            limits: config.limits.clone(),
+            metadata_keys: (!config.metadata_keys.is_empty()).then(|| config.metadata_keys.clone()),
+        })
+    }
+
+    /// Apply the field mappings to a raw message before it is parsed; `None` when there
+    /// are none, so the message is parsed as received
+    pub fn map_fields(&self, data: &[u8]) -> Option<Result<Vec<u8>, ParseError>> {
+        self.mapper.as_ref().map(|mapper| mapper.map(data))
+    }
+
+    /// `payload`, a raw message that failed validation, with the configured redaction applied
+    pub fn redact_payload(&self, payload: &str) -> String {
+        match &self.redactor {
+            Some(redactor) => redactor.redact_payload(payload),
+            None => payload.to_string(),
+        }
+    }
+
+    /// Whether `process` uses the sending process's credentials
+    pub fn wants_peer(&self) -> bool {
+        self.enricher.as_ref().is_some_and(Enricher::wants_peer)
+    }
+
+    /// Apply every configured stage to `entry`, received from `peer`, or reject it
+    pub fn process(
+        &self,
+        entry: &mut LogEntry,
+        peer: Option<&PeerCredentials>,
+    ) -> Result<(), ParseError> {
+        // Before the limits, so dropped keys do not count towards the metadata size
+        if let Some(keys) = &self.metadata_keys {
+            filter_metadata_keys(entry, keys);
+        }
+        // Also before the limits, so a secret cut at the limit still matches its pattern and
+        // enriched metadata is held to the limit too
+        if let Some(redactor) = &self.redactor {
+            redactor.redact_entry(entry);
+        }
+        if let Some(enricher) = &self.enricher {
+            enricher.enrich(entry, peer);
+        }
+        self.enforce_limits(entry)?;
+        self.normalize_timestamp(entry)?;
+        if self.normalize_levels {
+            self.normalize_level(entry);
+        }
+        Ok(())
+    }
+
+    /// Rewrite the timestamp as RFC 3339, which storage and queries expect
+    fn normalize_timestamp(&self, entry: &mut LogEntry) -> Result<(), ParseError> {
+        if DateTime::parse_from_rfc3339(&entry.timestamp).is_ok() {
+            return Ok(());
+        }
+
+        let parsed = match (parse_timestamp(&entry.timestamp), self.timestamp_fallback) {
+            (Some(parsed), _) => parsed,
+            (None, TimestampFallback::IngestTime) => Utc::now(),
+            (None, TimestampFallback::Reject) if entry.timestamp.is_empty() => {
+                return Err(ParseError::new(
+                    RejectReason::InvalidTimestamp,
+                    "Missing timestamp",
+                ))
+            }
+            (None, TimestampFallback::Reject) => {
+                return Err(ParseError::new(
+                    RejectReason::InvalidTimestamp,
+                    format!("Unrecognized timestamp: {}", entry.timestamp),
+                ))
+            }
+        };
+        entry.timestamp = parsed.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+        Ok(())
+    }
+
+    fn enforce_limits(&self, entry: &mut LogEntry) -> Result<(), ParseError> {
+        let limits = &self.limits;
+        let message_bytes = entry.message.len();
+        let metadata_bytes = entry.metadata.as_ref().map_or(0, encoded_len);
+        let message_over = message_bytes > limits.max_message_bytes;
+        let metadata_over = metadata_bytes > limits.max_metadata_bytes;
+        if !message_over && !metadata_over {
+            return Ok(());
+        }
+
+        if limits.policy == OversizePolicy::Reject {
+            let (field, size, limit) = if message_over {
+                ("Message", message_bytes, limits.max_message_bytes)
+            } else {
+                ("Metadata", metadata_bytes, limits.max_metadata_bytes)
+            };
+            return Err(ParseError::new(
+                RejectReason::TooLarge,
+                format!("{} is {} bytes, over the {} byte limit", field, size, limit),
+            ));
+        }
+
+        let mut truncated = Map::new();
+        if message_over {
+            let mut end = limits.max_message_bytes;
+            while !entry.message.is_char_boundary(end) {
+                end -= 1;
+            }
+            entry.message.truncate(end);
+            truncated.insert("message".to_string(), Value::from(message_bytes as u64));
+        }
+        if metadata_over {
+            entry.metadata = None;
+            truncated.insert("metadata".to_string(), Value::from(metadata_bytes as u64));
+        }
+        // Metadata that is not an object has nowhere to carry the flag
+        let metadata = entry
+            .metadata
+            .get_or_insert_with(|| Value::Object(Map::new()));
+        if let Value::Object(object) = metadata {
+            object.insert(TRUNCATED_KEY.to_string(), Value::Object(truncated));
+        }
+        Ok(())
+    }
+
+    fn normalize_level(&self, entry: &mut LogEntry) {
+        let canonical = canonical_level(&entry.level).unwrap_or(self.unknown_level.as_str());
+        // Canonical levels are the common case; keep their shared copy
+        if &*entry.level != canonical {
+            entry.level = intern(canonical);
+        }
+    }
+}
+
+/// Drop the top-level metadata keys `keys` does not keep; the daemon's own `_invalid` record
+/// is always kept, and enrichment runs later so its keys are never filtered
+fn filter_metadata_keys(entry: &mut LogEntry, keys: &MetadataKeysConfig) {
+    if let Some(Value::Object(object)) = &mut entry.metadata {
+        object.retain(|key, _| key == INVALID_KEY || keys.keeps(key));
+    }
+}
+
+/// Length of `value` encoded as JSON, without allocating the encoding
+fn encoded_len(value: &Value) -> usize {
+    struct Counter(usize);
+    impl std::io::Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    let _ = serde_json::to_writer(&mut counter, value);
+    counter.0
+}
+
+impl Default for IngestPipeline {
+    fn default() -> Self {
+        Self::from_config(&IngestConfig::default()).expect("default ingest config is valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dead_letter::InvalidPolicy;
+    use serde_json::json;
+
+    #[test]
+    fn test_timestamps_are_parsed_or_stamped() {
+        let expected = "2023-11-14T22:13:20Z";
+        for timestamp in [
+            "1700000000",
+            "1700000000000",
+            "1700000000000000",
+            "1700000000.0",
+            "2023-11-14 22:13:20",
+            "2023-11-14T23:13:20+01:00",
+            "Tue, 14 Nov 2023 22:13:20 +0000",
+            "14/Nov/2023:22:13:20 +0000",
+        ] {
+            let parsed = parse_timestamp(timestamp).unwrap();
+            assert_eq!(
+                parsed.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+                expected
+            );
+        }
+        assert!(parse_timestamp("yesterday").is_none());
+
+        let entry = |timestamp: &str| -> LogEntry {
+            serde_json::from_value(
+                json!({ "timestamp": timestamp, "level": "info", "message": "m" }),
+            )
+            .unwrap()
+        };
+        let rejecting = IngestPipeline::default();
+        let mut epoch = entry("1700000000000");
+        rejecting.process(&mut epoch, None).unwrap();
+        assert_eq!(epoch.timestamp, expected);
+        let err = rejecting.process(&mut entry(""), None).unwrap_err();
+        assert_eq!(err.reason, RejectReason::InvalidTimestamp);
+
+        let stamping = IngestPipeline::from_config(&IngestConfig {
+            timestamp_fallback: TimestampFallback::IngestTime,
+            ..IngestConfig::default()
+        })
+        .unwrap();
+        let mut garbled = entry("yesterday");
+        stamping.process(&mut garbled, None).unwrap();
+        assert!(parse_timestamp(&garbled.timestamp).unwrap() > parse_timestamp(expected).unwrap());
+    }
+
+    #[test]
+    fn test_size_limits_truncate_or_reject() {
+        let limits = SizeLimits {
+            max_message_bytes: 8,
+            max_metadata_bytes: 16,
+            policy: OversizePolicy::Truncate,
+        };
+        let truncating = IngestPipeline::from_config(&IngestConfig {
+            limits: limits.clone(),
+            ..IngestConfig::default()
+        })
+        .unwrap();
+        let entry = |metadata: serde_json::Value| -> LogEntry {
+            serde_json::from_value(json!({
+                "timestamp": "2026-01-15T19:00:00Z",
+                "level": "info",
+                "message": "héllo wörld",
+                "metadata": metadata
+            }))
+            .unwrap()
+        };
+
+        let mut log = entry(json!({ "blob": "x".repeat(32) }));
+        truncating.process(&mut log, None).unwrap();
+        // Cut back to a character boundary
+        assert_eq!(log.message, "héllo w");
+        assert_eq!(
+            serde_json::to_value(log.metadata.unwrap()).unwrap(),
+            json!({ "_truncated": { "message": 13, "metadata": 43 } })
+        );
+
+        let rejecting = IngestPipeline::from_config(&IngestConfig {
+            limits: SizeLimits {
+                policy: OversizePolicy::Reject,
+                ..limits
+            },
+            ..IngestConfig::default()
+        })
+        .unwrap();
+        let err = rejecting.process(&mut entry(json!({})), None).unwrap_err();
+        assert_eq!(err.reason, RejectReason::TooLarge);
+    }
+
+    #[test]
+    fn test_limits_apply_after_redaction_and_enrichment() {
+        let pipeline = IngestPipeline::from_config(&IngestConfig {
+            limits: SizeLimits {
+                max_message_bytes: 20,
+                max_metadata_bytes: 64,
+                policy: OversizePolicy::Truncate,
+            },
+            redaction: LogRedactionConfig {
+                builtins: vec![crate::redaction::BuiltinPattern::Email],
+                ..LogRedactionConfig::default()
+            },
+            enrich: EnrichmentConfig {
+                fields: [("environment".to_string(), "x".repeat(80))].into(),
+                ..EnrichmentConfig::default()
+            },
+            ..IngestConfig::default()
+        })
+        .unwrap();
+        // The limit falls inside the address
+        let mut log: LogEntry = serde_json::from_value(json!({
+            "timestamp": "2026-01-15T19:00:00Z",
+            "level": "info",
+            "message": "contact alice@example.com now",
+        }))
+        .unwrap();
+        pipeline.process(&mut log, None).unwrap();
+        assert_eq!(log.message, "contact [REDACTED] n");
+        assert_eq!(
+            serde_json::to_value(log.metadata.unwrap()).unwrap(),
+            json!({ "_truncated": { "message": 22, "metadata": 98 } })
+        );
+    }
+
+    #[test]
+    fn test_metadata_keys_are_filtered() {
+        let pipeline = |allow: &[&str], deny: &[&str]| {
+            IngestPipeline::from_config(&IngestConfig {
+                metadata_keys: MetadataKeysConfig {
+                    allow: allow.iter().map(|k| k.to_string()).collect(),
+                    deny: deny.iter().map(|k| k.to_string()).collect(),
+                },
+                ..IngestConfig::default()
+            })
+            .unwrap()
+        };
+        let filtered = |pipeline: IngestPipeline| {
+            let mut entry: LogEntry = serde_json::from_value(json!({
+                "timestamp": "2026-01-15T19:00:00Z",
+                "level": "info",
+                "message": "m",
+                "metadata": { "user_id": 7, "http_path": "/", "http_cookie": "s", "request_id": "r" }
+            }))
+            .unwrap();
+            pipeline.process(&mut entry, None).unwrap();
+            serde_json::to_value(entry.metadata.unwrap()).unwrap()
+        };
+
+        assert_eq!(
+            filtered(pipeline(&[], &["request_id", "http_cookie"])),
+            json!({ "user_id": 7, "http_path": "/" })
+        );
+        assert_eq!(
+            filtered(pipeline(&["http_*", "user_id"], &["http_cookie"])),
+            json!({ "user_id": 7, "http_path": "/" })
+        );
+
+        let invalid = |pattern: &str| MetadataKeysConfig {
+            allow: vec![pattern.to_string()],
+            deny: Vec::new(),
+        };
+        assert!(invalid("http_*").validate().is_ok());
+        assert!(invalid("*_id").validate().is_err());
+        assert!(invalid(" ").validate().is_err());
+    }
+
+    #[test]
+    fn test_listeners_keep_their_own_invalid_policy() {
+        let config: IngestConfig = toml::from_str(
+            r#"
+            invalid.policy = "dead_letter"
+
+            [[listeners]]
+            name = "legacy"
+            socket_path = "/run/legacy.sock"
+
+            [[listeners]]
+            name = "strict"
+            socket_path = "/run/strict.sock"
+            invalid.policy = "reject"
+            "#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+
+        let storage = Path::new("/var/lib/logs");
+        let by_listener = config.invalid_by_listener(storage);
+        let policies: Vec<_> = by_listener.iter().map(|(n, i)| (*n, i.policy)).collect();
+        assert_eq!(
+            policies,
+            [
+                ("ingest", InvalidPolicy::DeadLetter),
+                ("legacy", InvalidPolicy::DeadLetter),
+                ("strict", InvalidPolicy::Reject),
+            ]
+        );
+        assert_eq!(
+            config.dead_letter_dirs(storage),
+            [
+                storage.join("dead_letter"),
+                storage.join("dead_letter/legacy")
+            ]
+        );
+
+        let mut clash = config.clone();
+        clash.listeners[1].name = "legacy".to_string();
+        assert!(clash.validate().is_err());
+    }
+
+    #[test]
+    fn test_levels_are_normalized() {
+        assert_eq!(canonical_level("INFO"), Some("info"));
+        assert_eq!(canonical_level("Warning"), Some("warn"));
+        assert_eq!(canonical_level("3"), Some("error"));
+        assert_eq!(canonical_level("50"), Some("error"));
+        assert_eq!(canonical_level("CRIT"), Some("fatal"));
+        assert_eq!(canonical_level("loud"), None);
+
+        let pipeline = IngestPipeline::from_config(&IngestConfig {
+            unknown_level: "warn".to_string(),
+            ..IngestConfig::default()
+        })
+        .unwrap();
+        let mut entry: LogEntry = serde_json::from_value(json!({
+            "timestamp": "2026-01-15T19:00:00Z",
+            "level": "loud",
+            "message": "m"
+        }))
+        .unwrap();
+        pipeline.process(&mut entry, None).unwrap();
+        assert_eq!(&*entry.level, "warn");
+
+        assert!(IngestConfig {
+            unknown_level: "verbose".to_string(),
+            ..IngestConfig::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
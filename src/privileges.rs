@@ -0,0 +1,327 @@
+use anyhow::{bail, Context, Result};
+use std::ffi::{CStr, CString};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+
+/// The account `serve` switches to once its socket is bound
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrivilegeDrop {
+    /// `None` keeps the current user and only changes the group
+    uid: Option<libc::uid_t>,
+    gid: libc::gid_t,
+    /// User name whose supplementary groups are taken on
+    user: Option<CString>,
+}
+
+impl PrivilegeDrop {
+    /// Resolve `user` and `group`, given as names or numeric ids; the group defaults to the
+    /// user's primary group. `None` when neither is set.
+    pub fn resolve(user: Option<&str>, group: Option<&str>) -> Result<Option<Self>> {
+        let user = user.map(lookup_user).transpose()?;
+        let gid = match (group, &user) {
+            (Some(group), _) => lookup_group(group)?,
+            (None, Some((_, primary_gid, _))) => *primary_gid,
+            (None, None) => return Ok(None),
+        };
+        Ok(Some(Self {
+            uid: user.as_ref().map(|(uid, _, _)| *uid),
+            gid,
+            user: user.and_then(|(_, _, name)| name),
+        }))
+    }
+
+    pub fn uid(&self) -> Option<u32> {
+        self.uid
+    }
+
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    /// Whether the process already runs as the account, e.g. when started by an upgrade of a
+    /// daemon that dropped privileges
+    pub fn is_current(&self) -> bool {
+        // SAFETY: plain system calls without arguments
+        let (euid, egid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        self.uid.is_none_or(|uid| uid == euid) && self.gid == egid
+    }
+
+    /// Create `dir` if needed and give it to the account, so it can keep writing there after
+    /// the switch. Files already inside keep their owner.
+    pub fn own_dir(&self, dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {:?}", dir))?;
+        std::os::unix::fs::chown(dir, self.uid, Some(self.gid))
+            .with_context(|| format!("Failed to change the owner of {:?}", dir))
+    }
+
+    /// Switch the whole process to the account: supplementary groups, then group, then user.
+    /// This cannot be undone.
+    pub fn apply(&self) -> Result<()> {
+        // SAFETY: plain system calls on values owned by `self`
+        unsafe {
+            let groups_set = match (&self.user, self.uid) {
+                (Some(user), Some(_)) => libc::initgroups(user.as_ptr(), self.gid),
+                _ => libc::setgroups(1, &self.gid),
+            };
+            if groups_set != 0 {
+                return Err(std::io::Error::last_os_error())
+                    .context("Failed to set supplementary groups");
+            }
+            if libc::setgid(self.gid) != 0 {
+                return Err(std::io::Error::last_os_error())
+                    .with_context(|| format!("Failed to switch to group {}", self.gid));
+            }
+            if let Some(uid) = self.uid {
+                if libc::setuid(uid) != 0 {
+                    return Err(std::io::Error::last_os_error())
+                        .with_context(|| format!("Failed to switch to user {}", uid));
+                }
+                // Regaining root must not be possible
+                if uid != 0 && libc::setuid(0) == 0 {
+                    bail!("Still able to regain root after switching to user {}", uid);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for PrivilegeDrop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(uid) = self.uid {
+            write!(f, "uid {}, ", uid)?;
+        }
+        write!(f, "gid {}", self.gid)
+    }
+}
+
+/// Mode and owner given to the Unix socket once it is bound
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SocketPermissions {
+    mode: Option<u32>,
+    uid: Option<libc::uid_t>,
+    gid: Option<libc::gid_t>,
+}
+
+impl SocketPermissions {
+    /// Resolve an octal `mode` such as `0660` and an `owner` such as `app:loggers`, `app` or
+    /// `:loggers`, with names or numeric ids
+    pub fn resolve(mode: Option<&str>, owner: Option<&str>) -> Result<Self> {
+        let mode = mode.map(parse_mode).transpose()?;
+        let (user, group) = match owner {
+            Some(owner) => parse_owner(owner)?,
+            None => (None, None),
+        };
+        Ok(Self {
+            mode,
+            uid: user.map(lookup_user).transpose()?.map(|(uid, _, _)| uid),
+            gid: group.map(lookup_group).transpose()?,
+        })
+    }
+
+    /// Whether the socket keeps the mode and owner it was created with
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Change the owner, then the mode, of the socket at `path`
+    pub fn apply(&self, path: &Path) -> Result<()> {
+        if self.uid.is_some() || self.gid.is_some() {
+            std::os::unix::fs::chown(path, self.uid, self.gid)
+                .with_context(|| format!("Failed to change the owner of socket {:?}", path))?;
+        }
+        if let Some(mode) = self.mode {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+                .with_context(|| format!("Failed to change the mode of socket {:?}", path))?;
+        }
+        Ok(())
+    }
+}
+
+/// Bind a Unix socket at `path` created with `umask` applied to its mode, so it is never
+/// reachable with wider permissions than meant. The bind runs on a thread of its own that
+/// first stops sharing its umask with the rest of the process, which keeps creating files
+/// with its own meanwhile.
+pub fn bind_with_umask(path: &Path, umask: libc::mode_t) -> std::io::Result<UnixListener> {
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                // SAFETY: unshare(CLONE_FS) gives this thread its own umask and working
+                // directory; umask then only swaps this thread's file mode mask
+                if unsafe { libc::unshare(libc::CLONE_FS) } != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                unsafe { libc::umask(umask) };
+                UnixListener::bind(path)
+            })
+            .join()
+            .unwrap_or_else(|_| Err(std::io::Error::other("Socket bind thread panicked")))
+    })
+}
+
+/// Parse an octal permission mode such as `0660`, `660` or `0o660`
+pub fn parse_mode(mode: &str) -> Result<u32> {
+    let digits = mode.strip_prefix("0o").unwrap_or(mode);
+    match u32::from_str_radix(digits, 8) {
+        Ok(parsed) if !digits.is_empty() && parsed <= 0o777 => Ok(parsed),
+        _ => bail!(
+            "Invalid permission mode, expected octal like 0660: {}",
+            mode
+        ),
+    }
+}
+
+/// Split `user:group`, `user` or `:group` into its parts
+pub fn parse_owner(owner: &str) -> Result<(Option<&str>, Option<&str>)> {
+    let (user, group) = owner.split_once(':').unwrap_or((owner, ""));
+    let user = Some(user).filter(|user| !user.is_empty());
+    let group = Some(group).filter(|group| !group.is_empty());
+    if user.is_none() && group.is_none() {
+        bail!(
+            "Invalid owner, expected user:group, user or :group: {:?}",
+            owner
+        );
+    }
+    Ok((user, group))
+}
+
+/// Uid, primary gid and name of a user given by name or numeric id
+fn lookup_user(user: &str) -> Result<(libc::uid_t, libc::gid_t, Option<CString>)> {
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    let status = match user.parse::<libc::uid_t>() {
+        // SAFETY: every pointer refers to a live local buffer of the stated size
+        Ok(uid) => unsafe {
+            libc::getpwuid_r(uid, &mut passwd, buf.as_mut_ptr(), buf.len(), &mut result)
+        },
+        Err(_) => {
+            let name = CString::new(user).context("User name contains a NUL byte")?;
+            // SAFETY: as above
+            unsafe {
+                libc::getpwnam_r(
+                    name.as_ptr(),
+                    &mut passwd,
+                    buf.as_mut_ptr(),
+                    buf.len(),
+                    &mut result,
+                )
+            }
+        }
+    };
+    if status != 0 {
+        return Err(std::io::Error::from_raw_os_error(status))
+            .with_context(|| format!("Failed to look up user {}", user));
+    }
+    if result.is_null() {
+        // A numeric id without a passwd entry keeps its own id as the group
+        return match user.parse::<libc::uid_t>() {
+            Ok(uid) => Ok((uid, uid, None)),
+            Err(_) => bail!("Unknown user: {}", user),
+        };
+    }
+    // SAFETY: `pw_name` points into `buf`, which outlives this borrow
+    let name = unsafe { CStr::from_ptr(passwd.pw_name) }.to_owned();
+    Ok((passwd.pw_uid, passwd.pw_gid, Some(name)))
+}
+
+/// Gid of a group given by name or numeric id
+fn lookup_group(group: &str) -> Result<libc::gid_t> {
+    if let Ok(gid) = group.parse::<libc::gid_t>() {
+        return Ok(gid);
+    }
+    let name = CString::new(group).context("Group name contains a NUL byte")?;
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+    let mut entry: libc::group = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::group = std::ptr::null_mut();
+    // SAFETY: every pointer refers to a live local buffer of the stated size
+    let status = unsafe {
+        libc::getgrnam_r(
+            name.as_ptr(),
+            &mut entry,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+    if status != 0 {
+        return Err(std::io::Error::from_raw_os_error(status))
+            .with_context(|| format!("Failed to look up group {}", group));
+    }
+    if result.is_null() {
+        bail!("Unknown group: {}", group);
+    }
+    Ok(entry.gr_gid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_with_umask_leaves_the_process_umask_alone() {
+        let process_umask = || {
+            std::fs::read_to_string("/proc/self/status")
+                .unwrap()
+                .lines()
+                .find_map(|line| line.strip_prefix("Umask:").map(str::trim).map(String::from))
+        };
+        let before = process_umask();
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("private.sock");
+        let _listener = bind_with_umask(&path, 0o177).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(process_umask(), before);
+    }
+
+    #[test]
+    fn test_resolve_names_and_ids() {
+        assert_eq!(PrivilegeDrop::resolve(None, None).unwrap(), None);
+
+        let root = PrivilegeDrop::resolve(Some("root"), None).unwrap().unwrap();
+        assert_eq!((root.uid(), root.gid()), (Some(0), 0));
+        assert_eq!(
+            root,
+            PrivilegeDrop::resolve(Some("0"), None).unwrap().unwrap()
+        );
+
+        let group_only = PrivilegeDrop::resolve(None, Some("4242")).unwrap().unwrap();
+        assert_eq!((group_only.uid(), group_only.gid()), (None, 4242));
+
+        assert!(PrivilegeDrop::resolve(Some("no-such-user-daemon-rs"), None).is_err());
+        assert!(PrivilegeDrop::resolve(None, Some("no-such-group-daemon-rs")).is_err());
+
+        assert_eq!(parse_mode("0660").unwrap(), 0o660);
+        assert_eq!(parse_mode("0o600").unwrap(), 0o600);
+        assert!(parse_mode("0800").is_err());
+        assert!(parse_mode("1777").is_err());
+        assert_eq!(
+            parse_owner("app:loggers").unwrap(),
+            (Some("app"), Some("loggers"))
+        );
+        assert_eq!(parse_owner(":loggers").unwrap(), (None, Some("loggers")));
+        assert!(parse_owner(":").is_err());
+
+        let socket = SocketPermissions::resolve(Some("0660"), Some("root:0")).unwrap();
+        assert_eq!(
+            socket,
+            SocketPermissions {
+                mode: Some(0o660),
+                uid: Some(0),
+                gid: Some(0),
+            }
+        );
+        assert!(SocketPermissions::resolve(None, None).unwrap().is_empty());
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        SocketPermissions::resolve(Some("0640"), None)
+            .unwrap()
+            .apply(file.path())
+            .unwrap();
+        let mode = std::fs::metadata(file.path()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+}
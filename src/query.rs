@@ -1,22 +1,208 @@
 use anyhow::{Context, Result};
-use arrow::array::RecordBatch;
+use arrow::array::{
+    new_null_array, Array, ArrayRef, AsArray, RecordBatch, StringArray, TimestampMillisecondArray,
+};
+use arrow::compute::{cast, concat_batches, interleave};
+use arrow::datatypes::{DataType, Field, Schema};
 use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ParquetRecordBatchStreamBuilder;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap};
 use std::fs::File;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio_stream::StreamExt;
 use tracing::info;
 
+use crate::query_cache::{Lookup, QueryCache};
+use crate::schema::LogEntry;
+
+/// Rows per batch returned by `QueryEngine::read_ordered`
+const MERGE_BATCH_ROWS: usize = 8192;
+
+/// A stored log entry decoded from Parquet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema))]
+pub struct LogRecord {
+    pub timestamp: String,
+    #[serde(skip)]
+    pub timestamp_ms: i64,
+    pub level: String,
+    pub message: String,
+    pub service: Option<String>,
+    pub trace_id: Option<String>,
+    #[cfg_attr(feature = "api", schema(value_type = Option<Object>))]
+    pub metadata: Option<serde_json::Value>,
+    /// Registry schema version the entry was validated with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<String>,
+}
+
+impl LogRecord {
+    /// Convert a freshly ingested entry into the shape served by the API
+    pub fn from_entry(entry: &LogEntry) -> Self {
+        let timestamp_ms = chrono::DateTime::parse_from_rfc3339(&entry.timestamp)
+            .map(|dt| dt.timestamp_millis())
+            .unwrap_or(0);
+
+        Self {
+            timestamp: entry.timestamp.clone(),
+            timestamp_ms,
+            level: entry.level.to_string(),
+            message: entry.message.clone(),
+            service: entry.service.as_deref().map(str::to_string),
+            trace_id: entry.trace_id.clone(),
+            metadata: entry
+                .metadata
+                .as_ref()
+                .and_then(|m| serde_json::from_str(&m.to_string()).ok()),
+            schema_version: entry.schema_version.clone(),
+        }
+    }
+}
+
+/// Filters applied when querying stored logs
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct LogFilter {
+    /// Inclusive lower bound (milliseconds since epoch)
+    pub start_ms: Option<i64>,
+    /// Exclusive upper bound (milliseconds since epoch)
+    pub end_ms: Option<i64>,
+    /// Case-insensitive level match
+    pub level: Option<String>,
+    pub service: Option<String>,
+    pub trace_id: Option<String>,
+    /// Any of these traces, to read the logs of several traces in one pass
+    pub trace_ids: Option<BTreeSet<String>>,
+    /// Case-insensitive substring of the message
+    pub contains: Option<String>,
+}
+
+impl LogFilter {
+    /// Parse a compact filter expression such as `level:error service:checkout timeout`.
+    ///
+    /// `level:`, `service:` and `trace_id:` terms set the matching field; remaining words
+    /// form the message text filter.
+    pub fn parse(expression: &str) -> Self {
+        let mut filter = Self::default();
+        let mut words = Vec::new();
+
+        for term in expression.split_whitespace() {
+            match term.split_once(':') {
+                Some(("level", value)) => filter.level = Some(value.to_string()),
+                Some(("service", value)) => filter.service = Some(value.to_string()),
+                Some(("trace_id", value)) => filter.trace_id = Some(value.to_string()),
+                _ => words.push(term),
+            }
+        }
+
+        if !words.is_empty() {
+            filter.contains = Some(words.join(" "));
+        }
+        filter
+    }
+
+    pub fn matches(&self, record: &LogRecord) -> bool {
+        self.start_ms
+            .is_none_or(|start| record.timestamp_ms >= start)
+            && self.end_ms.is_none_or(|end| record.timestamp_ms < end)
+            && self
+                .level
+                .as_ref()
+                .is_none_or(|level| record.level.eq_ignore_ascii_case(level))
+            && self
+                .service
+                .as_ref()
+                .is_none_or(|service| record.service.as_ref() == Some(service))
+            && self
+                .trace_id
+                .as_ref()
+                .is_none_or(|trace_id| record.trace_id.as_ref() == Some(trace_id))
+            && self.trace_ids.as_ref().is_none_or(|trace_ids| {
+                record
+                    .trace_id
+                    .as_ref()
+                    .is_some_and(|trace_id| trace_ids.contains(trace_id))
+            })
+            && self
+                .contains
+                .as_ref()
+                .is_none_or(|text| record.message.to_lowercase().contains(&text.to_lowercase()))
+    }
+}
+
+/// Aggregate counts over matching logs
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema))]
+pub struct LogStats {
+    pub total_count: usize,
+    pub by_level: HashMap<String, usize>,
+    pub by_service: HashMap<String, usize>,
+    pub earliest: Option<String>,
+    pub latest: Option<String>,
+    /// Federated sources that could not be queried and are missing from the counts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unavailable_sources: Vec<String>,
+}
+
+impl LogStats {
+    /// Count `records`, which are newest first
+    pub fn from_records(records: &[LogRecord]) -> Self {
+        let mut stats = Self {
+            total_count: records.len(),
+            latest: records.first().map(|r| r.timestamp.clone()),
+            earliest: records.last().map(|r| r.timestamp.clone()),
+            ..Default::default()
+        };
+
+        for record in records {
+            *stats.by_level.entry(record.level.clone()).or_insert(0) += 1;
+            if let Some(service) = &record.service {
+                *stats.by_service.entry(service.clone()).or_insert(0) += 1;
+            }
+        }
+        stats
+    }
+}
+
 /// Query interface for reading logs from Parquet files
 pub struct QueryEngine {
     storage_dir: PathBuf,
+    cache: Option<Arc<QueryCache>>,
 }
 
 impl QueryEngine {
     pub fn new(storage_dir: PathBuf) -> Self {
-        Self { storage_dir }
+        Self {
+            storage_dir,
+            cache: None,
+        }
+    }
+
+    /// Keep the directory listing and each file's matching logs in `cache` for
+    /// `query_async` and `stats_async`
+    pub fn with_cache(mut self, cache: Arc<QueryCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn storage_dir(&self) -> &Path {
+        &self.storage_dir
     }
 
     /// List all Parquet files in the storage directory
     pub fn list_files(&self) -> Result<Vec<PathBuf>> {
+        match &self.cache {
+            Some(cache) => cache.list_files(&self.storage_dir, || self.read_dir()),
+            None => self.read_dir(),
+        }
+    }
+
+    fn read_dir(&self) -> Result<Vec<PathBuf>> {
         let mut files = Vec::new();
 
         for entry in std::fs::read_dir(&self.storage_dir)? {
@@ -35,13 +221,31 @@ impl QueryEngine {
     /// Read all logs from Parquet files
     #[tracing::instrument(skip(self))]
     pub fn read_all(&self) -> Result<Vec<RecordBatch>> {
+        Ok(self.read_files()?.into_iter().flatten().collect())
+    }
+
+    /// Read all logs oldest first. Files overlapping in time, e.g. through late logs or
+    /// partitions, are merged row by row instead of following each other.
+    #[tracing::instrument(skip(self))]
+    pub fn read_ordered(&self) -> Result<Vec<RecordBatch>> {
+        let mut runs = Vec::new();
+        for batches in self.read_files()? {
+            if let Some(first) = batches.first() {
+                runs.push(concat_batches(&first.schema(), &batches)?);
+            }
+        }
+        merge_by_timestamp(&runs, MERGE_BATCH_ROWS)
+    }
+
+    /// Batches of each readable file, in file order
+    fn read_files(&self) -> Result<Vec<Vec<RecordBatch>>> {
         let files = self.list_files()?;
         let mut batches = Vec::new();
 
         for file_path in files {
             info!("Reading file: {:?}", file_path);
             match self.read_file(&file_path) {
-                Ok(file_batches) => batches.extend(file_batches),
+                Ok(file_batches) => batches.push(file_batches),
                 Err(e) => {
                     tracing::warn!("Skipping corrupted or invalid file {:?}: {}", file_path, e);
                 }
@@ -72,7 +276,7 @@ impl QueryEngine {
         for batch in batches {
             println!(
                 "{}",
-                arrow::util::pretty::pretty_format_batches(&[batch.clone()])?
+                arrow::util::pretty::pretty_format_batches(std::slice::from_ref(batch))?
             );
         }
         Ok(())
@@ -85,6 +289,376 @@ impl QueryEngine {
         let total: usize = batches.iter().map(|b| b.num_rows()).sum();
         Ok(total)
     }
+
+    /// Read logs matching a filter, newest first
+    #[tracing::instrument(skip(self))]
+    pub fn query(&self, filter: &LogFilter) -> Result<Vec<LogRecord>> {
+        // An empty store has no directory until the first flush
+        if !self.storage_dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut records = Vec::new();
+        for batch in self.read_all()? {
+            records.extend(
+                records_from_batch(&batch)?
+                    .into_iter()
+                    .filter(|r| filter.matches(r)),
+            );
+        }
+
+        records.sort_by_key(|r| std::cmp::Reverse(r.timestamp_ms));
+        Ok(records)
+    }
+
+    /// Count matching logs by level and service
+    #[tracing::instrument(skip(self))]
+    pub fn stats(&self, filter: &LogFilter) -> Result<LogStats> {
+        Ok(LogStats::from_records(&self.query(filter)?))
+    }
+
+    /// `query` for async callers: files are read with the async Parquet reader, several at a
+    /// time, so reading one file overlaps with decoding and filtering the others
+    #[tracing::instrument(skip(self))]
+    pub async fn query_async(&self, filter: &LogFilter) -> Result<Vec<LogRecord>> {
+        if !self.storage_dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let in_flight = num_cpus::get().max(2);
+        let filter = Arc::new(filter.clone());
+        let mut files = self.list_files()?.into_iter().enumerate();
+        let mut reads = JoinSet::new();
+        let mut by_file = Vec::new();
+        loop {
+            while reads.len() < in_flight {
+                let Some((position, path)) = files.next() else {
+                    break;
+                };
+                let filter = filter.clone();
+                let cache = self.cache.clone();
+                reads.spawn(async move {
+                    let key = match &cache {
+                        Some(cache) => match cache.get(&filter, &path).await {
+                            Lookup::Hit(records) => return (position, path, Ok(records)),
+                            Lookup::Miss(key) => Some(key),
+                            Lookup::Unknown => None,
+                        },
+                        None => None,
+                    };
+                    let read = read_file_async(&path, &filter).await.map(Arc::new);
+                    if let (Ok(records), Some(cache), Some(key)) = (&read, cache, key) {
+                        cache.insert(key, records.clone());
+                    }
+                    (position, path, read)
+                });
+            }
+            let Some(joined) = reads.join_next().await else {
+                break;
+            };
+            match joined? {
+                (position, _, Ok(file_records)) => by_file.push((position, file_records)),
+                (_, path, Err(e)) => {
+                    tracing::warn!("Skipping corrupted or invalid file {:?}: {}", path, e);
+                }
+            }
+        }
+
+        // File order first, so logs with equal timestamps come out as `query` returns them
+        by_file.sort_by_key(|(position, _)| *position);
+        let mut records: Vec<LogRecord> = by_file
+            .into_iter()
+            .flat_map(|(_, file_records)| Arc::unwrap_or_clone(file_records))
+            .collect();
+        records.sort_by_key(|r| std::cmp::Reverse(r.timestamp_ms));
+        Ok(records)
+    }
+
+    /// `stats` for async callers, reading files as `query_async` does
+    #[tracing::instrument(skip(self))]
+    pub async fn stats_async(&self, filter: &LogFilter) -> Result<LogStats> {
+        Ok(LogStats::from_records(&self.query_async(filter).await?))
+    }
+
+    /// Send up to `limit` logs matching `filter` to `tx`, newest first, without reading the
+    /// whole store first: files are read newest first by the time range in their metadata, and
+    /// a log is sent once no unread file can hold a newer one. Stops when `tx` is closed.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn stream_async(
+        &self,
+        filter: &LogFilter,
+        limit: usize,
+        tx: mpsc::Sender<LogRecord>,
+    ) -> Result<()> {
+        if !self.storage_dir.exists() || limit == 0 {
+            return Ok(());
+        }
+
+        let mut files = Vec::new();
+        for path in self.list_files()? {
+            let range = file_time_range(&path).await;
+            // The range is start-inclusive, end-exclusive, like the filter itself
+            let outside = range.is_some_and(|(min, max)| {
+                filter.start_ms.is_some_and(|start| max < start)
+                    || filter.end_ms.is_some_and(|end| min >= end)
+            });
+            if !outside {
+                files.push((path, range));
+            }
+        }
+        // Files without a recorded range could hold any timestamp, so they are read first
+        files.sort_by_key(|(_, range)| Reverse(range.map_or(i64::MAX, |(_, max)| max)));
+
+        let mut ready = BinaryHeap::new();
+        let (mut read, mut sent) = (0, 0);
+        let mut files = files.into_iter().peekable();
+        while let Some((path, _)) = files.next() {
+            match read_file_async(&path, filter).await {
+                Ok(records) => {
+                    for record in records {
+                        ready.push(Newest(record, read));
+                        read += 1;
+                    }
+                }
+                Err(e) => tracing::warn!("Skipping corrupted or invalid file {:?}: {}", path, e),
+            }
+            let unread_max = match files.peek() {
+                Some((_, range)) => range.map_or(i64::MAX, |(_, max)| max),
+                None => i64::MIN,
+            };
+            while ready
+                .peek()
+                .is_some_and(|newest: &Newest| newest.0.timestamp_ms >= unread_max)
+            {
+                let Some(Newest(record, _)) = ready.pop() else {
+                    break;
+                };
+                if tx.send(record).await.is_err() {
+                    return Ok(());
+                }
+                sent += 1;
+                if sent == limit {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A log ordered newest first by its timestamp, then by `seq`, its arrival order
+pub(crate) struct Newest(pub LogRecord, pub u64);
+
+impl Ord for Newest {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.0.timestamp_ms, Reverse(self.1)).cmp(&(other.0.timestamp_ms, Reverse(other.1)))
+    }
+}
+
+impl PartialOrd for Newest {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Newest {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Newest {}
+
+/// Time range recorded in the metadata of the file at `path`, in milliseconds; None when the
+/// file does not record one or cannot be read
+async fn file_time_range(path: &Path) -> Option<(i64, i64)> {
+    let file = tokio::fs::File::open(path).await.ok()?;
+    let builder = ParquetRecordBatchStreamBuilder::new(file).await.ok()?;
+    let entries = crate::file_metadata::daemon_entries(
+        builder.metadata().file_metadata().key_value_metadata(),
+    );
+    crate::file_metadata::time_range_ms(&entries)
+}
+
+/// Matching logs of one Parquet file, decoded a batch at a time as reads complete
+async fn read_file_async(path: &Path, filter: &LogFilter) -> Result<Vec<LogRecord>> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| format!("Failed to open Parquet file: {:?}", path))?;
+    let mut batches = ParquetRecordBatchStreamBuilder::new(file).await?.build()?;
+
+    let mut records = Vec::new();
+    while let Some(batch) = batches.next().await {
+        records.extend(
+            records_from_batch(&batch?)?
+                .into_iter()
+                .filter(|r| filter.matches(r)),
+        );
+    }
+    Ok(records)
+}
+
+/// K-way merge of `runs` into batches of `batch_rows` rows ordered by timestamp. Each run is
+/// sorted first, keeping arrival order among equal timestamps. Runs written before a column
+/// was added get nulls for it.
+pub fn merge_by_timestamp(runs: &[RecordBatch], batch_rows: usize) -> Result<Vec<RecordBatch>> {
+    let schema = union_schema(runs)?;
+    let runs = runs
+        .iter()
+        .map(|run| align(run, &schema))
+        .collect::<Result<Vec<_>>>()?;
+    let timestamps = runs
+        .iter()
+        .map(|run| {
+            run.column_by_name("timestamp")
+                .and_then(|c| c.as_any().downcast_ref::<TimestampMillisecondArray>())
+                .context("Missing or invalid column: timestamp")
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let key = |run: usize, row: usize| {
+        let timestamps = timestamps[run];
+        timestamps.is_valid(row).then(|| timestamps.value(row))
+    };
+    let orders: Vec<Vec<usize>> = runs
+        .iter()
+        .enumerate()
+        .map(|(run, batch)| {
+            let mut order: Vec<usize> = (0..batch.num_rows()).collect();
+            order.sort_by_key(|&row| key(run, row));
+            order
+        })
+        .collect();
+
+    let mut heap = BinaryHeap::new();
+    for (run, order) in orders.iter().enumerate() {
+        if let Some(&row) = order.first() {
+            heap.push(Reverse((key(run, row), run, 0)));
+        }
+    }
+    let mut batches = Vec::new();
+    let mut indices = Vec::with_capacity(batch_rows);
+    while let Some(Reverse((_, run, position))) = heap.pop() {
+        indices.push((run, orders[run][position]));
+        if let Some(&row) = orders[run].get(position + 1) {
+            heap.push(Reverse((key(run, row), run, position + 1)));
+        }
+        if indices.len() == batch_rows || heap.is_empty() {
+            let columns = (0..schema.fields().len())
+                .map(|i| {
+                    let arrays: Vec<&dyn Array> =
+                        runs.iter().map(|run| run.column(i).as_ref()).collect();
+                    interleave(&arrays, &indices)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            batches.push(RecordBatch::try_new(schema.clone(), columns)?);
+            indices.clear();
+        }
+    }
+    Ok(batches)
+}
+
+/// Every column of `runs` in first-seen order, nullable, without the per-file metadata. A
+/// column dictionary-encoded in some runs and not in others, like level and service in files
+/// written before they were, takes the plain type.
+fn union_schema(runs: &[RecordBatch]) -> Result<Arc<Schema>> {
+    let plain = |data_type: &DataType| match data_type {
+        DataType::Dictionary(_, values) => values.as_ref().clone(),
+        other => other.clone(),
+    };
+    let mut fields: Vec<Field> = Vec::new();
+    for run in runs {
+        for field in run.schema().fields() {
+            match fields.iter_mut().find(|f| f.name() == field.name()) {
+                Some(seen) if seen.data_type() != field.data_type() => {
+                    let data_type = plain(field.data_type());
+                    if plain(seen.data_type()) != data_type {
+                        anyhow::bail!(
+                            "Column {} is {} in one file and {} in another",
+                            field.name(),
+                            seen.data_type(),
+                            field.data_type()
+                        );
+                    }
+                    *seen = seen.clone().with_data_type(data_type);
+                }
+                Some(_) => {}
+                None => fields.push(field.as_ref().clone().with_nullable(true)),
+            }
+        }
+    }
+    Ok(Arc::new(Schema::new(fields)))
+}
+
+/// `batch` with the columns of `schema`, missing ones all null and dictionary-encoded ones
+/// decoded where `schema` has the plain type
+fn align(batch: &RecordBatch, schema: &Arc<Schema>) -> Result<RecordBatch> {
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| match batch.column_by_name(field.name()) {
+            Some(column) if column.data_type() != field.data_type() => {
+                cast(column, field.data_type())
+            }
+            Some(column) => Ok(column.clone()),
+            None => Ok(new_null_array(field.data_type(), batch.num_rows())),
+        })
+        .collect::<Result<Vec<ArrayRef>, _>>()?;
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+/// Decode log rows from a RecordBatch written by the storage engine
+pub fn records_from_batch(batch: &RecordBatch) -> Result<Vec<LogRecord>> {
+    // Level and service are dictionary-encoded, except in files written before they were
+    let strings = |name: &str| -> Result<StringArray> {
+        let column = batch
+            .column_by_name(name)
+            .with_context(|| format!("Missing column: {}", name))?;
+        let column = match column.data_type() {
+            DataType::Dictionary(..) => cast(column, &DataType::Utf8)?,
+            _ => column.clone(),
+        };
+        column
+            .as_string_opt::<i32>()
+            .cloned()
+            .with_context(|| format!("Invalid column: {}", name))
+    };
+
+    let timestamps = batch
+        .column_by_name("timestamp")
+        .and_then(|c| c.as_any().downcast_ref::<TimestampMillisecondArray>())
+        .context("Missing or invalid column: timestamp")?;
+    let levels = strings("level")?;
+    let messages = strings("message")?;
+    let services = strings("service")?;
+    let trace_ids = strings("trace_id")?;
+    let metadata = strings("metadata")?;
+    // Files written before schema versioning lack the column
+    let schema_versions = batch
+        .column_by_name("schema_version")
+        .and_then(|c| c.as_string_opt::<i32>());
+
+    let optional =
+        |array: &StringArray, i: usize| (!array.is_null(i)).then(|| array.value(i).to_string());
+
+    let mut records = Vec::with_capacity(batch.num_rows());
+    for i in 0..batch.num_rows() {
+        let timestamp_ms = timestamps.value(i);
+        records.push(LogRecord {
+            timestamp: chrono::DateTime::from_timestamp_millis(timestamp_ms)
+                .unwrap_or_default()
+                .to_rfc3339(),
+            timestamp_ms,
+            level: levels.value(i).to_string(),
+            message: messages.value(i).to_string(),
+            service: optional(&services, i),
+            trace_id: optional(&trace_ids, i),
+            metadata: optional(&metadata, i)
+                .map(|m| serde_json::from_str(&m).unwrap_or(serde_json::Value::String(m))),
+            schema_version: schema_versions.and_then(|versions| optional(versions, i)),
+        });
+    }
+
+    Ok(records)
 }
 
 #[cfg(test)]
@@ -124,4 +698,208 @@ mod tests {
         let count = query_engine.count_logs().unwrap();
         assert_eq!(count, 10);
     }
+
+    #[test]
+    fn test_contains_filter_ignores_case() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage_dir = temp_dir.path().to_path_buf();
+        let mut engine = StorageEngine::new(
+            storage_dir.clone(),
+            parse_compression("snappy"),
+            5,
+            1024 * 1024,
+        )
+        .unwrap();
+
+        for i in 0..10 {
+            let log: crate::schema::LogEntry = serde_json::from_value(json!({
+                "timestamp": "2026-01-15T19:00:00Z",
+                "level": "info",
+                "message": format!("Test log {}", i)
+            }))
+            .unwrap();
+            engine.add_log(log).unwrap();
+        }
+        engine.flush().unwrap();
+        let query_engine = QueryEngine::new(storage_dir);
+
+        let filter = LogFilter {
+            contains: Some("LOG 7".to_string()),
+            ..Default::default()
+        };
+        let records = query_engine.query(&filter).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].message, "Test log 7");
+    }
+
+    #[test]
+    fn test_parsed_filter_expression() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage_dir = temp_dir.path().to_path_buf();
+        let mut engine = StorageEngine::new(
+            storage_dir.clone(),
+            parse_compression("snappy"),
+            5,
+            1024 * 1024,
+        )
+        .unwrap();
+
+        for i in 0..10 {
+            let log: crate::schema::LogEntry = serde_json::from_value(json!({
+                "timestamp": "2026-01-15T19:00:00Z",
+                "level": "info",
+                "message": format!("Test log {}", i)
+            }))
+            .unwrap();
+            engine.add_log(log).unwrap();
+        }
+        engine.flush().unwrap();
+        let query_engine = QueryEngine::new(storage_dir);
+
+        let filter = LogFilter::parse("level:INFO log 3");
+        assert_eq!(filter.level.as_deref(), Some("INFO"));
+        assert_eq!(filter.contains.as_deref(), Some("log 3"));
+        // Field terms and free text both apply, case-insensitively
+        assert_eq!(query_engine.query(&filter).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_async_query_matches_sync_query() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage_dir = temp_dir.path().to_path_buf();
+        let mut engine = StorageEngine::new(
+            storage_dir.clone(),
+            parse_compression("snappy"),
+            4,
+            1024 * 1024,
+        )
+        .unwrap();
+
+        for i in 0..30 {
+            let log: crate::schema::LogEntry = serde_json::from_value(json!({
+                "timestamp": format!("2026-01-15T19:00:{:02}Z", i),
+                "level": if i % 3 == 0 { "error" } else { "info" },
+                "message": format!("Test log {}", i)
+            }))
+            .unwrap();
+            engine.add_log(log).unwrap();
+        }
+        engine.flush().unwrap();
+        std::fs::write(storage_dir.join("zz_corrupt.parquet"), b"not parquet").unwrap();
+
+        let query_engine = QueryEngine::new(storage_dir);
+        assert!(query_engine.list_files().unwrap().len() > 2);
+        let messages = |records: Vec<LogRecord>| -> Vec<String> {
+            records.into_iter().map(|r| r.message).collect()
+        };
+        for filter in [LogFilter::default(), LogFilter::parse("level:error")] {
+            let expected = messages(query_engine.query(&filter).unwrap());
+            let actual = messages(query_engine.query_async(&filter).await.unwrap());
+            assert_eq!(actual, expected);
+
+            // Streaming sends the same logs in the same order, stopping at the limit
+            for limit in [7, 100] {
+                let (tx, mut rx) = mpsc::channel(4);
+                let streamed = tokio::spawn(async move {
+                    let mut records = Vec::new();
+                    while let Some(record) = rx.recv().await {
+                        records.push(record);
+                    }
+                    records
+                });
+                query_engine.stream_async(&filter, limit, tx).await.unwrap();
+                let streamed = messages(streamed.await.unwrap());
+                assert_eq!(streamed, expected[..limit.min(expected.len())]);
+            }
+        }
+
+        let stats = query_engine
+            .stats_async(&LogFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(stats.total_count, 30);
+        assert_eq!(stats.by_level["error"], 10);
+        assert_eq!(stats.latest.as_deref(), Some("2026-01-15T19:00:29+00:00"));
+    }
+
+    #[test]
+    fn test_ordered_read_merges_overlapping_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage_dir = temp_dir.path().to_path_buf();
+        let mut engine = StorageEngine::new(
+            storage_dir.clone(),
+            parse_compression("snappy"),
+            100,
+            1024 * 1024,
+        )
+        .unwrap();
+        let mut write = |seconds: &[u32]| {
+            for second in seconds {
+                let log: LogEntry = serde_json::from_value(json!({
+                    "timestamp": format!("2026-01-15T19:00:{:02}Z", second),
+                    "level": "info",
+                    "message": second.to_string(),
+                }))
+                .unwrap();
+                engine.add_log(log).unwrap();
+            }
+            engine.flush().unwrap();
+        };
+        write(&[4, 0, 2]);
+        write(&[3, 1]);
+
+        let query_engine = QueryEngine::new(storage_dir);
+        let messages = |batches: &[RecordBatch]| -> Vec<String> {
+            batches
+                .iter()
+                .flat_map(|batch| records_from_batch(batch).unwrap())
+                .map(|r| r.message)
+                .collect()
+        };
+        let in_file_order = query_engine.read_all().unwrap();
+        assert_eq!(messages(&in_file_order), ["4", "0", "2", "3", "1"]);
+        let ordered = query_engine.read_ordered().unwrap();
+        assert_eq!(messages(&ordered), ["0", "1", "2", "3", "4"]);
+
+        // A file from before schema_version was added merges too, in batches of the asked size
+        let old = in_file_order[1].project(&[0, 1, 2, 3, 4, 5]).unwrap();
+        let merged = merge_by_timestamp(&[in_file_order[0].clone(), old], 2).unwrap();
+        let rows: Vec<usize> = merged.iter().map(RecordBatch::num_rows).collect();
+        assert_eq!(rows, [2, 2, 1]);
+        assert_eq!(messages(&merged), ["0", "1", "2", "3", "4"]);
+        assert!(merged[0].column_by_name("schema_version").is_some());
+
+        // A file from before level and service were dictionary-encoded merges with newer ones
+        let plain = |batch: &RecordBatch| {
+            let (fields, columns): (Vec<Field>, Vec<ArrayRef>) = batch
+                .schema()
+                .fields()
+                .iter()
+                .zip(batch.columns())
+                .map(|(field, column)| match field.data_type() {
+                    DataType::Dictionary(..) => (
+                        field.as_ref().clone().with_data_type(DataType::Utf8),
+                        cast(column, &DataType::Utf8).unwrap(),
+                    ),
+                    _ => (field.as_ref().clone(), column.clone()),
+                })
+                .unzip();
+            RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap()
+        };
+        assert!(matches!(
+            in_file_order[0]
+                .schema()
+                .field_with_name("level")
+                .unwrap()
+                .data_type(),
+            DataType::Dictionary(..)
+        ));
+        let old = plain(&in_file_order[1]);
+        let merged = merge_by_timestamp(&[in_file_order[0].clone(), old], 10).unwrap();
+        assert_eq!(messages(&merged), ["0", "1", "2", "3", "4"]);
+        let level = merged[0].schema().field_with_name("level").unwrap().clone();
+        assert_eq!(level.data_type(), &DataType::Utf8);
+        let records = records_from_batch(&merged[0]).unwrap();
+        assert!(records.iter().all(|record| record.level == "info"));
+    }
 }
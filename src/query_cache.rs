@@ -0,0 +1,241 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::query::{LogFilter, LogRecord};
+
+/// Results kept at most, however few logs they hold
+const MAX_ENTRIES: usize = 10_000;
+
+/// Coarsest modification time resolution expected of the storage filesystem: a listing taken
+/// sooner than this after the directory changed may miss a file added in the same tick, so it
+/// is not kept
+const MTIME_RESOLUTION: Duration = Duration::from_secs(1);
+
+/// Cached query results (the `[api.query_cache]` section of the config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryCacheConfig {
+    /// Matching logs kept across all cached results; 0 turns the cache off
+    #[serde(default = "default_max_records")]
+    pub max_records: usize,
+}
+
+impl Default for QueryCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_records: default_max_records(),
+        }
+    }
+}
+
+fn default_max_records() -> usize {
+    100_000
+}
+
+/// A Parquet file as it was when its logs were read; a rewritten file gets a new key
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct FileKey {
+    path: PathBuf,
+    modified: SystemTime,
+    len: u64,
+}
+
+impl FileKey {
+    async fn of(path: &Path) -> Option<Self> {
+        let metadata = tokio::fs::metadata(path).await.ok()?;
+        Some(Self {
+            path: path.to_path_buf(),
+            modified: metadata.modified().ok()?,
+            len: metadata.len(),
+        })
+    }
+}
+
+/// Matching logs of one file for one filter
+struct Entry {
+    records: Arc<Vec<LogRecord>>,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    map: HashMap<(LogFilter, FileKey), Entry>,
+    /// Keys of `map` by when they were last used, least recently used first
+    order: BTreeMap<u64, (LogFilter, FileKey)>,
+    records: usize,
+    /// Bumped on every lookup and insert, so each entry has a `last_used` of its own
+    clock: u64,
+}
+
+impl Entries {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn remove(&mut self, key: &(LogFilter, FileKey)) {
+        if let Some(entry) = self.map.remove(key) {
+            self.order.remove(&entry.last_used);
+            self.records -= entry.records.len();
+        }
+    }
+}
+
+/// Matching logs per filter and file, and the listing of the storage directory. Stored
+/// files are immutable, so a flush only adds files to read: the others come from here.
+pub struct QueryCache {
+    max_records: usize,
+    /// Listing of the directory as of its modification time
+    listing: Mutex<Option<(SystemTime, Vec<PathBuf>)>>,
+    entries: Mutex<Entries>,
+}
+
+impl QueryCache {
+    pub fn new(config: &QueryCacheConfig) -> Self {
+        Self {
+            max_records: config.max_records,
+            listing: Mutex::new(None),
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Parquet files in `dir`, listed again only when the directory changed. A listing taken
+    /// within `MTIME_RESOLUTION` of the change is not kept, as a file added later in the same
+    /// tick would leave the modification time as it is.
+    pub fn list_files(
+        &self,
+        dir: &Path,
+        list: impl FnOnce() -> anyhow::Result<Vec<PathBuf>>,
+    ) -> anyhow::Result<Vec<PathBuf>> {
+        let Some(modified) = std::fs::metadata(dir).and_then(|m| m.modified()).ok() else {
+            return list();
+        };
+        let mut listing = self.listing.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((_, files)) = listing.as_ref().filter(|(at, _)| *at == modified) {
+            return Ok(files.clone());
+        }
+        let listed_at = SystemTime::now();
+        let files = list()?;
+        let settled = listed_at
+            .duration_since(modified)
+            .is_ok_and(|since| since >= MTIME_RESOLUTION);
+        *listing = settled.then(|| (modified, files.clone()));
+        Ok(files)
+    }
+
+    /// Logs of `path` matching `filter`, if cached for the file as it is now
+    pub async fn get(&self, filter: &LogFilter, path: &Path) -> Lookup {
+        let Some(file) = FileKey::of(path).await else {
+            return Lookup::Unknown;
+        };
+        let key = (filter.clone(), file);
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let clock = entries.tick();
+        let Some(entry) = entries.map.get_mut(&key) else {
+            crate::metrics::record_query_cache(false);
+            return Lookup::Miss(CacheKey(key));
+        };
+        let used = std::mem::replace(&mut entry.last_used, clock);
+        let records = entry.records.clone();
+        if let Some(key) = entries.order.remove(&used) {
+            entries.order.insert(clock, key);
+        }
+        crate::metrics::record_query_cache(true);
+        Lookup::Hit(records)
+    }
+
+    /// Keep `records`, read under `key`, dropping the least recently used results past
+    /// `max_records`
+    pub fn insert(&self, key: CacheKey, records: Arc<Vec<LogRecord>>) {
+        if records.len() > self.max_records {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.remove(&key.0);
+        while entries.records + records.len() > self.max_records || entries.map.len() >= MAX_ENTRIES
+        {
+            let Some((_, oldest)) = entries.order.pop_first() else {
+                break;
+            };
+            if let Some(evicted) = entries.map.remove(&oldest) {
+                entries.records -= evicted.records.len();
+            }
+        }
+        let last_used = entries.tick();
+        entries.records += records.len();
+        entries.order.insert(last_used, key.0.clone());
+        entries.map.insert(key.0, Entry { records, last_used });
+    }
+}
+
+/// Answer of `QueryCache::get`
+pub enum Lookup {
+    Hit(Arc<Vec<LogRecord>>),
+    /// Not cached; `insert` the logs read under this key
+    Miss(CacheKey),
+    /// The file could not be looked at
+    Unknown,
+}
+
+/// Where the logs of a file read after a cache miss go
+pub struct CacheKey((LogFilter, FileKey));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::QueryEngine;
+    use crate::schema::LogEntry;
+    use crate::storage::StorageEngine;
+    use parquet::basic::Compression;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_cached_results_follow_new_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().to_path_buf();
+        let mut storage =
+            StorageEngine::new(dir.clone(), Compression::SNAPPY, 100, 1 << 20).unwrap();
+        let mut write = |level: &str| {
+            let log: LogEntry = serde_json::from_value(json!({
+                "timestamp": "2026-01-15T19:00:00Z",
+                "level": level,
+                "message": "m",
+            }))
+            .unwrap();
+            storage.add_log(log).unwrap();
+            storage.flush().unwrap();
+        };
+        write("error");
+        write("info");
+
+        let cache = Arc::new(QueryCache::new(&QueryCacheConfig { max_records: 2 }));
+        let engine = QueryEngine::new(dir.clone()).with_cache(cache.clone());
+        let errors = LogFilter::parse("level:error");
+        assert_eq!(engine.query_async(&errors).await.unwrap().len(), 1);
+        let files = engine.list_files().unwrap();
+        assert!(matches!(
+            cache.get(&errors, &files[0]).await,
+            Lookup::Hit(_)
+        ));
+
+        // A new file is read; the cached ones are not
+        write("error");
+        assert_eq!(engine.query_async(&errors).await.unwrap().len(), 2);
+        assert_eq!(engine.list_files().unwrap().len(), 3);
+        assert!(matches!(
+            cache.get(&errors, &files[1]).await,
+            Lookup::Hit(_)
+        ));
+
+        // Past max_records the least recently used results go first
+        let all = LogFilter::default();
+        assert_eq!(engine.query_async(&all).await.unwrap().len(), 3);
+        let entries = cache.entries.lock().unwrap();
+        assert!(entries.records <= 2);
+        assert_eq!(entries.order.len(), entries.map.len());
+        assert!(entries.map.keys().all(|(filter, _)| *filter == all));
+    }
+}
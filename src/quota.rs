@@ -0,0 +1,417 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::warn;
+
+use crate::notify::{Notification, Notifier, Sinks};
+
+/// Service name of a quota that applies to every service without one of its own
+pub const ANY_SERVICE: &str = "*";
+
+/// Services whose use of one quota is tracked apiece; services first seen in a period after
+/// this many share one allowance
+const MAX_QUOTA_SERVICES: usize = 10_000;
+
+/// Usage key of the services past MAX_QUOTA_SERVICES; no log has an empty service name
+const OVERFLOW_SERVICE: &str = "";
+
+/// One `[[ingest.quotas]]` entry: how much a service may send per hour or day
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaConfig {
+    /// Service the quota applies to, or `*` for each service without a quota of its own
+    pub service: String,
+
+    #[serde(default)]
+    pub period: QuotaPeriod,
+
+    /// Logs allowed per period
+    #[serde(default)]
+    pub max_rows: Option<u64>,
+
+    /// Bytes, as received on the socket, allowed per period
+    #[serde(default)]
+    pub max_bytes: Option<u64>,
+
+    /// What happens to logs over the quota
+    #[serde(default)]
+    pub action: QuotaAction,
+
+    /// With `action = "sample"`, keep one in this many logs over the quota
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: u64,
+
+    /// Notification sinks told the first time a service goes over the quota in a period
+    #[serde(default)]
+    pub sinks: Vec<String>,
+}
+
+/// Quota windows, aligned to UTC
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaPeriod {
+    Hour,
+    #[default]
+    Day,
+}
+
+impl QuotaPeriod {
+    fn seconds(self) -> i64 {
+        match self {
+            QuotaPeriod::Hour => 3600,
+            QuotaPeriod::Day => 86400,
+        }
+    }
+
+    /// Start of the period holding `now`, in Unix seconds
+    fn start(self, now: DateTime<Utc>) -> i64 {
+        now.timestamp().div_euclid(self.seconds()) * self.seconds()
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            QuotaPeriod::Hour => "hour",
+            QuotaPeriod::Day => "day",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaAction {
+    /// Drop every log over the quota
+    #[default]
+    Drop,
+    /// Keep one in `sample_rate` logs over the quota
+    Sample,
+    /// Keep every log; only notify
+    Alert,
+}
+
+impl QuotaAction {
+    fn as_str(self) -> &'static str {
+        match self {
+            QuotaAction::Drop => "drop",
+            QuotaAction::Sample => "sample",
+            QuotaAction::Alert => "alert",
+        }
+    }
+}
+
+fn default_sample_rate() -> u64 {
+    100
+}
+
+impl QuotaConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.service.is_empty() {
+            bail!("ingest.quotas need a service, or \"*\" for every service");
+        }
+        if self.max_rows.is_none() && self.max_bytes.is_none() {
+            bail!(
+                "ingest.quotas for {}: set max_rows, max_bytes or both",
+                self.service
+            );
+        }
+        if self.max_rows == Some(0) || self.max_bytes == Some(0) {
+            bail!(
+                "ingest.quotas for {}: limits must be greater than 0",
+                self.service
+            );
+        }
+        if self.sample_rate == 0 {
+            bail!(
+                "ingest.quotas for {}: sample_rate must be greater than 0",
+                self.service
+            );
+        }
+        if self.action == QuotaAction::Alert && self.sinks.is_empty() {
+            bail!(
+                "ingest.quotas for {}: action \"alert\" needs sinks to notify",
+                self.service
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Use of one quota by one service in the current period
+#[derive(Debug, Default)]
+struct Usage {
+    period_start: i64,
+    rows: u64,
+    bytes: u64,
+    /// Logs over the quota so far this period
+    over: u64,
+}
+
+/// Use of one quota by each service, at most MAX_QUOTA_SERVICES of them
+#[derive(Debug, Default)]
+struct QuotaUsage {
+    services: HashMap<String, Usage>,
+    /// Period the services of earlier periods were last pruned in
+    pruned_for: i64,
+}
+
+impl QuotaUsage {
+    /// Usage by `service` in the period starting at `period_start`
+    fn of(&mut self, service: &str, period_start: i64) -> &mut Usage {
+        let full = |services: &HashMap<String, Usage>| {
+            services.len() >= MAX_QUOTA_SERVICES && !services.contains_key(service)
+        };
+        if full(&self.services) && self.pruned_for != period_start {
+            self.services
+                .retain(|_, usage| usage.period_start == period_start);
+            self.pruned_for = period_start;
+        }
+        let key = if full(&self.services) {
+            OVERFLOW_SERVICE
+        } else {
+            service
+        };
+        if !self.services.contains_key(key) {
+            self.services.insert(key.to_string(), Usage::default());
+        }
+        let usage = self.services.get_mut(key).expect("inserted above");
+        if usage.period_start != period_start {
+            *usage = Usage {
+                period_start,
+                ..Usage::default()
+            };
+        }
+        usage
+    }
+}
+
+struct Quota {
+    config: QuotaConfig,
+    sinks: Sinks,
+}
+
+/// Tracks per-service volume against the configured quotas
+pub struct Quotas {
+    quotas: Vec<Quota>,
+    /// Keyed by quota index, then by service name, so services sharing a metrics label still
+    /// get their own allowance
+    usage: Mutex<Vec<QuotaUsage>>,
+    notifier: Option<Notifier>,
+}
+
+impl Quotas {
+    /// Enforce `quotas`, notifying their sinks through `notifier`
+    pub fn new(quotas: &[QuotaConfig], notifier: Option<Notifier>) -> Result<Self> {
+        let quotas: Vec<_> = quotas
+            .iter()
+            .map(|config| {
+                config.validate()?;
+                let sinks = match &notifier {
+                    Some(notifier) => notifier.sinks(&config.sinks)?,
+                    None if config.sinks.is_empty() => Vec::new(),
+                    None => bail!(
+                        "ingest.quotas for {} name sinks, but none are configured",
+                        config.service
+                    ),
+                };
+                Ok(Quota {
+                    config: config.clone(),
+                    sinks: sinks.into(),
+                })
+            })
+            .collect::<Result<_>>()?;
+        let usage = (0..quotas.len()).map(|_| QuotaUsage::default()).collect();
+        Ok(Self {
+            quotas,
+            usage: Mutex::new(usage),
+            notifier,
+        })
+    }
+
+    /// Count a log of `bytes` from `service`, whose bounded metrics label is `label`, at
+    /// `now`; whether to keep it
+    pub fn admit(&self, service: &str, label: &str, bytes: u64, now: DateTime<Utc>) -> bool {
+        let own = self
+            .quotas
+            .iter()
+            .any(|quota| quota.config.service == service);
+        let wanted = if own { service } else { ANY_SERVICE };
+
+        let mut keep = true;
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        for (index, quota) in self.quotas.iter().enumerate() {
+            let config = &quota.config;
+            if config.service != wanted {
+                continue;
+            }
+            let usage = usage[index].of(service, config.period.start(now));
+            usage.rows += 1;
+            usage.bytes += bytes;
+            let over = config.max_rows.is_some_and(|max| usage.rows > max)
+                || config.max_bytes.is_some_and(|max| usage.bytes > max);
+            if !over {
+                continue;
+            }
+            usage.over += 1;
+            if usage.over == 1 {
+                self.exceeded(quota, service, label, usage);
+            }
+            keep &= match config.action {
+                QuotaAction::Drop => false,
+                QuotaAction::Sample => (usage.over - 1).is_multiple_of(config.sample_rate),
+                QuotaAction::Alert => true,
+            };
+        }
+        if !keep {
+            crate::metrics::record_quota_dropped(label);
+        }
+        keep
+    }
+
+    fn exceeded(&self, quota: &Quota, service: &str, label: &str, usage: &Usage) {
+        let config = &quota.config;
+        let summary = format!(
+            "Service {} is over its quota for this {} ({} logs, {} bytes); over-quota logs: {}",
+            service,
+            config.period.as_str(),
+            usage.rows,
+            usage.bytes,
+            config.action.as_str()
+        );
+        warn!("{}", summary);
+        crate::metrics::record_quota_exceeded(label, config.action.as_str());
+        if let Some(notifier) = &self.notifier {
+            let details = serde_json::json!({
+                "service": service,
+                "period": config.period.as_str(),
+                "period_start": DateTime::from_timestamp(usage.period_start, 0)
+                    .map(|start| start.to_rfc3339()),
+                "max_rows": config.max_rows,
+                "max_bytes": config.max_bytes,
+                "action": config.action.as_str(),
+            });
+            notifier.send(
+                Notification::new("quota", service, summary, details),
+                &quota.sinks,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::{SinkConfig, SinkTarget};
+    use chrono::TimeZone;
+
+    fn quotas(toml: &str) -> Vec<QuotaConfig> {
+        #[derive(Deserialize)]
+        struct Ingest {
+            quotas: Vec<QuotaConfig>,
+        }
+        toml::from_str::<Ingest>(toml).unwrap().quotas
+    }
+
+    #[test]
+    fn test_quotas_drop_sample_and_reset() {
+        let config = quotas(
+            r#"
+            [[quotas]]
+            service = "*"
+            period = "hour"
+            max_rows = 2
+            sinks = ["ops"]
+
+            [[quotas]]
+            service = "checkout"
+            max_bytes = 100
+            action = "sample"
+            sample_rate = 3
+            "#,
+        );
+        let ops = SinkConfig {
+            name: "ops".to_string(),
+            target: SinkTarget::Exec {
+                command: vec!["true".to_string()],
+            },
+        };
+        assert!(Quotas::new(&config, None).is_err());
+        let (notifier, mut notifications) = Notifier::channel(&[ops]);
+        let quotas = Quotas::new(&config, Some(notifier)).unwrap();
+        let at =
+            |hour: u32, minute: u32| Utc.with_ymd_and_hms(2026, 1, 15, hour, minute, 0).unwrap();
+
+        // Every service gets its own share of the `*` quota
+        let kept = |service: &str, bytes: u64, now| {
+            (0..5)
+                .map(|_| quotas.admit(service, service, bytes, now))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(kept("api", 10, at(9, 0)), [true, true, false, false, false]);
+        assert_eq!(
+            kept("search", 10, at(9, 10)),
+            [true, true, false, false, false]
+        );
+        let (notification, sinks) = notifications.try_recv().unwrap();
+        assert_eq!(
+            (notification.kind, notification.name.as_str()),
+            ("quota", "api")
+        );
+        assert_eq!(sinks[0].name, "ops");
+        assert_eq!(notifications.try_recv().unwrap().0.name, "search");
+        assert!(notifications.try_recv().is_err());
+        // A new hour starts a new period
+        assert_eq!(
+            kept("api", 10, at(10, 0)),
+            [true, true, false, false, false]
+        );
+
+        // checkout has its own quota: 100 bytes a day, then one in three kept
+        assert_eq!(
+            kept("checkout", 40, at(9, 0)),
+            [true, true, true, false, false]
+        );
+        assert_eq!(
+            kept("checkout", 40, at(23, 0)),
+            [true, false, false, true, false]
+        );
+        assert_eq!(
+            kept("checkout", 40, at(23, 59)),
+            [false, true, false, false, true]
+        );
+
+        let mut alert = config[1].clone();
+        alert.action = QuotaAction::Alert;
+        assert!(alert.validate().is_err());
+        alert.sinks = vec!["ops".to_string()];
+        alert.max_bytes = None;
+        assert!(alert.validate().is_err());
+    }
+
+    #[test]
+    fn test_quotas_key_on_service_names_with_a_bounded_map() {
+        let config = quotas(
+            r#"
+            [[quotas]]
+            service = "*"
+            max_rows = 1
+            "#,
+        );
+        let quotas = Quotas::new(&config, None).unwrap();
+        let now = Utc.with_ymd_and_hms(2026, 1, 15, 9, 0, 0).unwrap();
+        // Services past the metrics label cap share a label, not an allowance
+        assert!(quotas.admit("billing", "other", 10, now));
+        assert!(quotas.admit("search", "other", 10, now));
+        assert!(!quotas.admit("search", "other", 10, now));
+
+        for i in 2..MAX_QUOTA_SERVICES {
+            assert!(quotas.admit(&format!("svc-{}", i), "other", 10, now));
+        }
+        // Once the map is full, new services share one allowance for the rest of the period
+        assert!(quotas.admit("late-1", "other", 10, now));
+        assert!(!quotas.admit("late-2", "other", 10, now));
+        // The next period starts over with the services seen in it
+        let tomorrow = now + chrono::Duration::days(1);
+        assert!(quotas.admit("late-2", "other", 10, tomorrow));
+        assert!(quotas.admit("late-3", "other", 10, tomorrow));
+    }
+}
@@ -0,0 +1,187 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Idle clients are forgotten once the table grows past this many entries
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Request limits applied to the AI API query endpoints
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// Sustained requests per second allowed for each client address (0 disables)
+    #[serde(default = "default_requests_per_second")]
+    pub requests_per_second: f64,
+
+    /// Requests a client may make in a burst above the sustained rate
+    #[serde(default = "default_burst")]
+    pub burst: u32,
+
+    /// Query requests processed at once across all clients; the rest wait their turn
+    #[serde(default = "default_max_concurrent")]
+    pub max_concurrent: usize,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_second: default_requests_per_second(),
+            burst: default_burst(),
+            max_concurrent: default_max_concurrent(),
+        }
+    }
+}
+
+fn default_requests_per_second() -> f64 {
+    10.0
+}
+
+fn default_burst() -> u32 {
+    20
+}
+
+fn default_max_concurrent() -> usize {
+    4
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token bucket per client address
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// `f64` bits, so the limits can change while requests are served
+    rate: AtomicU64,
+    burst: AtomicU64,
+    clients: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: f64, burst: u32) -> Self {
+        let limiter = Self {
+            rate: AtomicU64::new(0),
+            burst: AtomicU64::new(0),
+            clients: Mutex::new(HashMap::new()),
+        };
+        limiter.set_limits(requests_per_second, burst);
+        limiter
+    }
+
+    pub fn from_config(config: &RateLimitConfig) -> Self {
+        Self::new(config.requests_per_second, config.burst)
+    }
+
+    /// Change the limits; buckets keep their tokens, capped at the new burst on next use
+    pub fn set_limits(&self, requests_per_second: f64, burst: u32) {
+        self.rate
+            .store(requests_per_second.to_bits(), Ordering::Relaxed);
+        self.burst
+            .store(f64::from(burst.max(1)).to_bits(), Ordering::Relaxed);
+    }
+
+    fn rate(&self) -> f64 {
+        f64::from_bits(self.rate.load(Ordering::Relaxed))
+    }
+
+    fn burst(&self) -> f64 {
+        f64::from_bits(self.burst.load(Ordering::Relaxed))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.rate() > 0.0
+    }
+
+    /// Take a token for `client`, or return how long until one is available
+    pub fn check(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let (rate, burst) = (self.rate(), self.burst());
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+
+        if clients.len() >= MAX_TRACKED_CLIENTS && !clients.contains_key(&client) {
+            clients.retain(|_, b| {
+                b.tokens + now.duration_since(b.updated).as_secs_f64() * rate < burst
+            });
+        }
+
+        let bucket = clients.entry(client).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+}
+
+/// Middleware answering 429 once a client exceeds its request rate
+pub async fn enforce_rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if !limiter.is_enabled() {
+        return next.run(req).await;
+    }
+
+    match limiter.check(peer.ip(), Instant::now()) {
+        Ok(()) => next.run(req).await,
+        Err(wait) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(
+                header::RETRY_AFTER,
+                (wait.as_secs_f64().ceil() as u64).max(1).to_string(),
+            )],
+            "Rate limit exceeded",
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_refills_at_configured_rate() {
+        let limiter = RateLimiter::new(2.0, 2);
+        let client: IpAddr = "127.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.1".parse().unwrap();
+        let start = Instant::now();
+
+        assert!(limiter.check(client, start).is_ok());
+        assert!(limiter.check(client, start).is_ok());
+        let wait = limiter.check(client, start).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+
+        // Other clients have their own bucket
+        assert!(limiter.check(other, start).is_ok());
+
+        assert!(limiter
+            .check(client, start + Duration::from_millis(500))
+            .is_ok());
+
+        // New limits apply to existing buckets
+        limiter.set_limits(4.0, 2);
+        let later = start + Duration::from_millis(500);
+        let wait = limiter.check(client, later).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(250));
+    }
+}
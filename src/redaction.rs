@@ -0,0 +1,403 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::schema::LogEntry;
+use crate::trace_storage::TraceSpan;
+
+/// Replacement value used by `RedactionMode::Mask`
+pub const REDACTED_PLACEHOLDER: &str = "[REDACTED]";
+
+/// How a sensitive attribute value is rewritten before it is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RedactionMode {
+    /// Replace the value with a fixed placeholder
+    Mask,
+    /// Replace the value with a SHA-256 digest so equal values stay correlatable
+    Hash,
+}
+
+impl RedactionMode {
+    /// Rewrite a single value
+    pub fn apply(self, value: &str) -> String {
+        match self {
+            RedactionMode::Mask => REDACTED_PLACEHOLDER.to_string(),
+            RedactionMode::Hash => {
+                let digest = Sha256::digest(value.as_bytes());
+                let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+                format!("sha256:{}", hex)
+            }
+        }
+    }
+}
+
+/// Redaction settings for span attributes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedactionConfig {
+    /// Case-insensitive substrings matched against attribute keys
+    #[serde(default = "default_redaction_patterns")]
+    pub patterns: Vec<String>,
+
+    /// Rewrite applied to matching values
+    #[serde(default = "default_redaction_mode")]
+    pub mode: RedactionMode,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            patterns: default_redaction_patterns(),
+            mode: default_redaction_mode(),
+        }
+    }
+}
+
+fn default_redaction_patterns() -> Vec<String> {
+    vec![
+        "authorization".to_string(),
+        "password".to_string(),
+        "set-cookie".to_string(),
+    ]
+}
+
+fn default_redaction_mode() -> RedactionMode {
+    RedactionMode::Mask
+}
+
+/// Rewrites sensitive span attributes before they hit disk
+#[derive(Debug, Clone)]
+pub struct AttributeRedactor {
+    patterns: Vec<String>,
+    mode: RedactionMode,
+}
+
+impl AttributeRedactor {
+    pub fn new(patterns: Vec<String>, mode: RedactionMode) -> Self {
+        Self {
+            patterns: patterns.into_iter().map(|p| p.to_lowercase()).collect(),
+            mode,
+        }
+    }
+
+    pub fn from_config(config: &RedactionConfig) -> Self {
+        Self::new(config.patterns.clone(), config.mode)
+    }
+
+    /// Check whether an attribute key matches any configured pattern
+    pub fn is_sensitive(&self, key: &str) -> bool {
+        let key = key.to_lowercase();
+        self.patterns.iter().any(|p| key.contains(p.as_str()))
+    }
+
+    /// Rewrite a single value according to the redaction mode
+    pub fn redact_value(&self, value: &str) -> String {
+        self.mode.apply(value)
+    }
+
+    /// Redact matching entries of an attribute map in place
+    pub fn redact_attributes(&self, attributes: &mut HashMap<String, String>) {
+        for (key, value) in attributes.iter_mut() {
+            if self.is_sensitive(key) {
+                *value = self.redact_value(value);
+            }
+        }
+    }
+
+    /// Redact span and span event attributes in place
+    pub fn redact_span(&self, span: &mut TraceSpan) {
+        self.redact_attributes(&mut span.attributes);
+        for event in &mut span.events {
+            self.redact_attributes(&mut event.attributes);
+        }
+    }
+}
+
+impl Default for AttributeRedactor {
+    fn default() -> Self {
+        Self::from_config(&RedactionConfig::default())
+    }
+}
+
+/// Value patterns that ship with the daemon
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BuiltinPattern {
+    /// Email addresses
+    Email,
+    /// 13-19 digit card numbers, optionally grouped by spaces or dashes, that pass the Luhn check
+    CreditCard,
+}
+
+impl BuiltinPattern {
+    fn regex(self) -> &'static str {
+        match self {
+            BuiltinPattern::Email => r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}",
+            BuiltinPattern::CreditCard => r"\b\d(?:[ -]?\d){12,18}\b",
+        }
+    }
+
+    fn matches(self, candidate: &str) -> bool {
+        match self {
+            BuiltinPattern::Email => true,
+            BuiltinPattern::CreditCard => passes_luhn(candidate),
+        }
+    }
+}
+
+/// Luhn checksum over the digits of `candidate`, which keeps ids and timestamps from
+/// being mistaken for card numbers
+fn passes_luhn(candidate: &str) -> bool {
+    let mut sum = 0;
+    for (i, digit) in candidate
+        .bytes()
+        .rev()
+        .filter(u8::is_ascii_digit)
+        .map(|b| u32::from(b - b'0'))
+        .enumerate()
+    {
+        sum += if i % 2 == 1 {
+            let doubled = digit * 2;
+            if doubled > 9 {
+                doubled - 9
+            } else {
+                doubled
+            }
+        } else {
+            digit
+        };
+    }
+    sum % 10 == 0
+}
+
+/// Redaction of incoming logs (the `[ingest.redaction]` section of the config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogRedactionConfig {
+    /// Case-insensitive substrings matched against metadata keys at any depth; the whole
+    /// value of a matching key is redacted
+    #[serde(default)]
+    pub keys: Vec<String>,
+
+    /// Built-in value patterns redacted wherever they appear in the message or metadata
+    #[serde(default)]
+    pub builtins: Vec<BuiltinPattern>,
+
+    /// Regular expressions redacted wherever they match in the message or metadata
+    #[serde(default)]
+    pub patterns: Vec<String>,
+
+    /// Rewrite applied to redacted values
+    #[serde(default = "default_redaction_mode")]
+    pub mode: RedactionMode,
+}
+
+impl Default for LogRedactionConfig {
+    fn default() -> Self {
+        Self {
+            keys: Vec::new(),
+            builtins: Vec::new(),
+            patterns: Vec::new(),
+            mode: default_redaction_mode(),
+        }
+    }
+}
+
+impl LogRedactionConfig {
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty() && self.builtins.is_empty() && self.patterns.is_empty()
+    }
+}
+
+/// A compiled value pattern; built-ins may reject a regex match
+#[derive(Debug, Clone)]
+struct ValuePattern {
+    regex: Regex,
+    builtin: Option<BuiltinPattern>,
+}
+
+/// Rewrites sensitive parts of incoming logs before they are queued for storage
+#[derive(Debug, Clone)]
+pub struct LogRedactor {
+    keys: Vec<String>,
+    patterns: Vec<ValuePattern>,
+    mode: RedactionMode,
+}
+
+impl LogRedactor {
+    pub fn from_config(config: &LogRedactionConfig) -> Result<Self> {
+        let mut patterns: Vec<ValuePattern> = config
+            .builtins
+            .iter()
+            .map(|builtin| ValuePattern {
+                regex: Regex::new(builtin.regex()).expect("built-in redaction pattern"),
+                builtin: Some(*builtin),
+            })
+            .collect();
+        for pattern in &config.patterns {
+            let regex = Regex::new(pattern)
+                .with_context(|| format!("Invalid ingest.redaction pattern {:?}", pattern))?;
+            patterns.push(ValuePattern {
+                regex,
+                builtin: None,
+            });
+        }
+
+        Ok(Self {
+            keys: config.keys.iter().map(|k| k.to_lowercase()).collect(),
+            patterns,
+            mode: config.mode,
+        })
+    }
+
+    fn is_sensitive_key(&self, key: &str) -> bool {
+        let key = key.to_lowercase();
+        self.keys.iter().any(|k| key.contains(k.as_str()))
+    }
+
+    /// Redact the message and metadata of `entry` in place
+    pub fn redact_entry(&self, entry: &mut LogEntry) {
+        if let Cow::Owned(message) = self.redact_text(&entry.message) {
+            entry.message = message;
+        }
+        if let Some(metadata) = &mut entry.metadata {
+            self.redact_metadata(metadata);
+        }
+    }
+
+    /// `payload`, a raw message that failed validation, redacted like a log: as JSON when it
+    /// parses, as text otherwise
+    pub fn redact_payload(&self, payload: &str) -> String {
+        match serde_json::from_str::<Value>(payload) {
+            Ok(mut value) => {
+                self.redact_metadata(&mut value);
+                value.to_string()
+            }
+            Err(_) => self.redact_text(payload).into_owned(),
+        }
+    }
+
+    fn redact_metadata(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => {
+                if let Cow::Owned(redacted) = self.redact_text(text) {
+                    *text = redacted;
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|v| self.redact_metadata(v)),
+            Value::Object(fields) => {
+                for (key, field) in fields.iter_mut() {
+                    if self.is_sensitive_key(key) {
+                        let original = match &mut *field {
+                            Value::String(text) => std::mem::take(text),
+                            other => serde_json::to_string(other).unwrap_or_default(),
+                        };
+                        *field = Value::String(self.mode.apply(&original));
+                    } else {
+                        self.redact_metadata(field);
+                    }
+                }
+            }
+            Value::Null | Value::Bool(_) | Value::Number(_) => {}
+        }
+    }
+
+    /// `text` with every pattern match redacted; borrowed when nothing matched
+    fn redact_text<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for pattern in &self.patterns {
+            let replaced = pattern
+                .regex
+                .replace_all(&text, |captures: &regex::Captures| {
+                    let matched = &captures[0];
+                    if pattern.builtin.is_none_or(|b| b.matches(matched)) {
+                        self.mode.apply(matched)
+                    } else {
+                        matched.to_string()
+                    }
+                });
+            if let Cow::Owned(replaced) = replaced {
+                if replaced != *text {
+                    text = Cow::Owned(replaced);
+                }
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_attributes() {
+        let redactor = AttributeRedactor::default();
+        let mut attrs = HashMap::new();
+        attrs.insert(
+            "http.request.header.Authorization".to_string(),
+            "Bearer secret".to_string(),
+        );
+        attrs.insert("http.route".to_string(), "/checkout".to_string());
+
+        redactor.redact_attributes(&mut attrs);
+
+        assert_eq!(
+            attrs["http.request.header.Authorization"],
+            REDACTED_PLACEHOLDER
+        );
+        assert_eq!(attrs["http.route"], "/checkout");
+    }
+
+    #[test]
+    fn test_redact_log_entry() {
+        let redactor = LogRedactor::from_config(&LogRedactionConfig {
+            keys: vec!["password".into()],
+            builtins: vec![BuiltinPattern::Email, BuiltinPattern::CreditCard],
+            patterns: vec![r"ssn=\d{3}-\d{2}-\d{4}".into()],
+            mode: RedactionMode::Mask,
+        })
+        .unwrap();
+        let mut entry: LogEntry = serde_json::from_value(serde_json::json!({
+            "timestamp": "2026-01-15T19:00:00Z",
+            "level": "info",
+            "message": "jane@example.com paid with 4111 1111 1111 1111, order 1234567890123, ssn=123-45-6789",
+            "metadata": { "user": { "DB_Password": 42, "email": "jane@example.com" }, "tags": ["ok"] }
+        }))
+        .unwrap();
+
+        redactor.redact_entry(&mut entry);
+
+        assert_eq!(
+            entry.message,
+            "[REDACTED] paid with [REDACTED], order 1234567890123, [REDACTED]"
+        );
+        let metadata = serde_json::to_value(entry.metadata.unwrap()).unwrap();
+        assert_eq!(
+            metadata,
+            serde_json::json!({
+                "user": { "DB_Password": "[REDACTED]", "email": "[REDACTED]" },
+                "tags": ["ok"]
+            })
+        );
+        assert!(LogRedactor::from_config(&LogRedactionConfig {
+            patterns: vec!["(".into()],
+            ..LogRedactionConfig::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_hash_mode_is_stable() {
+        let redactor = AttributeRedactor::new(vec!["password".into()], RedactionMode::Hash);
+        let a = redactor.redact_value("hunter2");
+        let b = redactor.redact_value("hunter2");
+
+        assert_eq!(a, b);
+        assert!(a.starts_with("sha256:"));
+        assert_ne!(a, "hunter2");
+    }
+}
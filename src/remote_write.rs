@@ -0,0 +1,400 @@
+use anyhow::{bail, Context, Result};
+use glob::Pattern;
+use metrics_exporter_prometheus::PrometheusHandle;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::metrics::{rename_exposition, rename_prefix};
+
+/// Longest a remote-write request may take
+const REMOTE_WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Suffixes of the series a histogram or summary is exposed as
+const SERIES_SUFFIXES: &[&str] = &["_bucket", "_sum", "_count"];
+
+/// The `[metrics.remote_write]` section: derived metrics pushed to a Prometheus remote-write
+/// endpoint for long-term storage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteWriteConfig {
+    /// Endpoint such as `http://prometheus:9090/api/v1/write`
+    pub url: String,
+
+    /// Seconds between writes
+    #[serde(default = "default_remote_write_interval_secs")]
+    pub interval_secs: u64,
+
+    /// Metrics written, as globs over exported names; defaults to the `[[metrics.extract]]`
+    /// metrics
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    /// Labels added to every written series, e.g. `instance`
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+
+    /// Extra request headers, e.g. for authentication
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+fn default_remote_write_interval_secs() -> u64 {
+    30
+}
+
+impl RemoteWriteConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !(self.url.starts_with("http://") || self.url.starts_with("https://")) {
+            bail!(
+                "metrics.remote_write.url must be an http:// or https:// URL: {}",
+                self.url
+            );
+        }
+        if self.interval_secs == 0 {
+            bail!("metrics.remote_write.interval_secs must be at least 1");
+        }
+        for pattern in &self.include {
+            Pattern::new(pattern)
+                .with_context(|| format!("Invalid metrics.remote_write.include {}", pattern))?;
+        }
+        for name in self.labels.keys() {
+            let valid = !name.starts_with("__")
+                && name.chars().enumerate().all(|(i, c)| {
+                    c.is_ascii_alphabetic() || c == '_' || (i > 0 && c.is_ascii_digit())
+                });
+            if name.is_empty() || !valid {
+                bail!("metrics.remote_write.labels: invalid label name {}", name);
+            }
+        }
+        for (name, value) in &self.headers {
+            reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("metrics.remote_write.headers: invalid name {}", name))?;
+            // The value may be a credential, so it is not echoed
+            reqwest::header::HeaderValue::from_str(value).with_context(|| {
+                format!("metrics.remote_write.headers: invalid value of {}", name)
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// One sample of the text exposition, its labels sorted and `__name__` among them
+#[derive(Debug, Clone, PartialEq)]
+struct Series {
+    labels: Vec<(String, String)>,
+    value: f64,
+}
+
+impl Series {
+    fn name(&self) -> &str {
+        self.labels
+            .iter()
+            .find(|(name, _)| name == "__name__")
+            .map_or("", |(_, value)| value)
+    }
+}
+
+/// Parse the samples of a Prometheus text exposition, skipping comments and malformed lines
+fn parse_exposition(text: &str) -> Vec<Series> {
+    text.lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(parse_sample)
+        .collect()
+}
+
+fn parse_sample(line: &str) -> Option<Series> {
+    let name_end = line.find(['{', ' '])?;
+    let mut labels = vec![("__name__".to_string(), line[..name_end].to_string())];
+    let mut rest = &line[name_end..];
+    if let Some(inner) = rest.strip_prefix('{') {
+        rest = inner;
+        loop {
+            rest = rest.trim_start_matches(',');
+            if let Some(after) = rest.strip_prefix('}') {
+                rest = after;
+                break;
+            }
+            let (name, after) = rest.split_once("=\"")?;
+            let mut value = String::new();
+            let mut chars = after.char_indices();
+            let end = loop {
+                match chars.next()? {
+                    (i, '"') => break i,
+                    (_, '\\') => match chars.next()?.1 {
+                        'n' => value.push('\n'),
+                        c => value.push(c),
+                    },
+                    (_, c) => value.push(c),
+                }
+            };
+            labels.push((name.to_string(), value));
+            rest = &after[end + 1..];
+        }
+    }
+    let value = rest.split_whitespace().next()?.parse().ok()?;
+    labels.sort();
+    Some(Series { labels, value })
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// A length-delimited protobuf field
+fn put_bytes(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    put_varint(buf, field << 3 | 2);
+    put_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+/// Protobuf `prometheus.WriteRequest` holding `series`, each with one sample at `timestamp_ms`
+fn encode_write_request(series: &[Series], timestamp_ms: i64) -> Vec<u8> {
+    let mut request = Vec::new();
+    for series in series {
+        let mut encoded = Vec::new();
+        for (name, value) in &series.labels {
+            let mut label = Vec::new();
+            put_bytes(&mut label, 1, name.as_bytes());
+            put_bytes(&mut label, 2, value.as_bytes());
+            put_bytes(&mut encoded, 1, &label);
+        }
+        let mut sample = vec![1 << 3 | 1];
+        sample.extend_from_slice(&series.value.to_le_bytes());
+        put_varint(&mut sample, 2 << 3);
+        put_varint(&mut sample, timestamp_ms as u64);
+        put_bytes(&mut encoded, 2, &sample);
+        put_bytes(&mut request, 1, &encoded);
+    }
+    request
+}
+
+/// Sends the selected series of the exposition to a remote-write endpoint
+struct RemoteWriter {
+    client: reqwest::Client,
+    config: RemoteWriteConfig,
+    include: Vec<Pattern>,
+    prefix: String,
+}
+
+impl RemoteWriter {
+    /// `include` holds the recorded (`log_daemon_`) names written when the config has none
+    fn new(config: RemoteWriteConfig, prefix: String, include: &[String]) -> Result<Self> {
+        config.validate()?;
+        let include = if config.include.is_empty() {
+            include
+                .iter()
+                .map(|name| Pattern::new(&Pattern::escape(&rename_prefix(name, &prefix))))
+                .collect::<Result<_, _>>()?
+        } else {
+            config
+                .include
+                .iter()
+                .map(|pattern| Pattern::new(pattern))
+                .collect::<Result<_, _>>()?
+        };
+        let client = reqwest::Client::builder()
+            .timeout(REMOTE_WRITE_TIMEOUT)
+            .build()
+            .context("Failed to build remote-write client")?;
+        Ok(Self {
+            client,
+            config,
+            include,
+            prefix,
+        })
+    }
+
+    fn wanted(&self, name: &str) -> bool {
+        let family = SERIES_SUFFIXES
+            .iter()
+            .find_map(|suffix| name.strip_suffix(suffix));
+        self.include.iter().any(|pattern| {
+            pattern.matches(name) || family.is_some_and(|family| pattern.matches(family))
+        })
+    }
+
+    /// Snappy-compressed write request for the wanted series of `exposition`
+    fn body(&self, exposition: &str, timestamp_ms: i64) -> Result<Vec<u8>> {
+        let series: Vec<Series> = parse_exposition(&rename_exposition(exposition, &self.prefix))
+            .into_iter()
+            .filter(|series| self.wanted(series.name()))
+            .map(|mut series| {
+                for (name, value) in &self.config.labels {
+                    match series.labels.binary_search_by(|(n, _)| n.cmp(name)) {
+                        Ok(i) => series.labels[i].1 = value.clone(),
+                        Err(i) => series.labels.insert(i, (name.clone(), value.clone())),
+                    }
+                }
+                series
+            })
+            .collect();
+        Ok(snap::raw::Encoder::new().compress_vec(&encode_write_request(&series, timestamp_ms))?)
+    }
+
+    async fn send(&self, exposition: &str) -> Result<()> {
+        let body = self.body(exposition, chrono::Utc::now().timestamp_millis())?;
+        let mut request = self
+            .client
+            .post(&self.config.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/x-protobuf")
+            .header(reqwest::header::CONTENT_ENCODING, "snappy")
+            .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+            .body(body);
+        for (name, value) in &self.config.headers {
+            request = request.header(name, value);
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Periodically write the selected metrics to the remote-write endpoint; `include` is the
+/// default selection
+pub fn spawn_remote_write(
+    config: RemoteWriteConfig,
+    handle: PrometheusHandle,
+    prefix: String,
+    include: &[String],
+) -> Result<()> {
+    let interval = Duration::from_secs(config.interval_secs);
+    let writer = RemoteWriter::new(config, prefix, include)?;
+    info!(
+        "Writing metrics to {} every {}s",
+        writer.config.url,
+        interval.as_secs()
+    );
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // Counters are cumulative, so a failed write only leaves a gap until the next one
+        let mut failing = false;
+        loop {
+            interval.tick().await;
+            match writer.send(&handle.render()).await {
+                Ok(()) if failing => {
+                    info!("Remote write to {} succeeded again", writer.config.url);
+                    failing = false;
+                }
+                Ok(()) => {}
+                Err(e) if !failing => {
+                    warn!("Remote write to {} failed: {:#}", writer.config.url, e);
+                    failing = true;
+                }
+                Err(_) => {}
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Bytes;
+    use axum::http::HeaderMap;
+    use axum::routing::post;
+    use axum::Router;
+
+    const EXPOSITION: &str = r#"# TYPE log_daemon_checkout_latency_ms histogram
+log_daemon_checkout_latency_ms_bucket{service="checkout",le="100"} 3
+log_daemon_checkout_latency_ms_bucket{service="checkout",le="+Inf"} 4
+log_daemon_checkout_latency_ms_sum{service="checkout"} 250.5
+log_daemon_checkout_latency_ms_count{service="checkout"} 4
+# TYPE log_daemon_ingest_count counter
+log_daemon_ingest_count{service="say \"hi\"\\n"} 12
+log_daemon_active_connections 2
+"#;
+
+    #[tokio::test]
+    async fn test_writes_selected_series() {
+        let series = parse_exposition(EXPOSITION);
+        assert_eq!(series.len(), 6);
+        assert_eq!(
+            series[1].labels,
+            [
+                ("__name__", "log_daemon_checkout_latency_ms_bucket"),
+                ("le", "+Inf"),
+                ("service", "checkout")
+            ]
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+        );
+        assert_eq!(series[4].labels[1].1, "say \"hi\"\\n");
+        assert_eq!(series[5].value, 2.0);
+
+        let one = Series {
+            labels: vec![("__name__".to_string(), "a".to_string())],
+            value: 1.0,
+        };
+        let mut expected = vec![0x0a, 0x1c, 0x0a, 0x0d, 0x0a, 0x08];
+        expected.extend_from_slice(b"__name__");
+        expected.extend_from_slice(&[0x12, 0x01, b'a', 0x12, 0x0b, 0x09]);
+        expected.extend_from_slice(&1.0f64.to_le_bytes());
+        expected.extend_from_slice(&[0x10, 0x01]);
+        assert_eq!(encode_write_request(&[one], 1), expected);
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let receiver = Router::new().route(
+            "/api/v1/write",
+            post(move |headers: HeaderMap, body: Bytes| async move {
+                tx.send((headers, body)).unwrap();
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, receiver).await });
+
+        let config: RemoteWriteConfig = toml::from_str(&format!(
+            r#"
+            url = "http://{}/api/v1/write"
+            labels = {{ instance = "host-a" }}
+            headers = {{ Authorization = "Bearer secret" }}
+            "#,
+            addr
+        ))
+        .unwrap();
+        let include = ["log_daemon_checkout_latency_ms".to_string()];
+        let writer = RemoteWriter::new(config.clone(), "acme".to_string(), &include).unwrap();
+        writer.send(EXPOSITION).await.unwrap();
+        let (headers, body) = rx.recv().await.unwrap();
+        assert_eq!(headers["content-encoding"], "snappy");
+        assert_eq!(headers["authorization"], "Bearer secret");
+        let request = snap::raw::Decoder::new().decompress_vec(&body).unwrap();
+        let contains = |needle: &[u8]| request.windows(needle.len()).any(|w| w == needle);
+        // Only the histogram, renamed and with the extra label
+        assert!(contains(b"acme_checkout_latency_ms_sum"));
+        assert!(contains(b"host-a"));
+        assert!(!contains(b"log_daemon"));
+        assert!(!contains(b"ingest_count"));
+
+        let mut globbed = config;
+        globbed.include = vec!["*_count".to_string()];
+        let writer = RemoteWriter::new(globbed.clone(), "log_daemon".to_string(), &[]).unwrap();
+        let request = snap::raw::Decoder::new()
+            .decompress_vec(&writer.body(EXPOSITION, 0).unwrap())
+            .unwrap();
+        let contains = |needle: &[u8]| request.windows(needle.len()).any(|w| w == needle);
+        assert!(contains(b"log_daemon_ingest_count"));
+        assert!(contains(b"log_daemon_checkout_latency_ms_count"));
+        assert!(!contains(b"log_daemon_checkout_latency_ms_sum"));
+
+        globbed
+            .labels
+            .insert("__name__".to_string(), "x".to_string());
+        assert!(globbed.validate().is_err());
+
+        globbed.labels.clear();
+        globbed.validate().unwrap();
+        for (name, value) in [("X Token", "secret"), ("X-Token", "line\nbreak")] {
+            let mut invalid = globbed.clone();
+            invalid.headers = BTreeMap::from([(name.to_string(), value.to_string())]);
+            assert!(invalid.validate().is_err(), "{:?}", name);
+        }
+    }
+}
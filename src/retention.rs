@@ -0,0 +1,463 @@
+use anyhow::{bail, Context, Result};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use crate::file_metadata;
+use crate::query::QueryEngine;
+use crate::stats::human_bytes;
+use crate::trace_storage::list_trace_files;
+use crate::units;
+
+/// Limits on how much stored data is kept
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    /// Delete files last written longer ago than this
+    pub max_age: Option<Duration>,
+    /// Delete the oldest files until logs and traces together take at most this many bytes
+    pub max_size: Option<u64>,
+    /// Ages replacing `max_age` for the logs of some services or levels; the first match wins
+    pub overrides: Vec<RetentionOverride>,
+}
+
+impl RetentionPolicy {
+    /// Age past which a file holding `contents` is deleted; None keeps it
+    pub fn max_age_for(&self, contents: &Contents) -> Option<Duration> {
+        if self.overrides.is_empty() {
+            return self.max_age;
+        }
+        match contents {
+            Contents::Traces => self.max_age,
+            // Keep it as long as any of its logs might need
+            Contents::Unknown => self
+                .overrides
+                .iter()
+                .fold(self.max_age, |age, rule| longest(age, Some(rule.keep))),
+            Contents::Logs { services, levels } if !services.is_empty() && !levels.is_empty() => {
+                // Every pairing, as the file does not say which services logged at which level
+                let mut age = Some(Duration::ZERO);
+                for service in services {
+                    for level in levels {
+                        let rule = self.overrides.iter().find(|r| r.matches(service, level));
+                        age = longest(age, rule.map_or(self.max_age, |rule| Some(rule.keep)));
+                    }
+                }
+                age
+            }
+            Contents::Logs { .. } => self.max_age,
+        }
+    }
+}
+
+/// The longer of two ages, where None is forever
+fn longest(a: Option<Duration>, b: Option<Duration>) -> Option<Duration> {
+    a.zip(b).map(|(a, b)| a.max(b))
+}
+
+/// How long the logs of a service, a level or both are kept instead of the max age, written
+/// `service=payments:180d`, `level=debug:24h` or `service=payments,level=error:1y`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetentionOverride {
+    pub service: Option<String>,
+    pub level: Option<String>,
+    pub keep: Duration,
+}
+
+impl RetentionOverride {
+    /// Whether the rule applies to logs of `service` (`""` for none) at `level`
+    fn matches(&self, service: &str, level: &str) -> bool {
+        self.service.as_ref().is_none_or(|s| s == service)
+            && self
+                .level
+                .as_ref()
+                .is_none_or(|l| l.eq_ignore_ascii_case(level))
+    }
+}
+
+impl FromStr for RetentionOverride {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let Some((selectors, keep)) = s.rsplit_once(':') else {
+            bail!(
+                "Invalid retention override {:?}; expected e.g. service=payments:180d",
+                s
+            );
+        };
+        let mut rule = Self {
+            service: None,
+            level: None,
+            keep: units::parse_duration(keep)?,
+        };
+        for selector in selectors.split(',') {
+            match selector.split_once('=') {
+                Some(("service", service)) if !service.is_empty() => {
+                    rule.service = Some(service.to_string())
+                }
+                Some(("level", level)) if !level.is_empty() => rule.level = Some(level.to_string()),
+                _ => bail!(
+                    "Invalid selector {:?} in retention override {:?}; use service=<name> or level=<level>",
+                    selector,
+                    s
+                ),
+            }
+        }
+        Ok(rule)
+    }
+}
+
+/// Why a file is deleted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteReason {
+    Age,
+    /// Past the shorter age an override gives all of its logs
+    Override,
+    Size,
+}
+
+/// What a file holds, as far as retention overrides are concerned
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Contents {
+    /// Spans, which overrides do not apply to
+    Traces,
+    /// Logs of these services (`""` for none) and levels
+    Logs {
+        services: BTreeSet<String>,
+        levels: BTreeSet<String>,
+    },
+    /// Logs from a file that does not list its services and levels, or could not be read
+    #[default]
+    Unknown,
+}
+
+impl Contents {
+    /// What the footer of the file at `path` lists
+    pub fn read(path: &Path) -> Self {
+        let file = std::fs::File::open(path).ok();
+        let Some(reader) = file.and_then(|file| SerializedFileReader::new(file).ok()) else {
+            return Contents::Unknown;
+        };
+        let entries =
+            file_metadata::daemon_entries(reader.metadata().file_metadata().key_value_metadata());
+        if entries.get("kind").is_some_and(|kind| kind == "traces") {
+            return Contents::Traces;
+        }
+        match (
+            file_metadata::listed_values(&entries, "services"),
+            file_metadata::listed_values(&entries, "levels"),
+        ) {
+            (Some(services), Some(levels)) => Contents::Logs { services, levels },
+            _ => Contents::Unknown,
+        }
+    }
+}
+
+/// A Parquet file of logs or traces
+#[derive(Debug, Clone)]
+pub struct StoredFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+    pub contents: Contents,
+}
+
+/// The files a policy deletes, oldest first, and what is left
+#[derive(Debug, Default)]
+pub struct RetentionPlan {
+    pub delete: Vec<(StoredFile, DeleteReason)>,
+    pub kept_files: usize,
+    pub kept_bytes: u64,
+    /// Files past the max age kept by an override
+    pub extended: usize,
+}
+
+impl RetentionPlan {
+    /// Bytes freed by deleting the planned files
+    pub fn reclaimed(&self) -> u64 {
+        self.delete.iter().map(|(file, _)| file.size).sum()
+    }
+
+    /// Delete the planned files; stops at the first file that cannot be removed
+    pub fn apply(&self) -> Result<()> {
+        for (file, _) in &self.delete {
+            match std::fs::remove_file(&file.path) {
+                // Already gone, e.g. removed by another retention run
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                result => result.with_context(|| format!("Failed to delete {:?}", file.path))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RetentionPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (file, reason) in &self.delete {
+            let reason = match reason {
+                DeleteReason::Age => "older than max age",
+                DeleteReason::Override => "older than its override age",
+                DeleteReason::Size => "over max size",
+            };
+            writeln!(
+                f,
+                "{}  {}  ({})",
+                file.path.display(),
+                human_bytes(file.size),
+                reason
+            )?;
+        }
+        write!(
+            f,
+            "{} file(s), {} reclaimed; {} file(s), {} kept",
+            self.delete.len(),
+            human_bytes(self.reclaimed()),
+            self.kept_files,
+            human_bytes(self.kept_bytes)
+        )?;
+        if self.extended > 0 {
+            write!(
+                f,
+                "; {} file(s) kept past max age by overrides",
+                self.extended
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Parquet files in the log and trace storage directories, oldest first. A trace directory
+/// that does not exist yet has no files.
+pub fn stored_files(storage_dir: &Path, trace_dir: &Path) -> Result<Vec<StoredFile>> {
+    let logs = QueryEngine::new(storage_dir.to_path_buf())
+        .list_files()
+        .with_context(|| format!("Failed to list {:?}", storage_dir))?;
+    // Both kinds of file can share one directory
+    let traces = if trace_dir == storage_dir {
+        Vec::new()
+    } else {
+        list_trace_files(trace_dir)?
+    };
+
+    let mut files = Vec::with_capacity(logs.len() + traces.len());
+    let logs = logs.into_iter().map(|path| (path, Contents::Unknown));
+    let traces = traces.into_iter().map(|path| (path, Contents::Traces));
+    for (path, contents) in logs.chain(traces) {
+        let metadata =
+            std::fs::metadata(&path).with_context(|| format!("Failed to stat {:?}", path))?;
+        files.push(StoredFile {
+            path,
+            size: metadata.len(),
+            modified: metadata.modified()?,
+            contents,
+        });
+    }
+    files.sort_by(|a, b| a.modified.cmp(&b.modified).then(a.path.cmp(&b.path)));
+    Ok(files)
+}
+
+/// Decide which of `files`, sorted oldest first, `policy` deletes at `now`: everything past
+/// its max age, then the oldest of the rest until the total fits in the max size
+pub fn plan(files: Vec<StoredFile>, policy: &RetentionPolicy, now: SystemTime) -> RetentionPlan {
+    let mut plan = RetentionPlan::default();
+    let mut kept = Vec::with_capacity(files.len());
+    for file in files {
+        let age = now.duration_since(file.modified).unwrap_or_default();
+        let past = |max_age: Option<Duration>| max_age.is_some_and(|max_age| age > max_age);
+        let max_age = policy.max_age_for(&file.contents);
+        if past(max_age) {
+            let reason = if max_age == policy.max_age {
+                DeleteReason::Age
+            } else {
+                DeleteReason::Override
+            };
+            plan.delete.push((file, reason));
+        } else {
+            if past(policy.max_age) {
+                plan.extended += 1;
+            }
+            kept.push(file);
+        }
+    }
+
+    let mut total: u64 = kept.iter().map(|file| file.size).sum();
+    let mut kept = kept.into_iter();
+    if let Some(max_size) = policy.max_size {
+        while total > max_size {
+            let Some(file) = kept.next() else { break };
+            total -= file.size;
+            plan.delete.push((file, DeleteReason::Size));
+        }
+    }
+    for file in kept {
+        plan.kept_files += 1;
+        plan.kept_bytes += file.size;
+    }
+    plan
+}
+
+/// Plan retention for the log and trace directories under `policy`
+pub fn plan_dirs(
+    storage_dir: &Path,
+    trace_dir: &Path,
+    policy: &RetentionPolicy,
+) -> Result<RetentionPlan> {
+    if policy.max_age.is_none() && policy.max_size.is_none() && policy.overrides.is_empty() {
+        bail!("Set a max age, a max size, overrides, or any of them together");
+    }
+    let mut files = stored_files(storage_dir, trace_dir)?;
+    read_contents(&mut files, policy);
+    Ok(plan(files, policy, SystemTime::now()))
+}
+
+/// Read what each of `files` holds from its metadata when `policy` has overrides to match
+pub fn read_contents(files: &mut [StoredFile], policy: &RetentionPolicy) {
+    if policy.overrides.is_empty() {
+        return;
+    }
+    for file in files {
+        if file.contents == Contents::Unknown {
+            file.contents = Contents::read(&file.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_plan_deletes_by_age_then_size() {
+        let temp_dir = TempDir::new().unwrap();
+        let now = SystemTime::now();
+        let hour = Duration::from_secs(3600);
+        let file = |name: &str, size: u64, hours_ago: u32| {
+            let path = temp_dir.path().join(name);
+            std::fs::write(&path, vec![0u8; size as usize]).unwrap();
+            StoredFile {
+                path,
+                size,
+                modified: now - hour * hours_ago,
+                contents: Contents::Unknown,
+            }
+        };
+        let files = vec![
+            file("logs_1.parquet", 400, 50),
+            file("traces_1.parquet", 300, 30),
+            file("logs_2.parquet", 200, 20),
+            file("traces_2.parquet", 100, 10),
+            file("logs_3.parquet", 100, 1),
+        ];
+
+        let policy = RetentionPolicy {
+            max_age: Some(hour * 48),
+            max_size: Some(250),
+            overrides: Vec::new(),
+        };
+        let plan = plan(files, &policy, now);
+        let deleted: Vec<(String, DeleteReason)> = plan
+            .delete
+            .iter()
+            .map(|(file, reason)| {
+                let name = file.path.file_name().unwrap().to_string_lossy();
+                (name.into_owned(), *reason)
+            })
+            .collect();
+        assert_eq!(
+            deleted,
+            vec![
+                ("logs_1.parquet".to_string(), DeleteReason::Age),
+                ("traces_1.parquet".to_string(), DeleteReason::Size),
+                ("logs_2.parquet".to_string(), DeleteReason::Size),
+            ]
+        );
+        assert_eq!(
+            (plan.reclaimed(), plan.kept_files, plan.kept_bytes),
+            (900, 2, 200)
+        );
+        assert!(plan
+            .to_string()
+            .ends_with("3 file(s), 900 B reclaimed; 2 file(s), 200 B kept"));
+
+        plan.apply().unwrap();
+        let remaining = stored_files(temp_dir.path(), &temp_dir.path().join("traces")).unwrap();
+        assert_eq!(remaining.len(), 2);
+        assert!(plan_dirs(
+            temp_dir.path(),
+            temp_dir.path(),
+            &RetentionPolicy::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_overrides_by_service_and_level() {
+        let rules: Vec<RetentionOverride> = ["service=payments:180d", "level=DEBUG:1d"]
+            .iter()
+            .map(|rule| rule.parse().unwrap())
+            .collect();
+        assert_eq!(rules[0].service.as_deref(), Some("payments"));
+        assert_eq!(rules[1].keep, Duration::from_secs(86400));
+        for invalid in [
+            "payments:180d",
+            "service=payments",
+            "host=a:1d",
+            "level=:1d",
+        ] {
+            assert!(invalid.parse::<RetentionOverride>().is_err(), "{}", invalid);
+        }
+
+        let day = Duration::from_secs(86400);
+        let policy = RetentionPolicy {
+            max_age: Some(day * 30),
+            max_size: None,
+            overrides: rules,
+        };
+        let logs = |services: &[&str], levels: &[&str]| Contents::Logs {
+            services: services.iter().map(|s| s.to_string()).collect(),
+            levels: levels.iter().map(|l| l.to_string()).collect(),
+        };
+        let now = SystemTime::now();
+        let file = |name: &str, days_ago: u32, contents: Contents| StoredFile {
+            path: PathBuf::from(name),
+            size: 10,
+            modified: now - day * days_ago,
+            contents,
+        };
+        let files = vec![
+            file("payments_old", 200, logs(&["payments"], &["info"])),
+            file("payments", 100, logs(&["payments", "api"], &["info"])),
+            file("unknown", 100, Contents::Unknown),
+            file("traces", 100, Contents::Traces),
+            file("mixed", 10, logs(&["api"], &["debug", "info"])),
+            file("debug", 2, logs(&["api", ""], &["debug"])),
+        ];
+        // Debug logs of payments are kept for its 180 days, as its rule comes first
+        assert_eq!(
+            policy.max_age_for(&logs(&["payments"], &["debug"])),
+            Some(day * 180)
+        );
+
+        let plan = plan(files, &policy, now);
+        let deleted: Vec<(&str, DeleteReason)> = plan
+            .delete
+            .iter()
+            .map(|(file, reason)| (file.path.to_str().unwrap(), *reason))
+            .collect();
+        assert_eq!(
+            deleted,
+            [
+                ("payments_old", DeleteReason::Override),
+                ("traces", DeleteReason::Age),
+                ("debug", DeleteReason::Override),
+            ]
+        );
+        assert_eq!((plan.kept_files, plan.extended), (3, 2));
+        assert!(plan
+            .to_string()
+            .ends_with("; 2 file(s) kept past max age by overrides"));
+    }
+}
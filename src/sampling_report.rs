@@ -0,0 +1,157 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::intern::intern;
+use crate::pipeline::CANONICAL_LEVELS;
+use crate::schema::LogEntry;
+
+/// Service of the sampling report rows stored among the logs
+pub const REPORT_SERVICE: &str = "daemon_rs";
+
+/// Message of the sampling report rows
+pub const REPORT_MESSAGE: &str = "sampling report";
+
+/// Metadata key holding the counts of a sampling report row
+pub const REPORT_KEY: &str = "sampling_report";
+
+/// Level reported for logs whose level is not one of the canonical ones
+const OTHER_LEVEL: &str = "other";
+
+/// Why a log accepted on the socket was never stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DropReason {
+    /// Over its service's quota
+    Quota,
+    /// Shed or rejected by the disk watchdog
+    DiskPressure,
+    /// The ingest queue was full
+    QueueFull,
+}
+
+impl DropReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DropReason::Quota => "quota",
+            DropReason::DiskPressure => "disk_pressure",
+            DropReason::QueueFull => "queue_full",
+        }
+    }
+}
+
+/// Counts of the logs dropped at ingest since the last report, stored as a log row so lower
+/// stored volumes are not misread later
+pub struct SamplingReport {
+    dropped: Mutex<Dropped>,
+}
+
+struct Dropped {
+    since: DateTime<Utc>,
+    /// Keyed by service label, level and reason
+    counts: BTreeMap<(String, &'static str, DropReason), u64>,
+}
+
+impl SamplingReport {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            dropped: Mutex::new(Dropped {
+                since: now,
+                counts: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Count a log at `level` from `service` (its bounded metrics label) dropped for `reason`
+    pub fn record(&self, service: &str, level: &str, reason: DropReason) {
+        let level = CANONICAL_LEVELS
+            .iter()
+            .find(|canonical| canonical.eq_ignore_ascii_case(level))
+            .copied()
+            .unwrap_or(OTHER_LEVEL);
+        let mut dropped = self.dropped.lock().unwrap_or_else(|e| e.into_inner());
+        *dropped
+            .counts
+            .entry((service.to_string(), level, reason))
+            .or_default() += 1;
+    }
+
+    /// A log recording how many logs each service and level lost, and why, since the last
+    /// report; None when nothing was dropped
+    pub fn take(&self, now: DateTime<Utc>) -> Option<LogEntry> {
+        let mut dropped = self.dropped.lock().unwrap_or_else(|e| e.into_inner());
+        let since = std::mem::replace(&mut dropped.since, now);
+        let counts = std::mem::take(&mut dropped.counts);
+        if counts.is_empty() {
+            return None;
+        }
+        let time = |t: DateTime<Utc>| t.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+        let total: u64 = counts.values().sum();
+        let drops: Vec<_> = counts
+            .into_iter()
+            .map(|((service, level, reason), dropped)| {
+                serde_json::json!({
+                    "service": service,
+                    "level": level,
+                    "reason": reason.as_str(),
+                    "dropped": dropped,
+                })
+            })
+            .collect();
+        Some(LogEntry {
+            timestamp: time(now),
+            level: intern("info"),
+            message: REPORT_MESSAGE.to_string(),
+            service: Some(intern(REPORT_SERVICE)),
+            trace_id: None,
+            metadata: Some(serde_json::json!({
+                REPORT_KEY: {
+                    "since": time(since),
+                    "until": time(now),
+                    "dropped": total,
+                    "drops": drops,
+                }
+            })),
+            schema_version: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_dropped_logs_are_reported_by_service_level_and_reason() {
+        let now = Utc.with_ymd_and_hms(2026, 1, 15, 9, 0, 0).unwrap();
+        let report = SamplingReport::new(now);
+        assert!(report.take(now).is_none());
+
+        for level in ["info", "INFO", "error", "notice"] {
+            report.record("api", level, DropReason::Quota);
+        }
+        report.record("search", "warn", DropReason::DiskPressure);
+        report.record("search", "warn", DropReason::QueueFull);
+
+        let later = now + chrono::Duration::seconds(60);
+        let row = report.take(later).unwrap();
+        assert_eq!(row.service.as_deref(), Some(REPORT_SERVICE));
+        assert_eq!(row.message, REPORT_MESSAGE);
+        assert_eq!(row.timestamp, "2026-01-15T09:01:00Z");
+        let counts = &row.metadata.unwrap()[REPORT_KEY];
+        assert_eq!(counts["since"], "2026-01-15T09:00:00Z");
+        assert_eq!(counts["dropped"], 6);
+        assert_eq!(
+            counts["drops"],
+            serde_json::json!([
+                { "service": "api", "level": "error", "reason": "quota", "dropped": 1 },
+                { "service": "api", "level": "info", "reason": "quota", "dropped": 2 },
+                { "service": "api", "level": "other", "reason": "quota", "dropped": 1 },
+                { "service": "search", "level": "warn", "reason": "disk_pressure", "dropped": 1 },
+                { "service": "search", "level": "warn", "reason": "queue_full", "dropped": 1 },
+            ])
+        );
+        // Counts start over after each report
+        assert!(report.take(later).is_none());
+    }
+}
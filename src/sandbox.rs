@@ -0,0 +1,481 @@
+use anyhow::{bail, Context, Result};
+use landlock::{
+    path_beneath_rules, Access, AccessFs, BitFlags, Ruleset, RulesetAttr, RulesetCreatedAttr,
+    RulesetStatus, ABI,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::keys::KeySource;
+use crate::notify::SinkTarget;
+
+/// Landlock rights are those of this ABI, or the subset the kernel knows
+const LANDLOCK_ABI: ABI = ABI::V3;
+
+/// System directories that stay readable, so the loader, exec sinks, TLS roots and
+/// `/proc/self` keep working
+const SYSTEM_READ: &[&str] = &["/usr", "/bin", "/sbin", "/lib", "/lib64", "/etc", "/proc"];
+
+const DEVICES: &[&str] = &["/dev/null", "/dev/zero", "/dev/urandom", "/dev/random"];
+
+/// Opt-in hardening of `serve` (the `[sandbox]` section of the config file)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SandboxConfig {
+    /// Restrict filesystem access with Landlock and block dangerous syscalls with seccomp
+    /// once the config is loaded
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Extra files or directories the daemon may read, e.g. what exec sinks need
+    #[serde(default)]
+    pub read_paths: Vec<PathBuf>,
+
+    /// Extra files or directories the daemon may read and write
+    #[serde(default)]
+    pub write_paths: Vec<PathBuf>,
+}
+
+impl SandboxConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self
+            .read_paths
+            .iter()
+            .chain(&self.write_paths)
+            .any(|path| path.as_os_str().is_empty())
+        {
+            bail!("sandbox.read_paths and sandbox.write_paths must not contain empty paths");
+        }
+        Ok(())
+    }
+}
+
+/// Filesystem access left to a sandboxed daemon
+#[derive(Debug, Default)]
+pub struct Sandbox {
+    /// Readable, and executable for the loader and exec sinks
+    system: Vec<PathBuf>,
+    read: Vec<PathBuf>,
+    write: Vec<PathBuf>,
+    /// Directories holding the listening sockets
+    sockets: Vec<PathBuf>,
+}
+
+/// What a sandbox could enforce on this kernel
+#[derive(Debug)]
+pub struct Applied {
+    pub landlock: RulesetStatus,
+    pub seccomp: bool,
+    /// Allowed paths that did not exist, and so stay inaccessible
+    pub missing: Vec<PathBuf>,
+}
+
+impl fmt::Display for Applied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let landlock = match self.landlock {
+            RulesetStatus::FullyEnforced => "enforced",
+            RulesetStatus::PartiallyEnforced => "partially enforced",
+            RulesetStatus::NotEnforced => "not supported by this kernel",
+        };
+        let seccomp = if self.seccomp {
+            "enforced"
+        } else {
+            "not supported on this architecture"
+        };
+        write!(f, "Landlock {}, seccomp {}", landlock, seccomp)
+    }
+}
+
+impl Sandbox {
+    /// The paths `serve` uses with `config`, loaded from `config_path`; creates the
+    /// directories it writes so they can be allowed before they are needed
+    pub fn for_serve(
+        config: &Config,
+        config_path: Option<&Path>,
+        config_dir: &Path,
+    ) -> Result<Self> {
+        let mut sandbox = Sandbox {
+            system: SYSTEM_READ.iter().map(PathBuf::from).collect(),
+            ..Sandbox::default()
+        };
+        // The binary's own directory, for the new process started on SIGUSR2
+        if let Some(dir) = std::env::current_exe()
+            .ok()
+            .as_deref()
+            .and_then(Path::parent)
+        {
+            sandbox.system.push(dir.to_path_buf());
+        }
+        sandbox.system.extend(
+            std::env::split_paths(&std::env::var_os("PATH").unwrap_or_default())
+                .filter(|dir| dir.is_absolute()),
+        );
+        sandbox.system.push("/sys/fs/cgroup".into());
+
+        let mut dirs = vec![
+            config.storage_dir.clone(),
+            config.trace_storage_dir.clone(),
+            config.registry_dir(config_dir),
+        ];
+        dirs.extend(config.ingest.dead_letter_dirs(&config.storage_dir));
+        dirs.extend(config.forward.as_ref().map(|f| f.spool_dir.clone()));
+        dirs.extend(config.write_failures.wal_dir.clone());
+        // Files appended to are replaced on rotation, so their directories are allowed
+        let appended = config
+            .notify
+            .sinks
+            .iter()
+            .filter_map(|sink| match &sink.target {
+                SinkTarget::File { path } => Some(path),
+                _ => None,
+            })
+            .chain(&config.audit_log);
+        dirs.extend(appended.map(|path| parent(path)));
+        for dir in &dirs {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory {:?}", dir))?;
+        }
+        sandbox.write = dirs;
+        sandbox
+            .write
+            .extend(config.sandbox.write_paths.iter().cloned());
+
+        // Reloaded files are watched and replaced in their directories
+        sandbox.read.extend(config_path.map(parent));
+        sandbox.read.extend(
+            config
+                .schema_path
+                .iter()
+                .chain(config.schemas.values())
+                .map(|path| parent(path)),
+        );
+        sandbox.read.extend(config.alert_rules.iter().cloned());
+        sandbox
+            .read
+            .extend(config.ingest.enrich.files.values().cloned());
+        if let Some(tls) = &config.api.tls {
+            sandbox.read.push(tls.cert_path.clone());
+            if let KeySource::File(path) = tls.key_source() {
+                sandbox.read.push(path);
+            }
+        }
+        sandbox
+            .read
+            .extend(config.sandbox.read_paths.iter().cloned());
+
+        sandbox.sockets.push(parent(&config.socket_path));
+        sandbox
+            .sockets
+            .extend(config.admin_socket.as_deref().map(parent));
+        Ok(sandbox)
+    }
+
+    /// Restrict this thread, and every thread and process it starts from now on, to the
+    /// sandbox; threads already running keep full access
+    pub fn apply(&self) -> Result<Applied> {
+        let missing = self
+            .read
+            .iter()
+            .chain(&self.write)
+            .filter(|path| !path.exists())
+            .cloned()
+            .collect();
+        let abi = LANDLOCK_ABI;
+        let device: BitFlags<AccessFs> =
+            AccessFs::ReadFile | AccessFs::WriteFile | AccessFs::Truncate;
+        let data: BitFlags<AccessFs> = AccessFs::ReadFile | AccessFs::ReadDir;
+        let socket: BitFlags<AccessFs> = AccessFs::MakeSock | AccessFs::RemoveFile;
+        let status = Ruleset::default()
+            .handle_access(AccessFs::from_all(abi))?
+            .create()?
+            .add_rules(path_beneath_rules(&self.system, AccessFs::from_read(abi)))?
+            .add_rules(path_beneath_rules(DEVICES, device))?
+            .add_rules(path_beneath_rules(&self.read, data))?
+            .add_rules(path_beneath_rules(&self.write, AccessFs::from_all(abi)))?
+            .add_rules(path_beneath_rules(&self.sockets, socket))?
+            .restrict_self()
+            .context("Failed to apply the Landlock ruleset")?;
+
+        let seccomp = seccomp::apply()?;
+        Ok(Applied {
+            landlock: status.ruleset,
+            seccomp,
+            missing,
+        })
+    }
+}
+
+/// Sandbox `serve` if `config` asks for it; call before any other thread starts
+pub fn apply_for_serve(
+    config: &Config,
+    config_path: Option<&Path>,
+    config_dir: &Path,
+) -> Result<Option<Applied>> {
+    if !config.sandbox.enabled {
+        return Ok(None);
+    }
+    let applied = Sandbox::for_serve(config, config_path, config_dir)?.apply()?;
+    Ok(Some(applied))
+}
+
+/// Log what `apply_for_serve` enforced, once logging is set up
+pub fn report(applied: &Applied) {
+    if applied.landlock == RulesetStatus::NotEnforced {
+        warn!("Sandbox: {}; filesystem access is not restricted", applied);
+    } else {
+        info!("Sandbox: {}", applied);
+    }
+    for path in &applied.missing {
+        warn!(
+            "Sandbox path {:?} does not exist and stays inaccessible",
+            path
+        );
+    }
+}
+
+/// Directory of `path`, `.` for a bare file name
+fn parent(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// A seccomp filter failing syscalls a log daemon has no use for with `EPERM`
+mod seccomp {
+    use anyhow::{bail, Result};
+    use libc::{
+        c_long, sock_filter, sock_fprog, BPF_ABS, BPF_JEQ, BPF_JGE, BPF_JMP, BPF_JSET, BPF_K,
+        BPF_LD, BPF_RET, BPF_W, SECCOMP_RET_ALLOW, SECCOMP_RET_ERRNO,
+    };
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xC000_003E);
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xC000_00B7);
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    const AUDIT_ARCH: Option<u32> = None;
+
+    /// Offsets into `struct seccomp_data`
+    const NR: u32 = 0;
+    const ARCH: u32 = 4;
+    /// Low half of the first argument
+    const ARG0: u32 = 16;
+
+    /// Namespaces, which `clone` may not create
+    const NEW_NAMESPACES: libc::c_int = libc::CLONE_NEWNS
+        | libc::CLONE_NEWUSER
+        | libc::CLONE_NEWPID
+        | libc::CLONE_NEWNET
+        | libc::CLONE_NEWUTS
+        | libc::CLONE_NEWIPC
+        | libc::CLONE_NEWCGROUP;
+
+    /// Kernel, namespace, tracing and clock administration; setuid and execve stay allowed
+    /// for the privilege drop, exec sinks and the SIGUSR2 upgrade
+    #[rustfmt::skip]
+    const DENIED: &[c_long] = &[
+        libc::SYS_ptrace, libc::SYS_process_vm_readv, libc::SYS_process_vm_writev,
+        libc::SYS_mount, libc::SYS_umount2, libc::SYS_pivot_root, libc::SYS_chroot,
+        libc::SYS_fsopen, libc::SYS_fsmount, libc::SYS_move_mount, libc::SYS_open_tree,
+        libc::SYS_open_by_handle_at, libc::SYS_setns, libc::SYS_unshare,
+        libc::SYS_swapon, libc::SYS_swapoff, libc::SYS_reboot, libc::SYS_quotactl, libc::SYS_acct,
+        libc::SYS_kexec_load, libc::SYS_kexec_file_load,
+        libc::SYS_init_module, libc::SYS_finit_module, libc::SYS_delete_module,
+        libc::SYS_bpf, libc::SYS_perf_event_open, libc::SYS_userfaultfd,
+        libc::SYS_keyctl, libc::SYS_add_key, libc::SYS_request_key,
+        libc::SYS_settimeofday, libc::SYS_clock_settime, libc::SYS_clock_adjtime,
+        libc::SYS_adjtimex, libc::SYS_sethostname, libc::SYS_setdomainname,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_iopl,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_ioperm,
+    ];
+
+    #[derive(Clone, Copy)]
+    enum To {
+        Next,
+        Allow,
+        Deny,
+        /// `clone3` passes its flags behind a pointer; `ENOSYS` makes libc fall back to `clone`
+        NoSys,
+    }
+
+    struct Insn {
+        code: u32,
+        k: u32,
+        jt: To,
+        jf: To,
+    }
+
+    fn load(offset: u32) -> Insn {
+        Insn {
+            code: BPF_LD | BPF_W | BPF_ABS,
+            k: offset,
+            jt: To::Next,
+            jf: To::Next,
+        }
+    }
+
+    fn jump(op: u32, k: u32, jt: To, jf: To) -> Insn {
+        Insn {
+            code: BPF_JMP | op | BPF_K,
+            k,
+            jt,
+            jf,
+        }
+    }
+
+    /// The filter program for `arch`
+    fn program(arch: u32) -> Vec<sock_filter> {
+        let mut body = vec![
+            load(ARCH),
+            jump(BPF_JEQ, arch, To::Next, To::Deny),
+            load(NR),
+        ];
+        // x32 syscalls on x86_64 would bypass the numbers below
+        if cfg!(target_arch = "x86_64") {
+            body.push(jump(BPF_JGE, 0x4000_0000, To::Deny, To::Next));
+        }
+        for &nr in DENIED {
+            body.push(jump(BPF_JEQ, nr as u32, To::Deny, To::Next));
+        }
+        body.push(jump(BPF_JEQ, libc::SYS_clone3 as u32, To::NoSys, To::Next));
+        body.push(jump(BPF_JEQ, libc::SYS_clone as u32, To::Next, To::Allow));
+        body.push(load(ARG0));
+        body.push(jump(BPF_JSET, NEW_NAMESPACES as u32, To::Deny, To::Allow));
+
+        let allow = body.len();
+        let target = |at: usize, to: To| -> u8 {
+            let index = match to {
+                To::Next => at + 1,
+                To::Allow => allow,
+                To::Deny => allow + 1,
+                To::NoSys => allow + 2,
+            };
+            u8::try_from(index - at - 1).expect("seccomp program fits short jumps")
+        };
+        let mut program: Vec<sock_filter> = body
+            .iter()
+            .enumerate()
+            .map(|(at, insn)| sock_filter {
+                code: insn.code as u16,
+                jt: target(at, insn.jt),
+                jf: target(at, insn.jf),
+                k: insn.k,
+            })
+            .collect();
+        for action in [
+            SECCOMP_RET_ALLOW,
+            SECCOMP_RET_ERRNO | libc::EPERM as u32,
+            SECCOMP_RET_ERRNO | libc::ENOSYS as u32,
+        ] {
+            program.push(sock_filter {
+                code: (BPF_RET | BPF_K) as u16,
+                jt: 0,
+                jf: 0,
+                k: action,
+            });
+        }
+        program
+    }
+
+    /// Install the filter on this thread and its future children; false on architectures
+    /// without syscall numbers here
+    pub fn apply() -> Result<bool> {
+        let Some(arch) = AUDIT_ARCH else {
+            return Ok(false);
+        };
+        let program = program(arch);
+        let fprog = sock_fprog {
+            len: program.len() as u16,
+            filter: program.as_ptr() as *mut sock_filter,
+        };
+        // SAFETY: plain prctl calls; `fprog` points at `program`, which outlives them
+        unsafe {
+            if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                bail!(
+                    "Failed to set no_new_privs: {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+            if libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &fprog as *const sock_fprog,
+            ) != 0
+            {
+                bail!(
+                    "Failed to install the seccomp filter: {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sandbox_limits_files_and_syscalls() {
+        let temp_dir = TempDir::new().unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            storage_dir = "{dir}/logs"
+            trace_storage_dir = "{dir}/traces"
+            socket_path = "{dir}/run/daemon.sock"
+            audit_log = "{dir}/audit/audit.jsonl"
+
+            [sandbox]
+            enabled = true
+            "#,
+            dir = temp_dir.path().display()
+        ))
+        .unwrap();
+        config.validate().unwrap();
+        let secret = temp_dir.path().join("secret.txt");
+        std::fs::write(&secret, "hunter2").unwrap();
+
+        // Only the thread below and what it starts are sandboxed
+        let dir = temp_dir.path().to_path_buf();
+        let applied = std::thread::spawn(move || {
+            let sandbox = Sandbox::for_serve(&config, None, &dir).unwrap();
+            let applied = sandbox.apply().unwrap();
+            std::fs::write(config.storage_dir.join("a.parquet"), "ok").unwrap();
+            assert!(dir.join("audit").is_dir() && dir.join("schema_registry").is_dir());
+            if applied.landlock != RulesetStatus::NotEnforced {
+                assert!(std::fs::read(&secret).is_err());
+                assert!(std::fs::write(dir.join("new.txt"), "").is_err());
+            }
+            if applied.seccomp {
+                // SAFETY: fails with EPERM under the filter
+                let unshared = unsafe { libc::unshare(libc::CLONE_NEWUSER) };
+                assert_eq!(unshared, -1);
+                assert_eq!(
+                    std::io::Error::last_os_error().raw_os_error(),
+                    Some(libc::EPERM)
+                );
+                // Threads still start: clone3 falls back to clone
+                std::thread::spawn(|| 1).join().unwrap();
+            }
+            applied
+        })
+        .join()
+        .unwrap();
+        // Every directory serve writes was created before it was allowed
+        assert!(
+            !applied
+                .missing
+                .iter()
+                .any(|path| path.starts_with(temp_dir.path())),
+            "{:?}",
+            applied.missing
+        );
+    }
+}
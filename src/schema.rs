@@ -1,29 +1,470 @@
 use anyhow::{Context, Result};
-use jsonschema::JSONSchema;
+use jsonschema::error::ValidationErrorKind;
+use jsonschema::{JSONSchema, ValidationError};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use simd_json_derive::{Deserialize as SimdDeserialize, Serialize as SimdSerialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
-use simd_json::OwnedValue;
+use crate::intern::{intern, interned, interned_opt};
+use crate::schema_formats;
 
-/// Strongly typed log entry for SIMD parsing
-#[derive(Debug, Clone, Serialize, Deserialize, SimdSerialize, SimdDeserialize)]
+/// Strongly typed log entry for SIMD parsing; levels and services are interned, since a few
+/// values repeat across millions of logs
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LogEntry {
     pub timestamp: String,
-    pub level: String,
+    #[serde(deserialize_with = "interned")]
+    pub level: Arc<str>,
     pub message: String,
-    pub service: Option<String>,
+    #[serde(default, deserialize_with = "interned_opt")]
+    pub service: Option<Arc<str>>,
     pub trace_id: Option<String>,
-    pub metadata: Option<OwnedValue>,
+    pub metadata: Option<Value>,
+    /// Registry version of the schema that accepted the entry; set by the daemon on parse
+    pub schema_version: Option<String>,
+}
+
+/// Wire shape of a log entry: like `LogEntry`, but `level` and `timestamp` may also be numbers
+/// (severities and epoch times), `timestamp` may be missing, and producers cannot set the
+/// schema version
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WireEntry {
+    #[serde(default, deserialize_with = "timestamp_from_string_or_number")]
+    timestamp: Option<String>,
+    #[serde(deserialize_with = "interned_string_or_number")]
+    level: Arc<str>,
+    message: String,
+    #[serde(default, deserialize_with = "interned_opt")]
+    service: Option<Arc<str>>,
+    trace_id: Option<String>,
+    metadata: Option<Value>,
+}
+
+impl From<WireEntry> for LogEntry {
+    fn from(wire: WireEntry) -> Self {
+        Self {
+            // Missing timestamps are left to the ingest pipeline
+            timestamp: wire.timestamp.unwrap_or_default(),
+            level: wire.level,
+            message: wire.message,
+            service: wire.service,
+            trace_id: wire.trace_id,
+            metadata: wire.metadata,
+            schema_version: None,
+        }
+    }
+}
+
+/// Reads a string, or a number as its decimal text, into whatever `F` makes of the text
+struct StringOrNumber<F>(F);
+
+impl<T, F: FnOnce(&str) -> T> serde::de::Visitor<'_> for StringOrNumber<F> {
+    type Value = Option<T>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a string or a number")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(Some((self.0)(v)))
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(Some((self.0)(&v.to_string())))
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(Some((self.0)(&v.to_string())))
+    }
+
+    fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(Some((self.0)(&v.to_string())))
+    }
+
+    fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+}
+
+fn interned_string_or_number<'de, D>(deserializer: D) -> Result<Arc<str>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    deserializer
+        .deserialize_any(StringOrNumber(intern))?
+        .ok_or_else(|| serde::de::Error::custom("expected a string or a number, found null"))
+}
+
+fn timestamp_from_string_or_number<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    deserializer.deserialize_any(StringOrNumber(str::to_string))
+}
+
+/// Why an incoming message was rejected, reported as the `reason` metric label
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    /// Not parseable as JSON
+    MalformedJson,
+    /// Valid JSON that violates the schema or lacks required fields
+    SchemaViolation,
+    /// A `timestamp` that is missing or not in any accepted format
+    InvalidTimestamp,
+    /// A message or metadata over the configured size limit
+    TooLarge,
+}
+
+impl RejectReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RejectReason::MalformedJson => "malformed_json",
+            RejectReason::SchemaViolation => "schema_violation",
+            RejectReason::InvalidTimestamp => "invalid_timestamp",
+            RejectReason::TooLarge => "too_large",
+        }
+    }
+}
+
+/// A message that could not be turned into a `LogEntry`
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+pub struct ParseError {
+    pub reason: RejectReason,
+    message: String,
+    issues: Vec<ValidationIssue>,
+}
+
+impl ParseError {
+    pub(crate) fn new(reason: RejectReason, message: impl std::fmt::Display) -> Self {
+        Self {
+            reason,
+            message: message.to_string(),
+            issues: Vec::new(),
+        }
+    }
+
+    /// A schema violation made of `issues`
+    fn violation(issues: Vec<ValidationIssue>) -> Self {
+        let messages: Vec<String> = issues
+            .iter()
+            .map(|issue| format!("{} at {}", issue.message, issue.path))
+            .collect();
+        Self {
+            reason: RejectReason::SchemaViolation,
+            message: format!("Validation errors: {}", messages.join(", ")),
+            issues,
+        }
+    }
+
+    /// The failed schema keywords, when the message was rejected by a schema
+    pub fn issues(&self) -> &[ValidationIssue] {
+        &self.issues
+    }
+
+    /// The error without values from the message, for the daemon's own logs: the reason
+    /// and failed keywords, or the sizes of an oversized log
+    pub fn scrubbed(&self) -> String {
+        if self.reason == RejectReason::TooLarge {
+            return self.message.clone();
+        }
+        if self.issues.is_empty() {
+            return self.reason.as_str().to_string();
+        }
+        let issues: Vec<String> = self
+            .issues
+            .iter()
+            .map(|issue| format!("{} at {}", issue.keyword, issue.path))
+            .collect();
+        format!("{} ({})", self.reason.as_str(), issues.join(", "))
+    }
+}
+
+/// One failed schema keyword, in a form clients and dead-letter readers can act on
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValidationIssue {
+    /// JSON pointer to the offending value, e.g. `/level`; for `required`, the missing property
+    pub path: String,
+    /// The keyword that failed, e.g. `enum` or `required`
+    pub keyword: String,
+    /// The keyword's value in the schema
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<Value>,
+    /// The offending value; omitted for objects and arrays, which can be large
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actual: Option<Value>,
+    pub message: String,
+}
+
+impl ValidationIssue {
+    /// Describe `error` from validating against `schema`, whose paths are nested under
+    /// `prefix` (empty, or e.g. `/level` for a field checked on its own)
+    fn from_error(error: &ValidationError, schema: &Value, prefix: &str) -> Self {
+        let mut schema_path = error.schema_path.to_string();
+        // jsonschema reports this keyword at the schema that holds it
+        if matches!(
+            error.kind,
+            ValidationErrorKind::UnevaluatedProperties { .. }
+        ) && !schema_path.ends_with("/unevaluatedProperties")
+        {
+            schema_path.push_str("/unevaluatedProperties");
+        }
+        let keyword = schema_path
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let message = error.to_string();
+
+        if let ValidationErrorKind::Required { property } = &error.kind {
+            let property = property.as_str().unwrap_or_default();
+            return Self {
+                path: format!("{}{}/{}", prefix, error.instance_path, property),
+                keyword,
+                expected: None,
+                actual: None,
+                message,
+            };
+        }
+
+        let actual = match error.instance.as_ref() {
+            Value::Object(_) | Value::Array(_) => None,
+            scalar => Some(scalar.clone()),
+        };
+        Self {
+            path: format!("{}{}", prefix, error.instance_path),
+            expected: schema.pointer(&schema_path).cloned(),
+            keyword,
+            actual,
+            message,
+        }
+    }
+
+    fn missing(field: LogField) -> Self {
+        Self {
+            path: format!("/{}", field.name()),
+            keyword: "required".to_string(),
+            expected: None,
+            actual: None,
+            message: format!("\"{}\" is a required property", field.name()),
+        }
+    }
+}
+
+/// A `LogEntry` field as named in incoming JSON
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LogField {
+    Timestamp,
+    Level,
+    Message,
+    Service,
+    TraceId,
+    Metadata,
+}
+
+impl LogField {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "timestamp" => Some(LogField::Timestamp),
+            "level" => Some(LogField::Level),
+            "message" => Some(LogField::Message),
+            "service" => Some(LogField::Service),
+            "traceId" => Some(LogField::TraceId),
+            "metadata" => Some(LogField::Metadata),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            LogField::Timestamp => "timestamp",
+            LogField::Level => "level",
+            LogField::Message => "message",
+            LogField::Service => "service",
+            LogField::TraceId => "traceId",
+            LogField::Metadata => "metadata",
+        }
+    }
+
+    fn is_string(&self) -> bool {
+        !matches!(self, LogField::Metadata)
+    }
+
+    /// The field's value as JSON, `None` when an optional field is absent
+    fn value(&self, entry: &LogEntry) -> Option<Value> {
+        let text = match self {
+            LogField::Timestamp => Some(entry.timestamp.as_str()).filter(|t| !t.is_empty()),
+            LogField::Level => Some(&*entry.level),
+            LogField::Message => Some(entry.message.as_str()),
+            LogField::Service => entry.service.as_deref(),
+            LogField::TraceId => entry.trace_id.as_deref(),
+            LogField::Metadata => {
+                return entry
+                    .metadata
+                    .as_ref()
+                    .and_then(|m| serde_json::to_value(m).ok())
+            }
+        };
+        text.map(|t| Value::String(t.to_string()))
+    }
+}
+
+/// Top-level keywords a schema may use and still be checked field by field
+const FAST_PATH_KEYWORDS: &[&str] = &[
+    "$schema",
+    "$id",
+    "$comment",
+    "title",
+    "description",
+    "type",
+    "required",
+    "properties",
+    "additionalProperties",
+];
+
+/// Constraints of a user schema that the typed `LogEntry` parse does not already enforce
+#[derive(Default)]
+struct FastPath {
+    /// Fields the typed parse lets be missing that the schema requires; `null` counts as missing
+    required: Vec<LogField>,
+    /// Property subschemas, checked against the parsed field values
+    checks: Vec<FieldCheck>,
+}
+
+struct FieldCheck {
+    field: LogField,
+    /// The property subschema, for reporting what was expected
+    schema: Value,
+    compiled: JSONSchema,
+}
+
+impl FastPath {
+    /// Split `schema` into per-field checks, or `None` when it constrains anything a
+    /// `LogEntry` does not keep (other properties, `$ref`s, combinators, ...)
+    fn analyze(schema: &Value) -> Option<Self> {
+        let schema = schema.as_object()?;
+        if schema
+            .keys()
+            .any(|k| !FAST_PATH_KEYWORDS.contains(&k.as_str()))
+        {
+            return None;
+        }
+        if schema.get("type").is_some_and(|t| t != "object") {
+            return None;
+        }
+        // Unknown properties are dropped by the typed parse, so they must be allowed
+        if schema
+            .get("additionalProperties")
+            .is_some_and(|a| a != &Value::Bool(true))
+        {
+            return None;
+        }
+
+        let mut fast_path = FastPath::default();
+        if let Some(required) = schema.get("required") {
+            for name in required.as_array()? {
+                let field = LogField::from_name(name.as_str()?)?;
+                if field != LogField::Level && field != LogField::Message {
+                    fast_path.required.push(field);
+                }
+            }
+        }
+
+        if let Some(properties) = schema.get("properties") {
+            for (name, subschema) in properties.as_object()? {
+                let field = LogField::from_name(name)?;
+                if contains_ref(subschema) {
+                    return None;
+                }
+                if is_implied(field, subschema) {
+                    continue;
+                }
+                let mut subschema = subschema.clone();
+                // Compiled on its own, a subschema would otherwise fall back to the default draft
+                if let (Some(draft), Value::Object(keywords)) =
+                    (schema.get("$schema"), &mut subschema)
+                {
+                    keywords.insert("$schema".to_string(), draft.clone());
+                }
+                let compiled = schema_formats::compile(&subschema).ok()?;
+                fast_path.checks.push(FieldCheck {
+                    field,
+                    schema: subschema,
+                    compiled,
+                });
+            }
+        }
+
+        Some(fast_path)
+    }
+
+    fn check(&self, entry: &LogEntry) -> Result<(), ParseError> {
+        let mut issues = Vec::new();
+        for field in &self.required {
+            if field.value(entry).is_none() {
+                issues.push(ValidationIssue::missing(*field));
+            }
+        }
+        for check in &self.checks {
+            if let Some(value) = check.field.value(entry) {
+                if let Err(errors) = check.compiled.validate(&value) {
+                    let prefix = format!("/{}", check.field.name());
+                    issues.extend(
+                        errors.map(|e| ValidationIssue::from_error(&e, &check.schema, &prefix)),
+                    );
+                }
+            }
+        }
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(ParseError::violation(issues))
+        }
+    }
+}
+
+/// Whether `subschema` holds for every value the typed parse accepts for `field`
+fn is_implied(field: LogField, subschema: &Value) -> bool {
+    match subschema {
+        Value::Bool(true) => true,
+        Value::Object(keywords) => keywords.iter().all(|(key, value)| match key.as_str() {
+            "title" | "description" | "$comment" => true,
+            "type" => field.is_string() && value == "string",
+            _ => false,
+        }),
+        _ => false,
+    }
+}
+
+/// References cannot be resolved once a property subschema is compiled on its own
+fn contains_ref(schema: &Value) -> bool {
+    match schema {
+        Value::Object(keywords) => keywords.iter().any(|(key, value)| {
+            key.ends_with("$ref")
+                || key == "$dynamicRef"
+                || key == "$recursiveRef"
+                || contains_ref(value)
+        }),
+        Value::Array(items) => items.iter().any(contains_ref),
+        _ => false,
+    }
 }
 
 /// Schema validator for JSON log entries
 pub struct SchemaValidator {
     schema: Arc<JSONSchema>,
-    use_fast_path: bool,
+    /// The schema as written, for reporting what a failed keyword expected
+    document: Arc<Value>,
+    /// Set when messages can be SIMD-parsed straight into `LogEntry`
+    fast_path: Option<FastPath>,
+    /// Registry version stamped on every accepted entry
+    version: Option<String>,
 }
 
 impl SchemaValidator {
@@ -35,20 +476,40 @@ impl SchemaValidator {
         let schema_json: Value =
             serde_json::from_str(&schema_content).with_context(|| "Failed to parse schema JSON")?;
 
-        Self::from_value(schema_json, false)
+        Self::from_value(schema_json)
     }
 
     /// Create a validator from a JSON Schema value
-    pub fn from_value(schema: Value, use_fast_path: bool) -> Result<Self> {
-        let compiled = JSONSchema::compile(&schema)
-            .map_err(|e| anyhow::anyhow!("Failed to compile schema: {}", e))?;
+    ///
+    /// Schemas that only constrain `LogEntry` fields keep the SIMD fast path and validate
+    /// just their extra constraints; anything else is validated as a whole document.
+    pub fn from_value(schema: Value) -> Result<Self> {
+        let compiled = schema_formats::compile(&schema)?;
 
         Ok(Self {
             schema: Arc::new(compiled),
-            use_fast_path,
+            fast_path: FastPath::analyze(&schema),
+            document: Arc::new(schema),
+            version: None,
         })
     }
 
+    /// Tag entries accepted by this schema with a registry version
+    pub fn with_version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// The registry version entries are tagged with, if any
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// Whether messages are SIMD-parsed rather than validated as `serde_json` values
+    pub fn uses_fast_path(&self) -> bool {
+        self.fast_path.is_some()
+    }
+
     /// Create a validator with the default schema
     pub fn default_schema() -> Result<Self> {
         let default_schema = serde_json::json!({
@@ -65,35 +526,300 @@ impl SchemaValidator {
             }
         });
 
-        // Use fast path for default schema since it matches LogEntry struct
-        Self::from_value(default_schema, true)
+        let compiled = schema_formats::compile(&default_schema)?;
+
+        // The default schema matches LogEntry, so the typed parse is all the validation needed
+        Ok(Self {
+            schema: Arc::new(compiled),
+            document: Arc::new(default_schema),
+            fast_path: Some(FastPath::default()),
+            version: None,
+        })
     }
 
     /// Validate a log entry against the schema
     pub fn validate(&self, log: &Value) -> Result<()> {
-        self.schema.validate(log).map_err(|errors| {
-            let error_messages: Vec<String> = errors
-                .map(|e| format!("{} at {}", e, e.instance_path))
-                .collect();
-            anyhow::anyhow!("Validation errors: {}", error_messages.join(", "))
-        })
+        let issues = self.issues(log);
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(ParseError::violation(issues).into())
+        }
+    }
+
+    /// Every keyword of the schema that `log` fails
+    pub fn issues(&self, log: &Value) -> Vec<ValidationIssue> {
+        match self.schema.validate(log) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors
+                .map(|e| ValidationIssue::from_error(&e, &self.document, ""))
+                .collect(),
+        }
     }
 
     /// Parse and validate bytes using SIMD if fast path is enabled
     /// Returns the parsed LogEntry or error
-    pub fn parse_fast(&self, data: &mut [u8]) -> Result<LogEntry> {
-        if self.use_fast_path {
-            // SIMD parsing + validation (type checking)
-            let entry: LogEntry = simd_json::from_slice(data)
-                .map_err(|e| anyhow::anyhow!("SIMD Parse error: {}", e))?;
+    pub fn parse_fast(&self, data: &mut [u8]) -> Result<LogEntry, ParseError> {
+        self.parse_with(data, &mut ParseBuffers::new(data.len()))
+    }
+
+    /// `parse_fast` with scratch space kept by the caller across messages
+    pub fn parse_with(
+        &self,
+        data: &mut [u8],
+        buffers: &mut ParseBuffers,
+    ) -> Result<LogEntry, ParseError> {
+        let mut entry = self.parse_entry(data, buffers)?;
+        entry.schema_version = self.version.clone();
+        Ok(entry)
+    }
+
+    /// Validate an entry already parsed into its wire shape, as the fast path does; only for
+    /// schemas that use it, as the others need the whole document
+    fn check_parsed(&self, mut entry: LogEntry) -> Result<LogEntry, ParseError> {
+        if let Some(fast_path) = &self.fast_path {
+            fast_path.check(&entry)?;
+        }
+        entry.schema_version = self.version.clone();
+        Ok(entry)
+    }
+
+    fn parse_entry(
+        &self,
+        data: &mut [u8],
+        buffers: &mut ParseBuffers,
+    ) -> Result<LogEntry, ParseError> {
+        if let Some(fast_path) = &self.fast_path {
+            // Typed parsing + validation (type checking)
+            let entry = LogEntry::from(parse_wire(data, buffers)?);
+            fast_path.check(&entry)?;
             Ok(entry)
         } else {
             // Slow path: Deserialize to Value -> Validate -> Convert to LogEntry
-            let val: Value = serde_json::from_slice(data)?;
-            self.validate(&val)?;
-            let entry: LogEntry = serde_json::from_value(val)?;
-            Ok(entry)
+            let mut val: Value = serde_json::from_slice(data)
+                .map_err(|e| ParseError::new(RejectReason::MalformedJson, e))?;
+            // Numeric levels and epoch timestamps are validated as strings, the way the fast
+            // path reads them
+            for field in ["level", "timestamp"] {
+                if let Some(value @ Value::Number(_)) = val.get_mut(field) {
+                    *value = Value::String(value.to_string());
+                }
+            }
+            let issues = self.issues(&val);
+            if !issues.is_empty() {
+                return Err(ParseError::violation(issues));
+            }
+            serde_json::from_value::<WireEntry>(val)
+                .map(LogEntry::from)
+                .map_err(|e| ParseError::new(RejectReason::SchemaViolation, e))
+        }
+    }
+}
+
+/// Scratch space for parsing; a connection keeps one so that its messages reuse the
+/// allocations of the ones before
+#[derive(Default)]
+pub struct ParseBuffers {
+    #[cfg(feature = "simd")]
+    simd: simd_json::Buffers,
+}
+
+impl ParseBuffers {
+    /// Buffers sized for messages of about `input_len` bytes
+    #[cfg_attr(not(feature = "simd"), allow(unused_variables))]
+    pub fn new(input_len: usize) -> Self {
+        Self {
+            #[cfg(feature = "simd")]
+            simd: simd_json::Buffers::new(input_len),
+        }
+    }
+}
+
+/// Parse a message straight into its wire shape with SIMD instructions
+#[cfg(feature = "simd")]
+fn parse_wire(data: &mut [u8], buffers: &mut ParseBuffers) -> Result<WireEntry, ParseError> {
+    simd_json::serde::from_slice_with_buffers::<WireEntry>(data, &mut buffers.simd).map_err(|e| {
+        // Field errors come back through serde; everything else is a syntax error
+        let (reason, missing) = match e.error() {
+            simd_json::ErrorType::Serde(message) => {
+                (RejectReason::SchemaViolation, missing_field(message))
+            }
+            simd_json::ErrorType::Unexpected(..) => (RejectReason::SchemaViolation, None),
+            _ => (RejectReason::MalformedJson, None),
+        };
+        let mut error = ParseError::new(reason, format!("SIMD Parse error: {}", e));
+        error.issues.extend(missing.map(ValidationIssue::missing));
+        error
+    })
+}
+
+/// Parse a message straight into its wire shape
+#[cfg(not(feature = "simd"))]
+fn parse_wire(data: &mut [u8], _buffers: &mut ParseBuffers) -> Result<WireEntry, ParseError> {
+    serde_json::from_slice::<WireEntry>(data).map_err(|e| {
+        let reason = match e.classify() {
+            serde_json::error::Category::Data => RejectReason::SchemaViolation,
+            _ => RejectReason::MalformedJson,
+        };
+        let missing = missing_field(&e.to_string());
+        let mut error = ParseError::new(reason, format!("Parse error: {}", e));
+        error.issues.extend(missing.map(ValidationIssue::missing));
+        error
+    })
+}
+
+/// The field a typed parse reported missing; the parse enforces `required` for level and
+/// message itself
+fn missing_field(message: &str) -> Option<LogField> {
+    let rest = message.strip_prefix("missing field `")?;
+    LogField::from_name(&rest[..rest.find('`')?])
+}
+
+/// Just the routing key of a message, read before the message is parsed for real
+#[derive(Deserialize)]
+struct ServiceField<'a> {
+    #[serde(borrow, default)]
+    service: Option<Cow<'a, str>>,
+}
+
+/// Picks the validator for each message: a per-service schema when the message's `service`
+/// has one, the default schema otherwise
+#[derive(Clone)]
+pub struct SchemaRouter {
+    default: Arc<SchemaValidator>,
+    services: HashMap<String, Arc<SchemaValidator>>,
+    /// Whether every schema validates the parsed entry, so one typed parse both routes and
+    /// validates a message
+    all_fast: bool,
+}
+
+impl SchemaRouter {
+    pub fn new(default: SchemaValidator) -> Self {
+        Self::from_parts(Arc::new(default), HashMap::new())
+    }
+
+    fn from_parts(
+        default: Arc<SchemaValidator>,
+        services: HashMap<String, Arc<SchemaValidator>>,
+    ) -> Self {
+        let all_fast = default.uses_fast_path() && services.values().all(|v| v.uses_fast_path());
+        Self {
+            default,
+            services,
+            all_fast,
+        }
+    }
+
+    /// Validate messages whose `service` is `service` against `validator` instead
+    pub fn with_service_schema(
+        mut self,
+        service: impl Into<String>,
+        validator: SchemaValidator,
+    ) -> Self {
+        self.services.insert(service.into(), Arc::new(validator));
+        Self::from_parts(self.default, self.services)
+    }
+
+    /// Replace the schema used for messages without a service-specific one
+    pub fn with_default_schema(self, validator: SchemaValidator) -> Self {
+        Self::from_parts(Arc::new(validator), self.services)
+    }
+
+    /// A router validating every message against the schema of `service`, whatever service
+    /// the message names; for listeners dedicated to one producer
+    pub fn pinned(&self, service: &str) -> Self {
+        let validator = self.services.get(service).unwrap_or(&self.default);
+        Self::from_parts(validator.clone(), HashMap::new())
+    }
+
+    /// The validator used for messages from `service`
+    pub fn validator_for(&self, service: Option<&str>) -> &SchemaValidator {
+        service
+            .and_then(|s| self.services.get(s))
+            .unwrap_or(&self.default)
+    }
+
+    /// Parse and validate a message with the schema its service selects
+    pub fn parse_fast(&self, data: &mut [u8]) -> Result<LogEntry, ParseError> {
+        self.parse_with(data, &mut ParseBuffers::new(data.len()))
+    }
+
+    /// `parse_fast` with scratch space kept by the caller across messages
+    pub fn parse_with(
+        &self,
+        data: &mut [u8],
+        buffers: &mut ParseBuffers,
+    ) -> Result<LogEntry, ParseError> {
+        if self.services.is_empty() {
+            return self.default.parse_with(data, buffers);
         }
+        if self.all_fast {
+            // The typed parse is the same for every schema, so it picks the schema too
+            let entry = LogEntry::from(parse_wire(data, buffers)?);
+            return self
+                .validator_for(entry.service.as_deref())
+                .check_parsed(entry);
+        }
+
+        // simd-json rewrites the buffer in place, so the service is peeked with serde_json
+        // first; messages that cannot be peeked get the default schema's error
+        let validator = match serde_json::from_slice::<ServiceField>(data) {
+            Ok(peeked) => self.validator_for(peeked.service.as_deref()),
+            Err(_) => &self.default,
+        };
+        validator.parse_with(data, buffers)
+    }
+}
+
+impl From<SchemaValidator> for SchemaRouter {
+    fn from(default: SchemaValidator) -> Self {
+        Self::new(default)
+    }
+}
+
+/// The schema router in effect, shared by every connection and replaced as a whole when
+/// schemas change at runtime
+#[derive(Clone)]
+pub struct SharedSchemas {
+    current: Arc<RwLock<Arc<SchemaRouter>>>,
+}
+
+impl SharedSchemas {
+    pub fn new(router: SchemaRouter) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(router))),
+        }
+    }
+
+    /// The router to validate with; connections keep using it until they load again
+    pub fn load(&self) -> Arc<SchemaRouter> {
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Swap in a new router
+    pub fn store(&self, router: SchemaRouter) {
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(router);
+    }
+
+    /// Swap in a router derived from the current one
+    pub fn update(&self, change: impl FnOnce(SchemaRouter) -> SchemaRouter) {
+        let mut current = self.current.write().unwrap_or_else(|e| e.into_inner());
+        *current = Arc::new(change(SchemaRouter::clone(&current)));
+    }
+}
+
+impl From<SchemaRouter> for SharedSchemas {
+    fn from(router: SchemaRouter) -> Self {
+        Self::new(router)
+    }
+}
+
+impl From<SchemaValidator> for SharedSchemas {
+    fn from(validator: SchemaValidator) -> Self {
+        Self::new(validator.into())
     }
 }
 
@@ -114,6 +840,236 @@ mod tests {
         assert_eq!(entry.message, "Fast log");
     }
 
+    #[test]
+    fn test_fast_path_interns_levels_and_services() {
+        let validator = SchemaValidator::default_schema().unwrap();
+        let parse = |message: &str| {
+            let mut data = format!(
+                r#"{{"timestamp":"2026-01-15T19:00:00Z","level":"info","service":"api","message":"{}"}}"#,
+                message
+            )
+            .into_bytes();
+            validator.parse_fast(&mut data).unwrap()
+        };
+
+        let (a, b) = (parse("first"), parse("second"));
+        assert!(Arc::ptr_eq(&a.level, &b.level));
+        assert!(Arc::ptr_eq(
+            a.service.as_ref().unwrap(),
+            b.service.as_ref().unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_numeric_level() {
+        let validator = SchemaValidator::default_schema().unwrap();
+        let mut numeric =
+            br#"{"timestamp":"2026-01-15T19:00:00Z","level":3,"message":"x"}"#.to_vec();
+        assert_eq!(&*validator.parse_fast(&mut numeric).unwrap().level, "3");
+    }
+
+    #[test]
+    fn test_parse_errors_carry_reject_reason() {
+        let validator = SchemaValidator::default_schema().unwrap();
+        let mut missing_level = br#"{"timestamp":"2026-01-15T19:00:00Z","message":"x"}"#.to_vec();
+        let mut truncated = br#"{"timestamp":"2026-01-15T19:00:00Z","#.to_vec();
+
+        let err = validator.parse_fast(&mut missing_level).unwrap_err();
+        assert_eq!(err.reason, RejectReason::SchemaViolation);
+        let err = validator.parse_fast(&mut truncated).unwrap_err();
+        assert_eq!(err.reason, RejectReason::MalformedJson);
+    }
+
+    #[test]
+    fn test_structured_issues_and_newer_drafts() {
+        for draft in [
+            "http://json-schema.org/draft-07/schema#",
+            "https://json-schema.org/draft/2019-09/schema",
+            "https://json-schema.org/draft/2020-12/schema",
+        ] {
+            let schema = |extra: Value| {
+                let mut schema = json!({
+                    "$schema": draft,
+                    "properties": {
+                        "level": { "enum": ["info", "error"] },
+                        "metadata": {
+                            "properties": { "host": { "type": "string" } },
+                            "unevaluatedProperties": false
+                        }
+                    }
+                });
+                schema
+                    .as_object_mut()
+                    .unwrap()
+                    .extend(extra.as_object().unwrap().clone());
+                SchemaValidator::from_value(schema).unwrap()
+            };
+            let fast = schema(json!({}));
+            let slow = schema(json!({ "required": ["host"] }));
+            assert!(fast.uses_fast_path() && !slow.uses_fast_path());
+
+            for validator in [&fast, &slow] {
+                let mut bad = br#"{"timestamp":"t","level":"debug","message":"m","host":"a","metadata":{"tags":[1]}}"#.to_vec();
+                let err = validator.parse_fast(&mut bad).unwrap_err();
+                let level = err
+                    .issues()
+                    .iter()
+                    .find(|issue| issue.path == "/level")
+                    .unwrap();
+                assert_eq!(level.keyword, "enum");
+                assert_eq!(level.expected, Some(json!(["info", "error"])));
+                assert_eq!(level.actual, Some(json!("debug")));
+                // `unevaluatedProperties` only exists from 2019-09 on
+                let unevaluated = err
+                    .issues()
+                    .iter()
+                    .any(|issue| issue.keyword == "unevaluatedProperties");
+                assert_eq!(unevaluated, !draft.contains("draft-07"), "{}", draft);
+            }
+        }
+
+        let err = SchemaValidator::default_schema()
+            .unwrap()
+            .parse_fast(&mut br#"{"timestamp":"t","level":"info"}"#.to_vec())
+            .unwrap_err();
+        assert_eq!(err.issues()[0].path, "/message");
+        assert_eq!(err.issues()[0].keyword, "required");
+    }
+
+    #[test]
+    fn test_custom_schema_keeps_fast_path() {
+        let validator = SchemaValidator::from_value(json!({
+            "type": "object",
+            "required": ["timestamp", "level", "message", "service"],
+            "properties": {
+                "timestamp": { "type": "string" },
+                "level": { "enum": ["debug", "info", "warn", "error"] },
+                "message": { "type": "string", "maxLength": 16 },
+                "service": { "type": "string" }
+            }
+        }))
+        .unwrap();
+        assert!(validator.uses_fast_path());
+
+        let mut ok =
+            br#"{"timestamp":"t","level":"info","message":"hi","service":"api","host":"a"}"#
+                .to_vec();
+        assert_eq!(&*validator.parse_fast(&mut ok).unwrap().level, "info");
+
+        for bad in [
+            br#"{"timestamp":"t","level":"trace","message":"hi","service":"api"}"#.to_vec(),
+            br#"{"timestamp":"t","level":"info","message":"a rather long message","service":"api"}"#.to_vec(),
+            br#"{"timestamp":"t","level":"info","message":"hi"}"#.to_vec(),
+        ] {
+            let err = validator.parse_fast(&mut bad.clone()).unwrap_err();
+            assert_eq!(err.reason, RejectReason::SchemaViolation);
+        }
+
+        // Constraints on properties LogEntry drops need the whole document
+        let validator = SchemaValidator::from_value(json!({
+            "type": "object",
+            "required": ["timestamp", "level", "message", "host"]
+        }))
+        .unwrap();
+        assert!(!validator.uses_fast_path());
+        let mut no_host = br#"{"timestamp":"t","level":"info","message":"hi"}"#.to_vec();
+        assert!(validator.parse_fast(&mut no_host).is_err());
+
+        // Formats are enforced the same way on both paths
+        let format_schema = |extra: Value| {
+            let mut schema = json!({
+                "properties": {
+                    "traceId": { "type": "string", "format": "uuid" },
+                    "service": { "type": "string", "format": "hostname" }
+                }
+            });
+            schema
+                .as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            SchemaValidator::from_value(schema).unwrap()
+        };
+        let fast = format_schema(json!({}));
+        let slow = format_schema(json!({ "required": ["host"] }));
+        assert!(fast.uses_fast_path() && !slow.uses_fast_path());
+        let log = |trace_id: &str| {
+            format!(
+                r#"{{"timestamp":"t","level":"info","message":"m","service":"api.internal","traceId":"{}","host":"a"}}"#,
+                trace_id
+            )
+            .into_bytes()
+        };
+        for validator in [&fast, &slow] {
+            assert!(validator
+                .parse_fast(&mut log("67e55044-10b1-426f-9247-bb680e5fe0c8"))
+                .is_ok());
+            let err = validator.parse_fast(&mut log("not-a-uuid")).unwrap_err();
+            assert_eq!(err.reason, RejectReason::SchemaViolation);
+        }
+    }
+
+    #[test]
+    fn test_router_selects_schema_by_service() {
+        let strict = SchemaValidator::from_value(json!({
+            "type": "object",
+            "required": ["timestamp", "level", "message", "traceId"],
+            "properties": { "level": { "enum": ["info", "error"] } }
+        }))
+        .unwrap();
+        let router = SchemaRouter::new(SchemaValidator::default_schema().unwrap())
+            .with_service_schema("checkout", strict);
+        assert!(router.all_fast);
+
+        let mut other =
+            br#"{"timestamp":"t","level":"debug","message":"m","service":"search"}"#.to_vec();
+        let mut loose =
+            br#"{"timestamp":"t","level":"debug","message":"m","service":"checkout"}"#.to_vec();
+        let mut strict_ok =
+            br#"{"timestamp":"t","level":"info","message":"m","service":"checkout","traceId":"abc"}"#.to_vec();
+
+        assert!(router.parse_fast(&mut other).is_ok());
+        assert_eq!(
+            router.parse_fast(&mut loose).unwrap_err().reason,
+            RejectReason::SchemaViolation
+        );
+        assert_eq!(
+            router
+                .parse_fast(&mut strict_ok)
+                .unwrap()
+                .trace_id
+                .as_deref(),
+            Some("abc")
+        );
+
+        // A listener pinned to a schema ignores the service the message names
+        let mut other =
+            br#"{"timestamp":"t","level":"debug","message":"m","service":"search"}"#.to_vec();
+        assert_eq!(
+            router
+                .pinned("checkout")
+                .parse_fast(&mut other)
+                .unwrap_err()
+                .reason,
+            RejectReason::SchemaViolation
+        );
+
+        // Schemas that need the whole document are picked by peeking at the service first
+        let whole_document = SchemaValidator::from_value(json!({
+            "type": "object",
+            "required": ["timestamp", "level", "message", "orderId"]
+        }))
+        .unwrap();
+        let router = router.with_service_schema("orders", whole_document);
+        assert!(!router.all_fast);
+        let mut order =
+            br#"{"timestamp":"t","level":"info","message":"m","service":"orders","orderId":1}"#
+                .to_vec();
+        let mut missing =
+            br#"{"timestamp":"t","level":"info","message":"m","service":"orders"}"#.to_vec();
+        assert!(router.parse_fast(&mut order).is_ok());
+        assert!(router.parse_fast(&mut missing).is_err());
+    }
+
     #[test]
     fn test_default_schema_valid() {
         let validator = SchemaValidator::default_schema().unwrap();
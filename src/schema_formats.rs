@@ -0,0 +1,169 @@
+use anyhow::Result;
+use jsonschema::{Draft, JSONSchema};
+use serde_json::Value;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+/// Compile `schema` with `format` enforced and the daemon's validators for formats the
+/// `jsonschema` crate skips or only loosely checks
+pub fn compile(schema: &Value) -> Result<JSONSchema> {
+    let mut options = JSONSchema::options();
+    if let Some(draft) = schema
+        .get("$schema")
+        .and_then(Value::as_str)
+        .and_then(draft)
+    {
+        options.with_draft(draft);
+    }
+    options
+        .should_validate_formats(true)
+        .with_format("uuid", is_uuid)
+        .with_format("ipv4", is_ipv4)
+        .with_format("ipv6", is_ipv6)
+        .with_format("hostname", is_hostname)
+        .with_format("duration", is_duration)
+        .compile(schema)
+        .map_err(|e| anyhow::anyhow!("Failed to compile schema: {}", e))
+}
+
+/// The draft a `$schema` URL names; `jsonschema` only recognizes the URLs with a trailing `#`,
+/// which the 2019-09 and 2020-12 meta-schemas do not use themselves
+fn draft(url: &str) -> Option<Draft> {
+    let url = url.trim_end_matches('#');
+    let url = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    match url {
+        "json-schema.org/draft/2020-12/schema" => Some(Draft::Draft202012),
+        "json-schema.org/draft/2019-09/schema" => Some(Draft::Draft201909),
+        "json-schema.org/draft-07/schema" => Some(Draft::Draft7),
+        "json-schema.org/draft-06/schema" => Some(Draft::Draft6),
+        "json-schema.org/draft-04/schema" => Some(Draft::Draft4),
+        _ => None,
+    }
+}
+
+/// Hyphenated RFC 4122 form, e.g. `67e55044-10b1-426f-9247-bb680e5fe0c8`
+pub fn is_uuid(value: &str) -> bool {
+    value.len() == 36
+        && value.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+/// Dotted quad without leading zeros
+pub fn is_ipv4(value: &str) -> bool {
+    Ipv4Addr::from_str(value).is_ok()
+}
+
+/// RFC 4291 text form, without a zone index
+pub fn is_ipv6(value: &str) -> bool {
+    Ipv6Addr::from_str(value).is_ok()
+}
+
+/// RFC 1123 host name: dot-separated labels of 1-63 letters, digits and inner hyphens, 253
+/// characters at most, with an optional trailing dot
+pub fn is_hostname(value: &str) -> bool {
+    let name = value.strip_suffix('.').unwrap_or(value);
+    !name.is_empty()
+        && name.len() <= 253
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
+}
+
+/// ISO 8601 duration as profiled by RFC 3339 appendix A, e.g. `P1DT12H`, `PT0.5S` or `P2W`;
+/// only seconds may have a fraction
+pub fn is_duration(value: &str) -> bool {
+    let Some(rest) = value.strip_prefix('P') else {
+        return false;
+    };
+    if let Some(weeks) = rest.strip_suffix('W') {
+        return !weeks.is_empty() && weeks.bytes().all(|b| b.is_ascii_digit());
+    }
+
+    let (date, time) = match rest.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (rest, None),
+    };
+    let Some(date_parts) = components(date, "YMD", false) else {
+        return false;
+    };
+    let time_parts = match time {
+        Some(time) => match components(time, "HMS", true) {
+            // A `T` must be followed by at least one component
+            Some(0) | None => return false,
+            Some(parts) => parts,
+        },
+        None => 0,
+    };
+    date_parts + time_parts > 0
+}
+
+/// Count the `<number><designator>` components of `part`, which must use `designators` in
+/// order and at most once each; `None` when it is malformed
+fn components(part: &str, designators: &str, fractional_seconds: bool) -> Option<usize> {
+    let mut allowed = designators.chars();
+    let mut count = 0;
+    let mut number = String::new();
+    for c in part.chars() {
+        if c.is_ascii_digit() || (c == '.' && fractional_seconds) {
+            number.push(c);
+            continue;
+        }
+        // Designators must appear in order; skipping ahead consumes the ones passed over
+        allowed.by_ref().find(|&d| d == c)?;
+        let valid_number = match number.split_once('.') {
+            None => !number.is_empty(),
+            Some((whole, fraction)) => {
+                c == 'S' && !whole.is_empty() && !fraction.is_empty() && !fraction.contains('.')
+            }
+        };
+        if !valid_number {
+            return None;
+        }
+        number.clear();
+        count += 1;
+    }
+    // Trailing digits without a designator
+    number.is_empty().then_some(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_format_validators() {
+        assert!(is_uuid("67e55044-10b1-426f-9247-bb680e5fe0c8"));
+        assert!(!is_uuid("67e5504410b1426f9247bb680e5fe0c8"));
+        assert!(is_ipv4("192.168.0.1"));
+        assert!(!is_ipv4("192.168.00.1"));
+        assert!(is_ipv6("2001:db8::1"));
+        assert!(!is_ipv6("2001:db8::1%eth0"));
+        assert!(is_hostname("api-1.example.com."));
+        assert!(!is_hostname("api..example.com"));
+        assert!(!is_hostname("-api.example.com"));
+        for valid in ["P1Y2M3DT4H5M6S", "PT0.5S", "P2W", "P1D", "PT36H"] {
+            assert!(is_duration(valid), "{}", valid);
+        }
+        for invalid in [
+            "P", "PT", "P1DT", "1D", "P1H", "PT1D", "P1M1Y", "P1.5D", "P1W2D",
+        ] {
+            assert!(!is_duration(invalid), "{}", invalid);
+        }
+
+        let schema = json!({ "properties": { "request": { "format": "uuid" } } });
+        let compiled = compile(&schema).unwrap();
+        assert!(compiled.is_valid(&json!({ "request": "67e55044-10b1-426f-9247-bb680e5fe0c8" })));
+        assert!(!compiled.is_valid(&json!({ "request": "not-a-uuid" })));
+    }
+}
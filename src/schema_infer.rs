@@ -0,0 +1,283 @@
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::BufRead;
+
+/// Fields whose observed values are offered as an `enum`
+const ENUM_FIELDS: &[&str] = &["level", "service"];
+
+/// Default for the most distinct values an `enum` candidate may have
+pub const DEFAULT_MAX_ENUM_VALUES: usize = 20;
+
+/// Observations about one field across the samples
+#[derive(Debug, Default)]
+struct FieldStats {
+    /// Samples in which the field was present and not null
+    present: usize,
+    types: BTreeSet<&'static str>,
+    /// Distinct string values, until there are too many to be an enum
+    values: Option<BTreeSet<String>>,
+    /// Whether every string value was an RFC 3339 timestamp
+    all_date_time: bool,
+    /// Object values seen, and stats for their keys
+    objects: usize,
+    properties: BTreeMap<String, FieldStats>,
+    /// Stats for the elements of array values
+    items: Option<Box<FieldStats>>,
+}
+
+impl FieldStats {
+    fn new(track_values: bool) -> Self {
+        Self {
+            values: track_values.then(BTreeSet::new),
+            all_date_time: true,
+            ..Self::default()
+        }
+    }
+
+    fn observe(&mut self, value: &Value, max_enum: usize) {
+        if value.is_null() {
+            self.types.insert("null");
+            return;
+        }
+        self.present += 1;
+        match value {
+            Value::Bool(_) => {
+                self.types.insert("boolean");
+            }
+            Value::Number(n) => {
+                self.types
+                    .insert(if n.is_f64() { "number" } else { "integer" });
+            }
+            Value::String(s) => {
+                self.types.insert("string");
+                if self.all_date_time && chrono::DateTime::parse_from_rfc3339(s).is_err() {
+                    self.all_date_time = false;
+                }
+                if let Some(values) = &mut self.values {
+                    values.insert(s.clone());
+                    if values.len() > max_enum {
+                        self.values = None;
+                    }
+                }
+            }
+            Value::Array(items) => {
+                self.types.insert("array");
+                let stats = self
+                    .items
+                    .get_or_insert_with(|| Box::new(FieldStats::new(false)));
+                for item in items {
+                    stats.observe(item, max_enum);
+                }
+            }
+            Value::Object(fields) => {
+                self.types.insert("object");
+                self.objects += 1;
+                observe_fields(&mut self.properties, fields, false, max_enum);
+            }
+            Value::Null => unreachable!("handled above"),
+        }
+    }
+
+    fn schema(&self) -> Value {
+        let mut schema = Map::new();
+
+        // Integers are numbers too, so mixed samples widen to `number`
+        let mut types = self.types.clone();
+        if types.contains("number") {
+            types.remove("integer");
+        }
+        match types.len() {
+            0 => {}
+            1 => {
+                schema.insert("type".into(), json!(types.first()));
+            }
+            _ => {
+                schema.insert("type".into(), json!(types));
+            }
+        }
+
+        if types.iter().eq(["string"].iter()) {
+            if let Some(values) = self.values.as_ref().filter(|v| !v.is_empty()) {
+                schema.insert("enum".into(), json!(values));
+            } else if self.all_date_time && self.present > 0 {
+                schema.insert("format".into(), json!("date-time"));
+            }
+        }
+        if self.objects > 0 {
+            schema.insert("properties".into(), properties_schema(&self.properties));
+            let required = required(&self.properties, self.objects);
+            if !required.is_empty() {
+                schema.insert("required".into(), json!(required));
+            }
+        }
+        if let Some(items) = &self.items {
+            if !items.types.is_empty() {
+                schema.insert("items".into(), items.schema());
+            }
+        }
+        Value::Object(schema)
+    }
+}
+
+fn observe_fields(
+    properties: &mut BTreeMap<String, FieldStats>,
+    fields: &Map<String, Value>,
+    top_level: bool,
+    max_enum: usize,
+) {
+    for (key, value) in fields {
+        properties
+            .entry(key.clone())
+            .or_insert_with(|| FieldStats::new(top_level && ENUM_FIELDS.contains(&key.as_str())))
+            .observe(value, max_enum);
+    }
+}
+
+fn properties_schema(properties: &BTreeMap<String, FieldStats>) -> Value {
+    Value::Object(
+        properties
+            .iter()
+            .map(|(key, stats)| (key.clone(), stats.schema()))
+            .collect(),
+    )
+}
+
+/// Keys present and non-null in all `objects` samples
+fn required(properties: &BTreeMap<String, FieldStats>, objects: usize) -> Vec<&str> {
+    properties
+        .iter()
+        .filter(|(_, stats)| stats.present == objects)
+        .map(|(key, _)| key.as_str())
+        .collect()
+}
+
+/// Derives a JSON Schema from sample logs
+#[derive(Debug)]
+pub struct SchemaInference {
+    samples: usize,
+    skipped: usize,
+    properties: BTreeMap<String, FieldStats>,
+    max_enum: usize,
+}
+
+impl Default for SchemaInference {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ENUM_VALUES)
+    }
+}
+
+impl SchemaInference {
+    /// `max_enum` caps how many distinct `level`/`service` values still produce an `enum`
+    pub fn new(max_enum: usize) -> Self {
+        Self {
+            samples: 0,
+            skipped: 0,
+            properties: BTreeMap::new(),
+            max_enum,
+        }
+    }
+
+    /// Samples that contributed to the schema
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    /// Lines that were not JSON objects
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Record one sample log
+    pub fn observe(&mut self, sample: &Value) {
+        match sample {
+            Value::Object(fields) => {
+                self.samples += 1;
+                observe_fields(&mut self.properties, fields, true, self.max_enum);
+            }
+            _ => self.skipped += 1,
+        }
+    }
+
+    /// Record every line of newline-delimited JSON; blank lines are ignored and lines that
+    /// are not JSON objects are counted as skipped
+    pub fn observe_ndjson(&mut self, reader: impl BufRead) -> Result<()> {
+        for (number, line) in reader.lines().enumerate() {
+            let line = line.with_context(|| format!("Failed to read line {}", number + 1))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<Value>(&line) {
+                Ok(sample) => self.observe(&sample),
+                Err(_) => self.skipped += 1,
+            }
+        }
+        Ok(())
+    }
+
+    /// The inferred schema: observed types, fields present in every sample as `required`,
+    /// `enum` candidates for `level` and `service`, and `date-time` for RFC 3339 strings
+    pub fn schema(&self) -> Result<Value> {
+        if self.samples == 0 {
+            bail!("No JSON object samples to infer a schema from");
+        }
+        Ok(json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "title": "Inferred log schema",
+            "type": "object",
+            "required": required(&self.properties, self.samples),
+            "properties": properties_schema(&self.properties),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer_schema_from_samples() {
+        let samples = r#"
+{"timestamp":"2026-01-15T19:00:00Z","level":"info","message":"started","service":"api","metadata":{"port":8080}}
+{"timestamp":"2026-01-15T19:00:01Z","level":"error","message":"failed","service":"api","metadata":{"port":8080,"retry":1.5},"traceId":null}
+not json
+{"timestamp":"2026-01-15T19:00:02Z","level":"info","message":"done","service":"worker","traceId":"abc"}
+"#;
+        let mut inference = SchemaInference::default();
+        inference.observe_ndjson(samples.as_bytes()).unwrap();
+        assert_eq!((inference.samples(), inference.skipped()), (3, 1));
+
+        let schema = inference.schema().unwrap();
+        assert_eq!(
+            schema["required"],
+            json!(["level", "message", "service", "timestamp"])
+        );
+        let properties = &schema["properties"];
+        assert_eq!(
+            properties["timestamp"],
+            json!({ "type": "string", "format": "date-time" })
+        );
+        assert_eq!(
+            properties["level"],
+            json!({ "type": "string", "enum": ["error", "info"] })
+        );
+        assert_eq!(properties["message"], json!({ "type": "string" }));
+        assert_eq!(properties["traceId"], json!({ "type": ["null", "string"] }));
+        assert_eq!(
+            properties["metadata"],
+            json!({
+                "type": "object",
+                "properties": { "port": { "type": "integer" }, "retry": { "type": "number" } },
+                "required": ["port"]
+            })
+        );
+        crate::schema::SchemaValidator::from_value(schema).unwrap();
+
+        let mut many = SchemaInference::new(1);
+        many.observe_ndjson(samples.as_bytes()).unwrap();
+        assert_eq!(
+            many.schema().unwrap()["properties"]["service"],
+            json!({ "type": "string" })
+        );
+    }
+}
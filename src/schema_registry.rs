@@ -0,0 +1,318 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::info;
+
+use crate::schema::{SchemaRouter, SchemaValidator, SharedSchemas};
+
+/// Registry name of the schema used for messages without a service-specific one
+pub const DEFAULT_SCHEMA_NAME: &str = "default";
+
+/// File in a schema's directory holding its active version number
+const ACTIVE_FILE: &str = "active";
+
+/// Longest accepted schema name; names are directory names under the registry
+const MAX_NAME_LEN: usize = 128;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RegistryError {
+    #[error("{0}")]
+    Invalid(String),
+    #[error("Schema {name} version {version} not found")]
+    NotFound { name: String, version: u32 },
+    #[error("Schema registry storage error: {0}")]
+    Storage(#[from] std::io::Error),
+}
+
+/// Registered versions of one schema
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema))]
+pub struct SchemaVersions {
+    /// `default` or the service the schema applies to
+    pub name: String,
+    /// Registered versions, oldest first
+    pub versions: Vec<u32>,
+    /// Version validating incoming logs, if one was activated
+    pub active: Option<u32>,
+}
+
+/// A stored schema version
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema))]
+pub struct RegisteredSchema {
+    pub name: String,
+    pub version: u32,
+}
+
+/// Versioned schemas stored as `<dir>/<name>/<version>.json`, activated into the live router
+/// without restarting ingestion
+pub struct SchemaRegistry {
+    dir: PathBuf,
+    schemas: SharedSchemas,
+    /// Serializes registrations and activations so version numbers are not handed out twice
+    writes: Mutex<()>,
+}
+
+impl SchemaRegistry {
+    pub fn new(dir: PathBuf, schemas: SharedSchemas) -> Self {
+        Self {
+            dir,
+            schemas,
+            writes: Mutex::new(()),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Install the active version of every stored schema; returns how many were installed
+    pub fn load_active(&self) -> Result<usize> {
+        self.rebuild(SchemaRouter::clone(&self.schemas.load()))
+    }
+
+    /// Serve `base` with the active registry versions installed over it, e.g. after the
+    /// schema files it was built from were reloaded; returns how many were installed
+    pub fn rebuild(&self, mut base: SchemaRouter) -> Result<usize> {
+        // Held so an activation cannot slip in between reading the active versions and the swap
+        let _guard = self.writes.lock().unwrap_or_else(|e| e.into_inner());
+        let mut installed = 0;
+        for name in self.names()? {
+            if let Some(version) = read_active(&self.dir.join(&name))? {
+                let validator = self.compile(&name, version).with_context(|| {
+                    format!("Failed to load active schema {}@{}", name, version)
+                })?;
+                base = install(base, &name, validator);
+                installed += 1;
+            }
+        }
+        self.schemas.store(base);
+        Ok(installed)
+    }
+
+    /// Every stored schema with its versions
+    pub fn list(&self) -> Result<Vec<SchemaVersions>, RegistryError> {
+        self.names()?
+            .into_iter()
+            .map(|name| {
+                let dir = self.dir.join(&name);
+                Ok(SchemaVersions {
+                    versions: versions(&dir)?,
+                    active: read_active(&dir)?,
+                    name,
+                })
+            })
+            .collect()
+    }
+
+    /// The JSON Schema stored as `name` `version`
+    pub fn get(&self, name: &str, version: u32) -> Result<Value, RegistryError> {
+        validate_name(name)?;
+        let path = self.version_path(name, version);
+        let content = match std::fs::read(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(RegistryError::NotFound {
+                    name: name.to_string(),
+                    version,
+                })
+            }
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_slice(&content).map_err(|e| {
+            RegistryError::Invalid(format!("Stored schema {:?} is not JSON: {}", path, e))
+        })
+    }
+
+    /// Store `schema` as the next version of `name`; it takes effect once activated
+    pub fn register(&self, name: &str, schema: Value) -> Result<RegisteredSchema, RegistryError> {
+        validate_name(name)?;
+        SchemaValidator::from_value(schema.clone())
+            .map_err(|e| RegistryError::Invalid(e.to_string()))?;
+
+        let _guard = self.writes.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = self.dir.join(name);
+        std::fs::create_dir_all(&dir)?;
+        let version = versions(&dir)?.last().map_or(1, |latest| latest + 1);
+
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(self.version_path(name, version))?;
+        file.write_all(&serde_json::to_vec_pretty(&schema).unwrap_or_default())?;
+        file.sync_all()?;
+
+        info!("Registered schema {}@{}", name, version);
+        Ok(RegisteredSchema {
+            name: name.to_string(),
+            version,
+        })
+    }
+
+    /// Validate new logs for `name` with `version`, and keep it active across restarts
+    pub fn activate(&self, name: &str, version: u32) -> Result<RegisteredSchema, RegistryError> {
+        validate_name(name)?;
+        let _guard = self.writes.lock().unwrap_or_else(|e| e.into_inner());
+        let validator = self.compile(name, version)?;
+
+        // Persist first so a restart never reverts to an older version than was served
+        let dir = self.dir.join(name);
+        let staged = dir.join(format!("{}.tmp", ACTIVE_FILE));
+        std::fs::write(&staged, version.to_string())?;
+        std::fs::rename(&staged, dir.join(ACTIVE_FILE))?;
+
+        self.schemas
+            .update(|router| install(router, name, validator));
+        info!("Activated schema {}@{}", name, version);
+        Ok(RegisteredSchema {
+            name: name.to_string(),
+            version,
+        })
+    }
+
+    fn compile(&self, name: &str, version: u32) -> Result<SchemaValidator, RegistryError> {
+        let schema = self.get(name, version)?;
+        SchemaValidator::from_value(schema)
+            .map(|validator| validator.with_version(format!("{}@{}", name, version)))
+            .map_err(|e| RegistryError::Invalid(e.to_string()))
+    }
+
+    fn version_path(&self, name: &str, version: u32) -> PathBuf {
+        self.dir.join(name).join(format!("{}.json", version))
+    }
+
+    /// Names of the stored schemas, sorted
+    fn names(&self) -> std::io::Result<Vec<String>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut names = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                if let Some(name) = entry.file_name().to_str() {
+                    if validate_name(name).is_ok() {
+                        names.push(name.to_string());
+                    }
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+}
+
+fn install(router: SchemaRouter, name: &str, validator: SchemaValidator) -> SchemaRouter {
+    if name == DEFAULT_SCHEMA_NAME {
+        router.with_default_schema(validator)
+    } else {
+        router.with_service_schema(name, validator)
+    }
+}
+
+/// Schema names become directory names, so only plain identifiers are accepted
+fn validate_name(name: &str) -> Result<(), RegistryError> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(RegistryError::Invalid(format!(
+            "Invalid schema name {:?}: use letters, digits, '_', '-' and '.'",
+            name
+        )))
+    }
+}
+
+/// Version numbers stored in a schema directory, ascending
+fn versions(dir: &Path) -> std::io::Result<Vec<u32>> {
+    let mut versions = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let file_name = entry?.file_name();
+        if let Some(version) = file_name
+            .to_str()
+            .and_then(|n| n.strip_suffix(".json"))
+            .and_then(|n| n.parse().ok())
+        {
+            versions.push(version);
+        }
+    }
+    versions.sort_unstable();
+    Ok(versions)
+}
+
+fn read_active(dir: &Path) -> std::io::Result<Option<u32>> {
+    match std::fs::read_to_string(dir.join(ACTIVE_FILE)) {
+        Ok(content) => Ok(content.trim().parse().ok()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_register_and_activate_versions() {
+        let temp_dir = TempDir::new().unwrap();
+        let schemas = SharedSchemas::new(SchemaValidator::default_schema().unwrap().into());
+        let registry = SchemaRegistry::new(temp_dir.path().to_path_buf(), schemas.clone());
+
+        let v1 = registry
+            .register(
+                "checkout",
+                json!({ "required": ["timestamp", "level", "message"] }),
+            )
+            .unwrap();
+        let v2 = registry
+            .register(
+                "checkout",
+                json!({ "properties": { "level": { "enum": ["info", "error"] } } }),
+            )
+            .unwrap();
+        assert_eq!((v1.version, v2.version), (1, 2));
+        assert!(registry.register("../etc", json!({})).is_err());
+        assert!(registry.activate("checkout", 3).is_err());
+
+        let mut debug =
+            br#"{"timestamp":"t","level":"debug","message":"m","service":"checkout"}"#.to_vec();
+        assert!(schemas.load().parse_fast(&mut debug.clone()).is_ok());
+
+        registry.activate("checkout", 2).unwrap();
+        assert!(schemas.load().parse_fast(&mut debug).is_err());
+        // simd-json parses in place, so every parse gets a fresh buffer
+        let info =
+            || br#"{"timestamp":"t","level":"info","message":"m","service":"checkout"}"#.to_vec();
+        let entry = schemas.load().parse_fast(&mut info()).unwrap();
+        assert_eq!(entry.schema_version.as_deref(), Some("checkout@2"));
+
+        assert_eq!(
+            registry.list().unwrap(),
+            vec![SchemaVersions {
+                name: "checkout".to_string(),
+                versions: vec![1, 2],
+                active: Some(2),
+            }]
+        );
+
+        // A restarted daemon picks the active version back up
+        let restarted = SharedSchemas::new(SchemaValidator::default_schema().unwrap().into());
+        let registry = SchemaRegistry::new(temp_dir.path().to_path_buf(), restarted.clone());
+        assert_eq!(registry.load_active().unwrap(), 1);
+        let entry = restarted.load().parse_fast(&mut info()).unwrap();
+        assert_eq!(entry.schema_version.as_deref(), Some("checkout@2"));
+    }
+}
@@ -0,0 +1,253 @@
+use anyhow::Result;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::audit::{self, Outcome};
+use crate::schema::{SchemaRouter, SchemaValidator};
+use crate::schema_registry::SchemaRegistry;
+
+/// Quiet period after a file event before reloading, so multi-step saves are read once
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Reload reason, and audit actor, of an edit to a schema file
+const FILE_CHANGE: &str = "schema file change";
+
+/// The schema files the router is built from: `--schema` and the `[schemas]` section
+#[derive(Debug, Clone, Default)]
+pub struct SchemaSources {
+    /// Schema for messages without a service-specific one; the built-in schema when unset
+    pub default: Option<PathBuf>,
+    /// Per-service schema files
+    pub services: BTreeMap<String, PathBuf>,
+}
+
+impl SchemaSources {
+    /// Compile every schema file into a router
+    pub fn build(&self) -> Result<SchemaRouter> {
+        let default = match &self.default {
+            Some(path) => {
+                info!("Loading schema from {:?}", path);
+                let validator = SchemaValidator::from_file(path)?;
+                if !validator.uses_fast_path() {
+                    info!("Schema constrains more than the log entry fields; using the slower full-document validation");
+                }
+                validator
+            }
+            None => {
+                info!("Using default schema");
+                SchemaValidator::default_schema()?
+            }
+        };
+
+        let mut router = SchemaRouter::new(default);
+        for (service, path) in &self.services {
+            info!("Loading schema for service {} from {:?}", service, path);
+            router = router.with_service_schema(service.clone(), SchemaValidator::from_file(path)?);
+        }
+        Ok(router)
+    }
+
+    fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.default
+            .iter()
+            .chain(self.services.values())
+            .map(PathBuf::as_path)
+    }
+}
+
+/// Schema sources shared with the reloader, so a config reload can point it at other files
+pub type SharedSources = Arc<RwLock<SchemaSources>>;
+
+/// Asks the task started by `spawn_schema_reloader` to reload the schemas
+#[derive(Debug, Clone)]
+pub struct SchemaReloadTrigger(mpsc::Sender<&'static str>);
+
+impl SchemaReloadTrigger {
+    /// Reload soon, reporting `reason` in the log
+    pub fn reload(&self, reason: &'static str) {
+        // A full channel already has a reload pending, which reads the latest sources
+        let _ = self.0.try_send(reason);
+    }
+}
+
+/// Recompile the schema files and swap them in, keeping the active registry versions on top;
+/// on error the schemas in use are left untouched
+pub fn reload(sources: &SchemaSources, registry: &SchemaRegistry) -> Result<()> {
+    let router = sources.build()?;
+    registry.rebuild(router)?;
+    Ok(())
+}
+
+/// Schema files being watched, and the directories watched for them
+#[derive(Debug, Default)]
+struct WatchedFiles {
+    files: HashSet<PathBuf>,
+    dirs: HashSet<PathBuf>,
+}
+
+impl WatchedFiles {
+    /// Whether an event on `path` may have changed a schema file: the file itself, or in its
+    /// directory a `..`-prefixed entry, which a Kubernetes ConfigMap volume swaps to update
+    /// every file at once through symlinks
+    fn affects(&self, path: &Path) -> bool {
+        self.files.contains(path)
+            || path.parent().is_some_and(|dir| self.dirs.contains(dir))
+                && path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with(".."))
+    }
+}
+
+/// Watch the files in `sources` in place of those watched so far, adding the directories not
+/// watched yet. Paths are kept as
+/// configured rather than resolved, so a symlink pointed elsewhere still counts as their
+/// change. Failures are logged: the files can still be reloaded on SIGHUP.
+fn watch_sources(
+    watcher: &mut impl Watcher,
+    watched: &Mutex<WatchedFiles>,
+    sources: &SchemaSources,
+) {
+    let mut watched = watched.lock().unwrap_or_else(|e| e.into_inner());
+    watched.files.clear();
+    for path in sources.paths() {
+        let path = match std::path::absolute(path) {
+            Ok(path) => path,
+            Err(e) => {
+                warn!("Not watching schema file {:?}: {}", path, e);
+                continue;
+            }
+        };
+        if let Some(dir) = path.parent() {
+            if !watched.dirs.contains(dir) {
+                match watcher.watch(dir, RecursiveMode::NonRecursive) {
+                    Ok(()) => {
+                        watched.dirs.insert(dir.to_path_buf());
+                    }
+                    Err(e) => warn!("Not watching schema directory {:?}: {}", dir, e),
+                }
+            }
+        }
+        watched.files.insert(path);
+    }
+}
+
+/// Reload the schemas whenever one of the schema files changes or the returned trigger fires;
+/// files are watched from the sources at startup and from the current ones after each reload,
+/// so files a config reload points at are watched too
+pub fn spawn_schema_reloader(
+    sources: SharedSources,
+    registry: Arc<SchemaRegistry>,
+) -> SchemaReloadTrigger {
+    let (tx, mut requests) = mpsc::channel::<&'static str>(1);
+    let trigger = SchemaReloadTrigger(tx.clone());
+
+    // Editors often replace files by renaming over them, so the directories are watched
+    let watched = Arc::new(Mutex::new(WatchedFiles::default()));
+    let events = watched.clone();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else { return };
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        let watched = events.lock().unwrap_or_else(|e| e.into_inner());
+        if event.paths.iter().any(|path| watched.affects(path)) {
+            // A full channel already has a reload pending
+            let _ = tx.try_send(FILE_CHANGE);
+        }
+    });
+    let mut watcher = match watcher {
+        Ok(mut watcher) => {
+            let initial = sources.read().unwrap_or_else(|e| e.into_inner()).clone();
+            watch_sources(&mut watcher, &watched, &initial);
+            Some(watcher)
+        }
+        Err(e) => {
+            warn!("Not watching schema files, reload them with SIGHUP: {}", e);
+            None
+        }
+    };
+
+    tokio::spawn(async move {
+        // The watcher is kept here, as dropping it would stop the events
+        while let Some(trigger) = requests.recv().await {
+            tokio::time::sleep(RELOAD_DEBOUNCE).await;
+            while requests.try_recv().is_ok() {}
+
+            let sources = sources.read().unwrap_or_else(|e| e.into_inner()).clone();
+            if let Some(watcher) = &mut watcher {
+                watch_sources(watcher, &watched, &sources);
+            }
+            let result = reload(&sources, &registry);
+            match &result {
+                Ok(()) => info!("Reloaded schemas after {}", trigger),
+                Err(e) => warn!(
+                    "Schema reload after {} failed, keeping the previous schemas: {:#}",
+                    trigger, e
+                ),
+            }
+            // Reloads for a config reload or admin request are audited there
+            if trigger == FILE_CHANGE {
+                audit::record(
+                    FILE_CHANGE,
+                    "schema.reload",
+                    Outcome::of(&result),
+                    serde_json::Value::Null,
+                );
+            }
+        }
+    });
+
+    trigger
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::SharedSchemas;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_reload_swaps_schema_and_keeps_failures_out() {
+        let temp_dir = TempDir::new().unwrap();
+        let schema_path = temp_dir.path().join("schema.json");
+        let levels =
+            |levels: &[&str]| json!({ "properties": { "level": { "enum": levels } } }).to_string();
+        std::fs::write(&schema_path, levels(&["info", "debug"])).unwrap();
+
+        let sources = SchemaSources {
+            default: Some(schema_path.clone()),
+            services: BTreeMap::new(),
+        };
+        let schemas = SharedSchemas::new(sources.build().unwrap());
+        let registry = SchemaRegistry::new(temp_dir.path().join("registry"), schemas.clone());
+        let debug = || br#"{"timestamp":"t","level":"debug","message":"m"}"#.to_vec();
+        assert!(schemas.load().parse_fast(&mut debug()).is_ok());
+
+        std::fs::write(&schema_path, levels(&["info"])).unwrap();
+        reload(&sources, &registry).unwrap();
+        assert!(schemas.load().parse_fast(&mut debug()).is_err());
+
+        // A broken edit is rejected and the last good schema stays in place
+        std::fs::write(&schema_path, "{ not json").unwrap();
+        assert!(reload(&sources, &registry).is_err());
+        assert!(schemas.load().parse_fast(&mut debug()).is_err());
+        let mut info = br#"{"timestamp":"t","level":"info","message":"m"}"#.to_vec();
+        assert!(schemas.load().parse_fast(&mut info).is_ok());
+
+        // A ConfigMap update renames `..data` next to the file, which is a symlink through it
+        let watched = WatchedFiles {
+            files: HashSet::from([PathBuf::from("/etc/schemas/schema.json")]),
+            dirs: HashSet::from([PathBuf::from("/etc/schemas")]),
+        };
+        assert!(watched.affects(Path::new("/etc/schemas/schema.json")));
+        assert!(watched.affects(Path::new("/etc/schemas/..data")));
+        assert!(!watched.affects(Path::new("/etc/schemas/other.json")));
+        assert!(!watched.affects(Path::new("/etc/..data")));
+    }
+}
@@ -1,35 +1,441 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use bytes::{Buf, BytesMut};
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::{mpsc, Semaphore};
+use std::task::Poll;
+use tokio::sync::{mpsc, oneshot, Notify, OwnedSemaphorePermit, Semaphore, TryAcquireError};
 use tokio::time::Duration;
-use tokio_uring::net::{UnixListener, UnixStream};
-use tracing::{debug, error, info, warn};
+use tokio_uring::net::UnixStream;
+use tracing::{debug, error, info, warn, Span};
 
-use crate::schema::{LogEntry, SchemaValidator};
+use crate::dead_letter::{InvalidLogHandler, InvalidOutcome, InvalidPolicy};
+use crate::disk_watch::{self, DiskPressure, DiskWatchConfig};
+use crate::enrichment::PeerCredentials;
+use crate::handover;
+use crate::live::LiveFeed;
+use crate::parse_pool::{parse_frame, ParsePool};
+use crate::pipeline::{IngestConfig, IngestPipeline};
+use crate::privileges::{PrivilegeDrop, SocketPermissions};
+use crate::quota::Quotas;
+use crate::sampling_report::{DropReason, SamplingReport};
+use crate::schema::{LogEntry, ParseBuffers, SharedSchemas};
+use crate::status::DaemonStatus;
 use crate::storage::StorageEngine;
 
 /// Unix socket server using io_uring for zero-copy ingestion
 pub struct LogServer {
     socket_path: std::path::PathBuf,
-    validator: Arc<SchemaValidator>,
+    validator: SharedSchemas,
+    pipeline: Arc<IngestPipeline>,
+    invalid: Arc<InvalidLogHandler>,
+    /// Sockets accepting logs besides `socket_path`
+    listeners: Vec<Listener>,
     max_connections: usize,
-    flush_interval: Duration,
+    parse_workers: usize,
+    flush_interval: FlushInterval,
+    live: Option<LiveFeed>,
+    on_log: Option<LogCallback>,
+    quotas: Option<Arc<Quotas>>,
+    status: DaemonStatus,
+    /// Counts of dropped logs, stored as a log row at the interval
+    sampling_report: Option<(Arc<SamplingReport>, Duration)>,
+    /// Whether sockets are taken over from and passed on to another process at upgrades
+    handover: bool,
+    /// Watchdog config with the log and trace directories it watches
+    disk_watch: Option<(DiskWatchConfig, PathBuf, PathBuf)>,
+    socket_permissions: SocketPermissions,
+    privilege_drop: Option<(PrivilegeDrop, oneshot::Sender<()>)>,
+    control: ServerControl,
+    storage_commands: Option<mpsc::Receiver<StorageCommand>>,
+}
+
+/// A further socket accepting logs, with its own handling of invalid logs
+struct Listener {
+    name: String,
+    socket_path: std::path::PathBuf,
+    invalid: Arc<InvalidLogHandler>,
+    /// Service whose schema validates every log on the socket
+    schema: Option<Arc<str>>,
+}
+
+/// How long the storage task waits for logs before flushing; clones share the value, so it can
+/// change while the server runs
+#[derive(Debug, Clone)]
+pub struct FlushInterval(Arc<AtomicU64>);
+
+impl FlushInterval {
+    pub fn from_secs(secs: u64) -> Self {
+        Self(Arc::new(AtomicU64::new(secs)))
+    }
+
+    pub fn get(&self) -> Duration {
+        Duration::from_secs(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn set_secs(&self, secs: u64) {
+        self.0.store(secs, Ordering::Relaxed);
+    }
+}
+
+/// Called with every accepted log on the connection that received it, before it is queued
+pub type LogCallback = Arc<dyn Fn(&LogEntry) + Send + Sync>;
+
+/// Logs buffered between connection handlers and the storage task
+const INGEST_QUEUE_CAPACITY: usize = 10000;
+
+/// Most logs a connection queues as one message; smaller batches go out at the end of each read
+const INGEST_BATCH_MAX: usize = 256;
+
+/// Logs from one connection, queued as one message so the storage task wakes once per batch;
+/// holds a queue slot per log until the storage task takes them
+struct IngestBatch {
+    logs: Vec<LogEntry>,
+    _slots: OwnedSemaphorePermit,
+}
+
+/// A log waiting to be queued, with its service label and size for the ingest metrics
+type PendingLog = (LogEntry, String, u64);
+
+/// Sending half of the ingest queue, bounded by logs rather than batches
+#[derive(Clone)]
+struct IngestQueue {
+    tx: mpsc::Sender<IngestBatch>,
+    slots: Arc<Semaphore>,
+    /// Where logs dropped at ingest are counted for the sampling report
+    report: Option<Arc<SamplingReport>>,
+}
+
+impl IngestQueue {
+    fn new(capacity: usize) -> (Self, mpsc::Receiver<IngestBatch>) {
+        // Each batch holds at least one slot, so the channel never fills before the slots do
+        let (tx, rx) = mpsc::channel(capacity);
+        let slots = Arc::new(Semaphore::new(capacity));
+        let queue = Self {
+            tx,
+            slots,
+            report: None,
+        };
+        (queue, rx)
+    }
+
+    /// Count a log dropped before it reached the queue in the sampling report
+    fn report_dropped(&self, service: &str, level: &str, reason: DropReason) {
+        if let Some(report) = &self.report {
+            report.record(service, level, reason);
+        }
+    }
+
+    /// Queue `pending` as one batch, dropping the logs there is no room for; returns how many
+    /// were dropped, or None once the storage task has stopped
+    fn send(&self, pending: &mut Vec<PendingLog>, status: &DaemonStatus) -> Option<usize> {
+        let slots = loop {
+            let room = self.slots.available_permits().min(pending.len());
+            match self.slots.clone().try_acquire_many_owned(room as u32) {
+                Ok(slots) => break slots,
+                // Another connection took some of the slots first; take what is left
+                Err(TryAcquireError::NoPermits) => continue,
+                Err(TryAcquireError::Closed) => return None,
+            }
+        };
+        // Only logs there is really no room for are dropped
+        let dropped = pending.split_off(slots.num_permits());
+        if !dropped.is_empty() {
+            for (log, service, _) in &dropped {
+                crate::metrics::record_dropped(service);
+                status.record_dropped();
+                self.report_dropped(service, &log.level, DropReason::QueueFull);
+            }
+            // The framing has no reply channel, so clients are not told
+            warn!("Backend overloaded, dropping {} log(s)", dropped.len());
+        }
+        if pending.is_empty() {
+            return Some(dropped.len());
+        }
+
+        let (logs, labels): (Vec<_>, Vec<_>) = pending
+            .drain(..)
+            .map(|(log, service, length)| (log, (service, length)))
+            .unzip();
+        let batch = IngestBatch {
+            logs,
+            _slots: slots,
+        };
+        match self.tx.try_send(batch) {
+            Ok(()) => {
+                for (service, length) in &labels {
+                    crate::metrics::record_ingested(service, *length);
+                    status.record_ingested();
+                }
+                Some(dropped.len())
+            }
+            Err(mpsc::error::TrySendError::Full(batch)) => {
+                for (log, (service, _)) in batch.logs.iter().zip(&labels) {
+                    crate::metrics::record_dropped(service);
+                    status.record_dropped();
+                    self.report_dropped(service, &log.level, DropReason::QueueFull);
+                }
+                Some(dropped.len() + labels.len())
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => None,
+        }
+    }
+
+    /// Logs queued and not yet taken by the storage task
+    fn depth(slots: &Semaphore) -> usize {
+        INGEST_QUEUE_CAPACITY.saturating_sub(slots.available_permits())
+    }
+}
+
+/// How long a drain waits for open connections to close before stopping anyway
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Requests the storage task handles between logs
+#[derive(Debug)]
+enum StorageCommand {
+    /// Store everything queued, write it out and reply with the number of logs written
+    Flush(oneshot::Sender<Result<usize>>),
+}
+
+/// Handle for flushing or draining a running server from outside the ingest thread
+#[derive(Debug, Clone)]
+pub struct ServerControl {
+    storage: mpsc::Sender<StorageCommand>,
+    drain: Arc<Notify>,
+    /// Another process took over the socket, so a drain leaves the socket file in place
+    handed_over: Arc<AtomicBool>,
+}
+
+impl ServerControl {
+    /// Write out every log received so far; returns how many were written
+    pub async fn flush(&self) -> Result<usize> {
+        let (reply, written) = oneshot::channel();
+        self.storage
+            .send(StorageCommand::Flush(reply))
+            .await
+            .map_err(|_| anyhow!("The storage task is not running"))?;
+        written
+            .await
+            .map_err(|_| anyhow!("The storage task stopped"))?
+    }
+
+    /// Stop accepting connections, wait up to `DRAIN_TIMEOUT` for open ones to close, flush
+    /// and stop the server
+    pub fn drain(&self) {
+        self.drain.notify_one();
+    }
+
+    /// Drain after another process took over the socket, leaving the socket file to it
+    pub fn hand_over(&self) {
+        self.handed_over.store(true, Ordering::SeqCst);
+        self.drain.notify_one();
+    }
 }
 
 impl LogServer {
     pub fn new(
         socket_path: std::path::PathBuf,
-        validator: SchemaValidator,
+        validator: impl Into<SharedSchemas>,
         max_connections: usize,
         flush_interval_secs: u64,
     ) -> Self {
+        let (storage, storage_commands) = mpsc::channel(8);
         Self {
             socket_path,
-            validator: Arc::new(validator),
+            validator: validator.into(),
+            pipeline: Arc::new(IngestPipeline::default()),
+            invalid: Arc::new(InvalidLogHandler::default()),
+            listeners: Vec::new(),
             max_connections,
-            flush_interval: Duration::from_secs(flush_interval_secs),
+            parse_workers: 0,
+            flush_interval: FlushInterval::from_secs(flush_interval_secs),
+            live: None,
+            on_log: None,
+            quotas: None,
+            status: DaemonStatus::new(),
+            sampling_report: None,
+            handover: true,
+            disk_watch: None,
+            socket_permissions: SocketPermissions::default(),
+            privilege_drop: None,
+            control: ServerControl {
+                storage,
+                drain: Arc::new(Notify::new()),
+                handed_over: Arc::new(AtomicBool::new(false)),
+            },
+            storage_commands: Some(storage_commands),
+        }
+    }
+
+    /// Process accepted logs with `pipeline` before they are queued
+    pub fn with_pipeline(mut self, pipeline: IngestPipeline) -> Self {
+        self.pipeline = Arc::new(pipeline);
+        self
+    }
+
+    /// Handle logs that fail validation with `invalid` instead of dropping them
+    pub fn with_invalid_handler(mut self, invalid: InvalidLogHandler) -> Self {
+        self.invalid = Arc::new(invalid);
+        self
+    }
+
+    /// Also accept logs on `socket_path`, handling invalid ones with `invalid`; `name` tells the
+    /// socket apart in logs, metrics and upgrades
+    pub fn with_listener(
+        mut self,
+        name: impl Into<String>,
+        socket_path: std::path::PathBuf,
+        invalid: InvalidLogHandler,
+    ) -> Self {
+        self.listeners.push(Listener {
+            name: name.into(),
+            socket_path,
+            invalid: Arc::new(invalid),
+            schema: None,
+        });
+        self
+    }
+
+    /// Validate every log on the listener added last against the schema of `service`,
+    /// whatever service the log names
+    pub fn with_listener_schema(mut self, service: &str) -> Self {
+        if let Some(listener) = self.listeners.last_mut() {
+            listener.schema = Some(service.into());
+        }
+        self
+    }
+
+    /// Handle invalid logs on the main socket and accept logs on the further sockets as
+    /// `ingest` says
+    pub fn with_ingest_listeners(
+        mut self,
+        ingest: &IngestConfig,
+        storage_dir: &Path,
+    ) -> Result<Self> {
+        let handler = |(name, invalid): (&str, _)| {
+            let handler =
+                InvalidLogHandler::from_config(name, &invalid, &ingest.redaction, storage_dir)?;
+            if handler.policy() != InvalidPolicy::Drop {
+                info!("Invalid logs on {}: {:?}", name, handler.policy());
+            }
+            anyhow::Ok(handler)
+        };
+        // The main socket comes first
+        let mut configs = ingest.invalid_by_listener(storage_dir).into_iter();
+        if let Some(main) = configs.next() {
+            self.invalid = Arc::new(handler(main)?);
         }
+        for (listener, config) in ingest.listeners.iter().zip(configs) {
+            let invalid = handler(config)?;
+            self = self.with_listener(&listener.name, listener.socket_path.clone(), invalid);
+            if let Some(schema) = &listener.schema {
+                self = self.with_listener_schema(schema);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Parse and validate on `workers` threads instead of the ingest thread; 0 keeps parsing
+    /// on the ingest thread
+    pub fn with_parse_workers(mut self, workers: usize) -> Self {
+        self.parse_workers = workers;
+        self
+    }
+
+    /// Publish every accepted log to live API subscribers
+    pub fn with_live_feed(mut self, live: LiveFeed) -> Self {
+        self.live = Some(live);
+        self
+    }
+
+    /// Call each of `callbacks`, in order, with every accepted log; they run on the ingest
+    /// thread, so they should not block
+    pub fn with_log_callbacks(mut self, callbacks: Vec<LogCallback>) -> Self {
+        self.on_log = match callbacks.len() {
+            0 => None,
+            1 => callbacks.into_iter().next(),
+            _ => Some(Arc::new(move |log: &LogEntry| {
+                for on_log in &callbacks {
+                    on_log(log);
+                }
+            })),
+        };
+        self
+    }
+
+    /// Drop or sample logs from services over their `quotas`
+    pub fn with_quotas(mut self, quotas: Quotas) -> Self {
+        self.quotas = Some(Arc::new(quotas));
+        self
+    }
+
+    /// Report queue depth, drops, flushes and storage errors into `status`
+    pub fn with_status(mut self, status: DaemonStatus) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Neither take sockets over from a previous process nor pass them on, and do not signal
+    /// readiness to one, for a server embedded in a program that does not upgrade in place
+    pub fn without_handover(mut self) -> Self {
+        self.handover = false;
+        self
+    }
+
+    /// Store a sampling report row every `interval` while logs are being dropped at ingest;
+    /// zero stores none
+    pub fn with_sampling_report(mut self, interval: Duration) -> Self {
+        self.sampling_report =
+            (!interval.is_zero()).then(|| (Arc::new(SamplingReport::new(Utc::now())), interval));
+        self
+    }
+
+    /// Degrade ingestion as the volume holding `storage_dir` fills up, as `config` says; a
+    /// `check_secs` of 0 leaves the watchdog off
+    pub fn with_disk_watch(
+        mut self,
+        config: &DiskWatchConfig,
+        storage_dir: &Path,
+        trace_dir: &Path,
+    ) -> Self {
+        self.disk_watch = (config.check_secs > 0).then(|| {
+            (
+                config.clone(),
+                storage_dir.to_path_buf(),
+                trace_dir.to_path_buf(),
+            )
+        });
+        self
+    }
+
+    /// Give the socket `permissions` as soon as it is bound
+    pub fn with_socket_permissions(mut self, permissions: SocketPermissions) -> Self {
+        self.socket_permissions = permissions;
+        self
+    }
+
+    /// Switch the process to `target` once the socket is bound, before accepting connections,
+    /// then signal `dropped`
+    pub fn with_privilege_drop(
+        mut self,
+        target: PrivilegeDrop,
+        dropped: oneshot::Sender<()>,
+    ) -> Self {
+        self.privilege_drop = Some((target, dropped));
+        self
+    }
+
+    /// Handle for changing the flush interval after the server starts
+    pub fn flush_interval(&self) -> FlushInterval {
+        self.flush_interval.clone()
+    }
+
+    /// Handle for flushing or draining the server after it starts
+    pub fn control(&self) -> ServerControl {
+        self.control.clone()
     }
 
     /// Start the server and listen for connections using io_uring
@@ -37,81 +443,225 @@ impl LogServer {
         tokio_uring::start(async move { self.run_async(storage).await })
     }
 
-    async fn run_async(self, mut storage: StorageEngine) -> Result<()> {
-        // Remove existing socket file if it exists
-        if self.socket_path.exists() {
-            std::fs::remove_file(&self.socket_path).with_context(|| {
-                format!("Failed to remove existing socket: {:?}", self.socket_path)
-            })?;
+    /// Bind the socket, replacing a stale socket file; only the daemon's own user may connect
+    /// until the configured permissions are applied
+    fn bind(&self, socket_path: &Path) -> Result<std::os::unix::net::UnixListener> {
+        if socket_path.exists() {
+            std::fs::remove_file(socket_path)
+                .with_context(|| format!("Failed to remove existing socket: {:?}", socket_path))?;
+        }
+
+        // Owner-only until the configured mode and owner are applied
+        let bound = if self.socket_permissions.is_empty() {
+            std::os::unix::net::UnixListener::bind(socket_path)
+        } else {
+            crate::privileges::bind_with_umask(socket_path, 0o177)
+        };
+        let listener =
+            bound.with_context(|| format!("Failed to bind to socket: {:?}", socket_path))?;
+        self.socket_permissions.apply(socket_path)?;
+        Ok(listener)
+    }
+
+    /// Bind `socket_path`, or take it over from the previous process, passing it on as
+    /// `handover_name` at the next upgrade unless the server runs without handover
+    fn listen(&self, handover_name: &str, socket_path: &Path) -> Result<tokio::net::UnixListener> {
+        // After an upgrade the socket is already bound and keeps its permissions
+        let inherited = self
+            .handover
+            .then(|| handover::take_unix(handover_name, socket_path))
+            .flatten();
+        let listener = match inherited {
+            Some(listener) => {
+                info!(
+                    "Took over the socket {:?} from the previous process",
+                    socket_path
+                );
+                listener
+            }
+            None => self.bind(socket_path)?,
+        };
+        if self.handover {
+            handover::register(handover_name, &listener)?;
+        }
+        // Connections are accepted through epoll since a tokio-uring listener cannot be built
+        // from an inherited socket; they are read with io_uring
+        listener.set_nonblocking(true)?;
+        Ok(tokio::net::UnixListener::from_std(listener)?)
+    }
+
+    async fn run_async(mut self, mut storage: StorageEngine) -> Result<()> {
+        let mut listeners = vec![(
+            self.listen(handover::INGEST, &self.socket_path)?,
+            (self.invalid.clone(), None),
+        )];
+        for extra in &self.listeners {
+            let handover_name = format!("{}.{}", handover::INGEST, extra.name);
+            listeners.push((
+                self.listen(&handover_name, &extra.socket_path)?,
+                (extra.invalid.clone(), extra.schema.clone()),
+            ));
         }
 
-        // Bind to Unix socket using tokio-uring
-        let listener = UnixListener::bind(&self.socket_path)
-            .with_context(|| format!("Failed to bind to socket: {:?}", self.socket_path))?;
+        if let Some((target, dropped)) = self.privilege_drop.take() {
+            target.apply()?;
+            info!("Dropped privileges to {}", target);
+            // The receiver is gone when nothing waits for the drop
+            let _ = dropped.send(());
+        }
 
         info!(
             "Log daemon listening on {:?} (io_uring enabled)",
             self.socket_path
         );
+        for extra in &self.listeners {
+            info!("Listener {} on {:?}", extra.name, extra.socket_path);
+        }
+        self.status.set_listening(true);
+        if self.handover {
+            handover::notify_ready();
+        }
+        if let Some((config, storage_dir, trace_dir)) = self.disk_watch.take() {
+            disk_watch::spawn(config, self.status.clone(), storage_dir, trace_dir)?;
+        }
+
+        // Create bounded channel for backpressure
+        let (mut queue, mut rx) = IngestQueue::new(INGEST_QUEUE_CAPACITY);
+        queue.report = self
+            .sampling_report
+            .as_ref()
+            .map(|(report, _)| report.clone());
+        let slots = queue.slots.clone();
+        self.status.set_queue(0, INGEST_QUEUE_CAPACITY);
+        metrics::gauge!(crate::metrics::CHANNEL_CAPACITY, INGEST_QUEUE_CAPACITY as f64, "channel" => "ingest");
+
+        // Tasks on this io_uring runtime are invisible to the main runtime's gauges
+        tokio_uring::spawn(async {
+            let mut ticker = tokio::time::interval(crate::metrics::SELF_METRICS_INTERVAL);
+            loop {
+                ticker.tick().await;
+                crate::metrics::record_runtime_tasks("uring");
+            }
+        });
 
-        // Create bounded channel for backpressure (10k items)
-        let (tx, mut rx) = mpsc::channel::<LogEntry>(10000);
+        let parse_pool = match self.parse_workers {
+            0 => None,
+            workers => {
+                info!("Parsing on {} worker thread(s)", workers);
+                Some(ParsePool::start(workers)?)
+            }
+        };
 
         // Semaphore for connection limiting
         let semaphore = Arc::new(Semaphore::new(self.max_connections));
 
         // Spawn storage task that consumes the channel
-        let flush_interval = self.flush_interval;
+        let flush_interval = self.flush_interval.clone();
+        let status = self.status.clone();
+        let mut commands = self
+            .storage_commands
+            .take()
+            .context("The server is already running")?;
+        let mut reports = self
+            .sampling_report
+            .clone()
+            .map(|(report, interval)| (report, tokio::time::interval(interval)));
         tokio_uring::spawn(async move {
             loop {
-                match tokio::time::timeout(flush_interval, rx.recv()).await {
-                    Ok(Some(log)) => {
-                        if let Err(e) = storage.add_log(log) {
+                tokio::select! {
+                    received = tokio::time::timeout(flush_interval.get(), rx.recv()) => {
+                        match received {
+                            Ok(Some(batch)) => {
+                                for log in batch.logs {
+                                    if let Err(e) = storage.add_log(log) {
+                                        error!("Storage error: {}", e);
+                                        status.record_storage_error(&e);
+                                    }
+                                }
+                            }
+                            Ok(None) => break, // Channel closed
+                            Err(_) => {
+                                // Timeout, flush
+                                if let Err(e) = storage.flush() {
+                                    error!("Flush error: {}", e);
+                                    status.record_storage_error(&e);
+                                }
+                            }
+                        }
+                    }
+                    Some(report) = next_sampling_report(&mut reports) => {
+                        if let Err(e) = storage.add_log(report) {
                             error!("Storage error: {}", e);
+                            status.record_storage_error(&e);
                         }
                     }
-                    Ok(None) => break, // Channel closed
-                    Err(_) => {
-                        // Timeout, flush
-                        if let Err(e) = storage.flush() {
+                    Some(StorageCommand::Flush(reply)) = commands.recv() => {
+                        if let Some((report, _)) = &reports {
+                            if let Some(report) = report.take(Utc::now()) {
+                                if let Err(e) = storage.add_log(report) {
+                                    error!("Storage error: {}", e);
+                                    status.record_storage_error(&e);
+                                }
+                            }
+                        }
+                        let result = flush_queued(&mut storage, &mut rx);
+                        if let Err(e) = &result {
                             error!("Flush error: {}", e);
+                            status.record_storage_error(e);
                         }
+                        let _ = reply.send(result);
                     }
                 }
+                let depth = IngestQueue::depth(&slots);
+                status.set_queue(depth, INGEST_QUEUE_CAPACITY);
+                metrics::gauge!(crate::metrics::INGEST_QUEUE_DEPTH, depth as f64);
+                metrics::gauge!(crate::metrics::CHANNEL_LEN, depth as f64, "channel" => "ingest");
+                if let Some(flushed) = storage.last_flush() {
+                    status.record_flush(flushed);
+                }
+                status.set_storage_failure_streak(storage.failure_streak());
             }
             // Final flush
-            let _ = storage.flush();
+            let _ = storage.flush_now();
         });
 
-        // Connection counter
-        let active_connections = std::sync::atomic::AtomicUsize::new(0);
-        let active_connections = Arc::new(active_connections);
-
-        // Accept connections
+        // Accept connections until a drain is requested
+        let drain = self.control.drain.clone();
         loop {
-            let permit = semaphore.clone().acquire_owned().await?;
+            let permit = tokio::select! {
+                permit = semaphore.clone().acquire_owned() => permit?,
+                _ = drain.notified() => break,
+            };
+            let (listener, accepted) = tokio::select! {
+                accepted = accept_any(&listeners) => accepted,
+                _ = drain.notified() => break,
+            };
 
-            match listener.accept().await {
+            match accepted.and_then(uring_stream) {
                 Ok(stream) => {
-                    // tokio-uring accept returns Ok(stream)
-                    let tx = tx.clone();
+                    let queue = queue.clone();
                     let validator = self.validator.clone();
-                    let connections = active_connections.clone();
+                    let pipeline = self.pipeline.clone();
+                    let (invalid, schema) = listeners[listener].1.clone();
+                    let live = self.live.clone();
+                    let on_log = self.on_log.clone();
+                    let quotas = self.quotas.clone();
+                    let status = self.status.clone();
+                    let parse_pool = parse_pool.clone();
 
                     tokio_uring::spawn(async move {
-                        // Increment gauge
-                        let count =
-                            connections.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
-                        metrics::gauge!(crate::metrics::ACTIVE_CONNECTIONS, count as f64);
+                        crate::metrics::connection_opened();
 
-                        if let Err(e) = handle_connection(stream, tx, validator).await {
+                        if let Err(e) = handle_connection(
+                            stream, queue, validator, schema, pipeline, parse_pool, invalid, live,
+                            on_log, quotas, status,
+                        )
+                        .await
+                        {
                             debug!("Connection closed: {}", e);
                         }
 
-                        // Decrement gauge
-                        let count =
-                            connections.fetch_sub(1, std::sync::atomic::Ordering::Relaxed) - 1;
-                        metrics::gauge!(crate::metrics::ACTIVE_CONNECTIONS, count as f64);
+                        crate::metrics::connection_closed();
 
                         drop(permit);
                     });
@@ -121,22 +671,193 @@ impl LogServer {
                 }
             }
         }
+
+        self.status.set_listening(false);
+        drop(listeners);
+        if !self.control.handed_over.load(Ordering::SeqCst) {
+            let _ = std::fs::remove_file(&self.socket_path);
+            for extra in &self.listeners {
+                let _ = std::fs::remove_file(&extra.socket_path);
+            }
+        }
+        let open = self.max_connections - semaphore.available_permits();
+        info!(
+            "Draining: stopped accepting connections, waiting up to {:?} for {} open one(s)",
+            DRAIN_TIMEOUT, open
+        );
+        let all = u32::try_from(self.max_connections).unwrap_or(u32::MAX);
+        if tokio::time::timeout(DRAIN_TIMEOUT, semaphore.acquire_many(all))
+            .await
+            .is_err()
+        {
+            warn!(
+                "{} connection(s) still open after {:?}; closing them",
+                self.max_connections - semaphore.available_permits(),
+                DRAIN_TIMEOUT
+            );
+        }
+        let written = self.control.flush().await?;
+        info!("Drained: wrote the last {} log(s), stopping", written);
+        Ok(())
+    }
+}
+
+/// The next connection on any of `listeners`, with the position of the listener it came in on
+async fn accept_any<T>(
+    listeners: &[(tokio::net::UnixListener, T)],
+) -> (usize, std::io::Result<tokio::net::UnixStream>) {
+    std::future::poll_fn(|cx| {
+        for (position, (listener, _)) in listeners.iter().enumerate() {
+            if let Poll::Ready(accepted) = listener.poll_accept(cx) {
+                return Poll::Ready((position, accepted.map(|(stream, _)| stream)));
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+/// Hand an accepted connection to io_uring, which expects a blocking socket
+fn uring_stream(stream: tokio::net::UnixStream) -> std::io::Result<UnixStream> {
+    let stream = stream.into_std()?;
+    stream.set_nonblocking(false)?;
+    Ok(UnixStream::from_std(stream))
+}
+
+/// The sampling report due at the next tick of `reports`, if logs were dropped since the
+/// last one; never resolves without a report configured
+async fn next_sampling_report(
+    reports: &mut Option<(Arc<SamplingReport>, tokio::time::Interval)>,
+) -> Option<LogEntry> {
+    match reports {
+        Some((report, ticker)) => {
+            ticker.tick().await;
+            report.take(Utc::now())
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Store the logs still in the queue and write everything out; returns how many were written
+fn flush_queued(
+    storage: &mut StorageEngine,
+    rx: &mut mpsc::Receiver<IngestBatch>,
+) -> Result<usize> {
+    let mut written = storage.pending();
+    while let Ok(batch) = rx.try_recv() {
+        for log in batch.logs {
+            storage.add_log(log)?;
+            written += 1;
+        }
+    }
+    storage.flush_now()?;
+    Ok(written)
+}
+
+/// What a connection sent, recorded on its span when it closes, so a trace shows which
+/// client was busy during a stall
+struct ConnectionStats {
+    span: Span,
+    frames: u64,
+    bytes: u64,
+    invalid: u64,
+    dropped: u64,
+    services: BTreeMap<String, u64>,
+}
+
+impl ConnectionStats {
+    fn new(span: Span) -> Self {
+        Self {
+            span,
+            frames: 0,
+            bytes: 0,
+            invalid: 0,
+            dropped: 0,
+            services: BTreeMap::new(),
+        }
+    }
+}
+
+impl Drop for ConnectionStats {
+    fn drop(&mut self) {
+        if self.span.is_disabled() {
+            return;
+        }
+        self.span.record("frames", self.frames);
+        self.span.record("bytes", self.bytes);
+        self.span.record("invalid", self.invalid);
+        self.span.record("dropped", self.dropped);
+        let services = self.services.iter().map(|(s, logs)| (s.as_str(), *logs));
+        self.span.record(
+            "services",
+            crate::metrics::service_summary(services).as_str(),
+        );
     }
 }
 
 /// Handle a single client connection
-#[tracing::instrument(skip(stream, tx, validator), fields(otel.kind = "server"))]
+#[allow(clippy::too_many_arguments)] // each is shared with every connection
+#[tracing::instrument(
+    skip(
+        stream, queue, validator, schema, pipeline, parse_pool, invalid, live, on_log, quotas,
+        status
+    ),
+    fields(
+        otel.kind = "server",
+        peer.pid = tracing::field::Empty,
+        peer.uid = tracing::field::Empty,
+        peer.process = tracing::field::Empty,
+        frames = tracing::field::Empty,
+        bytes = tracing::field::Empty,
+        invalid = tracing::field::Empty,
+        dropped = tracing::field::Empty,
+        services = tracing::field::Empty,
+    )
+)]
 async fn handle_connection(
     stream: UnixStream,
-    tx: mpsc::Sender<LogEntry>,
-    validator: Arc<SchemaValidator>,
+    queue: IngestQueue,
+    validator: SharedSchemas,
+    schema: Option<Arc<str>>,
+    pipeline: Arc<IngestPipeline>,
+    parse_pool: Option<ParsePool>,
+    invalid: Arc<InvalidLogHandler>,
+    live: Option<LiveFeed>,
+    on_log: Option<LogCallback>,
+    quotas: Option<Arc<Quotas>>,
+    status: DaemonStatus,
 ) -> Result<()> {
     // 8KB read buffer
     let mut buf = vec![0u8; 8192];
     // Accumulation buffer for framing
     let mut accumulator = BytesMut::with_capacity(16384);
+    // Parser scratch space, grown by the largest message so far
+    let mut parse_buffers = ParseBuffers::default();
 
     let stream = stream;
+    let mut stats = ConnectionStats::new(Span::current());
+    // Whether the client was told the storage volume is full since its last accepted log
+    let mut told_disk_full = false;
+
+    // Reading the peer's process name touches /proc, so only when logs are enriched with it
+    let peer = match pipeline
+        .wants_peer()
+        .then(|| PeerCredentials::from_fd(stream.as_raw_fd()))
+    {
+        Some(Ok(peer)) => {
+            stats.span.record("peer.pid", peer.pid);
+            stats.span.record("peer.uid", peer.uid);
+            if let Some(process) = &peer.process {
+                stats.span.record("peer.process", process.as_str());
+            }
+            Some(peer)
+        }
+        Some(Err(e)) => {
+            debug!("Failed to read peer credentials: {}", e);
+            None
+        }
+        None => None,
+    };
 
     loop {
         // Read into buffer using io_uring
@@ -151,7 +872,14 @@ async fn handle_connection(
         // Append read data to accumulator
         accumulator.extend_from_slice(&buf[..n]);
 
-        // Process framed messages
+        // Schema changes apply from the next read on
+        let mut router = validator.load();
+        if let Some(service) = &schema {
+            router = Arc::new(router.pinned(service));
+        }
+
+        // Split off every complete frame
+        let mut frames = Vec::new();
         loop {
             // Need at least 4 bytes for length
             if accumulator.len() < 4 {
@@ -173,43 +901,178 @@ async fn handle_connection(
 
             // Consume length + message
             accumulator.advance(4);
-            // We need a mutable slice for SIMD parsing.
-            // accumulator.split_to(length) gives us a BytesMut which is mostly contiguous,
-            // but simd-json needs `&mut [u8]`.
-            // We can make it contiguous.
-            let mut msg_bytes = accumulator.split_to(length);
-
-            // Fast Parse (SIMD)
-            // Note: simd_json modifies the input slice (in-place string filtering)
-            let parse_span = tracing::info_span!("parse_log", message_size = length);
-            let _guard = parse_span.enter();
-
-            match validator.parse_fast(&mut msg_bytes) {
-                Ok(log) => {
-                    drop(_guard);
-                    metrics::counter!(crate::metrics::INGEST_COUNT, 1);
-                    // Backpressure check: try_send
-                    match tx.try_send(log) {
-                        Ok(_) => {}
-                        Err(mpsc::error::TrySendError::Full(_)) => {
-                            metrics::counter!(crate::metrics::DROPPED_MESSAGES, 1);
-                            // In a real implementation we would send error back to client
-                            // But for io_uring proof-of-concept avoiding complex Write logic for now
-                            warn!("Backend overloaded, dropping log");
-
-                            // To send error:
-                            // let (res, b) = stream.write(vec_from("ERROR: Overloaded")).await;
-                            // buf = b; ... difficult with moved stream.
+            // simd-json needs `&mut [u8]`, which the split-off BytesMut gives
+            frames.push(accumulator.split_to(length));
+        }
+        if frames.is_empty() {
+            continue;
+        }
+        stats.frames += frames.len() as u64;
+        stats.bytes += frames
+            .iter()
+            .map(|frame| 4 + frame.len() as u64)
+            .sum::<u64>();
+
+        // Parsing rewrites the buffers, so policies that keep invalid payloads, and payload
+        // sampling, copy them first
+        let keep = invalid.keeps_payload() || crate::payload_log::enabled();
+        let received: Vec<_> = frames
+            .iter()
+            .map(|frame| (frame.len(), keep.then(|| frame.to_vec())))
+            .collect();
+        let parsed = match &parse_pool {
+            Some(pool) => {
+                pool.parse(router, pipeline.clone(), peer.clone(), frames)
+                    .await?
+            }
+            None => frames
+                .into_iter()
+                .map(|mut frame| {
+                    parse_frame(
+                        &router,
+                        &pipeline,
+                        peer.as_ref(),
+                        &mut frame,
+                        &mut parse_buffers,
+                    )
+                })
+                .collect(),
+        };
+
+        let mut pending = Vec::with_capacity(parsed.len().min(INGEST_BATCH_MAX));
+        for (parsed, (length, raw)) in parsed.into_iter().zip(received) {
+            let log = match parsed {
+                Ok(log) => log,
+                Err(e) => {
+                    stats.invalid += 1;
+                    crate::metrics::record_validation_failure(e.reason.as_str());
+                    // Client payloads stay out of the daemon's own logs unless sampled, and
+                    // are redacted as stored logs are
+                    let sampled = raw.as_deref().and_then(|raw| {
+                        crate::payload_log::sample(raw, |text| pipeline.redact_payload(text))
+                    });
+                    match sampled {
+                        Some(payload) => {
+                            warn!("Invalid log: {}; payload: {}", e.scrubbed(), payload)
+                        }
+                        None => warn!("Invalid log: {}", e.scrubbed()),
+                    }
+                    match invalid.handle(&e, raw.as_deref()) {
+                        InvalidOutcome::Dropped => continue,
+                        InvalidOutcome::Reply(frame) => {
+                            let (res, _) = stream.write_all(frame).await;
+                            res?;
+                            continue;
+                        }
+                        InvalidOutcome::Accepted(mut log) => {
+                            if let Err(e) = pipeline.process(&mut log, peer.as_ref()) {
+                                warn!("Dropping invalid log: {}", e.scrubbed());
+                                continue;
+                            }
+                            log
                         }
-                        Err(_) => break, // Channel closed
                     }
                 }
-                Err(e) => {
-                    warn!("Invalid log: {}", e);
+            };
+
+            let service = crate::metrics::service_label(log.service.as_deref());
+            let pressure = status.disk_pressure();
+            if !pressure.admits(&log.level) {
+                crate::metrics::record_disk_dropped(&service, pressure);
+                queue.report_dropped(&service, &log.level, DropReason::DiskPressure);
+                // One reply per connection until logs are accepted again, not one per log
+                if pressure == DiskPressure::Reject && !told_disk_full {
+                    told_disk_full = true;
+                    let (res, _) = stream.write_all(disk_watch::reject_frame()).await;
+                    res?;
+                }
+                continue;
+            }
+            told_disk_full = false;
+            if let Some(quotas) = &quotas {
+                let name = log.service.as_deref().filter(|name| !name.is_empty());
+                let name = name.unwrap_or(&service);
+                if !quotas.admit(name, &service, length as u64, Utc::now()) {
+                    queue.report_dropped(&service, &log.level, DropReason::Quota);
+                    continue;
+                }
+            }
+            if let Some(live) = &live {
+                live.publish_log(&log);
+            }
+            if let Some(on_log) = &on_log {
+                on_log(&log);
+            }
+            match stats.services.get_mut(service.as_str()) {
+                Some(logs) => *logs += 1,
+                None => {
+                    stats.services.insert(service.clone(), 1);
                 }
             }
+            pending.push((log, service, length as u64));
+            if pending.len() >= INGEST_BATCH_MAX {
+                let Some(dropped) = queue.send(&mut pending, &status) else {
+                    return Ok(()); // Storage stopped
+                };
+                stats.dropped += dropped as u64;
+            }
         }
+        // Queue the rest of this read rather than hold it while waiting for the next one
+        let Some(dropped) = queue.send(&mut pending, &status) else {
+            return Ok(());
+        };
+        stats.dropped += dropped as u64;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending(count: usize) -> Vec<PendingLog> {
+        (0..count)
+            .map(|i| {
+                let log: LogEntry = serde_json::from_value(serde_json::json!({
+                    "timestamp": "2026-01-01T00:00:00Z",
+                    "level": "info",
+                    "message": format!("m{}", i),
+                }))
+                .unwrap();
+                (log, "api".to_string(), 10)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ingest_queue_batches_and_drops_past_capacity() {
+        let status = DaemonStatus::new();
+        let (queue, mut rx) = IngestQueue::new(3);
+
+        let mut logs = pending(2);
+        assert_eq!(queue.send(&mut logs, &status), Some(0));
+        assert!(logs.is_empty());
+        let mut logs = pending(2);
+        assert_eq!(queue.send(&mut logs, &status), Some(1));
+        assert_eq!(queue.slots.available_permits(), 0);
+
+        // Two batches: the second cut down to the one slot left
+        let first = rx.try_recv().unwrap();
+        assert_eq!(first.logs.len(), 2);
+        let second = rx.try_recv().unwrap();
+        assert_eq!(second.logs.len(), 1);
+        assert_eq!(second.logs[0].message, "m0");
+        assert!(rx.try_recv().is_err());
+
+        // Slots come back once the storage task is done with a batch
+        drop(first);
+        assert_eq!(queue.slots.available_permits(), 2);
+        let report = status.report(std::path::Path::new("/"));
+        assert_eq!((report.ingested_total, report.dropped_total), (3, 1));
+
+        drop(second);
+        drop(rx);
+        assert_eq!(queue.send(&mut pending(1), &status), None);
+    }
+}
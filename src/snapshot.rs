@@ -0,0 +1,609 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::checksum::{self, Checksum, ChecksumWriter, MANIFEST_FILE};
+use crate::stats::human_bytes;
+
+/// First member of a snapshot, describing it
+const SNAPSHOT_FILE: &str = "snapshot.json";
+
+/// Tar block size; headers and padded file contents come in blocks
+const BLOCK: usize = 512;
+
+/// Written into `snapshot.json` and checked on restore
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotInfo {
+    version: String,
+    created_at: DateTime<Utc>,
+    files: usize,
+}
+
+/// A store directory and its name inside the archive
+struct Store<'a> {
+    name: &'static str,
+    dir: &'a Path,
+}
+
+fn stores<'a>(storage_dir: &'a Path, trace_dir: &'a Path) -> [Store<'a>; 2] {
+    [
+        Store {
+            name: "logs",
+            dir: storage_dir,
+        },
+        Store {
+            name: "traces",
+            dir: trace_dir,
+        },
+    ]
+}
+
+/// What `snapshot` archived
+#[derive(Debug, Default)]
+pub struct SnapshotReport {
+    pub files: usize,
+    pub bytes: u64,
+    /// Files without a checksum modified moments ago, left out as possibly half written
+    pub skipped: Vec<PathBuf>,
+    /// Files deleted, e.g. by retention, between the listing and their turn to be archived
+    pub vanished: Vec<PathBuf>,
+}
+
+impl fmt::Display for SnapshotReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for path in &self.skipped {
+            writeln!(f, "{}  (still being written, skipped)", path.display())?;
+        }
+        for path in &self.vanished {
+            writeln!(f, "{}  (deleted while archiving, skipped)", path.display())?;
+        }
+        write!(
+            f,
+            "{} file(s), {} archived",
+            self.files,
+            human_bytes(self.bytes)
+        )
+    }
+}
+
+/// Archive the Parquet files of the log and trace directories with their checksums into a
+/// zstd-compressed tar file at `out`. Stored files never change once written, so the archive
+/// is consistent as of the listing; files written during the snapshot are not in it, and files
+/// deleted meanwhile are left out.
+pub fn snapshot(
+    storage_dir: &Path,
+    trace_dir: &Path,
+    out: &Path,
+    level: i32,
+) -> Result<SnapshotReport> {
+    // List first, so snapshot.json and the manifests can lead the archive
+    let mut report = SnapshotReport::default();
+    let mut plans = Vec::new();
+    for store in stores(storage_dir, trace_dir) {
+        let recorded = checksum::load_manifest(store.dir)?;
+        let mut files = Vec::new();
+        for path in parquet_files(store.dir)? {
+            let name = file_name(&path)?;
+            let checksum = match recorded.get(&name) {
+                Some(checksum) => checksum.clone(),
+                None if checksum::in_flight(&path) => {
+                    report.skipped.push(path);
+                    continue;
+                }
+                // Written before checksums were recorded
+                None => checksum::checksum_file(&path)
+                    .with_context(|| format!("Failed to read {:?}", path))?,
+            };
+            report.files += 1;
+            report.bytes += checksum.bytes;
+            files.push((path, name, checksum));
+        }
+        plans.push((store, files));
+    }
+
+    let tmp = out.with_extension("partial");
+    let file = File::create(&tmp).with_context(|| format!("Failed to create {:?}", tmp))?;
+    let written = (|| -> Result<()> {
+        let encoder = zstd::stream::write::Encoder::new(BufWriter::new(file), level)?;
+        let mut tar = TarWriter::new(encoder);
+        let info = SnapshotInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            created_at: Utc::now(),
+            files: report.files,
+        };
+        tar.append_bytes(SNAPSHOT_FILE, &serde_json::to_vec_pretty(&info)?)?;
+        for (store, files) in &plans {
+            let manifest = checksum::manifest_contents(
+                files
+                    .iter()
+                    .map(|(_, name, checksum)| (name.clone(), checksum.clone())),
+            );
+            tar.append_bytes(
+                &format!("{}/{}", store.name, MANIFEST_FILE),
+                manifest.as_bytes(),
+            )?;
+            for (path, name, expected) in files {
+                let member = format!("{}/{}", store.name, name);
+                let file = match File::open(path) {
+                    Ok(file) => file,
+                    // Still listed in the manifest; restore finds no member for it
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                        report.files -= 1;
+                        report.bytes -= expected.bytes;
+                        report.vanished.push(path.clone());
+                        continue;
+                    }
+                    Err(e) => return Err(e).with_context(|| format!("Failed to open {:?}", path)),
+                };
+                let modified = file.metadata()?.modified()?;
+                let actual = tar.append_file(&member, expected.bytes, modified, file)?;
+                if actual != *expected {
+                    bail!(
+                        "{:?} does not match its recorded checksum; run `daemon_rs verify`",
+                        path
+                    );
+                }
+            }
+        }
+        tar.finish()?.finish()?.flush()?;
+        Ok(())
+    })();
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+    std::fs::rename(&tmp, out).with_context(|| format!("Failed to move {:?} to {:?}", tmp, out))?;
+    Ok(report)
+}
+
+/// What `restore` unpacked
+#[derive(Debug)]
+pub struct RestoreReport {
+    /// When and by which release the snapshot was taken
+    pub created_at: DateTime<Utc>,
+    pub version: String,
+    pub restored: usize,
+    pub bytes: u64,
+    /// Already present with the same contents
+    pub unchanged: usize,
+    /// Listed by the snapshot but deleted before they were archived
+    pub missing: usize,
+}
+
+impl fmt::Display for RestoreReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Restored {} file(s), {}; {} already present (snapshot of {} by daemon_rs {})",
+            self.restored,
+            human_bytes(self.bytes),
+            self.unchanged,
+            self.created_at.to_rfc3339(),
+            self.version
+        )?;
+        if self.missing > 0 {
+            write!(
+                f,
+                "; {} file(s) were deleted while it was taken",
+                self.missing
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Unpack a snapshot into the log and trace directories, next to the files already there.
+/// Every file is checked against the snapshot's checksums and only appears under its name
+/// once complete, so a running daemon can serve queries meanwhile. A file already present
+/// with different contents stops the restore.
+pub fn restore(archive: &Path, storage_dir: &Path, trace_dir: &Path) -> Result<RestoreReport> {
+    let file = File::open(archive).with_context(|| format!("Failed to open {:?}", archive))?;
+    let decoder = zstd::stream::read::Decoder::new(BufReader::new(file))?;
+    let mut tar = TarReader::new(decoder);
+    let stores = stores(storage_dir, trace_dir);
+    let mut manifests: HashMap<&str, HashMap<String, Checksum>> = HashMap::new();
+    let info: SnapshotInfo = match tar.next_member() {
+        Ok(Some(member)) if member.name == SNAPSHOT_FILE => {
+            serde_json::from_slice(&tar.read_to_vec(&member)?).context("Invalid snapshot.json")?
+        }
+        _ => bail!("{:?} is not a daemon_rs snapshot", archive),
+    };
+    let mut report = RestoreReport {
+        created_at: info.created_at,
+        version: info.version,
+        restored: 0,
+        bytes: 0,
+        unchanged: 0,
+        missing: 0,
+    };
+
+    while let Some(member) = tar.next_member()? {
+        let (store, name) = member
+            .name
+            .split_once('/')
+            .and_then(|(store, name)| Some((stores.iter().find(|s| s.name == store)?, name)))
+            .with_context(|| format!("Unexpected archive member {:?}", member.name))?;
+        // Only what `snapshot` writes, so an archive cannot drop other files into the stores
+        let known = name.ends_with(".parquet") || name == MANIFEST_FILE;
+        if name.contains(['/', '\\']) || name.starts_with('.') || !known {
+            bail!("Unexpected archive member {:?}", member.name);
+        }
+        if name == MANIFEST_FILE {
+            let manifest = checksum::parse_manifest(tar.read_to_vec(&member)?.as_slice())?;
+            manifests.insert(store.name, manifest);
+            continue;
+        }
+        let expected = manifests
+            .get(store.name)
+            .and_then(|manifest| manifest.get(name))
+            .with_context(|| format!("No checksum for {:?} in the snapshot", member.name))?
+            .clone();
+
+        std::fs::create_dir_all(store.dir)
+            .with_context(|| format!("Failed to create {:?}", store.dir))?;
+        let target = store.dir.join(name);
+        if target.exists() {
+            if checksum::checksum_file(&target)? != expected {
+                bail!(
+                    "{:?} already exists with different contents; move it away to restore",
+                    target
+                );
+            }
+            tar.skip(&member)?;
+            report.unchanged += 1;
+            continue;
+        }
+        let tmp = store.dir.join(format!(".{}.restoring", name));
+        let restored = (|| -> Result<Checksum> {
+            let mut writer = ChecksumWriter::new(BufWriter::new(File::create(&tmp)?));
+            tar.copy_to(&member, &mut writer)?;
+            let (file, checksum) = writer.finish();
+            let file = file.into_inner().map_err(|e| e.into_error())?;
+            // Retention goes by modification time, so restored data keeps its age
+            file.set_modified(UNIX_EPOCH + Duration::from_secs(member.mtime))?;
+            file.sync_all()?;
+            Ok(checksum)
+        })();
+        let checksum = match restored {
+            Ok(checksum) if checksum == expected => checksum,
+            Ok(_) => {
+                let _ = std::fs::remove_file(&tmp);
+                bail!(
+                    "{:?} does not match its checksum in the snapshot",
+                    member.name
+                );
+            }
+            Err(e) => {
+                let _ = std::fs::remove_file(&tmp);
+                return Err(e.context(format!("Failed to restore {:?}", target)));
+            }
+        };
+        std::fs::rename(&tmp, &target)
+            .with_context(|| format!("Failed to move {:?} to {:?}", tmp, target))?;
+        report.restored += 1;
+        report.bytes += checksum.bytes;
+        checksum::record(&target, checksum)?;
+    }
+    // Fewer when files were deleted while the snapshot was taken; the end marker was read, so
+    // the archive is not cut short
+    let held = report.restored + report.unchanged;
+    if held > info.files {
+        bail!("Snapshot holds {} file(s) but lists {}", held, info.files);
+    }
+    report.missing = info.files - held;
+    Ok(report)
+}
+
+/// Parquet files of `dir`, sorted; none when it does not exist
+fn parquet_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(files),
+        Err(e) => return Err(e).with_context(|| format!("Failed to list {:?}", dir)),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|s| s.to_str()) == Some("parquet") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn file_name(path: &Path) -> Result<String> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(str::to_string)
+        .with_context(|| format!("Unsupported file name {:?}", path))
+}
+
+/// Writer of the ustar subset snapshots need: regular files with short names
+struct TarWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> TarWriter<W> {
+    fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    fn append_bytes(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        self.append_file(name, data.len() as u64, std::time::SystemTime::now(), data)?;
+        Ok(())
+    }
+
+    /// Append `size` bytes of `data` as `name`, returning their checksum
+    fn append_file(
+        &mut self,
+        name: &str,
+        size: u64,
+        modified: std::time::SystemTime,
+        data: impl Read,
+    ) -> Result<Checksum> {
+        let mtime = modified
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.inner.write_all(&header(name, size, mtime)?)?;
+        let mut writer = ChecksumWriter::new(&mut self.inner);
+        let copied = std::io::copy(&mut data.take(size), &mut writer)?;
+        if copied != size {
+            bail!("{} changed size while being archived", name);
+        }
+        let (_, checksum) = writer.finish();
+        self.inner.write_all(&[0; BLOCK][..padding(size)])?;
+        Ok(checksum)
+    }
+
+    /// End the archive with two empty blocks
+    fn finish(mut self) -> Result<W> {
+        self.inner.write_all(&[0; BLOCK * 2])?;
+        Ok(self.inner)
+    }
+}
+
+fn padding(size: u64) -> usize {
+    (BLOCK - (size % BLOCK as u64) as usize) % BLOCK
+}
+
+fn header(name: &str, size: u64, mtime: u64) -> Result<[u8; BLOCK]> {
+    let mut header = [0u8; BLOCK];
+    // Names past 100 bytes go into the ustar prefix, split at a slash
+    let (prefix, name) = match name.len() {
+        0..=100 => ("", name),
+        _ => name
+            .split_once('/')
+            .filter(|(prefix, rest)| prefix.len() <= 155 && rest.len() <= 100)
+            .with_context(|| format!("Name too long for the archive: {}", name))?,
+    };
+    let mut field = |offset: usize, value: &[u8]| {
+        header[offset..offset + value.len()].copy_from_slice(value);
+    };
+    field(0, name.as_bytes());
+    field(100, b"0000644\0");
+    field(108, b"0000000\0");
+    field(116, b"0000000\0");
+    field(124, format!("{:011o}\0", size).as_bytes());
+    field(136, format!("{:011o}\0", mtime).as_bytes());
+    field(148, b"        ");
+    field(156, b"0");
+    field(257, b"ustar\0");
+    field(263, b"00");
+    field(345, prefix.as_bytes());
+    let sum: u32 = header.iter().map(|&byte| u32::from(byte)).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+    Ok(header)
+}
+
+/// A regular file in an archive being read
+struct Member {
+    name: String,
+    size: u64,
+    /// Modification time, seconds since the epoch
+    mtime: u64,
+}
+
+/// Reader of archives written by `TarWriter`
+struct TarReader<R: Read> {
+    inner: R,
+}
+
+impl<R: Read> TarReader<R> {
+    fn new(inner: R) -> Self {
+        Self { inner }
+    }
+
+    /// Header of the next member; None at the end of the archive
+    fn next_member(&mut self) -> Result<Option<Member>> {
+        let mut header = [0u8; BLOCK];
+        self.inner
+            .read_exact(&mut header)
+            .context("Archive ends without its end marker")?;
+        if header.iter().all(|&byte| byte == 0) {
+            return Ok(None);
+        }
+        let text = |range: std::ops::Range<usize>| {
+            let field = &header[range];
+            let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+            String::from_utf8_lossy(&field[..end]).into_owned()
+        };
+        let mut sum: u32 = header.iter().map(|&byte| u32::from(byte)).sum();
+        sum = sum - header[148..156].iter().map(|&b| u32::from(b)).sum::<u32>() + 8 * 32;
+        let recorded = u32::from_str_radix(text(148..156).trim(), 8).unwrap_or(u32::MAX);
+        if sum != recorded {
+            bail!("Corrupt archive header");
+        }
+        if header[156] != b'0' && header[156] != 0 {
+            bail!("Unsupported archive member type in {:?}", text(0..100));
+        }
+        let size = u64::from_str_radix(text(124..136).trim(), 8).context("Corrupt member size")?;
+        let prefix = text(345..500);
+        let name = match prefix.is_empty() {
+            true => text(0..100),
+            false => format!("{}/{}", prefix, text(0..100)),
+        };
+        let mtime = u64::from_str_radix(text(136..148).trim(), 8).unwrap_or(0);
+        Ok(Some(Member { name, size, mtime }))
+    }
+
+    fn copy_to(&mut self, member: &Member, writer: &mut impl Write) -> Result<()> {
+        let copied = std::io::copy(&mut (&mut self.inner).take(member.size), writer)?;
+        if copied != member.size {
+            bail!("Archive ends inside {:?}", member.name);
+        }
+        let mut padding_bytes = [0u8; BLOCK];
+        self.inner
+            .read_exact(&mut padding_bytes[..padding(member.size)])?;
+        Ok(())
+    }
+
+    fn read_to_vec(&mut self, member: &Member) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.copy_to(member, &mut data)?;
+        Ok(data)
+    }
+
+    fn skip(&mut self, member: &Member) -> Result<()> {
+        self.copy_to(member, &mut std::io::sink())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::QueryEngine;
+    use crate::schema::LogEntry;
+    use crate::storage::StorageEngine;
+    use crate::trace_storage::{SpanStatus, TraceSpan, TraceStorage};
+    use parquet::basic::Compression;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_snapshot_restores_into_empty_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        let logs = temp_dir.path().join("logs");
+        let traces = temp_dir.path().join("traces");
+        let mut storage =
+            StorageEngine::new(logs.clone(), Compression::SNAPPY, 100, 1 << 20).unwrap();
+        for i in 0..3 {
+            let log: LogEntry = serde_json::from_value(json!({
+                "timestamp": "2026-01-15T19:00:00Z",
+                "level": "info",
+                "message": format!("log {}", i),
+            }))
+            .unwrap();
+            storage.add_log(log).unwrap();
+            storage.flush().unwrap();
+        }
+        let mut trace_storage =
+            TraceStorage::new(traces.clone(), Compression::SNAPPY, 100).unwrap();
+        let now = Utc::now();
+        trace_storage
+            .add_span(TraceSpan {
+                trace_id: "t".to_string(),
+                span_id: "s".to_string(),
+                parent_span_id: None,
+                name: "request".to_string(),
+                start_time: now,
+                end_time: now,
+                duration_us: 0,
+                attributes: Default::default(),
+                events: Vec::new(),
+                status: SpanStatus::Ok,
+            })
+            .unwrap();
+        trace_storage.flush().unwrap();
+        // A long partition name goes through the ustar prefix
+        let long = logs.join(format!("logs_{}_0.parquet", "p".repeat(84)));
+        std::fs::copy(&storage.list_files().unwrap()[0], &long).unwrap();
+        checksum::record(&long, checksum::checksum_file(&long).unwrap()).unwrap();
+
+        let original = storage.list_files().unwrap()[0].clone();
+        let an_hour_ago = std::time::SystemTime::now() - Duration::from_secs(3600);
+        File::options()
+            .write(true)
+            .open(&original)
+            .unwrap()
+            .set_modified(an_hour_ago)
+            .unwrap();
+
+        let out = temp_dir.path().join("backup.tar.zst");
+        let report = snapshot(&logs, &traces, &out, 3).unwrap();
+        assert_eq!(report.files, 5);
+        assert!(report.skipped.is_empty());
+
+        let restored_logs = temp_dir.path().join("restored/logs");
+        let restored_traces = temp_dir.path().join("restored/traces");
+        let report = restore(&out, &restored_logs, &restored_traces).unwrap();
+        assert_eq!(report.restored, 5);
+        let modified = |path: &Path| std::fs::metadata(path).unwrap().modified().unwrap();
+        let restored = restored_logs.join(original.file_name().unwrap());
+        assert_eq!(
+            modified(&restored)
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            an_hour_ago.duration_since(UNIX_EPOCH).unwrap().as_secs()
+        );
+        assert_eq!(
+            QueryEngine::new(restored_logs.clone())
+                .count_logs()
+                .unwrap(),
+            4
+        );
+        for dir in [&restored_logs, &restored_traces] {
+            let verified = checksum::verify_dir(dir, false).unwrap();
+            assert!(verified
+                .files
+                .iter()
+                .all(|(_, check)| *check == checksum::FileCheck::Ok));
+        }
+
+        // Restoring again finds everything in place; a changed file stops it
+        let report = restore(&out, &restored_logs, &restored_traces).unwrap();
+        assert_eq!((report.restored, report.unchanged), (0, 5));
+        let first = QueryEngine::new(restored_logs.clone())
+            .list_files()
+            .unwrap()[0]
+            .clone();
+        std::fs::write(&first, b"changed").unwrap();
+        assert!(restore(&out, &restored_logs, &restored_traces).is_err());
+
+        // Members other than Parquet files and manifests are refused, checksum or not
+        let evil = temp_dir.path().join("evil.tar.zst");
+        let mut tar = TarWriter::new(
+            zstd::stream::write::Encoder::new(File::create(&evil).unwrap(), 3).unwrap(),
+        );
+        let info = SnapshotInfo {
+            version: "0".to_string(),
+            created_at: Utc::now(),
+            files: 1,
+        };
+        tar.append_bytes(SNAPSHOT_FILE, &serde_json::to_vec(&info).unwrap())
+            .unwrap();
+        let script = b"#!/bin/sh\n";
+        let mut writer = ChecksumWriter::new(std::io::sink());
+        writer.write_all(script).unwrap();
+        let (_, checksum) = writer.finish();
+        let manifest = checksum::manifest_contents([("run.sh".to_string(), checksum)]);
+        tar.append_bytes("logs/manifest.jsonl", manifest.as_bytes())
+            .unwrap();
+        tar.append_bytes("logs/run.sh", script).unwrap();
+        tar.finish().unwrap().finish().unwrap();
+        let elsewhere = temp_dir.path().join("elsewhere");
+        let error = restore(&evil, &elsewhere.join("logs"), &elsewhere.join("traces"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("Unexpected archive member"), "{}", error);
+        assert!(!elsewhere.join("logs/run.sh").exists());
+    }
+}
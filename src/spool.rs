@@ -0,0 +1,159 @@
+use anyhow::{Context, Result};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::schema::LogEntry;
+
+/// How a spool reports its activity
+#[derive(Debug, Clone, Copy)]
+pub struct SpoolMetrics {
+    /// Logs written to the spool
+    pub spooled: fn(u64),
+    /// Logs dropped because the spool was over its size
+    pub dropped: fn(u64),
+    /// Bytes and files in the spool
+    pub size: fn(u64, usize),
+}
+
+/// Batches of logs kept on disk, one JSON-lines file per batch named
+/// `spool-<sequence>.jsonl`, so they survive restarts
+pub struct Spool {
+    dir: PathBuf,
+    max_bytes: u64,
+    metrics: SpoolMetrics,
+    /// Sequence number and size of each file, oldest first
+    files: VecDeque<(u64, u64)>,
+    bytes: u64,
+}
+
+impl Spool {
+    /// Open the spool in `dir`, picking up the batches an earlier run left there
+    pub fn open(dir: &Path, max_bytes: u64, metrics: SpoolMetrics) -> Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create spool directory {:?}", dir))?;
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let sequence = name
+                .to_str()
+                .and_then(|name| name.strip_prefix("spool-")?.strip_suffix(".jsonl"))
+                .and_then(|sequence| sequence.parse::<u64>().ok());
+            if let Some(sequence) = sequence {
+                files.push((sequence, entry.metadata()?.len()));
+            }
+        }
+        files.sort_unstable();
+        let bytes = files.iter().map(|(_, size)| size).sum();
+        let spool = Self {
+            dir: dir.to_path_buf(),
+            max_bytes,
+            metrics,
+            files: files.into(),
+            bytes,
+        };
+        spool.record_size();
+        Ok(spool)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Batches in the spool
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, sequence: u64) -> PathBuf {
+        self.dir.join(format!("spool-{:020}.jsonl", sequence))
+    }
+
+    /// Write `logs` as the newest file, then drop the oldest files past `max_bytes`
+    pub fn append(&mut self, logs: &[LogEntry]) -> Result<()> {
+        let sequence = self.files.back().map_or(0, |(sequence, _)| sequence + 1);
+        let path = self.path(sequence);
+        let partial = path.with_extension("tmp");
+        let written = write_batch(&partial, logs).and_then(|()| {
+            std::fs::rename(&partial, &path)?;
+            Ok(std::fs::metadata(&path)?.len())
+        });
+        let size = match written {
+            Ok(size) => size,
+            Err(e) => {
+                // Most likely the disk is full; what was written only takes space
+                let _ = std::fs::remove_file(&partial);
+                return Err(e);
+            }
+        };
+        self.files.push_back((sequence, size));
+        self.bytes += size;
+        (self.metrics.spooled)(logs.len() as u64);
+        while self.bytes > self.max_bytes && self.files.len() > 1 {
+            let dropped = self.front()?.map_or(0, |logs| logs.len());
+            self.pop_front()?;
+            warn!(
+                "Spool {:?} over {} bytes: dropped the oldest {} log(s)",
+                self.dir, self.max_bytes, dropped
+            );
+            (self.metrics.dropped)(dropped as u64);
+        }
+        self.record_size();
+        Ok(())
+    }
+
+    /// Logs of the oldest file
+    pub fn front(&self) -> Result<Option<Vec<LogEntry>>> {
+        let Some((sequence, _)) = self.files.front() else {
+            return Ok(None);
+        };
+        let path = self.path(*sequence);
+        let file =
+            std::fs::File::open(&path).with_context(|| format!("Failed to read {:?}", path))?;
+        let mut logs = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            match serde_json::from_str(&line) {
+                Ok(log) => logs.push(log),
+                // A torn write from a crash; the rest of the file is still worth sending
+                // The error can quote the log, so only its position is logged
+                Err(e) => warn!(
+                    "Skipping unreadable spooled log in {:?}: {:?} error at column {}",
+                    path,
+                    e.classify(),
+                    e.column()
+                ),
+            }
+        }
+        Ok(Some(logs))
+    }
+
+    pub fn pop_front(&mut self) -> Result<()> {
+        if let Some((sequence, size)) = self.files.pop_front() {
+            std::fs::remove_file(self.path(sequence))?;
+            self.bytes -= size;
+            self.record_size();
+        }
+        Ok(())
+    }
+
+    fn record_size(&self) {
+        (self.metrics.size)(self.bytes, self.files.len());
+    }
+}
+
+fn write_batch(path: &Path, logs: &[LogEntry]) -> Result<()> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    for log in logs {
+        serde_json::to_writer(&mut file, log)?;
+        file.write_all(b"\n")?;
+    }
+    file.into_inner()?.sync_all()?;
+    Ok(())
+}
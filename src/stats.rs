@@ -0,0 +1,206 @@
+use std::fmt;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Process-wide mirrors of the main ingest counters.
+///
+/// The metrics facade cannot be read back, so these are kept alongside it for the SIGUSR1
+/// dump; they are updated even when the Prometheus endpoint is disabled.
+#[derive(Debug)]
+pub struct Stats {
+    started: Instant,
+    ingested: AtomicU64,
+    bytes_processed: AtomicU64,
+    dropped: AtomicU64,
+    validation_failures: AtomicU64,
+    active_connections: AtomicI64,
+    flushes: AtomicU64,
+    rows_written: AtomicU64,
+    bytes_written: AtomicU64,
+    /// Unix milliseconds of the last flush, 0 when none happened yet
+    last_flush_ms: AtomicI64,
+}
+
+/// Point-in-time copy of `Stats`, printed by SIGUSR1
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatsSnapshot {
+    pub uptime: Duration,
+    pub ingested: u64,
+    pub bytes_processed: u64,
+    pub dropped: u64,
+    pub validation_failures: u64,
+    pub active_connections: i64,
+    pub flushes: u64,
+    pub rows_written: u64,
+    pub bytes_written: u64,
+    pub last_flush: Option<SystemTime>,
+}
+
+/// The daemon's counters
+pub fn stats() -> &'static Stats {
+    static STATS: OnceLock<Stats> = OnceLock::new();
+    STATS.get_or_init(Stats::new)
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            ingested: AtomicU64::new(0),
+            bytes_processed: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            validation_failures: AtomicU64::new(0),
+            active_connections: AtomicI64::new(0),
+            flushes: AtomicU64::new(0),
+            rows_written: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+            last_flush_ms: AtomicI64::new(0),
+        }
+    }
+
+    pub fn record_ingested(&self, bytes: u64) {
+        self.ingested.fetch_add(1, Ordering::Relaxed);
+        self.bytes_processed.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn record_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_validation_failure(&self) {
+        self.validation_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_opened(&self) {
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_closed(&self) {
+        self.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn record_flush(&self, rows: u64, bytes: u64, at: SystemTime) {
+        self.flushes.fetch_add(1, Ordering::Relaxed);
+        self.rows_written.fetch_add(rows, Ordering::Relaxed);
+        self.bytes_written.fetch_add(bytes, Ordering::Relaxed);
+        let ms = at
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+        self.last_flush_ms.store(ms, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let last_flush_ms = self.last_flush_ms.load(Ordering::Relaxed);
+        StatsSnapshot {
+            uptime: self.started.elapsed(),
+            ingested: self.ingested.load(Ordering::Relaxed),
+            bytes_processed: self.bytes_processed.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            validation_failures: self.validation_failures.load(Ordering::Relaxed),
+            active_connections: self.active_connections.load(Ordering::Relaxed),
+            flushes: self.flushes.load(Ordering::Relaxed),
+            rows_written: self.rows_written.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            last_flush: (last_flush_ms > 0)
+                .then(|| UNIX_EPOCH + Duration::from_millis(last_flush_ms as u64)),
+        }
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `1.5 MiB` style sizes
+pub(crate) fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// `1h 2m 3s` style durations
+fn human_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, s) => format!("{}h {}m {}s", h, m, s),
+    }
+}
+
+impl fmt::Display for StatsSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let last_flush = match self.last_flush {
+            Some(at) => {
+                let ago = SystemTime::now().duration_since(at).unwrap_or_default();
+                format!(
+                    "{} ({} ago)",
+                    chrono::DateTime::<chrono::Utc>::from(at).to_rfc3339(),
+                    human_duration(ago)
+                )
+            }
+            None => "never".to_string(),
+        };
+
+        writeln!(f, "Daemon stats")?;
+        writeln!(f, "  uptime:              {}", human_duration(self.uptime))?;
+        writeln!(
+            f,
+            "  ingested:            {} logs ({})",
+            self.ingested,
+            human_bytes(self.bytes_processed)
+        )?;
+        writeln!(f, "  dropped:             {}", self.dropped)?;
+        writeln!(f, "  validation failures: {}", self.validation_failures)?;
+        writeln!(f, "  active connections:  {}", self.active_connections)?;
+        writeln!(
+            f,
+            "  flushes:             {} ({} rows, {} written)",
+            self.flushes,
+            self.rows_written,
+            human_bytes(self.bytes_written)
+        )?;
+        write!(f, "  last flush:          {}", last_flush)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_renders_counters() {
+        let stats = Stats::new();
+        stats.record_ingested(1536);
+        stats.record_ingested(512);
+        stats.record_dropped();
+        stats.connection_opened();
+        stats.connection_opened();
+        stats.connection_closed();
+        assert!(stats.snapshot().last_flush.is_none());
+        stats.record_flush(2, 4096, SystemTime::now());
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.ingested, 2);
+        assert_eq!(snapshot.active_connections, 1);
+        assert!(snapshot.last_flush.is_some());
+
+        let text = snapshot.to_string();
+        assert!(text.contains("ingested:            2 logs (2.0 KiB)"));
+        assert!(text.contains("flushes:             1 (2 rows, 4.0 KiB written)"));
+        assert_eq!(human_duration(Duration::from_secs(3723)), "1h 2m 3s");
+    }
+}
@@ -0,0 +1,347 @@
+use serde::Serialize;
+use std::path::Path;
+use std::sync::atomic::{
+    AtomicBool, AtomicI64, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering,
+};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::disk_watch::DiskPressure;
+
+/// Queue fill ratio at which ingestion is reported unhealthy
+const QUEUE_UNHEALTHY_RATIO: f64 = 0.9;
+
+/// Free space on the storage volume below which ingestion is reported unhealthy
+pub const MIN_FREE_DISK_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Storage errors newer than this make the daemon unhealthy
+const RECENT_ERROR_WINDOW: Duration = Duration::from_secs(60);
+
+/// Stands in for storage error messages in reports served without authentication, as they
+/// can hold paths and other internals
+const REDACTED_ERROR: &str = "storage write failed; see the daemon log";
+
+#[derive(Debug)]
+struct StatusInner {
+    started_ms: i64,
+    /// Whether the ingest socket is bound and accepting connections
+    listening: AtomicBool,
+    queue_depth: AtomicUsize,
+    queue_capacity: AtomicUsize,
+    ingested: AtomicU64,
+    dropped: AtomicU64,
+    storage_errors: AtomicU64,
+    /// Failed storage writes in a row
+    storage_failure_streak: AtomicU32,
+    /// Unix milliseconds of the last successful flush, 0 when none happened yet
+    last_flush_ms: AtomicI64,
+    last_error: Mutex<Option<(i64, String)>>,
+    /// `DiskPressure` set by the disk watchdog
+    disk_pressure: AtomicU8,
+}
+
+/// Live ingest and storage health, shared between the ingest thread and the API
+#[derive(Debug, Clone)]
+pub struct DaemonStatus {
+    inner: Arc<StatusInner>,
+}
+
+/// Snapshot served by `/api/status`
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema))]
+pub struct StatusReport {
+    /// `healthy` or `unhealthy`
+    pub status: &'static str,
+    /// Reasons for an unhealthy status
+    pub problems: Vec<String>,
+    pub uptime_secs: u64,
+    pub queue_depth: usize,
+    pub queue_capacity: usize,
+    pub ingested_total: u64,
+    pub dropped_total: u64,
+    pub last_flush: Option<String>,
+    pub storage_errors_total: u64,
+    /// Storage writes failed in a row, 0 once one succeeds
+    pub storage_failure_streak: u32,
+    pub last_storage_error: Option<String>,
+    pub last_storage_error_at: Option<String>,
+    pub disk_free_bytes: Option<u64>,
+    /// How far ingestion is degraded to spare the storage volume: `normal`, `shed_debug`,
+    /// `cleanup` or `reject`
+    pub disk_pressure: &'static str,
+}
+
+impl StatusReport {
+    pub fn is_healthy(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+fn unix_ms(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+fn rfc3339_ms(ms: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(ms)
+        .unwrap_or_default()
+        .to_rfc3339()
+}
+
+impl DaemonStatus {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(StatusInner {
+                started_ms: unix_ms(SystemTime::now()),
+                listening: AtomicBool::new(false),
+                queue_depth: AtomicUsize::new(0),
+                queue_capacity: AtomicUsize::new(0),
+                ingested: AtomicU64::new(0),
+                dropped: AtomicU64::new(0),
+                storage_errors: AtomicU64::new(0),
+                storage_failure_streak: AtomicU32::new(0),
+                last_flush_ms: AtomicI64::new(0),
+                last_error: Mutex::new(None),
+                disk_pressure: AtomicU8::new(0),
+            }),
+        }
+    }
+
+    pub fn set_listening(&self, listening: bool) {
+        self.inner.listening.store(listening, Ordering::Relaxed);
+    }
+
+    /// Whether the ingest socket is bound and accepting connections
+    pub fn is_listening(&self) -> bool {
+        self.inner.listening.load(Ordering::Relaxed)
+    }
+
+    fn queue_saturated(&self) -> Option<String> {
+        let depth = self.inner.queue_depth.load(Ordering::Relaxed);
+        let capacity = self.inner.queue_capacity.load(Ordering::Relaxed);
+        (capacity > 0 && depth as f64 >= capacity as f64 * QUEUE_UNHEALTHY_RATIO)
+            .then(|| format!("Ingest queue is {}/{} full", depth, capacity))
+    }
+
+    /// Reasons the daemon should not receive traffic yet; empty when ready
+    pub fn readiness_problems(&self, storage_dir: &Path) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !self.inner.listening.load(Ordering::Relaxed) {
+            problems.push("Ingest socket is not bound".to_string());
+        }
+        if !is_writable(storage_dir) {
+            problems.push(format!(
+                "Storage directory {:?} is not writable",
+                storage_dir
+            ));
+        }
+        problems.extend(self.queue_saturated());
+        problems
+    }
+
+    pub fn set_queue(&self, depth: usize, capacity: usize) {
+        self.inner.queue_depth.store(depth, Ordering::Relaxed);
+        self.inner.queue_capacity.store(capacity, Ordering::Relaxed);
+    }
+
+    pub fn record_ingested(&self) {
+        self.inner.ingested.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_dropped(&self) {
+        self.inner.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_flush(&self, at: SystemTime) {
+        self.inner
+            .last_flush_ms
+            .store(unix_ms(at), Ordering::Relaxed);
+    }
+
+    pub fn record_storage_error(&self, error: &anyhow::Error) {
+        self.inner.storage_errors.fetch_add(1, Ordering::Relaxed);
+        *self
+            .inner
+            .last_error
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) =
+            Some((unix_ms(SystemTime::now()), format!("{:#}", error)));
+    }
+
+    pub fn set_disk_pressure(&self, pressure: DiskPressure) {
+        self.inner
+            .disk_pressure
+            .store(pressure.as_u8(), Ordering::Relaxed);
+    }
+
+    pub fn disk_pressure(&self) -> DiskPressure {
+        DiskPressure::from_u8(self.inner.disk_pressure.load(Ordering::Relaxed))
+    }
+
+    pub fn set_storage_failure_streak(&self, streak: u32) {
+        self.inner
+            .storage_failure_streak
+            .store(streak, Ordering::Relaxed);
+    }
+
+    /// Evaluate health, checking free space on the volume holding `storage_dir`
+    pub fn report(&self, storage_dir: &Path) -> StatusReport {
+        self.report_at(SystemTime::now(), free_disk_bytes(storage_dir), true)
+    }
+
+    /// Like `report`, with storage error messages replaced by a generic one, for callers
+    /// that are not authenticated
+    pub fn public_report(&self, storage_dir: &Path) -> StatusReport {
+        self.report_at(SystemTime::now(), free_disk_bytes(storage_dir), false)
+    }
+
+    fn report_at(
+        &self,
+        now: SystemTime,
+        disk_free_bytes: Option<u64>,
+        error_details: bool,
+    ) -> StatusReport {
+        let inner = &self.inner;
+        let now_ms = unix_ms(now);
+        let queue_depth = inner.queue_depth.load(Ordering::Relaxed);
+        let queue_capacity = inner.queue_capacity.load(Ordering::Relaxed);
+        let last_flush_ms = inner.last_flush_ms.load(Ordering::Relaxed);
+        let storage_failure_streak = inner.storage_failure_streak.load(Ordering::Relaxed);
+        let last_error = inner
+            .last_error
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+            .map(|(at, message)| match error_details {
+                true => (at, message),
+                false => (at, REDACTED_ERROR.to_string()),
+            });
+
+        let mut problems: Vec<String> = self.queue_saturated().into_iter().collect();
+        if let Some(free) = disk_free_bytes.filter(|free| *free < MIN_FREE_DISK_BYTES) {
+            problems.push(format!("Only {} bytes free on the storage volume", free));
+        }
+        if self.disk_pressure() == DiskPressure::Reject {
+            problems.push("Rejecting logs until space is freed on the storage volume".to_string());
+        }
+        if let Some((at, message)) = &last_error {
+            if now_ms - at < RECENT_ERROR_WINDOW.as_millis() as i64 {
+                problems.push(format!("Recent storage error: {}", message));
+            }
+        }
+        if storage_failure_streak > 0 {
+            problems.push(format!(
+                "Storage writes failing: {} attempt(s) in a row",
+                storage_failure_streak
+            ));
+        }
+
+        StatusReport {
+            status: if problems.is_empty() {
+                "healthy"
+            } else {
+                "unhealthy"
+            },
+            problems,
+            uptime_secs: ((now_ms - inner.started_ms).max(0) / 1000) as u64,
+            queue_depth,
+            queue_capacity,
+            ingested_total: inner.ingested.load(Ordering::Relaxed),
+            dropped_total: inner.dropped.load(Ordering::Relaxed),
+            last_flush: (last_flush_ms > 0).then(|| rfc3339_ms(last_flush_ms)),
+            storage_errors_total: inner.storage_errors.load(Ordering::Relaxed),
+            storage_failure_streak,
+            last_storage_error: last_error.as_ref().map(|(_, m)| m.clone()),
+            last_storage_error_at: last_error.as_ref().map(|(at, _)| rfc3339_ms(*at)),
+            disk_free_bytes,
+            disk_pressure: self.disk_pressure().as_str(),
+        }
+    }
+}
+
+impl Default for DaemonStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Bytes available to unprivileged users on the filesystem holding `path`
+pub fn free_disk_bytes(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is NUL-terminated and `stat` is a valid out-pointer
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Whether the current user may create files in `path`
+pub fn is_writable(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: `path` is NUL-terminated
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_flags_full_queue_and_recent_errors() {
+        let status = DaemonStatus::new();
+        status.set_queue(10, 100);
+        let report = status.report_at(SystemTime::now(), Some(u64::MAX), true);
+        assert!(report.is_healthy());
+        assert!(report.last_flush.is_none());
+
+        status.set_queue(95, 100);
+        status.record_storage_error(&anyhow::anyhow!("disk full"));
+        let report = status.report_at(SystemTime::now(), Some(1024), true);
+        assert_eq!(report.status, "unhealthy");
+        assert_eq!(report.problems.len(), 3);
+        assert_eq!(report.last_storage_error.as_deref(), Some("disk full"));
+
+        // Error messages stay out of the unauthenticated report
+        let report = status.report_at(SystemTime::now(), Some(1024), false);
+        assert_eq!(report.problems.len(), 3);
+        assert!(report.problems.iter().all(|p| !p.contains("disk full")));
+        assert_eq!(report.last_storage_error.as_deref(), Some(REDACTED_ERROR));
+
+        // Old errors stop counting once the queue drains
+        status.set_queue(0, 100);
+        let later = SystemTime::now() + RECENT_ERROR_WINDOW * 2;
+        let report = status.report_at(later, Some(u64::MAX), true);
+        assert!(report.is_healthy());
+        assert_eq!(report.storage_errors_total, 1);
+
+        status.set_disk_pressure(DiskPressure::Reject);
+        let report = status.report_at(later, Some(u64::MAX), true);
+        assert_eq!((report.problems.len(), report.disk_pressure), (1, "reject"));
+    }
+
+    #[test]
+    fn test_readiness_requires_bound_socket_and_writable_storage() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let status = DaemonStatus::new();
+        status.set_queue(0, 100);
+        assert_eq!(status.readiness_problems(dir.path()).len(), 1);
+
+        status.set_listening(true);
+        assert!(status.readiness_problems(dir.path()).is_empty());
+        assert_eq!(
+            status.readiness_problems(&dir.path().join("missing")).len(),
+            1
+        );
+
+        status.set_queue(100, 100);
+        assert_eq!(status.readiness_problems(dir.path()).len(), 1);
+    }
+}
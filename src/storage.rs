@@ -1,16 +1,246 @@
-use anyhow::{Context, Result};
-use arrow::array::{ArrayRef, RecordBatch, StringBuilder, TimestampMillisecondArray};
-use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
-use chrono::{DateTime, Utc};
+use anyhow::{anyhow, bail, Context, Result};
+use arrow::array::{
+    Array, ArrayRef, AsArray, RecordBatch, StringBuilder, StringDictionaryBuilder,
+    TimestampMillisecondArray,
+};
+use arrow::datatypes::{DataType, Field, Int32Type, Schema, TimeUnit};
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
 use parquet::arrow::ArrowWriter;
-use parquet::basic::Compression;
+use parquet::basic::{Compression, GzipLevel, ZstdLevel};
 use parquet::file::properties::WriterProperties;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
 use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tracing::{debug, info};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
 
+use crate::checksum::{self, ChecksumWriter};
+use crate::file_metadata::FileMetadata;
 use crate::schema::LogEntry;
+use crate::spool::{Spool, SpoolMetrics};
+
+/// Partition of logs that arrived after their own partition closed
+pub const LATE_PARTITION: &str = "late";
+
+/// Event-time partitioning of log files (the `[partitioning]` section of the config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartitionConfig {
+    /// Write each log to the partition of its own timestamp instead of the one it arrived in
+    #[serde(default)]
+    pub by_event_time: bool,
+
+    /// Length of a partition
+    #[serde(default)]
+    pub granularity: PartitionGranularity,
+
+    /// How long after a partition ends logs for it are still written to it; older logs go to
+    /// the `late` partition
+    #[serde(default = "default_lateness_secs")]
+    pub lateness_secs: u64,
+}
+
+impl Default for PartitionConfig {
+    fn default() -> Self {
+        Self {
+            by_event_time: false,
+            granularity: PartitionGranularity::default(),
+            lateness_secs: default_lateness_secs(),
+        }
+    }
+}
+
+fn default_lateness_secs() -> u64 {
+    3600
+}
+
+/// Length of an event-time partition, in UTC
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionGranularity {
+    Hour,
+    #[default]
+    Day,
+}
+
+impl PartitionConfig {
+    /// Group `logs` by partition at `now`, keeping their order within each partition
+    pub fn split(
+        &self,
+        logs: Vec<LogEntry>,
+        now: DateTime<Utc>,
+    ) -> BTreeMap<String, Vec<LogEntry>> {
+        let mut partitions: BTreeMap<String, Vec<LogEntry>> = BTreeMap::new();
+        for log in logs {
+            let timestamp = DateTime::parse_from_rfc3339(&log.timestamp)
+                .map(|dt| dt.with_timezone(&Utc))
+                .unwrap_or(now);
+            partitions
+                .entry(self.partition(timestamp, now))
+                .or_default()
+                .push(log);
+        }
+        partitions
+    }
+
+    /// Name of the partition a log with `timestamp` goes to at `now`
+    pub fn partition(&self, timestamp: DateTime<Utc>, now: DateTime<Utc>) -> String {
+        let (length, format) = match self.granularity {
+            PartitionGranularity::Hour => (TimeDelta::hours(1), "%Y-%m-%dT%H"),
+            PartitionGranularity::Day => (TimeDelta::days(1), "%Y-%m-%d"),
+        };
+        let start = timestamp.duration_trunc(length).unwrap_or(timestamp);
+        let closes = start + length + TimeDelta::seconds(self.lateness_secs as i64);
+        if closes < now {
+            LATE_PARTITION.to_string()
+        } else {
+            start.format(format).to_string()
+        }
+    }
+}
+
+/// What happens while storage writes fail, e.g. on a full disk (the `[write_failures]`
+/// section of the config file)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WriteFailureConfig {
+    /// Wait after the first failed write before trying again; doubled after every further one
+    #[serde(default = "default_retry_secs")]
+    pub retry_secs: u64,
+
+    /// Upper bound on the wait between attempts
+    #[serde(default = "default_max_retry_secs")]
+    pub max_retry_secs: u64,
+
+    /// Keep the logs waiting for a write here, so they survive a restart; best on another
+    /// volume than `storage_dir`. Without it they are kept in memory only
+    #[serde(default)]
+    pub wal_dir: Option<PathBuf>,
+
+    /// Write-ahead log size past which the oldest logs in it are dropped
+    #[serde(default = "default_max_wal_bytes")]
+    pub max_wal_bytes: u64,
+
+    /// Logs kept in memory while writes fail and the write-ahead log is missing or failing;
+    /// past this the oldest are dropped, down to nine tenths of it
+    #[serde(default = "default_max_buffered_logs")]
+    pub max_buffered_logs: usize,
+}
+
+impl Default for WriteFailureConfig {
+    fn default() -> Self {
+        Self {
+            retry_secs: default_retry_secs(),
+            max_retry_secs: default_max_retry_secs(),
+            wal_dir: None,
+            max_wal_bytes: default_max_wal_bytes(),
+            max_buffered_logs: default_max_buffered_logs(),
+        }
+    }
+}
+
+fn default_retry_secs() -> u64 {
+    1
+}
+
+fn default_max_retry_secs() -> u64 {
+    60
+}
+
+fn default_max_wal_bytes() -> u64 {
+    1024 * 1024 * 1024
+}
+
+fn default_max_buffered_logs() -> usize {
+    100_000
+}
+
+impl WriteFailureConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.retry_secs == 0 || self.max_wal_bytes == 0 || self.max_buffered_logs == 0 {
+            bail!(
+                "write_failures.retry_secs, max_wal_bytes and max_buffered_logs must be greater than 0"
+            );
+        }
+        if self.max_retry_secs < self.retry_secs {
+            bail!("write_failures.max_retry_secs must be at least retry_secs");
+        }
+        Ok(())
+    }
+}
+
+const WAL_METRICS: SpoolMetrics = SpoolMetrics {
+    spooled: crate::metrics::record_storage_wal_logs,
+    dropped: |logs| crate::metrics::record_storage_dropped("wal_full", logs),
+    size: crate::metrics::record_storage_wal_size,
+};
+
+/// Failed writes in a row and when to try again
+struct WriteFailures {
+    config: WriteFailureConfig,
+    wal: Option<Spool>,
+    streak: u32,
+    retry_at: Option<Instant>,
+}
+
+impl WriteFailures {
+    fn new(config: WriteFailureConfig) -> Result<Self> {
+        let wal = match &config.wal_dir {
+            Some(dir) => {
+                let wal = Spool::open(dir, config.max_wal_bytes, WAL_METRICS)?;
+                if !wal.is_empty() {
+                    info!(
+                        "{} batch(es) in the write-ahead log {:?} from an earlier run will be stored",
+                        wal.len(),
+                        dir
+                    );
+                }
+                Some(wal)
+            }
+            None => None,
+        };
+        Ok(Self {
+            config,
+            wal,
+            streak: 0,
+            retry_at: None,
+        })
+    }
+
+    fn backing_off(&self) -> bool {
+        self.retry_at.is_some_and(|at| Instant::now() < at)
+    }
+
+    fn record(&mut self, result: &Result<()>) {
+        match result {
+            Ok(()) if self.streak > 0 => {
+                info!(
+                    "Storage writes recovered after {} failed attempt(s)",
+                    self.streak
+                );
+                self.streak = 0;
+                self.retry_at = None;
+            }
+            Ok(()) => return,
+            Err(_) => {
+                let backoff = Duration::from_secs(
+                    self.config
+                        .retry_secs
+                        .saturating_mul(1 << self.streak.min(16))
+                        .min(self.config.max_retry_secs),
+                );
+                self.streak += 1;
+                self.retry_at = Some(Instant::now() + backoff);
+                warn!(
+                    "Storage write failed {} time(s) in a row; trying again in {:?}",
+                    self.streak, backoff
+                );
+            }
+        }
+        crate::metrics::record_storage_failure_streak(self.streak);
+    }
+}
 
 /// Storage engine for writing logs to Parquet files
 pub struct StorageEngine {
@@ -24,6 +254,47 @@ pub struct StorageEngine {
     current_file_path: Option<PathBuf>,
     current_file_size: u64,
     file_counter: u64,
+    last_flush: Option<std::time::SystemTime>,
+    /// Arrow schema shared by every batch
+    schema: Arc<Schema>,
+    /// Sizes of the last batch's columns, to size the next batch's builders up front
+    column_sizes: ColumnSizes,
+    partitioning: PartitionConfig,
+    /// Time that event-time partitions are closed against
+    clock: fn() -> DateTime<Utc>,
+    failures: WriteFailures,
+}
+
+/// Strings and their total bytes in each string column, in schema order after the timestamp:
+/// one string per row in plain columns, one per distinct value in dictionary columns
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct ColumnSizes([(usize, usize); 6]);
+
+impl ColumnSizes {
+    fn of(batch: &RecordBatch) -> Self {
+        let mut sizes = [(0, 0); 6];
+        for (i, size) in sizes.iter_mut().enumerate() {
+            let column = batch.column(i + 1);
+            let strings = match column.as_dictionary_opt::<Int32Type>() {
+                Some(dictionary) => dictionary.values().as_string::<i32>(),
+                None => column.as_string::<i32>(),
+            };
+            *size = (strings.len(), strings.value_data().len());
+        }
+        Self(sizes)
+    }
+
+    /// A builder for plain column `i` with room for `rows` rows like the last batch's
+    fn builder(&self, i: usize, rows: usize) -> StringBuilder {
+        let (strings, bytes) = self.0[i];
+        StringBuilder::with_capacity(rows, bytes.div_ceil(strings.max(1)) * rows)
+    }
+
+    /// A builder for dictionary column `i` with room for the last batch's distinct values
+    fn dictionary_builder(&self, i: usize, rows: usize) -> StringDictionaryBuilder<Int32Type> {
+        let (strings, bytes) = self.0[i];
+        StringDictionaryBuilder::with_capacity(rows, strings, bytes)
+    }
 }
 
 impl StorageEngine {
@@ -46,14 +317,38 @@ impl StorageEngine {
             current_file_path: None,
             current_file_size: 0,
             file_counter: 0,
+            last_flush: None,
+            schema: Self::create_schema(),
+            column_sizes: ColumnSizes::default(),
+            partitioning: PartitionConfig::default(),
+            clock: Utc::now,
+            failures: WriteFailures::new(WriteFailureConfig::default())?,
         })
     }
 
+    /// Split each flush into one file per event-time partition
+    pub fn with_partitioning(mut self, partitioning: PartitionConfig) -> Self {
+        self.partitioning = partitioning;
+        self
+    }
+
+    #[cfg(test)]
+    fn with_clock(mut self, clock: fn() -> DateTime<Utc>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Retry failed writes as `config` says, keeping the logs in its write-ahead log meanwhile;
+    /// logs left there by an earlier run are stored first
+    pub fn with_write_failures(mut self, config: WriteFailureConfig) -> Result<Self> {
+        self.failures = WriteFailures::new(config)?;
+        Ok(self)
+    }
+
     /// Add a log entry to the current batch
     #[tracing::instrument(skip(self, log), fields(batch_size = self.current_batch.len()))]
     pub fn add_log(&mut self, log: LogEntry) -> Result<()> {
         self.current_batch.push(log);
-        metrics::counter!(crate::metrics::INGEST_COUNT, 1);
 
         // Flush if batch is full
         if self.current_batch.len() >= self.batch_size {
@@ -63,33 +358,127 @@ impl StorageEngine {
         Ok(())
     }
 
-    /// Flush the current batch to disk
+    /// Failed writes in a row, 0 once one succeeds
+    pub fn failure_streak(&self) -> u32 {
+        self.failures.streak
+    }
+
+    /// Logs buffered for the next flush
+    pub fn pending(&self) -> usize {
+        self.current_batch.len()
+    }
+
+    /// Time of the last batch written to disk
+    pub fn last_flush(&self) -> Option<std::time::SystemTime> {
+        self.last_flush
+    }
+
+    /// Flush the current batch to disk; after a failed write, only once the retry wait is
+    /// over, moving the batch to the write-ahead log until then
     #[tracing::instrument(skip(self), fields(batch_size = self.current_batch.len()))]
     pub fn flush(&mut self) -> Result<()> {
-        if self.current_batch.is_empty() {
+        if self.failures.backing_off() {
+            self.hold();
             return Ok(());
         }
+        self.flush_now()
+    }
 
-        debug!("Flushing {} logs to Parquet", self.current_batch.len());
-        let start = std::time::Instant::now();
-
-        // Always generate a new file for each batch to ensure valid Parquet
-        // (Appending to Parquet requires keeping writer open or complex merging)
-        let file_path = self.generate_file_path();
-
-        // Convert logs to RecordBatch
-        let batch = self.logs_to_record_batch(&self.current_batch)?;
-        let _num_rows = batch.num_rows();
+    /// Flush the write-ahead log and the current batch to disk now, even while waiting to
+    /// retry a failed write
+    pub fn flush_now(&mut self) -> Result<()> {
+        let wal_empty = self.failures.wal.as_ref().is_none_or(Spool::is_empty);
+        if self.current_batch.is_empty() && wal_empty {
+            return Ok(());
+        }
+        let result = self.write_pending();
+        self.failures.record(&result);
+        if result.is_err() {
+            self.hold();
+        }
+        result
+    }
 
-        // Write to Parquet
-        self.write_record_batch(&file_path, batch)?;
+    /// Write the logs in the write-ahead log, oldest first, then the current batch
+    fn write_pending(&mut self) -> Result<()> {
+        while let Some(logs) = self
+            .failures
+            .wal
+            .as_ref()
+            .map(Spool::front)
+            .transpose()?
+            .flatten()
+        {
+            let count = logs.len();
+            if let Err((unwritten, e)) = self.write_logs(logs) {
+                // A batch written in part leaves the WAL so its written partitions are not
+                // written twice; the rest waits in memory
+                if unwritten.len() < count {
+                    self.current_batch.splice(0..0, unwritten);
+                    self.failures
+                        .wal
+                        .as_mut()
+                        .map(Spool::pop_front)
+                        .transpose()?;
+                }
+                return Err(e);
+            }
+            self.failures
+                .wal
+                .as_mut()
+                .map(Spool::pop_front)
+                .transpose()?;
+        }
+        if !self.current_batch.is_empty() {
+            let logs =
+                std::mem::replace(&mut self.current_batch, Vec::with_capacity(self.batch_size));
+            if let Err((unwritten, e)) = self.write_logs(logs) {
+                self.current_batch = unwritten;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
 
-        let elapsed = start.elapsed().as_millis() as u64;
-        metrics::histogram!(crate::metrics::WRITE_LATENCY, elapsed as f64);
-        metrics::counter!(crate::metrics::BYTES_PROCESSED, self.current_file_size); // Approximate increment
+    /// Write `logs` to disk; on failure, hand back the logs not written
+    #[tracing::instrument(
+        name = "storage.write",
+        skip_all,
+        fields(rows = logs.len(), services = tracing::field::Empty)
+    )]
+    fn write_logs(&mut self, logs: Vec<LogEntry>) -> Result<(), (Vec<LogEntry>, anyhow::Error)> {
+        debug!("Flushing {} logs to Parquet", logs.len());
+        let span = tracing::Span::current();
+        if !span.is_disabled() {
+            let mut services: BTreeMap<&str, u64> = BTreeMap::new();
+            for log in &logs {
+                *services
+                    .entry(
+                        log.service
+                            .as_deref()
+                            .unwrap_or(crate::metrics::UNKNOWN_SERVICE_LABEL),
+                    )
+                    .or_default() += 1;
+            }
+            span.record(
+                "services",
+                crate::metrics::service_summary(services).as_str(),
+            );
+        }
 
-        // Clear the current batch
-        self.current_batch.clear();
+        if self.partitioning.by_event_time {
+            let mut partitions = self.partitioning.split(logs, (self.clock)()).into_iter();
+            while let Some((partition, logs)) = partitions.next() {
+                if let Err(e) = self.write_batch(&logs, Some(&partition)) {
+                    let mut unwritten = logs;
+                    unwritten.extend(partitions.flat_map(|(_, logs)| logs));
+                    return Err((unwritten, e));
+                }
+            }
+        } else if let Err(e) = self.write_batch(&logs, None) {
+            return Err((logs, e));
+        }
+        self.last_flush = Some(std::time::SystemTime::now());
 
         // Reset file path tracking (we don't keep files open across batches currently)
         self.current_file_path = None;
@@ -98,6 +487,39 @@ impl StorageEngine {
         Ok(())
     }
 
+    /// Keep the current batch until the next attempt: in the write-ahead log when there is
+    /// one, otherwise in memory up to `max_buffered_logs`. Past that the oldest are dropped down
+    /// to nine tenths of it at once, so logs added meanwhile do not shift the batch each time
+    fn hold(&mut self) {
+        if self.current_batch.is_empty() {
+            return;
+        }
+        if let Some(wal) = &mut self.failures.wal {
+            match wal.append(&self.current_batch) {
+                Ok(()) => {
+                    self.current_batch.clear();
+                    return;
+                }
+                Err(e) => warn!(
+                    "Failed to write {} log(s) to the write-ahead log {:?}: {:#}",
+                    self.current_batch.len(),
+                    wal.dir(),
+                    e
+                ),
+            }
+        }
+        let max = self.failures.config.max_buffered_logs;
+        if self.current_batch.len() > max {
+            let excess = self.current_batch.len() - (max - max / 10);
+            self.current_batch.drain(..excess);
+            warn!(
+                "Storage writes failing: dropped the oldest {} buffered log(s)",
+                excess
+            );
+            crate::metrics::record_storage_dropped("buffer_full", excess as u64);
+        }
+    }
+
     /*
     /// Check if the current file should be rotated
     fn should_rotate(&self) -> bool {
@@ -113,11 +535,55 @@ impl StorageEngine {
     }
     */
 
-    /// Generate a new file path with timestamp
-    fn generate_file_path(&mut self) -> PathBuf {
+    /// Write `logs` to a new file, named after `partition` when partitioning by event time
+    fn write_batch(&mut self, logs: &[LogEntry], partition: Option<&str>) -> Result<()> {
+        let start = std::time::Instant::now();
+
+        // Always generate a new file for each batch to ensure valid Parquet
+        // (Appending to Parquet requires keeping writer open or complex merging)
+        let file_path = self.generate_file_path(partition);
+
+        // Convert logs to RecordBatch
+        let batch = self.logs_to_record_batch(logs)?;
+        let num_rows = batch.num_rows();
+        self.column_sizes = ColumnSizes::of(&batch);
+
+        let timestamps = batch
+            .column(0)
+            .as_primitive::<arrow::datatypes::TimestampMillisecondType>();
+        let mut metadata = FileMetadata::new("logs")
+            .with_writer("compression", compression_name(self.compression))
+            .with_writer("batch_size", self.batch_size)
+            .with_time_range(
+                arrow::compute::min(timestamps).map(|ms| ms * 1000),
+                arrow::compute::max(timestamps).map(|ms| ms * 1000),
+            )
+            .with_schema_versions(logs.iter().filter_map(|log| log.schema_version.as_deref()))
+            .with_services(logs.iter().map(|log| log.service.as_deref()))
+            .with_levels(logs.iter().map(|log| &*log.level));
+        if let Some(partition) = partition {
+            metadata = metadata.with_writer("partition", partition);
+        }
+
+        // Write to Parquet
+        let write_start = std::time::Instant::now();
+        self.write_record_batch(&file_path, batch, &metadata)?;
+        crate::metrics::record_write_latency(write_start.elapsed().as_secs_f64() * 1000.0);
+
+        crate::metrics::record_flush(num_rows, start.elapsed(), self.current_file_size);
+        Ok(())
+    }
+
+    /// Generate a new file path with timestamp, after the partition when there is one
+    fn generate_file_path(&mut self, partition: Option<&str>) -> PathBuf {
         let now = Utc::now();
+        let prefix = match partition {
+            Some(partition) => format!("logs_{}", partition),
+            None => "logs".to_string(),
+        };
         let filename = format!(
-            "logs_{}_{}.parquet",
+            "{}_{}_{}.parquet",
+            prefix,
             now.format("%Y%m%d_%H%M%S_%3f"),
             self.file_counter
         );
@@ -127,13 +593,15 @@ impl StorageEngine {
 
     /// Convert JSON logs to Arrow RecordBatch
     fn logs_to_record_batch(&self, logs: &[LogEntry]) -> Result<RecordBatch> {
-        let schema = self.create_schema();
-        let mut timestamp_builder = Vec::with_capacity(logs.len());
-        let mut level_builder = StringBuilder::new();
-        let mut message_builder = StringBuilder::new();
-        let mut service_builder = StringBuilder::new();
-        let mut trace_id_builder = StringBuilder::new();
-        let mut metadata_builder = StringBuilder::new();
+        let rows = logs.len();
+        let sizes = self.column_sizes;
+        let mut timestamp_builder = Vec::with_capacity(rows);
+        let mut level_builder = sizes.dictionary_builder(0, rows);
+        let mut message_builder = sizes.builder(1, rows);
+        let mut service_builder = sizes.dictionary_builder(2, rows);
+        let mut trace_id_builder = sizes.builder(3, rows);
+        let mut metadata_builder = sizes.builder(4, rows);
+        let mut schema_version_builder = sizes.builder(5, rows);
 
         for log in logs {
             // Timestamp
@@ -144,17 +612,13 @@ impl StorageEngine {
             timestamp_builder.push(timestamp);
 
             // Level
-            level_builder.append_value(&log.level);
+            level_builder.append_value(&*log.level);
 
             // Message
             message_builder.append_value(&log.message);
 
             // Optional fields
-            if let Some(s) = &log.service {
-                service_builder.append_value(s);
-            } else {
-                service_builder.append_null();
-            }
+            service_builder.append_option(log.service.as_deref());
 
             if let Some(t) = &log.trace_id {
                 trace_id_builder.append_value(t);
@@ -163,11 +627,15 @@ impl StorageEngine {
             }
 
             // Metadata
+            // Written straight into the column instead of through a String per row
             if let Some(m) = &log.metadata {
-                metadata_builder.append_value(m.to_string());
+                write!(metadata_builder, "{}", m).context("Failed to encode metadata")?;
+                metadata_builder.append_value("");
             } else {
                 metadata_builder.append_null();
             }
+
+            schema_version_builder.append_option(log.schema_version.as_deref());
         }
 
         // Build arrays
@@ -178,9 +646,10 @@ impl StorageEngine {
         let service_array = Arc::new(service_builder.finish()) as ArrayRef;
         let trace_id_array = Arc::new(trace_id_builder.finish()) as ArrayRef;
         let metadata_array = Arc::new(metadata_builder.finish()) as ArrayRef;
+        let schema_version_array = Arc::new(schema_version_builder.finish()) as ArrayRef;
 
         RecordBatch::try_new(
-            schema,
+            self.schema.clone(),
             vec![
                 timestamp_array,
                 level_array,
@@ -188,38 +657,60 @@ impl StorageEngine {
                 service_array,
                 trace_id_array,
                 metadata_array,
+                schema_version_array,
             ],
         )
         .context("Failed to create RecordBatch")
     }
 
     /// Create Arrow schema for log entries
-    fn create_schema(&self) -> Arc<Schema> {
+    fn create_schema() -> Arc<Schema> {
         Arc::new(Schema::new(vec![
             Field::new(
                 "timestamp",
                 DataType::Timestamp(TimeUnit::Millisecond, None),
                 false,
             ),
-            Field::new("level", DataType::Utf8, false),
+            // Few distinct values, so stored once per batch with a small index per row
+            Field::new("level", dictionary(), false),
             Field::new("message", DataType::Utf8, false),
-            Field::new("service", DataType::Utf8, true),
+            Field::new("service", dictionary(), true),
             Field::new("trace_id", DataType::Utf8, true),
             Field::new("metadata", DataType::Utf8, true),
+            Field::new("schema_version", DataType::Utf8, true),
         ]))
     }
 
     /// Write RecordBatch to Parquet file
-    fn write_record_batch(&mut self, path: &Path, batch: RecordBatch) -> Result<()> {
+    fn write_record_batch(
+        &mut self,
+        path: &Path,
+        batch: RecordBatch,
+        metadata: &FileMetadata,
+    ) -> Result<()> {
         let file = File::create(path)?;
 
         let props = WriterProperties::builder()
             .set_compression(self.compression)
+            .set_key_value_metadata(Some(metadata.key_values()))
             .build();
 
-        let mut writer = ArrowWriter::try_new(file, batch.schema(), Some(props))?;
-        writer.write(&batch)?;
-        writer.close()?;
+        let written = ArrowWriter::try_new(ChecksumWriter::new(file), batch.schema(), Some(props))
+            .and_then(|mut writer| {
+                writer.write(&batch)?;
+                writer.into_inner()
+            });
+        let checksum = match written {
+            Ok(writer) => writer.finish().1,
+            Err(e) => {
+                // A truncated file would break every query over the directory
+                let _ = std::fs::remove_file(path);
+                return Err(e.into());
+            }
+        };
+        if let Err(e) = checksum::record(path, checksum) {
+            warn!("Failed to record checksum of {:?}: {:#}", path, e);
+        }
 
         // Update file size
         let metadata = std::fs::metadata(path)?;
@@ -252,20 +743,66 @@ impl StorageEngine {
 impl Drop for StorageEngine {
     fn drop(&mut self) {
         // Flush any remaining logs on drop
-        if let Err(e) = self.flush() {
+        if let Err(e) = self.flush_now() {
             eprintln!("Error flushing logs on drop: {}", e);
         }
     }
 }
 
+/// Type of the `level` and `service` columns
+fn dictionary() -> DataType {
+    DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8))
+}
+
 /// Parse compression string to Parquet Compression enum
 pub fn parse_compression(s: &str) -> Compression {
-    match s.to_lowercase().as_str() {
-        "snappy" => Compression::SNAPPY,
-        "zstd" => Compression::ZSTD(Default::default()),
-        "gzip" => Compression::GZIP(Default::default()),
-        "none" | "uncompressed" => Compression::UNCOMPRESSED,
-        _ => Compression::SNAPPY, // default
+    try_parse_compression(s).unwrap_or(Compression::SNAPPY)
+}
+
+/// Parse a codec with an optional level, `codec[:level]`: zstd takes 1-22, gzip 0-10
+pub fn try_parse_compression(s: &str) -> Result<Compression> {
+    let s = s.to_lowercase();
+    let (codec, level) = match s.split_once(':') {
+        Some((codec, level)) => {
+            let level = level
+                .parse::<u32>()
+                .with_context(|| format!("Invalid compression level: {}", level))?;
+            (codec, Some(level))
+        }
+        None => (s.as_str(), None),
+    };
+
+    Ok(match (codec, level) {
+        ("snappy", None) => Compression::SNAPPY,
+        ("zstd", level) => Compression::ZSTD(match level {
+            Some(level) => ZstdLevel::try_new(level as i32)
+                .map_err(|_| anyhow!("zstd levels are 1-22, got {}", level))?,
+            None => ZstdLevel::default(),
+        }),
+        ("gzip", level) => Compression::GZIP(match level {
+            Some(level) => GzipLevel::try_new(level)
+                .map_err(|_| anyhow!("gzip levels are 0-10, got {}", level))?,
+            None => GzipLevel::default(),
+        }),
+        ("none" | "uncompressed", None) => Compression::UNCOMPRESSED,
+        ("snappy" | "none" | "uncompressed", Some(_)) => {
+            bail!("{} has no compression levels", codec)
+        }
+        _ => bail!(
+            "Invalid compression codec: {}. Must be one of: snappy, zstd, gzip, none",
+            codec
+        ),
+    })
+}
+
+/// The codec as `try_parse_compression` takes it, e.g. `zstd:3`
+pub fn compression_name(compression: Compression) -> String {
+    match compression {
+        Compression::SNAPPY => "snappy".to_string(),
+        Compression::ZSTD(level) => format!("zstd:{}", level.compression_level()),
+        Compression::GZIP(level) => format!("gzip:{}", level.compression_level()),
+        Compression::UNCOMPRESSED => "none".to_string(),
+        other => other.to_string().to_lowercase(),
     }
 }
 
@@ -275,6 +812,110 @@ mod tests {
     use serde_json::json;
     use tempfile::TempDir;
 
+    #[test]
+    fn test_event_time_partitions() {
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        let now = at("2026-01-16T00:30:00Z");
+        let mut partitioning = PartitionConfig {
+            by_event_time: true,
+            granularity: PartitionGranularity::Day,
+            lateness_secs: 3600,
+        };
+        assert_eq!(
+            partitioning.partition(at("2026-01-16T00:10:00Z"), now),
+            "2026-01-16"
+        );
+        // Yesterday is still open within the lateness window
+        assert_eq!(
+            partitioning.partition(at("2026-01-15T23:59:00+00:00"), now),
+            "2026-01-15"
+        );
+        assert_eq!(
+            partitioning.partition(at("2026-01-14T12:00:00Z"), now),
+            LATE_PARTITION
+        );
+        partitioning.granularity = PartitionGranularity::Hour;
+        assert_eq!(
+            partitioning.partition(at("2026-01-16T01:10:00+01:00"), now),
+            "2026-01-16T00"
+        );
+        assert_eq!(
+            partitioning.partition(at("2026-01-15T22:59:00Z"), now),
+            LATE_PARTITION
+        );
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut engine = StorageEngine::new(
+            temp_dir.path().to_path_buf(),
+            Compression::SNAPPY,
+            10,
+            1024 * 1024,
+        )
+        .unwrap()
+        .with_partitioning(PartitionConfig {
+            by_event_time: true,
+            ..Default::default()
+        })
+        .with_clock(|| "2026-01-15T19:00:00Z".parse().unwrap());
+        for timestamp in [
+            "2026-01-15T18:59:00Z",
+            "2020-01-01T00:00:00Z",
+            "2026-01-15T18:30:00Z",
+        ] {
+            let log: LogEntry = serde_json::from_value(json!({
+                "timestamp": timestamp,
+                "level": "info",
+                "message": "partitioned"
+            }))
+            .unwrap();
+            engine.add_log(log).unwrap();
+        }
+        engine.flush().unwrap();
+
+        let names: Vec<String> = engine
+            .list_files()
+            .unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names.len(), 2, "{:?}", names);
+        assert!(names[0].starts_with("logs_2026-01-15_"), "{:?}", names);
+        assert!(names[1].starts_with("logs_late_"));
+        let rows = |i: usize| {
+            crate::query::QueryEngine::new(temp_dir.path().to_path_buf())
+                .read_file(&engine.list_files().unwrap()[i])
+                .unwrap()[0]
+                .num_rows()
+        };
+        assert_eq!((rows(0), rows(1)), (2, 1));
+    }
+
+    #[test]
+    fn test_compression_levels() {
+        assert_eq!(
+            try_parse_compression("zstd:7").unwrap(),
+            Compression::ZSTD(ZstdLevel::try_new(7).unwrap())
+        );
+        assert_eq!(
+            try_parse_compression("GZIP:9").unwrap(),
+            Compression::GZIP(GzipLevel::try_new(9).unwrap())
+        );
+        assert_eq!(
+            try_parse_compression("zstd").unwrap(),
+            Compression::ZSTD(ZstdLevel::default())
+        );
+        for invalid in [
+            "zstd:0",
+            "zstd:23",
+            "gzip:11",
+            "zstd:high",
+            "snappy:3",
+            "lz5",
+        ] {
+            assert!(try_parse_compression(invalid).is_err(), "{}", invalid);
+        }
+    }
+
     #[test]
     fn test_storage_engine_basic() {
         let temp_dir = TempDir::new().unwrap();
@@ -299,4 +940,171 @@ mod tests {
         let files = engine.list_files().unwrap();
         assert_eq!(files.len(), 1);
     }
+
+    #[test]
+    fn test_levels_and_services_stored_as_dictionaries() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut engine = StorageEngine::new(
+            temp_dir.path().to_path_buf(),
+            Compression::SNAPPY,
+            10,
+            1024 * 1024,
+        )
+        .unwrap();
+
+        for (level, service) in [("info", "api"), ("error", "api"), ("info", "web")] {
+            let log: LogEntry = serde_json::from_value(json!({
+                "timestamp": "2026-01-15T19:00:00Z",
+                "level": level,
+                "service": service,
+                "message": "Test log"
+            }))
+            .unwrap();
+            engine.add_log(log).unwrap();
+        }
+        engine.flush().unwrap();
+
+        let batches = crate::query::QueryEngine::new(temp_dir.path().to_path_buf())
+            .read_all()
+            .unwrap();
+        let schema = batches[0].schema();
+        for name in ["level", "service"] {
+            assert_eq!(
+                schema.field_with_name(name).unwrap().data_type(),
+                &dictionary()
+            );
+        }
+        let records = crate::query::records_from_batch(&batches[0]).unwrap();
+        let levels: Vec<_> = records.iter().map(|r| r.level.as_str()).collect();
+        assert_eq!(levels, ["info", "error", "info"]);
+    }
+
+    #[test]
+    fn test_builders_sized_after_previous_batch() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut engine = StorageEngine::new(
+            temp_dir.path().to_path_buf(),
+            Compression::SNAPPY,
+            10,
+            1024 * 1024,
+        )
+        .unwrap();
+
+        let log: LogEntry = serde_json::from_value(json!({
+            "timestamp": "2026-01-15T19:00:00Z",
+            "level": "info",
+            "message": "Test log"
+        }))
+        .unwrap();
+        engine.add_log(log).unwrap();
+        engine.flush().unwrap();
+
+        // The next batch's builders are sized after this one
+        assert_eq!(
+            engine.column_sizes,
+            ColumnSizes([(1, 4), (1, 8), (0, 0), (1, 0), (1, 0), (1, 0)])
+        );
+
+        // Metadata still round-trips after a batch that had none
+        let log: LogEntry = serde_json::from_value(json!({
+            "timestamp": "2026-01-15T19:00:01Z",
+            "level": "warn",
+            "message": "With metadata",
+            "metadata": {"user": "a"}
+        }))
+        .unwrap();
+        engine.add_log(log).unwrap();
+        engine.flush().unwrap();
+        let batches = crate::query::QueryEngine::new(temp_dir.path().to_path_buf())
+            .read_all()
+            .unwrap();
+        let metadata: Vec<_> = batches
+            .iter()
+            .flat_map(|batch| batch.column(5).as_string::<i32>().iter())
+            .collect();
+        assert!(metadata.contains(&None));
+        assert!(metadata.contains(&Some(r#"{"user":"a"}"#)));
+    }
+
+    #[test]
+    fn test_failed_writes_wait_in_the_wal() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage_dir = temp_dir.path().join("logs");
+        let config = WriteFailureConfig {
+            retry_secs: 60,
+            max_retry_secs: 60,
+            wal_dir: Some(temp_dir.path().join("wal")),
+            ..WriteFailureConfig::default()
+        };
+        let open = || {
+            StorageEngine::new(storage_dir.clone(), Compression::SNAPPY, 2, 1024 * 1024)
+                .unwrap()
+                .with_write_failures(config.clone())
+                .unwrap()
+        };
+        let log = |i: u32| -> LogEntry {
+            serde_json::from_value(json!({
+                "timestamp": "2026-01-15T19:00:00Z",
+                "level": "info",
+                "message": format!("m{}", i)
+            }))
+            .unwrap()
+        };
+
+        // Nothing can be created in a storage directory that is a file
+        let mut engine = open();
+        std::fs::remove_dir(&storage_dir).unwrap();
+        std::fs::write(&storage_dir, "").unwrap();
+        engine.add_log(log(0)).unwrap();
+        assert!(engine.add_log(log(1)).is_err());
+        assert_eq!(engine.failure_streak(), 1);
+        // While waiting to retry, flushing only moves logs to the WAL
+        engine.add_log(log(2)).unwrap();
+        engine.flush().unwrap();
+        assert_eq!(engine.failure_streak(), 1);
+        assert_eq!(engine.pending(), 0);
+        assert_eq!(engine.failures.wal.as_ref().unwrap().len(), 2);
+        assert!(engine.flush_now().is_err());
+        assert_eq!(engine.failure_streak(), 2);
+        drop(engine);
+
+        // A restart stores what the WAL kept, oldest first
+        std::fs::remove_file(&storage_dir).unwrap();
+        let mut engine = open();
+        engine.flush_now().unwrap();
+        assert_eq!(engine.failure_streak(), 0);
+        assert!(engine.failures.wal.as_ref().unwrap().is_empty());
+        let files = engine.list_files().unwrap();
+        assert_eq!(files.len(), 2);
+        let rows: usize = crate::query::QueryEngine::new(storage_dir.clone())
+            .read_all()
+            .unwrap()
+            .iter()
+            .map(|batch| batch.num_rows())
+            .sum();
+        assert_eq!(rows, 3);
+
+        // Without a WAL, logs past max_buffered_logs are dropped while writes fail
+        let mut engine =
+            StorageEngine::new(storage_dir.clone(), Compression::SNAPPY, 2, 1024 * 1024)
+                .unwrap()
+                .with_write_failures(WriteFailureConfig {
+                    retry_secs: 60,
+                    max_retry_secs: 60,
+                    max_buffered_logs: 3,
+                    ..WriteFailureConfig::default()
+                })
+                .unwrap();
+        std::fs::remove_dir_all(&storage_dir).unwrap();
+        std::fs::write(&storage_dir, "").unwrap();
+        for i in 0..6 {
+            let _ = engine.add_log(log(i));
+        }
+        assert_eq!(engine.pending(), 3);
+        assert_eq!(engine.current_batch[0].message, "m3");
+        std::fs::remove_file(&storage_dir).unwrap();
+        std::fs::create_dir(&storage_dir).unwrap();
+        engine.flush_now().unwrap();
+        assert_eq!(engine.list_files().unwrap().len(), 1);
+    }
 }
@@ -0,0 +1,213 @@
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::trace_storage::{SpanStatus, TraceSpan};
+
+/// Span attribute naming the service that emitted the span
+const SERVICE_NAME_ATTRIBUTE: &str = "service.name";
+
+/// Service reported for spans without a `service.name` attribute
+const UNKNOWN_SERVICE: &str = "unknown_service";
+
+fn service_name(span: &TraceSpan) -> &str {
+    span.attributes
+        .get(SERVICE_NAME_ATTRIBUTE)
+        .map(String::as_str)
+        .unwrap_or(UNKNOWN_SERVICE)
+}
+
+/// Attributes sorted by key so exports are stable
+fn sorted(attributes: &HashMap<String, String>) -> BTreeMap<&str, &str> {
+    attributes
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect()
+}
+
+fn otlp_attributes(attributes: &HashMap<String, String>) -> Vec<Value> {
+    sorted(attributes)
+        .into_iter()
+        .filter(|(key, _)| *key != SERVICE_NAME_ATTRIBUTE)
+        .map(|(key, value)| json!({ "key": key, "value": { "stringValue": value } }))
+        .collect()
+}
+
+fn unix_nanos(time: &chrono::DateTime<chrono::Utc>) -> String {
+    // OTLP/JSON encodes 64-bit integers as strings
+    time.timestamp_nanos_opt().unwrap_or_default().to_string()
+}
+
+/// Re-encode spans as an OTLP/JSON `TracesData` document, one resource per service
+pub fn to_otlp_json(spans: &[TraceSpan]) -> Value {
+    let mut by_service: BTreeMap<&str, Vec<&TraceSpan>> = BTreeMap::new();
+    for span in spans {
+        by_service.entry(service_name(span)).or_default().push(span);
+    }
+
+    let resource_spans: Vec<Value> = by_service
+        .into_iter()
+        .map(|(service, spans)| {
+            let spans: Vec<Value> = spans
+                .into_iter()
+                .map(|span| {
+                    let (code, message) = match &span.status {
+                        SpanStatus::Ok => (1, ""),
+                        SpanStatus::Error { message } => (2, message.as_str()),
+                    };
+                    json!({
+                        "traceId": span.trace_id,
+                        "spanId": span.span_id,
+                        "parentSpanId": span.parent_span_id.clone().unwrap_or_default(),
+                        "name": span.name,
+                        "kind": 1,
+                        "startTimeUnixNano": unix_nanos(&span.start_time),
+                        "endTimeUnixNano": unix_nanos(&span.end_time),
+                        "attributes": otlp_attributes(&span.attributes),
+                        "events": span.events.iter().map(|event| json!({
+                            "timeUnixNano": unix_nanos(&event.timestamp),
+                            "name": event.name,
+                            "attributes": otlp_attributes(&event.attributes),
+                        })).collect::<Vec<_>>(),
+                        "status": { "code": code, "message": message },
+                    })
+                })
+                .collect();
+
+            json!({
+                "resource": {
+                    "attributes": [
+                        { "key": SERVICE_NAME_ATTRIBUTE, "value": { "stringValue": service } }
+                    ]
+                },
+                "scopeSpans": [{ "scope": { "name": "daemon_rs" }, "spans": spans }],
+            })
+        })
+        .collect();
+
+    json!({ "resourceSpans": resource_spans })
+}
+
+fn jaeger_tags(attributes: &HashMap<String, String>) -> Vec<Value> {
+    sorted(attributes)
+        .into_iter()
+        .filter(|(key, _)| *key != SERVICE_NAME_ATTRIBUTE)
+        .map(|(key, value)| json!({ "key": key, "type": "string", "value": value }))
+        .collect()
+}
+
+/// Re-encode spans in the Jaeger query API JSON format accepted by the Jaeger UI upload
+pub fn to_jaeger_json(trace_id: &str, spans: &[TraceSpan]) -> Value {
+    let mut processes: BTreeMap<&str, String> = BTreeMap::new();
+    for span in spans {
+        let next_id = format!("p{}", processes.len() + 1);
+        processes.entry(service_name(span)).or_insert(next_id);
+    }
+
+    let jaeger_spans: Vec<Value> = spans
+        .iter()
+        .map(|span| {
+            let mut tags = jaeger_tags(&span.attributes);
+            if let SpanStatus::Error { message } = &span.status {
+                tags.push(json!({ "key": "error", "type": "bool", "value": true }));
+                tags.push(json!({ "key": "otel.status_description", "type": "string", "value": message }));
+            }
+
+            json!({
+                "traceID": span.trace_id,
+                "spanID": span.span_id,
+                "operationName": span.name,
+                "references": span.parent_span_id.iter().map(|parent| json!({
+                    "refType": "CHILD_OF",
+                    "traceID": span.trace_id,
+                    "spanID": parent,
+                })).collect::<Vec<_>>(),
+                "startTime": span.start_time.timestamp_micros(),
+                "duration": span.duration_us,
+                "tags": tags,
+                "logs": span.events.iter().map(|event| {
+                    let mut fields = jaeger_tags(&event.attributes);
+                    fields.insert(0, json!({ "key": "event", "type": "string", "value": event.name }));
+                    json!({ "timestamp": event.timestamp.timestamp_micros(), "fields": fields })
+                }).collect::<Vec<_>>(),
+                "processID": processes[service_name(span)],
+                "warnings": null,
+            })
+        })
+        .collect();
+
+    let processes: serde_json::Map<String, Value> = processes
+        .into_iter()
+        .map(|(service, id)| (id, json!({ "serviceName": service, "tags": [] })))
+        .collect();
+
+    json!({
+        "data": [{
+            "traceID": trace_id,
+            "spans": jaeger_spans,
+            "processes": processes,
+            "warnings": null,
+        }]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_exports_keep_hierarchy_status_and_service() {
+        let now = Utc::now();
+        let mut attributes = HashMap::new();
+        attributes.insert("service.name".to_string(), "checkout".to_string());
+        attributes.insert("http.method".to_string(), "GET".to_string());
+        let spans = vec![
+            TraceSpan {
+                trace_id: "4bf92f3577b34da6a3ce929d0e0e4736".to_string(),
+                span_id: "00f067aa0ba902b7".to_string(),
+                parent_span_id: None,
+                name: "GET /cart".to_string(),
+                start_time: now,
+                end_time: now,
+                duration_us: 1500,
+                attributes: attributes.clone(),
+                events: Vec::new(),
+                status: SpanStatus::Ok,
+            },
+            TraceSpan {
+                trace_id: "4bf92f3577b34da6a3ce929d0e0e4736".to_string(),
+                span_id: "b7ad6b7169203331".to_string(),
+                parent_span_id: Some("00f067aa0ba902b7".to_string()),
+                name: "db.query".to_string(),
+                start_time: now,
+                end_time: now,
+                duration_us: 500,
+                attributes,
+                events: Vec::new(),
+                status: SpanStatus::Error {
+                    message: "timeout".to_string(),
+                },
+            },
+        ];
+
+        let otlp = to_otlp_json(&spans);
+        let resource = &otlp["resourceSpans"][0];
+        assert_eq!(
+            resource["resource"]["attributes"][0]["value"]["stringValue"],
+            "checkout"
+        );
+        let otlp_spans = &resource["scopeSpans"][0]["spans"];
+        assert_eq!(otlp_spans[1]["parentSpanId"], "00f067aa0ba902b7");
+        assert_eq!(otlp_spans[1]["status"]["code"], 2);
+        assert_eq!(otlp_spans[0]["attributes"].as_array().unwrap().len(), 1);
+
+        let jaeger = to_jaeger_json("4bf92f3577b34da6a3ce929d0e0e4736", &spans);
+        let trace = &jaeger["data"][0];
+        assert_eq!(trace["processes"]["p1"]["serviceName"], "checkout");
+        assert_eq!(
+            trace["spans"][1]["references"][0]["spanID"],
+            "00f067aa0ba902b7"
+        );
+        assert_eq!(trace["spans"][1]["tags"][1]["key"], "error");
+    }
+}
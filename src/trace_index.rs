@@ -0,0 +1,519 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, warn};
+
+use crate::live::LiveFeed;
+use crate::trace_storage::{
+    dedup_spans, list_trace_files, scan_spans, SpanFilter, SpanStatus, TraceSpan,
+};
+
+/// Summary of a trace for listing
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "api", derive(utoipa::ToSchema))]
+pub struct TraceSummary {
+    pub trace_id: String,
+    pub root_span_name: String,
+    pub start_time: String,
+    #[serde(skip)]
+    pub start_time_us: i64,
+    pub total_duration_ms: f64,
+    pub span_count: usize,
+    pub error_count: usize,
+}
+
+/// Default interval between background index refreshes
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Shortest time between on-demand refreshes for the same incomplete or unknown trace
+pub const ON_DEMAND_REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Traces tracked for on-demand refreshes at once
+const MAX_ON_DEMAND_TRACES: usize = 4096;
+
+/// Span used to describe a trace in its summary
+#[derive(Debug, Clone)]
+struct HeadSpan {
+    name: String,
+    start_time: DateTime<Utc>,
+    duration_us: u64,
+}
+
+impl HeadSpan {
+    fn from_span(span: &TraceSpan) -> Self {
+        Self {
+            name: span.name.clone(),
+            start_time: span.start_time,
+            duration_us: span.duration_us,
+        }
+    }
+}
+
+/// Per-trace aggregate that can be merged across files
+#[derive(Debug, Clone, Default)]
+struct TraceAggregate {
+    span_count: usize,
+    error_count: usize,
+    /// Span without a parent, if it has been stored
+    root: Option<HeadSpan>,
+    /// Earliest span, used when the root span is missing
+    earliest: Option<HeadSpan>,
+}
+
+impl TraceAggregate {
+    fn add_span(&mut self, span: &TraceSpan) {
+        self.span_count += 1;
+        if matches!(span.status, SpanStatus::Error { .. }) {
+            self.error_count += 1;
+        }
+        if span.parent_span_id.is_none() && self.root.is_none() {
+            self.root = Some(HeadSpan::from_span(span));
+        }
+        if self
+            .earliest
+            .as_ref()
+            .is_none_or(|e| span.start_time < e.start_time)
+        {
+            self.earliest = Some(HeadSpan::from_span(span));
+        }
+    }
+
+    fn merge(&mut self, other: &TraceAggregate) {
+        self.span_count += other.span_count;
+        self.error_count += other.error_count;
+        if self.root.is_none() {
+            self.root = other.root.clone();
+        }
+        if let Some(theirs) = &other.earliest {
+            if self
+                .earliest
+                .as_ref()
+                .is_none_or(|e| theirs.start_time < e.start_time)
+            {
+                self.earliest = Some(theirs.clone());
+            }
+        }
+    }
+
+    fn to_summary(&self, trace_id: &str) -> Option<TraceSummary> {
+        let head = self.root.as_ref().or(self.earliest.as_ref())?;
+
+        Some(TraceSummary {
+            trace_id: trace_id.to_string(),
+            root_span_name: head.name.clone(),
+            start_time: head.start_time.to_rfc3339(),
+            start_time_us: head.start_time.timestamp_micros(),
+            total_duration_ms: head.duration_us as f64 / 1000.0,
+            span_count: self.span_count,
+            error_count: self.error_count,
+        })
+    }
+}
+
+/// Summary of a fully loaded trace, matching what the index reports for it
+pub fn summarize_trace(trace_id: &str, spans: &[TraceSpan]) -> Option<TraceSummary> {
+    let mut aggregate = TraceAggregate::default();
+    for span in spans {
+        aggregate.add_span(span);
+    }
+    aggregate.to_summary(trace_id)
+}
+
+/// Indexed contents of a single trace file
+#[derive(Debug)]
+struct FileEntry {
+    modified: Option<SystemTime>,
+    len: u64,
+    /// Earliest and latest span start time in the file (microseconds), used to prune scans
+    min_start_us: i64,
+    max_start_us: i64,
+    traces: HashMap<String, TraceAggregate>,
+}
+
+/// Modification state of a set of trace files, used for HTTP cache validators
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilesVersion {
+    /// Latest modification time among the files
+    pub modified: SystemTime,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Default)]
+struct IndexState {
+    files: HashMap<PathBuf, FileEntry>,
+    summaries: Vec<TraceSummary>,
+}
+
+/// In-memory index of trace summaries, refreshed incrementally from the trace directory
+pub struct TraceIndex {
+    storage_dir: PathBuf,
+    state: RwLock<IndexState>,
+    /// When an on-demand refresh last ran for each recently requested trace
+    on_demand: Mutex<HashMap<String, Instant>>,
+}
+
+impl TraceIndex {
+    pub fn new(storage_dir: PathBuf) -> Self {
+        Self {
+            storage_dir,
+            state: RwLock::new(IndexState::default()),
+            on_demand: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether an on-demand refresh for `trace_id` may run at `now`: at most one per trace
+    /// every `ON_DEMAND_REFRESH_INTERVAL`, and none while `MAX_ON_DEMAND_TRACES` others are
+    /// waiting theirs out, so requests for unknown traces cannot keep the index rescanning
+    pub fn claim_refresh(&self, trace_id: &str, now: Instant) -> bool {
+        let mut recent = self.on_demand.lock().unwrap_or_else(|e| e.into_inner());
+        recent.retain(|_, at| now.duration_since(*at) < ON_DEMAND_REFRESH_INTERVAL);
+        if recent.contains_key(trace_id) || recent.len() >= MAX_ON_DEMAND_TRACES {
+            return false;
+        }
+        recent.insert(trace_id.to_string(), now);
+        true
+    }
+
+    pub fn storage_dir(&self) -> &Path {
+        &self.storage_dir
+    }
+
+    /// Re-scan the trace directory, reading only new or changed files.
+    ///
+    /// Returns the summaries of traces that appeared in those files.
+    pub fn refresh(&self) -> Result<Vec<TraceSummary>> {
+        let paths = list_trace_files(&self.storage_dir)?;
+
+        // Work out which files need (re)reading without holding the lock during IO
+        let stale: Vec<(PathBuf, Option<SystemTime>, u64)> = {
+            let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+            paths
+                .iter()
+                .filter_map(|path| {
+                    let meta = std::fs::metadata(path).ok()?;
+                    let modified = meta.modified().ok();
+                    match state.files.get(path) {
+                        Some(entry) if entry.modified == modified && entry.len == meta.len() => {
+                            None
+                        }
+                        _ => Some((path.clone(), modified, meta.len())),
+                    }
+                })
+                .collect()
+        };
+
+        let mut loaded = Vec::with_capacity(stale.len());
+        for (path, modified, len) in stale {
+            let mut traces: HashMap<String, TraceAggregate> = HashMap::new();
+            let (mut min_start_us, mut max_start_us) = (i64::MAX, i64::MIN);
+            let scanned = scan_spans(&path, &SpanFilter::default(), |span| {
+                let start_us = span.start_time.timestamp_micros();
+                min_start_us = min_start_us.min(start_us);
+                max_start_us = max_start_us.max(start_us);
+                match traces.get_mut(&span.trace_id) {
+                    Some(aggregate) => aggregate.add_span(&span),
+                    None => traces
+                        .entry(span.trace_id.clone())
+                        .or_default()
+                        .add_span(&span),
+                }
+                ControlFlow::Continue(())
+            });
+            match scanned {
+                Ok(()) => loaded.push((
+                    path,
+                    FileEntry {
+                        modified,
+                        len,
+                        min_start_us,
+                        max_start_us,
+                        traces,
+                    },
+                )),
+                Err(e) => warn!("Skipping unreadable trace file {:?}: {}", path, e),
+            }
+        }
+
+        let updated: std::collections::HashSet<String> = loaded
+            .iter()
+            .flat_map(|(_, entry)| entry.traces.keys().cloned())
+            .collect();
+
+        let mut state = self.state.write().unwrap_or_else(|e| e.into_inner());
+        let changed = !loaded.is_empty() || state.files.len() != paths.len();
+        state.files.retain(|path, _| paths.contains(path));
+        for (path, entry) in loaded {
+            state.files.insert(path, entry);
+        }
+
+        if changed {
+            state.summaries = build_summaries(&state.files);
+            debug!(
+                "Trace index refreshed: {} files, {} traces",
+                state.files.len(),
+                state.summaries.len()
+            );
+        }
+
+        Ok(state
+            .summaries
+            .iter()
+            .filter(|s| updated.contains(&s.trace_id))
+            .cloned()
+            .collect())
+    }
+
+    /// Snapshot of all indexed trace summaries
+    pub fn summaries(&self) -> Vec<TraceSummary> {
+        self.state
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .summaries
+            .clone()
+    }
+
+    /// Files known to contain spans of the given trace
+    pub fn files_for_trace(&self, trace_id: &str) -> Vec<PathBuf> {
+        let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+        let mut files: Vec<PathBuf> = state
+            .files
+            .iter()
+            .filter(|(_, entry)| entry.traces.contains_key(trace_id))
+            .map(|(path, _)| path.clone())
+            .collect();
+        files.sort();
+        files
+    }
+
+    /// Version of the files holding `trace_id`, or of every indexed file when `None`
+    pub fn files_version(&self, trace_id: Option<&str>) -> Option<FilesVersion> {
+        let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+        let mut version: Option<FilesVersion> = None;
+        for entry in state.files.values() {
+            if trace_id.is_some_and(|id| !entry.traces.contains_key(id)) {
+                continue;
+            }
+            let modified = entry.modified.unwrap_or(SystemTime::UNIX_EPOCH);
+            let v = version.get_or_insert(FilesVersion {
+                modified,
+                files: 0,
+                bytes: 0,
+            });
+            v.modified = v.modified.max(modified);
+            v.files += 1;
+            v.bytes += entry.len;
+        }
+        version
+    }
+
+    /// Visit the spans in the indexed files that pass `filter`, streaming each file.
+    ///
+    /// Files that cannot hold a matching span, going by their traces and start times, are
+    /// skipped without being opened.
+    pub fn for_each_span(
+        &self,
+        filter: &SpanFilter,
+        mut visit: impl FnMut(&TraceSpan),
+    ) -> Result<()> {
+        let mut files: Vec<PathBuf> = {
+            let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+            state
+                .files
+                .iter()
+                .filter(|(_, entry)| {
+                    filter.overlaps(entry.min_start_us, entry.max_start_us)
+                        && entry.traces.keys().any(|id| filter.wants_trace(id))
+                })
+                .map(|(path, _)| path.clone())
+                .collect()
+        };
+        files.sort();
+
+        for path in files {
+            scan_spans(&path, filter, |span| {
+                visit(&span);
+                ControlFlow::Continue(())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Load every span of a trace, reading only the files that contain it
+    pub fn load_trace(&self, trace_id: &str) -> Result<Vec<TraceSpan>> {
+        let filter = SpanFilter::trace(trace_id);
+        let mut spans = Vec::new();
+        for path in self.files_for_trace(trace_id) {
+            scan_spans(&path, &filter, |span| {
+                spans.push(span);
+                ControlFlow::Continue(())
+            })?;
+        }
+        // A retried export can leave a span in two files
+        dedup_spans(&mut spans);
+        Ok(spans)
+    }
+}
+
+/// Merge per-file aggregates into one summary per trace
+fn build_summaries(files: &HashMap<PathBuf, FileEntry>) -> Vec<TraceSummary> {
+    let mut merged: HashMap<&str, TraceAggregate> = HashMap::new();
+    for entry in files.values() {
+        for (trace_id, aggregate) in &entry.traces {
+            merged
+                .entry(trace_id.as_str())
+                .or_default()
+                .merge(aggregate);
+        }
+    }
+
+    merged
+        .iter()
+        .filter_map(|(trace_id, aggregate)| aggregate.to_summary(trace_id))
+        .collect()
+}
+
+/// Refresh the index on a fixed interval in the background, publishing new traces
+pub fn spawn_refresh_task(index: Arc<TraceIndex>, interval: Duration, live: LiveFeed) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let index = index.clone();
+            match tokio::task::spawn_blocking(move || index.refresh()).await {
+                Ok(Ok(updated)) => {
+                    for summary in updated {
+                        live.publish_trace(summary);
+                    }
+                }
+                Ok(Err(e)) => warn!("Trace index refresh failed: {}", e),
+                Err(e) => warn!("Trace index refresh panicked: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace_storage::TraceStorage;
+    use parquet::basic::Compression;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_on_demand_refreshes_are_debounced_per_trace() {
+        let index = TraceIndex::new(PathBuf::from("/nonexistent"));
+        let start = Instant::now();
+        assert!(index.claim_refresh("t1", start));
+        assert!(!index.claim_refresh("t1", start + Duration::from_millis(500)));
+        assert!(index.claim_refresh("t2", start + Duration::from_millis(500)));
+        assert!(index.claim_refresh("t1", start + ON_DEMAND_REFRESH_INTERVAL));
+
+        // Lookups of ever new unknown ids stop refreshing once enough are waiting
+        let later = start + ON_DEMAND_REFRESH_INTERVAL;
+        for n in 0..MAX_ON_DEMAND_TRACES {
+            index.claim_refresh(&format!("unknown-{}", n), later);
+        }
+        assert!(!index.claim_refresh("one-more", later));
+        assert!(index.claim_refresh("one-more", later + ON_DEMAND_REFRESH_INTERVAL));
+    }
+
+    fn span(trace_id: &str, span_id: &str, parent: Option<&str>) -> TraceSpan {
+        let now = Utc::now();
+        TraceSpan {
+            trace_id: trace_id.to_string(),
+            span_id: span_id.to_string(),
+            parent_span_id: parent.map(str::to_string),
+            name: format!("op-{}", span_id),
+            start_time: now,
+            end_time: now,
+            duration_us: 1500,
+            attributes: HashMap::new(),
+            events: Vec::new(),
+            status: SpanStatus::Ok,
+        }
+    }
+
+    #[test]
+    fn test_index_merges_traces_across_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut storage =
+            TraceStorage::new(temp_dir.path().to_path_buf(), Compression::SNAPPY, 100).unwrap();
+
+        storage.add_span(span("t1", "root", None)).unwrap();
+        storage.flush().unwrap();
+        storage.add_span(span("t1", "child", Some("root"))).unwrap();
+        storage.add_span(span("t2", "other", None)).unwrap();
+        storage.flush().unwrap();
+
+        let index = TraceIndex::new(temp_dir.path().to_path_buf());
+        index.refresh().unwrap();
+
+        let mut summaries = index.summaries();
+        summaries.sort_by(|a, b| a.trace_id.cmp(&b.trace_id));
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].span_count, 2);
+        assert_eq!(summaries[0].root_span_name, "op-root");
+        assert_eq!(index.files_for_trace("t1").len(), 2);
+        assert_eq!(index.load_trace("t1").unwrap().len(), 2);
+
+        assert_eq!(index.files_version(Some("t1")).unwrap().files, 2);
+        assert_eq!(index.files_version(Some("t2")).unwrap().files, 1);
+        assert_eq!(index.files_version(None).unwrap().files, 2);
+        assert!(index.files_version(Some("missing")).is_none());
+    }
+
+    #[test]
+    fn test_filtered_scans_read_only_matching_spans() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut storage =
+            TraceStorage::new(temp_dir.path().to_path_buf(), Compression::SNAPPY, 5000).unwrap();
+
+        // More rows than one scan batch, an hour apart per trace
+        let base = Utc::now() - chrono::Duration::hours(10);
+        for i in 0..3000 {
+            let mut s = span(&format!("t{}", i % 3), &format!("s{}", i), None);
+            s.start_time = base + chrono::Duration::hours(i % 3);
+            storage.add_span(s).unwrap();
+        }
+        storage.flush().unwrap();
+        let index = TraceIndex::new(temp_dir.path().to_path_buf());
+        index.refresh().unwrap();
+
+        let count = |filter: &SpanFilter| {
+            let mut seen = 0;
+            index.for_each_span(filter, |_| seen += 1).unwrap();
+            seen
+        };
+        assert_eq!(count(&SpanFilter::default()), 3000);
+        assert_eq!(count(&SpanFilter::trace("t1")), 1000);
+        assert_eq!(count(&SpanFilter::trace("missing")), 0);
+        let since = (base + chrono::Duration::hours(1)).timestamp_micros();
+        assert_eq!(count(&SpanFilter::since(Some(since))), 2000);
+        let window = SpanFilter {
+            trace_ids: Some(["t0".to_string(), "t1".to_string()].into()),
+            start_us: Some(since),
+            end_us: Some(since + 1),
+        };
+        assert_eq!(count(&window), 1000);
+        assert_eq!(index.load_trace("t2").unwrap().len(), 1000);
+
+        let path = &index.files_for_trace("t0")[0];
+        let mut taken = 0;
+        scan_spans(path, &SpanFilter::default(), |_| {
+            taken += 1;
+            if taken == 10 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })
+        .unwrap();
+        assert_eq!(taken, 10);
+    }
+}
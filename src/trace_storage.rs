@@ -2,15 +2,26 @@ use anyhow::{Context, Result};
 use arrow::array::{ArrayRef, RecordBatch, StringBuilder, TimestampMicrosecondArray, UInt64Array};
 use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
 use chrono::{DateTime, Utc};
-use parquet::arrow::ArrowWriter;
+use parquet::arrow::arrow_reader::{
+    ArrowPredicate, ArrowPredicateFn, ParquetRecordBatchReaderBuilder, RowFilter,
+};
+use parquet::arrow::{ArrowWriter, ProjectionMask};
 use parquet::basic::Compression;
+use parquet::file::metadata::ParquetMetaData;
 use parquet::file::properties::WriterProperties;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::File;
+use std::ops::ControlFlow;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tracing::{debug, info};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::checksum::{self, ChecksumWriter};
+use crate::file_metadata::FileMetadata;
+use crate::redaction::AttributeRedactor;
+use crate::storage::compression_name;
 
 /// Represents a single span in a distributed trace
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,6 +51,206 @@ pub enum SpanStatus {
     Error { message: String },
 }
 
+/// Attribute recording how many attributes a span or event lost to `max_attributes`
+pub const DROPPED_ATTRIBUTES: &str = "dropped_attributes_count";
+
+/// Attribute recording how many events a span lost to `max_events`
+pub const DROPPED_EVENTS: &str = "dropped_events_count";
+
+/// Caps on what one stored span may hold (the `[trace_limits]` section of the config file)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpanLimits {
+    /// Attributes kept per span and per event, by key order; the others are dropped and
+    /// counted in `dropped_attributes_count`
+    #[serde(default = "default_max_attributes")]
+    pub max_attributes: usize,
+
+    /// Events kept per span, the earliest first; the others are counted in
+    /// `dropped_events_count`
+    #[serde(default = "default_max_events")]
+    pub max_events: usize,
+
+    /// Bytes kept of an attribute value; a longer value is cut and ends with its full size
+    #[serde(default = "default_max_value_bytes")]
+    pub max_value_bytes: usize,
+}
+
+impl Default for SpanLimits {
+    fn default() -> Self {
+        Self {
+            max_attributes: default_max_attributes(),
+            max_events: default_max_events(),
+            max_value_bytes: default_max_value_bytes(),
+        }
+    }
+}
+
+fn default_max_attributes() -> usize {
+    128
+}
+
+fn default_max_events() -> usize {
+    128
+}
+
+fn default_max_value_bytes() -> usize {
+    4096
+}
+
+impl SpanLimits {
+    pub fn validate(&self) -> Result<()> {
+        if self.max_attributes == 0 {
+            anyhow::bail!("trace_limits.max_attributes must be greater than 0");
+        }
+        if self.max_value_bytes == 0 {
+            anyhow::bail!("trace_limits.max_value_bytes must be greater than 0");
+        }
+        Ok(())
+    }
+
+    /// Cut `span` down to the limits
+    pub fn apply(&self, span: &mut TraceSpan) {
+        self.limit_attributes(&mut span.attributes);
+        if span.events.len() > self.max_events {
+            let dropped = span.events.len() - self.max_events;
+            span.events.sort_by_key(|event| event.timestamp);
+            span.events.truncate(self.max_events);
+            span.attributes
+                .insert(DROPPED_EVENTS.to_string(), dropped.to_string());
+            crate::metrics::record_span_truncated("events", dropped as u64);
+        }
+        for event in &mut span.events {
+            self.limit_attributes(&mut event.attributes);
+        }
+    }
+
+    fn limit_attributes(&self, attributes: &mut HashMap<String, String>) {
+        if attributes.len() > self.max_attributes {
+            let mut keys: Vec<String> = attributes.keys().cloned().collect();
+            keys.sort_unstable();
+            for key in &keys[self.max_attributes..] {
+                attributes.remove(key);
+            }
+            let dropped = keys.len() - self.max_attributes;
+            attributes.insert(DROPPED_ATTRIBUTES.to_string(), dropped.to_string());
+            crate::metrics::record_span_truncated("attributes", dropped as u64);
+        }
+        for value in attributes.values_mut() {
+            if value.len() > self.max_value_bytes {
+                let len = value.len();
+                let mut end = self.max_value_bytes;
+                while !value.is_char_boundary(end) {
+                    end -= 1;
+                }
+                value.truncate(end);
+                value.push_str(&format!("...({} bytes)", len));
+                crate::metrics::record_span_truncated("value_bytes", 1);
+            }
+        }
+    }
+}
+
+/// Rows per row group of co-located trace files; lookups by trace id skip the others
+const GROUPED_ROW_GROUP_ROWS: usize = 1024;
+
+/// Spans held back at most in co-location mode; past it, the traces held longest are written
+/// as they are
+const MAX_PENDING_SPANS: usize = 100_000;
+
+/// Traces held back at most in co-location mode
+const MAX_PENDING_TRACES: usize = 10_000;
+
+/// Spans held back until their trace is complete, in co-location mode
+struct TraceGrouping {
+    timeout: Duration,
+    pending: HashMap<String, PendingTrace>,
+    /// Trace ids in the order they were first held, with the generation they were held as; ids
+    /// of traces written since are skipped
+    order: VecDeque<(String, u64)>,
+    generation: u64,
+    /// Spans held in `pending`
+    spans: usize,
+}
+
+struct PendingTrace {
+    spans: Vec<TraceSpan>,
+    /// Position of each span in `spans` by span id
+    index: HashMap<String, usize>,
+    since: Instant,
+    generation: u64,
+}
+
+impl TraceGrouping {
+    /// Whether `trace_id` is still held as `generation`
+    fn holds(&self, trace_id: &str, generation: u64) -> bool {
+        self.pending
+            .get(trace_id)
+            .is_some_and(|trace| trace.generation == generation)
+    }
+
+    /// Take the trace held longest, if any
+    fn pop_oldest(&mut self) -> Option<PendingTrace> {
+        while let Some((trace_id, generation)) = self.order.pop_front() {
+            if self.holds(&trace_id, generation) {
+                return self.remove(&trace_id);
+            }
+        }
+        None
+    }
+
+    /// Take the trace held longest if it was held for the timeout
+    fn pop_expired(&mut self) -> Option<PendingTrace> {
+        loop {
+            let (trace_id, generation) = self.order.front()?;
+            match self.pending.get(trace_id) {
+                Some(trace) if trace.generation == *generation => {
+                    return (trace.since.elapsed() >= self.timeout)
+                        .then(|| self.pop_oldest())
+                        .flatten();
+                }
+                _ => self.order.pop_front(),
+            };
+        }
+    }
+
+    fn remove(&mut self, trace_id: &str) -> Option<PendingTrace> {
+        let trace = self.pending.remove(trace_id)?;
+        self.spans -= trace.spans.len();
+        Some(trace)
+    }
+
+    /// Whether more spans or traces are held than allowed
+    fn over_limits(&self) -> bool {
+        self.spans > MAX_PENDING_SPANS || self.pending.len() > MAX_PENDING_TRACES
+    }
+}
+
+/// Spans written last whose ids are remembered, so an exporter's retry of one is dropped
+const RECENT_SPANS: usize = 100_000;
+
+/// Trace and span id of a span
+type SpanKey = (String, String);
+
+/// Ids of the spans written last, oldest first
+#[derive(Default)]
+struct RecentSpans {
+    order: VecDeque<SpanKey>,
+    ids: HashSet<SpanKey>,
+}
+
+impl RecentSpans {
+    fn insert(&mut self, key: SpanKey) {
+        if self.ids.insert(key.clone()) {
+            self.order.push_back(key);
+        }
+        while self.order.len() > RECENT_SPANS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+    }
+}
+
 /// Storage engine for trace data using Parquet
 pub struct TraceStorage {
     storage_dir: PathBuf,
@@ -47,6 +258,12 @@ pub struct TraceStorage {
     batch_size: usize,
     current_batch: Vec<TraceSpan>,
     file_counter: usize,
+    redactor: Option<AttributeRedactor>,
+    limits: SpanLimits,
+    grouping: Option<TraceGrouping>,
+    /// Position of each span in `current_batch`
+    batch_index: HashMap<SpanKey, usize>,
+    written: RecentSpans,
 }
 
 impl TraceStorage {
@@ -60,12 +277,119 @@ impl TraceStorage {
             batch_size,
             current_batch: Vec::with_capacity(batch_size),
             file_counter: 0,
+            redactor: None,
+            limits: SpanLimits::default(),
+            grouping: None,
+            batch_index: HashMap::new(),
+            written: RecentSpans::default(),
         })
     }
 
-    /// Add a span to the current batch
+    /// Co-location mode: hold the spans of each trace until its root span ends, or for at
+    /// most `timeout`, then write the trace whole; files are sorted by trace id
+    pub fn with_trace_grouping(mut self, timeout: Duration) -> Self {
+        self.grouping = Some(TraceGrouping {
+            timeout,
+            pending: HashMap::new(),
+            order: VecDeque::new(),
+            generation: 0,
+            spans: 0,
+        });
+        self
+    }
+
+    /// Cap the attributes and events of each span; `SpanLimits::default()` until set
+    pub fn with_limits(mut self, limits: SpanLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Redact sensitive span attributes before they are written
+    pub fn with_redactor(mut self, redactor: AttributeRedactor) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    /// Add a span to the current batch. A span added again, as exporters do when they retry,
+    /// replaces the held copy if it ended later and is dropped once that copy is written.
     pub fn add_span(&mut self, span: TraceSpan) -> Result<()> {
-        self.current_batch.push(span);
+        let root = span.parent_span_id.is_none();
+        self.add(span, root)
+    }
+
+    /// Add a span whose parent is in another process, such as a server span continuing a
+    /// trace from a request header; in co-location mode it completes this process's part of
+    /// the trace as a root span does
+    pub fn add_entry_span(&mut self, span: TraceSpan) -> Result<()> {
+        self.add(span, true)
+    }
+
+    fn add(&mut self, mut span: TraceSpan, root: bool) -> Result<()> {
+        if let Some(redactor) = &self.redactor {
+            redactor.redact_span(&mut span);
+        }
+        self.limits.apply(&mut span);
+        let key = (span.trace_id.clone(), span.span_id.clone());
+        if self.written.ids.contains(&key) {
+            crate::metrics::record_duplicate_spans(1);
+            return Ok(());
+        }
+        match &mut self.grouping {
+            None => self.push_ready(key, span),
+            Some(grouping) => {
+                // Spans are added as they end, and the root span ends last
+                let pending = match grouping.pending.get_mut(&key.0) {
+                    Some(pending) => pending,
+                    None => {
+                        grouping.generation += 1;
+                        let generation = grouping.generation;
+                        // Drop the ids of traces written since they were held
+                        if grouping.order.len() > 2 * MAX_PENDING_TRACES {
+                            let pending = &grouping.pending;
+                            grouping.order.retain(|(trace_id, generation)| {
+                                pending
+                                    .get(trace_id)
+                                    .is_some_and(|trace| trace.generation == *generation)
+                            });
+                        }
+                        grouping.order.push_back((key.0.clone(), generation));
+                        grouping
+                            .pending
+                            .entry(key.0.clone())
+                            .or_insert(PendingTrace {
+                                spans: Vec::new(),
+                                index: HashMap::new(),
+                                since: Instant::now(),
+                                generation,
+                            })
+                    }
+                };
+                match pending.index.get(&key.1) {
+                    Some(&held) => {
+                        crate::metrics::record_duplicate_spans(1);
+                        keep_latest(&mut pending.spans[held], span);
+                    }
+                    None => {
+                        pending.index.insert(key.1.clone(), pending.spans.len());
+                        pending.spans.push(span);
+                        grouping.spans += 1;
+                    }
+                }
+                let mut ready = Vec::new();
+                if root {
+                    ready.extend(grouping.remove(&key.0));
+                }
+                while grouping.over_limits() {
+                    let Some(oldest) = grouping.pop_oldest() else {
+                        break;
+                    };
+                    ready.push(oldest);
+                }
+                for trace in ready {
+                    self.push_trace(trace);
+                }
+            }
+        }
 
         if self.current_batch.len() >= self.batch_size {
             self.flush()?;
@@ -74,16 +398,86 @@ impl TraceStorage {
         Ok(())
     }
 
-    /// Flush the current batch to disk
+    /// Queue a span for the next file, unless a copy of it is already queued
+    fn push_ready(&mut self, key: SpanKey, span: TraceSpan) {
+        match self.batch_index.get(&key) {
+            Some(&index) => {
+                crate::metrics::record_duplicate_spans(1);
+                keep_latest(&mut self.current_batch[index], span);
+            }
+            None => {
+                self.batch_index.insert(key, self.current_batch.len());
+                self.current_batch.push(span);
+            }
+        }
+    }
+
+    fn push_trace(&mut self, trace: PendingTrace) {
+        for span in trace.spans {
+            self.push_ready((span.trace_id.clone(), span.span_id.clone()), span);
+        }
+    }
+
+    /// Flush the current batch to disk; in co-location mode, only complete traces and those
+    /// held longer than the timeout are written
     pub fn flush(&mut self) -> Result<()> {
+        if let Some(grouping) = &mut self.grouping {
+            // Traces are held in order, so the expired ones come first
+            let mut expired = Vec::new();
+            while let Some(trace) = grouping.pop_expired() {
+                expired.push(trace);
+            }
+            for trace in expired {
+                self.push_trace(trace);
+            }
+        }
+        self.write_batch()
+    }
+
+    /// Write every span held, including traces still waiting for their root span
+    pub fn flush_all(&mut self) -> Result<()> {
+        if let Some(grouping) = &mut self.grouping {
+            let pending: Vec<PendingTrace> = grouping.pending.drain().map(|(_, t)| t).collect();
+            grouping.order.clear();
+            grouping.spans = 0;
+            for trace in pending {
+                self.push_trace(trace);
+            }
+        }
+        self.write_batch()
+    }
+
+    fn write_batch(&mut self) -> Result<()> {
         if self.current_batch.is_empty() {
             return Ok(());
         }
+        if self.grouping.is_some() {
+            self.current_batch
+                .sort_by(|a, b| (&a.trace_id, a.start_time).cmp(&(&b.trace_id, b.start_time)));
+            for (index, span) in self.current_batch.iter().enumerate() {
+                self.batch_index
+                    .insert((span.trace_id.clone(), span.span_id.clone()), index);
+            }
+        }
 
         let batch = self.spans_to_record_batch(&self.current_batch)?;
         let file_path = self.generate_file_path();
+        let metadata = FileMetadata::new("traces")
+            .with_writer("compression", compression_name(self.compression))
+            .with_writer("batch_size", self.batch_size)
+            .with_writer("colocate", self.grouping.is_some())
+            .with_time_range(
+                self.current_batch
+                    .iter()
+                    .map(|span| span.start_time.timestamp_micros())
+                    .min(),
+                self.current_batch
+                    .iter()
+                    .map(|span| span.end_time.timestamp_micros())
+                    .max(),
+            );
 
-        self.write_record_batch(&file_path, batch)?;
+        self.write_record_batch(&file_path, batch, &metadata)?;
 
         info!(
             "Flushed {} spans to {:?}",
@@ -91,6 +485,9 @@ impl TraceStorage {
             file_path
         );
 
+        for (key, _) in self.batch_index.drain() {
+            self.written.insert(key);
+        }
         self.current_batch.clear();
         Ok(())
     }
@@ -184,15 +581,28 @@ impl TraceStorage {
     }
 
     /// Write RecordBatch to Parquet file
-    fn write_record_batch(&mut self, path: &Path, batch: RecordBatch) -> Result<()> {
+    fn write_record_batch(
+        &mut self,
+        path: &Path,
+        batch: RecordBatch,
+        metadata: &FileMetadata,
+    ) -> Result<()> {
         let file = File::create(path)?;
-        let props = WriterProperties::builder()
+        let mut props = WriterProperties::builder()
             .set_compression(self.compression)
-            .build();
+            .set_key_value_metadata(Some(metadata.key_values()));
+        if self.grouping.is_some() {
+            props = props.set_max_row_group_size(GROUPED_ROW_GROUP_ROWS);
+        }
+        let props = props.build();
 
-        let mut writer = ArrowWriter::try_new(file, batch.schema(), Some(props))?;
+        let mut writer =
+            ArrowWriter::try_new(ChecksumWriter::new(file), batch.schema(), Some(props))?;
         writer.write(&batch)?;
-        writer.close()?;
+        let (_, checksum) = writer.into_inner()?.finish();
+        if let Err(e) = checksum::record(path, checksum) {
+            warn!("Failed to record checksum of {:?}: {:#}", path, e);
+        }
 
         debug!("Wrote trace batch to {:?}", path);
         Ok(())
@@ -218,6 +628,501 @@ impl TraceStorage {
 
 impl Drop for TraceStorage {
     fn drop(&mut self) {
-        let _ = self.flush();
+        let _ = self.flush_all();
+    }
+}
+
+/// List trace Parquet files in a directory (empty if it doesn't exist yet)
+pub fn list_trace_files(storage_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    if !storage_dir.exists() {
+        return Ok(files);
+    }
+
+    for entry in std::fs::read_dir(storage_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if path.extension().and_then(|s| s.to_str()) == Some("parquet") {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Rows decoded at a time by a span scan, which bounds its memory whatever the file size
+const SCAN_BATCH_ROWS: usize = 1024;
+
+/// Which spans a scan keeps; it is applied while a file is decoded, so rejected rows never
+/// become spans
+#[derive(Debug, Clone, Default)]
+pub struct SpanFilter {
+    /// Only spans of these traces
+    pub trace_ids: Option<HashSet<String>>,
+    /// Only spans starting at or after this time, in microseconds
+    pub start_us: Option<i64>,
+    /// Only spans starting before this time, in microseconds
+    pub end_us: Option<i64>,
+}
+
+impl SpanFilter {
+    /// Spans of `trace_id`
+    pub fn trace(trace_id: &str) -> Self {
+        Self {
+            trace_ids: Some(HashSet::from([trace_id.to_string()])),
+            ..Self::default()
+        }
+    }
+
+    /// Spans starting at or after `start_us`, when set
+    pub fn since(start_us: Option<i64>) -> Self {
+        Self {
+            start_us,
+            ..Self::default()
+        }
+    }
+
+    /// Whether spans of `trace_id` can pass
+    pub fn wants_trace(&self, trace_id: &str) -> bool {
+        self.trace_ids
+            .as_ref()
+            .is_none_or(|ids| ids.contains(trace_id))
+    }
+
+    /// Whether a file whose spans start between `min_us` and `max_us` can hold matching spans
+    pub fn overlaps(&self, min_us: i64, max_us: i64) -> bool {
+        self.start_us.is_none_or(|start| max_us >= start)
+            && self.end_us.is_none_or(|end| min_us < end)
+    }
+
+    /// Parquet predicates for the filter, evaluated on the trace id and start time columns
+    /// before the other columns are decoded
+    fn row_filter(&self, schema: &parquet::schema::types::SchemaDescriptor) -> Option<RowFilter> {
+        use arrow::array::{Array, BooleanArray, StringArray};
+        use arrow::compute::kernels::cmp::{gt_eq, lt};
+
+        let mut predicates: Vec<Box<dyn ArrowPredicate>> = Vec::new();
+        if let Some(trace_ids) = self.trace_ids.clone() {
+            predicates.push(Box::new(ArrowPredicateFn::new(
+                ProjectionMask::roots(schema, [0]),
+                move |batch| {
+                    let ids = batch
+                        .column(0)
+                        .as_any()
+                        .downcast_ref::<StringArray>()
+                        .ok_or_else(|| {
+                            arrow::error::ArrowError::SchemaError(
+                                "trace_id is not a string column".to_string(),
+                            )
+                        })?;
+                    Ok(ids
+                        .iter()
+                        .map(|id| Some(id.is_some_and(|id| trace_ids.contains(id))))
+                        .collect::<BooleanArray>())
+                },
+            )));
+        }
+        let (start_us, end_us) = (self.start_us, self.end_us);
+        if start_us.is_some() || end_us.is_some() {
+            predicates.push(Box::new(ArrowPredicateFn::new(
+                ProjectionMask::roots(schema, [4]),
+                move |batch| {
+                    let starts = batch.column(0);
+                    let mut keep = BooleanArray::from(vec![true; starts.len()]);
+                    if let Some(start) = start_us {
+                        let after = gt_eq(starts, &TimestampMicrosecondArray::new_scalar(start))?;
+                        keep = arrow::compute::and(&keep, &after)?;
+                    }
+                    if let Some(end) = end_us {
+                        let before = lt(starts, &TimestampMicrosecondArray::new_scalar(end))?;
+                        keep = arrow::compute::and(&keep, &before)?;
+                    }
+                    Ok(keep)
+                },
+            )));
+        }
+        (!predicates.is_empty()).then(|| RowFilter::new(predicates))
+    }
+}
+
+/// Visit the spans of a trace Parquet file that pass `filter`, a batch at a time; stops
+/// reading once `visit` breaks
+pub fn scan_spans(
+    path: &Path,
+    filter: &SpanFilter,
+    mut visit: impl FnMut(TraceSpan) -> ControlFlow<()>,
+) -> Result<()> {
+    let file =
+        File::open(path).with_context(|| format!("Failed to open trace file: {:?}", path))?;
+    let mut builder =
+        ParquetRecordBatchReaderBuilder::try_new(file)?.with_batch_size(SCAN_BATCH_ROWS);
+    if let Some(trace_ids) = &filter.trace_ids {
+        let row_groups = row_groups_with_traces(builder.metadata(), trace_ids);
+        builder = builder.with_row_groups(row_groups);
+    }
+    if let Some(row_filter) = filter.row_filter(builder.parquet_schema()) {
+        builder = builder.with_row_filter(row_filter);
+    }
+
+    for batch_result in builder.build()? {
+        let batch = batch_result?;
+        for span in parse_spans_from_batch(&batch)? {
+            if visit(span).is_break() {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Keep the copy of a span exported twice that ended last
+fn keep_latest(kept: &mut TraceSpan, span: TraceSpan) {
+    if span.end_time > kept.end_time {
+        *kept = span;
+    }
+}
+
+/// Drop repeated copies of a span, such as one written again by a retrying exporter, keeping
+/// the one that ended last; returns how many were dropped
+pub fn dedup_spans(spans: &mut Vec<TraceSpan>) -> usize {
+    let before = spans.len();
+    let mut positions: HashMap<SpanKey, usize> = HashMap::with_capacity(spans.len());
+    let mut kept: Vec<TraceSpan> = Vec::with_capacity(spans.len());
+    for span in spans.drain(..) {
+        let key = (span.trace_id.clone(), span.span_id.clone());
+        match positions.get(&key) {
+            Some(&index) => keep_latest(&mut kept[index], span),
+            None => {
+                positions.insert(key, kept.len());
+                kept.push(span);
+            }
+        }
+    }
+    *spans = kept;
+    before - spans.len()
+}
+
+/// Row groups whose trace id statistics do not rule out every one of `trace_ids`; files
+/// written in co-location mode keep each trace within a few of them
+fn row_groups_with_traces(metadata: &ParquetMetaData, trace_ids: &HashSet<String>) -> Vec<usize> {
+    (0..metadata.num_row_groups())
+        .filter(|&index| {
+            let Some(stats) = metadata.row_group(index).column(0).statistics() else {
+                return true;
+            };
+            match (stats.min_bytes_opt(), stats.max_bytes_opt()) {
+                (Some(min), Some(max)) => trace_ids
+                    .iter()
+                    .any(|id| (min..=max).contains(&id.as_bytes())),
+                _ => true,
+            }
+        })
+        .collect()
+}
+
+/// Read all spans stored in a single trace Parquet file
+pub fn read_spans_from_file(path: &Path) -> Result<Vec<TraceSpan>> {
+    let mut spans = Vec::new();
+    scan_spans(path, &SpanFilter::default(), |span| {
+        spans.push(span);
+        ControlFlow::Continue(())
+    })?;
+    Ok(spans)
+}
+
+/// Parse spans from Arrow RecordBatch
+pub fn parse_spans_from_batch(batch: &RecordBatch) -> Result<Vec<TraceSpan>> {
+    use arrow::array::{Array, StringArray, TimestampMicrosecondArray, UInt64Array};
+
+    let trace_ids = batch
+        .column(0)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .unwrap();
+    let span_ids = batch
+        .column(1)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .unwrap();
+    let parent_span_ids = batch
+        .column(2)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .unwrap();
+    let names = batch
+        .column(3)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .unwrap();
+    let start_times = batch
+        .column(4)
+        .as_any()
+        .downcast_ref::<TimestampMicrosecondArray>()
+        .unwrap();
+    let end_times = batch
+        .column(5)
+        .as_any()
+        .downcast_ref::<TimestampMicrosecondArray>()
+        .unwrap();
+    let durations = batch
+        .column(6)
+        .as_any()
+        .downcast_ref::<UInt64Array>()
+        .unwrap();
+    let attributes = batch
+        .column(7)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .unwrap();
+    let events = batch
+        .column(8)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .unwrap();
+    let statuses = batch
+        .column(9)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .unwrap();
+
+    let mut spans = Vec::new();
+
+    for i in 0..batch.num_rows() {
+        let trace_id = trace_ids.value(i).to_string();
+        let span_id = span_ids.value(i).to_string();
+        let parent_span_id = if parent_span_ids.is_null(i) {
+            None
+        } else {
+            Some(parent_span_ids.value(i).to_string())
+        };
+        let name = names.value(i).to_string();
+        let start_time = DateTime::from_timestamp_micros(start_times.value(i)).unwrap_or_default();
+        let end_time = DateTime::from_timestamp_micros(end_times.value(i)).unwrap_or_default();
+        let duration_us = durations.value(i);
+
+        let attrs: HashMap<String, String> = serde_json::from_str(attributes.value(i))?;
+        let evts: Vec<SpanEvent> = serde_json::from_str(events.value(i))?;
+
+        let status_str = statuses.value(i);
+        let status = if status_str.starts_with("ERROR") {
+            SpanStatus::Error {
+                message: status_str.strip_prefix("ERROR: ").unwrap_or("").to_string(),
+            }
+        } else {
+            SpanStatus::Ok
+        };
+
+        spans.push(TraceSpan {
+            trace_id,
+            span_id,
+            parent_span_id,
+            name,
+            start_time,
+            end_time,
+            duration_us,
+            attributes: attrs,
+            events: evts,
+            status,
+        });
+    }
+
+    Ok(spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_span_limits_cut_with_markers() {
+        let limits = SpanLimits {
+            max_attributes: 2,
+            max_events: 1,
+            max_value_bytes: 2,
+        };
+        let attributes = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+        let now = Utc::now();
+        let event = |name: &str, offset_ms: i64| SpanEvent {
+            name: name.to_string(),
+            timestamp: now + chrono::Duration::milliseconds(offset_ms),
+            attributes: attributes(&[("a", "1"), ("b", "2"), ("c", "3")]),
+        };
+        let mut span = TraceSpan {
+            trace_id: "t".to_string(),
+            span_id: "s".to_string(),
+            parent_span_id: None,
+            name: "request".to_string(),
+            start_time: now,
+            end_time: now,
+            duration_us: 0,
+            attributes: attributes(&[("c", "x"), ("a", "héllo"), ("b", "ok")]),
+            events: vec![event("late", 5), event("early", 1)],
+            status: SpanStatus::Ok,
+        };
+        limits.apply(&mut span);
+
+        // The first keys are kept; a cut value stays valid UTF-8 and names its size
+        assert_eq!(span.attributes["a"], "h...(6 bytes)");
+        assert_eq!(span.attributes["b"], "ok");
+        assert!(!span.attributes.contains_key("c"));
+        assert_eq!(span.attributes[DROPPED_ATTRIBUTES], "1");
+        assert_eq!(span.attributes[DROPPED_EVENTS], "1");
+        assert_eq!(span.events.len(), 1);
+        assert_eq!(span.events[0].name, "early");
+        assert_eq!(span.events[0].attributes.len(), 3);
+        assert_eq!(span.events[0].attributes[DROPPED_ATTRIBUTES], "1");
+
+        assert!(SpanLimits::default().validate().is_ok());
+        assert!(SpanLimits {
+            max_value_bytes: 0,
+            ..SpanLimits::default()
+        }
+        .validate()
+        .is_err());
+    }
+
+    fn span(trace_id: &str, span_id: &str, parent: Option<&str>) -> TraceSpan {
+        TraceSpan {
+            trace_id: trace_id.to_string(),
+            span_id: span_id.to_string(),
+            parent_span_id: parent.map(str::to_string),
+            name: span_id.to_string(),
+            start_time: Utc::now(),
+            end_time: Utc::now(),
+            duration_us: 0,
+            attributes: HashMap::new(),
+            events: Vec::new(),
+            status: SpanStatus::Ok,
+        }
+    }
+
+    #[test]
+    fn test_duplicate_spans_keep_the_latest_copy() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path().to_path_buf();
+        let mut storage = TraceStorage::new(dir.clone(), Compression::SNAPPY, 10).unwrap();
+        let first = span("t", "s1", None);
+        let mut retried = first.clone();
+        retried.end_time = first.end_time + chrono::Duration::milliseconds(5);
+        storage.add_span(first.clone()).unwrap();
+        storage.add_span(span("t", "s2", Some("s1"))).unwrap();
+        storage.add_span(retried.clone()).unwrap();
+        storage.flush().unwrap();
+        // Already written: the copy is dropped
+        storage.add_span(first.clone()).unwrap();
+        storage.flush().unwrap();
+
+        let files = list_trace_files(&dir).unwrap();
+        assert_eq!(files.len(), 1);
+        let spans = read_spans_from_file(&files[0]).unwrap();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(
+            spans[0].end_time.timestamp_micros(),
+            retried.end_time.timestamp_micros()
+        );
+
+        let mut read = vec![first, span("t", "s2", Some("s1")), retried.clone()];
+        assert_eq!(dedup_spans(&mut read), 1);
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0].end_time, retried.end_time);
+    }
+
+    #[test]
+    fn test_colocated_traces_are_written_whole() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path().to_path_buf();
+        let mut storage = TraceStorage::new(dir.clone(), Compression::SNAPPY, 5)
+            .unwrap()
+            .with_trace_grouping(Duration::from_secs(3600));
+        // Interleaved children wait for their root; a batch holds whole traces
+        let b1 = span("b", "b1", Some("b0"));
+        storage.add_span(b1.clone()).unwrap();
+        storage.add_span(span("a", "a1", Some("a0"))).unwrap();
+        storage.add_span(span("b", "b2", Some("b0"))).unwrap();
+        // A retried span is held once
+        storage.add_span(b1).unwrap();
+        storage.add_span(span("c", "c1", Some("c0"))).unwrap();
+        storage.flush().unwrap();
+        assert!(list_trace_files(&dir).unwrap().is_empty());
+        storage.add_span(span("b", "b0", None)).unwrap();
+        storage.add_span(span("a", "a0", None)).unwrap();
+        let files = list_trace_files(&dir).unwrap();
+        assert_eq!(files.len(), 1);
+        let ids: Vec<String> = read_spans_from_file(&files[0])
+            .unwrap()
+            .into_iter()
+            .map(|span| span.span_id)
+            .collect();
+        assert_eq!(ids, ["a1", "a0", "b1", "b2", "b0"]);
+
+        // A span continuing a trace from another process completes this process's part
+        storage.add_span(span("d", "d1", Some("d0"))).unwrap();
+        storage
+            .add_entry_span(span("d", "d0", Some("remote")))
+            .unwrap();
+        assert!(!storage.grouping.as_ref().unwrap().pending.contains_key("d"));
+        assert_eq!(storage.current_batch.len(), 2);
+
+        // A trace whose root never ends is written on timeout or at shutdown
+        storage.flush_all().unwrap();
+        let files = list_trace_files(&dir).unwrap();
+        assert_eq!(files.len(), 2);
+        let mut found = Vec::new();
+        scan_spans(&files[1], &SpanFilter::trace("c"), |span| {
+            found.push(span.span_id);
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        assert_eq!(found, ["c1"]);
+
+        // Lookups by trace id skip row groups that cannot hold the trace
+        let file = File::open(&files[0]).unwrap();
+        let metadata = ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .metadata()
+            .clone();
+        assert!(row_groups_with_traces(&metadata, &HashSet::from(["c".to_string()])).is_empty());
+        assert_eq!(
+            row_groups_with_traces(&metadata, &HashSet::from(["b".to_string()])),
+            [0]
+        );
+    }
+
+    #[test]
+    fn test_colocation_holds_a_bounded_number_of_traces() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut storage = TraceStorage::new(temp_dir.path().to_path_buf(), Compression::SNAPPY, 1)
+            .unwrap()
+            .with_trace_grouping(Duration::from_secs(3600));
+        storage.batch_size = usize::MAX;
+        for trace in 0..=MAX_PENDING_TRACES {
+            let trace_id = format!("t{}", trace);
+            storage
+                .add_span(span(&trace_id, "child", Some("root")))
+                .unwrap();
+        }
+        // The trace held longest was written to make room
+        let grouping = storage.grouping.as_ref().unwrap();
+        assert_eq!(grouping.pending.len(), MAX_PENDING_TRACES);
+        assert!(!grouping.pending.contains_key("t0"));
+        assert_eq!(storage.current_batch.len(), 1);
+        assert_eq!(storage.current_batch[0].trace_id, "t0");
+
+        // Completed traces leave no ids behind for long
+        for trace in 0..3 * MAX_PENDING_TRACES {
+            let trace_id = format!("done{}", trace);
+            storage.add_span(span(&trace_id, "root", None)).unwrap();
+        }
+        let grouping = storage.grouping.as_ref().unwrap();
+        assert!(grouping.order.len() <= 2 * MAX_PENDING_TRACES + 1);
+        assert_eq!(grouping.spans, grouping.pending.len());
     }
 }
@@ -0,0 +1,223 @@
+use chrono::{DateTime, Utc};
+use opentelemetry::trace::{SpanId, SpanKind, Status, TraceError};
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::trace_storage::{SpanEvent, SpanStatus, TraceSpan, TraceStorage};
+
+/// Span exporter writing the daemon's own spans into its trace storage directory, where the
+/// AI Agent API reads them
+#[derive(Clone)]
+pub struct TraceWriter {
+    storage: Arc<Mutex<TraceStorage>>,
+}
+
+impl TraceWriter {
+    pub fn new(storage: TraceStorage) -> Self {
+        Self {
+            storage: Arc::new(Mutex::new(storage)),
+        }
+    }
+
+    fn storage(&self) -> MutexGuard<'_, TraceStorage> {
+        self.storage.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Write a partial batch every `interval`, so quiet periods still reach the API
+    pub fn spawn_flusher(&self, interval: Duration) {
+        let writer = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let writer = writer.clone();
+                let flushed = tokio::task::spawn_blocking(move || writer.storage().flush()).await;
+                if let Ok(Err(e)) = flushed {
+                    warn!("Failed to flush traces: {:#}", e);
+                }
+            }
+        });
+    }
+}
+
+impl std::fmt::Debug for TraceWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TraceWriter").finish_non_exhaustive()
+    }
+}
+
+impl SpanExporter for TraceWriter {
+    fn export(
+        &mut self,
+        batch: Vec<SpanData>,
+    ) -> Pin<Box<dyn Future<Output = ExportResult> + Send + 'static>> {
+        let writer = self.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let mut storage = writer.storage();
+                batch.into_iter().try_for_each(|span| {
+                    // A server or consumer span with a parent continues a trace started in
+                    // another process, so its part of the trace ends with it
+                    let entry = matches!(span.span_kind, SpanKind::Server | SpanKind::Consumer)
+                        && span.parent_span_id != SpanId::INVALID;
+                    let span = trace_span(span);
+                    if entry {
+                        storage.add_entry_span(span)
+                    } else {
+                        storage.add_span(span)
+                    }
+                })
+            })
+            .await
+            .map_err(|e| TraceError::from(e.to_string()))?
+            .map_err(|e| TraceError::from(format!("{:#}", e)))
+        })
+    }
+
+    fn shutdown(&mut self) {
+        if let Err(e) = self.storage().flush_all() {
+            warn!("Failed to flush traces: {:#}", e);
+        }
+    }
+}
+
+/// The stored form of an exported span; resource attributes such as `service.name` are
+/// added to its own
+fn trace_span(span: SpanData) -> TraceSpan {
+    let start_time: DateTime<Utc> = span.start_time.into();
+    let end_time: DateTime<Utc> = span.end_time.into();
+    let mut attributes = to_strings(span.attributes);
+    for (key, value) in span.resource.iter() {
+        attributes
+            .entry(key.to_string())
+            .or_insert_with(|| value.to_string());
+    }
+    TraceSpan {
+        trace_id: span.span_context.trace_id().to_string(),
+        span_id: span.span_context.span_id().to_string(),
+        parent_span_id: (span.parent_span_id != SpanId::INVALID)
+            .then(|| span.parent_span_id.to_string()),
+        name: span.name.into_owned(),
+        start_time,
+        end_time,
+        duration_us: (end_time - start_time)
+            .num_microseconds()
+            .unwrap_or(0)
+            .max(0) as u64,
+        attributes,
+        events: span
+            .events
+            .into_iter()
+            .map(|event| SpanEvent {
+                name: event.name.into_owned(),
+                timestamp: event.timestamp.into(),
+                attributes: to_strings(event.attributes),
+            })
+            .collect(),
+        status: match span.status {
+            Status::Error { description } => SpanStatus::Error {
+                message: description.into_owned(),
+            },
+            Status::Ok | Status::Unset => SpanStatus::Ok,
+        },
+    }
+}
+
+fn to_strings(attributes: Vec<KeyValue>) -> HashMap<String, String> {
+    attributes
+        .into_iter()
+        .map(|kv| (kv.key.to_string(), kv.value.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace_storage::{list_trace_files, scan_spans, SpanFilter};
+    use opentelemetry::trace::{
+        SpanContext, SpanKind, TraceFlags, TraceId, TraceState, Tracer, TracerProvider as _,
+    };
+    use opentelemetry_sdk::trace::{EvictedQueue, TracerProvider};
+    use parquet::basic::Compression;
+    use std::ops::ControlFlow;
+    use tempfile::TempDir;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_spans_are_written_in_batches() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().to_path_buf();
+        let storage = TraceStorage::new(dir.clone(), Compression::UNCOMPRESSED, 2).unwrap();
+        let mut writer = TraceWriter::new(storage);
+
+        let span = |span_id: u64, parent: SpanId| SpanData {
+            span_context: SpanContext::new(
+                TraceId::from(7),
+                SpanId::from(span_id),
+                TraceFlags::SAMPLED,
+                false,
+                TraceState::default(),
+            ),
+            parent_span_id: parent,
+            span_kind: SpanKind::Internal,
+            name: "flush".into(),
+            start_time: std::time::SystemTime::now(),
+            end_time: std::time::SystemTime::now() + Duration::from_millis(3),
+            attributes: vec![KeyValue::new("rows", 10)],
+            dropped_attributes_count: 0,
+            events: EvictedQueue::new(0),
+            links: EvictedQueue::new(0),
+            status: Status::error("disk full"),
+            resource: Default::default(),
+            instrumentation_lib: Default::default(),
+        };
+        writer
+            .export(vec![
+                span(1, SpanId::INVALID),
+                span(2, SpanId::from(1)),
+                span(3, SpanId::from(1)),
+            ])
+            .await
+            .unwrap();
+        // Two spans fill a batch; the third waits for a flush
+        assert_eq!(list_trace_files(&dir).unwrap().len(), 1);
+        writer.shutdown();
+        let files = list_trace_files(&dir).unwrap();
+        assert_eq!(files.len(), 2);
+
+        let mut spans = Vec::new();
+        for file in &files {
+            scan_spans(file, &SpanFilter::default(), |span| {
+                spans.push(span);
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        }
+        spans.sort_by(|a, b| a.span_id.cmp(&b.span_id));
+        assert_eq!(spans[0].trace_id, TraceId::from(7).to_string());
+        assert_eq!(spans[0].parent_span_id, None);
+        assert_eq!(spans[1].parent_span_id, Some(SpanId::from(1).to_string()));
+        assert_eq!(spans[0].attributes["rows"], "10");
+        assert!(
+            matches!(&spans[0].status, SpanStatus::Error { message } if message == "disk full")
+        );
+
+        // Spans of a tracer reach the writer through a batch processor
+        let provider = TracerProvider::builder()
+            .with_batch_exporter(writer.clone(), opentelemetry_sdk::runtime::Tokio)
+            .build();
+        provider.tracer("test").in_span("request", |_| {});
+        let flushed = tokio::task::spawn_blocking(move || provider.force_flush())
+            .await
+            .unwrap();
+        assert!(flushed.iter().all(Result::is_ok));
+        writer.storage().flush().unwrap();
+        assert_eq!(list_trace_files(&dir).unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,102 @@
+use anyhow::{bail, Context, Result};
+use std::time::Duration;
+
+/// Parse a duration such as `500ms`, `60s`, `5m`, `2h` or `30d`; a bare number is seconds
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .with_context(|| format!("Invalid duration: {:?}", value))?;
+    let unit_secs = match unit.trim() {
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        "d" => 86400.0,
+        _ => bail!("Invalid duration unit in {:?}; use ms, s, m, h or d", value),
+    };
+    Duration::try_from_secs_f64(number * unit_secs)
+        .with_context(|| format!("Invalid duration: {:?}", value))
+}
+
+/// Parse a count such as `5000`, `10k`, `1M` or `2.5B`
+pub fn parse_count(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&value[..i], 1e3),
+        Some((i, 'm' | 'M')) => (&value[..i], 1e6),
+        Some((i, 'b' | 'B' | 'g' | 'G')) => (&value[..i], 1e9),
+        _ => (value, 1.0),
+    };
+    let number: f64 = number
+        .replace('_', "")
+        .parse()
+        .with_context(|| format!("Invalid count: {:?}", value))?;
+    let count = number * multiplier;
+    if !count.is_finite() || count < 0.0 || count.fract() != 0.0 {
+        bail!("Invalid count: {:?}", value);
+    }
+    Ok(count as u64)
+}
+
+/// Parse a size such as `512MB`, `50GB`, `1.5TiB` or `4096`; units are binary, so `1KB` is
+/// 1024 bytes, and a bare number is bytes
+pub fn parse_size(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .with_context(|| format!("Invalid size: {:?}", value))?;
+    let unit = unit.trim().to_ascii_uppercase();
+    let power = match unit.strip_suffix('B').unwrap_or(&unit) {
+        "" => 0,
+        "K" | "KI" => 1,
+        "M" | "MI" => 2,
+        "G" | "GI" => 3,
+        "T" | "TI" => 4,
+        _ => bail!("Invalid size unit in {:?}; use B, KB, MB, GB or TB", value),
+    };
+    let bytes = number * 1024f64.powi(power);
+    if !bytes.is_finite() || bytes >= u64::MAX as f64 {
+        bail!("Invalid size: {:?}", value);
+    }
+    Ok(bytes as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_units() {
+        assert_eq!(parse_duration("60s").unwrap(), Duration::from_secs(60));
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse_duration("1.5m").unwrap(), Duration::from_secs(90));
+        assert_eq!(
+            parse_duration("30d").unwrap(),
+            Duration::from_secs(30 * 86400)
+        );
+        assert!(parse_duration("10 weeks").is_err());
+        assert!(parse_duration("s").is_err());
+
+        assert_eq!(parse_count("1M").unwrap(), 1_000_000);
+        assert_eq!(parse_count("2.5k").unwrap(), 2500);
+        assert_eq!(parse_count("10_000").unwrap(), 10_000);
+        assert!(parse_count("1.5").is_err());
+        assert!(parse_count("lots").is_err());
+
+        assert_eq!(parse_size("50GB").unwrap(), 50 << 30);
+        assert_eq!(parse_size("1.5KiB").unwrap(), 1536);
+        assert_eq!(parse_size("512m").unwrap(), 512 << 20);
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert!(parse_size("10 PB").is_err());
+    }
+}